@@ -31,6 +31,8 @@ type Metadata = apis::metadata::Metadata;
 type RpcHandler = pubsub::PubSubHandler<Metadata>;
 pub type HttpServer = http::Server;
 pub type WsServer = ws::Server;
+/// Node specific rpc methods, served alongside the substrate apis.
+pub type RpcExtensions = std::collections::HashMap<String, http::jsonrpc_core::RemoteProcedure<Metadata>>;
 
 /// Construct rpc `IoHandler`
 pub fn rpc_handler<Block: BlockT, ExHash, S, C, A, Y>(
@@ -38,6 +40,7 @@ pub fn rpc_handler<Block: BlockT, ExHash, S, C, A, Y>(
 	chain: C,
 	author: A,
 	system: Y,
+	extensions: RpcExtensions,
 ) -> RpcHandler where
 	Block: BlockT + 'static,
 	ExHash: Send + Sync + 'static + sr_primitives::Serialize + sr_primitives::DeserializeOwned,
@@ -51,6 +54,7 @@ pub fn rpc_handler<Block: BlockT, ExHash, S, C, A, Y>(
 	io.extend_with(chain.to_delegate());
 	io.extend_with(author.to_delegate());
 	io.extend_with(system.to_delegate());
+	io.extend_with(extensions);
 	io
 }
 
@@ -180,6 +180,7 @@ impl<C: Components> StartRPC<Self> for C where
 				chain,
 				author,
 				system,
+				C::build_rpc_extensions(client),
 			)
 		};
 
@@ -354,6 +355,11 @@ pub trait ServiceFactory: 'static + Sized {
 			Err("Chain Specification doesn't contain any consensus_engine name".into())
 		}
 	}
+
+	/// Node specific rpc methods served by a full node
+	fn build_full_rpc_extensions(_client: Arc<FullClient<Self>>) -> rpc::RpcExtensions {
+		Default::default()
+	}
 }
 
 /// A collection of types and function to generalize over full / light client type.
@@ -398,6 +404,9 @@ pub trait Components: Sized + 'static {
 		config: &mut FactoryFullConfiguration<Self::Factory>,
 		client: Arc<ComponentClient<Self>>
 	) -> Result<Self::ImportQueue, error::Error>;
+
+	/// Node specific rpc methods
+	fn build_rpc_extensions(client: Arc<ComponentClient<Self>>) -> rpc::RpcExtensions;
 }
 
 /// A struct that implement `Components` for the full client.
@@ -478,6 +487,10 @@ impl<Factory: ServiceFactory> Components for FullComponents<Factory> {
 	) -> Result<Self::ImportQueue, error::Error> {
 		Factory::build_full_import_queue(config, client)
 	}
+
+	fn build_rpc_extensions(client: Arc<ComponentClient<Self>>) -> rpc::RpcExtensions {
+		Factory::build_full_rpc_extensions(client)
+	}
 }
 
 /// A struct that implement `Components` for the light client.
@@ -554,6 +567,10 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 	) -> Result<Self::ImportQueue, error::Error> {
 		Factory::build_light_import_queue(config, client)
 	}
+
+	fn build_rpc_extensions(_client: Arc<ComponentClient<Self>>) -> rpc::RpcExtensions {
+		Default::default()
+	}
 }
 
 #[cfg(test)]
@@ -61,6 +61,7 @@ pub use components::{ServiceFactory, FullBackend, FullExecutor, LightBackend,
 use components::{StartRPC, MaintainTransactionPool, OffchainWorker};
 #[doc(hidden)]
 pub use std::{ops::Deref, result::Result, sync::Arc};
+pub use rpc::RpcExtensions;
 #[doc(hidden)]
 pub use network::OnDemand;
 #[doc(hidden)]
@@ -547,6 +548,7 @@ macro_rules! construct_service_factory {
 				{ $( $full_import_queue_init:tt )* },
 			LightImportQueue = $light_import_queue:ty
 				{ $( $light_import_queue_init:tt )* },
+			$( FullRpcExtensions = { $( $full_rpc_extensions_init:tt )* }, )*
 		}
 	) => {
 		$( #[$attr] )*
@@ -603,6 +605,14 @@ macro_rules! construct_service_factory {
 				( $( $light_import_queue_init )* ) (config, client)
 			}
 
+			$(
+				fn build_full_rpc_extensions(
+					client: $crate::Arc<$crate::FullClient<Self>>,
+				) -> $crate::RpcExtensions {
+					( $( $full_rpc_extensions_init )* ) (client)
+				}
+			)*
+
 			fn new_light(
 				config: $crate::FactoryFullConfiguration<Self>,
 				executor: $crate::TaskExecutor
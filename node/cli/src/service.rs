@@ -162,6 +162,12 @@ construct_service_factory! {
 				).map_err(Into::into)
 			}
 		},
+		FullRpcExtensions = {
+			|client: Arc<FullClient<Self>>| {
+				use node_rpc::{Totem, TotemApi};
+				TotemApi::to_delegate(Totem::new(client)).into()
+			}
+		},
 	}
 }
 
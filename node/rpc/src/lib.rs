@@ -0,0 +1,734 @@
+//! Totem rpc methods (`totem_*`) over the accounting, orders and prefunding runtime apis.
+//!
+//! The runtime apis return SCALE encoded tuples, which web clients cannot easily decode. These methods return
+//! named json fields instead. Amounts are returned as decimal strings, because i128 and u128 values do not fit in
+//! a javascript number, and hashes are returned as hex strings. Every method takes an optional block hash as its
+//! last parameter, so that the state can be queried at any block that has not been pruned. When it is omitted
+//! the best block is used.
+
+#![warn(missing_docs)]
+
+use std::sync::Arc;
+
+use client::blockchain::HeaderBackend;
+use jsonrpc_core::{Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use log::warn;
+use node_primitives::{AccountId, Block, BlockNumber, Hash};
+use node_runtime::{ChartOfAccountsApi, GlobalLedgerApi, NetworkStatusApi, OrderFeesApi, PostingsApi, PrefundingApi, ProfitabilityApi, ReportingCurrencyApi};
+use serde::{Deserialize, Serialize};
+use sr_primitives::{generic::BlockId, traits::ProvideRuntimeApi};
+
+/// A posting committed in a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Posting {
+	/// Block in which the posting was committed
+	pub block: BlockNumber,
+	/// Identity that owns the ledger
+	pub identity: AccountId,
+	/// Ledger account number
+	pub account: u64,
+	/// Signed amount
+	pub amount: String,
+	/// Posting index
+	pub index: String,
+	/// Reference hash of the transaction
+	pub reference: Hash,
+}
+
+/// Balance of a ledger account in the global ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerBalance {
+	/// Ledger account number
+	pub account: u64,
+	/// Signed balance
+	pub balance: String,
+}
+
+/// Total of the global ledger for a statement type (first digit of the account number)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementTotal {
+	/// Statement type
+	pub statement_type: u8,
+	/// Signed total
+	pub total: String,
+}
+
+/// Balance of a ledger account of an identity in XTX and in its reporting currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertedBalance {
+	/// Ledger account number
+	pub account: u64,
+	/// Signed balance in XTX
+	pub balance: String,
+	/// Signed balance in the reporting currency, if there is a rate for the period
+	pub converted: Option<String>,
+}
+
+/// Trial balance of an identity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalance {
+	/// ISO 4217 numeric code of the reporting currency of the identity
+	pub currency: Option<u16>,
+	/// Balances ordered by account number
+	pub balances: Vec<ConvertedBalance>,
+}
+
+/// Total of an identity for a statement type in XTX and in its reporting currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertedStatementTotal {
+	/// Statement type
+	pub statement_type: u8,
+	/// Signed total in XTX
+	pub total: String,
+	/// Signed total in the reporting currency, if there is a rate for the period
+	pub converted: Option<String>,
+}
+
+/// Totals of an identity per statement type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementTotals {
+	/// ISO 4217 numeric code of the reporting currency of the identity
+	pub currency: Option<u16>,
+	/// Totals ordered by statement type
+	pub totals: Vec<ConvertedStatementTotal>,
+}
+
+/// Ledger account of a network identity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkAccount {
+	/// Ledger account number
+	pub account: u64,
+	/// Signed balance
+	pub balance: String,
+	/// Number of postings in the recent blocks
+	pub movements: u32,
+}
+
+/// Ledger of a network identity (escrow, network fees)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkIdentity {
+	/// Network identity
+	pub identity: AccountId,
+	/// Ledger accounts used by the identity
+	pub accounts: Vec<NetworkAccount>,
+}
+
+/// Financial health of the network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStatus {
+	/// Ledgers of the network identities
+	pub identities: Vec<NetworkIdentity>,
+	/// Global ledger balances of the key network accounts
+	pub accounts: Vec<LedgerBalance>,
+}
+
+/// Display metadata of a ledger account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDisplay {
+	/// Ledger account number
+	pub account: u64,
+	/// Number of decimals to display
+	pub decimals: u8,
+	/// Display unit
+	pub unit: String,
+	/// Whether the account holds quantities rather than amounts
+	pub quantity: bool,
+}
+
+/// Account number of an account of the original chart under the chart of accounts in force at a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartAccount {
+	/// Chart of accounts version in force at the block (zero is the original chart)
+	pub version: u32,
+	/// Account number in that version
+	pub account: u64,
+}
+
+/// Revenue and cost of a project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profitability {
+	/// Revenue posted against the orders of the project
+	pub revenue: String,
+	/// Cost posted against the orders of the project
+	pub cost: String,
+}
+
+/// State of a prefunded reference
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prefunding {
+	/// Buyer that locked the funds
+	pub owner: AccountId,
+	/// Seller in whose favour the funds are locked
+	pub beneficiary: AccountId,
+	/// Prefunded amount
+	pub amount: String,
+	/// Block after which the owner can reclaim the funds
+	pub deadline: BlockNumber,
+	/// Reference status
+	pub status: u16,
+	/// Total of the adjustments made to the invoice
+	pub adjustments: String,
+	/// Total of the credit notes issued against the invoice
+	pub credits: String,
+}
+
+/// Reference status code of the prefunding module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusCode {
+	/// Status code
+	pub status: u16,
+	/// Name of the status
+	pub name: String,
+	/// Statuses to which the runtime can move a reference from this status
+	pub transitions: Vec<u16>,
+}
+
+/// Escrow held in the ledger for a prefunded reference compared with its prefunding lock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EscrowReconciliation {
+	/// Prefunded reference
+	pub reference: Hash,
+	/// Amount locked by the prefunding module
+	pub locked: String,
+	/// Balance of the escrow sub-ledger for the reference
+	pub escrowed: String,
+	/// Whether the escrow balance equals the locked amount
+	pub reconciled: bool,
+}
+
+/// Days a payer took to pay the invoices of a beneficiary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaysToPay {
+	/// Beneficiary of the invoices
+	pub beneficiary: AccountId,
+	/// Number of settled invoices
+	pub settled: u32,
+	/// Average days between invoice and settlement
+	pub average_days: u32,
+	/// Longest days between invoice and settlement
+	pub longest_days: u32,
+}
+
+/// Invoice awaiting settlement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgedReceivable {
+	/// Invoiced reference
+	pub reference: Hash,
+	/// Payer of the invoice
+	pub payer: AccountId,
+	/// Block at which the reference was invoiced
+	pub invoiced: BlockNumber,
+	/// Days since the invoice
+	pub days_outstanding: u32,
+}
+
+/// Unsettled invoice between two identities
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenItem {
+	/// Invoiced reference
+	pub reference: Hash,
+	/// Outstanding amount
+	pub outstanding: String,
+}
+
+/// Statement of account between an identity and a counterparty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenItems {
+	/// Invoices issued to the counterparty awaiting settlement
+	pub receivables: Vec<OpenItem>,
+	/// Invoices received from the counterparty awaiting settlement
+	pub payables: Vec<OpenItem>,
+	/// Receivables less payables
+	pub net: String,
+}
+
+/// Prefunding lock of an account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefundingLock {
+	/// Account holding the lock
+	pub account: AccountId,
+	/// Lock id
+	pub lock_id: [u8; 8],
+}
+
+/// Transaction fee of a step of the order lifecycle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderStepFee {
+	/// Order status after the step
+	pub status: u16,
+	/// Transaction fee of the step
+	pub fee: String,
+}
+
+/// Transaction fees of the order lifecycle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderFees {
+	/// Fees of the create, accept, invoice and settle steps
+	pub steps: Vec<OrderStepFee>,
+	/// Total fees of the lifecycle
+	pub total: String,
+}
+
+/// Totem rpc methods
+#[rpc]
+pub trait TotemApi {
+	/// Postings committed in a block range, in pages. Optionally only the postings with the given origin.
+	#[rpc(name = "totem_postingsInRange")]
+	fn postings_in_range(
+		&self,
+		from: BlockNumber,
+		to: BlockNumber,
+		page: u32,
+		origin: Option<u8>,
+		at: Option<Hash>,
+	) -> Result<Vec<Posting>>;
+
+	/// Balances of the global ledger in pages ordered by account number
+	#[rpc(name = "totem_globalLedger")]
+	fn global_ledger(&self, start: u32, count: u32, at: Option<Hash>) -> Result<Vec<LedgerBalance>>;
+
+	/// Totals of the global ledger per statement type
+	#[rpc(name = "totem_globalLedgerTotals")]
+	fn global_ledger_totals(&self, at: Option<Hash>) -> Result<Vec<StatementTotal>>;
+
+	/// Balances and recent movements of the network identities and key global ledger accounts
+	#[rpc(name = "totem_networkStatus")]
+	fn network_status(&self, at: Option<Hash>) -> Result<NetworkStatus>;
+
+	/// Display metadata of the accounts in the chart of accounts
+	#[rpc(name = "totem_accountsDisplay")]
+	fn accounts_display(&self, at: Option<Hash>) -> Result<Vec<AccountDisplay>>;
+
+	/// Decimals of XTX. Ledger balances are returned in the smallest unit
+	#[rpc(name = "totem_ledgerDecimals")]
+	fn ledger_decimals(&self, at: Option<Hash>) -> Result<u8>;
+
+	/// Account number of an account of the original chart under the chart of accounts in force at a block
+	#[rpc(name = "totem_chartAccount")]
+	fn chart_account(&self, account: u64, at_block: BlockNumber, at: Option<Hash>) -> Result<ChartAccount>;
+
+	/// Trial balance of an identity, also converted into its reporting currency at the rate for the period of the block
+	#[rpc(name = "totem_trialBalance")]
+	fn trial_balance(&self, owner: AccountId, at_block: BlockNumber, at: Option<Hash>) -> Result<TrialBalance>;
+
+	/// Totals of an identity per statement type, also converted into its reporting currency at the rate for the period of the block
+	#[rpc(name = "totem_statementTotals")]
+	fn statement_totals(&self, owner: AccountId, at_block: BlockNumber, at: Option<Hash>) -> Result<StatementTotals>;
+
+	/// Revenue and cost posted by an identity against the orders of a project
+	#[rpc(name = "totem_projectProfitability")]
+	fn project_profitability(&self, owner: AccountId, project: Hash, at: Option<Hash>) -> Result<Profitability>;
+
+	/// State of a prefunded reference
+	#[rpc(name = "totem_prefunding")]
+	fn prefunding(&self, reference: Hash, at: Option<Hash>) -> Result<Option<Prefunding>>;
+
+	/// Reference status codes of the prefunding module with their valid transitions
+	#[rpc(name = "totem_prefundingStatusCodes")]
+	fn prefunding_status_codes(&self, at: Option<Hash>) -> Result<Vec<StatusCode>>;
+
+	/// Escrow sub-ledger balances of an owner reconciled against the prefunding locks
+	#[rpc(name = "totem_escrowReconciliation")]
+	fn escrow_reconciliation(&self, owner: AccountId, at: Option<Hash>) -> Result<Vec<EscrowReconciliation>>;
+
+	/// Days a payer took to pay the invoices of each beneficiary
+	#[rpc(name = "totem_daysToPay")]
+	fn days_to_pay(&self, payer: AccountId, at: Option<Hash>) -> Result<Vec<DaysToPay>>;
+
+	/// Invoices of a beneficiary awaiting settlement with the days outstanding
+	#[rpc(name = "totem_receivablesAging")]
+	fn receivables_aging(&self, beneficiary: AccountId, at: Option<Hash>) -> Result<Vec<AgedReceivable>>;
+
+	/// The supplied prefunding locks that are orphaned (no prefunding or performance bond in storage)
+	#[rpc(name = "totem_orphanedLocks")]
+	fn orphaned_locks(&self, candidates: Vec<PrefundingLock>, at: Option<Hash>) -> Result<Vec<PrefundingLock>>;
+
+	/// References of an owner with a prefunding in storage but no currency lock
+	#[rpc(name = "totem_unlockedPrefundings")]
+	fn unlocked_prefundings(&self, owner: AccountId, at: Option<Hash>) -> Result<Vec<Hash>>;
+
+	/// Unsettled invoices and net position between an identity and a counterparty
+	#[rpc(name = "totem_openItems")]
+	fn open_items(&self, me: AccountId, counterparty: AccountId, at: Option<Hash>) -> Result<OpenItems>;
+
+	/// Transaction fees of the lifecycle of an order with the given number of items, before it is submitted
+	#[rpc(name = "totem_estimateOrderFees")]
+	fn estimate_order_fees(&self, item_count: u32, with_project: bool, at: Option<Hash>) -> Result<OrderFees>;
+}
+
+/// Implementation of the Totem rpc methods over a client
+pub struct Totem<C> {
+	client: Arc<C>,
+}
+
+impl<C> Totem<C> {
+	/// Create new `Totem` rpc methods over the given client
+	pub fn new(client: Arc<C>) -> Self {
+		Totem { client }
+	}
+}
+
+impl<C> Totem<C> where C: HeaderBackend<Block> {
+	fn block_id(&self, at: Option<Hash>) -> Result<BlockId<Block>> {
+		match at {
+			Some(hash) => Ok(BlockId::Hash(hash)),
+			None => self.client.info().map(|info| BlockId::Hash(info.best_hash)).map_err(internal),
+		}
+	}
+}
+
+fn internal<E: ::std::fmt::Debug>(e: E) -> Error {
+	warn!("Totem rpc error: {:?}", e);
+	Error {
+		code: ErrorCode::InternalError,
+		message: "Runtime query failed".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+impl<C> TotemApi for Totem<C> where
+	C: ProvideRuntimeApi + HeaderBackend<Block> + Send + Sync + 'static,
+	C::Api: PostingsApi<Block> + GlobalLedgerApi<Block> + NetworkStatusApi<Block> + ChartOfAccountsApi<Block>
+		+ ReportingCurrencyApi<Block> + ProfitabilityApi<Block> + PrefundingApi<Block> + OrderFeesApi<Block>,
+{
+	fn postings_in_range(
+		&self,
+		from: BlockNumber,
+		to: BlockNumber,
+		page: u32,
+		origin: Option<u8>,
+		at: Option<Hash>,
+	) -> Result<Vec<Posting>> {
+		let at = self.block_id(at)?;
+		let api = self.client.runtime_api();
+		let postings = match origin {
+			Some(origin) => api.postings_in_range_by_origin(&at, from, to, page, origin),
+			None => api.postings_in_range(&at, from, to, page),
+		}.map_err(internal)?;
+		Ok(postings.into_iter().map(|p| Posting {
+			block: p.0,
+			identity: p.1,
+			account: p.2,
+			amount: p.3.to_string(),
+			index: p.4.to_string(),
+			reference: p.5,
+		}).collect())
+	}
+
+	fn global_ledger(&self, start: u32, count: u32, at: Option<Hash>) -> Result<Vec<LedgerBalance>> {
+		let at = self.block_id(at)?;
+		let balances = self.client.runtime_api().global_ledger(&at, start, count).map_err(internal)?;
+		Ok(balances.into_iter().map(|b| LedgerBalance { account: b.0, balance: b.1.to_string() }).collect())
+	}
+
+	fn global_ledger_totals(&self, at: Option<Hash>) -> Result<Vec<StatementTotal>> {
+		let at = self.block_id(at)?;
+		let totals = self.client.runtime_api().global_ledger_totals(&at).map_err(internal)?;
+		Ok(totals.into_iter().map(|t| StatementTotal { statement_type: t.0, total: t.1.to_string() }).collect())
+	}
+
+	fn network_status(&self, at: Option<Hash>) -> Result<NetworkStatus> {
+		let at = self.block_id(at)?;
+		let (identities, accounts) = self.client.runtime_api().network_status(&at).map_err(internal)?;
+		Ok(NetworkStatus {
+			identities: identities.into_iter().map(|(identity, accounts)| NetworkIdentity {
+				identity,
+				accounts: accounts.into_iter().map(|a| NetworkAccount {
+					account: a.0,
+					balance: a.1.to_string(),
+					movements: a.2,
+				}).collect(),
+			}).collect(),
+			accounts: accounts.into_iter().map(|b| LedgerBalance { account: b.0, balance: b.1.to_string() }).collect(),
+		})
+	}
+
+	fn accounts_display(&self, at: Option<Hash>) -> Result<Vec<AccountDisplay>> {
+		let at = self.block_id(at)?;
+		let accounts = self.client.runtime_api().accounts_display(&at).map_err(internal)?;
+		Ok(accounts.into_iter().map(|(account, (decimals, unit, quantity))| AccountDisplay {
+			account,
+			decimals,
+			unit: String::from_utf8_lossy(&unit).into_owned(),
+			quantity,
+		}).collect())
+	}
+
+	fn ledger_decimals(&self, at: Option<Hash>) -> Result<u8> {
+		let at = self.block_id(at)?;
+		self.client.runtime_api().ledger_decimals(&at).map_err(internal)
+	}
+
+	fn chart_account(&self, account: u64, at_block: BlockNumber, at: Option<Hash>) -> Result<ChartAccount> {
+		let at = self.block_id(at)?;
+		let api = self.client.runtime_api();
+		Ok(ChartAccount {
+			version: api.chart_version_at(&at, at_block).map_err(internal)?,
+			account: api.chart_account(&at, account, at_block).map_err(internal)?,
+		})
+	}
+
+	fn trial_balance(&self, owner: AccountId, at_block: BlockNumber, at: Option<Hash>) -> Result<TrialBalance> {
+		let at = self.block_id(at)?;
+		let (currency, lines) = self.client.runtime_api().trial_balance(&at, owner, at_block).map_err(internal)?;
+		Ok(TrialBalance {
+			currency,
+			balances: lines.into_iter().map(|(account, balance, converted)| ConvertedBalance {
+				account,
+				balance: balance.to_string(),
+				converted: converted.map(|c| c.to_string()),
+			}).collect(),
+		})
+	}
+
+	fn statement_totals(&self, owner: AccountId, at_block: BlockNumber, at: Option<Hash>) -> Result<StatementTotals> {
+		let at = self.block_id(at)?;
+		let (currency, lines) = self.client.runtime_api().statement_totals(&at, owner, at_block).map_err(internal)?;
+		Ok(StatementTotals {
+			currency,
+			totals: lines.into_iter().map(|(statement_type, total, converted)| ConvertedStatementTotal {
+				statement_type,
+				total: total.to_string(),
+				converted: converted.map(|c| c.to_string()),
+			}).collect(),
+		})
+	}
+
+	fn project_profitability(&self, owner: AccountId, project: Hash, at: Option<Hash>) -> Result<Profitability> {
+		let at = self.block_id(at)?;
+		let (revenue, cost) = self.client.runtime_api().project_profitability(&at, owner, project).map_err(internal)?;
+		Ok(Profitability { revenue: revenue.to_string(), cost: cost.to_string() })
+	}
+
+	fn prefunding(&self, reference: Hash, at: Option<Hash>) -> Result<Option<Prefunding>> {
+		let at = self.block_id(at)?;
+		let state = self.client.runtime_api().prefunding_state(&at, reference).map_err(internal)?;
+		Ok(state.map(|s| Prefunding {
+			owner: s.0,
+			beneficiary: s.1,
+			amount: s.2.to_string(),
+			deadline: s.3,
+			status: s.4,
+			adjustments: s.5.to_string(),
+			credits: s.6.to_string(),
+		}))
+	}
+
+	fn prefunding_status_codes(&self, at: Option<Hash>) -> Result<Vec<StatusCode>> {
+		let at = self.block_id(at)?;
+		let api = self.client.runtime_api();
+		let codes = api.status_codes(&at).map_err(internal)?;
+		codes.into_iter().map(|(status, name)| Ok(StatusCode {
+			status,
+			name: String::from_utf8_lossy(&name).into_owned(),
+			transitions: api.valid_transitions(&at, status).map_err(internal)?,
+		})).collect()
+	}
+
+	fn escrow_reconciliation(&self, owner: AccountId, at: Option<Hash>) -> Result<Vec<EscrowReconciliation>> {
+		let at = self.block_id(at)?;
+		let lines = self.client.runtime_api().escrow_reconciliation(&at, owner).map_err(internal)?;
+		Ok(lines.into_iter().map(|(reference, locked, escrowed)| EscrowReconciliation {
+			reference,
+			locked: locked.to_string(),
+			escrowed: escrowed.to_string(),
+			reconciled: escrowed >= 0 && escrowed as u128 == locked,
+		}).collect())
+	}
+
+	fn days_to_pay(&self, payer: AccountId, at: Option<Hash>) -> Result<Vec<DaysToPay>> {
+		let at = self.block_id(at)?;
+		let lines = self.client.runtime_api().days_to_pay(&at, payer).map_err(internal)?;
+		Ok(lines.into_iter().map(|(beneficiary, settled, average_days, longest_days)| DaysToPay {
+			beneficiary,
+			settled,
+			average_days,
+			longest_days,
+		}).collect())
+	}
+
+	fn receivables_aging(&self, beneficiary: AccountId, at: Option<Hash>) -> Result<Vec<AgedReceivable>> {
+		let at = self.block_id(at)?;
+		let lines = self.client.runtime_api().receivables_aging(&at, beneficiary).map_err(internal)?;
+		Ok(lines.into_iter().map(|(reference, payer, invoiced, days_outstanding)| AgedReceivable {
+			reference,
+			payer,
+			invoiced,
+			days_outstanding,
+		}).collect())
+	}
+
+	fn orphaned_locks(&self, candidates: Vec<PrefundingLock>, at: Option<Hash>) -> Result<Vec<PrefundingLock>> {
+		let at = self.block_id(at)?;
+		let candidates = candidates.into_iter().map(|c| (c.account, c.lock_id)).collect();
+		let locks = self.client.runtime_api().orphaned_locks(&at, candidates).map_err(internal)?;
+		Ok(locks.into_iter().map(|(account, lock_id)| PrefundingLock { account, lock_id }).collect())
+	}
+
+	fn unlocked_prefundings(&self, owner: AccountId, at: Option<Hash>) -> Result<Vec<Hash>> {
+		let at = self.block_id(at)?;
+		self.client.runtime_api().unlocked_prefundings(&at, owner).map_err(internal)
+	}
+
+	fn open_items(&self, me: AccountId, counterparty: AccountId, at: Option<Hash>) -> Result<OpenItems> {
+		let at = self.block_id(at)?;
+		let (receivables, payables, net) = self.client.runtime_api().open_items(&at, me, counterparty).map_err(internal)?;
+		let items = |lines: Vec<(Hash, i128)>| lines.into_iter().map(|(reference, outstanding)| OpenItem {
+			reference,
+			outstanding: outstanding.to_string(),
+		}).collect();
+		Ok(OpenItems {
+			receivables: items(receivables),
+			payables: items(payables),
+			net: net.to_string(),
+		})
+	}
+
+	fn estimate_order_fees(&self, item_count: u32, with_project: bool, at: Option<Hash>) -> Result<OrderFees> {
+		let at = self.block_id(at)?;
+		let (steps, total) = self.client.runtime_api().estimate_order_fees(&at, item_count, with_project).map_err(internal)?;
+		Ok(OrderFees {
+			steps: steps.into_iter().map(|(status, fee)| OrderStepFee { status, fee: fee.to_string() }).collect(),
+			total: total.to_string(),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::{json, Value};
+
+	fn account(n: u8) -> AccountId {
+		AccountId::from_raw([n; 32])
+	}
+
+	fn to_json<T: Serialize>(value: &T) -> Value {
+		serde_json::to_value(value).unwrap()
+	}
+
+	#[test]
+	fn posting_amounts_are_decimal_strings_and_hashes_are_hex() {
+		let posting = Posting {
+			block: 7,
+			identity: account(1),
+			account: 110100050000000,
+			amount: i128::min_value().to_string(),
+			index: u128::max_value().to_string(),
+			reference: Hash::repeat_byte(0xab),
+		};
+		let value = to_json(&posting);
+		assert_eq!(value["block"], json!(7));
+		assert_eq!(value["identity"], to_json(&account(1)));
+		assert_eq!(value["account"], json!(110100050000000u64));
+		assert_eq!(value["amount"], json!("-170141183460469231731687303715884105728"));
+		assert_eq!(value["index"], json!("340282366920938463463374607431768211455"));
+		assert_eq!(value["reference"], json!(format!("0x{}", "ab".repeat(32))));
+		assert_eq!(serde_json::from_value::<Posting>(value).unwrap(), posting);
+	}
+
+	#[test]
+	fn fields_are_camel_case() {
+		let totals = StatementTotals {
+			currency: Some(978),
+			totals: vec![ConvertedStatementTotal { statement_type: 1, total: "-5".into(), converted: None }],
+		};
+		assert_eq!(to_json(&totals), json!({
+			"currency": 978,
+			"totals": [{ "statementType": 1, "total": "-5", "converted": null }],
+		}));
+
+		let aged = AgedReceivable {
+			reference: Hash::zero(),
+			payer: account(2),
+			invoiced: 100,
+			days_outstanding: 3,
+		};
+		let value = to_json(&aged);
+		assert_eq!(value["daysOutstanding"], json!(3));
+		assert!(value.get("days_outstanding").is_none());
+	}
+
+	#[test]
+	fn missing_reporting_currency_is_null() {
+		let balance = TrialBalance {
+			currency: None,
+			balances: vec![ConvertedBalance { account: 110100050000000, balance: "10".into(), converted: Some("9".into()) }],
+		};
+		assert_eq!(to_json(&balance), json!({
+			"currency": null,
+			"balances": [{ "account": 110100050000000u64, "balance": "10", "converted": "9" }],
+		}));
+	}
+
+	#[test]
+	fn prefunding_round_trips() {
+		let prefunding = Prefunding {
+			owner: account(1),
+			beneficiary: account(2),
+			amount: u128::max_value().to_string(),
+			deadline: 5760,
+			status: 400,
+			adjustments: "-25".into(),
+			credits: "0".into(),
+		};
+		let value = to_json(&prefunding);
+		assert_eq!(value["amount"], json!("340282366920938463463374607431768211455"));
+		assert_eq!(value["adjustments"], json!("-25"));
+		assert_eq!(serde_json::from_value::<Prefunding>(value).unwrap(), prefunding);
+		assert_eq!(to_json(&None::<Prefunding>), Value::Null);
+	}
+
+	#[test]
+	fn prefunding_lock_candidates_are_read_from_json() {
+		let lock = PrefundingLock { account: account(3), lock_id: *b"prefund1" };
+		let value = to_json(&lock);
+		assert_eq!(value["lockId"], json!([112, 114, 101, 102, 117, 110, 100, 49]));
+
+		let candidate: PrefundingLock = serde_json::from_value(json!({
+			"account": to_json(&account(3)),
+			"lockId": [112, 114, 101, 102, 117, 110, 100, 49],
+		})).unwrap();
+		assert_eq!(candidate, lock);
+		assert!(serde_json::from_value::<PrefundingLock>(json!({
+			"account": to_json(&account(3)),
+			"lockId": [1, 2, 3],
+		})).is_err());
+	}
+
+	#[test]
+	fn order_fees_are_decimal_strings() {
+		let fees = OrderFees {
+			steps: vec![OrderStepFee { status: 0, fee: "1000".into() }, OrderStepFee { status: 5, fee: "1500".into() }],
+			total: "2500".into(),
+		};
+		assert_eq!(to_json(&fees), json!({
+			"steps": [{ "status": 0, "fee": "1000" }, { "status": 5, "fee": "1500" }],
+			"total": "2500",
+		}));
+	}
+}
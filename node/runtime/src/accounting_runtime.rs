@@ -84,20 +84,15 @@
 // * Therefore the currency conversion for revenue and related expenses is calculated at the spot rate for the period (block) in which they are recognised.
 // * All other currency conversions are made at the rate for the period close. The UI can therefore present the correct conversions for any given value at any point in time. 
 
-use parity_codec::{ Encode }; //v1
-// use codec::{ Encode }; //v2
+use parity_codec::{ Encode };
 
-use support::{decl_event, decl_module, decl_storage, dispatch::Result, StorageValue, StorageMap}; //v1
-// use frame_support::{decl_event, decl_error, decl_module, decl_storage, dispatch::DispatchResult, weights::{Weight, DispatchClass}, StorageValue, StorageMap}; // v2
+use support::{decl_event, decl_module, decl_storage, dispatch::Result, StorageValue, StorageMap};
 
-use system::{self}; //v1
-// use frame_system::{self}; //v2
+use system::{self};
 
-use rstd::prelude::*; //v1
-// use sp_std::prelude::*; //v2
+use rstd::prelude::*;
 
-use runtime_primitives::traits::Hash; //v1
-// use sp_runtime{DispatchResult, DispatchError, traits::{Hash},}; //v2
+use runtime_primitives::traits::Hash;
 
 // Totem Traits
 use crate::accounting_traits::{ Posting };
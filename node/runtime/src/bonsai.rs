@@ -64,10 +64,18 @@
 /// (effectively rejecting the attempt to store the data), and if it does match then store the data using the reference hash as the key
 /// 3. in the event that an reference hash already exists, the data-hash obtained from the blockchain is always king. Provided it matches, overwrite exiting data.
 
+/// Deduplication
+/// The same data-hash is often claimed under several reference hashes (repeated document references, retries from the UI).
+/// Data-hashes are content-addressed and reference counted, so the off-chain database only needs to keep one copy of the data.
+/// Claiming a reference for the data-hash it already has is a no-op. When the last reference to a data-hash is overwritten 
+/// or released, the count is garbage collected and an event signals that the data can be dropped off-chain.
+/// References stored before the counting was introduced are counted when they are next touched, or by root in batches.
+/// Until root marks the legacy references as counted, no data-hash is signalled as unreferenced.
+
 use parity_codec::{Encode};
 use support::{decl_event, decl_module, decl_storage, dispatch::Result, StorageMap};
 use substrate_primitives::H256;
-use system::{self, ensure_signed};
+use system::{self, ensure_signed, ensure_root};
 use rstd::prelude::*;
 use runtime_primitives::traits::{Hash, Convert};
 
@@ -92,14 +100,24 @@ pub trait Trait: system::Trait {
 
 pub type RecordType = u16;
 
+// Maximum number of legacy references counted in one call
+const MAX_REFERENCE_MIGRATIONS: usize = 100;
+
 decl_storage! {
     trait Store for Module<T: Trait> as BonsaiModule {
         // Bonsai Storage
         IsValidRecord get(is_valid_record): map T::Hash => Option<T::Hash>; 
+        // Number of reference hashes that point at a data-hash. Removed when no reference is left.
+        DataReferences get(data_references): map T::Hash => u32;
+        // Reference hashes that are included in the count of their data-hash. Legacy references are not.
+        ReferenceCounted get(reference_counted): map T::Hash => bool;
+        // Set by root once all legacy references are counted. Data-hashes are only signalled as unreferenced after this.
+        LegacyReferencesCounted get(legacy_references_counted): bool;
         // Hacky workaround for inability of RPC to query transaction by hash
         IsStarted get(is_started): map T::Hash => Option<T::BlockNumber>; // maps to current block number allows interrogation of errors
         IsSuccessful get(is_successful): map T::Hash => Option<T::BlockNumber>; // future block number beyond which the Hash should deleted
         TxList get(tx_list):  map T::Hash => Vec<T::Hash>; // Tracking to ensure that we can perform housekeeping on finalization of block 
+        IsFailed get(is_failed): map T::Hash => Option<(T::BlockNumber, Vec<u8>)>; // block number at which the transaction failed and the error, allows the UI to reconcile its cache
     }
 }
 
@@ -134,6 +152,25 @@ decl_module! {
             Ok(())
         }
         
+        /// Counts references that were stored before the reference counting. Already counted references are skipped.
+        /// Root sets `complete` with the last batch, after which unreferenced data-hashes are signalled.
+        fn count_legacy_references(origin, references: Vec<T::Hash>, complete: bool) -> Result {
+            ensure_root(origin)?;
+            if references.len() > MAX_REFERENCE_MIGRATIONS {
+                Self::deposit_event(RawEvent::ErrorTooManyReferences());
+                return Err("Too many references in one call");
+            }
+            for k in references.into_iter() {
+                if let Some(t) = Self::is_valid_record(&k) {
+                    Self::count_reference(k, t);
+                }
+            }
+            if complete {
+                <LegacyReferencesCounted<T>>::put(true);
+            }
+            Ok(())
+        }
+        
         fn on_finalize_example(origin) -> Result {
             let _who = ensure_signed(origin)?;
             let current_block: T::BlockNumber = <system::Module<T>>::block_number();
@@ -175,7 +212,15 @@ decl_module! {
                                         ();
                                     }       
                                 },
-                                None => (),
+                                None => {
+                                    // cleanup failed transactions 30 Days after they failed
+                                    if let Some((block, _)) = Self::is_failed(&key) {
+                                        let target_block: u64 = <T::BonsaiConversions as Convert<T::BlockNumber, u64>>::convert(block) + 172800u64;
+                                        if current >= target_block {
+                                            <IsFailed<T>>::remove(key.clone());
+                                        }
+                                    }
+                                },
                             }
                         },
                     }
@@ -223,18 +268,57 @@ impl<T: Trait> Module<T> {
     
     fn insert_record(k: T::Hash, t: T::Hash) -> Result {
         // TODO implement fee payment mechanism (currently just transaction fee)
-        if <IsValidRecord<T>>::exists(&k) {
+        match Self::is_valid_record(&k) {
+            // Retry of the same claim. Nothing to store, but a legacy reference is counted now
+            Some(existing) if existing == t => {
+                Self::count_reference(k, t);
+                return Ok(());
+            },
             // remove store the token. This overwrites any existing hash.
-            <IsValidRecord<T>>::remove(k.clone());
-        } else {
-            ();
+            Some(existing) => {
+                <IsValidRecord<T>>::remove(k.clone());
+                Self::release_reference(k, existing);
+            },
+            None => (),
         }
         
         <IsValidRecord<T>>::insert(k, t);
+        Self::count_reference(k, t);
         
         Ok(())
     }
     
+    fn remove_record(k: T::Hash) {
+        if let Some(existing) = <IsValidRecord<T>>::take(&k) {
+            Self::release_reference(k, existing);
+        }
+    }
+    
+    /// Includes the reference in the count of its data-hash, unless it is already counted
+    fn count_reference(k: T::Hash, t: T::Hash) {
+        if !Self::reference_counted(&k) {
+            <DataReferences<T>>::mutate(&t, |references| *references = references.saturating_add(1));
+            <ReferenceCounted<T>>::insert(&k, true);
+        }
+    }
+    
+    /// Decrements the references to a data-hash and garbage collects it when no reference is left.
+    /// A legacy reference was never counted, so it does not decrement the count.
+    fn release_reference(k: T::Hash, t: T::Hash) {
+        if !<ReferenceCounted<T>>::take(&k) {
+            return;
+        }
+        let references: u32 = Self::data_references(&t).saturating_sub(1);
+        if references == 0 {
+            <DataReferences<T>>::remove(&t);
+            if Self::legacy_references_counted() {
+                Self::deposit_event(RawEvent::DataUnreferenced(t));
+            }
+        } else {
+            <DataReferences<T>>::insert(&t, references);
+        }
+    }
+    
     fn start_uuid(u: T::Hash) -> Result {
         
         if <IsSuccessful<T>>::exists(&u) {
@@ -242,7 +326,7 @@ impl<T: Trait> Module<T> {
             Self::deposit_event(RawEvent::ErrorTransactionCompleted(u));
             return Err("Queued transaction already completed");
             
-        } else if <IsStarted<T>>::exists(&u) {
+        } else if <IsStarted<T>>::exists(&u) || <IsFailed<T>>::exists(&u) {
             // Apparently someone is attempting to use this TX_UID after a transaction failed.
             Self::deposit_event(RawEvent::ErrorTransactionIDInUse(u));
             return Err("The transaction ID is not unique. Create a new one.");
@@ -284,6 +368,16 @@ impl<T: Trait> Module<T> {
         }
         Ok(())
     }
+
+    fn fail_uuid(u: T::Hash, reason: &'static str) {
+        // Only a started transaction can fail. 
+        if <IsStarted<T>>::exists(&u) {
+            let current_block = <system::Module<T>>::block_number();
+            <IsStarted<T>>::remove(&u);
+            <IsFailed<T>>::insert(u, (current_block, reason.as_bytes().to_vec()));
+            Self::deposit_event(RawEvent::TransactionFailed(u));
+        }
+    }
 }
 
 impl<T: Trait> Storing<T::Hash> for Module<T> {
@@ -291,6 +385,9 @@ impl<T: Trait> Storing<T::Hash> for Module<T> {
         Self::insert_record(r.clone(), d.clone())?;
         Ok(())
     }
+    fn release_data(r: T::Hash) {
+        Self::remove_record(r);
+    }
     fn start_tx(u: T::Hash) -> Result {
         Self::start_uuid(u.clone())?;
         Ok(())
@@ -299,6 +396,9 @@ impl<T: Trait> Storing<T::Hash> for Module<T> {
         Self::end_uuid(u.clone())?;
         Ok(())
     }
+    fn fail_tx(u: T::Hash, reason: &'static str) {
+        Self::fail_uuid(u, reason);
+    }
 }
 
 decl_event!(
@@ -314,5 +414,108 @@ decl_event!(
         ErrorTransactionCompleted(Hash),
         /// The transaction ID is not unique. Create a new one.
         ErrorTransactionIDInUse(Hash),
+        /// The transaction failed. The error is recorded against the transaction ID
+        TransactionFailed(Hash),
+        /// No reference points at this data-hash anymore. The data can be dropped off-chain
+        DataUnreferenced(Hash),
+        /// Too many legacy references in one call
+        ErrorTooManyReferences(),
+    }
+);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use support::assert_ok;
+    use crate::mock::{account, Origin, Test};
+
+    type BonsaiModule = Module<Test>;
+
+    // Activity records are owned by anyone in the mock
+    const ACTIVITY: RecordType = 3000;
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![])
+    }
+
+    fn claim(k: u8, t: u8) -> Result {
+        BonsaiModule::update_record(Origin::signed(account(1)), ACTIVITY, H256::repeat_byte(k), H256::repeat_byte(t))
+    }
+
+    #[test]
+    fn references_to_a_data_hash_are_counted_once_each() {
+        with_externalities(&mut new_test_ext(), || {
+            let data = H256::repeat_byte(100);
+            assert_ok!(claim(1, 100));
+            assert_ok!(claim(2, 100));
+            assert_eq!(BonsaiModule::data_references(data), 2);
+            
+            // Retrying a claim does not count the reference again
+            assert_ok!(claim(1, 100));
+            assert_eq!(BonsaiModule::data_references(data), 2);
+            assert!(BonsaiModule::reference_counted(H256::repeat_byte(1)));
+        });
+    }
+
+    #[test]
+    fn overwriting_a_reference_moves_its_count_to_the_new_data_hash() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(claim(1, 100));
+            assert_ok!(claim(2, 100));
+            assert_ok!(claim(1, 101));
+            assert_eq!(BonsaiModule::data_references(H256::repeat_byte(100)), 1);
+            assert_eq!(BonsaiModule::data_references(H256::repeat_byte(101)), 1);
+            assert_eq!(BonsaiModule::is_valid_record(H256::repeat_byte(1)), Some(H256::repeat_byte(101)));
+        });
+    }
+
+    #[test]
+    fn data_hash_is_removed_when_its_last_reference_is_released() {
+        with_externalities(&mut new_test_ext(), || {
+            let data = H256::repeat_byte(100);
+            assert_ok!(claim(1, 100));
+            assert_ok!(claim(2, 100));
+            
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(1));
+            assert_eq!(BonsaiModule::data_references(data), 1);
+            assert!(<DataReferences<Test>>::exists(data));
+            assert!(BonsaiModule::is_valid_record(H256::repeat_byte(1)).is_none());
+            assert!(!BonsaiModule::reference_counted(H256::repeat_byte(1)));
+            
+            // Releasing the same reference again does not decrement the count
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(1));
+            assert_eq!(BonsaiModule::data_references(data), 1);
+            
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(2));
+            assert_eq!(BonsaiModule::data_references(data), 0);
+            assert!(!<DataReferences<Test>>::exists(data));
+        });
     }
-);
\ No newline at end of file
+
+    #[test]
+    fn legacy_references_are_only_decremented_once_counted() {
+        with_externalities(&mut new_test_ext(), || {
+            let data = H256::repeat_byte(100);
+            // Stored before the reference counting
+            <IsValidRecord<Test>>::insert(H256::repeat_byte(1), data);
+            <IsValidRecord<Test>>::insert(H256::repeat_byte(2), data);
+            assert_ok!(claim(3, 100));
+            assert_eq!(BonsaiModule::data_references(data), 1);
+            
+            // A legacy reference that was never counted does not decrement the count
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(1));
+            assert_eq!(BonsaiModule::data_references(data), 1);
+            
+            assert!(BonsaiModule::count_legacy_references(Origin::signed(account(1)), vec![H256::repeat_byte(2)], true).is_err());
+            assert_ok!(BonsaiModule::count_legacy_references(Origin::ROOT, vec![H256::repeat_byte(2), H256::repeat_byte(3)], true));
+            assert_eq!(BonsaiModule::data_references(data), 2);
+            assert!(BonsaiModule::legacy_references_counted());
+            
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(2));
+            <BonsaiModule as Storing<H256>>::release_data(H256::repeat_byte(3));
+            assert!(!<DataReferences<Test>>::exists(data));
+        });
+    }
+}
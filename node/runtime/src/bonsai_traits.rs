@@ -37,6 +37,8 @@ use support::{ dispatch::Result };
 
 pub trait Storing<Hash> {
     fn claim_data(r: Hash, d: Hash) -> Result;
+    fn release_data(r: Hash);
     fn start_tx(u: Hash) -> Result;
     fn end_tx(u: Hash) -> Result;
+    fn fail_tx(u: Hash, reason: &'static str);
 }
\ No newline at end of file
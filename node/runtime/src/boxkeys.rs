@@ -80,23 +80,48 @@
 ///
 /// 7. The process for replacing keys is identical with the added exception that the keys must be signed by the previous signature key.
 ///
+/// Attestation
+/// In addition to the self-verification above, a registrar identity (for example the Live Accounting Association) can attest 
+/// that a set of verified keys belongs to a verified business. The attestation is for the keys at the time of attesting, 
+/// therefore replacing the keys invalidates the attestation. Attestations expire at a given block and can be revoked by the registrar.
+/// Counterparties can require attested keys before exchanging sensitive commercial documents.
+///
+/// Aliases
+/// A user can register a short human-readable alias that resolves to their account and the verified box (encryption) key 
+/// of their user hash, so that encrypted documents can be exchanged by alias instead of copying the public key. 
+/// Each alias reserves a deposit from the owner to discourage squatting, which is returned when the alias is released. 
+/// An alias is transferred by the owner offering it to another account which accepts it, reserving its own deposit.
+///
 
 use parity_codec::{Decode, Encode};
 use substrate_primitives::{ed25519, H256};
 // use node_primitives::Hash;
 use rstd::prelude::*;
-use runtime_primitives::traits::Verify;
-use support::{decl_event, decl_module, decl_storage, StorageMap, dispatch::Result, ensure};
-use system::{self, ensure_signed};
+use runtime_primitives::traits::{As, Verify};
+use support::{decl_event, decl_module, decl_storage, StorageMap, StorageValue, dispatch::Result, ensure};
+use support::traits::{Currency, ReservableCurrency};
+use system::{self, ensure_root, ensure_signed};
 use runtime_io::{blake2_128, blake2_256};
 
 // bring in Nacl encryption
 use sodalite::{box_, box_keypair_seed, BoxPublicKey, BoxSecretKey, BoxNonce};
 
+use crate::boxkeys_traits::{ KeyVerifying };
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
 pub trait Trait: system::Trait + timestamp::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    type Currency: ReservableCurrency<Self::AccountId>;
 }
 
+/// Bounds of the length of an alias in bytes
+const MIN_ALIAS_LENGTH: usize = 3;
+const MAX_ALIAS_LENGTH: usize = 32;
+
+// Deposit reserved for an alias until the Super User sets another
+const DEFAULT_ALIAS_DEPOSIT: u64 = 1_000;
+
 pub type EncryptNonce = BoxNonce;
 pub type EncryptPublicKey = H256; //32 bytes Hex
 
@@ -107,6 +132,8 @@ pub type SignedBy = <Ed25519signature as Verify>::Signer; //AuthorityId
 
 pub type Data = Vec<u8>;
 
+pub type Alias = Vec<u8>;
+
 type EphemeralPublicKey = BoxSecretKey; // generated internally
 type EphemeralSecretKey = BoxSecretKey; // generated internally
 
@@ -140,6 +167,20 @@ decl_storage! {
         PublicKeySign get(public_key_sign): map UserNameHash => Option<SignedBy>;
         TempPublicKeySign get(temp_public_key_sign): map UserNameHash => Option<SignedBy>;
         VerificationData get(verification_data): map UserNameHash => Option<EncryptedVerificationData<EncryptPublicKey, Data>>;
+        // Account that proved control of the verified keys by signing the verification
+        KeysOwner get(keys_owner): map UserNameHash => Option<T::AccountId>;
+        // Identities permitted to attest keys
+        Registrars get(registrars): Vec<T::AccountId>;
+        // Attestation of the verified keys (registrar, encryption key, signing key, expiry block, revoked)
+        Attestations get(attestations): map UserNameHash => Option<(T::AccountId, EncryptPublicKey, SignedBy, T::BlockNumber, bool)>;
+        // Deposit reserved from the owner of an alias
+        AliasDeposit get(alias_deposit): BalanceOf<T> = BalanceOf::<T>::sa(DEFAULT_ALIAS_DEPOSIT);
+        // Alias to the owner, the user hash of the box keys and the deposit reserved
+        Aliases get(aliases): map Alias => Option<(T::AccountId, UserNameHash, BalanceOf<T>)>;
+        // Alias owned by an account (one per account)
+        AliasOf get(alias_of): map T::AccountId => Option<Alias>;
+        // Account an alias is offered to by the owner, pending acceptance
+        AliasTransferOffer get(alias_transfer_offer): map Alias => Option<T::AccountId>;
     }
 }
 
@@ -166,11 +207,64 @@ decl_module! {
             <PublicKeySign<T>>::take(&user_hash);
             <TempPublicKeySign<T>>::take(&user_hash);
             <VerificationData<T>>::take(&user_hash);
+            <Attestations<T>>::take(&user_hash);
+            <KeysOwner<T>>::remove(&user_hash);
     
             Ok(())
 
         }
         
+        /// Super User adds a registrar that can attest keys
+        fn add_registrar(origin, registrar: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            ensure!(!Self::registrars().contains(&registrar), "Registrar already exists");
+            <Registrars<T>>::mutate(|registrars| registrars.push(registrar.clone()));
+            Self::deposit_event(RawEvent::RegistrarAdded(registrar));
+            Ok(())
+        }
+        
+        /// Super User removes a registrar. Attestations made by the registrar are no longer valid.
+        fn remove_registrar(origin, registrar: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            ensure!(Self::registrars().contains(&registrar), "Registrar does not exist");
+            <Registrars<T>>::mutate(|registrars| registrars.retain(|r| r != &registrar));
+            Self::deposit_event(RawEvent::RegistrarRemoved(registrar));
+            Ok(())
+        }
+        
+        /// A registrar attests that the verified keys belong to a verified business until the expiry block
+        fn attest_keys(
+            origin,
+            user_hash: UserNameHash,
+            expiry: T::BlockNumber
+        ) -> Result {
+            let registrar = ensure_signed(origin)?;
+            ensure!(Self::registrars().contains(&registrar), "Not a registrar");
+            ensure!(expiry > <system::Module<T>>::block_number(), "Expiry must be in the future");
+            // only verified keys can be attested
+            ensure!(Self::user_keys_verified(&user_hash) == Some(true), "Keys are not verified");
+            let enc_key = Self::public_key_enc(&user_hash).ok_or("Storage Read Error: cannot get encryption key")?; 
+            let sign_key = Self::public_key_sign(&user_hash).ok_or("Storage Read Error: cannot get signature key")?; 
+            
+            <Attestations<T>>::insert(&user_hash, (registrar.clone(), enc_key, sign_key, expiry, false));
+            
+            Self::deposit_event(RawEvent::KeysAttested(user_hash, registrar, expiry));
+            Ok(())
+        }
+        
+        /// The registrar that made the attestation revokes it
+        fn revoke_attestation(origin, user_hash: UserNameHash) -> Result {
+            let registrar = ensure_signed(origin)?;
+            let mut attestation = Self::attestations(&user_hash).ok_or("Storage Read Error: cannot get attestation")?;
+            ensure!(attestation.0 == registrar, "Not the attesting registrar");
+            ensure!(!attestation.4, "Attestation already revoked");
+            attestation.4 = true;
+            <Attestations<T>>::insert(&user_hash, attestation);
+            
+            Self::deposit_event(RawEvent::AttestationRevoked(user_hash, registrar));
+            Ok(())
+        }
+        
         /// this should only be called when there is data to verify.
         /// TODO implement a significant fee
         fn auto_verification(
@@ -180,7 +274,7 @@ decl_module! {
             signature: Ed25519signature // detached signature of "discovered ephemeral secret key"
         ) -> Result {
             // transaction must be signed
-            let _user = ensure_signed(origin)?;
+            let user = ensure_signed(origin)?;
 
             // have they signed the decrypted_data with the correct public key? Yes
             let decrypted_data = decrypted.clone(); 
@@ -233,10 +327,102 @@ decl_module! {
             // remove the keys fro the temp storage
             Self::delete_temp_keys(user_hash)?;
             
+            // the signer of the verification controls the keys
+            <KeysOwner<T>>::insert(&user_hash, user);
+            
             Ok(())
                 
         }
         
+        /// Super User sets the deposit reserved for new aliases. Existing aliases keep the deposit reserved when registered.
+        fn set_alias_deposit(origin, deposit: BalanceOf<T>) -> Result {
+            let _who = ensure_root(origin)?;
+            <AliasDeposit<T>>::put(deposit);
+            Self::deposit_event(RawEvent::AliasDepositSet(deposit));
+            Ok(())
+        }
+        
+        /// Registers an unused alias resolving to the signer and the verified box keys of the user hash.
+        /// The signer must have verified the keys themselves. The alias deposit is reserved from the signer.
+        fn register_alias(origin, alias: Alias, user_hash: UserNameHash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::check_alias(&alias)?;
+            ensure!(!<Aliases<T>>::exists(&alias), "Alias is already registered");
+            ensure!(!<AliasOf<T>>::exists(&who), "Account already has an alias");
+            ensure!(Self::owns_keys(&who, user_hash), "Keys are not verified by this account");
+            
+            let deposit = Self::alias_deposit();
+            T::Currency::reserve(&who, deposit)?;
+            
+            <Aliases<T>>::insert(&alias, (who.clone(), user_hash, deposit));
+            <AliasOf<T>>::insert(&who, alias.clone());
+            
+            Self::deposit_event(RawEvent::AliasRegistered(alias, who, user_hash));
+            Ok(())
+        }
+        
+        /// The owner points their alias at the verified box keys of another user hash
+        fn update_alias_keys(origin, user_hash: UserNameHash) -> Result {
+            let who = ensure_signed(origin)?;
+            let alias = Self::alias_of(&who).ok_or("Account has no alias")?;
+            let mut details = Self::aliases(&alias).ok_or("Storage Read Error: cannot get alias")?;
+            ensure!(Self::owns_keys(&who, user_hash), "Keys are not verified by this account");
+            details.1 = user_hash;
+            <Aliases<T>>::insert(&alias, details);
+            
+            Self::deposit_event(RawEvent::AliasKeysUpdated(alias, user_hash));
+            Ok(())
+        }
+        
+        /// The owner offers their alias to another account. A new offer replaces any previous one.
+        fn offer_alias_transfer(origin, recipient: T::AccountId) -> Result {
+            let who = ensure_signed(origin)?;
+            let alias = Self::alias_of(&who).ok_or("Account has no alias")?;
+            ensure!(recipient != who, "Cannot offer the alias to the owner");
+            <AliasTransferOffer<T>>::insert(&alias, recipient.clone());
+            
+            Self::deposit_event(RawEvent::AliasTransferOffered(alias, who, recipient));
+            Ok(())
+        }
+        
+        /// The recipient of an offer accepts the alias for the verified box keys of their user hash. 
+        /// The recipient reserves the current alias deposit and the deposit of the previous owner is returned.
+        fn accept_alias_transfer(origin, alias: Alias, user_hash: UserNameHash) -> Result {
+            let who = ensure_signed(origin)?;
+            ensure!(Self::alias_transfer_offer(&alias) == Some(who.clone()), "Alias is not offered to this account");
+            ensure!(!<AliasOf<T>>::exists(&who), "Account already has an alias");
+            ensure!(Self::owns_keys(&who, user_hash), "Keys are not verified by this account");
+            let (previous, _, previous_deposit) = Self::aliases(&alias).ok_or("Storage Read Error: cannot get alias")?;
+            
+            let deposit = Self::alias_deposit();
+            T::Currency::reserve(&who, deposit)?;
+            T::Currency::unreserve(&previous, previous_deposit);
+            
+            <AliasTransferOffer<T>>::remove(&alias);
+            <AliasOf<T>>::remove(&previous);
+            <AliasOf<T>>::insert(&who, alias.clone());
+            <Aliases<T>>::insert(&alias, (who.clone(), user_hash, deposit));
+            
+            Self::deposit_event(RawEvent::AliasTransferred(alias, previous, who));
+            Ok(())
+        }
+        
+        /// The owner releases their alias, which becomes available to register. The deposit is returned.
+        fn release_alias(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            let alias = Self::alias_of(&who).ok_or("Account has no alias")?;
+            let (_, _, deposit) = Self::aliases(&alias).ok_or("Storage Read Error: cannot get alias")?;
+            
+            T::Currency::unreserve(&who, deposit);
+            
+            <Aliases<T>>::remove(&alias);
+            <AliasOf<T>>::remove(&who);
+            <AliasTransferOffer<T>>::remove(&alias);
+            
+            Self::deposit_event(RawEvent::AliasReleased(alias, who));
+            Ok(())
+        }
+        
         // a unique User registers (untrusted/unvalidated) encryption and signing keys
         /// TODO implement a significant fee
         fn register_keys(
@@ -249,7 +435,7 @@ decl_module! {
         ) -> Result {
             
             // check that the transaction is signed
-            let _user = ensure_signed(origin)?;
+            let user = ensure_signed(origin)?;
             // if the usernamehash exists, compare keys
             
             // TODO Errors can occur here!!!! Need to validate inputs.
@@ -295,7 +481,11 @@ decl_module! {
                         // set the verification status to false.
                         Self::set_verification_state(user_hash, false)?;
 
-                    }; // if the keys are the same, do nothing    
+                    } else if !<KeysOwner<T>>::exists(&user_hash) {
+                        // Keys verified before the owner was recorded are claimed by signing them again
+                        ensure!(signature.verify(&encoded_data[..], &old_sign_key), "Invalid signature for this key");
+                        <KeysOwner<T>>::insert(&user_hash, user);
+                    }; // if the keys are the same, do nothing else
                     
                     
                 }, 
@@ -325,12 +515,69 @@ decl_event!(
     where
     AccountId = <T as system::Trait>::AccountId,
     Hash = <T as system::Trait>::Hash,
+        BlockNumber = <T as system::Trait>::BlockNumber,
+    Balance = BalanceOf<T>,
     {
         SubmitedKeys(AccountId, Hash),
+        RegistrarAdded(AccountId),
+        RegistrarRemoved(AccountId),
+        /// User hash, registrar, expiry
+        KeysAttested(UserNameHash, AccountId, BlockNumber),
+        /// User hash, registrar
+        AttestationRevoked(UserNameHash, AccountId),
+        /// Alias, owner, user hash of the box keys
+        AliasRegistered(Alias, AccountId, UserNameHash),
+        /// Alias, user hash of the box keys
+        AliasKeysUpdated(Alias, UserNameHash),
+        /// Alias, owner, recipient
+        AliasTransferOffered(Alias, AccountId, AccountId),
+        /// Alias, previous owner, new owner
+        AliasTransferred(Alias, AccountId, AccountId),
+        /// Alias, previous owner
+        AliasReleased(Alias, AccountId),
+        AliasDepositSet(Balance),
     }
 );
 
 impl<T: Trait> Module<T> {
+    /// Resolves an alias to the owner and the box (encryption) key, only if the keys are still verified
+    pub fn resolve_alias(alias: Alias) -> Option<(T::AccountId, EncryptPublicKey)> {
+        let (owner, user_hash, _) = Self::aliases(&alias)?;
+        let key = <Self as KeyVerifying<T::AccountId, UserNameHash>>::verified_encryption_key(user_hash)?;
+        Some((owner, key))
+    }
+
+    /// Whether the account verified the keys of the user hash, so that an alias cannot resolve to another user's keys
+    fn owns_keys(who: &T::AccountId, user_hash: UserNameHash) -> bool {
+        <Self as KeyVerifying<T::AccountId, UserNameHash>>::is_keys_owner(who, user_hash)
+    }
+
+    /// Aliases are bounded and limited to lower case letters, digits, '-', '_' and '.' so that they are unique when read
+    fn check_alias(alias: &Alias) -> Result {
+        ensure!(alias.len() >= MIN_ALIAS_LENGTH && alias.len() <= MAX_ALIAS_LENGTH, "Alias length is out of bounds");
+        ensure!(
+            alias.iter().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || *c == b'-' || *c == b'_' || *c == b'.'),
+            "Alias contains invalid characters"
+        );
+        Ok(())
+    }
+
+    /// Checks that the current keys for the user hash are attested by a registrar. The attestation must not be revoked 
+    /// or expired, the registrar must still be registered and the keys must not have been replaced since attesting.
+    pub fn is_attested(user_hash: UserNameHash) -> bool {
+        match Self::attestations(&user_hash) {
+            Some((registrar, enc_key, sign_key, expiry, revoked)) => {
+                !revoked 
+                && expiry > <system::Module<T>>::block_number()
+                && Self::registrars().contains(&registrar)
+                && Self::user_keys_verified(&user_hash) == Some(true)
+                && Self::public_key_enc(&user_hash) == Some(enc_key)
+                && Self::public_key_sign(&user_hash) == Some(sign_key)
+            },
+            None => false,
+        }
+    }
+
     fn get_pseudo_random_value(data: &SignedData<UserNameHash, EncryptPublicKey, SignedBy, EncryptNonce>) -> [u8; 16] {
         let input = (
             <timestamp::Module<T>>::get(),
@@ -436,4 +683,95 @@ impl<T: Trait> Module<T> {
     
         return true;
     }
-}
\ No newline at end of file
+}
+
+impl<T: Trait> KeyVerifying<T::AccountId, UserNameHash> for Module<T> {
+    /// Returns the encryption key of the user hash, only if the keys have been verified
+    fn verified_encryption_key(user_hash: UserNameHash) -> Option<EncryptPublicKey> {
+        match Self::user_keys_verified(&user_hash) {
+            Some(true) => Self::public_key_enc(&user_hash),
+            _ => None,
+        }
+    }
+    /// Whether the account proved control of the verified keys of the user hash
+    fn is_keys_owner(who: &T::AccountId, user_hash: UserNameHash) -> bool {
+        Self::user_keys_verified(&user_hash) == Some(true) && Self::keys_owner(&user_hash).as_ref() == Some(who)
+    }
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use support::{assert_err, assert_ok};
+    use crate::mock::{account, Origin, Test};
+
+    type BoxKeys = Module<Test>;
+    type Balances = balances::Module<Test>;
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![(account(1), 5_000), (account(2), 5_000)])
+    }
+
+    // Keys of the user hash as left by a successful verification signed by the account
+    fn verify_keys(user_hash: UserNameHash, who: sr25519::Public) {
+        <UserKeysVerified<Test>>::insert(&user_hash, true);
+        <PublicKeyEnc<Test>>::insert(&user_hash, H256::repeat_byte(9));
+        <KeysOwner<Test>>::insert(&user_hash, who);
+    }
+
+    #[test]
+    fn aliases_need_keys_verified_by_the_signer() {
+        with_externalities(&mut new_test_ext(), || {
+            let mine = H256::repeat_byte(1);
+            let theirs = H256::repeat_byte(2);
+            let unverified = H256::repeat_byte(3);
+            verify_keys(mine, account(1));
+            verify_keys(theirs, account(2));
+            <UserKeysVerified<Test>>::insert(&unverified, false);
+            <KeysOwner<Test>>::insert(&unverified, account(1));
+
+            assert_err!(
+                BoxKeys::register_alias(Origin::signed(account(1)), b"alice".to_vec(), theirs),
+                "Keys are not verified by this account"
+            );
+            assert_err!(
+                BoxKeys::register_alias(Origin::signed(account(1)), b"alice".to_vec(), unverified),
+                "Keys are not verified by this account"
+            );
+            assert_ok!(BoxKeys::register_alias(Origin::signed(account(1)), b"alice".to_vec(), mine));
+            assert_eq!(BoxKeys::resolve_alias(b"alice".to_vec()), Some((account(1), H256::repeat_byte(9))));
+
+            assert_err!(BoxKeys::update_alias_keys(Origin::signed(account(1)), theirs), "Keys are not verified by this account");
+
+            assert_ok!(BoxKeys::offer_alias_transfer(Origin::signed(account(1)), account(2)));
+            assert_err!(
+                BoxKeys::accept_alias_transfer(Origin::signed(account(2)), b"alice".to_vec(), mine),
+                "Keys are not verified by this account"
+            );
+            assert_ok!(BoxKeys::accept_alias_transfer(Origin::signed(account(2)), b"alice".to_vec(), theirs));
+            assert_eq!(BoxKeys::aliases(b"alice".to_vec()), Some((account(2), theirs, DEFAULT_ALIAS_DEPOSIT)));
+            assert_eq!(Balances::reserved_balance(&account(1)), 0);
+        });
+    }
+
+    #[test]
+    fn alias_deposit_defaults_to_non_zero() {
+        with_externalities(&mut new_test_ext(), || {
+            let mine = H256::repeat_byte(1);
+            verify_keys(mine, account(1));
+            assert_eq!(BoxKeys::alias_deposit(), DEFAULT_ALIAS_DEPOSIT);
+
+            assert_ok!(BoxKeys::register_alias(Origin::signed(account(1)), b"alice".to_vec(), mine));
+            assert_eq!(Balances::reserved_balance(&account(1)), DEFAULT_ALIAS_DEPOSIT);
+            assert_eq!(Balances::free_balance(&account(1)), 5_000 - DEFAULT_ALIAS_DEPOSIT);
+
+            assert_ok!(BoxKeys::release_alias(Origin::signed(account(1))));
+            assert_eq!(Balances::reserved_balance(&account(1)), 0);
+            assert_eq!(Balances::free_balance(&account(1)), 5_000);
+        });
+    }
+}
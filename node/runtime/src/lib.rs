@@ -40,6 +40,7 @@
 #![recursion_limit="256"]
 
 use rstd::prelude::*;
+use parity_codec::{Compact, Encode};
 use support::construct_runtime;
 use substrate_primitives::u32_trait::{_2, _4};
 use node_primitives::{
@@ -48,7 +49,7 @@ use node_primitives::{
 use grandpa::fg_primitives::{self, ScheduledChange};
 use client::{
 	block_builder::api::{self as block_builder_api, InherentData, CheckInherentsResult},
-	runtime_api as client_api, impl_runtime_apis
+	runtime_api as client_api, impl_runtime_apis, decl_runtime_apis
 };
 use runtime_primitives::{ApplyResult, generic, create_runtime_str};
 use runtime_primitives::transaction_validity::TransactionValidity;
@@ -81,15 +82,18 @@ mod archive;
 mod bonsai;
 mod bonsai_traits;
 mod boxkeys;
+mod boxkeys_traits;
 mod orders;
+mod orders_core;
 mod orders_traits;
 mod prefunding;
-mod prefunding_traits;
 mod projects;
 mod projects_traits;
 mod timekeeping;
 mod timekeeping_traits;
 mod transfer;
+mod mock;
+mod tests;
 // mod crowdsale;
 // mod crowdsale_traits;
 
@@ -102,7 +106,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	// for block authoring // fork risk, on change
 	authoring_version: 1,
 	// spec version // fork risk, on change
-	spec_version: 21,
+	spec_version: 22,
     // incremental changes
 	impl_version: 2,
 	apis: RUNTIME_API_VERSIONS,
@@ -118,21 +122,29 @@ pub fn native_version() -> NativeVersion {
 }
 
 // Totem implemented for converting between Accounting Balances and Internal Balances
+// Ledger balances are denominated in the smallest unit of XTX scaled by accounting::LEDGER_UNIT_SCALE. 
+// Amounts outside the range of the target type saturate instead of wrapping.
 pub struct ConversionHandler;
 
 // Basic type conversion
 impl ConversionHandler {
-	fn signed_to_unsigned(x: i128) -> u128 { x.abs() as u128 }
+	fn signed_to_unsigned(x: i128) -> u128 { 
+		(x.checked_abs().unwrap_or(i128::max_value()) as u128).saturating_mul(accounting::LEDGER_UNIT_SCALE) 
+	}
+	fn unsigned_to_signed(x: u128) -> i128 { 
+		let ledger_units: u128 = x / accounting::LEDGER_UNIT_SCALE;
+		if ledger_units > i128::max_value() as u128 { i128::max_value() } else { ledger_units as i128 }
+	}
 }
 
 // Takes the AccountBalance and converts for use with BalanceOf<T>
 impl Convert<i128, u128> for ConversionHandler {
-	fn convert(x: i128) -> u128 { Self::signed_to_unsigned(x) as u128 }
+	fn convert(x: i128) -> u128 { Self::signed_to_unsigned(x) }
 }
 
 // Takes BalanceOf<T> and converts for use with AccountBalance type
 impl Convert<u128, i128> for ConversionHandler {
-    fn convert(x: u128) -> i128 { x as i128 }
+    fn convert(x: u128) -> i128 { Self::unsigned_to_signed(x) }
 }
 
 // Takes integer u64 and converts for use with AccountOf<T> type or BlockNumber
@@ -153,11 +165,6 @@ impl Convert<i128, i128> for ConversionHandler {
 impl Convert<u128, u128> for ConversionHandler {
     fn convert(x: u128) -> u128 { x }
 }
-// Used to convert to associated type UnLocked<T> 
-impl Convert<bool, bool> for ConversionHandler {
-    fn convert(x: bool) -> bool { x }
-}
-
 // Takes Vec<u8> encoded hash and converts for as a LockIdentifier type
 impl Convert<Vec<u8>, [u8;8]> for ConversionHandler {
 	fn convert(x: Vec<u8>) -> [u8;8] { 
@@ -205,6 +212,7 @@ impl accounting::Trait for Runtime {
 	type Event = Event;
 	type CoinAmount = Balance;
 	type AccountingConversions = ConversionHandler;
+	type OnPeriodClose = TimekeepingModule;
 }
 
 impl aura::Trait for Runtime {
@@ -319,6 +327,9 @@ impl finality_tracker::Trait for Runtime {
 // Totem impl
 impl projects::Trait for Runtime {
 	type Event = Event;
+	type Currency = balances::Module<Self>;
+	type ProjectConversions = ConversionHandler;
+	type Accounting = accounting::Module<Self>;
 }
 
 impl timekeeping::Trait for Runtime {
@@ -328,6 +339,7 @@ impl timekeeping::Trait for Runtime {
 
 impl boxkeys::Trait for Runtime {
 	type Event = Event;
+	type Currency = balances::Module<Self>;
 }
 
 impl bonsai::Trait for Runtime {
@@ -348,6 +360,8 @@ impl prefunding::Trait for Runtime {
 	type Currency = balances::Module<Self>;
 	type PrefundingConversions = ConversionHandler;
     type Accounting = accounting::Module<Self>;
+    type Settlement = ();
+	type Holds = OrdersModule;
 }
 
 impl orders::Trait for Runtime {
@@ -356,10 +370,19 @@ impl orders::Trait for Runtime {
 	type Prefunding = PrefundingModule;
 	type OrderConversions = ConversionHandler;
     type Bonsai = BonsaiModule;
+	type Projects = ProjectModule;
+	type Reminders = ReminderHandler;
+	type BoxKeys = BoxKeyS;
+	type Timekeeping = TimekeepingModule;
+	type ComplianceOrigin = council_motions::EnsureMembers<_2>;
 }
 
 impl funding::Trait for Runtime {
 	type Event = Event;
+	type Currency = balances::Module<Self>;
+	type FundingConversions = ConversionHandler;
+	type Accounting = accounting::Module<Self>;
+	type Statements = StatementHandler;
 }
 
 impl transfer::Trait for Runtime {
@@ -368,6 +391,75 @@ impl transfer::Trait for Runtime {
 	type TransferConversions = ConversionHandler;
 	type Bonsai = BonsaiModule;
 	type Accounting = accounting::Module<Self>;
+	type BoxKeys = BoxKeyS;
+}
+
+/// Submits the reminders found by the orders offchain worker as inherents
+pub struct ReminderHandler;
+
+impl orders_traits::Reminding<AccountId, Hash> for ReminderHandler {
+	fn submit_reminder(h: Hash, deadline: u64, notifications: Vec<(AccountId, Vec<u8>, Hash)>) {
+		let call = Call::OrdersModule(orders::Call::record_reminder(h, deadline, notifications));
+		runtime_io::submit_extrinsic(&UncheckedExtrinsic::new_unsigned(call));
+	}
+}
+
+/// Submits the statements built by the funding offchain worker as inherents
+pub struct StatementHandler;
+
+impl funding::StatementIssuing<AccountId, BlockNumber> for StatementHandler {
+	fn submit_statement(id: u64, statement: funding::HolderStatement<AccountId, BlockNumber>) {
+		let call = Call::FundingModule(funding::Call::record_statement(id, statement));
+		runtime_io::submit_extrinsic(&UncheckedExtrinsic::new_unsigned(call));
+	}
+}
+
+/// Upper bound of the bytes a signed extrinsic adds to its call: length prefix, version, account address,
+/// signature, nonce and mortal era
+const SIGNED_EXTRINSIC_OVERHEAD: u64 = 2 + 1 + 33 + 64 + 9 + 2;
+
+/// Transaction fees of the steps of an order lifecycle (create, accept, invoice, settle) under the current fee model,
+/// as (order status after the step, fee) and the total. The fee model charges a base fee plus a fee per byte of the
+/// extrinsic. The postings made by each step are accounted for but not charged.
+fn order_lifecycle_fees(item_count: u32, with_project: bool) -> (Vec<(u16, Balance)>, Balance) {
+	let project_hash: Option<Hash> = match with_project {
+		true => Some(Hash::default()),
+		false => None,
+	};
+	// the order items are the only variable length argument, so the call is encoded without them
+	// and the items are added to the length.
+	let create = Call::OrdersModule(orders::Call::create_order(
+		AccountId::default(),
+		AccountId::default(),
+		0u16,
+		0i128,
+		false,
+		0u16,
+		0u64,
+		0u64,
+		Vec::new(),
+		project_hash,
+		0u16,
+		orders::TXKeysL::default(),
+	));
+	let items_length: u64 = (orders::OrderItem::<Hash>::default().encode().len() as u64).saturating_mul(item_count as u64)
+		+ Compact(item_count).encode().len() as u64
+		- Compact(0u32).encode().len() as u64;
+	let create_length: u64 = create.encode().len() as u64 + items_length;
+	let handle_length = |s: u16| Call::OrdersModule(orders::Call::handle_spfso(Hash::default(), s, Hash::default())).encode().len() as u64;
+
+	let fee = |length: u64| -> Balance {
+		let length: Balance = length.saturating_add(SIGNED_EXTRINSIC_OVERHEAD).into();
+		Balances::transaction_base_fee().saturating_add(Balances::transaction_byte_fee().saturating_mul(length))
+	};
+	let steps: Vec<(u16, Balance)> = vec![
+		(0u16, fee(create_length)),
+		(1u16, fee(handle_length(1u16))),
+		(5u16, fee(handle_length(5u16))),
+		(6u16, fee(handle_length(6u16))),
+	];
+	let total: Balance = steps.iter().fold(0, |total, step| total.saturating_add(step.1));
+	(steps, total)
 }
 
 construct_runtime!(
@@ -377,7 +469,7 @@ construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic
 	{
 		System: system::{default, Log(ChangesTrieRoot)},
-		Accounting: accounting::{Module, Storage, Event<T>},
+		Accounting: accounting::{Module, Call, Storage, Event<T>},
 		Aura: aura::{Module, Inherent(Timestamp)},
 		Timestamp: timestamp::{Module, Call, Storage, Config<T>, Inherent},
 		Consensus: consensus::{Module, Call, Storage, Config<T>, Log(AuthoritiesChange), Inherent},
@@ -403,7 +495,7 @@ construct_runtime!(
 		OrdersModule: orders::{Module, Call, Storage, Event<T>},
         PrefundingModule: prefunding::{Module, Call, Storage, Event<T>},
         FundingModule: funding::{Module, Call, Storage, Event<T>},
-        TransferModule: transfer::{Module, Call, Event<T>},
+        TransferModule: transfer::{Module, Call, Storage, Event<T>},
 	}
 );
 
@@ -422,7 +514,186 @@ pub type UncheckedExtrinsic = generic::UncheckedMortalCompactExtrinsic<Address,
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Index, Call>;
 /// Executive: handles dispatch to the various modules.
-pub type Executive = executive::Executive<Runtime, Block, system::ChainContext<Runtime>, Balances, AllModules>;
+pub type Executive = executive::Executive<Runtime, Block, system::ChainContext<Runtime>, accounting::AccountedPayment<Runtime, Balances, Balances>, AllModules>;
+
+decl_runtime_apis! {
+	/// Totem project profitability: revenue and cost posted by the identity against the orders of a project
+	pub trait ProfitabilityApi {
+		fn project_profitability(owner: AccountId, project: Hash) -> (i128, i128);
+	}
+
+	/// Totem settlement certificates (proof of payment) by certificate id, and the certificate id with the certificate
+	/// of a settled reference. The certificate id is the hash of the encoded certificate.
+	pub trait SettlementCertificateApi {
+		fn settlement_certificate(id: Hash) -> Option<prefunding::SettlementCertificate<AccountId, Hash, BlockNumber>>;
+		fn settlement_certificate_of(reference: Hash) -> Option<(Hash, prefunding::SettlementCertificate<AccountId, Hash, BlockNumber>)>;
+	}
+
+	/// Totem reporting by dimension, the reference hash a posting is made against (order, prefunding or project):
+	/// the balance of an identity on a ledger account for a dimension, and the profit and loss of a project 
+	/// (account, movement) between two blocks over the project and its orders
+	pub trait DimensionReportingApi {
+		fn balance_by_dimension(who: AccountId, ledger: u64, dimension: Hash) -> i128;
+		fn pl_by_project(who: AccountId, project: Hash, from_block: u64, to_block: u64) -> Vec<(u64, i128)>;
+	}
+
+	/// Totem key discovery by alias: the owner of an alias and the verified box (encryption) key it resolves to
+	pub trait AliasApi {
+		fn resolve_alias(alias: Vec<u8>) -> Option<(AccountId, boxkeys::EncryptPublicKey)>;
+	}
+
+	/// Totem global ledger for explorers: (account, balance) in pages ordered by account number,
+	/// and totals per statement type (first digit of the account number)
+	pub trait GlobalLedgerApi {
+		fn global_ledger(start: u32, count: u32) -> Vec<(u64, i128)>;
+		fn global_ledger_totals() -> Vec<(u8, i128)>;
+	}
+
+	/// Totem network status: the ledger accounts of the network identities (escrow, network fees) with
+	/// (account, balance, postings in the recent blocks), and the global ledger balances of the key network accounts
+	pub trait NetworkStatusApi {
+		fn network_status() -> (Vec<(AccountId, Vec<(u64, i128, u32)>)>, Vec<(u64, i128)>);
+	}
+
+	/// Totem chart of accounts display metadata: (decimals, display unit, quantity account) per ledger account,
+	/// and the decimals of XTX in which ledger balances are denominated (smallest unit)
+	pub trait ChartOfAccountsApi {
+		fn account_display(account: u64) -> Option<(u8, Vec<u8>, bool)>;
+		fn accounts_display() -> Vec<(u64, (u8, Vec<u8>, bool))>;
+		fn ledger_decimals() -> u8;
+		fn chart_version_at(at_block: BlockNumber) -> u32;
+		fn chart_account(account: u64, at_block: BlockNumber) -> u64;
+	}
+
+	/// Totem contra accounts of the chart of accounts (contra account, parent account), and the balances of an identity
+	/// presented net of the contra accounts (account, gross balance, balance of the contra accounts, net balance)
+	pub trait ContraAccountsApi {
+		fn contra_accounts() -> Vec<(u64, u64)>;
+		fn net_presentation(who: AccountId, at_block: BlockNumber) -> Vec<(u64, i128, i128, i128)>;
+	}
+
+	/// Totem figures of an identity in XTX and converted into its reporting currency at the rate for the period of a block:
+	/// the reporting currency with the trial balance (account, balance, converted balance) 
+	/// or the totals per statement type (statement type, total, converted total)
+	pub trait ReportingCurrencyApi {
+		fn trial_balance(who: AccountId, at_block: BlockNumber) -> (Option<u16>, Vec<(u64, i128, Option<i128>)>);
+		fn statement_totals(who: AccountId, at_block: BlockNumber) -> (Option<u16>, Vec<(u8, i128, Option<i128>)>);
+	}
+
+	/// Totem accounting equation of an identity: assets, liabilities, equity, revenue and expenses totals, and whether 
+	/// assets equal liabilities and equity once the profit and loss is notionally closed
+	pub trait AccountingEquationApi {
+		fn accounting_equation(who: AccountId) -> accounting::AccountingEquation;
+	}
+
+	/// Totem credit control: the open receivables of an identity from each counterparty with a credit limit or 
+	/// an open receivable (counterparty, open receivables, credit limit and whether it is enforced)
+	pub trait CreditControlApi {
+		fn credit_exposure(who: AccountId) -> Vec<(AccountId, i128, Option<(i128, bool)>)>;
+	}
+
+	/// Totem dry run of the period close of an identity for review and approval before closing: the last close,
+	/// the budget variances (account, budget, actual, variance) and the proposed closing entries (account, amount, credit)
+	pub trait ClosePreviewApi {
+		fn preview_close(who: AccountId) -> accounting::ClosePreview<BlockNumber>;
+	}
+
+	/// Totem postings committed in a block range for incremental off-chain indexing:
+	/// (block, identity, account, amount, posting index, reference hash) in pages,
+	/// and the posting index and lines (account, amount, credit) of a journal of an identity by journal number
+	pub trait PostingsApi {
+		fn postings_in_range(from_block: BlockNumber, to_block: BlockNumber, page: u32) -> Vec<(BlockNumber, AccountId, u64, i128, u128, Hash)>;
+		fn postings_in_range_by_origin(from_block: BlockNumber, to_block: BlockNumber, page: u32, origin: u8) -> Vec<(BlockNumber, AccountId, u64, i128, u128, Hash)>;
+		fn journal_entry(who: AccountId, number: u64) -> Option<(u128, Vec<(u64, i128, bool)>)>;
+	}
+
+	/// Totem tags on the journals (user entries) of an identity for lightweight custom analytics:
+	/// the journals carrying a tag (journal number, posting index) and the movement on a ledger account of those journals
+	pub trait PostingTagsApi {
+		fn journals_by_tag(who: AccountId, tag: Vec<u8>) -> Vec<(u64, u128)>;
+		fn balance_by_tag(who: AccountId, ledger: u64, tag: Vec<u8>) -> i128;
+	}
+
+	/// Totem prefunding state of a reference:
+	/// (owner, beneficiary, amount, deadline, status, total adjustments, total credits),
+	/// the reference status codes with their names and the statuses the runtime can move a status to,
+	/// the days a payer takes to pay per beneficiary (beneficiary, settled invoices, average days, longest days)
+	/// and the invoices of a beneficiary awaiting settlement (reference, payer, invoice block, days outstanding).
+	/// Invariant checks: the supplied (account, lock id) pairs that are orphaned locks, 
+	/// and the references of an owner with a prefunding but no currency lock.
+	/// The prefunds awaiting acceptance whose deadline falls within the next blocks (reference, deadline).
+	pub trait PrefundingApi {
+		fn prefunding_state(reference: Hash) -> Option<(AccountId, AccountId, Balance, BlockNumber, u16, u128, u128)>;
+		fn status_codes() -> Vec<(u16, Vec<u8>)>;
+		fn valid_transitions(status: u16) -> Vec<u16>;
+		fn escrow_reconciliation(owner: AccountId) -> Vec<(Hash, u128, i128)>;
+		fn days_to_pay(payer: AccountId) -> Vec<(AccountId, u32, u32, u32)>;
+		fn receivables_aging(beneficiary: AccountId) -> Vec<(Hash, AccountId, BlockNumber, u32)>;
+		fn orphaned_locks(candidates: Vec<(AccountId, [u8; 8])>) -> Vec<(AccountId, [u8; 8])>;
+		fn unlocked_prefundings(owner: AccountId) -> Vec<Hash>;
+		fn open_items(me: AccountId, counterparty: AccountId) -> (Vec<(Hash, i128)>, Vec<(Hash, i128)>, i128);
+		fn expiring_prefundings(within: u64) -> Vec<(Hash, BlockNumber)>;
+	}
+
+	/// Totem transaction fees of the order lifecycle before submission. Per step (order status after the step, fee)
+	/// and the total, for an order with the given number of items, optionally assigned to a project.
+	pub trait OrderFeesApi {
+		fn estimate_order_fees(item_count: u32, with_project: bool) -> (Vec<(u16, Balance)>, Balance);
+	}
+
+	/// Totem overdue invoices for dunning workflows: of a payer (order, payee, due block) 
+	/// and of a payee (order, payer, due block)
+	pub trait OverdueInvoicesApi {
+		fn overdue_payables(payer: AccountId) -> Vec<(Hash, AccountId, u64)>;
+		fn overdue_receivables(payee: AccountId) -> Vec<(Hash, AccountId, u64)>;
+	}
+
+	/// Totem notification registry for integrations that deliver the order notifications themselves: the parties to an 
+	/// order and its observers subscribed to any of the events (bitmask: 1 reminders, 2 status changes, 4 settlement) with
+	/// (party, endpoint, shared secret hash). Reminders are also published on-chain by the orders offchain worker.
+	pub trait OrderNotificationsApi {
+		fn notification_subscribers(order: Hash, events: u16) -> Vec<(AccountId, Vec<u8>, Hash)>;
+	}
+
+	/// Totem order book statistics per day: the current period, (period, orders created, accepted, settled, cancelled
+	/// and value settled) from a period and the total value of all orders settled
+	pub trait OrderStatisticsApi {
+		fn statistics_period() -> u64;
+		fn order_statistics(from_period: u64, count: u64) -> Vec<(u64, orders::OrderStatistics)>;
+		fn total_value_settled() -> u128;
+	}
+
+	/// Totem ISO 11649 structured creditor references for reconciling payments in external accounting software
+	pub trait CreditorReferenceApi {
+		fn generate_creditor_reference(base: Vec<u8>) -> Option<Vec<u8>>;
+		fn incoming_payments(to: AccountId, reference: Vec<u8>) -> Vec<(AccountId, Balance, Hash)>;
+	}
+
+	/// Totem token supply statistics for exchanges and data aggregators: 
+	/// (distributed, locked under vesting, circulating) and the transfers per velocity period (period, transfers, volume)
+	pub trait FundingStatisticsApi {
+		fn supply_statistics() -> (u128, u128, u128);
+		fn transfer_velocity(from_period: u64, count: u64) -> Vec<(u64, u32, u128)>;
+	}
+
+	/// Totem token header: transfer status, maximum issuance, issued, unissued, total distributed and controller
+	pub trait FundingInfoApi {
+		fn funding_info() -> funding::FundingInfo<AccountId>;
+	}
+
+	/// Totem token movements of a holder (transfers, distributions, fees, burns and dust sweeps) from a movement id, 
+	/// at most count movements. Only the latest movements are kept on chain.
+	pub trait HolderMovementsApi {
+		fn holder_movements(holder: AccountId, start: u64, count: u64) -> Vec<(u64, funding::HolderMovement<AccountId, Hash, BlockNumber>)>;
+	}
+
+	/// Totem token migration to the next chain: the migration set (registrations, total, merkle root, finalization block) 
+	/// and its registrations (index, holder, amount) from an index, at most count registrations, for the next genesis
+	pub trait MigrationApi {
+		fn migration_set() -> funding::MigrationSet<Hash, BlockNumber>;
+		fn migration_entries(start: u32, count: u32) -> Vec<(u32, AccountId, u128)>;
+	}
+}
 
 impl_runtime_apis! {
 	impl client_api::Core<Block> for Runtime {
@@ -443,6 +714,257 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl self::ProfitabilityApi<Block> for Runtime {
+		fn project_profitability(owner: AccountId, project: Hash) -> (i128, i128) {
+			OrdersModule::project_profitability(owner, project)
+		}
+	}
+
+	impl self::SettlementCertificateApi<Block> for Runtime {
+		fn settlement_certificate(id: Hash) -> Option<prefunding::SettlementCertificate<AccountId, Hash, BlockNumber>> {
+			PrefundingModule::settlement_certificate(id)
+		}
+
+		fn settlement_certificate_of(reference: Hash) -> Option<(Hash, prefunding::SettlementCertificate<AccountId, Hash, BlockNumber>)> {
+			PrefundingModule::settlement_certificate_of(reference)
+		}
+	}
+
+	impl self::DimensionReportingApi<Block> for Runtime {
+		fn balance_by_dimension(who: AccountId, ledger: u64, dimension: Hash) -> i128 {
+			Accounting::balance_by_dimension(who, ledger, dimension)
+		}
+
+		fn pl_by_project(who: AccountId, project: Hash, from_block: u64, to_block: u64) -> Vec<(u64, i128)> {
+			let mut dimensions: Vec<Hash> = OrdersModule::project_orders(project);
+			dimensions.push(project);
+			Accounting::pl_by_dimensions(who, dimensions, from_block, to_block)
+		}
+	}
+
+	impl self::AliasApi<Block> for Runtime {
+		fn resolve_alias(alias: Vec<u8>) -> Option<(AccountId, boxkeys::EncryptPublicKey)> {
+			BoxKeyS::resolve_alias(alias)
+		}
+	}
+
+	impl self::GlobalLedgerApi<Block> for Runtime {
+		fn global_ledger(start: u32, count: u32) -> Vec<(u64, i128)> {
+			Accounting::global_ledger_page(start, count)
+		}
+
+		fn global_ledger_totals() -> Vec<(u8, i128)> {
+			Accounting::global_ledger_totals()
+		}
+	}
+
+	impl self::NetworkStatusApi<Block> for Runtime {
+		fn network_status() -> (Vec<(AccountId, Vec<(u64, i128, u32)>)>, Vec<(u64, i128)>) {
+			Accounting::network_status()
+		}
+	}
+
+	impl self::ReportingCurrencyApi<Block> for Runtime {
+		fn trial_balance(who: AccountId, at_block: BlockNumber) -> (Option<u16>, Vec<(u64, i128, Option<i128>)>) {
+			Accounting::trial_balance(who, at_block)
+		}
+
+		fn statement_totals(who: AccountId, at_block: BlockNumber) -> (Option<u16>, Vec<(u8, i128, Option<i128>)>) {
+			Accounting::statement_totals(who, at_block)
+		}
+	}
+
+	impl self::AccountingEquationApi<Block> for Runtime {
+		fn accounting_equation(who: AccountId) -> accounting::AccountingEquation {
+			Accounting::accounting_equation(who)
+		}
+	}
+
+	impl self::CreditControlApi<Block> for Runtime {
+		fn credit_exposure(who: AccountId) -> Vec<(AccountId, i128, Option<(i128, bool)>)> {
+			Accounting::credit_exposure(who)
+		}
+	}
+
+	impl self::ClosePreviewApi<Block> for Runtime {
+		fn preview_close(who: AccountId) -> accounting::ClosePreview<BlockNumber> {
+			Accounting::preview_close(who)
+		}
+	}
+
+	impl self::ChartOfAccountsApi<Block> for Runtime {
+		fn account_display(account: u64) -> Option<(u8, Vec<u8>, bool)> {
+			Accounting::account_decimals(account)
+		}
+
+		fn accounts_display() -> Vec<(u64, (u8, Vec<u8>, bool))> {
+			Accounting::accounts_display()
+		}
+
+		fn ledger_decimals() -> u8 {
+			Accounting::ledger_decimals()
+		}
+
+		fn chart_version_at(at_block: BlockNumber) -> u32 {
+			Accounting::chart_version_at(at_block)
+		}
+
+		fn chart_account(account: u64, at_block: BlockNumber) -> u64 {
+			Accounting::chart_account(account, at_block)
+		}
+	}
+
+	impl self::ContraAccountsApi<Block> for Runtime {
+		fn contra_accounts() -> Vec<(u64, u64)> {
+			Accounting::contra_accounts()
+		}
+
+		fn net_presentation(who: AccountId, at_block: BlockNumber) -> Vec<(u64, i128, i128, i128)> {
+			Accounting::net_presentation(who, at_block)
+		}
+	}
+
+	impl self::PostingsApi<Block> for Runtime {
+		fn postings_in_range(from_block: BlockNumber, to_block: BlockNumber, page: u32) -> Vec<(BlockNumber, AccountId, u64, i128, u128, Hash)> {
+			Accounting::postings_in_range(from_block, to_block, page)
+		}
+		fn postings_in_range_by_origin(from_block: BlockNumber, to_block: BlockNumber, page: u32, origin: u8) -> Vec<(BlockNumber, AccountId, u64, i128, u128, Hash)> {
+			Accounting::postings_in_range_by_origin(from_block, to_block, page, origin)
+		}
+		fn journal_entry(who: AccountId, number: u64) -> Option<(u128, Vec<(u64, i128, bool)>)> {
+			Accounting::journal_entry(who, number)
+		}
+	}
+
+	impl self::PostingTagsApi<Block> for Runtime {
+		fn journals_by_tag(who: AccountId, tag: Vec<u8>) -> Vec<(u64, u128)> {
+			Accounting::journals_by_tag(who, tag)
+		}
+		fn balance_by_tag(who: AccountId, ledger: u64, tag: Vec<u8>) -> i128 {
+			Accounting::balance_by_tag(who, ledger, tag)
+		}
+	}
+
+	impl self::PrefundingApi<Block> for Runtime {
+		fn prefunding_state(reference: Hash) -> Option<(AccountId, AccountId, Balance, BlockNumber, u16, u128, u128)> {
+			PrefundingModule::prefunding_state(reference)
+		}
+
+		fn status_codes() -> Vec<(u16, Vec<u8>)> {
+			PrefundingModule::status_codes()
+		}
+
+		fn valid_transitions(status: u16) -> Vec<u16> {
+			PrefundingModule::valid_transitions(status)
+		}
+
+		fn escrow_reconciliation(owner: AccountId) -> Vec<(Hash, u128, i128)> {
+			PrefundingModule::escrow_reconciliation(owner)
+		}
+
+		fn days_to_pay(payer: AccountId) -> Vec<(AccountId, u32, u32, u32)> {
+			PrefundingModule::days_to_pay(payer)
+		}
+
+		fn receivables_aging(beneficiary: AccountId) -> Vec<(Hash, AccountId, BlockNumber, u32)> {
+			PrefundingModule::receivables_aging(beneficiary)
+		}
+
+		fn orphaned_locks(candidates: Vec<(AccountId, [u8; 8])>) -> Vec<(AccountId, [u8; 8])> {
+			PrefundingModule::orphaned_locks(candidates)
+		}
+
+		fn unlocked_prefundings(owner: AccountId) -> Vec<Hash> {
+			PrefundingModule::unlocked_prefundings(owner)
+		}
+
+		fn open_items(me: AccountId, counterparty: AccountId) -> (Vec<(Hash, i128)>, Vec<(Hash, i128)>, i128) {
+			PrefundingModule::open_items(me, counterparty)
+		}
+
+		fn expiring_prefundings(within: u64) -> Vec<(Hash, BlockNumber)> {
+			PrefundingModule::expiring_prefundings(within)
+		}
+	}
+
+	impl self::OrderFeesApi<Block> for Runtime {
+		fn estimate_order_fees(item_count: u32, with_project: bool) -> (Vec<(u16, Balance)>, Balance) {
+			order_lifecycle_fees(item_count, with_project)
+		}
+	}
+
+	impl self::OverdueInvoicesApi<Block> for Runtime {
+		fn overdue_payables(payer: AccountId) -> Vec<(Hash, AccountId, u64)> {
+			OrdersModule::overdue_payables_of(payer)
+		}
+
+		fn overdue_receivables(payee: AccountId) -> Vec<(Hash, AccountId, u64)> {
+			OrdersModule::overdue_receivables_of(payee)
+		}
+	}
+
+	impl self::OrderNotificationsApi<Block> for Runtime {
+		fn notification_subscribers(order: Hash, events: u16) -> Vec<(AccountId, Vec<u8>, Hash)> {
+			OrdersModule::notification_subscribers(order, events)
+		}
+	}
+
+	impl self::OrderStatisticsApi<Block> for Runtime {
+		fn statistics_period() -> u64 {
+			OrdersModule::statistics_period()
+		}
+
+		fn order_statistics(from_period: u64, count: u64) -> Vec<(u64, orders::OrderStatistics)> {
+			OrdersModule::order_statistics_from(from_period, count)
+		}
+
+		fn total_value_settled() -> u128 {
+			OrdersModule::total_value_settled()
+		}
+	}
+
+	impl self::CreditorReferenceApi<Block> for Runtime {
+		fn generate_creditor_reference(base: Vec<u8>) -> Option<Vec<u8>> {
+			TransferModule::generate_creditor_reference(base)
+		}
+
+		fn incoming_payments(to: AccountId, reference: Vec<u8>) -> Vec<(AccountId, Balance, Hash)> {
+			TransferModule::incoming_payments(to, reference)
+		}
+	}
+
+	impl self::FundingStatisticsApi<Block> for Runtime {
+		fn supply_statistics() -> (u128, u128, u128) {
+			FundingModule::supply_statistics()
+		}
+
+		fn transfer_velocity(from_period: u64, count: u64) -> Vec<(u64, u32, u128)> {
+			FundingModule::velocity(from_period, count)
+		}
+	}
+
+	impl self::FundingInfoApi<Block> for Runtime {
+		fn funding_info() -> funding::FundingInfo<AccountId> {
+			FundingModule::funding_info()
+		}
+	}
+
+	impl self::HolderMovementsApi<Block> for Runtime {
+		fn holder_movements(holder: AccountId, start: u64, count: u64) -> Vec<(u64, funding::HolderMovement<AccountId, Hash, BlockNumber>)> {
+			FundingModule::holder_movements(holder, start, count)
+		}
+	}
+
+	impl self::MigrationApi<Block> for Runtime {
+		fn migration_set() -> funding::MigrationSet<Hash, BlockNumber> {
+			FundingModule::migration_set()
+		}
+
+		fn migration_entries(start: u32, count: u32) -> Vec<(u32, AccountId, u128)> {
+			FundingModule::migration_entries(start, count)
+		}
+	}
+
 	impl client_api::Metadata<Block> for Runtime {
 		fn metadata() -> OpaqueMetadata {
 			Runtime::metadata().into()
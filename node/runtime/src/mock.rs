@@ -0,0 +1,187 @@
+//! Test utilities shared by the unit tests of the Totem runtime modules
+
+#![cfg(test)]
+
+use rstd::prelude::*;
+use runtime_primitives::{
+    testing::{Digest, DigestItem, Header},
+    traits::{BlakeTwo256, Convert, IdentityLookup},
+    BuildStorage,
+};
+use substrate_primitives::{sr25519, Blake2Hasher, H256};
+use support::{impl_outer_origin, traits::LockIdentifier};
+use totem_traits::prefunding::mock::MockEncumbrance;
+
+use crate::{bonsai, boxkeys, orders, prefunding, projects, timekeeping, transfer};
+use crate::boxkeys_traits::{ KeyVerifying };
+use crate::orders_traits::{ Reminding };
+use crate::projects_traits::{ Validating as ProjectValidating };
+use crate::timekeeping_traits::{ Billing, Validating as TimeValidating };
+
+impl_outer_origin! {
+    pub enum Origin for Test {}
+}
+
+pub type AccountId = sr25519::Public;
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+impl system::Trait for Test {
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type Digest = Digest;
+    type AccountId = AccountId;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = ();
+    type Log = DigestItem;
+}
+impl timestamp::Trait for Test {
+    type Moment = u64;
+    type OnTimestampSet = ();
+}
+impl accounting::Trait for Test {
+    type Event = ();
+    type CoinAmount = u64;
+    type AccountingConversions = ConversionHandler;
+    type OnPeriodClose = ();
+}
+impl balances::Trait for Test {
+    type Balance = u64;
+    type OnFreeBalanceZero = ();
+    type OnNewAccount = ();
+    type TransactionPayment = ();
+    type TransferPayment = ();
+    type DustRemoval = ();
+    type Event = ();
+    type Accounting = accounting::Module<Test>;
+    type BalancesConversions = ConversionHandler;
+}
+impl bonsai::Trait for Test {
+    type Event = ();
+    type Timekeeping = Stub;
+    type Projects = Stub;
+    type Orders = orders::Module<Test>;
+    type BonsaiConversions = ConversionHandler;
+}
+impl boxkeys::Trait for Test {
+    type Event = ();
+    type Currency = balances::Module<Test>;
+}
+impl prefunding::Trait for Test {
+    type Event = ();
+    type Currency = balances::Module<Test>;
+    type PrefundingConversions = ConversionHandler;
+    type Accounting = accounting::Module<Test>;
+    type Settlement = ();
+    type Holds = ();
+}
+// The orders are tested against the prefunding mock, so that the calls and their outcomes can be scripted
+impl orders::Trait for Test {
+    type Event = ();
+    type OrderConversions = ConversionHandler;
+    type Accounting = accounting::Module<Test>;
+    type Prefunding = MockEncumbrance;
+    type Bonsai = bonsai::Module<Test>;
+    type Projects = Stub;
+    type Reminders = Stub;
+    type BoxKeys = Stub;
+    type Timekeeping = Stub;
+    type ComplianceOrigin = system::EnsureRoot<AccountId>;
+}
+impl projects::Trait for Test {
+    type Event = ();
+    type Currency = balances::Module<Test>;
+    type ProjectConversions = ConversionHandler;
+    type Accounting = accounting::Module<Test>;
+}
+impl timekeeping::Trait for Test {
+    type Event = ();
+    type Projects = Stub;
+}
+impl transfer::Trait for Test {
+    type Event = ();
+    type Currency = balances::Module<Test>;
+    type TransferConversions = ConversionHandler;
+    type Bonsai = bonsai::Module<Test>;
+    type Accounting = accounting::Module<Test>;
+    type BoxKeys = Stub;
+}
+
+pub struct ConversionHandler;
+impl Convert<i128, i128> for ConversionHandler { fn convert(x: i128) -> i128 { x } }
+impl Convert<i128, u128> for ConversionHandler { fn convert(x: i128) -> u128 { x as u128 } }
+impl Convert<i128, u64> for ConversionHandler { fn convert(x: i128) -> u64 { x as u64 } }
+impl Convert<u128, i128> for ConversionHandler { fn convert(x: u128) -> i128 { x as i128 } }
+impl Convert<u128, u64> for ConversionHandler { fn convert(x: u128) -> u64 { x as u64 } }
+impl Convert<u64, i128> for ConversionHandler { fn convert(x: u64) -> i128 { x as i128 } }
+impl Convert<u64, u128> for ConversionHandler { fn convert(x: u64) -> u128 { x as u128 } }
+impl Convert<u64, u64> for ConversionHandler { fn convert(x: u64) -> u64 { x } }
+impl Convert<H256, H256> for ConversionHandler { fn convert(x: H256) -> H256 { x } }
+impl Convert<Vec<u8>, LockIdentifier> for ConversionHandler {
+    fn convert(x: Vec<u8>) -> LockIdentifier {
+        let mut y: LockIdentifier = [0; 8];
+        y.copy_from_slice(&x[..8]);
+        y
+    }
+}
+
+// Stands in for the modules that are not under test
+pub struct Stub;
+impl ProjectValidating<AccountId, H256> for Stub {
+    fn is_project_owner(_: AccountId, _: H256) -> bool { true }
+    fn is_owner_and_project_valid(_: AccountId, _: H256) -> bool { true }
+    fn is_project_valid(_: H256) -> bool { true }
+    fn get_project_owner(_: H256) -> Option<AccountId> { None }
+    fn get_owner_projects(_: AccountId) -> Vec<H256> { Vec::new() }
+}
+impl TimeValidating<AccountId, H256> for Stub {
+    fn is_time_record_owner(_: AccountId, _: H256) -> bool { true }
+    fn validate_and_archive(_: AccountId, _: H256, _: bool) -> bool { true }
+}
+impl Billing<AccountId, H256> for Stub {
+    // Three hours of accepted time, worked after the order was accepted
+    fn billable_time(_: AccountId, _: H256, from: u64, to: u64) -> Vec<(H256, u64)> {
+        if from <= 1 && to >= 1 { vec![(H256::repeat_byte(60), 480), (H256::repeat_byte(61), 240)] } else { Vec::new() }
+    }
+    fn set_invoiced(_: H256, _: Vec<H256>) {}
+}
+impl Reminding<AccountId, H256> for Stub {
+    fn submit_reminder(_: H256, _: u64, _: Vec<(AccountId, Vec<u8>, H256)>) {}
+}
+impl KeyVerifying<AccountId, H256> for Stub {
+    fn verified_encryption_key(h: H256) -> Option<H256> { Some(h) }
+    // The keys of user hash n belong to account n
+    fn is_keys_owner(who: &AccountId, h: H256) -> bool { who.0[0] == h.0[0] }
+}
+
+/// Identity used in tests
+pub fn account(n: u8) -> AccountId {
+    sr25519::Public([n; 32])
+}
+
+/// Genesis storage with the free balances of the accounts, without fees or an existential deposit
+pub fn new_test_ext(balances: Vec<(AccountId, u64)>) -> runtime_io::TestExternalities<Blake2Hasher> {
+    let mut t = system::GenesisConfig::<Test>::default()
+        .build_storage()
+        .unwrap()
+        .0;
+    t.extend(
+        balances::GenesisConfig::<Test> {
+            transaction_base_fee: 0,
+            transaction_byte_fee: 0,
+            balances,
+            existential_deposit: 0,
+            transfer_fee: 0,
+            creation_fee: 0,
+            vesting: vec![],
+        }
+        .build_storage()
+        .unwrap()
+        .0,
+    );
+    t.into()
+}
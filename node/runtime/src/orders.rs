@@ -46,6 +46,23 @@
 //! * The completion state also generates the invoice, and relevant accounting postings for both the buyer and the seller.
 //! * The completed work is then approved by the buyer (or disputed or rejected). An approval triggers the release of prefunds and 
 //! the invoice is marked as settled in the accounts for both parties
+//! * The buyer can require the seller to lock a performance bond (a percentage of the order value) when accepting the order. 
+//! The bond is released at settlement. If the seller defaults (the order is not completed by the due date) the buyer can claim 
+//! the forfeit percentage of the bond and the remainder is released to the seller.
+//! * A company can set standing approval limits (maximum order value, cap per 30 day period) for delegates ordering on its
+//! behalf. Delegate orders within the limits are prefunded immediately, orders above them await the signature of the 
+//! approver before the prefund is locked.
+//! * Orders can optionally be associated with a project, so that the revenue and cost posted against the orders 
+//! of a project can be aggregated for a project profitability view.
+//! * Service orders of a project can be billed cost-plus. The invoice amount is then calculated at invoicing from the 
+//! accepted time of the seller on the project at the agreed hourly rate, plus the expenses recorded by the seller, plus 
+//! the agreed markup, up to the prefunded order amount.
+//! * A seller can stop accepting new orders (for example while on vacation). Orders naming the seller fail when they are 
+//! created, so that buyers do not lock funds in vain, while the existing orders of the seller continue as normal.
+//! * The parties to an order can copy observers on the order (for example a group CFO or an auditor). Observers receive the 
+//! notifications of the order and can list the orders they are copied on, but take no part in the order.
+//! * The number of orders created, accepted, settled and cancelled and the value settled are counted per day, so that 
+//! topline commerce metrics can be read from the chain without indexing the events.
 //! 
 //! The main types used in this module are:
 //!
@@ -56,8 +73,9 @@
 //! * buy_or_sell: u16, // 0: buy, 1: sell, extensible
 //! * amount: AccountBalanceOf<T>, // amount should be the sum of all the items untiprices * quantities
 //! * open_closed: bool, // 0: open(true) 1: closed(false)
-//! * order_type: u16, // 0 Services, 1 Goods, 2 Inventory
+//! * order_type: u16, // 0 Services, 1 Goods, 2 Expense reimbursement. Determines the ledger accounts used at settlement
 //! * deadline: u64, // prefunding acceptance deadline 
+//! * grace_period: u64, // blocks after the deadline during which the seller may still accept, unless the buyer has taken back the funds
 //! * due_date: u64, // due date is the future delivery date (in blocks) 
 
 use support::{
@@ -68,9 +86,9 @@ use support::{
     StorageMap
 };
 
-use system::ensure_signed;
-use parity_codec::{Decode, Encode};
-use runtime_primitives::traits::{Convert};
+use system::{ensure_inherent, ensure_root, ensure_signed};
+use parity_codec::{Decode, Encode, Input};
+use runtime_primitives::traits::{Convert, EnsureOrigin, Hash};
 use rstd::prelude::*;
 // use node_primitives::Hash; // Use only in full node
 
@@ -78,24 +96,93 @@ use rstd::prelude::*;
 use accounting::{ Posting };
 
 // Totem Traits
-use crate::prefunding_traits::{ Encumbrance };
+use totem_traits::prefunding::{ ComplianceHolding, Encumbrance, LockState, OrderType, ORDER_TYPE_SERVICE, ORDER_TYPE_GOODS, ORDER_TYPE_EXPENSE };
 use crate::bonsai_traits::{ Storing };
-use crate::orders_traits::{ Validating };
+use crate::orders_core;
+use crate::orders_traits::{ Reminding, Validating };
+use crate::projects_traits::{ Validating as ProjectValidating };
+use crate::timekeeping_traits::{ Billing };
+use crate::boxkeys_traits::{ KeyVerifying };
 
 // Totem Trait Types
+type AccountOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::Account;
 type AccountBalanceOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::LedgerBalance;
 
-// 0=Unlocked(false) 1=Locked(true)
-pub type UnLocked<T> = <<T as Trait>::Prefunding as Encumbrance<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber>>::UnLocked; 
-
 // Substrate trait types
 
 // Module Types
 type OrderStatus = u16; // Generic Status for whatever the HashReference refers to
 type ApprovalStatus = u16; // submitted(0), accepted(1), rejected(2)
+type SchemaVersion = u16; // Version of the format of the off-chain order document referenced by the order
+pub type NotificationEvents = u16; // Bitmask of the order lifecycle notifications an identity subscribes to
+
+// Schema version of the off-chain order document that is always supported
+const DEFAULT_SCHEMA_VERSION: SchemaVersion = 1;
+
+// Maximum length in bytes of a notification endpoint (URL)
+const MAX_ENDPOINT_LENGTH: usize = 256;
+
+// Order lifecycle notifications an identity subscribes to. Reminders are built by the offchain worker of this module, 
+// the other events are read by integrations through `notification_subscribers`.
+pub const NOTIFY_REMINDERS: NotificationEvents = 1; // approaching deadlines and due dates
+pub const NOTIFY_STATUS: NotificationEvents = 2; // order status changes
+pub const NOTIFY_SETTLEMENT: NotificationEvents = 4; // invoices, settlements and overdue invoices
+const NOTIFY_ALL: NotificationEvents = NOTIFY_REMINDERS | NOTIFY_STATUS | NOTIFY_SETTLEMENT;
+
+// Maximum number of sealed bids on a market order
+const MAX_SEALED_BIDS: usize = 100;
+
+// Maximum length in bytes of an encrypted sealed bid
+const MAX_SEALED_BID_LENGTH: usize = 1024;
+
+// Maximum number of messages in the message thread of an order
+const MAX_THREAD_MESSAGES: usize = 500;
+
+// Maximum number of observers copied on an order
+const MAX_ORDER_OBSERVERS: usize = 10;
+
+// Maximum number of orders an identity can be copied on as an observer
+const MAX_OBSERVED_ORDERS: usize = 100;
+
+// Maximum number of orders associated with a project
+const MAX_PROJECT_ORDERS: usize = 500;
+
+// Maximum number of counterproposals on the terms of an order
+const MAX_COUNTERPROPOSAL_ROUNDS: usize = 10;
+
+// Longest compliance hold in blocks (90 days). A hold lapses at its expiry unless it is placed again.
+const MAX_COMPLIANCE_HOLD: u64 = 518400;
+
+// Period in blocks over which the spending cap of a delegate applies (30 days)
+const SPENDING_PERIOD: u64 = 172800;
+
+// Longest payment terms in blocks (one year)
+const MAX_PAYMENT_TERMS: u64 = 2102400;
+
+// Number of blocks in an hour of booked time, at which cost-plus hourly rates are applied
+const BLOCKS_PER_HOUR: u64 = 240;
+
+// Maximum number of expenses recorded against a cost-plus order
+const MAX_COST_PLUS_EXPENSES: usize = 100;
+
+// Maximum number of invoices checked for being overdue in one block. The remainder is checked in the next block.
+const MAX_OVERDUE_CHECKS: usize = 100;
+
+// Maximum number of reminders checked in one block. The remainder is checked in the next block.
+const MAX_REMINDER_CHECKS: usize = 100;
+
+// Maximum number of reminders waiting for the offchain worker. Further reminders are queued in the next block.
+const MAX_PENDING_REMINDERS: usize = 500;
+
+// Period in blocks over which the order book statistics are counted (one day)
+const STATISTICS_PERIOD: u64 = 5760;
+
+// Maximum number of statistics periods returned at once
+const MAX_STATISTICS_PERIODS: u64 = 100;
 
 // This is the order header: contains common values for all items
-#[derive(PartialEq, Eq, Copy, Clone, Encode, Decode, Default)]
+// Orders stored before the schema version was introduced decode with the default schema version.
+#[derive(PartialEq, Eq, Copy, Clone, Encode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct OrderHeader<AccountId> {
     pub commander: AccountId,
@@ -106,9 +193,77 @@ pub struct OrderHeader<AccountId> {
     pub buy_or_sell: u16,
     pub amount: i128,
     pub market_order: bool,
-    pub order_type: u16,
+    pub order_type: OrderType,
     pub deadline: u64,
     pub due_date: u64,
+    pub schema_version: SchemaVersion,
+}
+
+impl<AccountId: Decode> Decode for OrderHeader<AccountId> {
+    fn decode<I: Input>(input: &mut I) -> Option<Self> {
+        Some(OrderHeader {
+            commander: AccountId::decode(input)?,
+            fulfiller: AccountId::decode(input)?,
+            approver: AccountId::decode(input)?,
+            order_status: u16::decode(input)?,
+            approval_status: u16::decode(input)?,
+            buy_or_sell: u16::decode(input)?,
+            amount: i128::decode(input)?,
+            market_order: bool::decode(input)?,
+            order_type: OrderType::decode(input)?,
+            deadline: u64::decode(input)?,
+            due_date: u64::decode(input)?,
+            schema_version: SchemaVersion::decode(input).unwrap_or(DEFAULT_SCHEMA_VERSION),
+        })
+    }
+}
+
+/// Fiat price of an order locked at creation at the exchange rate of the creation period.
+/// With fixed fiat semantics the XTX amount invoiced is the fiat amount at the rate of the invoicing period,
+/// up to the prefunded amount. With fixed XTX semantics the order amount is invoiced.
+#[derive(PartialEq, Eq, Copy, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PriceLock {
+    pub currency: u16,
+    pub fiat_amount: i128,
+    pub rate: (i128, i128),
+    pub fixed_fiat: bool,
+    pub invoiced: i128,
+}
+
+/// Calculation of the invoice of a cost-plus order, kept so that either party can verify the invoiced amount.
+/// The amount is the time at the hourly rate plus the expenses plus the markup, up to the prefunded order amount.
+#[derive(PartialEq, Eq, Copy, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct CostPlusInvoice {
+    pub blocks: u64,
+    pub time: i128,
+    pub expenses: i128,
+    pub markup: i128,
+    pub amount: i128,
+}
+
+/// Order book statistics of a period: the number of orders created, accepted, settled and cancelled and the value 
+/// settled. Orders are counted in the period of the transition, not of their creation. Orders deleted by the buyer 
+/// and orders rejected by the seller are counted as cancelled.
+#[derive(PartialEq, Eq, Copy, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct OrderStatistics {
+    pub created: u32,
+    pub accepted: u32,
+    pub settled: u32,
+    pub cancelled: u32,
+    pub value_settled: u128,
+}
+
+/// Subscription of an identity to the notifications of the orders it is a party to. The reminder notifications carry 
+/// a digest keyed with the hash of the shared secret registered here, so that the endpoint can match them to its subscription.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct NotificationSubscription<Hash> {
+    pub endpoint: Vec<u8>,
+    pub secret_hash: Hash,
+    pub events: NotificationEvents,
 }
 
 #[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
@@ -148,14 +303,20 @@ pub trait Trait: accounting::Trait + system::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
     type OrderConversions: Convert<i128, AccountBalanceOf<Self>> 
     + Convert<i128, u128> 
-    + Convert<bool, UnLocked<Self>>
     + Convert<AccountBalanceOf<Self>, i128> 
     + Convert<AccountBalanceOf<Self>, u128> 
+    + Convert<AccountOf<Self>, u64> 
     + Convert<u64, Self::BlockNumber> 
     + Convert<Self::BlockNumber, u64>;
     type Accounting: Posting<Self::AccountId,Self::Hash,Self::BlockNumber,Self::CoinAmount>;
     type Prefunding: Encumbrance<Self::AccountId,Self::Hash,Self::BlockNumber>;
     type Bonsai: Storing<Self::Hash>;
+    type Projects: ProjectValidating<Self::AccountId,Self::Hash>;
+    type Reminders: Reminding<Self::AccountId,Self::Hash>;
+    type BoxKeys: KeyVerifying<Self::AccountId,Self::Hash>;
+    type Timekeeping: Billing<Self::AccountId,Self::Hash>;
+    /// Origin that places and lifts compliance holds (for example the council, or a compliance account)
+    type ComplianceOrigin: EnsureOrigin<Self::Origin>;
 }
 
 decl_storage! {
@@ -166,52 +327,154 @@ decl_storage! {
         Postulate get(postulate): map T::Hash => Vec<T::Hash>;
         Orders get(orders): map T::Hash => Option<OrderHeader<T::AccountId>>;
         OrderItems get(order_items): map T::Hash => Vec<OrderItem<T::Hash>>;
+        // Performance bond terms set by the commander before acceptance. 
+        // (bond rate as a percentage of the order amount, forfeit rate as a percentage of the bond on seller default)
+        PerformanceBondTerms get(performance_bond_terms): map T::Hash => Option<(u16, u16)>;
+        // Orders associated with a project
+        ProjectOrders get(project_orders): map T::Hash => Vec<T::Hash>;
+        // The project an order is associated with
+        OrderProject get(order_project): map T::Hash => Option<T::Hash>;
+        // Number of blocks before a deadline or due date at which a reminder is raised
+        ReminderWindow get(reminder_window): u64;
+        // Reminders falling due at a block (order, deadline or due date), queued for the offchain worker at the end of that block
+        RemindersDueAt get(reminders_due_at): map u64 => Vec<(T::Hash, u64)>;
+        // Reminders that have fallen due and wait for the offchain worker (order, deadline or due date)
+        PendingReminders get(pending_reminders): Vec<(T::Hash, u64)>;
+        // Block at which a reminder was raised for an order and deadline (to avoid duplicate reminders)
+        ReminderSent get(reminder_sent): map (T::Hash, u64) => Option<T::BlockNumber>;
+        // Notification subscription of an identity (endpoint, hash of the shared secret, subscribed events). 
+        // Read by the reminder offchain worker and by the `notification_subscribers` runtime API, so that integrations are configured without the node operator.
+        NotificationSubscriptions get(notification_subscription): map T::AccountId => Option<NotificationSubscription<T::Hash>>;
+        // Order document schema versions supported in addition to the default version
+        SupportedSchemaVersions get(supported_schema_versions): Vec<SchemaVersion>;
+        // Sealed bidding on a market order (bidding deadline, verified box key of the buyer to which bids are encrypted)
+        SealedBidding get(sealed_bidding): map T::Hash => Option<(u64, T::Hash)>;
+        // Sealed bids on a market order (bidder, commitment, bid encrypted to the box key of the buyer)
+        SealedBids get(sealed_bids): map T::Hash => Vec<(T::AccountId, T::Hash, Vec<u8>)>;
+        // Sealed bid accepted by the buyer (bidder, amount)
+        AcceptedBid get(accepted_bid): map T::Hash => Option<(T::AccountId, i128)>;
+        // Standing approval limits set by a company for a delegate ordering on its behalf (company, delegate)
+        // (maximum order value, cap per spending period). Orders within the limits do not need the signature of the approver.
+        DelegateLimits get(delegate_limits): map (T::AccountId, T::AccountId) => Option<(u128, u128)>;
+        // Amount ordered by a delegate within the standing approval of a company in a spending period (company, delegate, period)
+        DelegateSpending get(delegate_spending): map (T::AccountId, T::AccountId, u64) => u128;
+        // Orders awaiting the signature of the approver before the prefund is locked (grace period of the prefund)
+        PendingApproval get(pending_approval): map T::Hash => Option<u64>;
+        // Approved closed orders up to this value take the express lane. They are invoiced at creation without the acceptance
+        // of the seller, and settled when the buyer confirms delivery. Zero disables the express lane.
+        ExpressLaneLimit get(express_lane_limit): u128;
+        // Messages exchanged between the parties of an order (sender, hash of the message content, block). 
+        // The content is encrypted off-chain to the box keys of the other parties and is never stored on chain.
+        MessageThread get(message_thread): map T::Hash => Vec<(T::AccountId, T::Hash, T::BlockNumber)>;
+        // Fiat price locked for an order priced with reference to a fiat currency
+        PriceLocks get(price_lock): map T::Hash => Option<PriceLock>;
+        // Variance at settlement of an order with a locked fiat price against the amounts locked at creation
+        // (XTX paid less the order amount, fiat value of the XTX paid at the settlement rate less the locked fiat amount)
+        PriceVariance get(price_variance): map T::Hash => Option<(i128, i128)>;
+        // Default payment terms of a seller: invoices are due this number of blocks after invoicing
+        DefaultPaymentTerms get(default_payment_terms): map T::AccountId => Option<u64>;
+        // Payment terms agreed for an order, in preference to the default payment terms of the seller
+        PaymentTerms get(payment_terms): map T::Hash => Option<u64>;
+        // Block at which the invoice of an order is due, set at invoicing
+        InvoiceDue get(invoice_due): map T::Hash => Option<u64>;
+        // Invoices falling due at a block, checked for payment at the end of that block
+        InvoicesDueAt get(invoices_due_at): map u64 => Vec<T::Hash>;
+        // Overdue invoices of a payer (buyer)
+        OverduePayables get(overdue_payables): map T::AccountId => Vec<T::Hash>;
+        // Overdue invoices of a payee (seller)
+        OverdueReceivables get(overdue_receivables): map T::AccountId => Vec<T::Hash>;
+        // Counterproposals on the terms of a submitted order, in the order they were made (proposer, amount, deadline, block)
+        Counterproposals get(counterproposals): map T::Hash => Vec<(T::AccountId, i128, u64, T::BlockNumber)>;
+        // The last counterproposal on an order awaits the response of the other party
+        OpenCounterproposal get(open_counterproposal): map T::Hash => bool;
+        // Cost-plus billing terms of a service order agreed before acceptance (hourly rate, markup percentage)
+        CostPlusTerms get(cost_plus_terms): map T::Hash => Option<(i128, u16)>;
+        // Expenses recorded by the seller against a cost-plus order (amount, hash of the receipt)
+        CostPlusExpenses get(cost_plus_expenses): map T::Hash => Vec<(i128, T::Hash)>;
+        // Calculation of the invoice of a cost-plus order, set at invoicing
+        CostPlusInvoices get(cost_plus_invoice): map T::Hash => Option<CostPlusInvoice>;
+        // Block at which a cost-plus order was accepted. Only the time worked from then until invoicing is billed
+        CostPlusAccepted get(cost_plus_accepted): map T::Hash => Option<u64>;
+        // Compliance hold on an order or prefunding (reason hash, expiry block). A held reference cannot be settled or cancelled
+        ComplianceHolds get(compliance_hold): map T::Hash => Option<(T::Hash, u64)>;
+        // Compliance holds expiring at a block
+        ComplianceHoldsExpiring get(compliance_holds_expiring): map u64 => Vec<T::Hash>;
+        // Sellers not accepting new orders. Their existing orders are not affected
+        NotAcceptingOrders get(not_accepting_orders): map T::AccountId => bool;
+        // Order book statistics by statistics period
+        OrderStatisticsByPeriod get(order_statistics): map u64 => OrderStatistics;
+        // Total value of all orders settled
+        TotalValueSettled get(total_value_settled): u128;
+        // Observers copied on an order by its parties. Observers are notified but take no part in the order
+        OrderObservers get(order_observers): map T::Hash => Vec<T::AccountId>;
+        // Orders an identity is copied on as an observer
+        ObservedOrders get(observed_orders): map T::AccountId => Vec<T::Hash>;
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event<T>() = default;
+        fn on_finalize(n: T::BlockNumber) {
+            Self::flag_overdue_invoices(n);
+            Self::expire_compliance_holds(n);
+            Self::queue_reminders(n);
+        }
         /// Only the owner of an order can delete it provided no work has been done on it. 
         fn delete_order(
             origin,
             tx_keys_medium: TXKeysM<T::Hash>
         ) -> Result {
             let who = ensure_signed(origin)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_keys_medium.tx_uid.clone())?;
-            // Only delete order if it has not been accepted by the fulfiller.
-            match Self::orders(&tx_keys_medium.record_id) {
-                Some(order) => {
-                    // Order is owned by sender, status unaccepted a
-                    let approver: T::AccountId = order.approver;
-                    let order_status: u16 = order.order_status;
-                    if (approver.clone(), order_status) == (who.clone(), 0u16) {
-                        <Owner<T>>::mutate(&order.commander, |owner| {
-                            owner.retain(|v| v != &tx_keys_medium.record_id)
-                        });
-                        <Beneficiary<T>>::mutate(&order.fulfiller, |owner| {
-                            owner.retain(|v| v != &tx_keys_medium.record_id)
-                        });
-                        // <Approver<T>>::mutate(&approver, |owner| {
-                        <Approver<T>>::mutate(approver, |owner| {
-                            owner.retain(|v| v != &tx_keys_medium.record_id)
-                        });
-                        <Postulate<T>>::remove(&tx_keys_medium.record_id);
-                        <Orders<T>>::remove(&tx_keys_medium.record_id);
-                        <OrderItems<T>>::remove(&tx_keys_medium.record_id);
-                    } else if (approver, order_status) != (who, 0u16) {
-                        Self::deposit_event(RawEvent::ErrorStatusNotAllowed6(tx_keys_medium.tx_uid));
-                        return Err("This is not your order or wrong status");
-                    }
-                },
-                None => {
-                    // Order does not exist
-                    Self::deposit_event(RawEvent::ErrorHashExists3(tx_keys_medium.tx_uid));
-                    return Err("This hash does not exist");
-                },
-            }
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_keys_medium.tx_uid)?;
-            Ok(())
+            Self::with_tx(tx_keys_medium.tx_uid, || {
+                // Only delete order if it has not been accepted by the fulfiller.
+                match Self::orders(&tx_keys_medium.record_id) {
+                    Some(order) => {
+                        // Order is owned by sender, status unaccepted a
+                        let approver: T::AccountId = order.approver;
+                        let order_status: u16 = order.order_status;
+                        if Self::is_held(tx_keys_medium.record_id) {
+                            Self::deposit_event(RawEvent::ErrorComplianceHold(tx_keys_medium.tx_uid));
+                            return Err("The order is under a compliance hold");
+                        }
+                        if (approver.clone(), order_status) == (who.clone(), 0u16) {
+                            <Owner<T>>::mutate(&order.commander, |owner| {
+                                owner.retain(|v| v != &tx_keys_medium.record_id)
+                            });
+                            <Beneficiary<T>>::mutate(&order.fulfiller, |owner| {
+                                owner.retain(|v| v != &tx_keys_medium.record_id)
+                            });
+                            // <Approver<T>>::mutate(&approver, |owner| {
+                            <Approver<T>>::mutate(approver, |owner| {
+                                owner.retain(|v| v != &tx_keys_medium.record_id)
+                            });
+                            <Postulate<T>>::remove(&tx_keys_medium.record_id);
+                            <Orders<T>>::remove(&tx_keys_medium.record_id);
+                            <OrderItems<T>>::remove(&tx_keys_medium.record_id);
+                            <PerformanceBondTerms<T>>::remove(&tx_keys_medium.record_id);
+                            <PendingApproval<T>>::remove(&tx_keys_medium.record_id);
+                            <CostPlusTerms<T>>::remove(&tx_keys_medium.record_id);
+                            for observer in <OrderObservers<T>>::take(&tx_keys_medium.record_id).into_iter() {
+                                <ObservedOrders<T>>::mutate(&observer, |observed| observed.retain(|o| o != &tx_keys_medium.record_id));
+                            }
+                            if let Some(project_hash) = <OrderProject<T>>::take(&tx_keys_medium.record_id) {
+                                <ProjectOrders<T>>::mutate(&project_hash, |project_orders| project_orders.retain(|o| o != &tx_keys_medium.record_id));
+                            }
+                            <<T as Trait>::Bonsai as Storing<T::Hash>>::release_data(tx_keys_medium.record_id);
+                            Self::record_statistics(|statistics| statistics.cancelled = statistics.cancelled.saturating_add(1));
+                        } else if (approver, order_status) != (who, 0u16) {
+                            Self::deposit_event(RawEvent::ErrorStatusNotAllowed6(tx_keys_medium.tx_uid));
+                            return Err("This is not your order or wrong status");
+                        }
+                    },
+                    None => {
+                        // Order does not exist
+                        Self::deposit_event(RawEvent::ErrorHashExists3(tx_keys_medium.tx_uid));
+                        return Err("This hash does not exist");
+                    },
+                }
+                Ok(())
+            })
         }
         
         /// Creates either a sales order or a purchase order with multi-line items and a parent order
@@ -223,84 +486,94 @@ decl_module! {
             buy_or_sell: u16, 
             total_amount: i128, 
             market_order: bool, 
-            order_type: u16, 
+            order_type: OrderType, 
             deadline: u64, 
             due_date: u64, 
             order_items: Vec<OrderItem<T::Hash>>,
+            project_hash: Option<T::Hash>,
+            schema_version: SchemaVersion,
             tx_keys_large: TXKeysL<T::Hash>
         ) -> Result {
             let who = ensure_signed(origin)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_keys_large.tx_uid.clone())?;
+            Self::with_tx(tx_keys_large.tx_uid, || {
+                Self::check_schema_version(schema_version)?;
+                Self::check_order_type(order_type, tx_keys_large.tx_uid)?;
+                Self::check_order_value(total_amount, tx_keys_large.tx_uid)?;
+                Self::check_order_project(who.clone(), project_hash, tx_keys_large.tx_uid)?;
             
-            // Check that the supplied record_id does not exist
-            if <Orders<T>>::exists(&tx_keys_large.record_id) {
-                Self::deposit_event(RawEvent::ErrorHashExists(tx_keys_large.tx_uid));
-                return Err("The hash already exists! Try again.");
-            }
+                // Check that the supplied record_id does not exist
+                if <Orders<T>>::exists(&tx_keys_large.record_id) {
+                    Self::deposit_event(RawEvent::ErrorHashExists(tx_keys_large.tx_uid));
+                    return Err("The hash already exists! Try again.");
+                }
 
-            let mut approval_status: u16 = 0u16;
-            // Check that it is an open order
-            if market_order {
-                // process open order - ignore fulfiller
-                // check that the order does not have a parent - by default the parent and the record_id must be the same 
-                if tx_keys_large.record_id == tx_keys_large.parent_id {
+                let mut approval_status: u16 = 0u16;
+                // Check that it is an open order
+                if market_order {
+                    // process open order - ignore fulfiller
+                    // check that the order does not have a parent - by default the parent and the record_id must be the same 
+                    if tx_keys_large.record_id == tx_keys_large.parent_id {
                     
-                } else {
-                    Self::deposit_event(RawEvent::ErrorMarketOrder(tx_keys_large.tx_uid));
-                    return Err("Cannot make an market order against a parent order")
-                }
-                // Go further - Store the Order
-                ();
-            } else {
-                // closed order, fulfiller must be completed and it must not be the origin
-                if fulfiller == who {
-                    Self::deposit_event(RawEvent::ErrorCannotBeBoth2(tx_keys_large.tx_uid));
-                    return Err("You cannot fulfill your own order");
-                }
-                // The order may have a parent - by default the parent and the record_id are the same, but they may also be different
-                if tx_keys_large.record_id == tx_keys_large.parent_id {
-                    // This order has no parent therefore is a simple unfunded order with a known fulfiller
-                    // TODO 
+                    } else {
+                        Self::deposit_event(RawEvent::ErrorMarketOrder(tx_keys_large.tx_uid));
+                        return Err("Cannot make an market order against a parent order")
+                    }
+                    // Go further - Store the Order
                     ();
                 } else {
-                    // This order has a parent therefore it is a proposal and this means there is a fulfiller
-                    // check that that the parent hash exists
-                    if !<Orders<T>>::exists(&tx_keys_large.parent_id) {
-                        Self::deposit_event(RawEvent::ErrorHashExists2(tx_keys_large.tx_uid));
-                        return Err("The parent hash does not exist.");
-                    };
-                    // if the approver is also the initiator of the order then automatically approve the order
-                    if Self::check_approver(who.clone(), approver.clone(), tx_keys_large.record_id.clone()) {
-                        // the order is approved because the approver is the commander.
-                        approval_status = 1u16;
+                    // closed order, fulfiller must be completed and it must not be the origin
+                    if fulfiller == who {
+                        Self::deposit_event(RawEvent::ErrorCannotBeBoth2(tx_keys_large.tx_uid));
+                        return Err("You cannot fulfill your own order");
+                    }
+                    Self::check_accepting_orders(&fulfiller, tx_keys_large.tx_uid)?;
+                    // The order may have a parent - by default the parent and the record_id are the same, but they may also be different
+                    if tx_keys_large.record_id == tx_keys_large.parent_id {
+                        // This order has no parent therefore is a simple unfunded order with a known fulfiller
+                        // TODO 
+                        ();
                     } else {
-                        // the order is not yet approved.
-                        // This is NOT an error but requires further processing by the approver.
+                        // This order has a parent therefore it is a proposal and this means there is a fulfiller
+                        // check that that the parent hash exists
+                        if !<Orders<T>>::exists(&tx_keys_large.parent_id) {
+                            Self::deposit_event(RawEvent::ErrorHashExists2(tx_keys_large.tx_uid));
+                            return Err("The parent hash does not exist.");
+                        };
+                        // if the approver is also the initiator of the order then automatically approve the order
+                        if Self::check_approver(who.clone(), approver.clone(), tx_keys_large.record_id.clone()) {
+                            // the order is approved because the approver is the commander.
+                            approval_status = 1u16;
+                        } else {
+                            // the order is not yet approved.
+                            // This is NOT an error but requires further processing by the approver.
                         
-                        // As this is a proposal against a parent order then associate the child with the parent
-                        // This does not happen when it is a simple order
-                        <Postulate<T>>::mutate(&tx_keys_large.parent_id, |v| v.push(tx_keys_large.record_id));
-                        // <TxList<T>>::mutate(list_key, |tx_list| tx_list.push(u));
+                            // As this is a proposal against a parent order then associate the child with the parent
+                            // This does not happen when it is a simple order
+                            <Postulate<T>>::mutate(&tx_keys_large.parent_id, |v| v.push(tx_keys_large.record_id));
+                            // <TxList<T>>::mutate(list_key, |tx_list| tx_list.push(u));
+                        }
                     }
+                    let order_header: OrderHeader<T::AccountId> = OrderHeader {
+                        commander: who.clone(),
+                        fulfiller: fulfiller.clone(),
+                        approver: who.clone(),
+                        order_status: 0u16,
+                        approval_status: approval_status,
+                        buy_or_sell: buy_or_sell,
+                        amount: total_amount,
+                        market_order: market_order,
+                        order_type: order_type,
+                        deadline: deadline,
+                        due_date: due_date,
+                        schema_version: schema_version,
+                    };
+                    Self::set_order(who, fulfiller, tx_keys_large.record_id, order_header, order_items)?;
+                    Self::set_order_project(tx_keys_large.record_id, project_hash);
+                    Self::record_statistics(|statistics| statistics.created = statistics.created.saturating_add(1));
                 }
-                let order_header: OrderHeader<T::AccountId> = OrderHeader {
-                    commander: who.clone(),
-                    fulfiller: fulfiller.clone(),
-                    approver: who.clone(),
-                    order_status: 0u16,
-                    approval_status: approval_status,
-                    buy_or_sell: buy_or_sell,
-                    amount: total_amount,
-                    market_order: market_order,
-                    order_type: order_type,
-                    deadline: deadline,
-                    due_date: due_date,
-                };
-                Self::set_order(who, fulfiller, tx_keys_large.record_id, order_header, order_items)?;
-            }
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_keys_large.tx_uid)?;
-            Self::deposit_event(RawEvent::OrderCreated(tx_keys_large.tx_uid.clone(), tx_keys_large.record_id));
-            Ok(())
+                Self::deposit_event(RawEvent::OrderCreated(tx_keys_large.tx_uid.clone(), tx_keys_large.record_id, schema_version));
+                Ok(())
+            })
         }
         
         /// Create Simple Prefunded Service Order
@@ -312,42 +585,53 @@ decl_module! {
             buy_or_sell: u16, // 0: buy, 1: sell, extensible
             total_amount: i128, // amount should be the sum of all the items untiprices * quantities
             market_order: bool, // 0: open(false) 1: closed(true)
-            order_type: u16, // 0: service, 1: inventory, 2: asset extensible 
+            order_type: OrderType, // 0: service, 1: goods, 2: expense reimbursement
             deadline: u64, // prefunding acceptance deadline 
+            grace_period: u64, // blocks after the deadline during which the seller may still accept
             due_date: u64, // due date is the future delivery date (in blocks) 
             order_item: OrderItem<T::Hash>, // for simple items there will only be one item, item number is accessed by its position in Vec 
+            project_hash: Option<T::Hash>, // optional project the order is associated with
+            schema_version: SchemaVersion, // version of the off-chain order document format
             bonsai_token: T::Hash, // Bonsai data Hash
             tx_uid: T::Hash // Bonsai data Hash
         ) -> Result {
             let who = ensure_signed(origin)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid.clone())?;
-            // Generate Hash for order
-            let order_hash: T::Hash = <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_pseudo_random_hash(who.clone(),approver.clone());
-            
-            if <Orders<T>>::exists(&order_hash) {
-                Self::deposit_event(RawEvent::ErrorHashExists(order_hash));
-                return Err("The hash already exists! Try again.");
-            }
-            
-            Self::set_simple_prefunded_service_order(
-                who,
-                approver,
-                fulfiller,
-                buy_or_sell,
-                total_amount,
-                market_order,
-                order_type,
-                deadline,
-                due_date,
-                order_hash,
-                order_item,
-                bonsai_token,
-                tx_uid
-            )?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
-            // issue events
-            Self::deposit_event(RawEvent::OrderCreated(tx_uid, order_hash));
-            Ok(())
+            Self::with_tx(tx_uid, || {
+                Self::check_schema_version(schema_version)?;
+                Self::check_order_type(order_type, tx_uid)?;
+                Self::check_order_value(total_amount, tx_uid)?;
+                Self::check_order_project(who.clone(), project_hash, tx_uid)?;
+                // Generate Hash for order
+                let order_hash: T::Hash = <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_pseudo_random_hash(who.clone(),approver.clone());
+            
+                if <Orders<T>>::exists(&order_hash) {
+                    Self::deposit_event(RawEvent::ErrorHashExists(order_hash));
+                    return Err("The hash already exists! Try again.");
+                }
+            
+                Self::set_simple_prefunded_service_order(
+                    who,
+                    approver,
+                    fulfiller,
+                    buy_or_sell,
+                    total_amount,
+                    market_order,
+                    order_type,
+                    deadline,
+                    grace_period,
+                    due_date,
+                    order_hash,
+                    order_item,
+                    schema_version,
+                    bonsai_token,
+                    tx_uid
+                )?;
+                Self::set_order_project(order_hash, project_hash);
+                Self::record_statistics(|statistics| statistics.created = statistics.created.saturating_add(1));
+                // issue events
+                Self::deposit_event(RawEvent::OrderCreated(tx_uid, order_hash, schema_version));
+                Ok(())
+            })
         }
         /// Change Simple Prefunded Service Order.
         /// Can only be changed by the original ordering party, and only before it is accepted and the deadline or due date is not passed
@@ -360,37 +644,62 @@ decl_module! {
             due_date: u64, 
             order_item: OrderItem<T::Hash>,
             record_id: T::Hash,
+            schema_version: SchemaVersion,
             bonsai_token: T::Hash, 
             tx_uid: T::Hash 
         ) -> Result {
             let who = ensure_signed(origin)?;
             // check owner of this record
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid)?;
-            Self::change_simple_prefunded_order(
-                who.clone(), 
-                approver.clone(),
-                fulfiller.clone(),
-                amount,
-                deadline,
-                due_date,
-                order_item,
-                record_id,
-                bonsai_token
-            )?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
-            // issue events
-            Self::deposit_event(RawEvent::OrderUpdated(tx_uid));
+            Self::with_tx(tx_uid, || {
+                Self::check_schema_version(schema_version)?;
+                Self::check_order_value(amount, tx_uid)?;
+                Self::change_simple_prefunded_order(
+                    who.clone(), 
+                    approver.clone(),
+                    fulfiller.clone(),
+                    amount,
+                    deadline,
+                    due_date,
+                    order_item,
+                    record_id,
+                    schema_version,
+                    bonsai_token
+                )?;
+                // issue events
+                Self::deposit_event(RawEvent::OrderUpdated(tx_uid, schema_version));
+                Ok(())
+            })
+        }
+        /// Sets the standing approval limits of a delegate ordering on behalf of the sender (company). Orders of the delegate 
+        /// naming the sender as approver are approved without a signature up to the maximum order value, and as long as the 
+        /// orders of the spending period do not exceed the cap. Orders above the limits await the signature of the approver.
+        /// A maximum order value of zero removes the limits.
+        fn set_delegate_limits(origin, delegate: T::AccountId, max_order_value: AccountBalanceOf<T>, period_cap: AccountBalanceOf<T>) -> Result {
+            let who = ensure_signed(origin)?;
+            let max_order_value: u128 = <T::OrderConversions as Convert<AccountBalanceOf<T>, u128>>::convert(max_order_value);
+            let period_cap: u128 = <T::OrderConversions as Convert<AccountBalanceOf<T>, u128>>::convert(period_cap);
+            if max_order_value == 0 {
+                <DelegateLimits<T>>::remove((who.clone(), delegate.clone()));
+            } else {
+                if who == delegate || period_cap < max_order_value {
+                    Self::deposit_event(RawEvent::ErrorDelegateLimits(who));
+                    return Err("Invalid delegate limits");
+                }
+                <DelegateLimits<T>>::insert((who.clone(), delegate.clone()), (max_order_value, period_cap));
+            }
+            Self::deposit_event(RawEvent::DelegateLimitsSet(who, delegate, max_order_value, period_cap));
             Ok(())
         }
         /// Sets the approval status of an order 
         /// Can only be used by the nominated approver (must be known to the ordering party)
+        /// Approving an order that awaits approval locks the prefund of the commander
         fn change_approval(origin, h: T::Hash, s: ApprovalStatus, b: T::Hash, tx_uid: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid)?;
-            Self::change_approval_state(who.clone(), h, s, b)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
-            Self::deposit_event(RawEvent::InvoiceSettled(h));
-            Ok(())
+            Self::with_tx(tx_uid, || {
+                Self::change_approval_state(who.clone(), h, s, b)?;
+                Self::deposit_event(RawEvent::InvoiceSettled(h));
+                Ok(())
+            })
         }
         
         /// Can be used by buyer or seller
@@ -398,51 +707,453 @@ decl_module! {
         /// Seller - Used to accept, reject or invoice the order. 
         fn handle_spfso(origin, h: T::Hash, s: OrderStatus, tx_uid: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid.clone())?;
-            // get order details and determine if the sender is the buyer or the seller
-            let order_hdr: OrderHeader<T::AccountId>;
-            match Self::orders(&h) {
-                Some(order) => order_hdr = order,
-                None => {
-                    Self::deposit_event(RawEvent::ErrorGettingOrder(tx_uid));
-                    return Err("Unable to fetch order with this reference.");
-                },
-            };
-            let commander: T::AccountId = order_hdr.commander.clone(); 
-            let fulfiller: T::AccountId = order_hdr.fulfiller.clone();
-            
-            if who == commander {
-                // This is the buyer 
-                //TODO if the order us passed as an arg it doesn't need to be read again
-                Self::accept_prefunded_invoice(who.clone(), h.clone(), s, order_hdr.clone(), tx_uid)?;
-                Self::deposit_event(RawEvent::InvoiceSettled(tx_uid));
-                
-            } else if who == fulfiller {
-                // This is the seller
-                //TODO if the order us passed as an arg it doesn't need to be read again
-                match Self::set_state_simple_prefunded_closed_order(who.clone(), h.clone(), s, order_hdr.clone(), tx_uid) {
-                    Ok(_) => {
-                        ();
-                    },
-                    Err(_e) => {
-                        Self::deposit_event(RawEvent::ErrorSetPrefundState(tx_uid));
-                        return Err("Error setting prefunding state");
+            Self::with_tx(tx_uid, || {
+                // get order details and determine if the sender is the buyer or the seller
+                let order_hdr: OrderHeader<T::AccountId>;
+                match Self::orders(&h) {
+                    Some(order) => order_hdr = order,
+                    None => {
+                        Self::deposit_event(RawEvent::ErrorGettingOrder(tx_uid));
+                        return Err("Unable to fetch order with this reference.");
                     },
+                };
+                let commander: T::AccountId = order_hdr.commander.clone(); 
+                let fulfiller: T::AccountId = order_hdr.fulfiller.clone();
+                
+                // Settlement by the buyer and rejection by the seller (which refunds the buyer) wait for the hold to be lifted
+                if ((who == commander && s == 6) || (who == fulfiller && s == 2)) && Self::is_held(h) {
+                    Self::deposit_event(RawEvent::ErrorComplianceHold(tx_uid));
+                    return Err("The order is under a compliance hold");
                 }
-            } else {
-                // this is an error
-                Self::deposit_event(RawEvent::ErrorURNobody(tx_uid));
-                return Err("You should not be doing this!");
+            
+                if who == commander {
+                    // This is the buyer 
+                    //TODO if the order us passed as an arg it doesn't need to be read again
+                    Self::accept_prefunded_invoice(who.clone(), h.clone(), s, order_hdr.clone(), tx_uid)?;
+                    Self::deposit_event(RawEvent::InvoiceSettled(tx_uid));
                 
-            }
+                } else if who == fulfiller {
+                    // This is the seller
+                    //TODO if the order us passed as an arg it doesn't need to be read again
+                    match Self::set_state_simple_prefunded_closed_order(who.clone(), h.clone(), s, order_hdr.clone(), tx_uid) {
+                        Ok(_) => {
+                            // An open counterproposal lapses once the seller accepts or rejects the order on its current terms
+                            <OpenCounterproposal<T>>::remove(&h);
+                        },
+                        Err(_e) => {
+                            Self::deposit_event(RawEvent::ErrorSetPrefundState(tx_uid));
+                            return Err("Error setting prefunding state");
+                        },
+                    }
+                } else {
+                    // this is an error
+                    Self::deposit_event(RawEvent::ErrorURNobody(tx_uid));
+                    return Err("You should not be doing this!");
+                
+                }
             
-            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Sets the performance bond terms that the seller must meet on acceptance of the order.
+        /// Can only be set by the commander before the order is accepted. Setting the bond rate to zero removes the requirement.
+        fn set_performance_bond_terms(origin, h: T::Hash, bond_rate: u16, forfeit_rate: u16, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_bond_terms(who, h, bond_rate, forfeit_rate, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Sets the payment terms (net blocks after invoicing) applied by the seller to orders without terms of their own.
+        /// Zero removes the default.
+        fn set_default_payment_terms(origin, net_blocks: u64) -> Result {
+            let who = ensure_signed(origin)?;
+            if net_blocks > MAX_PAYMENT_TERMS {
+                Self::deposit_event(RawEvent::ErrorPaymentTerms(who.clone()));
+                return Err("Payment terms are too long");
+            }
+            if net_blocks == 0 {
+                <DefaultPaymentTerms<T>>::remove(&who);
+            } else {
+                <DefaultPaymentTerms<T>>::insert(&who, net_blocks);
+            }
+            Self::deposit_event(RawEvent::DefaultPaymentTermsSet(who, net_blocks));
+            Ok(())
+        }
+        
+        /// Sets whether the seller accepts new orders. Orders naming a seller that does not accept new orders cannot be created,
+        /// nor can an order be changed to that seller. Existing orders of the seller continue as normal.
+        fn set_accepting_orders(origin, accepting: bool) -> Result {
+            let who = ensure_signed(origin)?;
+            if accepting {
+                <NotAcceptingOrders<T>>::remove(&who);
+            } else {
+                <NotAcceptingOrders<T>>::insert(&who, true);
+            }
+            Self::deposit_event(RawEvent::AcceptingOrdersSet(who, accepting));
+            Ok(())
+        }
+        
+        /// Sets the payment terms of an order (net blocks after invoicing), overriding the default terms of the seller.
+        /// Can only be set by the commander before the order is accepted. Zero removes the terms of the order.
+        fn set_payment_terms(origin, h: T::Hash, net_blocks: u64, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_order_payment_terms(who, h, net_blocks, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Used by the buyer when the seller has defaulted on an accepted order (it was not completed by the due date).
+        /// The forfeit percentage of the performance bond is paid to the buyer and the remainder is released to the seller.
+        /// The prefunded funds of the order are returned to the buyer.
+        fn claim_performance_bond(origin, h: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::claim_bond_on_default(who, h, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Super User resolves a dispute on an accepted or invoiced order with a performance bond, or a bond that
+        /// could not be released at settlement or forfeited on default.
+        /// The forfeit percentage of the bond is paid to the buyer and the remainder is released to the seller,
+        /// a forfeit rate of zero releases the bond in full. The order then continues without a bond.
+        fn arbitrate_performance_bond(origin, h: T::Hash, forfeit_rate: u16, tx_uid: T::Hash) -> Result {
+            ensure_root(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::resolve_bond_dispute(h, forfeit_rate, tx_uid)?;
+                Ok(())
+            })
+        }
+
+        /// Issued by the seller against an invoiced order for returns or billing corrections (in full or in part).
+        /// The invoice is reversed in the accounts for the credited amount, which is released to the buyer at settlement.
+        fn issue_credit_note(origin, reference: T::Hash, amount: AccountBalanceOf<T>, reason_hash: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let amount: u128 = <T::OrderConversions as Convert<AccountBalanceOf<T>, u128>>::convert(amount);
+            Self::with_tx(tx_uid, || {
+                Self::credit_invoiced_order(who, reference, amount, reason_hash, tx_uid)?;
+                Ok(())
+            })
+        }
+
+        /// Used by the buyer to open sealed bidding on a market order until the deadline (block). 
+        /// Suppliers encrypt their bids to the verified box key of the buyer so that other suppliers cannot read them.
+        fn open_sealed_bidding(origin, h: T::Hash, user_hash: T::Hash, deadline: u64, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_sealed_bidding(who, h, user_hash, deadline, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Submits (or replaces) a sealed bid on a market order before the bidding deadline.
+        /// The commitment is the hash of (bidder, amount, salt). The encrypted bid contains the amount and the salt.
+        fn submit_sealed_bid(origin, h: T::Hash, commitment: T::Hash, encrypted_bid: Vec<u8>, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::place_sealed_bid(who, h, commitment, encrypted_bid, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Used by the buyer after the bidding deadline to accept a bid by revealing the decrypted amount and salt.
+        /// The bid is only accepted if it matches the commitment made by the bidder.
+        fn accept_sealed_bid(origin, h: T::Hash, bidder: T::AccountId, amount: i128, salt: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::award_sealed_bid(who, h, bidder, amount, salt, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Locks the fiat price of an order at the exchange rate of the current period, before the order is accepted.
+        /// Fixed fiat settles the fiat amount, fixed XTX settles the order amount.
+        fn lock_fiat_price(origin, h: T::Hash, currency: u16, fixed_fiat: bool, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_price_lock(who, h, currency, fixed_fiat, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Sets the cost-plus billing terms of a service order of a project before it is accepted: the hourly rate applied 
+        /// to the accepted time of the seller and the markup percentage. The order amount becomes the ceiling of the invoice.
+        /// Setting the rate to zero removes the terms.
+        fn set_cost_plus_terms(origin, h: T::Hash, hourly_rate: i128, markup: u16, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_cost_plus(who, h, hourly_rate, markup, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Used by the seller to record an expense against an accepted cost-plus order before invoicing it.
+        /// Only the hash of the receipt is stored, the receipt itself is shared off-chain.
+        fn record_cost_plus_expense(origin, h: T::Hash, amount: i128, receipt_hash: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::add_cost_plus_expense(who, h, amount, receipt_hash, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Appends the hash of a message to the message thread of an order. 
+        /// Only the commander, fulfiller or approver of the order can post messages.
+        fn post_order_message(origin, h: T::Hash, message_hash: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::append_message(who, h, message_hash, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Copies an observer on an order, or removes it. Only the commander, fulfiller or approver of the order can 
+        /// change its observers. An observer can be copied on at most MAX_OBSERVED_ORDERS orders.
+        fn set_order_observer(origin, h: T::Hash, observer: T::AccountId, observing: bool, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::set_observer(who, h, observer, observing, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Used by the seller to respond to a submitted order with different terms (amount and/or deadline), or by either 
+        /// party to counter the open counterproposal of the other party. The number of rounds is bounded.
+        fn propose_counter(origin, h: T::Hash, amount: i128, deadline: u64, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::add_counterproposal(who, h, amount, deadline, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Accepts the open counterproposal of the other party. The order takes the proposed terms and the prefunding 
+        /// of the buyer is adjusted to the proposed amount and deadline. The seller still accepts the order itself.
+        fn accept_counterproposal(origin, h: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                Self::agree_counterproposal(who, h, tx_uid)?;
+                Ok(())
+            })
+        }
+        
+        /// Rejects the open counterproposal of the other party. The order remains on its current terms.
+        fn reject_counterproposal(origin, h: T::Hash, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::with_tx(tx_uid, || {
+                let (_, proposer) = Self::open_counterproposal_of(who.clone(), h, tx_uid)?;
+                <OpenCounterproposal<T>>::remove(&h);
+                Self::deposit_event(RawEvent::CounterproposalRejected(h, who, proposer));
+                Ok(())
+            })
+        }
+        
+        /// Registers the endpoint at which the identity receives the notifications of its orders, the hash of the secret 
+        /// shared with the integration and the events subscribed to. An empty endpoint or no events removes the subscription.
+        fn set_notification_subscription(origin, endpoint: Vec<u8>, secret_hash: T::Hash, events: NotificationEvents) -> Result {
+            let who = ensure_signed(origin)?;
+            if endpoint.len() > MAX_ENDPOINT_LENGTH {
+                Self::deposit_event(RawEvent::ErrorEndpointTooLong());
+                return Err("Endpoint is too long");
+            }
+            if events & !NOTIFY_ALL != 0 {
+                Self::deposit_event(RawEvent::ErrorNotificationEvents(events));
+                return Err("Unknown notification events");
+            }
+            if endpoint.is_empty() || events == 0 {
+                <NotificationSubscriptions<T>>::remove(&who);
+                Self::deposit_event(RawEvent::NotificationSubscriptionRemoved(who));
+            } else {
+                let subscription = NotificationSubscription {
+                    endpoint: endpoint,
+                    secret_hash: secret_hash,
+                    events: events,
+                };
+                <NotificationSubscriptions<T>>::insert(&who, subscription);
+                Self::deposit_event(RawEvent::NotificationSubscriptionSet(who, events));
+            }
+            Ok(())
+        }
+
+        /// Adds an order document schema version to the supported versions
+        fn add_schema_version(origin, schema_version: SchemaVersion) -> Result {
+            ensure_root(origin)?;
+            if Self::is_supported_schema_version(schema_version) {
+                Self::deposit_event(RawEvent::ErrorSchemaVersionExists(schema_version));
+                return Err("Schema version is already supported");
+            }
+            <SupportedSchemaVersions<T>>::mutate(|versions| versions.push(schema_version));
+            Self::deposit_event(RawEvent::SchemaVersionAdded(schema_version));
+            Ok(())
+        }
+
+        /// Places a compliance hold on an order or prefunding until the expiry block, for legal holds in regulated deployments.
+        /// The reason is the hash of the off-chain hold instruction and is mandatory. Placing a hold again replaces it.
+        fn place_compliance_hold(origin, h: T::Hash, reason_hash: T::Hash, expiry: u64) -> Result {
+            T::ComplianceOrigin::ensure_origin(origin)?;
+            let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+            if reason_hash == T::Hash::default() {
+                Self::deposit_event(RawEvent::ErrorComplianceHoldReason(h));
+                return Err("A compliance hold requires a reason");
+            }
+            if expiry <= current_block || expiry > current_block.saturating_add(MAX_COMPLIANCE_HOLD) {
+                Self::deposit_event(RawEvent::ErrorComplianceHoldExpiry(h));
+                return Err("The expiry of the compliance hold is out of bounds");
+            }
+            if let Some((_, previous)) = Self::compliance_hold(&h) {
+                <ComplianceHoldsExpiring<T>>::mutate(previous, |holds| holds.retain(|e| e != &h));
+            }
+            <ComplianceHolds<T>>::insert(&h, (reason_hash, expiry));
+            <ComplianceHoldsExpiring<T>>::mutate(expiry, |holds| holds.push(h));
+            Self::deposit_event(RawEvent::ComplianceHoldPlaced(h, reason_hash, expiry));
+            Ok(())
+        }
+
+        /// Lifts the compliance hold on an order or prefunding
+        fn lift_compliance_hold(origin, h: T::Hash) -> Result {
+            T::ComplianceOrigin::ensure_origin(origin)?;
+            let (reason_hash, expiry) = match <ComplianceHolds<T>>::take(&h) {
+                Some(hold) => hold,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoComplianceHold(h));
+                    return Err("There is no compliance hold on this reference");
+                },
+            };
+            <ComplianceHoldsExpiring<T>>::mutate(expiry, |holds| holds.retain(|e| e != &h));
+            Self::deposit_event(RawEvent::ComplianceHoldLifted(h, reason_hash));
+            Ok(())
+        }
+
+        /// Sets the value up to which approved closed orders take the express lane. Zero disables the express lane.
+        fn set_express_lane_limit(origin, limit: AccountBalanceOf<T>) -> Result {
+            ensure_root(origin)?;
+            let limit: u128 = <T::OrderConversions as Convert<AccountBalanceOf<T>, u128>>::convert(limit);
+            <ExpressLaneLimit<T>>::put(limit);
+            Self::deposit_event(RawEvent::ExpressLaneLimitSet(limit));
+            Ok(())
+        }
+
+        /// Sets the number of blocks before a deadline at which reminders are raised. Zero disables reminders.
+        /// The window applies to the deadlines and due dates set from then on.
+        fn set_reminder_window(origin, window: u64) -> Result {
+            ensure_root(origin)?;
+            <ReminderWindow<T>>::put(window);
+            Ok(())
+        }
+
+        /// Records a reminder with its notifications, one for each party subscribed to reminders. 
+        /// Submitted as an inherent by the offchain worker. The reminder and the notifications are checked again on-chain.
+        fn record_reminder(origin, h: T::Hash, deadline: u64, notifications: Vec<(T::AccountId, Vec<u8>, T::Hash)>) -> Result {
+            ensure_inherent(origin)?;
+            if !Self::pending_reminders().contains(&(h, deadline)) {
+                return Err("No reminder is pending for this order");
+            }
+            let order: OrderHeader<T::AccountId> = match Self::reminder_due(h) {
+                Some((due, order)) if due == deadline => order,
+                _ => return Err("No reminder is due for this order"),
+            };
+            if notifications != Self::reminder_notifications(h, deadline) {
+                return Err("The notifications do not match the subscriptions");
+            }
+            let current_block = <system::Module<T>>::block_number();
+            <ReminderSent<T>>::insert((h, deadline), current_block);
+            <PendingReminders<T>>::mutate(|pending| pending.retain(|r| r != &(h, deadline)));
+            Self::deposit_event(RawEvent::ReminderSent(h, order.commander, order.fulfiller, deadline));
+            for (party, endpoint, digest) in notifications.into_iter() {
+                Self::deposit_event(RawEvent::ReminderNotification(h, party, endpoint, digest));
+            }
             Ok(())
         }
+
+        /// Builds the notifications of the pending reminders from the notification subscriptions and submits them. 
+        /// Reminders already sent are skipped. The offchain API of this node can only submit extrinsics, so the 
+        /// notifications are published in the ReminderNotification event, from which the endpoints collect them.
+        fn offchain_worker(_n: T::BlockNumber) {
+            for (h, deadline) in Self::pending_reminders().into_iter() {
+                if <ReminderSent<T>>::exists((h, deadline)) {
+                    continue;
+                }
+                T::Reminders::submit_reminder(h, deadline, Self::reminder_notifications(h, deadline));
+            }
+        }
     }
 }
 
 impl<T: Trait> Module<T> {
+    /// Runs an order mutation inside a bonsai transaction. The transaction is ended when the mutation succeeds 
+    /// and marked as failed (with the error) when it does not, so that the UI can reconcile its cache.
+    fn with_tx<F: FnOnce() -> Result>(uid: T::Hash, f: F) -> Result {
+        <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(uid)?;
+        match f() {
+            Ok(_) => <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(uid),
+            Err(e) => {
+                <<T as Trait>::Bonsai as Storing<T::Hash>>::fail_tx(uid, e);
+                Err(e)
+            },
+        }
+    }
+    /// Checks that the order document schema version is supported
+    fn check_schema_version(schema_version: SchemaVersion) -> Result {
+        if !Self::is_supported_schema_version(schema_version) {
+            Self::deposit_event(RawEvent::ErrorSchemaVersion(schema_version));
+            return Err("Unsupported order schema version");
+        }
+        Ok(())
+    }
+    /// Checks that an order of the delegate is within the standing approval limits set by the company
+    fn within_delegate_limits(c: &T::AccountId, d: &T::AccountId, amount: u128) -> bool {
+        match Self::delegate_limits((c.clone(), d.clone())) {
+            Some((max_order_value, period_cap)) => {
+                let spent: u128 = Self::delegate_spending((c.clone(), d.clone(), Self::spending_period()));
+                amount <= max_order_value && spent.saturating_add(amount) <= period_cap
+            },
+            None => false,
+        }
+    }
+    fn add_delegate_spending(c: &T::AccountId, d: &T::AccountId, amount: u128) {
+        <DelegateSpending<T>>::mutate((c.clone(), d.clone(), Self::spending_period()), |spent| *spent = spent.saturating_add(amount));
+    }
+    /// Checks that the order value is within the express lane limit
+    fn is_express(amount: u128) -> bool {
+        orders_core::within_limit(amount, Self::express_lane_limit())
+    }
+    fn spending_period() -> u64 {
+        <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number()) / SPENDING_PERIOD
+    }
+    /// Checks that the order type is one of services, goods or expense reimbursement
+    fn check_order_type(order_type: OrderType, uid: T::Hash) -> Result {
+        match order_type {
+            ORDER_TYPE_SERVICE | ORDER_TYPE_GOODS | ORDER_TYPE_EXPENSE => Ok(()),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorOrderType(uid));
+                Err("Unknown order type")
+            },
+        }
+    }
+    /// Checks that the order value is within the network maximum set in the prefunding module
+    fn check_order_value(amount: i128, uid: T::Hash) -> Result {
+        if amount > 0 && !<<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::within_maximum(amount as u128) {
+            Self::deposit_event(RawEvent::ErrorAboveMaximumOrderValue(uid));
+            return Err("Order value is above the network maximum");
+        }
+        Ok(())
+    }
+    /// Checks that the seller accepts new orders
+    fn check_accepting_orders(f: &T::AccountId, uid: T::Hash) -> Result {
+        if Self::not_accepting_orders(f) {
+            Self::deposit_event(RawEvent::ErrorNotAcceptingOrders(uid));
+            return Err("The seller is not accepting new orders");
+        }
+        Ok(())
+    }
+    /// The default schema version is always supported
+    fn is_supported_schema_version(schema_version: SchemaVersion) -> bool {
+        schema_version == DEFAULT_SCHEMA_VERSION || Self::supported_schema_versions().contains(&schema_version)
+    }
     /// Create Open Order
     /// This function simply stores an open sales or purchase order. It is intended for the marketplace,
     /// yet it can be a complex purchase or sales order
@@ -474,7 +1185,7 @@ impl<T: Trait> Module<T> {
     /// The UI therefore handles presentation or reporting currency translations at spot rate 
     /// This is not for goods.
     /// If the order is open, the the fulfiller is ignored. 
-    /// Order type is generally goods (0) or services (1) but is left open for future-proofing 
+    /// Order type is services (0), goods (1) or expense reimbursement (2) and selects the ledger accounts used at settlement
     fn set_simple_prefunded_service_order(
         commander: T::AccountId, 
         approver: T::AccountId, 
@@ -482,18 +1193,20 @@ impl<T: Trait> Module<T> {
         buy_or_sell: u16, // 0: buy, 1: sell, extensible
         amount: i128, // amount should be the sum of all the items untiprices * quantities
         market_order: bool, // 0: open(false) 1: closed(true)
-        order_type: u16, // 0: personal, 1: business, extensible 
+        order_type: OrderType, // 0: service, 1: goods, 2: expense reimbursement
         deadline: u64, // prefunding acceptance deadline 
+        grace_period: u64, // blocks after the deadline during which the seller may still accept
         due_date: u64, // due date is the future delivery date (in blocks) 
         order_hash: T::Hash,
         order_item: OrderItem<T::Hash>, // for simple items there will only be one item, item number is accessed by its position in Vec 
+        schema_version: SchemaVersion,
         bonsai_token: T::Hash,
         uid: T::Hash
     ) -> Result {
         
         // Set order status to submitted by default 
-        // submitted(0), accepted(1), rejected(2), disputed(3), blocked(4), invoiced(5),
-        let order_status: OrderStatus = 0;
+        // submitted(0), accepted(1), rejected(2), disputed(3), blocked(4), invoiced(5), settled(6), defaulted(7)
+        let mut order_status: OrderStatus = 0;
         let mut fulfiller_override: T::AccountId = fulfiller.clone();
         
         // TODO Rewrite this MARKET_ORDER reversing the bool. This is because the API open_closed will be replaced by market_order bool.
@@ -512,20 +1225,24 @@ impl<T: Trait> Module<T> {
                     Self::deposit_event(RawEvent::ErrorCannotBeBoth(bonsai_token));
                     return Err("Cannot make an order for yourself!");
                 };
+                Self::check_accepting_orders(&fulfiller, uid)?;
             },
         }
         // check or set the approver status
-        if Self::check_approver(commander.clone(), approver.clone(), order_hash.clone()) {
+        // The order is also approved when it is within the standing approval limits of a delegate of the approver
+        let balance_amount: u128 = <T::OrderConversions as Convert<i128, u128>>::convert(amount.clone());
+        let delegated: bool = commander != approver && Self::within_delegate_limits(&approver, &commander, balance_amount);
+        let approval_status: ApprovalStatus;
+        if Self::check_approver(commander.clone(), approver.clone(), order_hash.clone()) || delegated {
             // the order is approved.
-            let approval_status: ApprovalStatus = 1;
+            approval_status = 1;
             let deadline_converted: T::BlockNumber = <T::OrderConversions as Convert<u64, T::BlockNumber>>::convert(deadline.clone());
+            let grace_period_converted: T::BlockNumber = <T::OrderConversions as Convert<u64, T::BlockNumber>>::convert(grace_period);
             // approval status has been set to approved, continue.
             
             // Set prefunding first. It does not matter if later the process fails, as this is locking funds for the commander
             // The risk is that they cannot get back the funds until after the deadline, even of they want to cancel.
-            let balance_amount: u128 = <T::OrderConversions as Convert<i128, u128>>::convert(amount.clone());
-            
-            match Self::set_prefunding(commander.clone(), fulfiller.clone(), balance_amount, deadline_converted, order_hash.clone(), uid) {
+            match Self::set_prefunding(commander.clone(), fulfiller.clone(), balance_amount, deadline_converted, grace_period_converted, order_hash.clone(), uid) {
                 Ok(_) => (),
                 Err(_e) => {
                     // Error from setting prefunding "somewhere" ;)
@@ -533,32 +1250,56 @@ impl<T: Trait> Module<T> {
                     return Err("Error in Prefunding Module");
                 },
             }
-            
-            let order_header: OrderHeader<T::AccountId> = OrderHeader {
-                commander: commander.clone(),
-                fulfiller: fulfiller_override.clone(),
-                approver: approver,
-                order_status: order_status,
-                approval_status: approval_status,
-                buy_or_sell: buy_or_sell,
-                amount: amount,
-                market_order: market_order,
-                order_type: order_type,
-                deadline: deadline,
-                due_date: due_date,
-            };
-            
-            let mut vec_order_items: Vec<OrderItem<T::Hash>> = Vec::new();
-            vec_order_items.push(order_item.clone());
-            
-            Self::set_order(commander, fulfiller, order_hash.clone(), order_header, vec_order_items)?;
-            
+            if delegated {
+                Self::add_delegate_spending(&approver, &commander, balance_amount);
+            }
+            // Express lane: the order is invoiced straight away and settled when the buyer confirms delivery.
+            // A prefund queued for co-approval is not locked yet, so the order takes the normal lane.
+            if !market_order && Self::is_express(balance_amount) 
+                && !<<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::awaiting_co_approval(order_hash) {
+                match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::send_express_invoice(fulfiller.clone(), commander.clone(), order_hash, order_type, uid) {
+                    Ok(_) => (),
+                    Err(_e) => {
+                        // No order is stored, so the prefund locked for it is released to the buyer
+                        <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::cancel_prefunding(commander.clone(), order_hash, uid)?;
+                        Self::deposit_event(RawEvent::ErrorInPrefunding5(uid));
+                        return Err("Error in prefunding");
+                    },
+                }
+                order_status = 5;
+                Self::deposit_event(RawEvent::ExpressOrderInvoiced(order_hash));
+            }
         } else {
-            // the order is not yet approved.
-            // This is NOT an error but requires further processing by the approver. Exiting gracefully.
+            // the order is not yet approved. The prefund is locked once the approver signs.
+            // This is NOT an error but requires further processing by the approver.
+            approval_status = 0;
+            <PendingApproval<T>>::insert(&order_hash, grace_period);
             Self::deposit_event(RawEvent::OrderCreatedForApproval(uid));
         }
         
+        let order_header: OrderHeader<T::AccountId> = OrderHeader {
+            commander: commander.clone(),
+            fulfiller: fulfiller_override.clone(),
+            approver: approver,
+            order_status: order_status,
+            approval_status: approval_status,
+            buy_or_sell: buy_or_sell,
+            amount: amount,
+            market_order: market_order,
+            order_type: order_type,
+            deadline: deadline,
+            due_date: due_date,
+            schema_version: schema_version,
+        };
+        
+        let mut vec_order_items: Vec<OrderItem<T::Hash>> = Vec::new();
+        vec_order_items.push(order_item.clone());
+        
+        Self::set_order(commander, fulfiller.clone(), order_hash.clone(), order_header, vec_order_items)?;
+        if order_status == 5 {
+            Self::set_invoice_due(order_hash, &fulfiller);
+        }
+        
         // claim hash in Bonsai
         <<T as Trait>::Bonsai as Storing<T::Hash>>::claim_data(order_hash.clone(), bonsai_token.clone())?;
         
@@ -570,10 +1311,11 @@ impl<T: Trait> Module<T> {
         f: T::AccountId, 
         a: u128, 
         d: T::BlockNumber,
+        g: T::BlockNumber,
         o: T::Hash,
         u: T::Hash
     ) -> Result {
-        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::prefunding_for(c.clone(), f.clone(), a, d, o.clone(), u) {
+        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::prefunding_for(c.clone(), f.clone(), a, d, g, o.clone(), u) {
             Ok(_) => (),
             Err(_e) => {
                 Self::deposit_event(RawEvent::ErrorInPrefunding7(u));
@@ -604,6 +1346,8 @@ impl<T: Trait> Module<T> {
         }
         
         // Set details of Order
+        Self::schedule_reminder(o, h.deadline);
+        Self::schedule_reminder(o, h.due_date);
         <Orders<T>>::insert(&o, h);
         <OrderItems<T>>::insert(&o, i);
         
@@ -616,7 +1360,7 @@ impl<T: Trait> Module<T> {
         let mut order_hdr: OrderHeader<T::AccountId> = Self::orders(&h).ok_or("some error")?;
         
         if a == order_hdr.approver && order_hdr.order_status == 0 {
-            match order_hdr.order_status {
+            match order_hdr.approval_status {
                 0 | 2 => {
                     // can only change to approved (1)
                     match s {
@@ -638,6 +1382,11 @@ impl<T: Trait> Module<T> {
                             return Err("The submitted status not allowed.");
                         },
                     }
+                    // The funds of the buyer are locked once the order is prefunded on approval
+                    if <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::check_ref_owner(order_hdr.commander.clone(), h) {
+                        Self::deposit_event(RawEvent::ErrorApproved(h));
+                        return Err("The order is prefunded and can no longer be unapproved");
+                    }
                 },
                 _ => {
                     // All other values not allowed
@@ -646,8 +1395,23 @@ impl<T: Trait> Module<T> {
                 }
             }
             
+            // An order awaiting approval is prefunded when it is approved. This is the signature of the higher authority
+            // for orders above the standing approval limits of a delegate.
+            if s == 1 {
+                if let Some(grace_period) = Self::pending_approval(&h) {
+                    let balance_amount: u128 = <T::OrderConversions as Convert<i128, u128>>::convert(order_hdr.amount);
+                    let deadline_converted: T::BlockNumber = <T::OrderConversions as Convert<u64, T::BlockNumber>>::convert(order_hdr.deadline);
+                    let grace_period_converted: T::BlockNumber = <T::OrderConversions as Convert<u64, T::BlockNumber>>::convert(grace_period);
+                    Self::set_prefunding(order_hdr.commander.clone(), order_hdr.fulfiller.clone(), balance_amount, deadline_converted, grace_period_converted, h, b)?;
+                    <PendingApproval<T>>::remove(&h);
+                    if <DelegateLimits<T>>::exists((a.clone(), order_hdr.commander.clone())) {
+                        Self::add_delegate_spending(&a, &order_hdr.commander, balance_amount);
+                    }
+                }
+            }
+            
             // All tests passed, set status to whatever.
-            order_hdr.order_status = s;
+            order_hdr.approval_status = s;
             
             <Orders<T>>::insert(&h, order_hdr);
             
@@ -672,6 +1436,7 @@ impl<T: Trait> Module<T> {
         due_date: u64, 
         order_item: OrderItem<T::Hash>,
         reference: T::Hash,
+        schema_version: SchemaVersion,
         bonsai_token: T::Hash
     ) -> Result {
         // Check that the hash exist
@@ -715,6 +1480,9 @@ impl<T: Trait> Module<T> {
             Self::deposit_event(RawEvent::ErrorFulfiller(reference));
             return Err("Not allowed to fulfill your own order!");
         }
+        if order_hdr.fulfiller != fulfiller {
+            Self::check_accepting_orders(&fulfiller, bonsai_token)?;
+        }
         
         if order_hdr.amount != amount {
             if amount < 0i128 {
@@ -729,9 +1497,9 @@ impl<T: Trait> Module<T> {
         let current_block_converted: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(current_block);
         if order_hdr.deadline != deadline {
             // TODO This may be unusable/unworkable needs trying out
-            // 48 hours is the minimum deadline
-            // every time there is a change the deadline gets pushed back by 48 hours byond the current block 
-            let min_deadline: u64 = current_block_converted + 11520u64;
+            // 48 hours is the minimum deadline, unless the parties have agreed a shorter minimum
+            // every time there is a change the deadline gets pushed back by the minimum byond the current block 
+            let min_deadline: u64 = current_block_converted + Self::minimum_deadline(commander.clone(), fulfiller.clone());
             if deadline < min_deadline {
                 Self::deposit_event(RawEvent::ErrorShortDeadline(bonsai_token));
                 return Err("Deadline is too short!");
@@ -741,8 +1509,8 @@ impl<T: Trait> Module<T> {
         
         if order_hdr.due_date != due_date {
             // due date must be at least 1 hours after deadline (TODO - Validate! as this is a guess)
-            // This is basically adding 49 hours to the current block
-            let minimum_due_date: u64 = current_block_converted + 11760u64;
+            // This is basically adding the minimum deadline and 1 hour to the current block
+            let minimum_due_date: u64 = current_block_converted + Self::minimum_deadline(commander.clone(), fulfiller.clone()) + 240u64;
             if due_date < minimum_due_date {
                 Self::deposit_event(RawEvent::ErrorShortDueDate(bonsai_token));
                 return Err("Due Date is too short!");
@@ -762,6 +1530,7 @@ impl<T: Trait> Module<T> {
             order_type: order_hdr.order_type,
             deadline: deadline,
             due_date: due_date,
+            schema_version: schema_version,
         };
         
         // currently just places all the items in the storage WITHOUT CHECKING
@@ -790,24 +1559,53 @@ impl<T: Trait> Module<T> {
                 match s {
                     1 => {
                         // Order Accepted
+                        // After the deadline the order can only be accepted within the grace period of the prefunding
+                        if !<<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::acceptance_open(h) {
+                            Self::deposit_event(RawEvent::ErrorAcceptanceClosed(uid));
+                            return Err("The deadline for accepting has passed");
+                        }
+                        // If the order requires a performance bond then calculate it before anything is changed
+                        let bond_amount: Option<u128> = match Self::performance_bond_terms(&h) {
+                            Some(terms) => match Self::calculate_percentage(<T::OrderConversions as Convert<i128, u128>>::convert(order.amount), terms.0) {
+                                Some(b) => Some(b),
+                                None => {
+                                    Self::deposit_event(RawEvent::ErrorBondAmount(uid));
+                                    return Err("Error calculating the performance bond");
+                                },
+                            },
+                            None => None,
+                        };
                         // Update the prefunding status (confirm locked funds)
-                        let lock: UnLocked<T> = <T::OrderConversions as Convert<bool, UnLocked<T>>>::convert(true);
-                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::set_release_state(f,lock,h,uid) {
+                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::set_release_state(f.clone(),LockState::Accepted,h,uid) {
                             Ok(_) => (),
                             Err(_e) => {
                                 Self::deposit_event(RawEvent::ErrorInPrefunding2(uid));
                                 return Err("Error in prefunding");
                             },
                         }
-                        
+                        // Lock the performance bond from the seller. If it cannot be locked the order is not accepted.
+                        if let Some(bond_amount) = bond_amount {
+                            match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::lock_performance_bond(f.clone(), order.commander.clone(), bond_amount, h, uid) {
+                                Ok(_) => (),
+                                Err(_e) => {
+                                    let _ = <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::set_release_state(f, LockState::AwaitingAcceptance, h, uid);
+                                    Self::deposit_event(RawEvent::ErrorInPrefunding8(uid));
+                                    return Err("Error locking the performance bond");
+                                },
+                            }
+                        }
+                        if Self::cost_plus_terms(&h).is_some() {
+                            let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+                            <CostPlusAccepted<T>>::insert(&h, current_block);
+                        }
+                        
                     },
                     2 => {
                         // order rejected
-                        let lock: UnLocked<T> = <T::OrderConversions as Convert<bool, UnLocked<T>>>::convert(false);
                         // We do not need to set release state for releasing funds for fulfiller.
                         
                         // set release state for releasing funds for commander.
-                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::set_release_state(order.commander.clone(),lock,h,uid.clone()) {
+                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::set_release_state(order.commander.clone(),LockState::ReleasedToSender,h,uid.clone()) {
                             Ok(_) => (),
                             Err(_e) => {
                                 Self::deposit_event(RawEvent::ErrorInPrefunding3(uid));
@@ -836,13 +1634,38 @@ impl<T: Trait> Module<T> {
                 match s {
                     5 => {
                         // Order Completed. Now we are going to issue the invoice.
-                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::send_simple_invoice(f.clone(), order.commander.clone(), order.amount, h, uid) {
+                        // The invoice of a cost-plus order is calculated from the time and expenses booked against it
+                        let cost_plus: Option<(CostPlusInvoice, Vec<T::Hash>)> = match Self::cost_plus_terms(&h) {
+                            Some(terms) => match Self::calculate_cost_plus(f.clone(), h, terms, order.amount) {
+                                Some(c) => Some(c),
+                                None => {
+                                    Self::deposit_event(RawEvent::ErrorCostPlusInvoice(uid));
+                                    return Err("Nothing to invoice, or error calculating the cost-plus invoice");
+                                },
+                            },
+                            None => None,
+                        };
+                        let invoice_amount: i128 = match cost_plus {
+                            Some((ref invoice, _)) => invoice.amount,
+                            None => Self::invoice_amount(h, order.amount),
+                        };
+                        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::send_simple_invoice(f.clone(), order.commander.clone(), invoice_amount, h, order.order_type, uid) {
                             Ok(_) => (),
                             Err(_e) => {
                                 Self::deposit_event(RawEvent::ErrorInPrefunding5(uid));
                                 return Err("Error in prefunding");
                             },
                         }
+                        if let Some(mut lock) = Self::price_lock(&h) {
+                            lock.invoiced = invoice_amount;
+                            <PriceLocks<T>>::insert(&h, lock);
+                        }
+                        if let Some((invoice, time_hashes)) = cost_plus {
+                            <<T as Trait>::Timekeeping as Billing<T::AccountId, T::Hash>>::set_invoiced(h, time_hashes);
+                            <CostPlusInvoices<T>>::insert(&h, invoice);
+                            Self::deposit_event(RawEvent::CostPlusInvoiced(h, invoice.blocks, invoice.time, invoice.expenses, invoice.markup));
+                        }
+                        Self::set_invoice_due(h, &f);
                         
                     },
                     _ => {
@@ -852,7 +1675,7 @@ impl<T: Trait> Module<T> {
                 }
                 
             },
-            2 | 5  => {
+            2 | 5 | 7 | 8 => {
                 Self::deposit_event(RawEvent::ErrorStatusNotAllowed3(uid));
                 return Err("The order has a status that cannot be changed!");
             },
@@ -866,6 +1689,12 @@ impl<T: Trait> Module<T> {
         <Orders<T>>::remove(&h);
         <Orders<T>>::insert(&h, order);
         
+        match s {
+            1 => Self::record_statistics(|statistics| statistics.accepted = statistics.accepted.saturating_add(1)),
+            // An order rejected by the seller is cancelled, as is an order deleted by the buyer
+            2 => Self::record_statistics(|statistics| statistics.cancelled = statistics.cancelled.saturating_add(1)),
+            _ => (),
+        }
         Self::deposit_event(RawEvent::OrderCompleted(uid));
         Ok(())
     }
@@ -873,11 +1702,12 @@ impl<T: Trait> Module<T> {
     fn accept_prefunded_invoice(o: T::AccountId, h: T::Hash, s: OrderStatus, mut order: OrderHeader<T::AccountId>, uid: T::Hash) -> Result {
         // check that this is the fulfiller
         match order.order_status {
-            5 => {
-                // Order has been invoiced. The buyer is now deciding to accept or other
+            5 | 8 => {
+                // Order has been invoiced (and possibly credited). The buyer is now deciding to accept or other
                 match s {
                     3 => {
                         // Invoice is disputed. TODO provide the ability to change the invoice and resubmit
+                        // A performance bond stays locked until the dispute is resolved by arbitrate_performance_bond
                         Self::deposit_event(RawEvent::ErrorNotImplmented1(uid));
                         return Err("TODO!");
                     },
@@ -891,6 +1721,24 @@ impl<T: Trait> Module<T> {
                             },
                         }
                         
+                        // Settled. Release the performance bond to the seller if there is one. The funds have already moved, 
+                        // so a bond that cannot be released does not undo the settlement. Its terms are kept so that 
+                        // the bond can still be released by arbitration.
+                        if <PerformanceBondTerms<T>>::exists(&h) {
+                            match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::release_performance_bond(h, uid) {
+                                Ok(_) => <PerformanceBondTerms<T>>::remove(&h),
+                                Err(_e) => Self::deposit_event(RawEvent::ErrorInPrefunding9(uid)),
+                            }
+                        }
+                        
+                        Self::record_price_variance(h, order.amount);
+                        Self::clear_invoice_due(h, &order);
+                        let value: u128 = Self::invoiced_value(h, order.amount);
+                        Self::record_statistics(|statistics| {
+                            statistics.settled = statistics.settled.saturating_add(1);
+                            statistics.value_settled = statistics.value_settled.saturating_add(value);
+                        });
+                        <TotalValueSettled<T>>::mutate(|total| *total = total.saturating_add(value));
                         Self::deposit_event(RawEvent::InvoiceSettled(uid));
                     },
                     _ => {
@@ -912,6 +1760,874 @@ impl<T: Trait> Module<T> {
         
         Ok(())
     }
+    /// Sets or removes the performance bond terms. Rates are percentages and cannot exceed 100.
+    fn set_bond_terms(c: T::AccountId, h: T::Hash, bond_rate: u16, forfeit_rate: u16, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder2(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander(uid));
+            return Err("Only the commander can set the performance bond terms");
+        }
+        // Terms cannot be changed once the seller has accepted the order
+        if order.order_status != 0 {
+            Self::deposit_event(RawEvent::ErrorOrderStatus4(uid));
+            return Err("Order already accepted - cannot change now!");
+        }
+        if bond_rate > 100 || forfeit_rate > 100 {
+            Self::deposit_event(RawEvent::ErrorBondRate(uid));
+            return Err("Rates cannot be more than 100 percent");
+        }
+        
+        if bond_rate == 0 {
+            <PerformanceBondTerms<T>>::remove(&h);
+        } else {
+            <PerformanceBondTerms<T>>::insert(&h, (bond_rate, forfeit_rate));
+        }
+        
+        Self::deposit_event(RawEvent::PerformanceBondTermsSet(h, bond_rate, forfeit_rate));
+        Ok(())
+    }
+    /// Sets or removes the payment terms of an order that has not been accepted
+    fn set_order_payment_terms(c: T::AccountId, h: T::Hash, net_blocks: u64, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder2(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander(uid));
+            return Err("Only the commander can set the payment terms");
+        }
+        if order.order_status != 0 {
+            Self::deposit_event(RawEvent::ErrorOrderStatus4(uid));
+            return Err("Order already accepted - cannot change now!");
+        }
+        if net_blocks > MAX_PAYMENT_TERMS {
+            Self::deposit_event(RawEvent::ErrorPaymentTerms(c));
+            return Err("Payment terms are too long");
+        }
+        if net_blocks == 0 {
+            <PaymentTerms<T>>::remove(&h);
+        } else {
+            <PaymentTerms<T>>::insert(&h, net_blocks);
+        }
+        Self::deposit_event(RawEvent::PaymentTermsSet(h, net_blocks));
+        Ok(())
+    }
+    /// Stores the due block of an invoice from the payment terms of the order or the default terms of the seller.
+    /// Invoices without payment terms have no due block.
+    fn set_invoice_due(h: T::Hash, f: &T::AccountId) {
+        let terms: u64 = match Self::payment_terms(&h).or_else(|| Self::default_payment_terms(f)) {
+            Some(t) => t,
+            None => return,
+        };
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        let due: u64 = current_block.saturating_add(terms);
+        <InvoiceDue<T>>::insert(&h, due);
+        <InvoicesDueAt<T>>::mutate(due, |invoices| invoices.push(h));
+        Self::deposit_event(RawEvent::InvoiceDueSet(h, due));
+    }
+    /// Removes a settled invoice from the due and overdue lists
+    fn clear_invoice_due(h: T::Hash, order: &OrderHeader<T::AccountId>) {
+        if let Some(due) = <InvoiceDue<T>>::take(&h) {
+            <InvoicesDueAt<T>>::mutate(due, |invoices| invoices.retain(|i| i != &h));
+            <OverduePayables<T>>::mutate(&order.commander, |invoices| invoices.retain(|i| i != &h));
+            <OverdueReceivables<T>>::mutate(&order.fulfiller, |invoices| invoices.retain(|i| i != &h));
+        }
+    }
+    /// Flags the invoices falling due at the block that have not been settled as overdue. At most MAX_OVERDUE_CHECKS 
+    /// invoices are checked, the remainder is carried over to the next block.
+    fn flag_overdue_invoices(n: T::BlockNumber) {
+        let block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(n);
+        let mut invoices: Vec<T::Hash> = <InvoicesDueAt<T>>::take(block);
+        if invoices.len() > MAX_OVERDUE_CHECKS {
+            let carried: Vec<T::Hash> = invoices.split_off(MAX_OVERDUE_CHECKS);
+            <InvoicesDueAt<T>>::mutate(block.saturating_add(1), |next| next.extend(carried));
+        }
+        for h in invoices.into_iter() {
+            let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+                Some(order) => order,
+                None => continue,
+            };
+            // Invoiced (5) or credited (8) and not settled
+            if order.order_status != 5 && order.order_status != 8 {
+                continue;
+            }
+            let due: u64 = Self::invoice_due(&h).unwrap_or(block);
+            <OverduePayables<T>>::mutate(&order.commander, |overdue| overdue.push(h));
+            <OverdueReceivables<T>>::mutate(&order.fulfiller, |overdue| overdue.push(h));
+            Self::deposit_event(RawEvent::OverdueInvoice(h, order.commander, order.fulfiller, due));
+        }
+    }
+    /// Schedules the reminder for a deadline or due date of an order at the start of the reminder window.
+    /// A deadline already within the window is reminded at the end of the next block.
+    fn schedule_reminder(h: T::Hash, deadline: u64) {
+        let window: u64 = Self::reminder_window();
+        if window == 0 {
+            return;
+        }
+        let next_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number()).saturating_add(1);
+        let at: u64 = rstd::cmp::max(deadline.saturating_sub(window), next_block);
+        if at > deadline {
+            return;
+        }
+        <RemindersDueAt<T>>::mutate(at, |due| {
+            if !due.contains(&(h, deadline)) {
+                due.push((h, deadline))
+            }
+        });
+    }
+    /// Queues the reminders falling due at the block for the offchain worker. The conditions are checked again, so that 
+    /// reminders for orders that have been deleted, amended or have reached a final status are dropped, also from the queue.
+    fn queue_reminders(n: T::BlockNumber) {
+        let block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(n);
+        let mut pending: Vec<(T::Hash, u64)> = Self::pending_reminders();
+        pending.retain(|(h, deadline)| Self::reminder_due(*h).map_or(false, |(due, _)| due == *deadline));
+        let capacity: usize = rstd::cmp::min(MAX_REMINDER_CHECKS, MAX_PENDING_REMINDERS.saturating_sub(pending.len()));
+        let mut reminders: Vec<(T::Hash, u64)> = <RemindersDueAt<T>>::take(block);
+        if reminders.len() > capacity {
+            let carried: Vec<(T::Hash, u64)> = reminders.split_off(capacity);
+            <RemindersDueAt<T>>::mutate(block.saturating_add(1), |next| next.extend(carried));
+        }
+        for (h, scheduled) in reminders.into_iter() {
+            match Self::reminder_due(h) {
+                Some((deadline, _)) if deadline == scheduled && !pending.contains(&(h, deadline)) => pending.push((h, deadline)),
+                _ => (),
+            }
+        }
+        <PendingReminders<T>>::put(pending);
+    }
+    /// Returns the reminder notification of each party to an order subscribed to reminders: (party, endpoint, digest). 
+    /// The digest of the order and the deadline is keyed with the hash of the shared secret of the subscription.
+    pub fn reminder_notifications(h: T::Hash, deadline: u64) -> Vec<(T::AccountId, Vec<u8>, T::Hash)> {
+        Self::notification_subscribers(h, NOTIFY_REMINDERS)
+            .into_iter()
+            .map(|(party, endpoint, secret_hash)| {
+                let digest: T::Hash = T::Hashing::hash_of(&(secret_hash, h, deadline, endpoint.clone()));
+                (party, endpoint, digest)
+            })
+            .collect()
+    }
+    /// Removes the compliance holds expiring at the block
+    fn expire_compliance_holds(n: T::BlockNumber) {
+        let block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(n);
+        for h in <ComplianceHoldsExpiring<T>>::take(block).into_iter() {
+            if let Some((reason_hash, _)) = <ComplianceHolds<T>>::take(&h) {
+                Self::deposit_event(RawEvent::ComplianceHoldExpired(h, reason_hash));
+            }
+        }
+    }
+    /// Returns the overdue invoices of a payer (order, payee, due block) for dunning
+    pub fn overdue_payables_of(payer: T::AccountId) -> Vec<(T::Hash, T::AccountId, u64)> {
+        Self::overdue_payables(&payer)
+            .into_iter()
+            .filter_map(|h| Some((h, Self::orders(&h)?.fulfiller, Self::invoice_due(&h)?)))
+            .collect()
+    }
+    /// Returns the overdue invoices of a payee (order, payer, due block) for dunning
+    pub fn overdue_receivables_of(payee: T::AccountId) -> Vec<(T::Hash, T::AccountId, u64)> {
+        Self::overdue_receivables(&payee)
+            .into_iter()
+            .filter_map(|h| Some((h, Self::orders(&h)?.commander, Self::invoice_due(&h)?)))
+            .collect()
+    }
+    /// Returns the order book statistics (period, statistics) from a period, at most MAX_STATISTICS_PERIODS periods
+    pub fn order_statistics_from(from_period: u64, count: u64) -> Vec<(u64, OrderStatistics)> {
+        let end: u64 = from_period.saturating_add(rstd::cmp::min(count, MAX_STATISTICS_PERIODS));
+        (from_period..end)
+            .map(|p| (p, Self::order_statistics(p)))
+            .collect()
+    }
+    /// Returns the current statistics period
+    pub fn statistics_period() -> u64 {
+        <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number()) / STATISTICS_PERIOD
+    }
+    fn record_statistics<F: FnOnce(&mut OrderStatistics)>(f: F) {
+        <OrderStatisticsByPeriod<T>>::mutate(Self::statistics_period(), f);
+    }
+    /// Returns the subscriptions of the parties to an order (commander, fulfiller, approver) and of its observers that 
+    /// subscribe to any of the events: (party, endpoint, hash of the shared secret)
+    pub fn notification_subscribers(h: T::Hash, events: NotificationEvents) -> Vec<(T::AccountId, Vec<u8>, T::Hash)> {
+        let order = match Self::orders(&h) {
+            Some(order) => order,
+            None => return Vec::new(),
+        };
+        let mut parties: Vec<T::AccountId> = Vec::with_capacity(3);
+        for party in [order.commander, order.fulfiller, order.approver].iter().chain(Self::order_observers(&h).iter()) {
+            if !parties.contains(party) {
+                parties.push(party.clone());
+            }
+        }
+        parties
+            .into_iter()
+            .filter_map(|party| {
+                let subscription = Self::notification_subscription(&party)?;
+                if subscription.events & events == 0 {
+                    return None;
+                }
+                Some((party, subscription.endpoint, subscription.secret_hash))
+            })
+            .collect()
+    }
+    /// Forfeits the performance bond when the seller has not completed an accepted order by the due date and returns 
+    /// the prefund to the buyer, which could otherwise only be released by the seller. Sets the order status to defaulted(7)
+    fn claim_bond_on_default(c: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        let mut order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder3(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander2(uid));
+            return Err("Only the commander can claim the performance bond");
+        }
+        if Self::is_held(h) {
+            Self::deposit_event(RawEvent::ErrorComplianceHold(uid));
+            return Err("The order is under a compliance hold");
+        }
+        // Only an accepted order that has not been invoiced can be in default
+        if order.order_status != 1 {
+            Self::deposit_event(RawEvent::ErrorOrderStatus5(uid));
+            return Err("The order is not in a state that can be defaulted");
+        }
+        let terms: (u16, u16) = match Self::performance_bond_terms(&h) {
+            Some(terms) => terms,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoBondTerms(uid));
+                return Err("This order does not have a performance bond");
+            },
+        };
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        if current_block <= order.due_date {
+            Self::deposit_event(RawEvent::ErrorDueDateInPlay(uid));
+            return Err("The due date has not passed yet");
+        }
+        
+        let bond_amount: u128 = Self::calculate_percentage(<T::OrderConversions as Convert<i128, u128>>::convert(order.amount), terms.0).ok_or("Error calculating the performance bond")?;
+        let forfeit_amount: u128 = Self::calculate_percentage(bond_amount, terms.1).ok_or("Error calculating the forfeited amount")?;
+        
+        // The prefund is returned first, so that nothing has moved if it cannot be returned
+        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::release_on_default(c, h, uid) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInPrefunding13(uid));
+                return Err("Error returning the prefund to the buyer");
+            },
+        }
+        
+        order.order_status = 7;
+        <Orders<T>>::insert(&h, order);
+        
+        // A bond that cannot be forfeited does not undo the default. Its terms are kept so that 
+        // the bond can still be resolved by arbitration.
+        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::forfeit_performance_bond(h, forfeit_amount, uid) {
+            Ok(_) => <PerformanceBondTerms<T>>::remove(&h),
+            Err(_e) => Self::deposit_event(RawEvent::ErrorInPrefunding10(uid)),
+        }
+        
+        Self::deposit_event(RawEvent::OrderDefaulted(h));
+        Ok(())
+    }
+    /// Forfeits the given percentage of the performance bond of a disputed order and releases the remainder.
+    /// The bond terms are removed so that the bond is not released again at settlement.
+    fn resolve_bond_dispute(h: T::Hash, forfeit_rate: u16, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder3(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if Self::is_held(h) {
+            Self::deposit_event(RawEvent::ErrorComplianceHold(uid));
+            return Err("The order is under a compliance hold");
+        }
+        // Only accepted orders, invoiced or not, have a locked bond. Settled and defaulted orders keep their bond 
+        // terms only when the bond could not be released or forfeited at the time.
+        match order.order_status {
+            1 | 5 | 6 | 7 | 8 => (),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorOrderStatus5(uid));
+                return Err("The order is not in a state with a performance bond");
+            },
+        }
+        let terms: (u16, u16) = match Self::performance_bond_terms(&h) {
+            Some(terms) => terms,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoBondTerms(uid));
+                return Err("This order does not have a performance bond");
+            },
+        };
+        if forfeit_rate > 100 {
+            Self::deposit_event(RawEvent::ErrorBondRate(uid));
+            return Err("Rates cannot be more than 100 percent");
+        }
+        
+        let bond_amount: u128 = Self::calculate_percentage(<T::OrderConversions as Convert<i128, u128>>::convert(order.amount), terms.0).ok_or("Error calculating the performance bond")?;
+        let forfeit_amount: u128 = Self::calculate_percentage(bond_amount, forfeit_rate).ok_or("Error calculating the forfeited amount")?;
+        
+        let outcome = match forfeit_amount {
+            0 => <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::release_performance_bond(h, uid),
+            _ => <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::forfeit_performance_bond(h, forfeit_amount, uid),
+        };
+        match outcome {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInPrefunding10(uid));
+                return Err("Error forfeiting the performance bond");
+            },
+        }
+        <PerformanceBondTerms<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::PerformanceBondArbitrated(h, forfeit_amount));
+        Ok(())
+    }
+    /// If a project is supplied it must be valid and owned by the commander
+    fn check_order_project(c: T::AccountId, p: Option<T::Hash>, uid: T::Hash) -> Result {
+        if let Some(project_hash) = p {
+            if !<<T as Trait>::Projects as ProjectValidating<T::AccountId, T::Hash>>::is_owner_and_project_valid(c, project_hash) {
+                Self::deposit_event(RawEvent::ErrorProject(uid));
+                return Err("Project is not valid or not owned by you");
+            }
+            if Self::project_orders(&project_hash).len() >= MAX_PROJECT_ORDERS {
+                Self::deposit_event(RawEvent::ErrorTooManyProjectOrders(uid));
+                return Err("Too many orders on this project");
+            }
+        }
+        Ok(())
+    }
+    /// Associates the order with the project
+    fn set_order_project(o: T::Hash, p: Option<T::Hash>) {
+        if let Some(project_hash) = p {
+            <OrderProject<T>>::insert(&o, project_hash);
+            <ProjectOrders<T>>::mutate(&project_hash, |project_orders| {
+                project_orders.retain(|h| h != &o);
+                project_orders.push(o);
+            });
+        }
+    }
+    /// Aggregates the revenue and cost posted by the identity against the orders of a project. Used by the runtime API.
+    /// Revenue is the movement on income accounts (2404...) and cost is the movement on expense accounts (2505...)
+    pub fn project_profitability(o: T::AccountId, p: T::Hash) -> (i128, i128) {
+        let mut revenue: i128 = 0;
+        let mut cost: i128 = 0;
+        for order_hash in Self::project_orders(&p).iter() {
+            let postings = <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_postings_by_reference(order_hash.clone());
+            for posting in postings.into_iter().filter(|posting| posting.0 == o) {
+                let account: u64 = <T::OrderConversions as Convert<AccountOf<T>, u64>>::convert(posting.1);
+                let amount: i128 = <T::OrderConversions as Convert<AccountBalanceOf<T>, i128>>::convert(posting.2);
+                match account / 100_000_000_000u64 {
+                    2404 => revenue = revenue.saturating_add(amount),
+                    2505 => cost = cost.saturating_add(amount),
+                    _ => (),
+                }
+            }
+        }
+        (revenue, cost)
+    }
+    fn is_final_status(s: OrderStatus) -> bool {
+        orders_core::is_final_status(s)
+    }
+    /// Returns the deadline and the order when a reminder is due and has not yet been raised
+    fn reminder_due(h: T::Hash) -> Option<(u64, OrderHeader<T::AccountId>)> {
+        let window: u64 = Self::reminder_window();
+        if window == 0 {
+            return None;
+        }
+        let order: OrderHeader<T::AccountId> = Self::orders(&h)?;
+        let deadline: u64 = match order.order_status {
+            0 => order.deadline,
+            s if Self::is_final_status(s) => return None,
+            _ => order.due_date,
+        };
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        if current_block > deadline || deadline - current_block > window || <ReminderSent<T>>::exists((h, deadline)) {
+            return None;
+        }
+        Some((deadline, order))
+    }
+    /// Credits an invoiced order. The order status becomes credited(8) and the buyer can still settle the remainder.
+    fn credit_invoiced_order(f: T::AccountId, h: T::Hash, amount: u128, reason: T::Hash, uid: T::Hash) -> Result {
+        let mut order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder4(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.fulfiller != f {
+            Self::deposit_event(RawEvent::ErrorNotFulfiller(uid));
+            return Err("Only the seller can issue a credit note");
+        }
+        match order.order_status {
+            5 | 8 => (),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorNotInvoiced(uid));
+                return Err("Only invoiced orders can be credited");
+            },
+        }
+        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::credit_invoice(f.clone(), h, amount, reason, uid) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInPrefunding11(uid));
+                return Err("Error crediting the invoice");
+            },
+        }
+        order.order_status = 8;
+        <Orders<T>>::insert(&h, order.clone());
+        Self::deposit_event(RawEvent::CreditNoteIssued(h, f, order.commander, amount, reason));
+        Ok(())
+    }
+    /// Opens sealed bidding on a market order that has not been accepted. The keys of the buyer must be verified.
+    fn set_sealed_bidding(c: T::AccountId, h: T::Hash, user_hash: T::Hash, deadline: u64, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder5(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander3(uid));
+            return Err("Only the commander can open sealed bidding");
+        }
+        if !order.market_order || order.order_status != 0 {
+            Self::deposit_event(RawEvent::ErrorNotOpenMarketOrder(uid));
+            return Err("Sealed bidding is only for market orders that have not been accepted");
+        }
+        if <SealedBidding<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+            return Err("Sealed bidding is already open");
+        }
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        if deadline <= current_block {
+            Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+            return Err("The bidding deadline must be in the future");
+        }
+        let key: T::Hash = match <<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::verified_encryption_key(user_hash) {
+            Some(key) => key,
+            None => {
+                Self::deposit_event(RawEvent::ErrorBoxKeys(uid));
+                return Err("The box keys are not verified");
+            },
+        };
+        // Bids are encrypted to the key, so it must be a key the commander controls
+        if !<<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::is_keys_owner(&c, user_hash) {
+            Self::deposit_event(RawEvent::ErrorBoxKeys(uid));
+            return Err("The box keys do not belong to the commander");
+        }
+        
+        <SealedBidding<T>>::insert(&h, (deadline, key));
+        Self::deposit_event(RawEvent::SealedBiddingOpened(h, deadline, key));
+        Ok(())
+    }
+    /// Stores a sealed bid. A bidder has only one bid, which is replaced by a later bid.
+    fn place_sealed_bid(b: T::AccountId, h: T::Hash, commitment: T::Hash, encrypted_bid: Vec<u8>, uid: T::Hash) -> Result {
+        let deadline: u64 = match Self::sealed_bidding(&h) {
+            Some((deadline, _)) => deadline,
+            None => {
+                Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+                return Err("Sealed bidding is not open for this order");
+            },
+        };
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        if current_block >= deadline {
+            Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+            return Err("The bidding deadline has passed");
+        }
+        match Self::orders(&h) {
+            Some(order) => {
+                if order.commander == b {
+                    Self::deposit_event(RawEvent::ErrorCannotBeBoth3(uid));
+                    return Err("You cannot bid on your own order");
+                }
+            },
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder5(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        }
+        let mut bids = Self::sealed_bids(&h);
+        bids.retain(|bid| bid.0 != b);
+        if encrypted_bid.is_empty() || encrypted_bid.len() > MAX_SEALED_BID_LENGTH || bids.len() >= MAX_SEALED_BIDS {
+            Self::deposit_event(RawEvent::ErrorSealedBid(uid));
+            return Err("The bid is empty, too long or there are too many bids");
+        }
+        bids.push((b.clone(), commitment, encrypted_bid));
+        <SealedBids<T>>::insert(&h, bids);
+        
+        Self::deposit_event(RawEvent::SealedBidSubmitted(h, b, commitment));
+        Ok(())
+    }
+    /// Converts an amount at an exchange rate (numerator, denominator) using the rounding policy of the ledger
+    fn convert_at_rate(amount: i128, numerator: i128, denominator: i128) -> Option<i128> {
+        let c = |x: i128| <T::OrderConversions as Convert<i128, AccountBalanceOf<T>>>::convert(x);
+        <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::apply_rate(c(amount), c(numerator), c(denominator))
+            .map(|a| <T::OrderConversions as Convert<AccountBalanceOf<T>, i128>>::convert(a))
+    }
+    /// Exchange rate of a currency for the current period
+    fn current_rate(currency: u16) -> Option<(i128, i128)> {
+        let current_block = <system::Module<T>>::block_number();
+        <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_exchange_rate(currency, current_block)
+            .map(|r| (
+                <T::OrderConversions as Convert<AccountBalanceOf<T>, i128>>::convert(r.0),
+                <T::OrderConversions as Convert<AccountBalanceOf<T>, i128>>::convert(r.1),
+            ))
+    }
+    /// Locks the fiat price of an order that has not been accepted
+    fn set_price_lock(c: T::AccountId, h: T::Hash, currency: u16, fixed_fiat: bool, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder2(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander(uid));
+            return Err("Only the commander can lock the fiat price");
+        }
+        if order.order_status != 0 {
+            Self::deposit_event(RawEvent::ErrorOrderStatus4(uid));
+            return Err("Order already accepted - cannot change now!");
+        }
+        let (fiat_amount, rate) = match Self::current_rate(currency).and_then(|r| Self::convert_at_rate(order.amount, r.0, r.1).map(|f| (f, r))) {
+            Some(lock) => lock,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoExchangeRate(uid));
+                return Err("No exchange rate for this currency");
+            },
+        };
+        <PriceLocks<T>>::insert(&h, PriceLock { currency, fiat_amount, rate, fixed_fiat, invoiced: 0 });
+        
+        Self::deposit_event(RawEvent::FiatPriceLocked(h, currency, fiat_amount, fixed_fiat));
+        Ok(())
+    }
+    /// XTX amount to invoice. With a fixed fiat price this is the fiat amount at the rate of the current period,
+    /// up to the prefunded order amount. Without a rate for the period the order amount is invoiced.
+    fn invoice_amount(h: T::Hash, amount: i128) -> i128 {
+        match Self::price_lock(&h) {
+            Some(lock) if lock.fixed_fiat => Self::current_rate(lock.currency)
+                .and_then(|r| Self::convert_at_rate(lock.fiat_amount, r.1, r.0))
+                .map_or(amount, |a| rstd::cmp::min(a, amount)),
+            _ => amount,
+        }
+    }
+    /// Value of a settled order: the cost-plus invoice or the XTX amount invoiced under a price lock, otherwise the order amount
+    fn invoiced_value(h: T::Hash, amount: i128) -> u128 {
+        let invoiced: i128 = match (Self::cost_plus_invoice(&h), Self::price_lock(&h)) {
+            (Some(invoice), _) => invoice.amount,
+            (None, Some(lock)) if lock.invoiced > 0 => lock.invoiced,
+            _ => amount,
+        };
+        invoiced.max(0) as u128
+    }
+    /// Records the variance of a settled order with a locked fiat price against the amounts locked at creation
+    fn record_price_variance(h: T::Hash, amount: i128) {
+        let lock: PriceLock = match Self::price_lock(&h) {
+            Some(lock) => lock,
+            None => return,
+        };
+        let paid: i128 = if lock.fixed_fiat { lock.invoiced } else { amount };
+        let fiat_paid: i128 = match Self::current_rate(lock.currency).and_then(|r| Self::convert_at_rate(paid, r.0, r.1)) {
+            Some(f) => f,
+            None => return,
+        };
+        let variance = (paid.saturating_sub(amount), fiat_paid.saturating_sub(lock.fiat_amount));
+        <PriceVariance<T>>::insert(&h, variance);
+        Self::deposit_event(RawEvent::PriceVarianceRecorded(h, variance.0, variance.1));
+    }
+    /// Sets or removes the cost-plus terms of a service order of a project that has not been accepted
+    fn set_cost_plus(c: T::AccountId, h: T::Hash, hourly_rate: i128, markup: u16, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder7(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander(uid));
+            return Err("Only the commander can set the cost-plus terms");
+        }
+        if order.order_status != 0 {
+            Self::deposit_event(RawEvent::ErrorOrderStatus4(uid));
+            return Err("Order already accepted - cannot change now!");
+        }
+        if order.order_type != ORDER_TYPE_SERVICE || Self::order_project(&h).is_none() || hourly_rate < 0 || markup > 100 {
+            Self::deposit_event(RawEvent::ErrorCostPlusTerms(uid));
+            return Err("Cost-plus billing is only for service orders of a project, with a markup up to 100 percent");
+        }
+        
+        if hourly_rate == 0 {
+            <CostPlusTerms<T>>::remove(&h);
+        } else {
+            <CostPlusTerms<T>>::insert(&h, (hourly_rate, markup));
+        }
+        
+        Self::deposit_event(RawEvent::CostPlusTermsSet(h, hourly_rate, markup));
+        Ok(())
+    }
+    /// Records an expense of the seller against an accepted cost-plus order
+    fn add_cost_plus_expense(f: T::AccountId, h: T::Hash, amount: i128, receipt_hash: T::Hash, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder7(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.fulfiller != f {
+            Self::deposit_event(RawEvent::ErrorNotFulfiller(uid));
+            return Err("Only the seller can record expenses");
+        }
+        let mut expenses = Self::cost_plus_expenses(&h);
+        if order.order_status != 1 || Self::cost_plus_terms(&h).is_none() || amount <= 0 || expenses.len() >= MAX_COST_PLUS_EXPENSES {
+            Self::deposit_event(RawEvent::ErrorCostPlusExpense(uid));
+            return Err("Expenses can only be recorded against accepted cost-plus orders, up to the maximum number of expenses");
+        }
+        expenses.push((amount, receipt_hash));
+        <CostPlusExpenses<T>>::insert(&h, expenses);
+        
+        Self::deposit_event(RawEvent::CostPlusExpenseRecorded(h, amount, receipt_hash));
+        Ok(())
+    }
+    /// Calculates the invoice of a cost-plus order from the billable time of the seller on the project of the order, 
+    /// worked between the acceptance of the order and invoicing, and the recorded expenses. Returns it with the time 
+    /// records invoiced. None if there is nothing to invoice.
+    fn calculate_cost_plus(f: T::AccountId, h: T::Hash, terms: (i128, u16), ceiling: i128) -> Option<(CostPlusInvoice, Vec<T::Hash>)> {
+        let from: u64 = Self::cost_plus_accepted(&h).unwrap_or(0);
+        let to: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        let billable: Vec<(T::Hash, u64)> = match Self::order_project(&h) {
+            Some(p) => <<T as Trait>::Timekeeping as Billing<T::AccountId, T::Hash>>::billable_time(f, p, from, to),
+            None => Vec::new(),
+        };
+        let blocks: u64 = billable.iter().fold(0u64, |total, r| total.saturating_add(r.1));
+        let time: i128 = Self::convert_at_rate(terms.0, blocks as i128, BLOCKS_PER_HOUR as i128)?;
+        let expenses: i128 = Self::cost_plus_expenses(&h).iter().try_fold(0i128, |total, e| total.checked_add(e.0))?;
+        let cost: i128 = time.checked_add(expenses)?;
+        let markup: i128 = Self::convert_at_rate(cost, terms.1 as i128, 100)?;
+        let amount: i128 = rstd::cmp::min(cost.checked_add(markup)?, ceiling);
+        if amount <= 0 {
+            return None;
+        }
+        Some((CostPlusInvoice { blocks, time, expenses, markup, amount }, billable.into_iter().map(|r| r.0).collect()))
+    }
+    /// Appends a message to the message thread of an order. The thread is append-only, so that it is a
+    /// tamper-evident record of the communication between the parties for disputes and audits.
+    fn append_message(s: T::AccountId, h: T::Hash, m: T::Hash, uid: T::Hash) -> Result {
+        if !<Self as Validating<T::AccountId, T::Hash>>::is_order_party(s.clone(), h) {
+            Self::deposit_event(RawEvent::ErrorNotOrderParty(uid));
+            return Err("Only the parties to an order can post messages");
+        }
+        let mut thread = Self::message_thread(&h);
+        if thread.len() >= MAX_THREAD_MESSAGES {
+            Self::deposit_event(RawEvent::ErrorMessageThreadFull(uid));
+            return Err("The message thread of this order is full");
+        }
+        let current_block = <system::Module<T>>::block_number();
+        thread.push((s.clone(), m, current_block));
+        <MessageThread<T>>::insert(&h, thread);
+        
+        Self::deposit_event(RawEvent::OrderMessagePosted(h, s, m));
+        Ok(())
+    }
+    fn set_observer(s: T::AccountId, h: T::Hash, observer: T::AccountId, observing: bool, uid: T::Hash) -> Result {
+        if !<Self as Validating<T::AccountId, T::Hash>>::is_order_party(s.clone(), h) {
+            Self::deposit_event(RawEvent::ErrorNotOrderParty(uid));
+            return Err("Only the parties to an order can change its observers");
+        }
+        let mut observers = Self::order_observers(&h);
+        if observing {
+            if observers.contains(&observer) || <Self as Validating<T::AccountId, T::Hash>>::is_order_party(observer.clone(), h) {
+                Self::deposit_event(RawEvent::ErrorOrderObserver(uid));
+                return Err("The observer is already copied on the order or is a party to it");
+            }
+            if observers.len() >= MAX_ORDER_OBSERVERS {
+                Self::deposit_event(RawEvent::ErrorOrderObserver(uid));
+                return Err("Too many observers on this order");
+            }
+            if Self::observed_orders(&observer).len() >= MAX_OBSERVED_ORDERS {
+                Self::deposit_event(RawEvent::ErrorOrderObserver(uid));
+                return Err("The observer is copied on too many orders");
+            }
+            observers.push(observer.clone());
+            <ObservedOrders<T>>::mutate(&observer, |observed| observed.push(h));
+        } else {
+            if !observers.contains(&observer) {
+                Self::deposit_event(RawEvent::ErrorOrderObserver(uid));
+                return Err("The observer is not copied on the order");
+            }
+            observers.retain(|o| o != &observer);
+            <ObservedOrders<T>>::mutate(&observer, |observed| observed.retain(|o| o != &h));
+        }
+        <OrderObservers<T>>::insert(&h, observers);
+        
+        Self::deposit_event(RawEvent::OrderObserverSet(h, s, observer, observing));
+        Ok(())
+    }
+    /// Accepts a sealed bid after the deadline if the revealed amount and salt match the commitment of the bidder.
+    /// The bidder becomes the fulfiller of the order and the remaining bids are removed.
+    fn award_sealed_bid(c: T::AccountId, h: T::Hash, b: T::AccountId, amount: i128, salt: T::Hash, uid: T::Hash) -> Result {
+        let mut order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder5(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.commander != c {
+            Self::deposit_event(RawEvent::ErrorNotCommander3(uid));
+            return Err("Only the commander can accept a sealed bid");
+        }
+        let deadline: u64 = match Self::sealed_bidding(&h) {
+            Some((deadline, _)) => deadline,
+            None => {
+                Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+                return Err("Sealed bidding is not open for this order");
+            },
+        };
+        let current_block: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number());
+        if current_block < deadline {
+            Self::deposit_event(RawEvent::ErrorSealedBidding(uid));
+            return Err("Bids cannot be accepted before the bidding deadline");
+        }
+        let commitment: T::Hash = match Self::sealed_bids(&h).into_iter().find(|bid| bid.0 == b) {
+            Some(bid) => bid.1,
+            None => {
+                Self::deposit_event(RawEvent::ErrorSealedBid(uid));
+                return Err("This identity has not bid on the order");
+            },
+        };
+        if T::Hashing::hash((b.clone(), amount, salt).encode().as_slice()) != commitment {
+            Self::deposit_event(RawEvent::ErrorBidCommitment(uid));
+            return Err("The revealed bid does not match the commitment");
+        }
+        if amount <= 0 || amount > order.amount {
+            Self::deposit_event(RawEvent::ErrorSealedBid(uid));
+            return Err("The bid must be positive and cannot exceed the order amount");
+        }
+        
+        // The order is awarded at the winning bid. The prefunded remainder is released to the commander at settlement.
+        order.fulfiller = b.clone();
+        order.amount = amount;
+        <Orders<T>>::insert(&h, order);
+        <Beneficiary<T>>::mutate(&b, |beneficiary| beneficiary.push(h.clone()));
+        <AcceptedBid<T>>::insert(&h, (b.clone(), amount));
+        <SealedBidding<T>>::remove(&h);
+        <SealedBids<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::SealedBidAccepted(h, b, amount));
+        Ok(())
+    }
+    /// Records a counterproposal on a submitted closed order that can still be accepted. The seller makes the first 
+    /// proposal, after which the parties can only counter the open proposal of the other party.
+    fn add_counterproposal(p: T::AccountId, h: T::Hash, amount: i128, deadline: u64, uid: T::Hash) -> Result {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder6(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        if order.market_order || order.order_status != 0 || !<<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::acceptance_open(h) {
+            Self::deposit_event(RawEvent::ErrorCounterproposalNotAllowed(uid));
+            return Err("Only submitted closed orders that can still be accepted take counterproposals");
+        }
+        let mut proposals = Self::counterproposals(&h);
+        let allowed: bool = match proposals.last() {
+            Some(last) if Self::open_counterproposal(&h) => (p == order.commander || p == order.fulfiller) && p != last.0,
+            _ => p == order.fulfiller,
+        };
+        if !allowed {
+            Self::deposit_event(RawEvent::ErrorCounterproposalNotAllowed(uid));
+            return Err("It is not your turn to make a counterproposal");
+        }
+        if proposals.len() >= MAX_COUNTERPROPOSAL_ROUNDS {
+            Self::deposit_event(RawEvent::ErrorCounterproposalRounds(uid));
+            return Err("The maximum number of counterproposal rounds has been reached");
+        }
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_converted: u64 = <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(current_block);
+        if amount <= 0i128 || (amount == order.amount && deadline == order.deadline) {
+            Self::deposit_event(RawEvent::ErrorCounterproposalTerms(uid));
+            return Err("A counterproposal must change the amount or the deadline");
+        }
+        // 48 hours (or the minimum agreed by the parties) is the minimum deadline, and the order must still be delivered by the due date
+        if deadline < current_block_converted + Self::minimum_deadline(order.commander.clone(), order.fulfiller.clone()) || deadline >= order.due_date {
+            Self::deposit_event(RawEvent::ErrorCounterproposalTerms(uid));
+            return Err("The deadline is too short or not before the due date");
+        }
+        Self::check_order_value(amount, uid)?;
+        
+        proposals.push((p.clone(), amount, deadline, current_block));
+        <Counterproposals<T>>::insert(&h, proposals);
+        <OpenCounterproposal<T>>::insert(&h, true);
+        
+        Self::deposit_event(RawEvent::CounterproposalMade(h, p, amount, deadline));
+        Ok(())
+    }
+    /// The open counterproposal on an order, and its proposer, as long as the sender is the other party to respond to it
+    fn open_counterproposal_of(r: T::AccountId, h: T::Hash, uid: T::Hash) -> rstd::result::Result<(OrderHeader<T::AccountId>, T::AccountId), &'static str> {
+        let order: OrderHeader<T::AccountId> = match Self::orders(&h) {
+            Some(order) => order,
+            None => {
+                Self::deposit_event(RawEvent::ErrorGettingOrder6(uid));
+                return Err("Unable to fetch order with this reference.");
+            },
+        };
+        let proposer: T::AccountId = match Self::counterproposals(&h).last() {
+            Some(last) if Self::open_counterproposal(&h) && order.order_status == 0 => last.0.clone(),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorNoCounterproposal(uid));
+                return Err("There is no open counterproposal on this order");
+            },
+        };
+        if (r != order.commander && r != order.fulfiller) || r == proposer {
+            Self::deposit_event(RawEvent::ErrorCounterproposalNotAllowed(uid));
+            return Err("Only the other party can respond to a counterproposal");
+        }
+        Ok((order, proposer))
+    }
+    /// Applies the terms of the open counterproposal to the order and adjusts the prefunding of the buyer
+    fn agree_counterproposal(r: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        let (mut order, proposer) = Self::open_counterproposal_of(r.clone(), h, uid)?;
+        let (amount, deadline) = match Self::counterproposals(&h).last() {
+            Some(last) => (last.1, last.2),
+            None => return Err("There is no open counterproposal on this order"),
+        };
+        let deadline_converted: T::BlockNumber = <T::OrderConversions as Convert<u64, T::BlockNumber>>::convert(deadline);
+        match <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::adjust_prefunding(order.commander.clone(), h, amount as u128, deadline_converted, uid) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInPrefunding12(uid));
+                return Err("Error adjusting the prefunding");
+            },
+        }
+        order.amount = amount;
+        order.deadline = deadline;
+        <Orders<T>>::insert(&h, order);
+        <OpenCounterproposal<T>>::remove(&h);
+        Self::schedule_reminder(h, deadline);
+        
+        Self::deposit_event(RawEvent::CounterproposalAccepted(h, r, proposer, amount, deadline));
+        Ok(())
+    }
+    fn minimum_deadline(c: T::AccountId, f: T::AccountId) -> u64 {
+        <<T as Trait>::Prefunding as Encumbrance<T::AccountId,T::Hash,T::BlockNumber>>::minimum_deadline(c, f)
+    }
+    fn calculate_percentage(amount: u128, rate: u16) -> Option<u128> {
+        orders_core::calculate_percentage(amount, rate)
+    }
     /// This is used by any party that wants to accept a market order in whole or part. 
     /// This is non-blocking and can accept many applicants
     fn postulate_simple_prefunded_open_order() -> Result {
@@ -919,6 +2635,16 @@ impl<T: Trait> Module<T> {
     }
 }
 
+impl<T: Trait> ComplianceHolding<T::Hash> for Module<T> {
+    /// The reference is held until the hold is lifted or the expiry block is reached
+    fn is_held(h: T::Hash) -> bool {
+        match Self::compliance_hold(&h) {
+            Some((_, expiry)) => expiry > <T::OrderConversions as Convert<T::BlockNumber, u64>>::convert(<system::Module<T>>::block_number()),
+            None => false,
+        }
+    }
+}
+
 impl<T: Trait> Validating<T::AccountId, T::Hash> for Module<T> {
     /// Check that the order is somehow managed by this identity. Mainly used for BONSAI
     fn is_order_party(o: T::AccountId, r: T::Hash) -> bool {
@@ -942,15 +2668,86 @@ impl<T: Trait> Validating<T::AccountId, T::Hash> for Module<T> {
 
 decl_event!(
     pub enum Event<T> where
+    AccountId = <T as system::Trait>::AccountId,
     Hash = <T as system::Trait>::Hash,
     {
-        OrderCreated(Hash, Hash),
-        OrderUpdated(Hash),
+        /// Order, commander, fulfiller, deadline (block)
+        ReminderSent(Hash, AccountId, AccountId, u64),
+        /// Order, subscribed party, endpoint, digest
+        ReminderNotification(Hash, AccountId, Vec<u8>, Hash),
+        /// Transaction id, order, schema version
+        OrderCreated(Hash, Hash, u16),
+        /// Transaction id, schema version
+        OrderUpdated(Hash, u16),
+        /// Schema version
+        SchemaVersionAdded(u16),
+        /// Company, delegate, maximum order value, cap per spending period
+        DelegateLimitsSet(AccountId, AccountId, u128, u128),
+        /// Express lane limit
+        ExpressLaneLimitSet(u128),
+        /// Order invoiced at creation (express lane)
+        ExpressOrderInvoiced(Hash),
         OrderCreatedForApproval(Hash),
         OrderCreatedForApproval2(Hash),
         OrderStatusUpdate(Hash),
         OrderCompleted(Hash),
         InvoiceSettled(Hash),
+        /// Reference, bond rate, forfeit rate
+        PerformanceBondTermsSet(Hash, u16, u16),
+        OrderDefaulted(Hash),
+        /// Reference, amount of the performance bond forfeited to the buyer
+        PerformanceBondArbitrated(Hash, u128),
+        /// Reference, seller, buyer, credited amount, reason
+        CreditNoteIssued(Hash, AccountId, AccountId, u128, Hash),
+        /// Order, bidding deadline (block), box key of the buyer
+        SealedBiddingOpened(Hash, u64, Hash),
+        /// Order, bidder, commitment
+        SealedBidSubmitted(Hash, AccountId, Hash),
+        /// Order, sender, message hash
+        OrderMessagePosted(Hash, AccountId, Hash),
+        /// Order, party, observer, copied (false when removed)
+        OrderObserverSet(Hash, AccountId, AccountId, bool),
+        /// Order, currency, fiat amount, fixed fiat
+        FiatPriceLocked(Hash, u16, i128, bool),
+        /// Order, XTX variance, fiat variance
+        PriceVarianceRecorded(Hash, i128, i128),
+        /// Order, hourly rate, markup percentage
+        CostPlusTermsSet(Hash, i128, u16),
+        /// Order, amount, receipt hash
+        CostPlusExpenseRecorded(Hash, i128, Hash),
+        /// Order, blocks of time, time at the hourly rate, expenses, markup
+        CostPlusInvoiced(Hash, u64, i128, i128, i128),
+        /// Seller, payment terms (blocks)
+        DefaultPaymentTermsSet(AccountId, u64),
+        /// Seller, accepting new orders
+        AcceptingOrdersSet(AccountId, bool),
+        /// Order, payment terms (blocks)
+        PaymentTermsSet(Hash, u64),
+        /// Order, due block of the invoice
+        InvoiceDueSet(Hash, u64),
+        /// Order, payer, payee, due block
+        OverdueInvoice(Hash, AccountId, AccountId, u64),
+        /// Payment terms are too long
+        ErrorPaymentTerms(AccountId),
+        /// Identity, subscribed events
+        NotificationSubscriptionSet(AccountId, u16),
+        NotificationSubscriptionRemoved(AccountId),
+        /// Unknown notification events
+        ErrorNotificationEvents(u16),
+        /// Order, bidder, amount
+        SealedBidAccepted(Hash, AccountId, i128),
+        /// Order, proposer, amount, deadline (block)
+        CounterproposalMade(Hash, AccountId, i128, u64),
+        /// Order, accepted by, proposer, amount, deadline (block)
+        CounterproposalAccepted(Hash, AccountId, AccountId, i128, u64),
+        /// Order, rejected by, proposer
+        CounterproposalRejected(Hash, AccountId, AccountId),
+        /// Order or prefunding, reason, expiry (block)
+        ComplianceHoldPlaced(Hash, Hash, u64),
+        /// Order or prefunding, reason
+        ComplianceHoldLifted(Hash, Hash),
+        /// Order or prefunding, reason
+        ComplianceHoldExpired(Hash, Hash),
         /// Cannot change an order that you are not the approver of
         ErrorNotApprover(Hash),
         /// This hash already exists! Try again.
@@ -1017,5 +2814,907 @@ decl_event!(
         ErrorInPrefunding7(Hash),
         /// Error Cannot make an market order against a parent order
         ErrorMarketOrder(Hash),
+        /// Unable to fetch order with this reference - bond terms
+        ErrorGettingOrder2(Hash),
+        /// Unable to fetch order with this reference - bond claim
+        ErrorGettingOrder3(Hash),
+        /// Only the commander can set the performance bond terms
+        ErrorNotCommander(Hash),
+        /// Only the commander can claim the performance bond
+        ErrorNotCommander2(Hash),
+        /// Order already accepted - cannot change now!
+        ErrorOrderStatus4(Hash),
+        /// The order is not in a state that can be defaulted
+        ErrorOrderStatus5(Hash),
+        /// Rates cannot be more than 100 percent
+        ErrorBondRate(Hash),
+        /// Error calculating the performance bond
+        ErrorBondAmount(Hash),
+        /// This order does not have a performance bond
+        ErrorNoBondTerms(Hash),
+        /// The due date has not passed yet
+        ErrorDueDateInPlay(Hash),
+        /// Endpoint is too long
+        ErrorEndpointTooLong(),
+        /// Unsupported order schema version
+        ErrorSchemaVersion(u16),
+        /// Schema version is already supported
+        ErrorSchemaVersionExists(u16),
+        /// Error locking the performance bond
+        ErrorInPrefunding8(Hash),
+        /// Error releasing the performance bond
+        ErrorInPrefunding9(Hash),
+        /// Error forfeiting the performance bond
+        ErrorInPrefunding10(Hash),
+        /// Error crediting the invoice
+        ErrorInPrefunding11(Hash),
+        /// Unable to fetch order with this reference - credit note
+        ErrorGettingOrder4(Hash),
+        /// Only the seller can issue a credit note
+        ErrorNotFulfiller(Hash),
+        /// Only invoiced orders can be credited
+        ErrorNotInvoiced(Hash),
+        /// Project is not valid or not owned by you
+        ErrorProject(Hash),
+        /// Too many orders on this project
+        ErrorTooManyProjectOrders(Hash),
+        /// Unable to fetch order with this reference - sealed bidding
+        ErrorGettingOrder5(Hash),
+        /// Only the commander can manage sealed bidding
+        ErrorNotCommander3(Hash),
+        /// Sealed bidding is only for market orders that have not been accepted
+        ErrorNotOpenMarketOrder(Hash),
+        /// Sealed bidding is not open, already open or the deadline does not allow it
+        ErrorSealedBidding(Hash),
+        /// The bid is invalid or there are too many bids
+        ErrorSealedBid(Hash),
+        /// The revealed bid does not match the commitment
+        ErrorBidCommitment(Hash),
+        /// The box keys are not verified
+        ErrorBoxKeys(Hash),
+        /// You cannot bid on your own order
+        ErrorCannotBeBoth3(Hash),
+        /// Unknown order type
+        ErrorOrderType(Hash),
+        /// The order value is above the network maximum
+        ErrorAboveMaximumOrderValue(Hash),
+        /// The deadline (and grace period) for accepting the order has passed
+        ErrorAcceptanceClosed(Hash),
+        /// Delegate limits must be for another identity and the cap cannot be below the maximum order value
+        ErrorDelegateLimits(AccountId),
+        /// Only the parties to an order can post messages
+        ErrorNotOrderParty(Hash),
+        /// The message thread of the order is full
+        ErrorMessageThreadFull(Hash),
+        /// The observer is already copied on the order, is a party to it or is not copied on it, or there are too many observers
+        ErrorOrderObserver(Hash),
+        /// No exchange rate for this currency
+        ErrorNoExchangeRate(Hash),
+        /// Unable to fetch order with this reference - counterproposal
+        ErrorGettingOrder6(Hash),
+        /// The order does not take counterproposals, or not from you
+        ErrorCounterproposalNotAllowed(Hash),
+        /// The maximum number of counterproposal rounds has been reached
+        ErrorCounterproposalRounds(Hash),
+        /// The amount or deadline of the counterproposal is not valid
+        ErrorCounterproposalTerms(Hash),
+        /// There is no open counterproposal on this order
+        ErrorNoCounterproposal(Hash),
+        /// Error adjusting the prefunding
+        ErrorInPrefunding12(Hash),
+        /// The order is under a compliance hold
+        ErrorComplianceHold(Hash),
+        /// A compliance hold requires a reason
+        ErrorComplianceHoldReason(Hash),
+        /// The expiry of the compliance hold is out of bounds
+        ErrorComplianceHoldExpiry(Hash),
+        /// There is no compliance hold on this reference
+        ErrorNoComplianceHold(Hash),
+        /// Unable to fetch order with this reference - cost-plus
+        ErrorGettingOrder7(Hash),
+        /// Cost-plus billing is only for service orders of a project, with a markup up to 100 percent
+        ErrorCostPlusTerms(Hash),
+        /// Expenses can only be recorded against accepted cost-plus orders, up to the maximum number of expenses
+        ErrorCostPlusExpense(Hash),
+        /// Nothing to invoice, or error calculating the cost-plus invoice
+        ErrorCostPlusInvoice(Hash),
+        /// The seller is not accepting new orders
+        ErrorNotAcceptingOrders(Hash),
+        /// Error returning the prefund of a defaulted order to the buyer
+        ErrorInPrefunding13(Hash),
+    }
+);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{sr25519, Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use runtime_primitives::traits::BlakeTwo256;
+    use support::{assert_err, assert_ok};
+    use totem_traits::prefunding::mock::MockEncumbrance;
+    use crate::bonsai;
+    use crate::mock::{account, Origin, Test};
+
+    type OrdersModule = Module<Test>;
+    type BonsaiModule = bonsai::Module<Test>;
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![])
+    }
+
+    fn create_order(fulfiller: sr25519::Public, record_id: H256, tx_uid: H256) -> Result {
+        create_versioned_order(fulfiller, record_id, DEFAULT_SCHEMA_VERSION, tx_uid)
+    }
+
+    fn create_versioned_order(fulfiller: sr25519::Public, record_id: H256, schema_version: SchemaVersion, tx_uid: H256) -> Result {
+        OrdersModule::create_order(
+            Origin::signed(account(1)),
+            account(1),
+            fulfiller,
+            0,
+            100,
+            false,
+            0,
+            20_000,
+            40_000,
+            Vec::new(),
+            None,
+            schema_version,
+            TXKeysL { record_id: record_id, parent_id: record_id, bonsai_token: H256::zero(), tx_uid: tx_uid },
+        )
+    }
+
+    #[test]
+    fn successful_order_ends_tx() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(1);
+            assert_ok!(create_order(account(2), H256::repeat_byte(10), uid));
+            assert!(BonsaiModule::is_successful(uid).is_some());
+            assert!(BonsaiModule::is_started(uid).is_none());
+            assert!(BonsaiModule::is_failed(uid).is_none());
+        });
+    }
+
+    #[test]
+    fn failed_order_leaves_failed_tx_record() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(2);
+            assert_err!(create_order(account(1), H256::repeat_byte(10), uid), "You cannot fulfill your own order");
+            assert_eq!(BonsaiModule::is_failed(uid), Some((0, b"You cannot fulfill your own order".to_vec())));
+            assert!(BonsaiModule::is_started(uid).is_none());
+            assert!(BonsaiModule::is_successful(uid).is_none());
+            assert!(OrdersModule::orders(H256::repeat_byte(10)).is_none());
+
+            // The failed transaction id cannot be reused
+            assert_err!(create_order(account(2), H256::repeat_byte(10), uid), "The transaction ID is not unique. Create a new one.");
+        });
+    }
+
+    #[test]
+    fn failed_delete_leaves_failed_tx_record() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(3);
+            let keys = TXKeysM { record_id: H256::repeat_byte(11), bonsai_token: H256::zero(), tx_uid: uid };
+            assert_err!(OrdersModule::delete_order(Origin::signed(account(1)), keys), "This hash does not exist");
+            assert_eq!(BonsaiModule::is_failed(uid), Some((0, b"This hash does not exist".to_vec())));
+        });
+    }
+
+    #[test]
+    fn schema_version_must_be_supported() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_err!(
+                create_versioned_order(account(2), H256::repeat_byte(12), 2, H256::repeat_byte(4)),
+                "Unsupported order schema version"
+            );
+            assert_err!(OrdersModule::add_schema_version(Origin::ROOT, DEFAULT_SCHEMA_VERSION), "Schema version is already supported");
+            assert_ok!(OrdersModule::add_schema_version(Origin::ROOT, 2));
+            assert_ok!(create_versioned_order(account(2), H256::repeat_byte(12), 2, H256::repeat_byte(5)));
+            assert_eq!(OrdersModule::orders(H256::repeat_byte(12)).map(|o| o.schema_version), Some(2));
+            
+            // Orders stored before the schema version was introduced decode with the default version
+            let order = OrdersModule::orders(H256::repeat_byte(12)).expect("order exists");
+            let mut legacy: Vec<u8> = order.encode();
+            legacy.truncate(legacy.len() - 2);
+            let decoded = OrderHeader::<sr25519::Public>::decode(&mut &legacy[..]).expect("legacy order decodes");
+            assert_eq!(decoded, OrderHeader { schema_version: DEFAULT_SCHEMA_VERSION, ..order });
+        });
+    }
+
+    #[test]
+    fn sellers_not_accepting_orders_keep_their_existing_orders() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(create_order(account(2), H256::repeat_byte(14), H256::repeat_byte(7)));
+            assert_ok!(OrdersModule::set_accepting_orders(Origin::signed(account(2)), false));
+            assert_err!(create_order(account(2), H256::repeat_byte(15), H256::repeat_byte(8)), "The seller is not accepting new orders");
+            assert!(OrdersModule::orders(H256::repeat_byte(15)).is_none());
+            assert!(OrdersModule::orders(H256::repeat_byte(14)).is_some());
+            assert_ok!(create_order(account(3), H256::repeat_byte(15), H256::repeat_byte(9)));
+            
+            assert_ok!(OrdersModule::set_accepting_orders(Origin::signed(account(2)), true));
+            assert_ok!(create_order(account(2), H256::repeat_byte(16), H256::repeat_byte(10)));
+        });
+    }
+
+    #[test]
+    fn order_statistics_count_the_transitions_per_period() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(create_order(account(2), H256::repeat_byte(11), H256::repeat_byte(2)));
+            let keys = TXKeysM { record_id: H256::repeat_byte(11), bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(3) };
+            assert_ok!(OrdersModule::delete_order(Origin::signed(account(1)), keys));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(4)));
+            // Rejected by the seller
+            assert_ok!(create_order(account(2), H256::repeat_byte(12), H256::repeat_byte(7)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), H256::repeat_byte(12), 2, H256::repeat_byte(8)));
+            
+            system::Module::<Test>::set_block_number(STATISTICS_PERIOD);
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(5)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(6)));
+            
+            assert_eq!(OrdersModule::statistics_period(), 1);
+            assert_eq!(
+                OrdersModule::order_statistics_from(0, 3),
+                vec![
+                    (0, OrderStatistics { created: 3, accepted: 1, settled: 0, cancelled: 2, value_settled: 0 }),
+                    (1, OrderStatistics { created: 0, accepted: 0, settled: 1, cancelled: 0, value_settled: 100 }),
+                    (2, OrderStatistics::default()),
+                ]
+            );
+            assert_eq!(OrdersModule::total_value_settled(), 100);
+        });
+    }
+
+    #[test]
+    fn order_type_must_be_known() {
+        with_externalities(&mut new_test_ext(), || {
+            let record_id = H256::repeat_byte(13);
+            let keys = TXKeysL { record_id: record_id, parent_id: record_id, bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(6) };
+            assert_err!(
+                OrdersModule::create_order(Origin::signed(account(1)), account(1), account(2), 0, 100, false, 3, 20_000, 40_000, Vec::new(), None, DEFAULT_SCHEMA_VERSION, keys),
+                "Unknown order type"
+            );
+            assert!(OrdersModule::orders(record_id).is_none());
+        });
+    }
+
+    #[test]
+    fn sealed_bid_must_match_commitment() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(20);
+            let salt = H256::repeat_byte(21);
+            <Orders<Test>>::insert(&h, OrderHeader {
+                commander: account(1),
+                fulfiller: account(1),
+                approver: account(1),
+                amount: 100,
+                market_order: true,
+                ..Default::default()
+            });
+            assert_err!(
+                OrdersModule::open_sealed_bidding(Origin::signed(account(1)), h, H256::repeat_byte(22), 10, H256::repeat_byte(5)),
+                "The box keys do not belong to the commander"
+            );
+            assert_ok!(OrdersModule::open_sealed_bidding(Origin::signed(account(1)), h, H256::repeat_byte(1), 10, H256::repeat_byte(6)));
+            
+            let commitment = BlakeTwo256::hash((account(2), 90i128, salt).encode().as_slice());
+            assert_err!(
+                OrdersModule::submit_sealed_bid(Origin::signed(account(1)), h, commitment, vec![1u8; 48], H256::repeat_byte(7)),
+                "You cannot bid on your own order"
+            );
+            assert_ok!(OrdersModule::submit_sealed_bid(Origin::signed(account(2)), h, commitment, vec![1u8; 48], H256::repeat_byte(8)));
+            assert_err!(
+                OrdersModule::accept_sealed_bid(Origin::signed(account(1)), h, account(2), 90, salt, H256::repeat_byte(9)),
+                "Bids cannot be accepted before the bidding deadline"
+            );
+            
+            system::Module::<Test>::set_block_number(10);
+            assert_err!(
+                OrdersModule::submit_sealed_bid(Origin::signed(account(3)), h, commitment, vec![1u8; 48], H256::repeat_byte(10)),
+                "The bidding deadline has passed"
+            );
+            assert_err!(
+                OrdersModule::accept_sealed_bid(Origin::signed(account(1)), h, account(2), 80, salt, H256::repeat_byte(11)),
+                "The revealed bid does not match the commitment"
+            );
+            assert_ok!(OrdersModule::accept_sealed_bid(Origin::signed(account(1)), h, account(2), 90, salt, H256::repeat_byte(12)));
+            assert_eq!(OrdersModule::accepted_bid(h), Some((account(2), 90)));
+            assert_eq!(OrdersModule::orders(h).map(|o| (o.fulfiller, o.amount)), Some((account(2), 90)));
+            assert!(OrdersModule::sealed_bids(h).is_empty());
+        });
+    }
+
+    #[test]
+    fn delegate_orders_above_limits_await_approval() {
+        with_externalities(&mut new_test_ext(), || {
+            let order = |amount: i128, n: u8| {
+                system::Module::<Test>::set_block_number(n as u64);
+                let item = OrderItem { product: H256::repeat_byte(30), unit_price: amount, quantity: 1, unit_of_measure: 1 };
+                OrdersModule::create_spfso(
+                    Origin::signed(account(3)), account(1), account(2), 0, amount, false, 0, 20_000, 0, 40_000, item, None,
+                    DEFAULT_SCHEMA_VERSION, H256::repeat_byte(n), H256::repeat_byte(n)
+                )
+            };
+            assert_err!(OrdersModule::set_delegate_limits(Origin::signed(account(1)), account(3), 100, 50), "Invalid delegate limits");
+            assert_ok!(OrdersModule::set_delegate_limits(Origin::signed(account(1)), account(3), 100, 150));
+            
+            // Within the limits, above the cap of the period and above the maximum order value
+            assert_ok!(order(80, 1));
+            assert_ok!(order(80, 2));
+            assert_ok!(order(120, 3));
+            let orders = OrdersModule::owner(account(3));
+            let approval = |h: &H256| OrdersModule::orders(h).map(|o| o.approval_status);
+            assert_eq!(orders.iter().map(|h| approval(h)).collect::<Vec<_>>(), vec![Some(1), Some(0), Some(0)]);
+            assert!(OrdersModule::pending_approval(orders[0]).is_none());
+            assert_eq!(OrdersModule::delegate_spending((account(1), account(3), 0)), 80);
+            
+            // The signature of the company prefunds the order
+            assert_err!(
+                OrdersModule::change_approval(Origin::signed(account(3)), orders[1], 1, H256::zero(), H256::repeat_byte(4)),
+                "Cannot change an order that you are not the approver of"
+            );
+            assert_ok!(OrdersModule::change_approval(Origin::signed(account(1)), orders[1], 1, H256::zero(), H256::repeat_byte(5)));
+            assert_eq!(approval(&orders[1]), Some(1));
+            assert!(OrdersModule::pending_approval(orders[1]).is_none());
+            assert_eq!(OrdersModule::delegate_spending((account(1), account(3), 0)), 160);
+        });
+    }
+
+    #[test]
+    fn prefunded_orders_cannot_be_unapproved() {
+        with_externalities(&mut new_test_ext(), || {
+            let item = OrderItem { product: H256::repeat_byte(30), unit_price: 120, quantity: 1, unit_of_measure: 1 };
+            assert_ok!(OrdersModule::set_delegate_limits(Origin::signed(account(1)), account(3), 100, 150));
+            assert_ok!(OrdersModule::create_spfso(
+                Origin::signed(account(3)), account(1), account(2), 0, 120, false, 0, 20_000, 0, 40_000, item, None,
+                DEFAULT_SCHEMA_VERSION, H256::repeat_byte(1), H256::repeat_byte(1)
+            ));
+            let h = OrdersModule::owner(account(3))[0];
+            assert_ok!(OrdersModule::change_approval(Origin::signed(account(1)), h, 1, H256::zero(), H256::repeat_byte(2)));
+            
+            // Approval prefunded the order, so it can no longer be unapproved
+            MockEncumbrance::reset();
+            assert_err!(
+                OrdersModule::change_approval(Origin::signed(account(1)), h, 2, H256::zero(), H256::repeat_byte(3)),
+                "The order is prefunded and can no longer be unapproved"
+            );
+            assert_eq!(MockEncumbrance::calls(), vec!["check_ref_owner"]);
+            assert_eq!(OrdersModule::orders(h).map(|o| o.approval_status), Some(1));
+            
+            // Without a prefunding the approval can be withdrawn
+            MockEncumbrance::deny("check_ref_owner");
+            assert_ok!(OrdersModule::change_approval(Origin::signed(account(1)), h, 2, H256::zero(), H256::repeat_byte(4)));
+            assert_eq!(OrdersModule::orders(h).map(|o| o.approval_status), Some(2));
+        });
+    }
+
+    #[test]
+    fn orders_within_express_lane_limit_are_invoiced_at_creation() {
+        with_externalities(&mut new_test_ext(), || {
+            let order = |amount: i128, n: u8| {
+                system::Module::<Test>::set_block_number(n as u64);
+                let item = OrderItem { product: H256::repeat_byte(30), unit_price: amount, quantity: 1, unit_of_measure: 1 };
+                OrdersModule::create_spfso(
+                    Origin::signed(account(1)), account(1), account(2), 0, amount, false, 0, 20_000, 0, 40_000, item, None,
+                    DEFAULT_SCHEMA_VERSION, H256::repeat_byte(n), H256::repeat_byte(n)
+                )
+            };
+            assert_ok!(order(10, 1));
+            assert_ok!(OrdersModule::set_express_lane_limit(Origin::ROOT, 50));
+            assert_ok!(order(50, 2));
+            assert_ok!(order(51, 3));
+            let status = OrdersModule::owner(account(1))
+                .iter()
+                .map(|h| OrdersModule::orders(h).map(|o| o.order_status))
+                .collect::<Vec<_>>();
+            assert_eq!(status, vec![Some(0), Some(5), Some(0)]);
+        });
+    }
+
+    #[test]
+    fn failed_express_invoice_cancels_the_prefund() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(1);
+            assert_ok!(OrdersModule::set_express_lane_limit(Origin::ROOT, 50));
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("send_express_invoice", "There was an error posting to accounts");
+            let item = OrderItem { product: H256::repeat_byte(30), unit_price: 50, quantity: 1, unit_of_measure: 1 };
+            assert_err!(
+                OrdersModule::create_spfso(
+                    Origin::signed(account(1)), account(1), account(2), 0, 50, false, 0, 20_000, 0, 40_000, item, None,
+                    DEFAULT_SCHEMA_VERSION, H256::repeat_byte(20), uid
+                ),
+                "Error in prefunding"
+            );
+            assert_eq!(MockEncumbrance::calls(), vec!["within_maximum", "prefunding_for", "send_express_invoice", "cancel_prefunding"]);
+            assert!(OrdersModule::owner(account(1)).is_empty());
+            assert!(OrdersModule::orders(H256::repeat_byte(20)).is_none());
+            assert!(BonsaiModule::is_failed(uid).is_some());
+        });
+    }
+
+    #[test]
+    fn observers_are_copied_on_orders_by_their_parties() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_err!(
+                OrdersModule::set_order_observer(Origin::signed(account(4)), h, account(4), true, H256::repeat_byte(2)),
+                "Only the parties to an order can change its observers"
+            );
+            assert_err!(
+                OrdersModule::set_order_observer(Origin::signed(account(1)), h, account(2), true, H256::repeat_byte(3)),
+                "The observer is already copied on the order or is a party to it"
+            );
+            assert_ok!(OrdersModule::set_order_observer(Origin::signed(account(1)), h, account(4), true, H256::repeat_byte(4)));
+            assert_ok!(OrdersModule::set_order_observer(Origin::signed(account(2)), h, account(5), true, H256::repeat_byte(5)));
+            assert_eq!(OrdersModule::order_observers(h), vec![account(4), account(5)]);
+            assert_eq!(OrdersModule::observed_orders(account(4)), vec![h]);
+            
+            // Observers cannot be copied on orders without end
+            <ObservedOrders<Test>>::insert(account(6), vec![H256::zero(); MAX_OBSERVED_ORDERS]);
+            assert_err!(
+                OrdersModule::set_order_observer(Origin::signed(account(1)), h, account(6), true, H256::repeat_byte(8)),
+                "The observer is copied on too many orders"
+            );
+            
+            // Observers are notified like the parties
+            let secret = H256::repeat_byte(50);
+            assert_ok!(OrdersModule::set_notification_subscription(Origin::signed(account(4)), b"https://cfo".to_vec(), secret, NOTIFY_STATUS));
+            assert_eq!(OrdersModule::notification_subscribers(h, NOTIFY_STATUS), vec![(account(4), b"https://cfo".to_vec(), secret)]);
+            
+            assert_ok!(OrdersModule::set_order_observer(Origin::signed(account(2)), h, account(4), false, H256::repeat_byte(6)));
+            assert!(OrdersModule::observed_orders(account(4)).is_empty());
+            assert!(OrdersModule::notification_subscribers(h, NOTIFY_STATUS).is_empty());
+            
+            // Deleting the order uncopies the observers
+            let keys = TXKeysM { record_id: h, bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(7) };
+            assert_ok!(OrdersModule::delete_order(Origin::signed(account(1)), keys));
+            assert!(OrdersModule::order_observers(h).is_empty());
+            assert!(OrdersModule::observed_orders(account(5)).is_empty());
+        });
+    }
+
+    #[test]
+    fn only_order_parties_post_to_the_message_thread() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            system::Module::<Test>::set_block_number(3);
+            assert_ok!(OrdersModule::post_order_message(Origin::signed(account(2)), h, H256::repeat_byte(40), H256::repeat_byte(2)));
+            assert_ok!(OrdersModule::post_order_message(Origin::signed(account(1)), h, H256::repeat_byte(41), H256::repeat_byte(3)));
+            assert_err!(
+                OrdersModule::post_order_message(Origin::signed(account(4)), h, H256::repeat_byte(42), H256::repeat_byte(4)),
+                "Only the parties to an order can post messages"
+            );
+            assert_eq!(
+                OrdersModule::message_thread(h),
+                vec![(account(2), H256::repeat_byte(40), 3), (account(1), H256::repeat_byte(41), 3)]
+            );
+        });
+    }
+
+    #[test]
+    fn fixed_fiat_orders_invoice_the_fiat_amount_and_record_the_variance() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(accounting::Module::<Test>::set_exchange_rate(Origin::ROOT, 978, 0, 2, 1));
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_err!(
+                OrdersModule::lock_fiat_price(Origin::signed(account(1)), h, 840, true, H256::repeat_byte(2)),
+                "No exchange rate for this currency"
+            );
+            assert_ok!(OrdersModule::lock_fiat_price(Origin::signed(account(1)), h, 978, true, H256::repeat_byte(3)));
+            assert_eq!(OrdersModule::price_lock(h).map(|l| l.fiat_amount), Some(200));
+            
+            // XTX doubles against the currency in the next rate period
+            assert_ok!(accounting::Module::<Test>::set_exchange_rate(Origin::ROOT, 978, 1, 4, 1));
+            system::Module::<Test>::set_block_number(5760);
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(4)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(5)));
+            assert_eq!(OrdersModule::price_lock(h).map(|l| l.invoiced), Some(50));
+            // The converted amount is invoiced, and the prefunding settles at the invoiced amount
+            assert_eq!(MockEncumbrance::invoiced(), vec![50]);
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(6)));
+            assert_eq!(OrdersModule::price_variance(h), Some((-50, 0)));
+            // The order is settled at the invoiced amount, not at the prefunded amount
+            assert_eq!(OrdersModule::total_value_settled(), 50);
+            assert_eq!(OrdersModule::order_statistics(1).value_settled, 50);
+        });
+    }
+
+    #[test]
+    fn unpaid_invoices_become_overdue_after_the_payment_terms() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_err!(OrdersModule::set_default_payment_terms(Origin::signed(account(2)), MAX_PAYMENT_TERMS + 1), "Payment terms are too long");
+            assert_ok!(OrdersModule::set_default_payment_terms(Origin::signed(account(2)), 100));
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_err!(
+                OrdersModule::set_payment_terms(Origin::signed(account(2)), h, 50, H256::repeat_byte(2)),
+                "Only the commander can set the payment terms"
+            );
+            assert_ok!(OrdersModule::set_payment_terms(Origin::signed(account(1)), h, 50, H256::repeat_byte(3)));
+            
+            system::Module::<Test>::set_block_number(10);
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(4)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(5)));
+            assert_eq!(OrdersModule::invoice_due(h), Some(60));
+            
+            OrdersModule::flag_overdue_invoices(59);
+            assert!(OrdersModule::overdue_payables_of(account(1)).is_empty());
+            OrdersModule::flag_overdue_invoices(60);
+            assert_eq!(OrdersModule::overdue_payables_of(account(1)), vec![(h, account(2), 60)]);
+            assert_eq!(OrdersModule::overdue_receivables_of(account(2)), vec![(h, account(1), 60)]);
+            
+            // Settling clears the overdue invoice
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(6)));
+            assert!(OrdersModule::overdue_payables_of(account(1)).is_empty());
+            assert!(OrdersModule::overdue_receivables_of(account(2)).is_empty());
+        });
+    }
+
+    #[test]
+    fn reminders_are_raised_at_the_start_of_the_window() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            let secret = H256::repeat_byte(50);
+            assert_ok!(OrdersModule::set_reminder_window(Origin::ROOT, 1_000));
+            assert_ok!(OrdersModule::set_notification_subscription(Origin::signed(account(2)), b"https://seller".to_vec(), secret, NOTIFY_REMINDERS));
+            assert_ok!(OrdersModule::set_notification_subscription(Origin::signed(account(1)), b"https://buyer".to_vec(), secret, NOTIFY_STATUS));
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_eq!(OrdersModule::reminders_due_at(19_000), vec![(h, 20_000)]);
+            assert_eq!(OrdersModule::reminders_due_at(39_000), vec![(h, 40_000)]);
+            
+            system::Module::<Test>::set_block_number(19_000);
+            OrdersModule::queue_reminders(19_000);
+            assert_eq!(OrdersModule::pending_reminders(), vec![(h, 20_000)]);
+            assert!(OrdersModule::reminders_due_at(19_000).is_empty());
+            
+            // Only the parties subscribed to reminders are notified
+            let notifications = OrdersModule::reminder_notifications(h, 20_000);
+            assert_eq!(notifications.len(), 1);
+            assert_eq!(notifications[0].0, account(2));
+            assert_eq!(notifications[0].1, b"https://seller".to_vec());
+            
+            // The notifications are checked against the subscriptions
+            let forged = vec![(account(2), b"https://elsewhere".to_vec(), notifications[0].2)];
+            assert_err!(
+                OrdersModule::record_reminder(Origin::INHERENT, h, 20_000, forged),
+                "The notifications do not match the subscriptions"
+            );
+            assert_err!(
+                OrdersModule::record_reminder(Origin::signed(account(2)), h, 20_000, notifications.clone()),
+                "bad origin: expected to be an inherent origin"
+            );
+            
+            system::Module::<Test>::set_block_number(19_001);
+            assert_ok!(OrdersModule::record_reminder(Origin::INHERENT, h, 20_000, notifications.clone()));
+            assert_eq!(OrdersModule::reminder_sent((h, 20_000)), Some(19_001));
+            assert!(OrdersModule::pending_reminders().is_empty());
+            
+            // A reminder is sent only once
+            assert_err!(
+                OrdersModule::record_reminder(Origin::INHERENT, h, 20_000, notifications),
+                "No reminder is pending for this order"
+            );
+            
+            // The order was not accepted, so there is no reminder for the due date
+            system::Module::<Test>::set_block_number(39_000);
+            OrdersModule::queue_reminders(39_000);
+            assert!(OrdersModule::pending_reminders().is_empty());
+            assert_eq!(OrdersModule::reminder_sent((h, 40_000)), None);
+        });
+    }
+
+    #[test]
+    fn reminders_no_longer_due_are_dropped_from_the_queue() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(OrdersModule::set_reminder_window(Origin::ROOT, 1_000));
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            system::Module::<Test>::set_block_number(19_000);
+            OrdersModule::queue_reminders(19_000);
+            assert_eq!(OrdersModule::pending_reminders(), vec![(h, 20_000)]);
+            
+            // The deadline passes before the reminder is recorded
+            system::Module::<Test>::set_block_number(20_001);
+            assert_err!(
+                OrdersModule::record_reminder(Origin::INHERENT, h, 20_000, OrdersModule::reminder_notifications(h, 20_000)),
+                "No reminder is due for this order"
+            );
+            OrdersModule::queue_reminders(20_001);
+            assert!(OrdersModule::pending_reminders().is_empty());
+            assert_eq!(OrdersModule::reminder_sent((h, 20_000)), None);
+        });
+    }
+
+    #[test]
+    fn failed_prefunding_leaves_no_order() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(1);
+            let item = OrderItem { product: H256::repeat_byte(30), unit_price: 100, quantity: 1, unit_of_measure: 1 };
+            MockEncumbrance::fail_next("prefunding_for", "Insufficient funds");
+            assert_err!(
+                OrdersModule::create_spfso(
+                    Origin::signed(account(1)), account(1), account(2), 0, 100, false, 0, 20_000, 0, 40_000, item, None,
+                    DEFAULT_SCHEMA_VERSION, H256::repeat_byte(20), uid
+                ),
+                "Error in Prefunding Module"
+            );
+            assert_eq!(MockEncumbrance::calls(), vec!["within_maximum", "prefunding_for"]);
+            assert!(OrdersModule::owner(account(1)).is_empty());
+            assert!(BonsaiModule::is_failed(uid).is_some());
+        });
+    }
+
+    #[test]
+    fn failed_invoice_keeps_the_order_accepted() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(2)));
+            
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("send_simple_invoice", "Prefunding not found");
+            assert_err!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(3)), "Error setting prefunding state");
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(1));
+            assert!(OrdersModule::invoice_due(h).is_none());
+            
+            // The failure is only scripted once
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(4)));
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(5));
+            assert_eq!(MockEncumbrance::calls(), vec!["send_simple_invoice", "send_simple_invoice"]);
+        });
+    }
+
+    #[test]
+    fn orders_are_not_accepted_when_the_performance_bond_cannot_be_locked() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(OrdersModule::set_performance_bond_terms(Origin::signed(account(1)), h, 10, 50, H256::repeat_byte(2)));
+            
+            // The bond is locked once the prefunding is accepted, and the acceptance is undone when it cannot be locked
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("lock_performance_bond", "Not enough funds for the performance bond");
+            assert_err!(
+                OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(3)),
+                "Error setting prefunding state"
+            );
+            assert_eq!(
+                MockEncumbrance::calls(),
+                vec!["acceptance_open", "set_release_state", "lock_performance_bond", "set_release_state"]
+            );
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(0));
+        });
+    }
+
+    #[test]
+    fn disputed_performance_bonds_are_forfeited_by_arbitration_and_not_released_again() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(OrdersModule::set_performance_bond_terms(Origin::signed(account(1)), h, 10, 50, H256::repeat_byte(2)));
+            assert!(OrdersModule::arbitrate_performance_bond(Origin::signed(account(1)), h, 100, H256::repeat_byte(3)).is_err());
+            assert_err!(
+                OrdersModule::arbitrate_performance_bond(Origin::ROOT, h, 100, H256::repeat_byte(3)),
+                "The order is not in a state with a performance bond"
+            );
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(4)));
+            
+            MockEncumbrance::reset();
+            assert_ok!(OrdersModule::arbitrate_performance_bond(Origin::ROOT, h, 100, H256::repeat_byte(5)));
+            assert_eq!(MockEncumbrance::calls(), vec!["forfeit_performance_bond"]);
+            assert!(OrdersModule::performance_bond_terms(h).is_none());
+            assert_err!(
+                OrdersModule::arbitrate_performance_bond(Origin::ROOT, h, 0, H256::repeat_byte(6)),
+                "This order does not have a performance bond"
+            );
+        });
+    }
+
+    #[test]
+    fn settlement_completes_when_the_performance_bond_cannot_be_released() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(OrdersModule::set_performance_bond_terms(Origin::signed(account(1)), h, 10, 50, H256::repeat_byte(2)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(3)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(4)));
+            
+            // The invoice has been paid, so the order is settled and the bond is left for arbitration
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("release_performance_bond", "Performance bond not found");
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(5)));
+            assert_eq!(MockEncumbrance::calls(), vec!["settle_prefunded_invoice", "release_performance_bond"]);
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(6));
+            assert_eq!(OrdersModule::performance_bond_terms(h), Some((10, 50)));
+            
+            assert_ok!(OrdersModule::arbitrate_performance_bond(Origin::ROOT, h, 0, H256::repeat_byte(6)));
+            assert!(OrdersModule::performance_bond_terms(h).is_none());
+        });
     }
-);
\ No newline at end of file
+
+    #[test]
+    fn performance_bonds_are_forfeited_only_once_the_prefund_is_returned_on_default() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            assert_ok!(OrdersModule::set_performance_bond_terms(Origin::signed(account(1)), h, 10, 50, H256::repeat_byte(2)));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(3)));
+            system::Module::<Test>::set_block_number(40_001);
+            
+            // Nothing is forfeited when the prefund cannot be returned
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("release_on_default", "The prefunding is not in a state that can be defaulted");
+            assert!(OrdersModule::claim_performance_bond(Origin::signed(account(1)), h, H256::repeat_byte(4)).is_err());
+            assert_eq!(MockEncumbrance::calls(), vec!["release_on_default"]);
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(1));
+            assert_eq!(OrdersModule::performance_bond_terms(h), Some((10, 50)));
+            
+            MockEncumbrance::reset();
+            assert_ok!(OrdersModule::claim_performance_bond(Origin::signed(account(1)), h, H256::repeat_byte(5)));
+            assert_eq!(MockEncumbrance::calls(), vec!["release_on_default", "forfeit_performance_bond"]);
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(7));
+            assert!(OrdersModule::performance_bond_terms(h).is_none());
+        });
+    }
+
+    #[test]
+    fn orders_cannot_be_accepted_once_the_acceptance_window_has_closed() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            MockEncumbrance::deny("acceptance_open");
+            assert_err!(
+                OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(2)),
+                "Error setting prefunding state"
+            );
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(0));
+            
+            // Rejecting releases the funds of the buyer
+            MockEncumbrance::reset();
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 2, H256::repeat_byte(3)));
+            assert_eq!(MockEncumbrance::calls(), vec!["set_release_state", "unlock_funds_for_owner"]);
+        });
+    }
+
+    #[test]
+    fn orders_above_the_network_maximum_are_rejected() {
+        with_externalities(&mut new_test_ext(), || {
+            let uid = H256::repeat_byte(1);
+            MockEncumbrance::deny("within_maximum");
+            assert_err!(create_order(account(2), H256::repeat_byte(10), uid), "Order value is above the network maximum");
+            assert!(OrdersModule::orders(H256::repeat_byte(10)).is_none());
+            assert!(BonsaiModule::is_failed(uid).is_some());
+        });
+    }
+    #[test]
+    fn counterproposals_alternate_and_adjust_the_prefunding_when_accepted() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            
+            // The seller opens, and nobody can counter their own proposal
+            assert_err!(
+                OrdersModule::propose_counter(Origin::signed(account(1)), h, 200, 25_000, H256::repeat_byte(2)),
+                "It is not your turn to make a counterproposal"
+            );
+            assert_ok!(OrdersModule::propose_counter(Origin::signed(account(2)), h, 200, 25_000, H256::repeat_byte(3)));
+            assert_err!(
+                OrdersModule::propose_counter(Origin::signed(account(2)), h, 180, 25_000, H256::repeat_byte(4)),
+                "It is not your turn to make a counterproposal"
+            );
+            assert_ok!(OrdersModule::propose_counter(Origin::signed(account(1)), h, 150, 22_000, H256::repeat_byte(5)));
+            
+            // A failed adjustment leaves the order on its terms and the proposal open
+            MockEncumbrance::reset();
+            MockEncumbrance::fail_next("adjust_prefunding", "Not enough funds to prefund");
+            assert_err!(OrdersModule::accept_counterproposal(Origin::signed(account(2)), h, H256::repeat_byte(6)), "Error adjusting the prefunding");
+            assert_eq!(OrdersModule::orders(h).map(|o| (o.amount, o.deadline)), Some((100, 20_000)));
+            assert!(OrdersModule::open_counterproposal(h));
+            
+            assert_ok!(OrdersModule::accept_counterproposal(Origin::signed(account(2)), h, H256::repeat_byte(7)));
+            assert_eq!(OrdersModule::orders(h).map(|o| (o.amount, o.deadline, o.order_status)), Some((150, 22_000, 0)));
+            assert_eq!(MockEncumbrance::calls(), vec!["adjust_prefunding", "adjust_prefunding"]);
+            assert!(!OrdersModule::open_counterproposal(h));
+            assert_eq!(OrdersModule::counterproposals(h).len(), 2);
+            assert_err!(
+                OrdersModule::reject_counterproposal(Origin::signed(account(1)), h, H256::repeat_byte(8)),
+                "There is no open counterproposal on this order"
+            );
+            
+            // The rounds are bounded
+            for round in 2..MAX_COUNTERPROPOSAL_ROUNDS {
+                let proposer = if round % 2 == 0 { account(2) } else { account(1) };
+                assert_ok!(OrdersModule::propose_counter(Origin::signed(proposer), h, 150 + round as i128, 22_000, H256::repeat_byte(10 + round as u8)));
+            }
+            assert_err!(
+                OrdersModule::propose_counter(Origin::signed(account(2)), h, 300, 22_000, H256::repeat_byte(50)),
+                "The maximum number of counterproposal rounds has been reached"
+            );
+        });
+    }
+
+    #[test]
+    fn compliance_holds_block_cancellation_until_lifted_or_expired() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            let reason = H256::repeat_byte(20);
+            assert_ok!(create_order(account(2), h, H256::repeat_byte(1)));
+            system::Module::<Test>::set_block_number(10);
+            
+            assert_err!(OrdersModule::place_compliance_hold(Origin::signed(account(1)), h, reason, 100), "bad origin: expected to be a root origin");
+            assert_err!(OrdersModule::place_compliance_hold(Origin::ROOT, h, H256::zero(), 100), "A compliance hold requires a reason");
+            assert_err!(OrdersModule::place_compliance_hold(Origin::ROOT, h, reason, 10), "The expiry of the compliance hold is out of bounds");
+            assert_ok!(OrdersModule::place_compliance_hold(Origin::ROOT, h, reason, 100));
+            
+            // Neither the seller can reject (refunding the buyer) nor the buyer can delete the order
+            let uid = H256::repeat_byte(2);
+            assert_err!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 2, uid), "The order is under a compliance hold");
+            assert!(BonsaiModule::is_failed(uid).is_some());
+            let keys = TXKeysM { record_id: h, bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(3) };
+            assert_err!(OrdersModule::delete_order(Origin::signed(account(1)), keys), "The order is under a compliance hold");
+            
+            // Lifted
+            assert_ok!(OrdersModule::lift_compliance_hold(Origin::ROOT, h));
+            assert!(!OrdersModule::is_held(h));
+            assert!(OrdersModule::compliance_holds_expiring(100).is_empty());
+            assert_err!(OrdersModule::lift_compliance_hold(Origin::ROOT, h), "There is no compliance hold on this reference");
+            
+            // Expired
+            assert_ok!(OrdersModule::place_compliance_hold(Origin::ROOT, h, reason, 50));
+            assert!(OrdersModule::is_held(h));
+            system::Module::<Test>::set_block_number(50);
+            assert!(!OrdersModule::is_held(h));
+            OrdersModule::expire_compliance_holds(50);
+            assert!(OrdersModule::compliance_hold(h).is_none());
+            let keys = TXKeysM { record_id: h, bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(4) };
+            assert_ok!(OrdersModule::delete_order(Origin::signed(account(1)), keys));
+        });
+    }
+
+    #[test]
+    fn cost_plus_orders_invoice_the_time_and_expenses_with_markup() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(10);
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(OrdersModule::create_order(
+                Origin::signed(account(1)), account(1), account(2), 0, 1000, false, 0, 20_000, 40_000, Vec::new(),
+                Some(H256::repeat_byte(70)), DEFAULT_SCHEMA_VERSION,
+                TXKeysL { record_id: h, parent_id: h, bonsai_token: H256::zero(), tx_uid: H256::repeat_byte(1) },
+            ));
+            // Only service orders of a project are billed cost-plus
+            assert_ok!(create_order(account(2), H256::repeat_byte(11), H256::repeat_byte(2)));
+            assert_err!(
+                OrdersModule::set_cost_plus_terms(Origin::signed(account(1)), H256::repeat_byte(11), 100, 10, H256::repeat_byte(3)),
+                "Cost-plus billing is only for service orders of a project, with a markup up to 100 percent"
+            );
+            assert_ok!(OrdersModule::set_cost_plus_terms(Origin::signed(account(1)), h, 100, 10, H256::repeat_byte(4)));
+            
+            let expense = |who: u8, n: u8| OrdersModule::record_cost_plus_expense(Origin::signed(account(who)), h, 50, H256::repeat_byte(40), H256::repeat_byte(n));
+            assert_err!(expense(2, 5), "Expenses can only be recorded against accepted cost-plus orders, up to the maximum number of expenses");
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(6)));
+            assert_eq!(OrdersModule::cost_plus_accepted(h), Some(1));
+            assert_err!(expense(1, 7), "Only the seller can record expenses");
+            assert_ok!(expense(2, 8));
+            assert_err!(
+                OrdersModule::set_cost_plus_terms(Origin::signed(account(1)), h, 200, 10, H256::repeat_byte(9)),
+                "Order already accepted - cannot change now!"
+            );
+            
+            // Three hours at 100, plus 50 expenses, plus 10 percent
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(10)));
+            assert_eq!(
+                OrdersModule::cost_plus_invoice(h),
+                Some(CostPlusInvoice { blocks: 720, time: 300, expenses: 50, markup: 35, amount: 385 })
+            );
+            assert_eq!(OrdersModule::orders(h).map(|o| o.order_status), Some(5));
+            assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(11)));
+            assert_eq!(OrdersModule::total_value_settled(), 385);
+        });
+    }
+}
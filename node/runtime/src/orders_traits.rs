@@ -34,7 +34,12 @@
 //! along with Totem.  If not, see <http://www.gnu.org/licenses/>.
 
 // use support::{ dispatch::Result };
+use rstd::prelude::Vec;
 
 pub trait Validating<AccountId, Hash> {
     fn is_order_party(o: AccountId, r: Hash) -> bool;
 }
+
+pub trait Reminding<AccountId, Hash> {
+    fn submit_reminder(h: Hash, deadline: u64, notifications: Vec<(AccountId, Vec<u8>, Hash)>);
+}
@@ -50,25 +50,26 @@
 // A further scenario is forseen where a dispute resolution method that relies upon an independent validator 
 // is required to set the lock-release state. 
 
-use parity_codec::{Encode};
+use parity_codec::{Decode, Encode, Input, Output};
 use support::{decl_event, decl_module, decl_storage, dispatch::Result, StorageMap, ensure};
-use runtime_primitives::traits::{Convert, Hash}; // Use with node template only
+use runtime_primitives::traits::{As, Bounded, Convert, Hash}; // Use with node template only
 // use node_primitives::{Convert, Hash}; // Use with full node
-use system::{self, ensure_signed};
+use system::{self, ensure_root, ensure_signed};
 use rstd::prelude::*;
 use support::traits::{
     Currency, 
     LockIdentifier, 
     LockableCurrency, 
     WithdrawReason,
+    WithdrawReasons,
 };
 
 // Totem Pallets
-use accounting::{ Posting };
+use accounting::{ Posting, ORIGIN_ADJUSTMENT };
 
 // Totem Traits
 // use crate::accounting_traits::{ Posting };
-use crate::prefunding_traits::{ Encumbrance };
+use totem_traits::prefunding::{ ComplianceHolding, Encumbrance, LockState, PostSettlement, OrderType, ORDER_TYPE_SERVICE, ORDER_TYPE_GOODS, ORDER_TYPE_EXPENSE };
 
 // Totem Trait Types
 type AccountOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::Account;
@@ -77,11 +78,131 @@ type AccountBalanceOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Tr
 // Other trait types
 type CurrencyBalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
+// Maximum number of co-signatories an identity can authorise for dual control
+const MAX_CO_SIGNATORIES: usize = 10;
+
+// Maximum number of income accounts the proceeds of an invoice can be split across
+const MAX_REVENUE_SPLITS: usize = 10;
+
+// Maximum number of (account, lock id) pairs checked for orphaned locks in one call
+const MAX_LOCK_CANDIDATES: usize = 100;
+
+// Lock id namespaces of this module. Every lock id set by this module starts with the namespace, followed by 6 bytes 
+// of the digest of the namespace and the reference, so that it cannot collide with the lock ids of other modules.
+pub type LockNamespace = [u8; 2];
+const PREFUNDING_LOCK_NAMESPACE: LockNamespace = *b"pf";
+const BOND_LOCK_NAMESPACE: LockNamespace = *b"pb";
+
+// Number of digests tried for a lock id that is not already in use on the account
+const MAX_LOCK_ID_ATTEMPTS: u32 = 16;
+
+// Maximum number of references migrated to namespaced lock ids in one call
+const MAX_LOCK_MIGRATIONS: usize = 100;
+
+// Blocks per day. A bucket of the deadlines index spans one day, and the days to pay statistics are counted in days.
+const BLOCKS_PER_DAY: u64 = 5760;
+
+// Maximum number of deadline buckets read by one expiry query
+const MAX_DEADLINE_BUCKETS: u64 = 100;
+
+// Maximum number of prefunds awaiting acceptance in a bucket of the deadlines index
+const MAX_DEADLINE_BUCKET_SIZE: usize = 1000;
+
+// Maximum number of references added to the deadlines index in one migration call
+const MAX_DEADLINE_MIGRATIONS: usize = 100;
+
+// Maximum number of expired prefunds pending co-approval swept in one block. The remainder is swept in the next block.
+const MAX_PENDING_EXPIRIES: usize = 100;
+
+// Income accounts start with statement type Profit and Loss (2) and account category Revenue (4)
+const REVENUE_ACCOUNT_PREFIX: u64 = 24;
+const REVENUE_ACCOUNT_DIVISOR: u64 = 10_000_000_000_000;
+
+// Ledger accounts of the buyer and the seller recognised at settlement, by order type
+const LABOUR: u64 = 250500120000013;
+const SALES_OF_SERVICES: u64 = 240400010000000;
+const INVENTORY: u64 = 110100140000000;
+const SALES_OF_GOODS: u64 = 240400020000000;
+const REIMBURSED_EXPENSES: u64 = 250500200000000;
+const RECHARGED_EXPENSES: u64 = 240400030000000;
+
+// Minimum number of blocks between prefunding and the deadline (48 hours). This is the minimum amount of time
+// before the money can be reclaimed. Trading partners can agree a shorter minimum between themselves.
+const DEFAULT_MINIMUM_DEADLINE: u64 = 11520;
+
+// How the network fees of the lifecycle extrinsics of a reference are shared between the buyer (owner) and the seller
+// (beneficiary). By default each party bears the fees of the extrinsics it signs.
+pub type FeeSharingRule = u8;
+pub const FEES_PAID_BY_SIGNER: FeeSharingRule = 0;
+pub const FEES_PAID_BY_BUYER: FeeSharingRule = 1;
+pub const FEES_PAID_BY_SELLER: FeeSharingRule = 2;
+pub const FEES_SHARED_EQUALLY: FeeSharingRule = 3;
+
 // Module Types
-pub type UnLocked = bool; // 0=Unlocked(false) 1=Locked(true)
 pub type Status = u16; // Generic Status for whatever the HashReference refers to
 pub type ComparisonAmounts = u128; // Used for comparisons
 
+// Reference status codes. See `status_codes` and `valid_transitions` for clients.
+pub const STATUS_DRAFT: Status = 0;
+pub const STATUS_SUBMITTED: Status = 1;
+pub const STATUS_CANCELLED: Status = 50; // cancelled by the buyer
+pub const STATUS_SELLER_REJECTED: Status = 51;
+pub const STATUS_EXPIRED: Status = 52; // not accepted by the deadline
+pub const STATUS_ARBITRATED: Status = 53;
+pub const STATUS_SELLER_DEFAULTED: Status = 54; // accepted but not delivered by the due date
+pub const STATUS_DISPUTED: Status = 100;
+pub const STATUS_REJECTED: Status = 200;
+pub const STATUS_ACCEPTED: Status = 300;
+pub const STATUS_INVOICED: Status = 400;
+pub const STATUS_SETTLED: Status = 500;
+pub const STATUS_BLOCKED: Status = 999;
+
+/// Receipt of a settled prefunded invoice that the beneficiary can present as proof of payment (for example to a bank 
+/// or a factoring provider). The certificate id is the hash of the encoded certificate: a third party recomputes it 
+/// from the certificate and checks it against `SettlementCertificates` with a storage proof of a finalised block.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct SettlementCertificate<AccountId, Hash, BlockNumber> {
+    pub reference: Hash,
+    pub payer: AccountId,
+    pub beneficiary: AccountId,
+    /// Amount paid to the beneficiary
+    pub paid: u128,
+    /// Amount released to the payer (adjustments and credits)
+    pub released: u128,
+    pub block: BlockNumber,
+    /// Indexes of the postings made against the reference
+    pub postings: Vec<u128>,
+}
+
+/// Owner and beneficiary of a prefunding, and the state of the locked funds.
+/// Encoded as the former (owner, owner locked, beneficiary, beneficiary locked) tuple, so that the prefundings stored 
+/// before the lock state was introduced decode unchanged and no storage needs to be rewritten.
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PrefundingParties<AccountId> {
+    pub owner: AccountId,
+    pub beneficiary: AccountId,
+    pub lock: LockState,
+}
+
+impl<AccountId: Encode> Encode for PrefundingParties<AccountId> {
+    fn encode_to<W: Output>(&self, dest: &mut W) {
+        let (owner_locked, beneficiary_locked) = self.lock.locks();
+        self.owner.encode_to(dest);
+        owner_locked.encode_to(dest);
+        self.beneficiary.encode_to(dest);
+        beneficiary_locked.encode_to(dest);
+    }
+}
+
+impl<AccountId: Decode> Decode for PrefundingParties<AccountId> {
+    fn decode<I: Input>(input: &mut I) -> Option<Self> {
+        let (owner, owner_locked, beneficiary, beneficiary_locked) = <(AccountId, bool, AccountId, bool)>::decode(input)?;
+        Some(PrefundingParties { owner, beneficiary, lock: LockState::from_locks(owner_locked, beneficiary_locked) })
+    }
+}
+
 pub trait Trait: balances::Trait + system::Trait + timestamp::Trait + accounting::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
     type Currency: Currency<Self::AccountId> + LockableCurrency<Self::AccountId, Moment=Self::BlockNumber>;
@@ -98,6 +219,8 @@ pub trait Trait: balances::Trait + system::Trait + timestamp::Trait + accounting
     + Convert<AccountBalanceOf<Self>, i128> 
     + Convert<CurrencyBalanceOf<Self>, u128>;
     type Accounting: Posting<Self::AccountId,Self::Hash,Self::BlockNumber,Self::CoinAmount>;
+    type Settlement: PostSettlement<Self::AccountId,Self::Hash>;
+    type Holds: ComplianceHolding<Self::Hash>;
 }
 
 decl_storage! {
@@ -109,19 +232,26 @@ decl_storage! {
         Prefunding get(prefunding): map T::Hash => Option<(CurrencyBalanceOf<T>, T::BlockNumber)>;
         
         // Says who can take the money after deadline. Includes intended owner (same as origin for market posting)
-        // 10, sender can take after deadline (initial state)
-        // 11, accepted by recipient. (funds locked, nobody can take) 
-        // 01, sender approves (recipient can take, or refund)
-        // 00, only the recipient authrises sender to retake funds regardless of deadline.
-        PrefundingHashOwner get(prefunding_hash_owner): map T::Hash => Option<(T::AccountId, UnLocked, T::AccountId, UnLocked)>;
+        // and the lock state, see `LockState`.
+        PrefundingHashOwner get(prefunding_hash_owner): map T::Hash => Option<PrefundingParties<T::AccountId>>;
+        
+        // Blocks after the deadline during which the recipient may still accept (late acceptance), 
+        // as long as the sender has not taken back the funds. Set at creation, there is no grace period by default.
+        AcceptanceGracePeriod get(acceptance_grace_period): map T::Hash => T::BlockNumber;
         
         // List for convenience
         OwnerPrefundingHashList get(owner_prefunding_hash_list): map T::AccountId => Vec<T::Hash>;
         
+        // List for convenience of the beneficiary (seller). Funds locked in favour of the beneficiary
+        BeneficiaryPrefundingHashList get(beneficiary_prefunding_hash_list): map T::AccountId => Vec<T::Hash>;
+        
         // Reference Hash generic status
         // draft(0),
         // submitted(1),
-        // Abandoned or cancelled (50),
+        // cancelled by the buyer (50),
+        // rejected or released by the seller (51),
+        // expired, not accepted by the deadline (52),
+        // refunded by arbitration (53),
         // disputed(100), can be resubmitted, if the current status is < 100 return this state
         // rejected(200), can be resubmitted, if the current status is < 100 return this state
         // accepted(300), can no longer be submitted,
@@ -130,31 +260,252 @@ decl_storage! {
         // blocked(999),
         // U16MAX, is quasi-error state
         ReferenceStatus get(reference_status): map T::Hash => Status;
+        
+        // Assignment of receivables (factoring). The beneficiary can assign the right to receive the settlement
+        // proceeds to a third party. The sale is still recorded for the beneficiary, but funds are released to the assignee.
+        ReceivablesAssignee get(receivables_assignee): map T::Hash => Option<T::AccountId>;
+        
+        // Performance bond locked by the seller when accepting an order (seller, buyer, amount).
+        // The bond is released at settlement, or forfeited in part or in full to the buyer on seller default.
+        PerformanceBond get(performance_bond): map T::Hash => Option<(T::AccountId, T::AccountId, CurrencyBalanceOf<T>)>;
+        
+        // Adjustments (discounts or credits) waived by the beneficiary on an invoice before settlement (amount, reason)
+        // The total adjusted amount is released to the buyer at settlement and only the remainder is paid to the beneficiary
+        InvoiceAdjustments get(invoice_adjustments): map T::Hash => Vec<(ComparisonAmounts, T::Hash)>;
+        
+        // Amount invoiced on a reference. Adjustments and credits together cannot exceed it.
+        InvoiceTotals get(invoice_total): map T::Hash => Option<ComparisonAmounts>;
+        
+        // Credit notes issued by the beneficiary against an invoice before settlement (amount, reason). 
+        // The invoice is reversed for the credited amount, which is released to the buyer at settlement.
+        InvoiceCredits get(invoice_credits): map T::Hash => Vec<(ComparisonAmounts, T::Hash)>;
+        
+        // Dual control configured by an identity (threshold, authorised co-signatories, approval window in blocks).
+        // Prefunds above the threshold are only locked once co-approved by one of the co-signatories within the window.
+        DualControl get(dual_control): map T::AccountId => Option<(ComparisonAmounts, Vec<T::AccountId>, T::BlockNumber)>;
+        
+        // Income accounts of the beneficiary across which the invoiced amount is recognised at settlement (account, amount).
+        // Without a split the invoiced amount is recognised in the income account of the order type.
+        RevenueSplit get(revenue_split): map T::Hash => Vec<(u64, ComparisonAmounts)>;
+        
+        // Order type of the invoice, set when invoicing. Defaults to services.
+        ReferenceOrderType get(reference_order_type): map T::Hash => OrderType;
+        
+        // Express orders (micro-orders) invoiced at creation without the acceptance of the beneficiary.
+        // They are invoiced and settled with the same postings as other orders, and cannot be adjusted, credited or assigned.
+        ExpressReference get(express_reference): map T::Hash => bool;
+        
+        // Block at which the reference was invoiced and the block at which the invoice was settled
+        InvoiceDates get(invoice_dates): map T::Hash => Option<(T::BlockNumber, Option<T::BlockNumber>)>;
+        
+        // Payment behaviour of a payer towards a beneficiary (payer, beneficiary)
+        // (settled invoices, total blocks taken to pay, longest blocks taken to pay)
+        PaymentHistory get(payment_history): map (T::AccountId, T::AccountId) => (u32, u64, u64);
+        
+        // List for convenience of the beneficiaries whose invoices a payer has settled
+        PayerCounterparties get(payer_counterparties): map T::AccountId => Vec<T::AccountId>;
+        
+        // Prefunds awaiting co-approval (owner, beneficiary, amount, deadline, expiry block of the co-approval)
+        PendingPrefunding get(pending_prefunding): map T::Hash => Option<(T::AccountId, T::AccountId, ComparisonAmounts, T::BlockNumber, T::BlockNumber)>;
+        
+        // List for convenience of the prefunds awaiting co-approval by owner
+        PendingPrefundingList get(pending_prefunding_list): map T::AccountId => Vec<T::Hash>;
+        
+        // Prefunds awaiting co-approval by the block after their co-approval window, swept at the end of that block
+        PendingPrefundingExpiring get(pending_prefunding_expiring): map T::BlockNumber => Vec<T::Hash>;
+        
+        // Smallest amount that can be prefunded. Smaller (dust) amounts create locks and postings without economic meaning.
+        // Zero amounts are always rejected.
+        MinimumPrefund get(minimum_prefund): ComparisonAmounts;
+        // Largest amount that can be prefunded or ordered on the network. Limits the exposure to defects while the network 
+        // is in beta. Zero removes the limit.
+        MaximumPrefund get(maximum_prefund): ComparisonAmounts;
+        
+        // Proforma invoice proposed by the beneficiary before acceptance (amount, hash of the terms, block proposed)
+        Proforma get(proforma): map T::Hash => Option<(ComparisonAmounts, T::Hash, T::BlockNumber)>;
+        
+        // Invoice amount agreed by the owner accepting a proforma. The reference can only be invoiced for this amount.
+        AgreedInvoiceAmount get(agreed_invoice_amount): map T::Hash => Option<ComparisonAmounts>;
+        
+        // Settlement certificates by certificate id (hash of the encoded certificate)
+        SettlementCertificates get(settlement_certificate): map T::Hash => Option<SettlementCertificate<T::AccountId, T::Hash, T::BlockNumber>>;
+        
+        // Certificate id of a settled reference
+        ReferenceCertificate get(reference_certificate): map T::Hash => Option<T::Hash>;
+        
+        // Namespaced lock id of a prefunding or performance bond (namespace, reference). 
+        // References locked before namespacing use the legacy lock id until they are migrated.
+        LockIds get(lock_ids): map (LockNamespace, T::Hash) => Option<LockIdentifier>;
+        
+        // Lock ids set by this module that are active on an account (lock id, reference)
+        ActiveLocks get(active_locks): map T::AccountId => Vec<(LockIdentifier, T::Hash)>;
+        
+        // Agreed sharing of the network fees of the lifecycle extrinsics of a reference
+        FeeSharing get(fee_sharing): map T::Hash => FeeSharingRule;
+        
+        // Minimum deadline (blocks) proposed by an identity for a trust agreement with a counterparty (proposer, counterparty)
+        TrustProposals get(trust_proposal): map (T::AccountId, T::AccountId) => Option<u64>;
+        
+        // Minimum deadline (blocks) agreed by a pair of trading partners for prefunds between them, in place of the 
+        // 48 hour default. The pair is stored in ascending order.
+        TrustAgreements get(trust_agreement): map (T::AccountId, T::AccountId) => Option<u64>;
+        
+        // Prefunds awaiting acceptance by the bucket of their deadline (deadline / BLOCKS_PER_DAY), so that the prefunds 
+        // expiring in a range of blocks are found without reading every reference
+        DeadlinesIndex get(deadlines_index): map u64 => Vec<T::Hash>;
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event<T>() = default;
+        fn on_finalize(n: T::BlockNumber) {
+            Self::expire_pending_prefundings(n);
+        }
         /// This function reserves funds from the buyer for a specific vendor account (Closed Order). It is used when an order is created.
         /// Quatity is not relevant 
         /// The prefunded amount remains as an asset of the buyer until the order is accepted
         /// Updates only the accounts of the buyer 
-        fn prefund_someone(origin, beneficiary: T::AccountId, amount: u128, deadline: T::BlockNumber, tx_uid: T::Hash) -> Result {
+        /// The beneficiary may still accept within the grace period after the deadline, unless the funds were taken back
+        fn prefund_someone(origin, beneficiary: T::AccountId, amount: CurrencyBalanceOf<T>, deadline: T::BlockNumber, grace_period: T::BlockNumber, tx_uid: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
             // check that the beneficiary is not the sender
             ensure!(who != beneficiary, "Beneficiary must be another account");
+            let amount_converted: u128 = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount);
+            Self::check_prefund_amount(amount_converted, tx_uid)?;
             let prefunding_hash: T::Hash = Self::get_pseudo_random_hash(who.clone(), beneficiary.clone());
-            Self::prefunding_for(who, beneficiary, amount.into(), deadline, prefunding_hash, tx_uid)?;
+            Self::prefunding_for(who, beneficiary, amount_converted, deadline, grace_period, prefunding_hash, tx_uid)?;
             
             Ok(())
         }
+        /// Sets the dual control for the sender. Prefunds above the threshold require co-approval by one of the signatories 
+        /// within the window (in blocks). An empty list of signatories removes the dual control.
+        fn set_dual_control(origin, threshold: CurrencyBalanceOf<T>, signatories: Vec<T::AccountId>, window: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            if signatories.is_empty() {
+                <DualControl<T>>::remove(&who);
+                return Ok(());
+            }
+            if signatories.len() > MAX_CO_SIGNATORIES || signatories.contains(&who) || window == T::BlockNumber::default() {
+                Self::deposit_event(RawEvent::ErrorDualControl());
+                return Err("Invalid dual control parameters");
+            }
+            let threshold_converted: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(threshold);
+            <DualControl<T>>::insert(&who, (threshold_converted, signatories, window));
+            Self::deposit_event(RawEvent::DualControlSet(who, threshold_converted));
+            Ok(())
+        }
+        /// Is used by a co-signatory to approve a prefund pending co-approval. The funds are locked at this point.
+        fn co_approve_prefunding(origin, reference: T::Hash, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let pending = match Self::pending_prefunding(&reference) {
+                Some(p) => p,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoPendingPrefunding(uid));
+                    return Err("No prefunding is pending co-approval");
+                },
+            };
+            let (owner, beneficiary, amount, deadline, expiry) = pending;
+            let co_signatory: bool = match Self::dual_control(&owner) {
+                Some((_, signatories, _)) => signatories.contains(&who),
+                None => false,
+            };
+            if !co_signatory {
+                Self::deposit_event(RawEvent::ErrorNotCoSignatory(uid));
+                return Err("Not an authorised co-signatory");
+            }
+            if <system::Module<T>>::block_number() > expiry {
+                Self::deposit_event(RawEvent::ErrorCoApprovalExpired(uid));
+                return Err("The co-approval window has passed");
+            }
+            Self::lock_prefunding(owner.clone(), beneficiary, amount, deadline, reference, uid)?;
+            Self::remove_pending_prefunding(&owner, reference);
+            Self::deposit_event(RawEvent::PrefundingCoApproved(who, reference));
+            Ok(())
+        }
+        /// Is used by the owner or a co-signatory to reject a prefund pending co-approval
+        fn reject_pending_prefunding(origin, reference: T::Hash, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let owner: T::AccountId = match Self::pending_prefunding(&reference) {
+                Some(p) => p.0,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoPendingPrefunding(uid));
+                    return Err("No prefunding is pending co-approval");
+                },
+            };
+            let allowed: bool = who == owner || match Self::dual_control(&owner) {
+                Some((_, signatories, _)) => signatories.contains(&who),
+                None => false,
+            };
+            if !allowed {
+                Self::deposit_event(RawEvent::ErrorNotCoSignatory(uid));
+                return Err("Not an authorised co-signatory");
+            }
+            Self::remove_pending_prefunding(&owner, reference);
+            <AcceptanceGracePeriod<T>>::remove(&reference);
+            Self::set_ref_status(reference, STATUS_CANCELLED)?;
+            Self::deposit_event(RawEvent::PendingPrefundingRejected(who, reference));
+            Self::deposit_event(RawEvent::PrefundingCancelled(owner, reference));
+            Ok(())
+        }
         /// Creates a single line simple invoice without taxes, tariffs or commissions
         /// This invoice is associated with a prefunded order - therefore needs to provide the hash reference of the order
         /// Updates the accounting for the vendor and the customer
-        fn invoice_prefunded_order(origin, payer: T::AccountId, amount: i128, reference: T::Hash, uid: T::Hash) -> Result {
+        fn invoice_prefunded_order(origin, payer: T::AccountId, amount: CurrencyBalanceOf<T>, reference: T::Hash, uid: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
-            Self::send_simple_invoice(who.clone(), payer.clone(), amount, reference, uid)?;
+            // The invoiced amount is posted as a signed ledger balance
+            if <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount) > i128::max_value() as u128 {
+                Self::deposit_event(RawEvent::ErrorInvoiceAmount(uid));
+                return Err("Invoice amount is out of range");
+            }
+            let amount_converted: i128 = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, i128>>::convert(
+                <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, AccountBalanceOf<T>>>::convert(amount)
+            );
+            Self::send_simple_invoice(who.clone(), payer.clone(), amount_converted, reference, ORDER_TYPE_SERVICE, uid)?;
+            Ok(())
+        }
+        /// Used by the beneficiary to propose a proforma invoice (amount and hash of the terms) on a prefunded order 
+        /// it has not yet accepted. A later proposal replaces an earlier one.
+        fn propose_proforma(origin, reference: T::Hash, amount: CurrencyBalanceOf<T>, terms_hash: T::Hash, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let amount_converted: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount);
+            Self::set_proforma(who, reference, amount_converted, terms_hash, uid)?;
+            Ok(())
+        }
+        /// Used by the owner to accept the proforma invoice. This accepts the order for the beneficiary 
+        /// and fixes the amount for which the reference can be invoiced.
+        fn accept_proforma(origin, reference: T::Hash, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::agree_proforma(who, reference, uid)?;
+            Ok(())
+        }
+        /// Used by the owner to set how the network fees of the lifecycle extrinsics of the reference are shared with the 
+        /// beneficiary. Can only be set before acceptance, so that the beneficiary agrees to the sharing by accepting.
+        /// The fees of each extrinsic are recharged in the accounts of both parties when the block is finalised.
+        fn set_fee_sharing(origin, reference: T::Hash, rule: FeeSharingRule, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            if rule > FEES_SHARED_EQUALLY {
+                Self::deposit_event(RawEvent::ErrorFeeSharing(uid));
+                return Err("Unknown fee sharing rule");
+            }
+            match Self::prefunding_hash_owner(&reference) {
+                Some(owners) => {
+                    if owners.owner != who || owners.lock != LockState::AwaitingAcceptance {
+                        Self::deposit_event(RawEvent::ErrorFeeSharing(uid));
+                        return Err("Only the owner can set the fee sharing before acceptance");
+                    }
+                },
+                None => {
+                    Self::deposit_event(RawEvent::ErrorFeeSharing(uid));
+                    return Err("Hash does not exist!");
+                },
+            }
+            if rule == FEES_PAID_BY_SIGNER {
+                <FeeSharing<T>>::remove(&reference);
+            } else {
+                <FeeSharing<T>>::insert(&reference, rule);
+            }
+            Self::deposit_event(RawEvent::FeeSharingSet(reference, rule));
             Ok(())
         }
         /// Buyer pays a prefunded order. Needs to supply the correct hash reference
@@ -171,10 +522,192 @@ decl_module! {
             Self::unlock_funds_for_owner(who.clone(), reference, uid)?;
             Ok(())
         }
+        /// Super User refunds the buyer of a prefunding that has not been invoiced following arbitration of a dispute.
+        /// A performance bond of the seller is released.
+        fn arbitrate_prefunding(origin, reference: T::Hash, uid: T::Hash) -> Result {
+            ensure_root(origin)?;
+            Self::refund_by_arbitration(reference, uid)?;
+            Ok(())
+        }
+        /// Is used by the beneficiary to assign the settlement proceeds to a third party (for example a financier).
+        /// The buyer is notified by event. Can only be done once the order is accepted and before it is settled, and only once.
+        /// The assignment is posted to the accounts of the beneficiary and the assignee.
+        fn assign_receivables(origin, reference: T::Hash, assignee: T::AccountId, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::set_receivables_assignee(who.clone(), reference, assignee, uid)?;
+            Ok(())
+        }
+        /// Is used by the beneficiary to waive part of an invoice (discount or credit) before settlement.
+        /// Reduces the receivable and the payable so that the invoice does not need to be cancelled and re-issued.
+        fn apply_invoice_adjustment(origin, reference: T::Hash, adjustment_amount: CurrencyBalanceOf<T>, reason_hash: T::Hash, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let amount_converted: u128 = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(adjustment_amount);
+            Self::adjust_invoice(who.clone(), reference, amount_converted, reason_hash, uid)?;
+            Ok(())
+        }
+        /// Is used by the beneficiary to split the invoiced amount across its own income accounts (for example services
+        /// and re-billed expenses) according to the invoice line types. The amounts must add up to the invoiced amount.
+        /// An empty split recognises the invoiced amount as Sales of services.
+        fn set_revenue_split(origin, reference: T::Hash, splits: Vec<(u64, CurrencyBalanceOf<T>)>, uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            let splits_converted: Vec<(u64, ComparisonAmounts)> = splits
+                .into_iter()
+                .map(|s| (s.0, <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(s.1)))
+                .collect();
+            Self::split_revenue(who.clone(), reference, splits_converted, uid)?;
+            Ok(())
+        }
+        /// Super User sets the smallest amount that can be prefunded
+        fn set_minimum_prefund(origin, amount: CurrencyBalanceOf<T>) -> Result {
+            ensure_root(origin)?;
+            let amount_converted: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount);
+            <MinimumPrefund<T>>::put(amount_converted);
+            Self::deposit_event(RawEvent::MinimumPrefundSet(amount_converted));
+            Ok(())
+        }
+        /// Super User sets the largest amount that can be prefunded or ordered on the network. Zero removes the limit.
+        fn set_maximum_prefund(origin, amount: CurrencyBalanceOf<T>) -> Result {
+            ensure_root(origin)?;
+            let amount_converted: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount);
+            <MaximumPrefund<T>>::put(amount_converted);
+            Self::deposit_event(RawEvent::MaximumPrefundSet(amount_converted));
+            Ok(())
+        }
+        /// Super User releases orphaned prefunding locks: locks held by an account for which no prefunding or performance bond 
+        /// exists in storage, for example after a failed flow. Each supplied (account, lock id) pair is verified against storage
+        /// and only orphaned locks are removed.
+        fn release_orphaned_locks(origin, candidates: Vec<(T::AccountId, LockIdentifier)>) -> Result {
+            ensure_root(origin)?;
+            if candidates.len() > MAX_LOCK_CANDIDATES {
+                Self::deposit_event(RawEvent::ErrorTooManyLockCandidates());
+                return Err("Too many lock candidates");
+            }
+            let scanned: u32 = candidates.len() as u32;
+            let mut released: u32 = 0;
+            for (o, id) in candidates.into_iter() {
+                if Self::is_orphaned_lock(&o, id) {
+                    T::Currency::remove_lock(id, &o);
+                    released += 1;
+                    Self::deposit_event(RawEvent::OrphanedLockReleased(o, id));
+                }
+            }
+            Self::deposit_event(RawEvent::OrphanedLocksScanned(scanned, released));
+            Ok(())
+        }
+        /// Proposes to a counterparty a trust agreement that reduces the minimum deadline of the prefunds between them.
+        /// The agreement is made when both parties have proposed the same minimum deadline.
+        fn propose_trust_agreement(origin, counterparty: T::AccountId, minimum_deadline: u64) -> Result {
+            let who = ensure_signed(origin)?;
+            if counterparty == who || minimum_deadline == 0 || minimum_deadline > DEFAULT_MINIMUM_DEADLINE {
+                Self::deposit_event(RawEvent::ErrorTrustAgreement(who));
+                return Err("Invalid trust agreement");
+            }
+            if Self::trust_proposal((counterparty.clone(), who.clone())) == Some(minimum_deadline) {
+                <TrustProposals<T>>::remove((counterparty.clone(), who.clone()));
+                <TrustProposals<T>>::remove((who.clone(), counterparty.clone()));
+                <TrustAgreements<T>>::insert(Self::trust_pair(who.clone(), counterparty.clone()), minimum_deadline);
+                Self::deposit_event(RawEvent::TrustAgreementMade(who, counterparty, minimum_deadline));
+            } else {
+                <TrustProposals<T>>::insert((who.clone(), counterparty.clone()), minimum_deadline);
+                Self::deposit_event(RawEvent::TrustAgreementProposed(who, counterparty, minimum_deadline));
+            }
+            Ok(())
+        }
+        /// Either party ends the trust agreement (or withdraws its proposal). Prefunds between them return to the 
+        /// default minimum deadline. Existing prefunds keep their deadlines.
+        fn revoke_trust_agreement(origin, counterparty: T::AccountId) -> Result {
+            let who = ensure_signed(origin)?;
+            <TrustProposals<T>>::remove((who.clone(), counterparty.clone()));
+            <TrustAgreements<T>>::remove(Self::trust_pair(who.clone(), counterparty.clone()));
+            Self::deposit_event(RawEvent::TrustAgreementRevoked(who, counterparty));
+            Ok(())
+        }
+        /// Super User moves the locks of existing prefundings and performance bonds from the legacy lock id 
+        /// (the first 8 bytes of the reference) to a namespaced lock id. References already migrated are skipped, 
+        /// as are locks for which no unused lock id can be derived. These keep their legacy id and can be migrated later.
+        fn migrate_lock_ids(origin, references: Vec<T::Hash>) -> Result {
+            ensure_root(origin)?;
+            if references.len() > MAX_LOCK_MIGRATIONS {
+                Self::deposit_event(RawEvent::ErrorTooManyLockMigrations());
+                return Err("Too many references to migrate");
+            }
+            let mut migrated: u32 = 0;
+            for h in references.into_iter() {
+                if let (Some(owners), Some(prefunding)) = (Self::prefunding_hash_owner(&h), Self::prefunding(&h)) {
+                    if !<LockIds<T>>::exists((PREFUNDING_LOCK_NAMESPACE, h)) {
+                        match Self::assign_lock_id(&owners.owner, PREFUNDING_LOCK_NAMESPACE, h) {
+                            Ok(id) => {
+                                T::Currency::remove_lock(Self::legacy_lock_id(h), &owners.owner);
+                                T::Currency::set_lock(id, &owners.owner, prefunding.0, prefunding.1, WithdrawReason::Reserve.into());
+                                migrated += 1;
+                            },
+                            // Nothing is written for this lock. It stays under the legacy id and the rest of the batch is migrated.
+                            Err(_e) => Self::deposit_event(RawEvent::ErrorLockId(h)),
+                        }
+                    }
+                }
+                if let Some(bond) = Self::performance_bond(&h) {
+                    if !<LockIds<T>>::exists((BOND_LOCK_NAMESPACE, h)) {
+                        match Self::assign_lock_id(&bond.0, BOND_LOCK_NAMESPACE, h) {
+                            Ok(id) => {
+                                T::Currency::remove_lock(Self::legacy_bond_id(h), &bond.0);
+                                T::Currency::set_lock(id, &bond.0, bond.2, T::BlockNumber::max_value(), WithdrawReason::Reserve.into());
+                                migrated += 1;
+                            },
+                            Err(_e) => Self::deposit_event(RawEvent::ErrorLockId(h)),
+                        }
+                    }
+                }
+            }
+            Self::deposit_event(RawEvent::LockIdsMigrated(migrated));
+            Ok(())
+        }
+        /// Super User adds the existing prefunds awaiting acceptance to the deadlines index. 
+        /// References that are not awaiting acceptance, are already indexed or whose bucket is full are skipped.
+        fn migrate_deadlines_index(origin, references: Vec<T::Hash>) -> Result {
+            ensure_root(origin)?;
+            if references.len() > MAX_DEADLINE_MIGRATIONS {
+                Self::deposit_event(RawEvent::ErrorTooManyDeadlineMigrations());
+                return Err("Too many references to add to the deadlines index");
+            }
+            let mut migrated: u32 = 0;
+            for h in references.into_iter() {
+                if let Some((_, deadline)) = Self::prefunding(&h) {
+                    let bucket: u64 = Self::deadline_bucket(deadline);
+                    let indexed: Vec<T::Hash> = Self::deadlines_index(bucket);
+                    if Self::reference_status(&h) == STATUS_SUBMITTED && !indexed.contains(&h) && indexed.len() < MAX_DEADLINE_BUCKET_SIZE {
+                        <DeadlinesIndex<T>>::mutate(bucket, |references| references.push(h));
+                        migrated += 1;
+                    }
+                }
+            }
+            Self::deposit_event(RawEvent::DeadlinesIndexMigrated(migrated));
+            Ok(())
+        }
     }
 }
 
 impl<T: Trait> Module<T> {
+    /// Key of the trust agreement of a pair of identities, in ascending order
+    fn trust_pair(a: T::AccountId, b: T::AccountId) -> (T::AccountId, T::AccountId) {
+        if a <= b { (a, b) } else { (b, a) }
+    }
+    /// Recharges the agreed share of the fee of the extrinsic signed by a party of the reference to the other party
+    fn share_fees(signer: &T::AccountId, owner: &T::AccountId, beneficiary: &T::AccountId, h: T::Hash) {
+        let rule: FeeSharingRule = Self::fee_sharing(&h);
+        let (counterparty, share): (&T::AccountId, u8) = match (signer == owner, rule) {
+            (true, FEES_PAID_BY_SELLER) => (beneficiary, 100),
+            (false, FEES_PAID_BY_BUYER) => (owner, 100),
+            (_, FEES_SHARED_EQUALLY) => (if signer == owner { beneficiary } else { owner }, 50),
+            _ => return,
+        };
+        // Sharing never fails the extrinsic. A failure is reported by the error event of the accounting module
+        let _ = <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::share_last_fee(signer.clone(), counterparty.clone(), share, h);
+    }
+    /// Minimum number of blocks before the deadline of a prefund between two identities
+    pub fn minimum_deadline_of(a: &T::AccountId, b: &T::AccountId) -> u64 {
+        Self::trust_agreement(Self::trust_pair(a.clone(), b.clone())).unwrap_or(DEFAULT_MINIMUM_DEADLINE)
+    }
     /// Reserve the prefunding deposit
     fn set_prefunding(s: T::AccountId, c: AccountBalanceOf<T>, d: T::BlockNumber, h: T::Hash, u: T::Hash) -> Result {
         
@@ -196,7 +729,14 @@ impl<T: Trait> Module<T> {
             let converted_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(c.clone());
             
             // Lock the amount from the sender and set deadline
-            T::Currency::set_lock(Self::get_prefunding_id(h), &s, converted_amount, d, WithdrawReason::Reserve.into());
+            let prefunding_id: LockIdentifier = match Self::assign_lock_id(&s, PREFUNDING_LOCK_NAMESPACE, h) {
+                Ok(id) => id,
+                Err(e) => {
+                    Self::deposit_event(RawEvent::ErrorLockId(u));
+                    return Err(e);
+                },
+            };
+            T::Currency::set_lock(prefunding_id, &s, converted_amount, d, WithdrawReason::Reserve.into());
             
         } else {
             Self::deposit_event(RawEvent::ErrorInsufficientPreFunds(s, prefund_amount, minimum_amount, current_balance));
@@ -205,11 +745,90 @@ impl<T: Trait> Module<T> {
         
         Ok(())
     }
-    /// Generate Prefund Id from hash  
+    /// Lock id of the prefunding of a reference. Prefundings locked before namespacing use the legacy lock id.
     fn get_prefunding_id(hash: T::Hash) -> LockIdentifier {
-        // Convert Hash to ID using first 8 bytes of hash
+        Self::lock_ids((PREFUNDING_LOCK_NAMESPACE, hash)).unwrap_or_else(|| Self::legacy_lock_id(hash))
+    }
+    /// Lock id of the performance bond of a reference. This differs from the prefunding lock id for the same reference.
+    fn get_bond_id(hash: T::Hash) -> LockIdentifier {
+        Self::lock_ids((BOND_LOCK_NAMESPACE, hash)).unwrap_or_else(|| Self::legacy_bond_id(hash))
+    }
+    /// Legacy lock id: the first 8 bytes of the hash
+    fn legacy_lock_id(hash: T::Hash) -> LockIdentifier {
         return <T::PrefundingConversions as Convert<Vec<u8>, LockIdentifier>>::convert(hash.encode());
     }
+    fn legacy_bond_id(hash: T::Hash) -> LockIdentifier {
+        let bond_hash: T::Hash = T::Hashing::hash((hash, b"bond".to_vec()).encode().as_slice());
+        return Self::legacy_lock_id(bond_hash);
+    }
+    /// Namespaced lock id: the namespace followed by the first 6 bytes of the digest of the namespace, reference and attempt
+    fn derive_lock_id(namespace: LockNamespace, hash: T::Hash, attempt: u32) -> LockIdentifier {
+        let digest: T::Hash = T::Hashing::hash((namespace, hash, attempt).encode().as_slice());
+        let mut id: LockIdentifier = [0; 8];
+        id[..2].copy_from_slice(&namespace);
+        id[2..].copy_from_slice(&digest.as_ref()[..6]);
+        id
+    }
+    /// Assigns a namespaced lock id to a reference that is not in use by any lock on the account and registers it as active.
+    /// The id already assigned to the reference is returned if there is one.
+    fn assign_lock_id(o: &T::AccountId, namespace: LockNamespace, hash: T::Hash) -> rstd::result::Result<LockIdentifier, &'static str> {
+        if let Some(id) = Self::lock_ids((namespace, hash)) {
+            return Ok(id);
+        }
+        let mut in_use: Vec<LockIdentifier> = <balances::Module<T>>::locks(o).into_iter().map(|l| l.id).collect();
+        in_use.extend(Self::active_locks(o).into_iter().map(|l| l.0));
+        for attempt in 0..MAX_LOCK_ID_ATTEMPTS {
+            let id: LockIdentifier = Self::derive_lock_id(namespace, hash, attempt);
+            if !in_use.contains(&id) {
+                <LockIds<T>>::insert((namespace, hash), id);
+                <ActiveLocks<T>>::mutate(o, |locks| locks.push((id, hash)));
+                return Ok(id);
+            }
+        }
+        Err("Unable to derive an unused lock id")
+    }
+    /// Removes the lock id of a reference from the registry once the lock has been removed
+    fn release_lock_id(o: &T::AccountId, namespace: LockNamespace, hash: T::Hash) {
+        if let Some(id) = <LockIds<T>>::take((namespace, hash)) {
+            <ActiveLocks<T>>::mutate(o, |locks| locks.retain(|l| l.0 != id));
+        }
+    }
+    /// Posts the accounting for a performance bond for both parties. 
+    /// The amount is signed: positive when the bond is locked, negative when it is released or forfeited.
+    fn account_for_performance_bond(s: T::AccountId, b: T::AccountId, n: i128, h: T::Hash, uid: T::Hash) -> Result {
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(Self::performance_bond_keys(s, b, n, h)) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting4(uid));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        Ok(())
+    }
+    /// The posting keys of a performance bond for both parties, with the signed amount as for account_for_performance_bond
+    fn performance_bond_keys(s: T::AccountId, b: T::AccountId, n: i128, h: T::Hash) -> Vec<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)> {
+        let amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n);
+        let inverted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n * -1);
+        
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Seller
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100050000000u64); // Debit  increase 110100050000000 Totem Runtime Deposit (Escrow)
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Credit decrease 110100040000000 XTX Balance
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600040000000u64); // Debit  increase 360600040000000 Escrowed Funds Control
+        
+        // Buyer
+        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600050000000u64); // Debit  increase 360600050000000 Performance Bonds Held Control
+        
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        forward_keys.push((s.clone(), b.clone(), account_1, amount, n < 0, h, current_block, current_block_dupe));
+        forward_keys.push((s.clone(), b.clone(), account_2, inverted, n > 0, h, current_block, current_block_dupe));
+        forward_keys.push((s.clone(), b.clone(), account_3, amount, n < 0, h, current_block, current_block_dupe));
+        forward_keys.push((b.clone(), s.clone(), account_4, amount, n < 0, h, current_block, current_block_dupe));
+        
+        forward_keys
+    }
     /// generate reference hash
     fn get_pseudo_random_hash(sender: T::AccountId, recipient: T::AccountId) -> T::Hash {
         let tuple = (sender, recipient);
@@ -225,7 +844,7 @@ impl<T: Trait> Module<T> {
     /// check hash exists and is valid
     fn reference_valid(h: T::Hash) -> bool {
         match <ReferenceStatus<T>>::get(&h) {
-            0 | 1 | 100 | 200 | 300 | 400 => return true,
+            STATUS_DRAFT | STATUS_SUBMITTED | STATUS_DISPUTED | STATUS_REJECTED | STATUS_ACCEPTED | STATUS_INVOICED => return true,
             _ => return false,
         }
     }
@@ -240,10 +859,24 @@ impl<T: Trait> Module<T> {
         };
         return false;
     }
+    /// Sets the grace period for late acceptance. No grace period is stored by default.
+    fn set_grace_period(h: T::Hash, g: T::BlockNumber) {
+        if g != T::BlockNumber::default() {
+            <AcceptanceGracePeriod<T>>::insert(&h, g);
+        }
+    }
+    /// Settlement and cancellation wait for a compliance hold on the reference to be lifted
+    fn check_not_held(h: T::Hash, uid: T::Hash) -> Result {
+        if T::Holds::is_held(h) {
+            Self::deposit_event(RawEvent::ErrorComplianceHold(uid));
+            return Err("The reference is under a compliance hold");
+        }
+        Ok(())
+    }
     /// Gets the state of the locked funds. The hash needs to be prequalified before passing in as no checks performed here.
-    fn get_release_state(h: T::Hash) -> (UnLocked, UnLocked) {
+    fn get_release_state(h: T::Hash) -> LockState {
         let owners = Self::prefunding_hash_owner(&h).unwrap();
-        return (owners.1, owners.3);
+        return owners.lock;
     }
     /// cancel lock for owner
     fn cancel_prefunding_lock(o: T::AccountId, h: T::Hash, s: Status) -> Result {
@@ -252,61 +885,130 @@ impl<T: Trait> Module<T> {
         let prefunding_id = Self::get_prefunding_id(h);
         // unlock the funds
         T::Currency::remove_lock(prefunding_id, &o);
+        Self::release_lock_id(&o, PREFUNDING_LOCK_NAMESPACE, h);
         // perform cleanup removing all reference hashes. No accounting posting have been made, so no cleanup needed there
+        Self::unindex_deadline(h);
         <Prefunding<T>>::take(&h);
-        <PrefundingHashOwner<T>>::take(&h);
+        <AcceptanceGracePeriod<T>>::remove(&h);
+        if let Some(owners) = <PrefundingHashOwner<T>>::take(&h) {
+            <BeneficiaryPrefundingHashList<T>>::mutate(&owners.beneficiary, |beneficiary_prefunding_hash_list| beneficiary_prefunding_hash_list.retain(|e| e != &h));
+        }
         <ReferenceStatus<T>>::insert(&h, s); // This sets the status but does not remove the hash
         <OwnerPrefundingHashList<T>>::mutate(&o, |owner_prefunding_hash_list| owner_prefunding_hash_list.retain(|e| e != &h));
-        // Issue event
-        Self::deposit_event(RawEvent::PrefundingCancelled(o, h));
+        <ReceivablesAssignee<T>>::take(&h);
+        // Issue the event of the outcome
+        match s {
+            STATUS_SELLER_REJECTED => Self::deposit_event(RawEvent::PrefundingRejectedBySeller(o, h)),
+            STATUS_EXPIRED => Self::deposit_event(RawEvent::PrefundingExpired(o, h)),
+            STATUS_ARBITRATED => Self::deposit_event(RawEvent::PrefundingArbitrated(o, h)),
+            STATUS_SELLER_DEFAULTED => Self::deposit_event(RawEvent::PrefundingReleasedOnDefault(o, h)),
+            _ => Self::deposit_event(RawEvent::PrefundingCancelled(o, h)),
+        }
+        Ok(())
+    }
+    /// Refunds the buyer of a submitted prefunding and releases the performance bond of the seller, if any
+    fn refund_by_arbitration(h: T::Hash, uid: T::Hash) -> Result {
+        Self::check_not_held(h, uid)?;
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist3(uid));
+                return Err("Hash does not exist!");
+            },
+        };
+        if Self::reference_status(&h) != STATUS_SUBMITTED {
+            Self::deposit_event(RawEvent::ErrorArbitration(uid));
+            return Err("Only prefundings that have not been invoiced can be refunded by arbitration");
+        }
+        let amount: ComparisonAmounts = Self::prefunding(&h).map_or(0u128, |p| <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(p.0));
+        // The assigned amount is checked before the bond is released
+        let assignment: Option<(T::AccountId, ComparisonAmounts)> = match Self::receivables_assignee(&h) {
+            Some(a) => Some((a, Self::assigned_amount(h, uid)?)),
+            None => None,
+        };
+        if Self::performance_bond(&h).is_some() {
+            Self::release_performance_bond(h, uid)?;
+        }
+        // The refunded order will not be settled, the assignment of its receivables is reversed
+        if let Some((a, n)) = assignment {
+            Self::post_assignment(owners.beneficiary.clone(), a, h, n, true, uid)?;
+        }
+        Self::cancel_prefunding_lock(owners.owner.clone(), h, STATUS_ARBITRATED)?;
+        T::Settlement::on_cancelled(h, owners.owner, owners.beneficiary, amount);
         Ok(())
     }
     /// unlock & pay beneficiary with funds transfer and account updates (settlement of invoice)
     fn unlock_funds_for_beneficiary(o: T::AccountId, h: T::Hash, u: T::Hash) -> Result {
+        Self::check_not_held(h, u)?;
         match Self::reference_valid(h) {
             true => {
                 match Self::check_ref_beneficiary(o.clone(), h) { // TODO this should return the details otherwise there is second read later in the process
                     true => {
                         match Self::get_release_state(h) {
-                            (true, false)  => { // submitted, but not yet accepted
+                            LockState::AwaitingAcceptance => {
                                 Self::deposit_event(RawEvent::ErrorNotApproved(u));
                                 return Err("The demander has not approved the work yet!");
                             },
-                            (true, true) => {
+                            LockState::Accepted => {
                                 Self::deposit_event(RawEvent::ErrorFundsInPlay(u));
                                 return Err("Funds locked for intended purpose by both parties.")
                             },
-                            (false, true) => { 
+                            LockState::SenderApproved => { 
                                 // Owner has approved now get status of hash. Only allow if invoiced.
                                 // Note handling the account posting is done outside of this function
                                 match <ReferenceStatus<T>>::get(&h) {
-                                    400 => {
+                                    STATUS_INVOICED => {
                                         // get details of lock
                                         let details = Self::prefunding_hash_owner(&h).ok_or("Error fetching details")?;
                                         // get details of prefunding
                                         let prefunding = Self::prefunding(&h).ok_or("Error getting prefunding details")?;
-                                        // Cancel prefunding lock
-                                        let status:  Status = 500; // Settled
-                                        match Self::cancel_prefunding_lock(details.0.clone(), h, status) {
-                                            Ok(_) => {
-                                                // transfer to beneficiary.
-                                                // TODO when currency conversion is implemnted the payment should be at the current rate for the currency
-                                                match T::Currency::transfer(&details.0, &o, prefunding.0) {
-                                                    Ok(_) => (),
-                                                    Err(_) => return Err("Error during transfer"),
-                                                }
+                                        // If the receivables were assigned the funds are paid to the assignee. 
+                                        // This must be read before the lock is cancelled as cancelling cleans up the assignment.
+                                        let payee: T::AccountId = match Self::receivables_assignee(&h) {
+                                            Some(assignee) => assignee,
+                                            None => o.clone(),
+                                        };
+                                        // The lock must be removed for the transfer to succeed. If the transfer fails the lock is 
+                                        // restored with the original amount and deadline so that the funds are not left unlocked but unpaid.
+                                        // Nothing else has been changed in this function at that point.
+                                        // An invoice without an amount, or adjusted below zero, cannot be settled. 
+                                        // This is checked before the lock is removed so that nothing is released.
+                                        let settled: ComparisonAmounts = match Self::settlement_amount(h) {
+                                            Some(n) => n,
+                                            None => {
+                                                Self::deposit_event(RawEvent::ErrorInvoiceAmount(u));
+                                                return Err("The invoice cannot be settled from the prefunded amount");
+                                            },
+                                        };
+                                        let prefunding_id = Self::get_prefunding_id(h);
+                                        T::Currency::remove_lock(prefunding_id, &details.owner);
+                                        // transfer to beneficiary.
+                                        // TODO when currency conversion is implemnted the payment should be at the current rate for the currency
+                                        // Only the invoiced amount net of adjustments and credit notes is paid, as posted to the beneficiary at settlement.
+                                        // The rest of the prefund is released to the buyer with the lock.
+                                        let payment_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(
+                                            <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(settled)
+                                        );
+                                        match T::Currency::transfer(&details.owner, &payee, payment_amount) {
+                                            Ok(_) => (),
+                                            Err(_) => {
+                                                T::Currency::set_lock(prefunding_id, &details.owner, prefunding.0, prefunding.1, WithdrawReason::Reserve.into());
+                                                Self::deposit_event(RawEvent::ErrorTransferFailed(u));
+                                                return Err("Error during transfer");
                                             },
-                                            Err(e) => return Err(e),
                                         }
+                                        // Only once the funds have been paid is the prefunding cleaned up
+                                        let status:  Status = STATUS_SETTLED;
+                                        Self::cancel_prefunding_lock(details.owner.clone(), h, status)?;
                                         
                                     },
                                     _ => return Err("Only allowed when status is Invoiced"),
                                 }
                             },
-                            (false, false) => {
+                            LockState::ReleasedToSender => {
                                 // Owner has been given permission by beneficiary to release funds
                                 Self::deposit_event(RawEvent::ErrorNotAllowed1(u));
-                                return Err("Funds already locked for intended purpose by both parties.")
+                                return Err("Funds have been released to the owner.")
                                 
                             },
                         }
@@ -325,8 +1027,503 @@ impl<T: Trait> Module<T> {
         
         Ok(())
     }
+    /// Records the assignment of the settlement proceeds by the beneficiary to a third party
+    fn set_receivables_assignee(o: T::AccountId, h: T::Hash, a: T::AccountId, u: T::Hash) -> Result {
+        match Self::reference_valid(h) {
+            true => {
+                let owners = Self::prefunding_hash_owner(&h).ok_or("Error fetching details")?;
+                if owners.beneficiary != o {
+                    Self::deposit_event(RawEvent::ErrorNotAllowed7(u));
+                    return Err("Not the beneficiary");
+                }
+                if Self::express_reference(&h) {
+                    Self::deposit_event(RawEvent::ErrorExpressOrder(u));
+                    return Err("Not allowed for express orders");
+                }
+                if a == owners.owner || a == owners.beneficiary {
+                    Self::deposit_event(RawEvent::ErrorAssigneeNotAllowed(u));
+                    return Err("The assignee cannot be the buyer or the beneficiary");
+                }
+                // The beneficiary must have accepted the order (locked the funds) before the proceeds can be assigned
+                if !owners.lock.is_accepted() {
+                    Self::deposit_event(RawEvent::ErrorNotAccepted(u));
+                    return Err("The order has not been accepted yet");
+                }
+                // A receivable can only be assigned once
+                if <ReceivablesAssignee<T>>::exists(&h) {
+                    Self::deposit_event(RawEvent::ErrorAlreadyAssigned(u));
+                    return Err("The receivables have already been assigned");
+                }
+                Self::post_assignment(o.clone(), a.clone(), h, Self::assigned_amount(h, u)?, false, u)?;
+                <ReceivablesAssignee<T>>::insert(&h, a.clone());
+                Self::deposit_event(RawEvent::ReceivablesAssigned(h, owners.owner, o, a));
+            },
+            false => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist4(u));
+                return Err("Hash does not exist!");
+            },
+        }
+        Ok(())
+    }
+    /// Amount of the assigned receivables: the invoiced amount (the prefunded amount before invoicing) net of the 
+    /// adjustments and credits made so far. Once assigned, the invoice can no longer be adjusted or credited, 
+    /// and receivables assigned before invoicing must be invoiced for the prefunded amount, 
+    /// so this is also the amount paid at settlement.
+    fn assigned_amount(h: T::Hash, u: T::Hash) -> rstd::result::Result<ComparisonAmounts, &'static str> {
+        match Self::settlement_amount(h) {
+            Some(n) => Ok(n),
+            None => {
+                Self::deposit_event(RawEvent::ErrorInvoiceAmount(u));
+                Err("The invoice cannot be settled from the prefunded amount")
+            },
+        }
+    }
+    /// Posts the assignment of the receivables of the beneficiary to the assignee, or its reversal.
+    /// The beneficiary records a liability towards the assignee, and the assignee the receivables it has purchased.
+    /// Both are cleared at settlement when the proceeds are paid to the assignee.
+    fn post_assignment(b: T::AccountId, a: T::AccountId, h: T::Hash, n: ComparisonAmounts, reverse: bool, u: T::Hash) -> Result {
+        let amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(n);
+        let amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(if reverse { amount * -1 } else { amount });
+        
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Beneficiary
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200060000000u64); // Credit increase 120200060000000 Liabilities from assigned receivables
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600060000000u64); // Debit increase 360600060000000 Assigned Receivables Control
+        
+        // Assignee
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100100000000u64); // Debit increase 110100100000000 Purchased receivables
+        
+        // Keys for posting
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        forward_keys.push((b.clone(), a.clone(), account_1, amount, !reverse, h, current_block, current_block_dupe));
+        forward_keys.push((b.clone(), a.clone(), account_2, amount, reverse, h, current_block, current_block_dupe));
+        
+        forward_keys.push((a.clone(), b.clone(), account_3, amount, reverse, h, current_block, current_block_dupe));
+        forward_keys.push((a.clone(), b.clone(), account_2, amount, reverse, h, current_block, current_block_dupe));
+        
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting10(u));
+                return Err("There was an error posting to accounts");
+            },
+        }
+        Ok(())
+    }
+    /// Validates and stores the split of the invoiced amount across the income accounts of the beneficiary
+    fn split_revenue(o: T::AccountId, h: T::Hash, splits: Vec<(u64, ComparisonAmounts)>, u: T::Hash) -> Result {
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist7(u));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.beneficiary != o {
+            Self::deposit_event(RawEvent::ErrorNotAllowed10(u));
+            return Err("Not the beneficiary");
+        }
+        if <ReferenceStatus<T>>::get(&h) != STATUS_INVOICED {
+            Self::deposit_event(RawEvent::ErrorNotInvoiced(u));
+            return Err("Only allowed when status is Invoiced");
+        }
+        if splits.is_empty() {
+            <RevenueSplit<T>>::remove(&h);
+            Self::deposit_event(RawEvent::RevenueSplitSet(h, o));
+            return Ok(());
+        }
+        let valid_accounts = splits.len() <= MAX_REVENUE_SPLITS && splits.iter().enumerate().all(|(i, s)| {
+            s.0 / REVENUE_ACCOUNT_DIVISOR == REVENUE_ACCOUNT_PREFIX && s.1 > 0 && !splits[..i].iter().any(|p| p.0 == s.0)
+        });
+        if !valid_accounts {
+            Self::deposit_event(RawEvent::ErrorRevenueSplit(u));
+            return Err("Splits must be distinct income accounts with an amount");
+        }
+        let total: Option<ComparisonAmounts> = splits.iter().fold(Some(0u128), |total, s| total.and_then(|t| t.checked_add(s.1)));
+        if total != Self::invoiced_amount(h) {
+            Self::deposit_event(RawEvent::ErrorRevenueSplitTotal(u));
+            return Err("Splits do not add up to the invoiced amount");
+        }
+        <RevenueSplit<T>>::insert(&h, splits);
+        Self::deposit_event(RawEvent::RevenueSplitSet(h, o));
+        Ok(())
+    }
+    /// Keys posting an invoice (or a credit note for a negative amount) of the seller (o) to the buyer (p)
+    fn invoice_keys(o: T::AccountId, p: T::AccountId, increase_amount: AccountBalanceOf<T>, h: T::Hash) -> Vec<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)> {
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Seller
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100090000000u64); // Debit increase 110100090000000 Trade receivables - non-related parties
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600020000000u64); // Debit increase 360600020000000 Sales Control
+        
+        // Buyer
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200030000000u64); // Credit increase 120200030000000 Accounts payable (Trade creditors)
+        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600010000000u64); // Debit increase 360600010000000 Purchase Control
+        
+        // Keys for posting
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        forward_keys.push((o.clone(), p.clone(), account_1, increase_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((o.clone(), p.clone(), account_2, increase_amount, false, h, current_block, current_block_dupe));
+        
+        forward_keys.push((p.clone(), o.clone(), account_3, increase_amount, true, h, current_block, current_block_dupe));
+        forward_keys.push((p.clone(), o, account_4, increase_amount, false, h, current_block, current_block_dupe));
+        forward_keys
+    }
+    /// Cost account of the buyer and income account of the seller for an order type
+    fn order_type_accounts(t: OrderType) -> Option<(u64, u64)> {
+        match t {
+            ORDER_TYPE_SERVICE => Some((LABOUR, SALES_OF_SERVICES)),
+            ORDER_TYPE_GOODS => Some((INVENTORY, SALES_OF_GOODS)),
+            ORDER_TYPE_EXPENSE => Some((REIMBURSED_EXPENSES, RECHARGED_EXPENSES)),
+            _ => None,
+        }
+    }
+    /// Invoiced amount net of credit notes
+    fn invoiced_amount(h: T::Hash) -> Option<ComparisonAmounts> {
+        Self::invoice_cap(h)?.checked_sub(Self::invoice_credit_total(h))
+    }
+    /// Amount that adjustments and credits are capped at: the invoiced amount, 
+    /// or the prefunded amount for invoices issued before the invoiced amount was recorded
+    fn invoice_cap(h: T::Hash) -> Option<ComparisonAmounts> {
+        match Self::invoice_total(&h) {
+            Some(invoiced) => Some(invoiced),
+            None => Self::prefunding(&h).map(|p| <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(p.0)),
+        }
+    }
+    /// Amount paid to the beneficiary at settlement: the invoiced amount net of credit notes and adjustments.
+    /// The rest of the prefunded amount is released to the buyer.
+    fn settlement_amount(h: T::Hash) -> Option<ComparisonAmounts> {
+        Self::invoiced_amount(h)?.checked_sub(Self::invoice_adjustment_total(h))
+    }
+    /// Total of the adjustments made to an invoice
+    fn invoice_adjustment_total(h: T::Hash) -> ComparisonAmounts {
+        Self::invoice_adjustments(&h).iter().fold(0u128, |total, a| total.saturating_add(a.0))
+    }
+    // total of the credit notes issued against an invoice
+    fn invoice_credit_total(h: T::Hash) -> ComparisonAmounts {
+        Self::invoice_credits(&h).iter().fold(0u128, |total, c| total.saturating_add(c.0))
+    }
+    /// Reference status codes with their names for off-chain clients
+    pub fn status_codes() -> Vec<(Status, Vec<u8>)> {
+        vec![
+            (STATUS_DRAFT, b"draft".to_vec()),
+            (STATUS_SUBMITTED, b"submitted".to_vec()),
+            (STATUS_CANCELLED, b"cancelled".to_vec()),
+            (STATUS_SELLER_REJECTED, b"rejected by seller".to_vec()),
+            (STATUS_EXPIRED, b"expired".to_vec()),
+            (STATUS_ARBITRATED, b"arbitrated".to_vec()),
+            (STATUS_SELLER_DEFAULTED, b"defaulted by seller".to_vec()),
+            (STATUS_DISPUTED, b"disputed".to_vec()),
+            (STATUS_REJECTED, b"rejected".to_vec()),
+            (STATUS_ACCEPTED, b"accepted".to_vec()),
+            (STATUS_INVOICED, b"invoiced".to_vec()),
+            (STATUS_SETTLED, b"settled".to_vec()),
+            (STATUS_BLOCKED, b"blocked".to_vec()),
+        ]
+    }
+    /// Statuses to which the runtime can move a reference from the given status.
+    /// Disputed, rejected, accepted and blocked are reserved and are not set by the runtime.
+    pub fn valid_transitions(s: Status) -> Vec<Status> {
+        match s {
+            STATUS_DRAFT => vec![STATUS_SUBMITTED, STATUS_CANCELLED],
+            STATUS_SUBMITTED => vec![STATUS_SELLER_REJECTED, STATUS_EXPIRED, STATUS_ARBITRATED, STATUS_SELLER_DEFAULTED, STATUS_INVOICED],
+            STATUS_INVOICED => vec![STATUS_SELLER_REJECTED, STATUS_SETTLED],
+            _ => Vec::new(),
+        }
+    }
+    /// State of a prefunded reference for off-chain clients 
+    /// (owner, beneficiary, amount, deadline, status, total adjustments, total credits)
+    pub fn prefunding_state(h: T::Hash) -> Option<(T::AccountId, T::AccountId, CurrencyBalanceOf<T>, T::BlockNumber, Status, ComparisonAmounts, ComparisonAmounts)> {
+        let (amount, deadline) = Self::prefunding(&h)?;
+        let owners = Self::prefunding_hash_owner(&h)?;
+        Some((owners.owner, owners.beneficiary, amount, deadline, Self::reference_status(&h), Self::invoice_adjustment_total(h), Self::invoice_credit_total(h)))
+    }
+    /// Records the settlement block and the blocks the payer took to pay the invoice of the beneficiary
+    fn record_settlement_date(h: T::Hash, p: T::AccountId, b: T::AccountId) {
+        let invoiced: T::BlockNumber = match Self::invoice_dates(&h) {
+            Some((invoiced, _)) => invoiced,
+            None => return,
+        };
+        let current_block: T::BlockNumber = <system::Module<T>>::block_number();
+        <InvoiceDates<T>>::insert(&h, (invoiced, Some(current_block)));
+        let (current, invoiced_at): (u64, u64) = (current_block.as_(), invoiced.as_());
+        let blocks_to_pay: u64 = current.saturating_sub(invoiced_at);
+        <PaymentHistory<T>>::mutate((p.clone(), b.clone()), |history| {
+            history.0 = history.0.saturating_add(1);
+            history.1 = history.1.saturating_add(blocks_to_pay);
+            history.2 = rstd::cmp::max(history.2, blocks_to_pay);
+        });
+        if !Self::payer_counterparties(&p).contains(&b) {
+            <PayerCounterparties<T>>::mutate(&p, |counterparties| counterparties.push(b));
+        }
+    }
+    /// Stores the settlement certificate of a reference under its id
+    fn issue_settlement_certificate(h: T::Hash, p: T::AccountId, b: T::AccountId, settled_amounts: (ComparisonAmounts, ComparisonAmounts)) {
+        let certificate = SettlementCertificate {
+            reference: h,
+            payer: p,
+            beneficiary: b,
+            paid: settled_amounts.0,
+            released: settled_amounts.1,
+            block: <system::Module<T>>::block_number(),
+            postings: <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_posting_indexes_by_reference(h)
+                .into_iter()
+                .map(|i| i.into())
+                .collect(),
+        };
+        let id: T::Hash = T::Hashing::hash(certificate.encode().as_slice());
+        <SettlementCertificates<T>>::insert(&id, certificate);
+        <ReferenceCertificate<T>>::insert(&h, id);
+        Self::deposit_event(RawEvent::SettlementCertificateIssued(h, id));
+    }
+    /// The settlement certificate of a reference with its id
+    pub fn settlement_certificate_of(h: T::Hash) -> Option<(T::Hash, SettlementCertificate<T::AccountId, T::Hash, T::BlockNumber>)> {
+        let id: T::Hash = Self::reference_certificate(&h)?;
+        Self::settlement_certificate(&id).map(|certificate| (id, certificate))
+    }
+    /// Days a payer took to pay the invoices of each beneficiary
+    /// (beneficiary, settled invoices, average days to pay, longest days to pay)
+    pub fn days_to_pay(p: T::AccountId) -> Vec<(T::AccountId, u32, u32, u32)> {
+        Self::payer_counterparties(&p)
+            .into_iter()
+            .map(|b| {
+                let (settled, total, longest) = Self::payment_history((p.clone(), b.clone()));
+                let average: u64 = if settled == 0 { 0 } else { total / settled as u64 };
+                (b, settled, (average / BLOCKS_PER_DAY) as u32, (longest / BLOCKS_PER_DAY) as u32)
+            })
+            .collect()
+    }
+    /// Invoices of a beneficiary awaiting settlement for receivables aging
+    /// (reference, payer, invoice block, days outstanding)
+    pub fn receivables_aging(b: T::AccountId) -> Vec<(T::Hash, T::AccountId, T::BlockNumber, u32)> {
+        let current: u64 = <system::Module<T>>::block_number().as_();
+        Self::beneficiary_prefunding_hash_list(&b)
+            .into_iter()
+            .filter(|h| Self::reference_status(h) == STATUS_INVOICED)
+            .filter_map(|h| {
+                let invoiced: T::BlockNumber = match Self::invoice_dates(&h)? {
+                    (invoiced, None) => invoiced,
+                    _ => return None,
+                };
+                let owners = Self::prefunding_hash_owner(&h)?;
+                let invoiced_at: u64 = invoiced.as_();
+                let outstanding: u64 = current.saturating_sub(invoiced_at);
+                Some((h, owners.owner, invoiced, (outstanding / BLOCKS_PER_DAY) as u32))
+            })
+            .collect()
+    }
+    /// Open items between an identity and a counterparty for a statement of account.
+    /// (invoices issued to the counterparty with the outstanding receivable, invoices received from the counterparty 
+    /// with the outstanding payable, net position). Outstanding amounts are the balances posted under the reference 
+    /// to trade receivables and trade payables, so adjustments and credit notes are taken into account.
+    pub fn open_items(o: T::AccountId, c: T::AccountId) -> (Vec<(T::Hash, i128)>, Vec<(T::Hash, i128)>, i128) {
+        let receivables: Vec<(T::Hash, i128)> = Self::beneficiary_prefunding_hash_list(&o)
+            .into_iter()
+            .filter(|h| Self::is_open_item(*h, &c, &o))
+            .map(|h| (h, Self::reference_balance(&o, h, 110100090000000u64))) // 110100090000000 Trade receivables
+            .filter(|i| i.1 != 0)
+            .collect();
+        let payables: Vec<(T::Hash, i128)> = Self::owner_prefunding_hash_list(&o)
+            .into_iter()
+            .filter(|h| Self::is_open_item(*h, &o, &c))
+            .map(|h| (h, Self::reference_balance(&o, h, 120200030000000u64))) // 120200030000000 Accounts payable
+            .filter(|i| i.1 != 0)
+            .collect();
+        let net: i128 = receivables.iter().fold(0i128, |n, r| n.saturating_add(r.1))
+            .saturating_sub(payables.iter().fold(0i128, |n, p| n.saturating_add(p.1)));
+        (receivables, payables, net)
+    }
+    /// An invoiced reference of the payer to the beneficiary that has not been settled
+    fn is_open_item(h: T::Hash, p: &T::AccountId, b: &T::AccountId) -> bool {
+        Self::reference_status(&h) == STATUS_INVOICED && Self::prefunding_hash_owner(&h).map_or(false, |owners| &owners.owner == p && &owners.beneficiary == b)
+    }
+    /// Balance of an identity on a ledger account from the postings made under a reference
+    fn reference_balance(o: &T::AccountId, h: T::Hash, a: u64) -> i128 {
+        let account: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(a);
+        <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_postings_by_reference(h)
+            .into_iter()
+            .filter(|p| &p.0 == o && p.1 == account)
+            .fold(0i128, |balance, p| balance.saturating_add(<T::PrefundingConversions as Convert<AccountBalanceOf<T>, i128>>::convert(p.2)))
+    }
+    /// Lock ids of the prefundings owned by an account and of the performance bonds it has locked as a seller
+    fn expected_locks(o: &T::AccountId) -> Vec<LockIdentifier> {
+        let prefundings = Self::owner_prefunding_hash_list(o)
+            .into_iter()
+            .filter(|h| <Prefunding<T>>::exists(h))
+            .map(|h| Self::get_prefunding_id(h));
+        let bonds = Self::beneficiary_prefunding_hash_list(o)
+            .into_iter()
+            .filter(|h| Self::performance_bond(h).map_or(false, |b| &b.0 == o))
+            .map(|h| Self::get_bond_id(h));
+        prefundings.chain(bonds).collect()
+    }
+    /// A lock is orphaned when the account holds a lock with this id of the kind set by this module (reserve only),
+    /// but no prefunding or performance bond in storage has this lock id.
+    /// Locks are read from the balances module, which is the currency of the runtime.
+    fn is_orphaned_lock(o: &T::AccountId, id: LockIdentifier) -> bool {
+        let reserve: WithdrawReasons = WithdrawReason::Reserve.into();
+        let locked: bool = <balances::Module<T>>::locks(o).iter().any(|l| l.id == id && l.reasons == reserve);
+        locked && !Self::expected_locks(o).contains(&id)
+    }
+    /// Invariant check. Returns the supplied (account, lock id) pairs that are orphaned locks.
+    pub fn orphaned_locks(candidates: Vec<(T::AccountId, LockIdentifier)>) -> Vec<(T::AccountId, LockIdentifier)> {
+        candidates
+            .into_iter()
+            .take(MAX_LOCK_CANDIDATES)
+            .filter(|c| Self::is_orphaned_lock(&c.0, c.1))
+            .collect()
+    }
+    fn deadline_bucket(deadline: T::BlockNumber) -> u64 {
+        deadline.as_() / BLOCKS_PER_DAY
+    }
+    /// Checks that the bucket of a deadline in the deadlines index has room for another prefunding
+    fn check_deadline_bucket(deadline: T::BlockNumber, uid: T::Hash) -> Result {
+        if Self::deadlines_index(Self::deadline_bucket(deadline)).len() >= MAX_DEADLINE_BUCKET_SIZE {
+            Self::deposit_event(RawEvent::ErrorDeadlineBucketFull(uid));
+            return Err("Too many prefunds expire on the day of this deadline");
+        }
+        Ok(())
+    }
+    /// Removes a prefunding from the bucket of its deadline in the deadlines index. The bucket is bounded by 
+    /// MAX_DEADLINE_BUCKET_SIZE.
+    fn unindex_deadline(h: T::Hash) {
+        if let Some((_, deadline)) = Self::prefunding(&h) {
+            <DeadlinesIndex<T>>::mutate(Self::deadline_bucket(deadline), |references| references.retain(|r| r != &h));
+        }
+    }
+    /// Returns the prefunds awaiting acceptance whose deadline falls within the next blocks (reference, deadline), 
+    /// in order of deadline. Accepted prefunds stay indexed until they are invoiced or cancelled, but are left out. 
+    /// At most MAX_DEADLINE_BUCKETS buckets of the deadlines index are read.
+    pub fn expiring_prefundings(within: u64) -> Vec<(T::Hash, T::BlockNumber)> {
+        let current: u64 = <system::Module<T>>::block_number().as_();
+        let last: u64 = current.saturating_add(within);
+        let first_bucket: u64 = current / BLOCKS_PER_DAY;
+        let end: u64 = rstd::cmp::min(last / BLOCKS_PER_DAY + 1, first_bucket.saturating_add(MAX_DEADLINE_BUCKETS));
+        let mut expiring: Vec<(T::Hash, T::BlockNumber)> = (first_bucket..end)
+            .flat_map(|b| Self::deadlines_index(b).into_iter())
+            .filter(|h| Self::prefunding_hash_owner(h).map_or(false, |owners| owners.lock == LockState::AwaitingAcceptance))
+            .filter_map(|h| Some((h, Self::prefunding(&h)?.1)))
+            .filter(|e| {
+                let deadline: u64 = e.1.as_();
+                deadline >= current && deadline <= last
+            })
+            .collect();
+        expiring.sort_by_key(|e| e.1);
+        expiring
+    }
+    /// Invariant check. Returns the references of an owner with a prefunding in storage but no matching currency lock.
+    pub fn unlocked_prefundings(o: T::AccountId) -> Vec<T::Hash> {
+        let locks: Vec<LockIdentifier> = <balances::Module<T>>::locks(&o).into_iter().map(|l| l.id).collect();
+        Self::owner_prefunding_hash_list(&o)
+            .into_iter()
+            .filter(|h| <Prefunding<T>>::exists(h) && !locks.contains(&Self::get_prefunding_id(*h)))
+            .collect()
+    }
+    /// Reconciles the escrow sub-ledger of an owner against the prefunding locks 
+    /// (reference, locked amount, escrow balance in the ledger). Any difference between the two needs investigating.
+    pub fn escrow_reconciliation(o: T::AccountId) -> Vec<(T::Hash, ComparisonAmounts, i128)> {
+        let escrow_account: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100050000000u64); // 110100050000000 Totem Runtime Deposit (Escrow)
+        Self::owner_prefunding_hash_list(&o)
+            .into_iter()
+            .filter_map(|h| {
+                let (amount, _) = Self::prefunding(&h)?;
+                let locked: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(amount);
+                let escrowed: i128 = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, i128>>::convert(
+                    <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::get_sub_ledger_balance(o.clone(), escrow_account, h)
+                );
+                Some((h, locked, escrowed))
+            })
+            .collect()
+    }
+    /// Records a discount or credit waived by the beneficiary on an invoice and posts the adjustment for both parties.
+    /// The total adjustments cannot exceed the prefunded amount.
+    fn adjust_invoice(o: T::AccountId, h: T::Hash, n: u128, r: T::Hash, u: T::Hash) -> Result {
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist5(u));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.beneficiary != o {
+            Self::deposit_event(RawEvent::ErrorNotAllowed8(u));
+            return Err("Not the beneficiary");
+        }
+        if Self::express_reference(&h) {
+            Self::deposit_event(RawEvent::ErrorExpressOrder(u));
+            return Err("Not allowed for express orders");
+        }
+        // Only invoiced references can be adjusted. 
+        if <ReferenceStatus<T>>::get(&h) != STATUS_INVOICED {
+            Self::deposit_event(RawEvent::ErrorNotInvoiced(u));
+            return Err("Only allowed when status is Invoiced");
+        }
+        // The amount of assigned receivables is fixed with the assignee
+        if <ReceivablesAssignee<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorReceivablesAssigned(u));
+            return Err("The receivables have been assigned");
+        }
+        let invoiced: ComparisonAmounts = match Self::invoice_cap(h) {
+            Some(invoiced) => invoiced,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoPrefunding2(u));
+                return Err("Error getting prefunding details from hash");
+            },
+        };
+        let new_total: ComparisonAmounts = match Self::invoice_adjustment_total(h).saturating_add(Self::invoice_credit_total(h)).checked_add(n) {
+            Some(t) => t,
+            None => {
+                Self::deposit_event(RawEvent::ErrorAdjustmentTooLarge(u));
+                return Err("Adjustment is larger than the invoice");
+            },
+        };
+        if n == 0 || new_total > invoiced {
+            Self::deposit_event(RawEvent::ErrorAdjustmentTooLarge(u));
+            return Err("Adjustment is larger than the invoice");
+        }
+        
+        let increase_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(n);
+        let decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(<T::PrefundingConversions as Convert<u128, i128>>::convert(n) * -1);
+        
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Seller
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(250500410000000u64); // Debit  increase 250500410000000 Discounts allowed
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100090000000u64); // Credit decrease 110100090000000 Trade receivables - non-related parties
+        
+        // Buyer
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200030000000u64); // Debit  decrease 120200030000000 Accounts payable (Trade creditors)
+        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(240400080000000u64); // Credit increase 240400080000000 Discounts received
+        
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        forward_keys.push((o.clone(), owners.owner.clone(), account_1, increase_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((o.clone(), owners.owner.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));
+        forward_keys.push((owners.owner.clone(), o.clone(), account_3, decrease_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((owners.owner.clone(), o.clone(), account_4, increase_amount, true, h, current_block, current_block_dupe));
+        
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(forward_keys.clone(), Vec::new(), ORIGIN_ADJUSTMENT) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting7(u));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        
+        <InvoiceAdjustments<T>>::mutate(&h, |invoice_adjustments| invoice_adjustments.push((n, r)));
+        
+        Self::deposit_event(RawEvent::InvoiceAdjusted(h, o, n, r));
+        Ok(())
+    }
     // set the status for the prefunding
     fn set_ref_status(h: T::Hash, s: Status) -> Result {
+        // Only prefunds awaiting acceptance can expire
+        if s != STATUS_SUBMITTED {
+            Self::unindex_deadline(h);
+        }
         <ReferenceStatus<T>>::remove(&h);
         <ReferenceStatus<T>>::insert(&h, s);
         Ok(())
@@ -336,13 +1533,8 @@ impl<T: Trait> Module<T> {
     fn settle_unfunded_invoice() -> Result {
         Ok(())
     }
-}
-
-impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
-    
-    type UnLocked = UnLocked;
-    
-    fn prefunding_for(who: T::AccountId, recipient: T::AccountId, amount: u128, deadline: T::BlockNumber, ref_hash: T::Hash, uid: T::Hash) -> Result {
+    /// Locks the prefunding and updates the accounts of the buyer
+    fn lock_prefunding(who: T::AccountId, recipient: T::AccountId, amount: u128, deadline: T::BlockNumber, ref_hash: T::Hash, uid: T::Hash) -> Result {
         
         // As amount will always be positive, convert for use in accounting
         let amount_converted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(amount);  
@@ -365,17 +1557,18 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         let currency_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(amount_converted.clone());
         
         // NEED TO CHECK THAT THE DEADLINE IS SENSIBLE!!!!
-        // 48 hours is the minimum deadline. This is the minimum amountof time before the money can be reclaimed
-        let minimum_deadline: T::BlockNumber = current_block + <T::PrefundingConversions as Convert<u64, T::BlockNumber>>::convert(11520u64);
+        // 48 hours is the minimum deadline, unless the parties have agreed a shorter minimum
+        let minimum_deadline: T::BlockNumber = current_block + <T::PrefundingConversions as Convert<u64, T::BlockNumber>>::convert(Self::minimum_deadline_of(&who, &recipient));
         
         if deadline < minimum_deadline {
             Self::deposit_event(RawEvent::ErrorShortDeadline(uid));
             return Err("Deadline is too short!");
         }
+        Self::check_deadline_bucket(deadline, uid)?;
         
         let prefunded = (currency_amount, deadline.clone());
         
-        let owners = (who.clone(), true, recipient.clone(), false);
+        let owners = PrefundingParties { owner: who.clone(), beneficiary: recipient.clone(), lock: LockState::AwaitingAcceptance };
         
         // manage the deposit
         match Self::set_prefunding(who.clone(), amount_converted.clone(), deadline, prefunding_hash, uid) {
@@ -398,16 +1591,12 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         forward_keys.push((who.clone(), recipient.clone(), account_2, decrease_amount, true, prefunding_hash, current_block, current_block_dupe));
         forward_keys.push((who.clone(), recipient.clone(), account_3, increase_amount, false, prefunding_hash, current_block, current_block_dupe));
         
-        // Reversal keys in case of errors
-        let mut reversal_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(2);
-        reversal_keys.push((who.clone(), recipient.clone(), account_1, decrease_amount, true, prefunding_hash, current_block, current_block_dupe));
-        reversal_keys.push((who.clone(), recipient.clone(), account_2, increase_amount, false, prefunding_hash, current_block, current_block_dupe));
-        
-        let track_rev_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(3);
-        
-        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone(),reversal_keys.clone(),track_rev_keys.clone()) {
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone()) {
             Ok(_) => (),
             Err(_e) => {
+                // The deposit was taken, so the lock is removed and its lock id freed
+                T::Currency::remove_lock(Self::get_prefunding_id(prefunding_hash), &who);
+                Self::release_lock_id(&who, PREFUNDING_LOCK_NAMESPACE, prefunding_hash);
                 Self::deposit_event(RawEvent::ErrorInAccounting1(uid));
                 return Err("An error occured posting to accounts");
             },
@@ -416,12 +1605,14 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         // Record Prefunding ownership and status
         <PrefundingHashOwner<T>>::insert(&prefunding_hash, owners); 
         <Prefunding<T>>::insert(&prefunding_hash, prefunded);
+        <DeadlinesIndex<T>>::mutate(Self::deadline_bucket(deadline), |references| references.push(prefunding_hash));
         
         // Add reference hash to list of hashes
         <OwnerPrefundingHashList<T>>::mutate(&who, |owner_prefunding_hash_list| owner_prefunding_hash_list.push(prefunding_hash));
+        <BeneficiaryPrefundingHashList<T>>::mutate(&recipient, |beneficiary_prefunding_hash_list| beneficiary_prefunding_hash_list.push(prefunding_hash));
         
         // Submitted, Locked by sender.
-        match Self::set_ref_status(prefunding_hash, 1) {
+        match Self::set_ref_status(prefunding_hash, STATUS_SUBMITTED) {
             Ok(_) => (),
             Err(_e) => {
                 Self::deposit_event(RawEvent::ErrorSettingStatus1(uid));
@@ -435,10 +1626,280 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         
         Ok(())
     }
+    /// Stores a proforma invoice of the beneficiary on a submitted reference that has not been accepted
+    fn set_proforma(b: T::AccountId, h: T::Hash, amount: ComparisonAmounts, terms: T::Hash, uid: T::Hash) -> Result {
+        if !Self::check_ref_beneficiary(b.clone(), h) {
+            Self::deposit_event(RawEvent::ErrorNotAllowed2(uid));
+            return Err("Not the beneficiary");
+        }
+        let accepted: bool = Self::get_release_state(h) != LockState::AwaitingAcceptance;
+        if accepted || Self::reference_status(&h) != STATUS_SUBMITTED || Self::express_reference(&h) || !Self::acceptance_open(h) {
+            Self::deposit_event(RawEvent::ErrorProforma(uid));
+            return Err("A proforma can only be proposed on a submitted reference open for acceptance");
+        }
+        let prefunded: ComparisonAmounts = match Self::prefunding(&h) {
+            Some(prefunding) => <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(prefunding.0),
+            None => 0u128,
+        };
+        if amount == 0u128 || amount > prefunded {
+            Self::deposit_event(RawEvent::ErrorInvoiceAmount(uid));
+            return Err("Proforma amount must be greater than zero and within the prefunded amount");
+        }
+        <Proforma<T>>::insert(&h, (amount, terms, <system::Module<T>>::block_number()));
+        
+        Self::deposit_event(RawEvent::ProformaProposed(h, b, amount));
+        Ok(())
+    }
+    /// Accepts the order on behalf of the beneficiary that proposed the proforma and records the agreed invoice amount
+    fn agree_proforma(o: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        if !Self::check_ref_owner(o.clone(), h) {
+            Self::deposit_event(RawEvent::ErrorNotOwner(uid));
+            return Err("Not the owner");
+        }
+        let (amount, _, _) = match Self::proforma(&h) {
+            Some(proforma) => proforma,
+            None => {
+                Self::deposit_event(RawEvent::ErrorProforma(uid));
+                return Err("No proforma to accept");
+            },
+        };
+        let beneficiary: T::AccountId = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners.beneficiary,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist2(uid));
+                return Err("Error getting the hash data");
+            },
+        };
+        // The proposal of the beneficiary is its acceptance. The lock state checks the deadline and grace period.
+        Self::set_release_state(beneficiary, LockState::Accepted, h, uid)?;
+        <AgreedInvoiceAmount<T>>::insert(&h, amount);
+        <Proforma<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::ProformaAccepted(h, o, amount));
+        Ok(())
+    }
+    /// Rejects zero and dust prefunds before any storage is changed
+    fn check_prefund_amount(amount: ComparisonAmounts, uid: T::Hash) -> Result {
+        if amount == 0u128 || amount < Self::minimum_prefund() {
+            Self::deposit_event(RawEvent::ErrorPrefundAmount(uid));
+            return Err("Prefund amount is zero or below the minimum");
+        }
+        if !Self::is_within_maximum(amount) {
+            Self::deposit_event(RawEvent::ErrorAboveMaximum(uid));
+            return Err("Prefund amount is above the network maximum");
+        }
+        Ok(())
+    }
+    /// Whether an amount is within the network maximum (if one is set)
+    fn is_within_maximum(amount: ComparisonAmounts) -> bool {
+        let maximum: ComparisonAmounts = Self::maximum_prefund();
+        maximum == 0u128 || amount <= maximum
+    }
+    /// Prefunds above the owner's dual control threshold must be co-approved by a second signatory
+    fn requires_co_signing(o: &T::AccountId, amount: ComparisonAmounts) -> bool {
+        match Self::dual_control(o) {
+            Some((threshold, signatories, _)) => !signatories.is_empty() && amount > threshold,
+            None => false,
+        }
+    }
+    /// Stores the prefund pending co-approval. Nothing is locked until a signatory co-approves.
+    fn set_pending_prefunding(o: T::AccountId, p: T::AccountId, amount: ComparisonAmounts, d: T::BlockNumber, h: T::Hash, uid: T::Hash) -> Result {
+        if <PendingPrefunding<T>>::exists(&h) || <Prefunding<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorHashExists(uid));
+            return Err("The hash already exists! Try again.");
+        }
+        let window: T::BlockNumber = match Self::dual_control(&o) {
+            Some(dual_control) => dual_control.2,
+            None => return Err("Dual control is not configured"),
+        };
+        let expiry: T::BlockNumber = <system::Module<T>>::block_number() + window;
+        <PendingPrefunding<T>>::insert(&h, (o.clone(), p, amount, d, expiry));
+        <PendingPrefundingList<T>>::mutate(&o, |pending_prefunding_list| pending_prefunding_list.push(h));
+        <PendingPrefundingExpiring<T>>::mutate(expiry + <T::PrefundingConversions as Convert<u64, T::BlockNumber>>::convert(1u64), |expiring| expiring.push(h));
+        Self::deposit_event(RawEvent::PrefundingPendingCoSign(o, h, expiry));
+        Ok(())
+    }
+    // removes the pending prefund and its reference from the owner's list
+    fn remove_pending_prefunding(o: &T::AccountId, h: T::Hash) {
+        <PendingPrefunding<T>>::remove(&h);
+        <PendingPrefundingList<T>>::mutate(o, |pending_prefunding_list| pending_prefunding_list.retain(|p| p != &h));
+    }
+    /// Cancels the prefunds whose co-approval window closed before the block. Nothing was locked for them.
+    fn expire_pending_prefundings(n: T::BlockNumber) {
+        let mut expiring: Vec<T::Hash> = <PendingPrefundingExpiring<T>>::take(n);
+        if expiring.len() > MAX_PENDING_EXPIRIES {
+            let carried: Vec<T::Hash> = expiring.split_off(MAX_PENDING_EXPIRIES);
+            <PendingPrefundingExpiring<T>>::mutate(n + <T::PrefundingConversions as Convert<u64, T::BlockNumber>>::convert(1u64), |next| next.extend(carried));
+        }
+        for h in expiring.into_iter() {
+            let owner: T::AccountId = match Self::pending_prefunding(&h) {
+                Some(p) if p.4 < n => p.0,
+                _ => continue,
+            };
+            Self::remove_pending_prefunding(&owner, h);
+            <AcceptanceGracePeriod<T>>::remove(&h);
+            <ReferenceStatus<T>>::insert(&h, STATUS_CANCELLED);
+            Self::deposit_event(RawEvent::PendingPrefundingExpired(h));
+            Self::deposit_event(RawEvent::PrefundingCancelled(owner, h));
+        }
+    }
+}
+
+impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
+    
+    fn prefunding_for(who: T::AccountId, recipient: T::AccountId, amount: u128, deadline: T::BlockNumber, grace_period: T::BlockNumber, ref_hash: T::Hash, uid: T::Hash) -> Result {
+        Self::check_prefund_amount(amount, uid)?;
+        // Prefunds above the dual control threshold are queued and only locked once co-approved
+        if Self::requires_co_signing(&who, amount) {
+            Self::set_pending_prefunding(who, recipient, amount, deadline, ref_hash, uid)?;
+        } else {
+            Self::lock_prefunding(who, recipient, amount, deadline, ref_hash, uid)?;
+        }
+        Self::set_grace_period(ref_hash, grace_period);
+        Ok(())
+    }
+    /// Whether the prefunding of a reference is queued for co-approval and not yet locked
+    fn awaiting_co_approval(h: T::Hash) -> bool {
+        <PendingPrefunding<T>>::exists(&h)
+    }
+    /// Whether the amount of an order or prefunding is within the network maximum
+    fn within_maximum(amount: u128) -> bool {
+        Self::is_within_maximum(amount)
+    }
+    fn minimum_deadline(o: T::AccountId, p: T::AccountId) -> u64 {
+        Self::minimum_deadline_of(&o, &p)
+    }
+    /// The recipient can accept before the deadline, or late within the grace period as long as the funds have not been taken back
+    fn acceptance_open(h: T::Hash) -> bool {
+        match Self::prefunding(&h) {
+            Some(prefunding) => <system::Module<T>>::block_number() < prefunding.1 + Self::acceptance_grace_period(&h),
+            None => false,
+        }
+    }
+    /// Changes the amount and deadline of a submitted prefunding that has not been accepted, for example when the
+    /// owner accepts a counterproposal. The lock is replaced and only the difference is posted to the escrow accounts.
+    fn adjust_prefunding(o: T::AccountId, h: T::Hash, amount: u128, deadline: T::BlockNumber, uid: T::Hash) -> Result {
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist8(uid));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.owner != o {
+            Self::deposit_event(RawEvent::ErrorNotOwner3(uid));
+            return Err("You are not the owner of the hash!");
+        }
+        if owners.lock != LockState::AwaitingAcceptance || Self::reference_status(&h) != STATUS_SUBMITTED || <AgreedInvoiceAmount<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorAdjustPrefunding(uid));
+            return Err("Only submitted prefundings awaiting acceptance can be adjusted");
+        }
+        let prefunded: CurrencyBalanceOf<T> = match Self::prefunding(&h) {
+            Some(prefunding) => prefunding.0,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoPrefunding4(uid));
+                return Err("Error getting prefunding details");
+            },
+        };
+        Self::check_prefund_amount(amount, uid)?;
+
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        let minimum_deadline: T::BlockNumber = current_block + <T::PrefundingConversions as Convert<u64, T::BlockNumber>>::convert(Self::minimum_deadline_of(&owners.owner, &owners.beneficiary));
+        if deadline < minimum_deadline {
+            Self::deposit_event(RawEvent::ErrorShortDeadline(uid));
+            return Err("Deadline is too short!");
+        }
+        // The prefunding leaves the bucket of its current deadline, so it always fits back in the same bucket
+        let current_deadline: Option<T::BlockNumber> = Self::prefunding(&h).map(|p| p.1);
+        if current_deadline.map_or(true, |d| Self::deadline_bucket(d) != Self::deadline_bucket(deadline)) {
+            Self::check_deadline_bucket(deadline, uid)?;
+        }
+
+        let locked_amount: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(prefunded);
+        if amount > locked_amount && Self::requires_co_signing(&o, amount) {
+            Self::deposit_event(RawEvent::ErrorCoSigningRequired(uid));
+            return Err("Prefunding above the dual control threshold must be co-approved");
+        }
+        // The funds currently locked are returned before the new amount is locked, so they count towards the balance
+        let min_balance: ComparisonAmounts = 1618u128;
+        let current_balance: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(T::Currency::free_balance(&o)) + locked_amount;
+        let minimum_amount: ComparisonAmounts = min_balance + amount;
+        if current_balance < minimum_amount {
+            Self::deposit_event(RawEvent::ErrorInsufficientPreFunds(o, amount, minimum_amount, current_balance));
+            return Err("Not enough funds to prefund");
+        }
+
+        // Post the difference, positive when the prefunding increases
+        let n: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(amount) - <T::PrefundingConversions as Convert<u128, i128>>::convert(locked_amount);
+        if n != 0 {
+            let difference: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n);
+            let inverted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n * -1);
+
+            let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100050000000u64); // Debit  increase 110100050000000 Totem Runtime Deposit (Escrow)
+            let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Credit decrease 110100040000000 XTX Balance
+            let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600040000000u64); // Debit  increase 360600040000000 Escrowed Funds Control
+
+            let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(3);
+            forward_keys.push((o.clone(), owners.beneficiary.clone(), account_1, difference, n < 0, h, current_block, current_block_dupe));
+            forward_keys.push((o.clone(), owners.beneficiary.clone(), account_2, inverted, n > 0, h, current_block, current_block_dupe));
+            forward_keys.push((o.clone(), owners.beneficiary.clone(), account_3, difference, n < 0, h, current_block, current_block_dupe));
+
+            match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone()) {
+                Ok(_) => (),
+                Err(_e) => {
+                    Self::deposit_event(RawEvent::ErrorInAccounting9(uid));
+                    return Err("An error occured posting to accounts");
+                },
+            }
+        }
+
+        // Replace the lock
+        let prefunding_id = Self::get_prefunding_id(h);
+        let amount_converted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(amount);
+        let currency_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(amount_converted);
+        T::Currency::remove_lock(prefunding_id, &o);
+        T::Currency::set_lock(prefunding_id, &o, currency_amount, deadline, WithdrawReason::Reserve.into());
+        Self::unindex_deadline(h);
+        <Prefunding<T>>::insert(&h, (currency_amount, deadline));
+        <DeadlinesIndex<T>>::mutate(Self::deadline_bucket(deadline), |references| references.push(h));
+        Self::share_fees(&o, &owners.owner, &owners.beneficiary, h);
+
+        Self::deposit_event(RawEvent::PrefundingAdjusted(h, amount, deadline));
+        Ok(())
+    }
     /// Simple invoice. Does not include tax jurisdiction, tax amounts, freight, commissions, tariffs, discounts and other extended line item values
     /// must include a connection to the originating reference. 
     /// Invoices cannot be made to parties that haven't asked for something identified by a valid hash
-    fn send_simple_invoice(o: T::AccountId, p: T::AccountId, n: i128, h: T::Hash, u: T::Hash) -> Result {
+    fn send_simple_invoice(o: T::AccountId, p: T::AccountId, n: i128, h: T::Hash, t: OrderType, u: T::Hash) -> Result {
+        
+        if n == 0i128 || n == i128::min_value() {
+            Self::deposit_event(RawEvent::ErrorInvoiceAmount(u));
+            return Err("Invoice amount is zero or out of range");
+        }
+        
+        if let Some(agreed) = Self::agreed_invoice_amount(&h) {
+            if <T::PrefundingConversions as Convert<u128, i128>>::convert(agreed) != n {
+                Self::deposit_event(RawEvent::ErrorInvoiceAmount(u));
+                return Err("The invoice amount differs from the accepted proforma");
+            }
+        }
+        
+        if Self::order_type_accounts(t).is_none() {
+            Self::deposit_event(RawEvent::ErrorOrderType(u));
+            return Err("Unknown order type");
+        }
+        
+        // The invoice is settled from the prefunded amount. Receivables assigned before invoicing were assigned at that amount.
+        if let Some((prefunded, _)) = Self::prefunding(&h) {
+            let prefunded: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(
+                <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(prefunded)
+            );
+            if n > prefunded || (n > 0i128 && n != prefunded && <ReceivablesAssignee<T>>::exists(&h)) {
+                Self::deposit_event(RawEvent::ErrorInvoiceAmount(u));
+                return Err("The invoice amount differs from the prefunded amount it is settled from");
+            }
+        }
         
         // Validate that the hash is indeed assigned to the seller
         match Self::check_ref_beneficiary(o.clone(), h) {
@@ -456,39 +1917,22 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         
         // As amount will always be positive, convert for use in accounting
         let amount_converted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n.clone());  
-        // invert the amount
-        let inverted: i128 = n * -1;
         let increase_amount: AccountBalanceOf<T> = amount_converted.clone();
-        let decrease_amount: AccountBalanceOf<T> =  <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(inverted);
-        
-        let current_block = <system::Module<T>>::block_number();
-        let current_block_dupe = <system::Module<T>>::block_number();
-        
-        // Seller
-        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100090000000u64); // Debit increase 110100090000000 Trade receivables - non-related parties
-        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600020000000u64); // Debit increase 360600020000000 Sales Control
-        
-        // Buyer
-        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200030000000u64); // Credit increase 120200030000000 Accounts payable (Trade creditors)
-        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600010000000u64); // Debit increase 360600010000000 Purchase Control
-        
-        // Keys for posting
-        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
-        forward_keys.push((o.clone(), p.clone(), account_1, increase_amount, false, h, current_block, current_block_dupe));
-        forward_keys.push((o.clone(), p.clone(), account_2, increase_amount, false, h, current_block, current_block_dupe));
-        
-        forward_keys.push((p.clone(), o.clone(), account_3, increase_amount, true, h, current_block, current_block_dupe));
-        forward_keys.push((p.clone(), o.clone(), account_4, increase_amount, false, h, current_block, current_block_dupe));
         
-        // Reversal keys in case of errors
-        let mut reversal_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(3);
-        reversal_keys.push((o.clone(), p.clone(), account_1, decrease_amount, true, h, current_block, current_block_dupe));
-        reversal_keys.push((o.clone(), p.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));
-        reversal_keys.push((p.clone(), o.clone(), account_3, decrease_amount, false, h, current_block, current_block_dupe));
+        // Credit notes reduce the exposure of the seller to the buyer, only invoices are checked against the credit limit
+        if n > 0i128 {
+            match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::check_credit_limit(o.clone(), p.clone(), amount_converted) {
+                Ok(_) => (),
+                Err(_e) => {
+                    Self::deposit_event(RawEvent::ErrorCreditLimit(u));
+                    return Err("The invoice would exceed the credit limit of the buyer");
+                },
+            }
+        }
         
-        let track_rev_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        let forward_keys = Self::invoice_keys(o.clone(), p.clone(), increase_amount, h);
         
-        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone(),reversal_keys.clone(),track_rev_keys.clone()) {
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone()) {
             Ok(_) => (),
             Err(_e) => {
                 Self::deposit_event(RawEvent::ErrorInAccounting2(u));
@@ -497,22 +1941,75 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         }
         
         // Add status processing
-        let new_status: Status = 400; // invoiced(400), can no longer be accepted, 
+        let new_status: Status = STATUS_INVOICED; // can no longer be accepted
         
         match Self::set_ref_status(h, new_status) {
             Ok(_) => (),
             Err(_e) => {
-                Self::deposit_event(RawEvent::ErrorSettingStatus2(u));
-                return Err("Did not set the status");
+                Self::deposit_event(RawEvent::ErrorSettingStatus2(u));
+                return Err("Did not set the status");
+            },
+        }
+        
+        <ReferenceOrderType<T>>::insert(&h, t);
+        <InvoiceDates<T>>::insert(&h, (<system::Module<T>>::block_number(), None::<T::BlockNumber>));
+        if n > 0i128 {
+            <InvoiceTotals<T>>::insert(&h, n as ComparisonAmounts);
+        }
+        Self::share_fees(&o, &p, &o, h);
+        
+        // Issue Event
+        Self::deposit_event(RawEvent::InvoiceIssued(u));
+        Ok(())
+    }
+    /// Invoices an express order (micro-order) at creation for the prefunded amount. The funds are locked for the beneficiary 
+    /// without its acceptance. The invoice is posted for both parties as any other invoice, so that the settlement clears it.
+    /// No postings are made, as the purchase and the sale are recognised when the invoice is settled.
+    fn send_express_invoice(o: T::AccountId, p: T::AccountId, h: T::Hash, t: OrderType, u: T::Hash) -> Result {
+        if Self::order_type_accounts(t).is_none() {
+            Self::deposit_event(RawEvent::ErrorOrderType(u));
+            return Err("Unknown order type");
+        }
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist2(u));
+                return Err("Error getting the hash data");
+            },
+        };
+        // Only a prefunding that has just been locked by the buyer for the seller can be invoiced
+        if owners.owner != p || owners.beneficiary != o || owners.lock != LockState::AwaitingAcceptance || Self::reference_status(&h) != STATUS_SUBMITTED {
+            Self::deposit_event(RawEvent::ErrorExpressOrder(u));
+            return Err("Not allowed for express orders");
+        }
+        let prefunded_amount: CurrencyBalanceOf<T> = match Self::prefunding(&h) {
+            Some(v) => v.0,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoPrefunding(u));
+                return Err("Error getting prefunding details from hash");
+            },
+        };
+        let amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>,AccountBalanceOf<T>>>::convert(prefunded_amount);
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(Self::invoice_keys(o, p, amount, h)) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting2(u));
+                return Err("There was an error posting to accounts");
             },
         }
+        <PrefundingHashOwner<T>>::insert(&h, PrefundingParties { lock: LockState::Accepted, ..owners });
+        Self::set_ref_status(h, STATUS_INVOICED)?;
+        <ReferenceOrderType<T>>::insert(&h, t);
+        <ExpressReference<T>>::insert(&h, true);
+        <InvoiceTotals<T>>::insert(&h, <T::PrefundingConversions as Convert<AccountBalanceOf<T>, u128>>::convert(amount));
+        <InvoiceDates<T>>::insert(&h, (<system::Module<T>>::block_number(), None::<T::BlockNumber>));
         
-        // Issue Event
         Self::deposit_event(RawEvent::InvoiceIssued(u));
         Ok(())
     }
     // Settles invoice by unlocking funds and updates various relevant accounts and pays prefunded amount
     fn settle_prefunded_invoice(o: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        Self::check_not_held(h, uid)?;
         
         // release state must be 11
         // sender must be owner
@@ -520,32 +2017,28 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         
         let payer: T::AccountId;
         let beneficiary: T::AccountId;
+        let posted_keys: Vec<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>;
+        // amount paid to the beneficiary and amount released to the payer
+        let settled_amounts: (ComparisonAmounts, ComparisonAmounts);
         
         match Self::get_release_state(h) {
-            (true, false)  => { // submitted, but not yet accepted
+            LockState::AwaitingAcceptance => {
                 Self::deposit_event(RawEvent::ErrorNotApproved2(h));
                 return Err("The demander has not approved the work yet!");
             },
-            (true, true) => {
+            LockState::Accepted => {
                 
                 // Validate that the hash is indeed owned by the buyer
                 match Self::check_ref_owner(o.clone(), h) {
                     true => {
                         // get beneficiary from hash
-                        // Initialise tuple with dummy values
-                        let mut details: (T::AccountId, UnLocked, T::AccountId, UnLocked) = (o.clone(), true, o.clone(), false); 
-                        match Self::prefunding_hash_owner(&h) {
-                            Some(v) => {
-                                details.0 = v.0.clone();
-                                details.1 = v.1.clone();
-                                details.2 = v.2.clone();
-                                details.3 = v.3.clone();
-                            },
+                        let details: PrefundingParties<T::AccountId> = match Self::prefunding_hash_owner(&h) {
+                            Some(v) => v,
                             None => {
                                 Self::deposit_event(RawEvent::ErrorNoDetails(uid));
                                 return Err("Error getting details from hash")
                             },
-                        }
+                        };
                         
                         // get prefunding amount for posting to accounts
                         let temp_balance: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<u64, CurrencyBalanceOf<T>>>::convert(0u64);
@@ -572,6 +2065,28 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                         let increase_amount: AccountBalanceOf<T> = amount;
                         let decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(to_invert);
                         
+                        // Credit notes have already reversed part of the invoice, so the purchase and the sale are recognised 
+                        // for the invoiced amount net of credits. The invoice cannot be settled for more than was prefunded.
+                        let prefunded: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(prefunded_amount);
+                        let (invoiced, settled): (ComparisonAmounts, ComparisonAmounts) = match (Self::invoiced_amount(h), Self::settlement_amount(h)) {
+                            (Some(i), Some(s)) if s <= prefunded => (i, s),
+                            _ => {
+                                Self::deposit_event(RawEvent::ErrorInvoiceAmount(uid));
+                                return Err("The invoice cannot be settled from the prefunded amount");
+                            },
+                        };
+                        let invoiced_amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(invoiced);
+                        let invoiced_increase_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(invoiced_amount);
+                        let invoiced_decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(invoiced_amount * -1);
+                        
+                        // Adjustments and credits have already reduced the payable and the receivable. Only the net invoiced amount is paid, 
+                        // and the rest of the prefunded amount is released back to the buyer.
+                        let net_amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(settled);
+                        let released: i128 = (to_invert * -1) - net_amount;
+                        let net_increase_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(net_amount);
+                        let net_decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(net_amount * -1);
+                        let released_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(released);
+                        
                         let current_block = <system::Module<T>>::block_number();
                         let current_block_dupe = <system::Module<T>>::block_number();
                         
@@ -580,48 +2095,65 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                         let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100050000000u64); // Credit decrease 110100050000000 Totem Runtime Deposit (Escrow)
                         let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600040000000u64); // Credit decrease 360600040000000 Escrowed Funds Control
                         let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600010000000u64); // Credit decrease 360600010000000 Purchase Control
-                        let account_5: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(250500120000013u64); // Debit  increase 250500120000013	Labour                        
+                        // Debit increase 250500120000013 Labour, or the cost account of the order type
+                        let (cost_account, income_account) = Self::order_type_accounts(Self::reference_order_type(&h)).unwrap_or((LABOUR, SALES_OF_SERVICES));
+                        let account_5: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(cost_account);
                         
                         // Seller
                         let account_6: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Debit  increase 110100040000000 XTX Balance
                         let account_7: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100090000000u64); // Credit decrease 110100090000000 Trade receivables - non-related parties
                         let account_8: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600020000000u64); // Credit decrease 360600020000000 Sales Control
-                        let account_9: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(240400010000000u64); // Credit increase 240400010000000 Sales of services
+                        // Credit increase 240400010000000 Sales of services (or the income account of the order type), 
+                        // or the income accounts of the revenue split.
+                        // A split that no longer adds up to the invoiced amount is ignored.
+                        let mut revenue: Vec<(u64, i128)> = Self::revenue_split(&h)
+                            .into_iter()
+                            .map(|s| (s.0, <T::PrefundingConversions as Convert<u128, i128>>::convert(s.1)))
+                            .collect();
+                        if revenue.iter().fold(0i128, |total, r| total.saturating_add(r.1)) != invoiced_amount {
+                            revenue = vec![(income_account, invoiced_amount)];
+                        }
 
                         // Keys for posting
                         // Buyer
                         let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(9);
-                        forward_keys.push((o.clone(), details.2.clone(), account_1, decrease_amount, false, h, current_block, current_block_dupe));           
-                        forward_keys.push((o.clone(), details.2.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));          
-                        forward_keys.push((o.clone(), details.2.clone(), account_3, decrease_amount, true, h, current_block, current_block_dupe));          
-                        forward_keys.push((o.clone(), details.2.clone(), account_4, decrease_amount, true, h, current_block, current_block_dupe));     
-                        forward_keys.push((o.clone(), details.2.clone(), account_5, increase_amount, false, h, current_block, current_block_dupe));
+                        forward_keys.push((o.clone(), details.beneficiary.clone(), account_1, net_decrease_amount, false, h, current_block, current_block_dupe));
+                        forward_keys.push((o.clone(), details.beneficiary.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));          
+                        forward_keys.push((o.clone(), details.beneficiary.clone(), account_3, decrease_amount, true, h, current_block, current_block_dupe));          
+                        forward_keys.push((o.clone(), details.beneficiary.clone(), account_4, invoiced_decrease_amount, true, h, current_block, current_block_dupe));
+                        forward_keys.push((o.clone(), details.beneficiary.clone(), account_5, invoiced_increase_amount, false, h, current_block, current_block_dupe));
+                        if released > 0 {
+                            forward_keys.push((o.clone(), details.beneficiary.clone(), account_6, released_amount, false, h, current_block, current_block_dupe));
+                        }
      
                         
                         // Seller
-                        forward_keys.push((details.2.clone(), o.clone(), account_6, increase_amount, false, h, current_block, current_block_dupe));   
-                        forward_keys.push((details.2.clone(), o.clone(), account_7, decrease_amount, true, h, current_block, current_block_dupe));  
-                        forward_keys.push((details.2.clone(), o.clone(), account_8, decrease_amount, true, h, current_block, current_block_dupe));  
-                        forward_keys.push((details.2.clone(), o.clone(), account_9, increase_amount, true, h, current_block, current_block_dupe));
-
-                        
-                        // Reversal keys in case of errors
-                        // Buyer
-                        let mut reversal_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(8);
-                        reversal_keys.push((o.clone(), details.2.clone(), account_1, decrease_amount, true, h, current_block, current_block_dupe));
-                        reversal_keys.push((o.clone(), details.2.clone(), account_2, increase_amount, false, h, current_block, current_block_dupe));
-                        reversal_keys.push((o.clone(), details.2.clone(), account_3, increase_amount, false, h, current_block, current_block_dupe));
-                        reversal_keys.push((o.clone(), details.2.clone(), account_4, increase_amount, false, h, current_block, current_block_dupe));
-                        reversal_keys.push((o.clone(), details.2.clone(), account_5, decrease_amount, true, h, current_block, current_block_dupe));
-                        
-                        // Seller
-                        reversal_keys.push((details.2.clone(), o.clone(), account_6, decrease_amount, true, h, current_block, current_block_dupe));
-                        reversal_keys.push((details.2.clone(), o.clone(), account_7, increase_amount, false, h, current_block, current_block_dupe));
-                        reversal_keys.push((details.2.clone(), o.clone(), account_8, increase_amount, false, h, current_block, current_block_dupe));
+                        forward_keys.push((details.beneficiary.clone(), o.clone(), account_6, net_increase_amount, false, h, current_block, current_block_dupe));   
+                        forward_keys.push((details.beneficiary.clone(), o.clone(), account_7, net_decrease_amount, true, h, current_block, current_block_dupe));
+                        forward_keys.push((details.beneficiary.clone(), o.clone(), account_8, invoiced_decrease_amount, true, h, current_block, current_block_dupe));
+                        for r in revenue.into_iter() {
+                            let account_9: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(r.0);
+                            let revenue_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128,AccountBalanceOf<T>>>::convert(r.1);
+                            forward_keys.push((details.beneficiary.clone(), o.clone(), account_9, revenue_amount, true, h, current_block, current_block_dupe));
+                        }
                         
-                        let track_rev_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(9);
+                        // Assignee (factoring). The sale remains with the seller, but the proceeds are passed on to the assignee
+                        // discharging the liability the seller has for the assigned receivables.
+                        let assignee: Option<T::AccountId> = Self::receivables_assignee(&h);
+                        let account_10: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200060000000u64); // Debit decrease 120200060000000 Liabilities from assigned receivables
+                        let account_11: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100100000000u64); // Credit decrease 110100100000000 Purchased receivables
+                        let account_12: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600060000000u64); // Credit decrease 360600060000000 Assigned Receivables Control
+                        if let Some(a) = assignee.clone() {
+                            forward_keys.push((details.beneficiary.clone(), a.clone(), account_6, net_decrease_amount, true, h, current_block, current_block_dupe));
+                            forward_keys.push((details.beneficiary.clone(), a.clone(), account_10, net_decrease_amount, false, h, current_block, current_block_dupe));
+                            forward_keys.push((details.beneficiary.clone(), a.clone(), account_12, net_decrease_amount, true, h, current_block, current_block_dupe));
+                            forward_keys.push((a.clone(), details.beneficiary.clone(), account_6, net_increase_amount, false, h, current_block, current_block_dupe));
+                            forward_keys.push((a.clone(), details.beneficiary.clone(), account_11, net_decrease_amount, true, h, current_block, current_block_dupe));
+                            forward_keys.push((a.clone(), details.beneficiary.clone(), account_12, net_decrease_amount, true, h, current_block, current_block_dupe));
+                        }
+
                         
-                        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone(),reversal_keys.clone(),track_rev_keys.clone()) {
+                        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone()) {
                             Ok(_) => (),
                             Err(_e) => {
                                 Self::deposit_event(RawEvent::ErrorInAccounting3(uid));
@@ -631,7 +2163,12 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                         
                         // export details for final payment steps
                         payer = o.clone();        
-                        beneficiary = details.2.clone();        
+                        beneficiary = details.beneficiary.clone();        
+                        posted_keys = forward_keys;
+                        settled_amounts = (
+                            <T::PrefundingConversions as Convert<AccountBalanceOf<T>, u128>>::convert(net_increase_amount),
+                            <T::PrefundingConversions as Convert<AccountBalanceOf<T>, u128>>::convert(released_amount),
+                        );
                         
                     },
                     false => {
@@ -641,21 +2178,21 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                 }
                 
             },
-            (false, true) => { // This state is not allowed for this functions
+            LockState::SenderApproved => { // This state is not allowed for this functions
                 Self::deposit_event(RawEvent::ErrorNotAllowed4(uid));
                 return Err("This function should not be used for this state")
             },
-            (false, false) => {
+            LockState::ReleasedToSender => {
                 // Owner has been given permission by beneficiary to release funds
                 Self::deposit_event(RawEvent::ErrorNotAllowed5(uid));
-                return Err("Funds locked for intended purpose by both parties.")
+                return Err("Funds have been released to the owner.")
                 
             },
         }
         
         // Set release lock "buyer who has approved invoice"
         // this may have been set independently, but is required for next step
-        match Self::set_release_state(payer.clone(), false, h.clone(), uid.clone()) {
+        match Self::set_release_state(payer.clone(), LockState::SenderApproved, h.clone(), uid.clone()) {
             Ok(_) => (),
             Err(_e) => {
                 Self::deposit_event(RawEvent::ErrorReleaseState(uid));
@@ -667,12 +2204,36 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         match Self::unlock_funds_for_beneficiary(beneficiary.clone(), h.clone(), uid.clone()) {
             Ok(_) => (),
             Err(_e) => {
+                // The funds were not paid, and remain locked. The settlement postings must be reversed out
+                // and the buyer's release state restored so that the settlement can be attempted again.
+                let mut compensating_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(posted_keys.len());
+                for k in posted_keys.iter() {
+                    let inverted: i128 = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, i128>>::convert(k.3) * -1;
+                    let amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(inverted);
+                    compensating_keys.push((k.0.clone(), k.1.clone(), k.2, amount, !k.4, k.5, k.6, k.7));
+                }
+                match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(compensating_keys, Vec::new(), ORIGIN_ADJUSTMENT) {
+                    Ok(_) => (),
+                    Err(_e) => {
+                        Self::deposit_event(RawEvent::ErrorInAccounting6(uid));
+                        return Err("An error occured reversing the settlement in accounts");
+                    },
+                }
+                <PrefundingHashOwner<T>>::mutate(&h, |owners| {
+                    if let Some(state) = owners {
+                        state.lock = LockState::Accepted;
+                    }
+                });
                 Self::deposit_event(RawEvent::ErrorUnlocking(uid));
                 return Err("Error unlocking for beneficiary")
             },
         }
         
+        Self::record_settlement_date(h, payer.clone(), beneficiary.clone());
+        Self::issue_settlement_certificate(h, payer.clone(), beneficiary.clone(), settled_amounts);
+        Self::share_fees(&o, &payer, &beneficiary, h);
         Self::deposit_event(RawEvent::InvoiceSettled(uid));
+        T::Settlement::on_settled(h, payer, beneficiary, settled_amounts.0, settled_amounts.1);
         Ok(())
     }
     /// check owner (of hash) - if anything fails then returns false
@@ -680,7 +2241,7 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         let mut answer: bool = false;
         match Self::prefunding_hash_owner(&h) {
             Some(owners) => {
-                if Some(owners.0) == Some(o) { 
+                if Some(owners.owner) == Some(o) { 
                     answer = true; 
                 } else { 
                     (); 
@@ -690,135 +2251,72 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
         };
         return answer;
     }
-    /// Sets the release state by the owner or the beneficiary is only called when something already exists
-    fn set_release_state(o: T::AccountId, o_lock: UnLocked, h: T::Hash, uid: T::Hash) -> Result {
-        // 0= false, 1=true
-        // 10, sender can take after deadline (initial state)
-        // 11, accepted by recipient. (funds locked, nobody can take) 
-        // 01, sender approves (recipient can take, or refund)
-        // 00, only the recipient authorises sender to retake funds regardless of deadline.
-        
-        // Initialise new tuple with some dummy values
-        let mut change: (T::AccountId, UnLocked, T::AccountId, UnLocked) = (o.clone(), false, o.clone(), false);
-        
-        match Self::prefunding_hash_owner(&h) {
-            Some(state_lock) => {
-                let locks: (UnLocked, UnLocked) = (state_lock.1, state_lock.3);
-                change.0 = state_lock.0.clone();
-                change.2 = state_lock.2.clone();
-                let commander = state_lock.0.clone();
-                let fulfiller = state_lock.2.clone();
-                
-                match locks {
-                    (true,false) => {
-                        // In this state the commander has created the lock, but it has not been accepted.
-                        // The commander can withdraw the lock (set to false) if the deadline has passed, or 
-                        // the fulfiller can accept the order (set to true) 
-                        match o_lock {
-                            true => {
-                                if o == commander {
-                                    Self::deposit_event(RawEvent::ErrorWrongState1(uid));
-                                    return Err("Error buyer cannot set true");
-                                } else if o == fulfiller {
-                                    change.1 = state_lock.1;
-                                    change.3 = o_lock;
-                                } else {
-                                    Self::deposit_event(RawEvent::ErrorLockNotAllowed1(uid));
-                                    return Err("Error not buyer or seller");
-                                };
-                            },
-                            false => {
-                                // We do care if the deadline has passed IF this is the commander calling directly
-                                // but that must be handled outside of this function
-                                if o == commander {
-                                    change.1 = o_lock;
-                                    change.3 = state_lock.3;
-                                } else if o == fulfiller {
-                                    Self::deposit_event(RawEvent::ErrorWrongState2(uid));
-                                    return Err("Error fulfiller cannot set false");
-                                } else {
-                                    Self::deposit_event(RawEvent::ErrorLockNotAllowed2(uid));
-                                    return Err("Error not buyer or seller");
-                                };
-                            },
-                        }
-                    },
-                    (true,true) => {
-                        // In this state the commander can change the lock, and they can only change it to false
-                        // In this state the fulfiller can change the lock, and they can only change it to false
-                        match o_lock {
-                            true => {
-                                Self::deposit_event(RawEvent::ErrorWrongState3(uid));
-                                return Err("Cannot set a lock");
-                            },
-                            false => {
-                                if o == commander {
-                                    change.1 = o_lock;
-                                    change.3 = state_lock.3;
-                                } else if o == fulfiller {
-                                    change.1 = state_lock.1;
-                                    change.3 = o_lock;
-                                } else {
-                                    Self::deposit_event(RawEvent::ErrorLockNotAllowed3(uid));
-                                    return Err("Error not buyer or seller");
-                                };
-                            },
-                        }
-                    },
-                    (false,true) => {
-                        // In this state the commander cannot change the lock
-                        // In this state the fulfiller can change the lock, and they can only change it to false
-                        match o_lock {
-                            true => {
-                                Self::deposit_event(RawEvent::ErrorLockNotAllowed4(uid));
-                                return Err("Error not buyer or seller");
-                            },
-                            false => {
-                                if o == commander {
-                                    Self::deposit_event(RawEvent::ErrorWrongState5(uid));
-                                    return Err("Error seller cannot set false");
-                                } else if o == fulfiller {
-                                    change.1 = state_lock.1;
-                                    change.3 = o_lock;
-                                } else {
-                                    Self::deposit_event(RawEvent::ErrorLockNotAllowed5(uid));
-                                    return Err("Error not buyer or seller");
-                                };
-                            },
-                        }
-                        
-                    },
-                    (false,false) => {
-                        // This state should technically make the funds refundable to the buyer. 
-                        // Even if the buy wanted to set this state they cannot. Meaning they must create a new order.
-                        Self::deposit_event(RawEvent::ErrorLockNotAllowed6(uid));
-                        return Err("Error nobody can change this state");
-                    },
-                }
-                
-            },
+    /// Sets the release state by the owner or the beneficiary is only called when something already exists.
+    /// The lock state can only move:
+    /// * from AwaitingAcceptance to Accepted, by the beneficiary before the deadline or within the grace period
+    /// * from AwaitingAcceptance to ReleasedToSender, by the owner. The deadline must be checked by the caller
+    /// * from Accepted to SenderApproved, by the owner
+    /// * from Accepted back to AwaitingAcceptance, by the beneficiary withdrawing the acceptance
+    /// * from SenderApproved to ReleasedToSender, by the beneficiary
+    /// Nobody can change ReleasedToSender, the owner must create a new prefunding.
+    fn set_release_state(o: T::AccountId, state: LockState, h: T::Hash, uid: T::Hash) -> Result {
+        let mut parties: PrefundingParties<T::AccountId> = match Self::prefunding_hash_owner(&h) {
+            Some(parties) => parties,
             None => {
                 Self::deposit_event(RawEvent::ErrorHashDoesNotExist2(uid));
                 return Err("Error getting the hash data");
-                
             },
         };
+        let by_owner: bool = o == parties.owner;
+        let by_beneficiary: bool = o == parties.beneficiary;
+        if !by_owner && !by_beneficiary {
+            Self::deposit_event(RawEvent::ErrorLockNotAllowed1(uid));
+            return Err("Error not buyer or seller");
+        }
+        if parties.lock == LockState::ReleasedToSender {
+            Self::deposit_event(RawEvent::ErrorWrongState2(uid));
+            return Err("Error nobody can change this state");
+        }
+        let allowed: bool = match (parties.lock, state) {
+            (LockState::AwaitingAcceptance, LockState::Accepted) => by_beneficiary,
+            (LockState::AwaitingAcceptance, LockState::ReleasedToSender) => by_owner,
+            (LockState::Accepted, LockState::SenderApproved) => by_owner,
+            (LockState::Accepted, LockState::AwaitingAcceptance) => by_beneficiary,
+            (LockState::SenderApproved, LockState::ReleasedToSender) => by_beneficiary,
+            _ => false,
+        };
+        if !allowed {
+            Self::deposit_event(RawEvent::ErrorWrongState1(uid));
+            return Err("This change of the lock state is not allowed");
+        }
+        // accepted by the recipient after the deadline, within the grace period
+        let mut late_acceptance: bool = false;
+        if state == LockState::Accepted {
+            if !Self::acceptance_open(h) {
+                Self::deposit_event(RawEvent::ErrorAcceptanceClosed(uid));
+                return Err("The deadline for accepting has passed");
+            }
+            late_acceptance = Self::prefund_deadline_passed(h);
+        }
         
-        <PrefundingHashOwner<T>>::remove(&h);
-        <PrefundingHashOwner<T>>::insert(&h, change);
+        parties.lock = state;
+        <PrefundingHashOwner<T>>::insert(&h, parties.clone());
+        Self::share_fees(&o, &parties.owner, &parties.beneficiary, h);
         
         // Issue event
         Self::deposit_event(RawEvent::PrefundingLockSet(uid));
+        if late_acceptance {
+            Self::deposit_event(RawEvent::PrefundingAcceptedLate(h, parties.beneficiary));
+        }
         
         Ok(())
-        
     }
     /// check beneficiary (of hash reference)
     fn check_ref_beneficiary(o: T::AccountId, h: T::Hash) -> bool {
         let mut answer: bool = false;
         match Self::prefunding_hash_owner(&h) {
             Some(owners) => {
-                if owners.2 == o { 
+                if owners.beneficiary == o { 
                     answer = true;
                 } else { 
                     (); 
@@ -830,16 +2328,20 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
     } 
     /// unlock for owner
     fn unlock_funds_for_owner(o: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        Self::check_not_held(h, uid)?;
+        // Captured before the prefunding is removed, for the post settlement hook
+        let beneficiary: Option<T::AccountId> = Self::prefunding_hash_owner(&h).map(|owners| owners.beneficiary);
+        let amount: ComparisonAmounts = Self::prefunding(&h).map_or(0u128, |p| <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(p.0));
         match Self::reference_valid(h) {
             true => {
                 match Self::check_ref_owner(o.clone(), h) {
                     true => {
                         match Self::get_release_state(h) {
-                            (true, false)  => { // submitted, but not yet accepted
+                            LockState::AwaitingAcceptance => {
                                 // Check if the dealine has passed. If not funds cannot be release
                                 match Self::prefund_deadline_passed(h) {
                                     true => {
-                                        let status: Status = 50; // Abandoned or Cancelled
+                                        let status: Status = STATUS_EXPIRED;
                                         match Self::cancel_prefunding_lock(o.clone(), h, status) {
                                             Ok(_) => (),
                                             Err(_e) => {
@@ -854,17 +2356,21 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                                     },
                                 }
                             },
-                            (true, true) => {
+                            LockState::Accepted => {
                                 Self::deposit_event(RawEvent::ErrorFundsInPlay2(uid));
                                 return Err("Funds locked for intended purpose by both parties.")
                             },
-                            (false, true) => {
+                            LockState::SenderApproved => {
                                 Self::deposit_event(RawEvent::ErrorNotAllowed6(uid));
                                 return Err("Funds locked for beneficiary.")
                             },
-                            (false, false) => {
+                            LockState::ReleasedToSender => {
                                 // Owner has been  given permission by beneficiary to release funds
-                                let status:  Status = 50; // Abandoned or cancelled
+                                // The seller has withdrawn from the order, so its performance bond (if any) is released
+                                if Self::performance_bond(&h).is_some() {
+                                    Self::release_performance_bond(h, uid)?;
+                                }
+                                let status:  Status = STATUS_SELLER_REJECTED;
                                 match Self::cancel_prefunding_lock(o.clone(), h, status) {
                                     Ok(_) => (),
                                     Err(_e) => {
@@ -886,6 +2392,284 @@ impl<T: Trait> Encumbrance<T::AccountId,T::Hash,T::BlockNumber> for Module<T> {
                 return Err("Hash does not exist!");
             }, 
         }      
+        if let Some(b) = beneficiary {
+            Self::share_fees(&o, &o, &b, h);
+            T::Settlement::on_cancelled(h, o, b, amount);
+        }
+        Ok(())
+    }
+    /// Returns the funds of an accepted reference that has not been invoiced to the owner when the beneficiary has defaulted.
+    /// Only the beneficiary can release accepted funds otherwise, so the lock moves to ReleasedToSender and the prefunding
+    /// is cancelled in the same call. The caller checks the default (for example the due date of the order).
+    fn release_on_default(o: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        Self::check_not_held(h, uid)?;
+        let mut owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist3(uid));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.owner != o {
+            Self::deposit_event(RawEvent::ErrorNotOwner2(uid));
+            return Err("You are not the owner of the hash!");
+        }
+        if owners.lock != LockState::Accepted || Self::reference_status(&h) != STATUS_SUBMITTED {
+            Self::deposit_event(RawEvent::ErrorWrongState1(uid));
+            return Err("Only accepted funds that have not been invoiced can be released on default");
+        }
+        let amount: ComparisonAmounts = Self::prefunding(&h).map_or(0u128, |p| <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(p.0));
+        // The defaulted order will not be settled, the assignment of its receivables is reversed
+        if let Some(a) = Self::receivables_assignee(&h) {
+            Self::post_assignment(owners.beneficiary.clone(), a, h, Self::assigned_amount(h, uid)?, true, uid)?;
+        }
+        owners.lock = LockState::ReleasedToSender;
+        <PrefundingHashOwner<T>>::insert(&h, owners.clone());
+        Self::cancel_prefunding_lock(o.clone(), h, STATUS_SELLER_DEFAULTED)?;
+        T::Settlement::on_cancelled(h, o, owners.beneficiary, amount);
+        Ok(())
+    }
+    /// Cancels a prefunding that has just been locked, when the order it was locked for cannot be created.
+    /// Only a prefunding the seller has not accepted or been invoiced for can be cancelled.
+    fn cancel_prefunding(o: T::AccountId, h: T::Hash, uid: T::Hash) -> Result {
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist3(uid));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.owner != o {
+            Self::deposit_event(RawEvent::ErrorNotOwner2(uid));
+            return Err("You are not the owner of the hash!");
+        }
+        if owners.lock != LockState::AwaitingAcceptance || Self::reference_status(&h) != STATUS_SUBMITTED {
+            Self::deposit_event(RawEvent::ErrorWrongState1(uid));
+            return Err("Only prefundings awaiting acceptance can be cancelled");
+        }
+        let amount: ComparisonAmounts = Self::prefunding(&h).map_or(0u128, |p| <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(p.0));
+        Self::cancel_prefunding_lock(o.clone(), h, STATUS_CANCELLED)?;
+        T::Settlement::on_cancelled(h, o, owners.beneficiary, amount);
+        Ok(())
+    }
+    /// Locks a performance bond from the seller when the order is accepted. Updates the accounts of both parties.
+    /// The bond has no deadline, it remains locked until it is either released or forfeited.
+    fn lock_performance_bond(o: T::AccountId, p: T::AccountId, amount: u128, h: T::Hash, uid: T::Hash) -> Result {
+        if <PerformanceBond<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorBondExists(uid));
+            return Err("A performance bond already exists for this reference");
+        }
+        
+        // The same minimum balance rule as for prefunding applies
+        let min_balance: ComparisonAmounts =  1618u128;
+        let current_balance: ComparisonAmounts = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(T::Currency::free_balance(&o));
+        let minimum_amount: ComparisonAmounts = min_balance + amount;
+        if current_balance < minimum_amount {
+            Self::deposit_event(RawEvent::ErrorInsufficientPreFunds(o, amount, minimum_amount, current_balance));
+            return Err("Not enough funds for the performance bond");
+        }
+        
+        let amount_converted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(amount);
+        let bond_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(amount_converted);
+        let posting_amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(amount);
+        
+        let bond_id: LockIdentifier = match Self::assign_lock_id(&o, BOND_LOCK_NAMESPACE, h) {
+            Ok(id) => id,
+            Err(e) => {
+                Self::deposit_event(RawEvent::ErrorLockId(uid));
+                return Err(e);
+            },
+        };
+        
+        if let Err(e) = Self::account_for_performance_bond(o.clone(), p.clone(), posting_amount, h, uid) {
+            Self::release_lock_id(&o, BOND_LOCK_NAMESPACE, h);
+            return Err(e);
+        }
+        
+        T::Currency::set_lock(bond_id, &o, bond_amount, T::BlockNumber::max_value(), WithdrawReason::Reserve.into());
+        <PerformanceBond<T>>::insert(&h, (o.clone(), p.clone(), bond_amount));
+        
+        Self::deposit_event(RawEvent::PerformanceBondLocked(h, o, p, amount));
+        Ok(())
+    }
+    /// Releases the performance bond back to the seller (normally at settlement). Reverses the accounts of both parties.
+    fn release_performance_bond(h: T::Hash, uid: T::Hash) -> Result {
+        let bond = match Self::performance_bond(&h) {
+            Some(b) => b,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoBond(uid));
+                return Err("There is no performance bond for this reference");
+            },
+        };
+        let amount: u128 = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(bond.2);
+        let posting_amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(amount) * -1;
+        
+        Self::account_for_performance_bond(bond.0.clone(), bond.1.clone(), posting_amount, h, uid)?;
+        
+        T::Currency::remove_lock(Self::get_bond_id(h), &bond.0);
+        Self::release_lock_id(&bond.0, BOND_LOCK_NAMESPACE, h);
+        <PerformanceBond<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::PerformanceBondReleased(h, bond.0, amount));
+        Ok(())
+    }
+    /// Credit note by the beneficiary against an invoice before settlement. Reverses the invoice postings of both parties 
+    /// for the credited amount. Credits and adjustments together cannot exceed the invoiced amount.
+    fn credit_invoice(o: T::AccountId, h: T::Hash, amount: u128, reason: T::Hash, uid: T::Hash) -> Result {
+        let owners = match Self::prefunding_hash_owner(&h) {
+            Some(owners) => owners,
+            None => {
+                Self::deposit_event(RawEvent::ErrorHashDoesNotExist6(uid));
+                return Err("Hash does not exist!");
+            },
+        };
+        if owners.beneficiary != o {
+            Self::deposit_event(RawEvent::ErrorNotAllowed9(uid));
+            return Err("Not the beneficiary");
+        }
+        if Self::express_reference(&h) {
+            Self::deposit_event(RawEvent::ErrorExpressOrder(uid));
+            return Err("Not allowed for express orders");
+        }
+        if <ReferenceStatus<T>>::get(&h) != STATUS_INVOICED {
+            Self::deposit_event(RawEvent::ErrorNotInvoiced(uid));
+            return Err("Only allowed when status is Invoiced");
+        }
+        if <ReceivablesAssignee<T>>::exists(&h) {
+            Self::deposit_event(RawEvent::ErrorReceivablesAssigned(uid));
+            return Err("The receivables have been assigned");
+        }
+        let invoiced: ComparisonAmounts = match Self::invoice_cap(h) {
+            Some(invoiced) => invoiced,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoPrefunding3(uid));
+                return Err("Error getting prefunding details from hash");
+            },
+        };
+        let new_total: Option<ComparisonAmounts> = Self::invoice_credit_total(h)
+            .checked_add(Self::invoice_adjustment_total(h))
+            .and_then(|t| t.checked_add(amount));
+        match new_total {
+            Some(t) if amount > 0 && t <= invoiced => (),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorCreditTooLarge(uid));
+                return Err("Credit is larger than the invoice");
+            },
+        }
+        
+        let decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(<T::PrefundingConversions as Convert<u128, i128>>::convert(amount) * -1);
+        
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Seller
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100090000000u64); // Credit decrease 110100090000000 Trade receivables - non-related parties
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600020000000u64); // Credit decrease 360600020000000 Sales Control
+        
+        // Buyer
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(120200030000000u64); // Debit  decrease 120200030000000 Accounts payable (Trade creditors)
+        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(360600010000000u64); // Credit decrease 360600010000000 Purchase Control
+        
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(4);
+        forward_keys.push((o.clone(), owners.owner.clone(), account_1, decrease_amount, true, h, current_block, current_block_dupe));
+        forward_keys.push((o.clone(), owners.owner.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));
+        forward_keys.push((owners.owner.clone(), o.clone(), account_3, decrease_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((owners.owner.clone(), o.clone(), account_4, decrease_amount, true, h, current_block, current_block_dupe));
+        
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(forward_keys.clone(), Vec::new(), ORIGIN_ADJUSTMENT) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting8(uid));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        
+        <InvoiceCredits<T>>::mutate(&h, |invoice_credits| invoice_credits.push((amount, reason)));
+        // The invoiced amount has changed, so the revenue split has to be set again
+        <RevenueSplit<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::InvoiceCredited(h, o, amount, reason));
+        Ok(())
+    }
+    /// Forfeits the performance bond (in part or in full) to the buyer on seller default.
+    /// The forfeited amount is paid to the buyer and the remainder of the bond is released to the seller.
+    fn forfeit_performance_bond(h: T::Hash, forfeit: u128, uid: T::Hash) -> Result {
+        let bond = match Self::performance_bond(&h) {
+            Some(b) => b,
+            None => {
+                Self::deposit_event(RawEvent::ErrorNoBond2(uid));
+                return Err("There is no performance bond for this reference");
+            },
+        };
+        let amount: u128 = <T::PrefundingConversions as Convert<CurrencyBalanceOf<T>, u128>>::convert(bond.2);
+        if forfeit > amount {
+            Self::deposit_event(RawEvent::ErrorForfeitTooLarge(uid));
+            return Err("Cannot forfeit more than the bond");
+        }
+        let forfeit_converted: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<u128, AccountBalanceOf<T>>>::convert(forfeit);
+        let forfeit_amount: CurrencyBalanceOf<T> = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, CurrencyBalanceOf<T>>>::convert(forfeit_converted);
+        
+        // Remove the bond from the books of both parties
+        let posting_amount: i128 = <T::PrefundingConversions as Convert<u128, i128>>::convert(amount) * -1;
+        let mut forward_keys = Self::performance_bond_keys(bond.0.clone(), bond.1.clone(), posting_amount, h);
+        
+        // Record the penalty for both parties
+        let increase_amount: AccountBalanceOf<T> = forfeit_converted;
+        let decrease_amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(<T::PrefundingConversions as Convert<u128, i128>>::convert(forfeit) * -1);
+        
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+        
+        // Seller
+        let account_1: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(250500400000000u64); // Debit  increase 250500400000000 Contractual penalties
+        let account_2: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Credit decrease 110100040000000 XTX Balance
+        
+        // Buyer
+        let account_3: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Debit  increase 110100040000000 XTX Balance
+        let account_4: AccountOf<T> = <T::PrefundingConversions as Convert<u64, AccountOf<T>>>::convert(240400070000000u64); // Credit increase 240400070000000 Income from contractual penalties
+        
+        forward_keys.push((bond.0.clone(), bond.1.clone(), account_1, increase_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((bond.0.clone(), bond.1.clone(), account_2, decrease_amount, true, h, current_block, current_block_dupe));
+        forward_keys.push((bond.1.clone(), bond.0.clone(), account_3, increase_amount, false, h, current_block, current_block_dupe));
+        forward_keys.push((bond.1.clone(), bond.0.clone(), account_4, increase_amount, true, h, current_block, current_block_dupe));
+        
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone()) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting5(uid));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        
+        // Unlock the bond and pay the forfeited amount to the buyer. If the transfer fails the bond is locked again 
+        // and the postings are reversed out.
+        let bond_id = Self::get_bond_id(h);
+        T::Currency::remove_lock(bond_id, &bond.0);
+        match T::Currency::transfer(&bond.0, &bond.1, forfeit_amount) {
+            Ok(_) => (),
+            Err(_) => {
+                T::Currency::set_lock(bond_id, &bond.0, bond.2, T::BlockNumber::max_value(), WithdrawReason::Reserve.into());
+                let mut compensating_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(forward_keys.len());
+                for k in forward_keys.iter() {
+                    let inverted: i128 = <T::PrefundingConversions as Convert<AccountBalanceOf<T>, i128>>::convert(k.3) * -1;
+                    let amount: AccountBalanceOf<T> = <T::PrefundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(inverted);
+                    compensating_keys.push((k.0.clone(), k.1.clone(), k.2, amount, !k.4, k.5, k.6, k.7));
+                }
+                match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(compensating_keys, Vec::new(), ORIGIN_ADJUSTMENT) {
+                    Ok(_) => (),
+                    Err(_e) => {
+                        Self::deposit_event(RawEvent::ErrorInAccounting5(uid));
+                        return Err("An error occured reversing the forfeited bond in accounts");
+                    },
+                }
+                Self::deposit_event(RawEvent::ErrorBondTransfer(uid));
+                return Err("Error during transfer");
+            },
+        }
+        Self::release_lock_id(&bond.0, BOND_LOCK_NAMESPACE, h);
+        <PerformanceBond<T>>::remove(&h);
+        
+        Self::deposit_event(RawEvent::PerformanceBondForfeited(h, bond.0, bond.1, forfeit, amount - forfeit));
         Ok(())
     }
 }
@@ -895,38 +2679,83 @@ decl_event!(
     where
     AccountId = <T as system::Trait>::AccountId,
     Hash = <T as system::Trait>::Hash,
+    BlockNumber = <T as system::Trait>::BlockNumber,
     ComparisonAmounts = u128,
     {
         PrefundingCancelled(AccountId, Hash),
+        /// Owner, reference. Released by the seller.
+        PrefundingRejectedBySeller(AccountId, Hash),
+        /// Owner, reference. Not accepted by the deadline.
+        PrefundingExpired(AccountId, Hash),
+        /// Owner, reference. Refunded by arbitration.
+        PrefundingArbitrated(AccountId, Hash),
+        /// Owner, reference. Returned to the owner on default of the seller.
+        PrefundingReleasedOnDefault(AccountId, Hash),
+        /// Account, lock id of the orphaned lock released
+        OrphanedLockReleased(AccountId, LockIdentifier),
+        /// Pairs scanned, orphaned locks released
+        OrphanedLocksScanned(u32, u32),
+        /// Locks moved to namespaced lock ids
+        LockIdsMigrated(u32),
+        /// Number of prefunds added to the deadlines index
+        DeadlinesIndexMigrated(u32),
+        /// Proposer, counterparty, minimum deadline (blocks)
+        TrustAgreementProposed(AccountId, AccountId, u64),
+        /// Identity completing the agreement, counterparty, minimum deadline (blocks)
+        TrustAgreementMade(AccountId, AccountId, u64),
+        /// Identity ending the agreement, counterparty
+        TrustAgreementRevoked(AccountId, AccountId),
+        /// Invalid trust agreement
+        ErrorTrustAgreement(AccountId),
+        /// Reference, fee sharing rule
+        FeeSharingSet(Hash, u8),
+        /// Unknown fee sharing rule, or not allowed to set it
+        ErrorFeeSharing(Hash),
+        /// Smallest amount that can be prefunded
+        MinimumPrefundSet(ComparisonAmounts),
+        /// Largest amount that can be prefunded or ordered (zero when there is no limit)
+        MaximumPrefundSet(ComparisonAmounts),
+        /// Reference, beneficiary, proforma amount
+        ProformaProposed(Hash, AccountId, ComparisonAmounts),
+        /// Reference, owner, agreed invoice amount
+        ProformaAccepted(Hash, AccountId, ComparisonAmounts),
+        /// Reference, buyer, beneficiary, assignee
+        ReceivablesAssigned(Hash, AccountId, AccountId, AccountId),
+        /// Reference, beneficiary, adjustment amount, reason
+        InvoiceAdjusted(Hash, AccountId, ComparisonAmounts, Hash),
+        /// Reference, beneficiary, credited amount, reason
+        InvoiceCredited(Hash, AccountId, ComparisonAmounts, Hash),
+        /// Reference, beneficiary
+        RevenueSplitSet(Hash, AccountId),
+        /// Reference, seller, buyer, bond amount
+        PerformanceBondLocked(Hash, AccountId, AccountId, ComparisonAmounts),
+        /// Reference, seller, bond amount
+        PerformanceBondReleased(Hash, AccountId, ComparisonAmounts),
+        /// Reference, seller, buyer, forfeited amount, amount released to seller
+        PerformanceBondForfeited(Hash, AccountId, AccountId, ComparisonAmounts, ComparisonAmounts),
         PrefundingLockSet(Hash),
         PrefundingCompleted(Hash),
+        /// Owner, threshold
+        DualControlSet(AccountId, ComparisonAmounts),
+        /// Owner, reference, expiry block of the co-approval
+        PrefundingPendingCoSign(AccountId, Hash, BlockNumber),
+        /// Co-signatory, reference
+        PrefundingCoApproved(AccountId, Hash),
+        /// Owner or co-signatory, reference
+        PendingPrefundingRejected(AccountId, Hash),
+        /// The co-approval window of a pending prefund closed without co-approval
+        PendingPrefundingExpired(Hash),
         InvoiceIssued(Hash),
         InvoiceSettled(Hash),
         /// You are not the owner or the beneficiary
         ErrorLockNotAllowed1(Hash),
-        /// You are not the owner or the beneficiary
-        ErrorLockNotAllowed2(Hash),
-        /// You are not the owner or the beneficiary
-        ErrorLockNotAllowed3(Hash),
-        /// You are not the owner or the beneficiary
-        ErrorLockNotAllowed4(Hash),
-        /// You are not the owner or the beneficiary
-        ErrorLockNotAllowed5(Hash),
-        /// You are not the owner or the beneficiary
-        ErrorLockNotAllowed6(Hash),
         /// Not enough funds to prefund
         ErrorInsufficientPreFunds(AccountId, ComparisonAmounts, ComparisonAmounts, ComparisonAmounts),
-        /// Cannot set this state
+        /// This change of the lock state is not allowed
         ErrorWrongState1(Hash),
-        /// Cannot set this state
+        /// Funds have been released to the owner, nobody can change the lock state
         ErrorWrongState2(Hash),
-        /// Cannot set this state
-        ErrorWrongState3(Hash),
-        /// Cannot set this state
-        ErrorWrongState4(Hash),
-        /// Cannot set this state
-        ErrorWrongState5(Hash),
-        /// Funds already locked for intended purpose by both parties.
+        /// Funds have been released to the owner.
         ErrorNotAllowed1(Hash),
         /// Not the beneficiary
         ErrorNotAllowed2(Hash),
@@ -934,7 +2763,7 @@ decl_event!(
         ErrorNotAllowed3(Hash),
         /// This function should not be used for this state
         ErrorNotAllowed4(Hash),
-        /// Funds locked for intended purpose by both parties.
+        /// Funds have been released to the owner.
         ErrorNotAllowed5(Hash),
         /// Funds locked for beneficiary.
         ErrorNotAllowed6(Hash),
@@ -960,7 +2789,7 @@ decl_event!(
         ErrorHashDoesNotExist2(Hash),
         /// Hash does not exist
         ErrorHashDoesNotExist3(Hash),
-        /// Deadline is too short! Must be at least 48 hours
+        /// Deadline is too short! Must be at least 48 hours or the minimum agreed by the parties
         ErrorShortDeadline(Hash),
         /// Deposit was not taken
         ErrorPrefundNotSet(Hash),
@@ -988,5 +2817,700 @@ decl_event!(
         ErrorCancelFailed(Hash),
         /// Cancelling prefunding failed for some reason
         ErrorCancelFailed2(Hash),
+        /// Not the beneficiary - assign receivables
+        ErrorNotAllowed7(Hash),
+        /// The assignee cannot be the buyer or the beneficiary
+        ErrorAssigneeNotAllowed(Hash),
+        /// The order has not been accepted yet
+        ErrorNotAccepted(Hash),
+        /// Hash does not exist
+        ErrorHashDoesNotExist4(Hash),
+        /// A performance bond already exists for this reference
+        ErrorBondExists(Hash),
+        /// There is no performance bond for this reference - release
+        ErrorNoBond(Hash),
+        /// There is no performance bond for this reference - forfeit
+        ErrorNoBond2(Hash),
+        /// Cannot forfeit more than the bond
+        ErrorForfeitTooLarge(Hash),
+        /// Error during transfer of the forfeited bond
+        ErrorBondTransfer(Hash),
+        /// An error occured posting to accounts - performance bond
+        ErrorInAccounting4(Hash),
+        /// An error occured posting to accounts - forfeited performance bond
+        ErrorInAccounting5(Hash),
+        /// An error occured reversing the settlement in accounts after a failed payment
+        ErrorInAccounting6(Hash),
+        /// The transfer to the beneficiary failed. The funds remain locked
+        ErrorTransferFailed(Hash),
+        /// Hash does not exist - invoice adjustment
+        ErrorHashDoesNotExist5(Hash),
+        /// Not the beneficiary - invoice adjustment
+        ErrorNotAllowed8(Hash),
+        /// Only allowed when status is Invoiced
+        ErrorNotInvoiced(Hash),
+        /// Error getting prefunding details - invoice adjustment
+        ErrorNoPrefunding2(Hash),
+        /// Adjustment is larger than the invoice
+        ErrorAdjustmentTooLarge(Hash),
+        /// An error occured posting to accounts - invoice adjustment
+        ErrorInAccounting7(Hash),
+        /// Hash does not exist - credit note
+        ErrorHashDoesNotExist6(Hash),
+        /// Not the beneficiary - credit note
+        ErrorNotAllowed9(Hash),
+        /// Error getting prefunding details - credit note
+        ErrorNoPrefunding3(Hash),
+        /// Credit is larger than the invoice
+        ErrorCreditTooLarge(Hash),
+        /// An error occured posting to accounts - credit note
+        ErrorInAccounting8(Hash),
+        /// Prefunding above the dual control threshold must be co-approved
+        ErrorCoSigningRequired(Hash),
+        /// Invalid dual control parameters
+        ErrorDualControl(),
+        /// No prefunding is pending co-approval
+        ErrorNoPendingPrefunding(Hash),
+        /// Not an authorised co-signatory
+        ErrorNotCoSignatory(Hash),
+        /// The co-approval window has passed
+        ErrorCoApprovalExpired(Hash),
+        /// Hash does not exist - revenue split
+        ErrorHashDoesNotExist7(Hash),
+        /// Not the beneficiary - revenue split
+        ErrorNotAllowed10(Hash),
+        /// Splits must be distinct income accounts with an amount
+        ErrorRevenueSplit(Hash),
+        /// Splits do not add up to the invoiced amount
+        ErrorRevenueSplitTotal(Hash),
+        /// Unknown order type
+        ErrorOrderType(Hash),
+        /// Reference, recipient. Accepted after the deadline within the grace period
+        PrefundingAcceptedLate(Hash, AccountId),
+        /// The deadline (and grace period) for accepting has passed
+        ErrorAcceptanceClosed(Hash),
+        /// Not allowed for express orders
+        ErrorExpressOrder(Hash),
+        /// Too many lock candidates
+        ErrorTooManyLockCandidates(),
+        /// Too many references to migrate
+        ErrorTooManyLockMigrations(),
+        /// Too many references to add to the deadlines index in one call
+        ErrorTooManyDeadlineMigrations(),
+        /// The bucket of the deadline in the deadlines index is full
+        ErrorDeadlineBucketFull(Hash),
+        /// Unable to derive an unused lock id
+        ErrorLockId(Hash),
+        /// Prefund amount is zero or below the minimum
+        ErrorPrefundAmount(Hash),
+        /// Prefund amount is above the network maximum
+        ErrorAboveMaximum(Hash),
+        /// There is no proforma, or the reference is not open for a proforma
+        ErrorProforma(Hash),
+        /// Invoice amount is zero or out of range
+        ErrorInvoiceAmount(Hash),
+        /// The invoice would exceed the enforced credit limit of the seller for the buyer
+        ErrorCreditLimit(Hash),
+        /// Only prefundings that have not been invoiced can be refunded by arbitration
+        ErrorArbitration(Hash),
+        /// Reference, new amount, new deadline
+        PrefundingAdjusted(Hash, ComparisonAmounts, BlockNumber),
+        /// Reference, certificate id
+        SettlementCertificateIssued(Hash, Hash),
+        /// Hash does not exist - prefunding adjustment
+        ErrorHashDoesNotExist8(Hash),
+        /// You are not the owner of the hash! - prefunding adjustment
+        ErrorNotOwner3(Hash),
+        /// Only submitted prefundings awaiting acceptance can be adjusted
+        ErrorAdjustPrefunding(Hash),
+        /// Error getting prefunding details - prefunding adjustment
+        ErrorNoPrefunding4(Hash),
+        /// An error occured posting to accounts - prefunding adjustment
+        ErrorInAccounting9(Hash),
+        /// The reference is under a compliance hold
+        ErrorComplianceHold(Hash),
+        /// The receivables have already been assigned
+        ErrorAlreadyAssigned(Hash),
+        /// The receivables have been assigned and can no longer be adjusted or credited
+        ErrorReceivablesAssigned(Hash),
+        /// An error occured posting to accounts - assignment of receivables
+        ErrorInAccounting10(Hash),
+    }
+);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{sr25519, Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use support::{assert_err, assert_ok};
+    use crate::mock::{account, Origin, Test};
+
+    type PrefundingModule = Module<Test>;
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![(account(1), 100_000)])
+    }
+
+    #[test]
+    fn zero_and_dust_prefunds_are_rejected() {
+        with_externalities(&mut new_test_ext(), || {
+            let prefund = |amount: u64, n: u8| {
+                PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), amount, 100, 0, H256::repeat_byte(n))
+            };
+            assert_err!(prefund(0, 1), "Prefund amount is zero or below the minimum");
+            
+            assert!(PrefundingModule::set_minimum_prefund(Origin::signed(account(1)), 100).is_err());
+            assert_ok!(PrefundingModule::set_minimum_prefund(Origin::ROOT, 100));
+            assert_eq!(PrefundingModule::minimum_prefund(), 100);
+            assert_err!(prefund(99, 2), "Prefund amount is zero or below the minimum");
+            assert!(PrefundingModule::owner_prefunding_hash_list(account(1)).is_empty());
+        });
+    }
+
+    #[test]
+    fn prefunds_above_the_dual_control_threshold_are_queued_and_expire() {
+        with_externalities(&mut new_test_ext(), || {
+            let h = H256::repeat_byte(30);
+            assert_ok!(PrefundingModule::set_dual_control(Origin::signed(account(1)), 500, vec![account(3)], 10));
+            // Orders prefund through the encumbrance interface
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::prefunding_for(account(1), account(2), 1000, 20_000, 0, h, H256::repeat_byte(1)));
+            assert!(<PrefundingModule as Encumbrance<_, _, _>>::awaiting_co_approval(h));
+            assert!(PrefundingModule::prefunding(h).is_none());
+            assert_eq!(PrefundingModule::pending_prefunding_expiring(11), vec![h]);
+            
+            // Still open at the end of the window
+            PrefundingModule::expire_pending_prefundings(10);
+            assert!(PrefundingModule::pending_prefunding(h).is_some());
+            PrefundingModule::expire_pending_prefundings(11);
+            assert!(PrefundingModule::pending_prefunding(h).is_none());
+            assert!(PrefundingModule::pending_prefunding_list(account(1)).is_empty());
+            assert_eq!(PrefundingModule::reference_status(h), STATUS_CANCELLED);
+        });
+    }
+
+    #[test]
+    fn zero_invoices_are_rejected() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_err!(
+                PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 0, H256::repeat_byte(10), H256::repeat_byte(1)),
+                "Invoice amount is zero or out of range"
+            );
+        });
+    }
+
+    #[test]
+    fn accepted_proforma_accepts_the_order_and_fixes_the_invoice_amount() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            let terms = H256::repeat_byte(20);
+            
+            assert_err!(PrefundingModule::propose_proforma(Origin::signed(account(1)), h, 800, terms, H256::repeat_byte(2)), "Not the beneficiary");
+            assert_err!(
+                PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 2000, terms, H256::repeat_byte(3)),
+                "Proforma amount must be greater than zero and within the prefunded amount"
+            );
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 800, terms, H256::repeat_byte(4)));
+            assert_err!(PrefundingModule::accept_proforma(Origin::signed(account(2)), h, H256::repeat_byte(5)), "Not the owner");
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(6)));
+            assert_eq!(PrefundingModule::prefunding_hash_owner(h).map(|p| p.lock), Some(LockState::Accepted));
+            assert_eq!(PrefundingModule::agreed_invoice_amount(h), Some(800));
+            assert!(PrefundingModule::proforma(h).is_none());
+            
+            assert_err!(
+                PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 900, h, H256::repeat_byte(7)),
+                "The invoice amount differs from the accepted proforma"
+            );
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 800, h, H256::repeat_byte(8)));
+            assert_eq!(PrefundingModule::reference_status(h), STATUS_INVOICED);
+        });
+    }
+
+    #[test]
+    fn invoices_above_an_enforced_credit_limit_are_rejected() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 800, H256::repeat_byte(20), H256::repeat_byte(2)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            
+            assert_ok!(Accounting::set_credit_limit(Origin::signed(account(2)), account(1), Some(500), true));
+            assert_err!(
+                PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 800, h, H256::repeat_byte(4)),
+                "The invoice would exceed the credit limit of the buyer"
+            );
+            assert!(PrefundingModule::invoice_dates(h).is_none());
+            assert_eq!(Accounting::receivable_exposure((account(2), account(1))), 0);
+            
+            // A limit that is not enforced only warns
+            assert_ok!(Accounting::set_credit_limit(Origin::signed(account(2)), account(1), Some(500), false));
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 800, h, H256::repeat_byte(5)));
+            assert_eq!(Accounting::credit_exposure(account(2)), vec![(account(1), 800, Some((500, false)))]);
+        });
+    }
+
+    #[test]
+    fn assigned_receivables_are_booked_once() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 1000, H256::repeat_byte(20), H256::repeat_byte(2)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            
+            assert_ok!(PrefundingModule::assign_receivables(Origin::signed(account(2)), h, account(3), H256::repeat_byte(4)));
+            assert_eq!(Accounting::balance_by_ledger((account(2), 120200060000000)), 1000);
+            assert_eq!(Accounting::balance_by_ledger((account(3), 110100100000000)), 1000);
+            assert_err!(
+                PrefundingModule::assign_receivables(Origin::signed(account(2)), h, account(4), H256::repeat_byte(5)),
+                "The receivables have already been assigned"
+            );
+            assert_eq!(PrefundingModule::receivables_assignee(h), Some(account(3)));
+        });
+    }
+
+    #[test]
+    fn invoices_below_the_prefund_are_settled_at_the_invoiced_amount() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            type Balances = balances::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 800, H256::repeat_byte(20), H256::repeat_byte(2)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 800, h, H256::repeat_byte(4)));
+            
+            // The seller is paid the invoice and the rest of the prefund is released to the buyer
+            assert_ok!(PrefundingModule::pay_prefunded_invoice(Origin::signed(account(1)), h, H256::repeat_byte(5)));
+            assert_eq!(Balances::free_balance(account(2)), 800);
+            assert_eq!(Balances::free_balance(account(1)), 99_200);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100040000000)), 800);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 120200030000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100050000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100040000000)), -800);
+        });
     }
-);
\ No newline at end of file
+
+    #[test]
+    fn beneficiary_list_follows_the_prefunds_through_settlement_and_cancellation() {
+        with_externalities(&mut new_test_ext(), || {
+            // The prefunding hash is derived from the block, so each prefund is made in its own block
+            for n in 1..4 {
+                system::Module::<Test>::set_block_number(n.into());
+                assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(n)));
+            }
+            let hashes = PrefundingModule::owner_prefunding_hash_list(account(1));
+            assert_eq!(PrefundingModule::beneficiary_prefunding_hash_list(account(2)), hashes);
+            
+            // Settled prefunds leave the list of the beneficiary
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), hashes[0], 1000, H256::repeat_byte(20), H256::repeat_byte(4)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), hashes[0], H256::repeat_byte(5)));
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 1000, hashes[0], H256::repeat_byte(6)));
+            assert_eq!(PrefundingModule::beneficiary_prefunding_hash_list(account(2)), hashes);
+            assert_ok!(PrefundingModule::pay_prefunded_invoice(Origin::signed(account(1)), hashes[0], H256::repeat_byte(7)));
+            assert_eq!(PrefundingModule::beneficiary_prefunding_hash_list(account(2)), vec![hashes[1], hashes[2]]);
+            
+            // So do cancelled prefunds, and the ones still open stay
+            system::Module::<Test>::set_block_number(20_000);
+            assert_ok!(PrefundingModule::cancel_prefunded_closed_order(Origin::signed(account(1)), hashes[1], H256::repeat_byte(8)));
+            assert_eq!(PrefundingModule::beneficiary_prefunding_hash_list(account(2)), vec![hashes[2]]);
+            assert_eq!(PrefundingModule::owner_prefunding_hash_list(account(1)), vec![hashes[2]]);
+            assert!(PrefundingModule::beneficiary_prefunding_hash_list(account(1)).is_empty());
+        });
+    }
+
+    #[test]
+    fn cost_plus_invoices_below_the_prefund_are_settled_at_the_invoiced_amount() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            type Balances = balances::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::set_release_state(account(2), LockState::Accepted, h, H256::repeat_byte(2)));
+            
+            // The cost-plus invoice of the time and expenses booked comes in under the prefund
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::send_simple_invoice(account(2), account(1), 385, h, ORDER_TYPE_SERVICE, H256::repeat_byte(3)));
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::settle_prefunded_invoice(account(1), h, H256::repeat_byte(4)));
+            assert_eq!(Balances::free_balance(account(2)), 385);
+            assert_eq!(Balances::free_balance(account(1)), 99_615);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 360600020000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 120200030000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 360600010000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100040000000)), -385);
+            assert_eq!(PrefundingModule::reference_status(h), STATUS_SETTLED);
+        });
+    }
+
+    #[test]
+    fn failed_settlement_transfer_keeps_the_funds_locked_and_reverses_the_postings() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            type Balances = balances::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 1000, H256::repeat_byte(20), H256::repeat_byte(2)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 1000, h, H256::repeat_byte(4)));
+            
+            // Another lock on the whole balance of the buyer prevents the payment
+            <Balances as LockableCurrency<_>>::set_lock(*b"blocking", &account(1), 100_000, u64::max_value(), WithdrawReasons::all());
+            assert_err!(
+                PrefundingModule::pay_prefunded_invoice(Origin::signed(account(1)), h, H256::repeat_byte(5)),
+                "Error unlocking for beneficiary"
+            );
+            assert_eq!(Balances::free_balance(account(2)), 0);
+            assert_eq!(PrefundingModule::reference_status(h), STATUS_INVOICED);
+            assert_eq!(PrefundingModule::prefunding_hash_owner(h).map(|p| p.lock), Some(LockState::Accepted));
+            let lock_id = PrefundingModule::get_prefunding_id(h);
+            assert!(Balances::locks(account(1)).iter().any(|l| l.id == lock_id && l.amount == 1000));
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100040000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 1000);
+            
+            // Once the other lock is lifted the settlement goes through
+            <Balances as LockableCurrency<_>>::remove_lock(*b"blocking", &account(1));
+            assert_ok!(PrefundingModule::pay_prefunded_invoice(Origin::signed(account(1)), h, H256::repeat_byte(6)));
+            assert_eq!(Balances::free_balance(account(2)), 1000);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 0);
+        });
+    }
+
+    #[test]
+    fn invoices_adjusted_below_zero_are_neither_settled_nor_assigned() {
+        with_externalities(&mut new_test_ext(), || {
+            type Balances = balances::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(PrefundingModule::propose_proforma(Origin::signed(account(2)), h, 1000, H256::repeat_byte(20), H256::repeat_byte(2)));
+            assert_ok!(PrefundingModule::accept_proforma(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            
+            // Adjustments larger than the prefunded amount leave no amount to assign
+            <InvoiceAdjustments<Test>>::insert(&h, vec![(1001u128, H256::repeat_byte(30))]);
+            assert_err!(
+                PrefundingModule::assign_receivables(Origin::signed(account(2)), h, account(3), H256::repeat_byte(4)),
+                "The invoice cannot be settled from the prefunded amount"
+            );
+            assert!(PrefundingModule::receivables_assignee(h).is_none());
+            
+            // Nor anything to pay, so the funds stay locked and the reference is not settled
+            <InvoiceAdjustments<Test>>::remove(&h);
+            assert_ok!(PrefundingModule::invoice_prefunded_order(Origin::signed(account(2)), account(1), 1000, h, H256::repeat_byte(5)));
+            <InvoiceAdjustments<Test>>::insert(&h, vec![(1001u128, H256::repeat_byte(30))]);
+            <PrefundingHashOwner<Test>>::mutate(&h, |owners| if let Some(owners) = owners { owners.lock = LockState::SenderApproved; });
+            assert_err!(
+                PrefundingModule::unlock_funds_for_beneficiary(account(2), h, H256::repeat_byte(6)),
+                "The invoice cannot be settled from the prefunded amount"
+            );
+            assert_eq!(Balances::free_balance(account(2)), 0);
+            assert_eq!(PrefundingModule::reference_status(h), STATUS_INVOICED);
+            let lock_id = PrefundingModule::get_prefunding_id(h);
+            assert!(Balances::locks(account(1)).iter().any(|l| l.id == lock_id && l.amount == 1000));
+        });
+    }
+
+    #[test]
+    fn express_orders_are_invoiced_and_settled_for_both_parties() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::send_express_invoice(account(2), account(1), h, 0, H256::repeat_byte(2)));
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 1000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 120200030000000)), 1000);
+            
+            assert_ok!(PrefundingModule::pay_prefunded_invoice(Origin::signed(account(1)), h, H256::repeat_byte(3)));
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 360600020000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 120200030000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 360600010000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100040000000)), 1000);
+        });
+    }
+
+    #[test]
+    fn failed_forfeit_transfer_locks_the_bond_again() {
+        with_externalities(&mut new_test_ext(), || {
+            type Accounting = accounting::Module<Test>;
+            type Balances = balances::Module<Test>;
+            let h = H256::repeat_byte(9);
+            let _ = <Balances as Currency<_>>::deposit_creating(&account(2), 10_000);
+            assert_ok!(<PrefundingModule as Encumbrance<_, _, _>>::lock_performance_bond(account(2), account(1), 100, h, H256::repeat_byte(1)));
+            
+            <Balances as LockableCurrency<_>>::set_lock(*b"blocking", &account(2), 10_000, u64::max_value(), WithdrawReasons::all());
+            assert_err!(
+                <PrefundingModule as Encumbrance<_, _, _>>::forfeit_performance_bond(h, 100, H256::repeat_byte(2)),
+                "Error during transfer"
+            );
+            assert_eq!(Balances::free_balance(account(1)), 100_000);
+            assert!(PrefundingModule::performance_bond(h).is_some());
+            let bond_id = PrefundingModule::get_bond_id(h);
+            assert!(Balances::locks(account(2)).iter().any(|l| l.id == bond_id && l.amount == 100));
+            
+            // The release of the bond and the penalty are reversed out of the books of both parties
+            assert_eq!(Accounting::balance_by_ledger((account(2), 110100050000000)), 100);
+            assert_eq!(Accounting::balance_by_ledger((account(2), 250500400000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 360600050000000)), 100);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 240400070000000)), 0);
+        });
+    }
+
+    #[test]
+    fn trust_agreements_shorten_the_minimum_deadline() {
+        with_externalities(&mut new_test_ext(), || {
+            let prefund = |deadline: u64, n: u8| {
+                PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, deadline, 0, H256::repeat_byte(n))
+            };
+            assert_err!(prefund(100, 1), "Deadline is too short!");
+            
+            assert_err!(PrefundingModule::propose_trust_agreement(Origin::signed(account(1)), account(2), 0), "Invalid trust agreement");
+            assert_err!(PrefundingModule::propose_trust_agreement(Origin::signed(account(1)), account(1), 100), "Invalid trust agreement");
+            assert_ok!(PrefundingModule::propose_trust_agreement(Origin::signed(account(1)), account(2), 100));
+            assert_eq!(PrefundingModule::minimum_deadline_of(&account(1), &account(2)), DEFAULT_MINIMUM_DEADLINE);
+            
+            // A different minimum is a new proposal, the same minimum makes the agreement
+            assert_ok!(PrefundingModule::propose_trust_agreement(Origin::signed(account(2)), account(1), 50));
+            assert!(PrefundingModule::trust_agreement((account(1), account(2))).is_none());
+            assert_ok!(PrefundingModule::propose_trust_agreement(Origin::signed(account(2)), account(1), 100));
+            assert_eq!(PrefundingModule::minimum_deadline_of(&account(2), &account(1)), 100);
+            assert!(PrefundingModule::trust_proposal((account(1), account(2))).is_none());
+            assert_ok!(prefund(100, 2));
+            assert_eq!(PrefundingModule::minimum_deadline_of(&account(1), &account(3)), DEFAULT_MINIMUM_DEADLINE);
+            
+            assert_ok!(PrefundingModule::revoke_trust_agreement(Origin::signed(account(2)), account(1)));
+            assert_err!(prefund(100, 3), "Deadline is too short!");
+        });
+    }
+
+    #[test]
+    fn expiry_and_arbitration_end_in_their_own_status() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(3), 1000, 20_000, 0, H256::repeat_byte(2)));
+            let hashes = PrefundingModule::owner_prefunding_hash_list(account(1));
+            
+            assert_ok!(PrefundingModule::arbitrate_prefunding(Origin::ROOT, hashes[0], H256::repeat_byte(3)));
+            assert_eq!(PrefundingModule::reference_status(hashes[0]), STATUS_ARBITRATED);
+            
+            assert_err!(
+                PrefundingModule::cancel_prefunded_closed_order(Origin::signed(account(1)), hashes[1], H256::repeat_byte(4)),
+                "Deadline not yet passed. Wait a bit longer!"
+            );
+            system::Module::<Test>::set_block_number(20_000);
+            assert_ok!(PrefundingModule::cancel_prefunded_closed_order(Origin::signed(account(1)), hashes[1], H256::repeat_byte(5)));
+            assert_eq!(PrefundingModule::reference_status(hashes[1]), STATUS_EXPIRED);
+            assert!(PrefundingModule::owner_prefunding_hash_list(account(1)).is_empty());
+        });
+    }
+
+    #[test]
+    fn deadlines_index_lists_the_prefunds_about_to_expire() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 30_000, 0, H256::repeat_byte(1)));
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(3), 1000, 20_000, 0, H256::repeat_byte(2)));
+            let hashes = PrefundingModule::owner_prefunding_hash_list(account(1));
+            
+            assert_eq!(PrefundingModule::expiring_prefundings(20_000), vec![(hashes[1], 20_000)]);
+            assert_eq!(PrefundingModule::expiring_prefundings(30_000), vec![(hashes[1], 20_000), (hashes[0], 30_000)]);
+            system::Module::<Test>::set_block_number(20_001);
+            assert_eq!(PrefundingModule::expiring_prefundings(10_000), vec![(hashes[0], 30_000)]);
+            
+            // Prefunds no longer awaiting acceptance leave the index
+            assert_ok!(PrefundingModule::arbitrate_prefunding(Origin::ROOT, hashes[0], H256::repeat_byte(3)));
+            assert!(PrefundingModule::expiring_prefundings(10_000).is_empty());
+            
+            // Prefunds locked before the index existed are added by the migration, once
+            <DeadlinesIndex<Test>>::remove(20_000 / BLOCKS_PER_DAY);
+            system::Module::<Test>::set_block_number(0);
+            assert!(PrefundingModule::expiring_prefundings(20_000).is_empty());
+            assert!(PrefundingModule::migrate_deadlines_index(Origin::signed(account(1)), vec![hashes[1]]).is_err());
+            assert_ok!(PrefundingModule::migrate_deadlines_index(Origin::ROOT, vec![hashes[0], hashes[1]]));
+            assert_ok!(PrefundingModule::migrate_deadlines_index(Origin::ROOT, vec![hashes[1]]));
+            assert_eq!(PrefundingModule::deadlines_index(20_000 / BLOCKS_PER_DAY), vec![hashes[1]]);
+            assert_eq!(PrefundingModule::expiring_prefundings(20_000), vec![(hashes[1], 20_000)]);
+            assert_err!(
+                PrefundingModule::migrate_deadlines_index(Origin::ROOT, vec![hashes[1]; MAX_DEADLINE_MIGRATIONS + 1]),
+                "Too many references to add to the deadlines index"
+            );
+            
+            // A full bucket takes no more prefunds
+            <DeadlinesIndex<Test>>::insert(40_000 / BLOCKS_PER_DAY, vec![H256::zero(); MAX_DEADLINE_BUCKET_SIZE]);
+            assert_err!(
+                PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 40_000, 0, H256::repeat_byte(4)),
+                "Too many prefunds expire on the day of this deadline"
+            );
+        });
+    }
+
+    const LOCK_STATES: [LockState; 4] = [
+        LockState::AwaitingAcceptance, LockState::Accepted, LockState::SenderApproved, LockState::ReleasedToSender,
+    ];
+
+    #[test]
+    fn prefunding_parties_decode_from_the_legacy_lock_pairs() {
+        for (owner_locked, beneficiary_locked) in [(true, false), (true, true), (false, true), (false, false)].iter() {
+            let legacy = (account(1), *owner_locked, account(2), *beneficiary_locked).encode();
+            let parties = PrefundingParties::<sr25519::Public>::decode(&mut &legacy[..]).expect("legacy tuple decodes");
+            assert_eq!(parties.lock.locks(), (*owner_locked, *beneficiary_locked));
+            assert_eq!((parties.owner, parties.beneficiary), (account(1), account(2)));
+            assert_eq!(parties.encode(), legacy);
+        }
+    }
+
+    #[test]
+    fn lock_state_changes_follow_the_transition_table() {
+        use totem_traits::prefunding::LockState::*;
+        let allowed = [
+            (AwaitingAcceptance, Accepted, 2u8),
+            (AwaitingAcceptance, ReleasedToSender, 1),
+            (Accepted, SenderApproved, 1),
+            (Accepted, AwaitingAcceptance, 2),
+            (SenderApproved, ReleasedToSender, 2),
+        ];
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            for from in LOCK_STATES.iter() {
+                for to in LOCK_STATES.iter() {
+                    for who in 1..=3u8 {
+                        <PrefundingHashOwner<Test>>::mutate(&h, |p| p.as_mut().expect("prefunding exists").lock = *from);
+                        let result = PrefundingModule::set_release_state(account(who), *to, h, H256::repeat_byte(2));
+                        let expected = allowed.iter().any(|a| (a.0, a.1, a.2) == (*from, *to, who));
+                        assert_eq!(result.is_ok(), expected, "{:?} -> {:?} by {}", from, to, who);
+                        let lock = PrefundingModule::prefunding_hash_owner(h).map(|p| p.lock);
+                        assert_eq!(lock, Some(if expected { *to } else { *from }));
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn acceptance_after_the_deadline_needs_a_grace_period() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            system::Module::<Test>::set_block_number(20_000);
+            assert_err!(
+                PrefundingModule::set_release_state(account(2), LockState::Accepted, h, H256::repeat_byte(2)),
+                "The deadline for accepting has passed"
+            );
+            assert_eq!(PrefundingModule::prefunding_hash_owner(h).map(|p| p.lock), Some(LockState::AwaitingAcceptance));
+        });
+    }
+
+    #[test]
+    fn prefunding_lock_ids_are_namespaced_and_unique_per_account() {
+        with_externalities(&mut new_test_ext(), || {
+            for n in 1..=5u8 {
+                assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(n)));
+            }
+            let mut ids: Vec<LockIdentifier> = PrefundingModule::active_locks(account(1)).into_iter().map(|l| l.0).collect();
+            let locks: Vec<LockIdentifier> = balances::Module::<Test>::locks(&account(1)).into_iter().map(|l| l.id).collect();
+            assert_eq!(ids.len(), 5);
+            for id in ids.iter() {
+                assert_eq!(&id[..2], &PREFUNDING_LOCK_NAMESPACE[..]);
+                assert!(locks.contains(id));
+            }
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 5);
+            
+            // A digest already in use on the account, by any locker, is skipped
+            let h = H256::repeat_byte(30);
+            let taken = PrefundingModule::derive_lock_id(PREFUNDING_LOCK_NAMESPACE, h, 0);
+            <balances::Module<Test> as LockableCurrency<_>>::set_lock(taken, &account(1), 10, 100, WithdrawReason::Reserve.into());
+            assert_eq!(PrefundingModule::assign_lock_id(&account(1), PREFUNDING_LOCK_NAMESPACE, h), Ok(PrefundingModule::derive_lock_id(PREFUNDING_LOCK_NAMESPACE, h, 1)));
+            
+            // Cancelling releases the lock id
+            let reference = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            system::Module::<Test>::set_block_number(20_000);
+            assert_ok!(PrefundingModule::cancel_prefunded_closed_order(Origin::signed(account(1)), reference, H256::repeat_byte(10)));
+            assert!(PrefundingModule::lock_ids((PREFUNDING_LOCK_NAMESPACE, reference)).is_none());
+            assert!(PrefundingModule::active_locks(account(1)).iter().all(|l| l.1 != reference));
+        });
+    }
+
+    #[test]
+    fn failed_prefunding_posting_releases_the_lock_and_its_lock_id() {
+        with_externalities(&mut new_test_ext(), || {
+            // The escrowed funds control account is full, so that posting the prefunding overflows the global ledger
+            let h = H256::repeat_byte(30);
+            let full = vec![(account(3), account(3), 360600040000000u64, i128::max_value(), false, h, 1u64, 1u64)];
+            assert_ok!(<accounting::Module<Test> as Posting<_, _, _, _>>::handle_multiposting_amounts(full));
+            
+            assert_err!(
+                <PrefundingModule as Encumbrance<_, _, _>>::prefunding_for(account(1), account(2), 1000, 20_000, 0, h, H256::repeat_byte(1)),
+                "An error occured posting to accounts"
+            );
+            assert!(balances::Module::<Test>::locks(&account(1)).is_empty());
+            assert!(PrefundingModule::lock_ids((PREFUNDING_LOCK_NAMESPACE, h)).is_none());
+            assert!(PrefundingModule::active_locks(account(1)).is_empty());
+            assert!(PrefundingModule::prefunding(h).is_none());
+        });
+    }
+
+    #[test]
+    fn legacy_locks_are_migrated_to_namespaced_lock_ids() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            let h = PrefundingModule::owner_prefunding_hash_list(account(1))[0];
+            
+            // Lock as it was before namespacing
+            let id = PrefundingModule::get_prefunding_id(h);
+            <balances::Module<Test> as LockableCurrency<_>>::remove_lock(id, &account(1));
+            <balances::Module<Test> as LockableCurrency<_>>::set_lock(PrefundingModule::legacy_lock_id(h), &account(1), 1000, 20_000, WithdrawReason::Reserve.into());
+            <LockIds<Test>>::remove((PREFUNDING_LOCK_NAMESPACE, h));
+            <ActiveLocks<Test>>::remove(account(1));
+            let free = balances::Module::<Test>::free_balance(&account(1));
+            
+            assert!(PrefundingModule::migrate_lock_ids(Origin::signed(account(1)), vec![h]).is_err());
+            assert_ok!(PrefundingModule::migrate_lock_ids(Origin::ROOT, vec![h]));
+            let locks: Vec<LockIdentifier> = balances::Module::<Test>::locks(&account(1)).into_iter().map(|l| l.id).collect();
+            assert_eq!(locks, vec![PrefundingModule::get_prefunding_id(h)]);
+            assert_eq!(&locks[0][..2], &PREFUNDING_LOCK_NAMESPACE[..]);
+            assert_eq!(balances::Module::<Test>::free_balance(&account(1)), free);
+            
+            // Migrating again changes nothing
+            assert_ok!(PrefundingModule::migrate_lock_ids(Origin::ROOT, vec![h]));
+            assert_eq!(PrefundingModule::active_locks(account(1)), vec![(locks[0], h)]);
+        });
+    }
+
+    #[test]
+    fn lock_migration_skips_references_without_an_unused_lock_id() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 1000, 20_000, 0, H256::repeat_byte(1)));
+            assert_ok!(PrefundingModule::prefund_someone(Origin::signed(account(1)), account(2), 500, 20_000, 0, H256::repeat_byte(2)));
+            let references = PrefundingModule::owner_prefunding_hash_list(account(1));
+            let (blocked, free) = (references[0], references[1]);
+            
+            // Both locks as they were before namespacing
+            for h in references.iter() {
+                let amount = PrefundingModule::prefunding(h).map(|p| p.0).unwrap();
+                <balances::Module<Test> as LockableCurrency<_>>::remove_lock(PrefundingModule::get_prefunding_id(*h), &account(1));
+                <balances::Module<Test> as LockableCurrency<_>>::set_lock(PrefundingModule::legacy_lock_id(*h), &account(1), amount, 20_000, WithdrawReason::Reserve.into());
+                <LockIds<Test>>::remove((PREFUNDING_LOCK_NAMESPACE, *h));
+            }
+            // Every lock id that can be derived for the first reference is in use
+            let taken: Vec<(LockIdentifier, H256)> = (0..MAX_LOCK_ID_ATTEMPTS)
+                .map(|attempt| (PrefundingModule::derive_lock_id(PREFUNDING_LOCK_NAMESPACE, blocked, attempt), H256::repeat_byte(9)))
+                .collect();
+            <ActiveLocks<Test>>::insert(account(1), taken);
+            
+            assert_ok!(PrefundingModule::migrate_lock_ids(Origin::ROOT, vec![blocked, free]));
+            assert!(PrefundingModule::lock_ids((PREFUNDING_LOCK_NAMESPACE, blocked)).is_none());
+            let id = PrefundingModule::lock_ids((PREFUNDING_LOCK_NAMESPACE, free)).unwrap();
+            let locks: Vec<LockIdentifier> = balances::Module::<Test>::locks(&account(1)).into_iter().map(|l| l.id).collect();
+            assert_eq!(locks, vec![PrefundingModule::legacy_lock_id(blocked), id]);
+            
+            // Migrated once the lock ids are released
+            <ActiveLocks<Test>>::insert(account(1), vec![(id, free)]);
+            assert_ok!(PrefundingModule::migrate_lock_ids(Origin::ROOT, vec![blocked, free]));
+            assert!(PrefundingModule::lock_ids((PREFUNDING_LOCK_NAMESPACE, blocked)).is_some());
+            assert_eq!(balances::Module::<Test>::locks(&account(1)).len(), 2);
+        });
+    }
+}
@@ -35,14 +35,31 @@
 
 use parity_codec::{Decode, Encode};
 use rstd::prelude::*;
-use support::{decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap};
-use system::{self, ensure_signed};
+use support::{decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue};
+use support::traits::{Currency, ReservableCurrency};
+use runtime_primitives::traits::{CheckedAdd, Convert, Zero};
+use system::{self, ensure_root, ensure_signed};
 
 // Totem traits
+use accounting::{ Posting };
 use crate::projects_traits::{ Validating };
 
 pub type ProjectStatus = u16; // Reference supplied externally
 
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type AccountOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::Account;
+type AccountBalanceOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::LedgerBalance;
+
+// Project lifecycle. Orders and time can only be booked against active (open or reopened) projects.
+pub const STATUS_OPEN: ProjectStatus = 0;
+pub const STATUS_DRAFT: ProjectStatus = 10;
+pub const STATUS_FUNDED: ProjectStatus = 20;
+pub const STATUS_REOPENED: ProjectStatus = 100;
+pub const STATUS_ON_HOLD: ProjectStatus = 200;
+pub const STATUS_ABANDONED: ProjectStatus = 300;
+pub const STATUS_CANCELLED: ProjectStatus = 400;
+pub const STATUS_CLOSED: ProjectStatus = 500;
+
 #[derive(PartialEq, Eq, Clone, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct DeletedProject<AccountId, ProjectStatus> {
@@ -51,8 +68,13 @@ pub struct DeletedProject<AccountId, ProjectStatus> {
     pub status: ProjectStatus,
 }
 
-pub trait Trait: system::Trait {
+pub trait Trait: system::Trait + accounting::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    type Currency: ReservableCurrency<Self::AccountId>;
+    type ProjectConversions: Convert<BalanceOf<Self>, u128> 
+    + Convert<i128, AccountBalanceOf<Self>> 
+    + Convert<u64, AccountOf<Self>>;
+    type Accounting: Posting<Self::AccountId,Self::Hash,Self::BlockNumber,Self::CoinAmount>;
 }
 
 decl_storage! {
@@ -61,6 +83,10 @@ decl_storage! {
         DeletedProjects get(deleted_project): map T::Hash => Vec<DeletedProject<T::AccountId, ProjectStatus>>;
         ProjectHashOwner get(project_hash_owner): map T::Hash => Option<T::AccountId>;
         OwnerProjectsList get(owner_projects_list): map T::AccountId => Vec<T::Hash>;
+        // Funds reserved for the project budget (funder, amount). Released when the project is closed or removed.
+        ProjectFunding get(project_funding): map T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        // Funding a draft project needs before it can be activated without the confirmation of the owner
+        MinimumProjectFunding get(minimum_project_funding): BalanceOf<T>;
     }
 }
 
@@ -78,7 +104,7 @@ decl_module! {
 
             // proceed to store project
             let who = ensure_signed(origin)?;
-            let project_status: ProjectStatus = 0;
+            let project_status: ProjectStatus = STATUS_DRAFT;
 
             // TODO limit nr of Projects per Account.
             <ProjectHashStatus<T>>::insert(project_hash.clone(), &project_status);
@@ -111,6 +137,9 @@ decl_module! {
                 status: 999
             };
 
+            // return the funding before anything is removed
+            Self::release_funding(project_hash.clone())?;
+
             // retain all other projects except the one we want to delete
             <OwnerProjectsList<T>>::mutate(&project_owner, |owner_projects_list| owner_projects_list.retain(|h| h != &project_hash));
 
@@ -165,7 +194,8 @@ decl_module! {
             // TODO Implement a sudo for cleaning data in cases where owner is lost
             // Otherwise onlu the owner can change the data
             ensure!(project_owner == changer, "You cannot close a project you do not own");
-            let project_status: ProjectStatus = 500;
+            let project_status: ProjectStatus = STATUS_CLOSED;
+            Self::release_funding(project_hash.clone())?;
             <ProjectHashStatus<T>>::insert(project_hash.clone(), &project_status);
 
             Self::deposit_event(RawEvent::ProjectChanged(project_hash, changer, project_status));
@@ -214,6 +244,8 @@ decl_module! {
             let proposed_project_status = project_status.clone();
 
             // Open	0
+            // Draft	10
+            // Funded	20
             // Reopen	100
             // On Hold	200
             // Abandon	300
@@ -221,7 +253,8 @@ decl_module! {
             // Close	500
             // Delete	999
 
-            // Project owner creates project, set status to 0
+            // Project owner creates project, set status to 10. Funding or activating the project moves it on (20 or 0)
+            // Project owner cancels a draft or funded project, setting the state to 400
             // Project owner puts on hold, setting the state to 200... 200 can only be set if the current status is  <= 101
             // Project owner abandons, setting the state to 300... 300 can only be set if the current status is  <= 101
             // Project owner cancels, setting the state to 400... 400 can only be set if the current status is  <= 101
@@ -239,6 +272,13 @@ decl_module! {
                             _ => return Err("The proposed project status cannot be applied to the current project status."),
                         };
                     },
+                    STATUS_DRAFT | STATUS_FUNDED => {
+                        // only set 400
+                        match proposed_project_status {
+                            400  => (),
+                            _ => return Err("The proposed project status cannot be applied to the current project status."),
+                        };
+                    },
                     200 | 300 | 500 => {
                         // only set 100
                         match proposed_project_status {
@@ -251,6 +291,10 @@ decl_module! {
 
             let allowed_project_status: ProjectStatus =  proposed_project_status.into();
 
+            match allowed_project_status {
+                STATUS_ABANDONED | STATUS_CANCELLED | STATUS_CLOSED => Self::release_funding(project_hash.clone())?,
+                _ => (),
+            }
             <ProjectHashStatus<T>>::insert(project_hash.clone(), &allowed_project_status);
 
             Self::deposit_event(RawEvent::ProjectChanged(project_hash, changer, allowed_project_status));
@@ -258,6 +302,110 @@ decl_module! {
             Ok(())
         }
 
+        /// The owner reserves funds for the budget of a project that is not closed. A draft project becomes funded 
+        /// once its funding reaches the minimum.
+        fn fund_project(origin, project_hash: T::Hash, amount: BalanceOf<T>) -> Result {
+            let who = ensure_signed(origin)?;
+            ensure!(Self::project_hash_owner(project_hash.clone()) == Some(who.clone()), "You cannot fund a project you do not own");
+            let current_project_status = Self::project_hash_status(project_hash.clone()).ok_or("Error fetching project status")?;
+            match current_project_status {
+                STATUS_DRAFT | STATUS_FUNDED | STATUS_OPEN | STATUS_REOPENED | STATUS_ON_HOLD => (),
+                _ => return Err("Only open projects can be funded"),
+            }
+            let funding: BalanceOf<T> = match Self::project_funding(project_hash.clone()) {
+                Some((funder, funded)) => {
+                    ensure!(funder == who, "The project is funded by a previous owner");
+                    funded
+                },
+                None => Zero::zero(),
+            };
+            let funding: BalanceOf<T> = funding.checked_add(&amount).ok_or("Project funding overflowed")?;
+            ensure!(T::Currency::can_reserve(&who, amount), "Insufficient funds to fund the project");
+            Self::account_for_funding(who.clone(), amount, true, project_hash.clone())?;
+            T::Currency::reserve(&who, amount)?;
+            <ProjectFunding<T>>::insert(project_hash.clone(), (who.clone(), funding));
+            Self::deposit_event(RawEvent::ProjectFunded(project_hash.clone(), who.clone(), funding));
+
+            if current_project_status == STATUS_DRAFT && funding >= Self::minimum_project_funding() {
+                <ProjectHashStatus<T>>::insert(project_hash.clone(), &STATUS_FUNDED);
+                Self::deposit_event(RawEvent::ProjectChanged(project_hash, who, STATUS_FUNDED));
+            }
+
+            Ok(())
+        }
+
+        /// The owner activates a draft or funded project so that orders and time can be booked against it. 
+        /// A project below the minimum funding is only activated if the owner confirms it.
+        fn activate_project(origin, project_hash: T::Hash, confirm_unfunded: bool) -> Result {
+            let who = ensure_signed(origin)?;
+            ensure!(Self::project_hash_owner(project_hash.clone()) == Some(who.clone()), "You cannot activate a project you do not own");
+            match Self::project_hash_status(project_hash.clone()) {
+                Some(STATUS_DRAFT) | Some(STATUS_FUNDED) => (),
+                _ => return Err("Only draft or funded projects can be activated"),
+            }
+            let funding: BalanceOf<T> = Self::project_funding(project_hash.clone()).map_or(Zero::zero(), |f| f.1);
+            ensure!(
+                confirm_unfunded || funding >= Self::minimum_project_funding(), 
+                "The project is below the minimum funding. The owner must confirm the activation"
+            );
+            <ProjectHashStatus<T>>::insert(project_hash.clone(), &STATUS_OPEN);
+
+            Self::deposit_event(RawEvent::ProjectChanged(project_hash, who, STATUS_OPEN));
+
+            Ok(())
+        }
+
+        /// Super User sets the minimum funding of a project
+        fn set_minimum_project_funding(origin, amount: BalanceOf<T>) -> Result {
+            let _who = ensure_root(origin)?;
+            <MinimumProjectFunding<T>>::put(amount);
+            Ok(())
+        }
+
+    }
+}
+
+impl<T: Trait> Module<T> {
+    // Returns the funds reserved for a project to the funder and reverses the reservation in the ledger. 
+    // No work in progress or accruals are posted per project, so the reservation is the only balance to clear when a project ends.
+    fn release_funding(project_hash: T::Hash) -> Result {
+        if let Some((funder, funding)) = Self::project_funding(project_hash.clone()) {
+            Self::account_for_funding(funder.clone(), funding, false, project_hash.clone())?;
+            <ProjectFunding<T>>::remove(project_hash.clone());
+            T::Currency::unreserve(&funder, funding);
+            Self::deposit_event(RawEvent::ProjectFundingReleased(project_hash, funder, funding));
+        }
+        Ok(())
+    }
+    // Posts the funds reserved for the budget of a project (reserve is true) or their release to the ledger of the funder.
+    fn account_for_funding(f: T::AccountId, a: BalanceOf<T>, reserve: bool, h: T::Hash) -> Result {
+        let amount: u128 = <T::ProjectConversions as Convert<BalanceOf<T>, u128>>::convert(a);
+        if amount > i128::max_value() as u128 {
+            Self::deposit_event(RawEvent::ErrorInAccounting(h));
+            return Err("The project funding is too large to be posted");
+        }
+        let n: i128 = if reserve { amount as i128 } else { amount as i128 * -1 };
+        let increase: AccountBalanceOf<T> = <T::ProjectConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n);
+        let decrease: AccountBalanceOf<T> = <T::ProjectConversions as Convert<i128, AccountBalanceOf<T>>>::convert(n * -1);
+
+        let current_block = <system::Module<T>>::block_number();
+        let current_block_dupe = <system::Module<T>>::block_number();
+
+        let account_1: AccountOf<T> = <T::ProjectConversions as Convert<u64, AccountOf<T>>>::convert(110100050000000u64); // Debit  increase 110100050000000 Totem Runtime Deposit (Escrow)
+        let account_2: AccountOf<T> = <T::ProjectConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // Credit decrease 110100040000000 XTX Balance
+
+        let mut forward_keys = Vec::<(T::AccountId, T::AccountId, AccountOf<T>, AccountBalanceOf<T>, bool, T::Hash, T::BlockNumber, T::BlockNumber)>::with_capacity(2);
+        forward_keys.push((f.clone(), f.clone(), account_1, increase, !reserve, h, current_block, current_block_dupe));
+        forward_keys.push((f.clone(), f.clone(), account_2, decrease, reserve, h, current_block, current_block_dupe));
+
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorInAccounting(h));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        Ok(())
     }
 }
 
@@ -267,11 +415,18 @@ decl_event!(
         AccountId = <T as system::Trait>::AccountId,
         Hash = <T as system::Trait>::Hash,
         ProjectStatus = u16,
+        Balance = BalanceOf<T>,
     {
         ProjectRegistered(Hash, AccountId),
         ProjectDeleted(Hash, AccountId, AccountId, ProjectStatus),
         ProjectReassigned(Hash, AccountId, AccountId),
         ProjectChanged(Hash, AccountId, ProjectStatus),
+        /// Project, funder, total funding reserved
+        ProjectFunded(Hash, AccountId, Balance),
+        /// Project, funder, funding returned
+        ProjectFundingReleased(Hash, AccountId, Balance),
+        /// The funding of the project could not be posted to the ledger
+        ErrorInAccounting(Hash),
     }
 );
 
@@ -328,6 +483,14 @@ impl<T: Trait> Validating<T::AccountId,T::Hash> for Module<T> {
 
         return valid;
     }
+
+    fn get_project_owner(h: T::Hash) -> Option<T::AccountId> {
+        Self::project_hash_owner(h)
+    }
+
+    fn get_owner_projects(o: T::AccountId) -> Vec<T::Hash> {
+        Self::owner_projects_list(o)
+    }
 }
 
 /// tests for this module
@@ -335,57 +498,59 @@ impl<T: Trait> Validating<T::AccountId,T::Hash> for Module<T> {
 mod tests {
     use super::*;
 
-    use primitives::{Blake2Hasher, H256};
+    use substrate_primitives::{Blake2Hasher, H256};
     use runtime_io::with_externalities;
-    use runtime_primitives::{
-        testing::{Digest, DigestItem, Header},
-        traits::{BlakeTwo256, IdentityLookup},
-        BuildStorage,
-    };
-    use support::{assert_ok, impl_outer_origin};
-
-    impl_outer_origin! {
-        pub enum Origin for Test {}
-    }
+    use support::{assert_err, assert_ok};
+    use crate::mock::{account, Origin, Test};
 
-    // For testing the module, we construct most of a mock runtime. This means
-    // first constructing a configuration type (`Test`) which `impl`s each of the
-    // configuration traits of modules we want to use.
-    #[derive(Clone, Eq, PartialEq)]
-    pub struct Test;
-    impl system::Trait for Test {
-        type Origin = Origin;
-        type Index = u64;
-        type BlockNumber = u64;
-        type Hash = H256;
-        type Hashing = BlakeTwo256;
-        type Digest = Digest;
-        type AccountId = u64;
-        type Lookup = IdentityLookup<Self::AccountId>;
-        type Header = Header;
-        type Event = ();
-        type Log = DigestItem;
-    }
-    impl Trait for Test {
-        type Event = ();
-    }
     type ProjectModule = Module<Test>;
+    type Balances = balances::Module<Test>;
+    type Accounting = accounting::Module<Test>;
 
-    // This function basically just builds a genesis storage key/value store according to
-    // our desired mockup.
     fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
-        system::GenesisConfig::<Test>::default()
-            .build_storage()
-            .unwrap()
-            .0
-            .into()
+        crate::mock::new_test_ext(vec![(account(1), 1_000)])
     }
 
-    // #[test]
-    // fn it_works_for_default_value() {
-    // 	with_externalities(&mut new_test_ext(), || {
-    // 		assert_ok!(ProjectModule::do_something(Origin::signed(1), 42));
-    // 		assert_eq!(ProjectModule::something(), Some(42));
-    // 	});
-    // }
+    #[test]
+    fn projects_are_activated_once_funded_or_confirmed() {
+        with_externalities(&mut new_test_ext(), || {
+            let funded = H256::repeat_byte(1);
+            let unfunded = H256::repeat_byte(2);
+            assert_ok!(ProjectModule::set_minimum_project_funding(Origin::ROOT, 500));
+            assert_ok!(ProjectModule::add_new_project(Origin::signed(account(1)), funded));
+            assert_ok!(ProjectModule::add_new_project(Origin::signed(account(1)), unfunded));
+            assert!(!ProjectModule::is_project_valid(funded));
+            
+            // Draft projects only become active with the minimum funding or the confirmation of the owner
+            assert_ok!(ProjectModule::fund_project(Origin::signed(account(1)), funded, 300));
+            assert_eq!(ProjectModule::project_hash_status(funded), Some(STATUS_DRAFT));
+            assert_err!(
+                ProjectModule::activate_project(Origin::signed(account(1)), funded, false),
+                "The project is below the minimum funding. The owner must confirm the activation"
+            );
+            assert_ok!(ProjectModule::fund_project(Origin::signed(account(1)), funded, 200));
+            assert_eq!(ProjectModule::project_hash_status(funded), Some(STATUS_FUNDED));
+            assert_eq!(Balances::reserved_balance(&account(1)), 500);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100050000000)), 500);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100040000000)), -500);
+            assert_ok!(ProjectModule::activate_project(Origin::signed(account(1)), funded, false));
+            assert!(ProjectModule::is_project_valid(funded));
+            
+            assert_err!(
+                ProjectModule::activate_project(Origin::signed(account(2)), unfunded, true),
+                "You cannot activate a project you do not own"
+            );
+            assert_ok!(ProjectModule::activate_project(Origin::signed(account(1)), unfunded, true));
+            assert!(ProjectModule::is_project_valid(unfunded));
+            
+            // On hold projects take no bookings, closing returns the funding
+            assert_ok!(ProjectModule::set_status_project(Origin::signed(account(1)), funded, STATUS_ON_HOLD));
+            assert!(!ProjectModule::is_project_valid(funded));
+            assert_ok!(ProjectModule::close_project(Origin::signed(account(1)), funded));
+            assert_eq!(Balances::reserved_balance(&account(1)), 0);
+            assert!(ProjectModule::project_funding(funded).is_none());
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100050000000)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(1), 110100040000000)), 0);
+        });
+    }
 }
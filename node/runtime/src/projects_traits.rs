@@ -35,8 +35,12 @@
 
 // use support::{ dispatch::Result };
 
+use rstd::prelude::Vec;
+
 pub trait Validating<AccountId, Hash> {
     fn is_project_owner(o: AccountId, h: Hash) -> bool;
     fn is_owner_and_project_valid(o: AccountId, h: Hash) -> bool;
     fn is_project_valid(h: Hash) -> bool;
+    fn get_project_owner(h: Hash) -> Option<AccountId>;
+    fn get_owner_projects(o: AccountId) -> Vec<Hash>;
 }
\ No newline at end of file
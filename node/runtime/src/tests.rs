@@ -0,0 +1,257 @@
+//! Cross-module tests against the runtime as it is wired by `construct_runtime!`.
+//! Orders, prefunding, transfers and fees are run through the real balances and accounting modules and the invariants
+//! between the ledgers and the wallet balances are checked after every step of a flow.
+
+#![cfg(test)]
+
+use super::*;
+use accounting::Posting;
+use orders::OrderItem;
+use prefunding::{FEES_PAID_BY_BUYER, FEES_SHARED_EQUALLY, STATUS_SELLER_DEFAULTED};
+use runtime_io::with_externalities;
+use runtime_primitives::traits::{Hash as HashT, MakePayment, OnFinalize};
+use substrate_primitives::{sr25519, Blake2Hasher, H256};
+use support::assert_ok;
+
+type LedgerBalance = i128;
+
+const GENESIS_BALANCE: Balance = 100_000;
+const XTX_BALANCE: u64 = 110100040000000;
+const ESCROW: u64 = 110100050000000;
+
+fn account(n: u8) -> AccountId {
+    sr25519::Public([n; 32])
+}
+
+fn new_test_ext(transaction_base_fee: Balance) -> runtime_io::TestExternalities<Blake2Hasher> {
+    let mut t = system::GenesisConfig::<Runtime>::default()
+        .build_storage()
+        .unwrap()
+        .0;
+    t.extend(
+        balances::GenesisConfig::<Runtime> {
+            transaction_base_fee,
+            transaction_byte_fee: 0,
+            balances: (1..=3).map(|n| (account(n), GENESIS_BALANCE)).collect(),
+            existential_deposit: 0,
+            transfer_fee: 0,
+            creation_fee: 0,
+            vesting: vec![],
+        }
+        .build_storage()
+        .unwrap()
+        .0,
+    );
+    t.into()
+}
+
+/// Every identity that can appear in the ledgers during the flows
+fn identities() -> Vec<AccountId> {
+    let mut identities: Vec<AccountId> = (1..=3).map(account).collect();
+    identities.push(<Accounting as Posting<AccountId, Hash, BlockNumber, Balance>>::get_escrow_account());
+    identities.push(<Accounting as Posting<AccountId, Hash, BlockNumber, Balance>>::get_netfees_account());
+    identities
+}
+
+/// The global ledger equals the sum of the ledgers of all identities.
+/// The XTX ledger is realigned to the wallet by the fee handler and has no global balance in that case.
+fn assert_global_ledger_is_sum(skip_xtx: bool) {
+    for a in Accounting::global_ledger_accounts().into_iter() {
+        if skip_xtx && a == XTX_BALANCE {
+            continue;
+        }
+        let sum: LedgerBalance = identities().into_iter().map(|o| Accounting::balance_by_ledger((o, a))).sum();
+        assert_eq!(Accounting::global_ledger(a), sum);
+    }
+}
+
+/// Per identity, the absolute debits recorded in the posting detail equal the absolute credits
+fn assert_identities_balance() {
+    for o in identities().into_iter() {
+        let mut debits: LedgerBalance = 0;
+        let mut credits: LedgerBalance = 0;
+        for a in Accounting::accounts_by_id(o.clone()).into_iter() {
+            for i in Accounting::id_account_posting_id_list((o.clone(), a)).into_iter() {
+                let lines = Accounting::posting_lines(o.clone(), a, i);
+                assert!(!lines.is_empty(), "posting detail exists");
+                for detail in lines.into_iter() {
+                    match detail.3 {
+                        false => debits += detail.2,
+                        true => credits += detail.2,
+                    }
+                }
+            }
+        }
+        assert_eq!(debits, credits);
+    }
+}
+
+/// The XTX ledger of each wallet moves with its free balance, and the escrow ledger of each owner matches the funds
+/// locked for every open prefunding.
+fn assert_wallets_match_ledgers() {
+    for n in 1..=3 {
+        let who = account(n);
+        let movement = Balances::free_balance(&who) as LedgerBalance - GENESIS_BALANCE as LedgerBalance;
+        assert_eq!(Accounting::balance_by_ledger((who.clone(), XTX_BALANCE)), movement);
+
+        let reconciliation = PrefundingModule::escrow_reconciliation(who.clone());
+        for (_, locked, escrowed) in reconciliation.iter() {
+            assert_eq!(*locked as LedgerBalance, *escrowed);
+        }
+        let locked: LedgerBalance = reconciliation.iter().map(|r| r.1 as LedgerBalance).sum();
+        assert_eq!(Accounting::balance_by_ledger((who, ESCROW)), locked);
+    }
+}
+
+fn assert_invariants() {
+    assert_global_ledger_is_sum(false);
+    assert_identities_balance();
+    assert_wallets_match_ledgers();
+}
+
+fn order(buyer: AccountId, seller: AccountId, amount: i128, n: u8) -> Hash {
+    let item = OrderItem { product: H256::repeat_byte(30), unit_price: amount, quantity: 1, unit_of_measure: 1 };
+    assert_ok!(OrdersModule::create_spfso(
+        Origin::signed(buyer.clone()), buyer.clone(), seller, 0, amount, false, 0, 20_000, 0, 40_000, item, None,
+        1, H256::repeat_byte(n), H256::repeat_byte(n)
+    ));
+    *OrdersModule::owner(buyer).last().expect("order is stored")
+}
+
+#[test]
+fn order_prefund_invoice_settle_keeps_ledgers_and_wallets_aligned() {
+    with_externalities(&mut new_test_ext(0), || {
+        System::set_block_number(1);
+        let h = order(account(1), account(2), 1000, 1);
+        assert_invariants();
+        assert_eq!(Balances::free_balance(&account(1)), GENESIS_BALANCE - 1000);
+
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(2)));
+        assert_invariants();
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(3)));
+        assert_invariants();
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(4)));
+        assert_invariants();
+
+        assert_eq!(OrdersModule::orders(h).expect("order exists").order_status, 6);
+        assert_eq!(Accounting::sub_ledger_balance((account(1), ESCROW, h)), 0);
+        assert_eq!(Balances::free_balance(&account(1)), GENESIS_BALANCE - 1000);
+        assert_eq!(Balances::free_balance(&account(2)), GENESIS_BALANCE + 1000);
+
+        // The settlement certificate is found by its id, the hash of the encoded certificate
+        let (id, certificate) = PrefundingModule::settlement_certificate_of(h).expect("certificate is issued");
+        assert_eq!(id, BlakeTwo256::hash(certificate.encode().as_slice()));
+        assert_eq!((certificate.payer, certificate.beneficiary, certificate.paid), (account(1), account(2), 1000));
+        assert_eq!(certificate.postings, <Accounting as Posting<AccountId, Hash, BlockNumber, Balance>>::get_posting_indexes_by_reference(h));
+        assert!(certificate.postings.len() >= 3);
+    });
+}
+
+#[test]
+fn transfers_between_wallets_keep_ledgers_aligned() {
+    with_externalities(&mut new_test_ext(0), || {
+        System::set_block_number(1);
+        let transfers: [(u8, u8, Balance); 4] = [(1, 2, 500), (2, 3, 700), (3, 1, 50), (1, 3, 1)];
+        for (i, (from, to, amount)) in transfers.iter().enumerate() {
+            assert_ok!(TransferModule::network_currency(Origin::signed(account(*from)), account(*to), *amount, H256::repeat_byte(i as u8 + 1)));
+            assert_invariants();
+        }
+        assert_eq!(Balances::free_balance(&account(3)), GENESIS_BALANCE + 651);
+    });
+}
+
+#[test]
+fn fees_are_accounted_and_realign_the_xtx_ledger() {
+    with_externalities(&mut new_test_ext(10), || {
+        System::set_block_number(1);
+        let pay = |who: &AccountId| <accounting::AccountedPayment<Runtime, Balances, Balances> as MakePayment<AccountId>>::make_payment(who, 0);
+
+        assert_ok!(pay(&account(1)));
+        assert_ok!(TransferModule::network_currency(Origin::signed(account(1)), account(2), 100, H256::repeat_byte(1)));
+        assert_ok!(pay(&account(2)));
+        // The fees are posted at the end of the block
+        <Accounting as OnFinalize<BlockNumber>>::on_finalize(1);
+
+        for (n, fees) in [(1u8, 10), (2, 10)].iter() {
+            let who = account(*n);
+            assert_eq!(Accounting::balance_by_ledger((who.clone(), XTX_BALANCE)), Balances::free_balance(&who) as LedgerBalance);
+            assert_eq!(Accounting::balance_by_ledger((who, 250500300000000)), *fees);
+        }
+        assert_global_ledger_is_sum(true);
+    });
+}
+
+#[test]
+fn accepted_counterproposals_adjust_the_lock_and_keep_ledgers_aligned() {
+    with_externalities(&mut new_test_ext(0), || {
+        System::set_block_number(1);
+        let h = order(account(1), account(2), 1000, 1);
+
+        // Increase, then decrease the prefunded amount
+        for (n, amount) in [(2u8, 1200i128), (4, 800)].iter() {
+            assert_ok!(OrdersModule::propose_counter(Origin::signed(account(2)), h, *amount, 25_000, H256::repeat_byte(*n)));
+            assert_ok!(OrdersModule::accept_counterproposal(Origin::signed(account(1)), h, H256::repeat_byte(*n + 1)));
+            assert_invariants();
+            assert_eq!(Balances::free_balance(&account(1)), GENESIS_BALANCE - *amount as Balance);
+            assert_eq!(Accounting::sub_ledger_balance((account(1), ESCROW, h)), *amount);
+        }
+        assert_eq!(PrefundingModule::prefunding(h).map(|p| p.1), Some(25_000));
+
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(6)));
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 5, H256::repeat_byte(7)));
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(1)), h, 6, H256::repeat_byte(8)));
+        assert_invariants();
+        assert_eq!(Balances::free_balance(&account(2)), GENESIS_BALANCE + 800);
+    });
+}
+
+#[test]
+fn fees_are_shared_between_the_parties_as_agreed() {
+    with_externalities(&mut new_test_ext(10), || {
+        System::set_block_number(1);
+        let pay = |who: &AccountId| <accounting::AccountedPayment<Runtime, Balances, Balances> as MakePayment<AccountId>>::make_payment(who, 0);
+        let h = order(account(1), account(2), 1000, 1);
+        assert_ok!(PrefundingModule::set_fee_sharing(Origin::signed(account(1)), h, FEES_PAID_BY_BUYER, H256::repeat_byte(2)));
+
+        // The seller accepts and the buyer bears the fee
+        System::set_extrinsic_index(1);
+        assert_ok!(pay(&account(2)));
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(3)));
+        assert!(PrefundingModule::set_fee_sharing(Origin::signed(account(1)), h, FEES_SHARED_EQUALLY, H256::repeat_byte(4)).is_err());
+        <Accounting as OnFinalize<BlockNumber>>::on_finalize(1);
+
+        let fees = |n: u8| Accounting::balance_by_ledger((account(n), 250500300000000));
+        assert_eq!((fees(1), fees(2)), (10, 0));
+        assert_eq!(Accounting::balance_by_ledger((account(2), 110100090000000)), 10);
+        assert_eq!(Accounting::balance_by_ledger((account(1), 120200030000000)), 10);
+        assert_eq!(Balances::free_balance(&account(2)), GENESIS_BALANCE - 10);
+        assert_global_ledger_is_sum(true);
+        assert_identities_balance();
+    });
+}
+
+#[test]
+fn claiming_the_performance_bond_returns_the_prefund_to_the_buyer() {
+    with_externalities(&mut new_test_ext(0), || {
+        System::set_block_number(1);
+        let h = order(account(1), account(2), 1000, 1);
+        assert_ok!(OrdersModule::set_performance_bond_terms(Origin::signed(account(1)), h, 10, 50, H256::repeat_byte(2)));
+        assert_ok!(OrdersModule::handle_spfso(Origin::signed(account(2)), h, 1, H256::repeat_byte(3)));
+        assert_eq!(Balances::locks(&account(2)).len(), 1);
+
+        // The accepted funds are locked for both parties until the seller defaults
+        assert!(PrefundingModule::cancel_prefunded_closed_order(Origin::signed(account(1)), h, H256::repeat_byte(4)).is_err());
+        System::set_block_number(40_001);
+        assert!(OrdersModule::claim_performance_bond(Origin::signed(account(2)), h, H256::repeat_byte(5)).is_err());
+        assert_ok!(OrdersModule::claim_performance_bond(Origin::signed(account(1)), h, H256::repeat_byte(6)));
+
+        // Half of the bond is paid to the buyer, the prefund is unlocked and the rest of the bond is released
+        assert_eq!(OrdersModule::orders(h).expect("order exists").order_status, 7);
+        assert_eq!(PrefundingModule::reference_status(h), STATUS_SELLER_DEFAULTED);
+        assert!(PrefundingModule::prefunding(h).is_none());
+        assert!(Balances::locks(&account(1)).is_empty());
+        assert!(Balances::locks(&account(2)).is_empty());
+        assert_eq!(Balances::free_balance(&account(1)), GENESIS_BALANCE + 50);
+        assert_eq!(Balances::free_balance(&account(2)), GENESIS_BALANCE - 50);
+    });
+}
@@ -39,17 +39,19 @@ use support::{
     decl_storage, 
     dispatch::Result, 
     ensure, 
-    StorageMap
+    StorageMap,
+    StorageValue
 };
 use system::ensure_signed;
 use parity_codec::{Decode, Encode};
-use runtime_primitives::traits::Hash;
+use runtime_primitives::traits::{As, Hash};
 // use node_primitives::Hash as ReferenceHash;
 use rstd::prelude::*;
 
 // Totem crates
-use crate::timekeeping_traits::{ Validating };
+use crate::timekeeping_traits::{ Billing, Validating };
 use crate::projects_traits::{ Validating as ProjectValidating};
+use accounting::OnPeriodClose;
 
 pub trait Trait: system::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
@@ -68,6 +70,19 @@ pub type ReasonCodeType = u16; // Category of reason code (TODO categories to be
                                // pub type ReasonCodeText = Vec<u8>; // Reason for status change in text (not on chain!)
 pub type BanStatus = bool; // Ban status (default is false)
 
+// Maximum number of pending invitations a project owner can have outstanding for one project
+pub const MAX_PROJECT_INVITES: usize = 50;
+
+// Reason code set by the runtime when an accepted time record is locked because the accounting period has been closed
+pub const REASON_PERIOD_CLOSED: ReasonCode = 10;
+// Reason code for time worked in a closed accounting period that is submitted late as an adjustment
+pub const REASON_LATE_ADJUSTMENT: ReasonCode = 11;
+// Category of the reason codes related to accounting periods
+pub const REASON_TYPE_PERIOD: ReasonCodeType = 10;
+
+// Maximum number of time records checked for locking after period closes in one block
+pub const MAX_PERIOD_CLOSE_LOCKS: u32 = 100;
+
 // Tuple for reason code changes
 #[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -131,7 +146,7 @@ decl_storage! {
         // Used mainly by the Project owner, but other workers can be seen.
         // The two here will logically replace the above two storage items, however as much of the code is dependent on the status
         // there will have to be a re-write.
-        // Note: Currently unbounded Vec!
+        // Note: Invites are bounded by MAX_PROJECT_INVITES, the workers list is currently unbounded!
         ProjectInvitesList get(project_invites_list): map T::Hash => Vec<T::AccountId>;
         ProjectWorkersList get(project_workers_list): map T::Hash => Vec<T::AccountId>;
 
@@ -171,6 +186,18 @@ decl_storage! {
         TimeRecord get(time_record): map T::Hash => Option<Timekeeper<T::AccountId,T::Hash,NumberOfBlocks,LockStatus,StatusOfTimeRecord,ReasonCodeStruct,PostingPeriod,StartOrEndBlockNumber,NumberOfBreaks>>;
         
         // ARCHIVE Experimental! May go somewhere else in future
+        // Block up to which the accounting periods of an identity are closed. 
+        // Accepted time records dated up to this block are locked, and time dated up to this block can only be submitted as an adjustment.
+        ClosedUntil get(closed_until): map T::AccountId => StartOrEndBlockNumber;
+        // Identities whose accepted time records are still to be locked after a period close.
+        // The records are locked in on_finalize, at most MAX_PERIOD_CLOSE_LOCKS per block.
+        PeriodCloseLocks get(period_close_locks): Vec<T::AccountId>;
+        // Position of the locking of an identity: (list, index in the list, records locked so far).
+        // List 0 is the worker list of the identity, list n the time records of the nth project it owns.
+        PeriodCloseLockCursor get(period_close_lock_cursor): map T::AccountId => (u32, u32, u32);
+        // Order on which an accepted time record was invoiced
+        InvoicedTime get(invoiced_time): map T::Hash => Option<T::Hash>;
+        
         WorkerTimeRecordsHashListArchive get(worker_time_records_hash_list_archive): map T::AccountId => Vec<T::Hash>;
         ProjectTimeRecordsHashListArchive get(project_time_records_hash_list_archive): map T::Hash => Vec<T::Hash>;
     }
@@ -179,6 +206,10 @@ decl_storage! {
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event<T>() = default;
+        
+        fn on_finalize() {
+            Self::lock_closed_time_records();
+        }
         // Project owner invites worker/team member to project
         fn notify_project_worker(origin, worker: T::AccountId, project_hash: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
@@ -197,6 +228,9 @@ decl_module! {
                 None => (),  // OK this project has not been assigned yet.
             };
 
+            // ensure that the worker is not banned from the project
+            ensure!(!<ProjectWorkersBanList<T>>::exists(&status_tuple_key), "This worker is banned!");
+
             if who == worker {
                 
                 // Adds project to list of projects assigned to worker address (in this case worker is project owner)
@@ -208,6 +242,9 @@ decl_module! {
 
             } else {
                 // the worker is not the project owner
+                // ensure that the outstanding invitations for this project are within bounds
+                ensure!(Self::project_invites_list(&project_hash).len() < MAX_PROJECT_INVITES, "Too many outstanding invitations for this project.");
+
                 // The initial status of the acceptance to work on the project
                 let accepted_status: AcceptAssignedStatus = false;
 
@@ -276,7 +313,7 @@ decl_module! {
                                     project_invites_list.retain(|h| h != &who)
                                 });
 
-
+                                Self::deposit_event(RawEvent::WorkerDeclinedProject(who.clone(), project_hash));
                             },
                             Some(true) => return Err("Cannot remove project that has been accepted already."),
                             None => return Err("Project worker has not been assigned to this project yet."),
@@ -288,6 +325,35 @@ decl_module! {
             Ok(())
         }
 
+        // Project owner withdraws an invitation that the worker has not yet accepted
+        fn revoke_project_invitation(origin, worker: T::AccountId, project_hash: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+
+            let hash_has_correct_owner = <<T as Trait>::Projects as ProjectValidating<T::AccountId, T::Hash>>::is_owner_and_project_valid(who.clone(), project_hash.clone());
+            ensure!(hash_has_correct_owner, "Invalid project or project owner is not correct");
+
+            let status_tuple_key = (project_hash.clone(), worker.clone());
+            match Self::worker_projects_backlog_status(&status_tuple_key) {
+                Some(false) => (),
+                Some(true) => return Err("Cannot revoke an invitation that has been accepted already."),
+                None => return Err("Project worker has not been assigned to this project yet."),
+            };
+
+            <WorkerProjectsBacklogStatus<T>>::take(&status_tuple_key);
+
+            <WorkerProjectsBacklogList<T>>::mutate(&worker, |worker_projects_backlog_list| {
+                worker_projects_backlog_list.retain(|h| h != &project_hash)
+            });
+
+            <ProjectInvitesList<T>>::mutate(&project_hash, |project_invites_list| {
+                project_invites_list.retain(|h| h != &worker)
+            });
+
+            Self::deposit_event(RawEvent::InvitationRevoked(worker, project_hash));
+
+            Ok(())
+        }
+
         // Worker submits/resubmits time record
         fn submit_time(
             origin,
@@ -310,6 +376,9 @@ decl_module! {
             let ban_list_key = (project_hash.clone(), who.clone());
             ensure!(!<ProjectWorkersBanList<T>>::exists(&ban_list_key), "This worker is banned!");
             
+            // Check worker has formally accepted the project
+            ensure!(Self::worker_projects_backlog_status(&ban_list_key) == Some(true), "This identity has not accepted the project!");
+
             // Check worker is part of the team
             let check_team_member = who.clone();
             
@@ -318,6 +387,11 @@ decl_module! {
             .find(| x| x == &check_team_member)
             .ok_or("This identity has not been assigned the project!")?;
 
+            // Time worked in a closed accounting period of the worker or the project owner is submitted as an adjustment
+            let late_submission = Self::is_in_closed_period(who.clone(), project_hash.clone(), end_block_number);
+            let adjustment_reason = ReasonCodeStruct(REASON_LATE_ADJUSTMENT, REASON_TYPE_PERIOD);
+            ensure!(!late_submission || reason_for_change == adjustment_reason, "Time record is dated in a closed period. Submit it as an adjustment.");
+
                 // For testing
                 // let input_time_hash_2 = hex!("e4d673a76e8b32ca3989dbb9f444f71813c88d36120170b15151d58c7106cc83");
                 // let default_hash: TimeHash = hex!("e4d673a76e8b32ca3989dbb9f444f71813c88d36120170b15151d58c7106cc83");
@@ -352,7 +426,7 @@ decl_module! {
                                 locked_status: false,
                                 locked_reason: initial_reason_for_lock,
                                 submit_status: 1, // new record always gets status 1
-                                reason_code: initial_submit_reason,
+                                reason_code: if late_submission { adjustment_reason } else { initial_submit_reason },
                                 posting_period: 0, // temporary for this version of totem (meccano).
                                 start_block: start_block_number.into(),
                                 end_block: end_block_number.into(),
@@ -506,6 +580,9 @@ decl_module! {
                         old_time_record.end_block = new_time_data.end_block;
                         old_time_record.posting_period = new_time_data.posting_period;
                         old_time_record.nr_of_breaks = new_time_data.nr_of_breaks;
+                        if late_submission {
+                            old_time_record.reason_code = adjustment_reason;
+                        }
 
                         Self::update_time_record(original_time_key, old_time_record)?;
                     } 
@@ -662,6 +739,30 @@ impl<T: Trait> Module<T> {
 
     // TODO Move lock/unlock to private function
 
+    // Lists the projects the worker has accepted, that are still active and from which the worker is not banned.
+    // These are the only projects against which the worker can currently book time.
+    pub fn worker_active_engagements(worker: T::AccountId) -> Vec<T::Hash> {
+        Self::worker_projects_backlog_list(&worker)
+            .into_iter()
+            .filter(|h| {
+                let key = (h.clone(), worker.clone());
+                Self::worker_projects_backlog_status(&key) == Some(true)
+                    && !<ProjectWorkersBanList<T>>::exists(&key)
+                    && <<T as Trait>::Projects as ProjectValidating<T::AccountId, T::Hash>>::is_project_valid(h.clone())
+            })
+            .collect()
+    }
+
+    // Checks if the time ends in a closed accounting period of the worker or of the project owner
+    fn is_in_closed_period(worker: T::AccountId, project_hash: T::Hash, end_block: StartOrEndBlockNumber) -> bool {
+        let owner_closed_until: StartOrEndBlockNumber = match <<T as Trait>::Projects as ProjectValidating<T::AccountId, T::Hash>>::get_project_owner(project_hash) {
+            Some(owner) => Self::closed_until(owner),
+            None => 0,
+        };
+        let closed_until: StartOrEndBlockNumber = rstd::cmp::max(Self::closed_until(worker), owner_closed_until);
+        closed_until > 0 && end_block <= closed_until
+    }
+
     // When the worker accepts to work on the project, they are added to the team
     fn store_worker_acceptance(
         project_hash: T::Hash,
@@ -926,6 +1027,94 @@ impl<T: Trait> Validating<T::AccountId,T::Hash> for Module<T> {
     }
 }
 
+impl<T: Trait> Billing<T::AccountId, T::Hash> for Module<T> {
+    // Accepted time records of the worker on the project worked between the blocks, that have not been invoiced yet 
+    // (time record hash, number of blocks)
+    fn billable_time(w: T::AccountId, p: T::Hash, from: StartOrEndBlockNumber, to: StartOrEndBlockNumber) -> Vec<(T::Hash, NumberOfBlocks)> {
+        Self::project_time_records_hash_list(p)
+            .into_iter()
+            .filter(|h| !<InvoicedTime<T>>::exists(h))
+            .filter_map(|h| Self::time_record(&h).map(|r| (h, r)))
+            .filter(|(_, r)| r.worker == w && r.submit_status == 300 && r.start_block >= from && r.end_block <= to)
+            .map(|(h, r)| (h, r.total_blocks))
+            .collect()
+    }
+    // Sets the status of the time records to invoiced on the order, after which they can no longer be changed or billed again
+    fn set_invoiced(o: T::Hash, time_hashes: Vec<T::Hash>) {
+        for h in time_hashes.into_iter() {
+            if let Some(mut time_record) = Self::time_record(&h) {
+                time_record.submit_status = 400;
+                <TimeRecord<T>>::insert(&h, time_record);
+                <InvoicedTime<T>>::insert(&h, o);
+            }
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// Locks the accepted time records dated in the closed periods of the queued identities.
+    /// At most MAX_PERIOD_CLOSE_LOCKS time records are checked per block, the remainder is carried to the next block.
+    fn lock_closed_time_records() {
+        let mut queue: Vec<T::AccountId> = Self::period_close_locks();
+        if queue.is_empty() {
+            return;
+        }
+        let mut budget: u32 = MAX_PERIOD_CLOSE_LOCKS;
+        while budget > 0 && !queue.is_empty() {
+            let who: T::AccountId = queue[0].clone();
+            let closed_until: StartOrEndBlockNumber = Self::closed_until(&who);
+            let (mut list, mut index, mut locked) = Self::period_close_lock_cursor(&who);
+            let projects: Vec<T::Hash> = <<T as Trait>::Projects as ProjectValidating<T::AccountId, T::Hash>>::get_owner_projects(who.clone());
+            while budget > 0 && (list as usize) <= projects.len() {
+                let time_hashes: Vec<T::Hash> = match list {
+                    0 => Self::worker_time_records_hash_list(&who),
+                    n => Self::project_time_records_hash_list(projects[n as usize - 1]),
+                };
+                let start: usize = rstd::cmp::min(index as usize, time_hashes.len());
+                let end: usize = rstd::cmp::min(start.saturating_add(budget as usize), time_hashes.len());
+                for h in time_hashes[start..end].iter() {
+                    if let Some(mut time_record) = Self::time_record(h) {
+                        if time_record.submit_status == 300 && !time_record.locked_status && time_record.end_block <= closed_until {
+                            time_record.locked_status = true;
+                            time_record.locked_reason = ReasonCodeStruct(REASON_PERIOD_CLOSED, REASON_TYPE_PERIOD);
+                            <TimeRecord<T>>::insert(h, time_record);
+                            locked += 1;
+                        }
+                    }
+                }
+                // each list read counts, so that identities with many empty projects are also spread over blocks
+                budget = budget.saturating_sub(rstd::cmp::max(end - start, 1) as u32);
+                if end < time_hashes.len() {
+                    index = end as u32;
+                } else {
+                    list += 1;
+                    index = 0;
+                }
+            }
+            if (list as usize) <= projects.len() {
+                <PeriodCloseLockCursor<T>>::insert(&who, (list, index, locked));
+                break;
+            }
+            <PeriodCloseLockCursor<T>>::remove(&who);
+            queue.remove(0);
+            Self::deposit_event(RawEvent::TimeRecordsLockedAtPeriodClose(who, closed_until, locked));
+        }
+        <PeriodCloseLocks<T>>::put(queue);
+    }
+}
+
+impl<T: Trait> OnPeriodClose<T::AccountId, T::BlockNumber> for Module<T> {
+    // Closes the time of the identity up to the closing block. Time dated up to this block can no longer be changed.
+    // The accepted time records of the identity (as worker or as project owner) are locked in the following blocks.
+    fn on_period_close(who: &T::AccountId, closed_at: T::BlockNumber) {
+        let closed_until: StartOrEndBlockNumber = closed_at.as_();
+        <ClosedUntil<T>>::insert(who, closed_until);
+        // A later close restarts the locking of the identity with the new closing block
+        <PeriodCloseLockCursor<T>>::remove(who);
+        <PeriodCloseLocks<T>>::mutate(|queue| if !queue.contains(who) { queue.push(who.clone()) });
+    }
+}
+
 decl_event!(
     pub enum Event<T>
     where
@@ -937,6 +1126,8 @@ decl_event!(
         SubmitedTimeRecord(Hash),
         NotifyProjectWorker(AccountId, Hash),
         WorkerAcceptanceStatus(AccountId, Hash, AcceptAssignedStatus),
+        WorkerDeclinedProject(AccountId, Hash),
+        InvitationRevoked(AccountId, Hash),
         SetAuthoriseStatus(AccountId),
         InvoiceTime(AccountId),
         PayTime(AccountId),
@@ -946,5 +1137,210 @@ decl_event!(
         UnBanned(),
         IncreaseTotalBlocks(AccountId, Hash, NumberOfBlocks),
         DecreaseTotalBlocks(AccountId, Hash, NumberOfBlocks),
+        /// Identity, block up to which the period is closed, number of time records locked
+        TimeRecordsLockedAtPeriodClose(AccountId, NumberOfBlocks, u32),
+    }
+);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use runtime_primitives::traits::OnFinalize;
+    use support::{assert_err, assert_ok};
+    use crate::mock::{account, AccountId, Origin, Test};
+
+    type TimekeepingModule = Module<Test>;
+
+    // Every identity owns every project in the mock
+    const PROJECT: H256 = H256([7; 32]);
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![])
+    }
+
+    fn default_hash() -> H256 {
+        <Test as system::Trait>::Hashing::hash(&"Default hash".encode().as_slice())
+    }
+
+    fn join_project(worker: u8) {
+        assert_ok!(TimekeepingModule::notify_project_worker(Origin::signed(account(1)), account(worker), PROJECT));
+        assert_ok!(TimekeepingModule::worker_acceptance_project(Origin::signed(account(worker)), PROJECT, true));
+    }
+
+    fn submit(worker: u8, end_block: u64, reason: ReasonCodeStruct) -> Result {
+        TimekeepingModule::submit_time(Origin::signed(account(worker)), PROJECT, default_hash(), 1, reason, 480, 0, end_block - 480, end_block, 0)
+    }
+
+    fn close_period(who: AccountId, closed_at: u64) {
+        <TimekeepingModule as OnPeriodClose<AccountId, u64>>::on_period_close(&who, closed_at);
+    }
+
+    // Accepted time record of the worker ending at the block
+    fn accepted_time(worker: AccountId, end_block: u64) -> H256 {
+        let h = <Test as system::Trait>::Hashing::hash_of(&(worker.clone(), end_block));
+        <TimeRecord<Test>>::insert(h, Timekeeper {
+            worker: worker.clone(),
+            project_hash: PROJECT,
+            total_blocks: 1,
+            locked_status: false,
+            locked_reason: ReasonCodeStruct(0, 0),
+            submit_status: 300,
+            reason_code: ReasonCodeStruct(0, 0),
+            posting_period: 0,
+            start_block: end_block - 1,
+            end_block: end_block,
+            nr_of_breaks: 0,
+        });
+        <WorkerTimeRecordsHashList<Test>>::mutate(&worker, |worker_time_records_hash_list| worker_time_records_hash_list.push(h));
+        h
+    }
+
+    fn locked(hashes: &[H256]) -> usize {
+        hashes.iter().filter(|h| TimekeepingModule::time_record(*h).unwrap().locked_status).count()
+    }
+
+    #[test]
+    fn invitations_can_be_revoked_until_they_are_accepted() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(TimekeepingModule::notify_project_worker(Origin::signed(account(1)), account(2), PROJECT));
+            assert_eq!(TimekeepingModule::project_invites_list(PROJECT), vec![account(2)]);
+            
+            assert_ok!(TimekeepingModule::revoke_project_invitation(Origin::signed(account(1)), account(2), PROJECT));
+            assert!(TimekeepingModule::project_invites_list(PROJECT).is_empty());
+            assert!(TimekeepingModule::worker_projects_backlog_list(account(2)).is_empty());
+            assert_eq!(TimekeepingModule::worker_projects_backlog_status((PROJECT, account(2))), None);
+            assert_err!(
+                TimekeepingModule::revoke_project_invitation(Origin::signed(account(1)), account(2), PROJECT),
+                "Project worker has not been assigned to this project yet."
+            );
+            assert_err!(
+                TimekeepingModule::worker_acceptance_project(Origin::signed(account(2)), PROJECT, true),
+                "This identity has not been assigned the project!"
+            );
+            
+            join_project(2);
+            assert_err!(
+                TimekeepingModule::revoke_project_invitation(Origin::signed(account(1)), account(2), PROJECT),
+                "Cannot revoke an invitation that has been accepted already."
+            );
+            assert_eq!(TimekeepingModule::project_workers_list(PROJECT), vec![account(2)]);
+        });
     }
-);
\ No newline at end of file
+
+    #[test]
+    fn outstanding_invitations_are_bounded() {
+        with_externalities(&mut new_test_ext(), || {
+            for n in 0..MAX_PROJECT_INVITES {
+                assert_ok!(TimekeepingModule::notify_project_worker(Origin::signed(account(1)), account(n as u8 + 2), PROJECT));
+            }
+            let next = account(MAX_PROJECT_INVITES as u8 + 2);
+            assert_err!(
+                TimekeepingModule::notify_project_worker(Origin::signed(account(1)), next.clone(), PROJECT),
+                "Too many outstanding invitations for this project."
+            );
+            assert!(TimekeepingModule::worker_projects_backlog_list(next.clone()).is_empty());
+            
+            // An accepted invitation is no longer outstanding
+            assert_ok!(TimekeepingModule::worker_acceptance_project(Origin::signed(account(2)), PROJECT, true));
+            assert_ok!(TimekeepingModule::notify_project_worker(Origin::signed(account(1)), next, PROJECT));
+            assert_eq!(TimekeepingModule::project_invites_list(PROJECT).len(), MAX_PROJECT_INVITES);
+        });
+    }
+
+    #[test]
+    fn time_is_only_accepted_from_workers_who_accepted_the_project() {
+        with_externalities(&mut new_test_ext(), || {
+            let no_reason = ReasonCodeStruct(0, 0);
+            assert_err!(submit(2, 1000, no_reason.clone()), "This identity has not accepted the project!");
+            
+            assert_ok!(TimekeepingModule::notify_project_worker(Origin::signed(account(1)), account(2), PROJECT));
+            assert_err!(submit(2, 1000, no_reason.clone()), "This identity has not accepted the project!");
+            assert!(TimekeepingModule::worker_time_records_hash_list(account(2)).is_empty());
+            
+            assert_ok!(TimekeepingModule::worker_acceptance_project(Origin::signed(account(2)), PROJECT, true));
+            assert_ok!(submit(2, 1000, no_reason.clone()));
+            assert_eq!(TimekeepingModule::worker_time_records_hash_list(account(2)).len(), 1);
+            
+            // Banned workers can neither be invited nor book time
+            <ProjectWorkersBanList<Test>>::insert((PROJECT, account(3)), BannedStruct(true, ReasonCodeStruct(0, 0)));
+            assert_err!(
+                TimekeepingModule::notify_project_worker(Origin::signed(account(1)), account(3), PROJECT),
+                "This worker is banned!"
+            );
+            <ProjectWorkersBanList<Test>>::insert((PROJECT, account(2)), BannedStruct(true, ReasonCodeStruct(0, 0)));
+            assert_err!(submit(2, 2000, no_reason), "This worker is banned!");
+        });
+    }
+
+    #[test]
+    fn time_in_a_closed_period_is_only_accepted_as_an_adjustment() {
+        with_externalities(&mut new_test_ext(), || {
+            join_project(2);
+            close_period(account(2), 1000);
+            
+            assert_err!(
+                submit(2, 1000, ReasonCodeStruct(0, 0)),
+                "Time record is dated in a closed period. Submit it as an adjustment."
+            );
+            assert!(TimekeepingModule::worker_time_records_hash_list(account(2)).is_empty());
+            
+            // Time after the close is submitted as usual
+            assert_ok!(submit(2, 1001, ReasonCodeStruct(0, 0)));
+            
+            assert_ok!(submit(2, 1000, ReasonCodeStruct(REASON_LATE_ADJUSTMENT, REASON_TYPE_PERIOD)));
+            let hashes = TimekeepingModule::worker_time_records_hash_list(account(2));
+            assert_eq!(hashes.len(), 2);
+            let adjustment = TimekeepingModule::time_record(hashes[1]).unwrap();
+            assert_eq!(adjustment.end_block, 1000);
+            assert_eq!(adjustment.reason_code, ReasonCodeStruct(REASON_LATE_ADJUSTMENT, REASON_TYPE_PERIOD));
+            assert_eq!(TimekeepingModule::time_record(hashes[0]).unwrap().reason_code, ReasonCodeStruct(0, 0));
+        });
+    }
+
+    #[test]
+    fn accepted_time_is_locked_over_several_blocks_after_a_period_close() {
+        with_externalities(&mut new_test_ext(), || {
+            let batch = MAX_PERIOD_CLOSE_LOCKS as u64;
+            let hashes: Vec<H256> = (1..=batch + 50).map(|n| accepted_time(account(2), n)).collect();
+            let later = accepted_time(account(2), 1000);
+            close_period(account(2), 500);
+            
+            <TimekeepingModule as OnFinalize<u64>>::on_finalize(1);
+            assert_eq!(locked(&hashes), batch as usize);
+            assert_eq!(TimekeepingModule::period_close_lock_cursor(account(2)), (0, MAX_PERIOD_CLOSE_LOCKS, MAX_PERIOD_CLOSE_LOCKS));
+            assert_eq!(TimekeepingModule::period_close_locks(), vec![account(2)]);
+            
+            // The next block resumes where the cursor stopped
+            <TimekeepingModule as OnFinalize<u64>>::on_finalize(2);
+            assert_eq!(locked(&hashes), hashes.len());
+            assert!(!TimekeepingModule::time_record(later).unwrap().locked_status);
+            assert_eq!(TimekeepingModule::time_record(hashes[0]).unwrap().locked_reason, ReasonCodeStruct(REASON_PERIOD_CLOSED, REASON_TYPE_PERIOD));
+            assert!(!<PeriodCloseLockCursor<Test>>::exists(account(2)));
+            assert!(TimekeepingModule::period_close_locks().is_empty());
+        });
+    }
+
+    #[test]
+    fn second_period_close_restarts_the_locking_with_the_new_closing_block() {
+        with_externalities(&mut new_test_ext(), || {
+            let batch = MAX_PERIOD_CLOSE_LOCKS as u64;
+            let hashes: Vec<H256> = (1..=batch + 50).map(|n| accepted_time(account(2), n)).collect();
+            close_period(account(2), 50);
+            <TimekeepingModule as OnFinalize<u64>>::on_finalize(1);
+            assert_eq!(locked(&hashes), 50);
+            assert!(<PeriodCloseLockCursor<Test>>::exists(account(2)));
+            
+            // The records already passed by the cursor are checked again against the new closing block
+            close_period(account(2), batch + 20);
+            assert!(!<PeriodCloseLockCursor<Test>>::exists(account(2)));
+            assert_eq!(TimekeepingModule::period_close_locks(), vec![account(2)]);
+            <TimekeepingModule as OnFinalize<u64>>::on_finalize(2);
+            <TimekeepingModule as OnFinalize<u64>>::on_finalize(3);
+            assert_eq!(locked(&hashes), (batch + 20) as usize);
+            assert!(!TimekeepingModule::time_record(hashes[(batch + 20) as usize]).unwrap().locked_status);
+            assert!(TimekeepingModule::period_close_locks().is_empty());
+        });
+    }
+}
@@ -35,7 +35,14 @@
 
 // use support::{ dispatch::Result };
 
+use rstd::prelude::Vec;
+
 pub trait Validating<AccountId, Hash> {
     fn is_time_record_owner(o: AccountId, h: Hash) -> bool;
     fn validate_and_archive(o: AccountId, h: Hash, a: bool) -> bool;
 }
+
+pub trait Billing<AccountId, Hash> {
+    fn billable_time(w: AccountId, p: Hash, from: u64, to: u64) -> Vec<(Hash, u64)>;
+    fn set_invoiced(o: Hash, h: Vec<Hash>);
+}
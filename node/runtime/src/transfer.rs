@@ -40,27 +40,22 @@
 // from the faucet so that funds are not resent to users when there is a network failure.
 //********************************************************//
 
+use parity_codec::{Decode, Encode};
 use support::{
     decl_event, 
     decl_module, 
-    dispatch::Result
+    decl_storage, 
+    dispatch::Result,
+    StorageMap
 };
-//v1
-// use frame_support::{decl_event, decl_error, decl_module, decl_storage, dispatch::DispatchResult, weights::{Weight, DispatchClass}, StorageValue, StorageMap}; // v2
 
-use system::{self, ensure_signed};
-//v1
-// use frame_system::{self}; //v2
+use system::{self, ensure_root, ensure_signed};
 
 use rstd::prelude::*;
-//v1
-// use sp_std::prelude::*; //v2
-use runtime_primitives::traits::{Convert};
+use runtime_primitives::traits::{Convert, CheckedSub, Zero};
 use support::traits::{Currency};
-//v1 
-// use frame_support::Traits{Currency}; // v2
 // Totem Pallets
-use accounting::{ Posting };
+use accounting::{ Posting, ORIGIN_ADJUSTMENT };
 
 // Totem Trait Types
 type AccountOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::Account;
@@ -70,6 +65,34 @@ type AccountBalanceOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Tr
 type CurrencyBalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
 use crate::bonsai_traits::{ Storing };
+use crate::boxkeys_traits::{ KeyVerifying };
+
+// ISO 11649 structured creditor reference in electronic format ("RF" + 2 check digits + up to 21 alphanumerics)
+pub type CreditorReference = Vec<u8>;
+
+// Maximum length of the part of the creditor reference following the check digits
+const MAX_CREDITOR_REFERENCE_BASE: usize = 21;
+
+// Maximum number of unmatched incoming payments indexed against one creditor reference of a recipient
+const MAX_INCOMING_BY_REFERENCE: usize = 100;
+
+// Maximum length of the amount and blinding encrypted to the recipient or to the auditor of a confidential transfer
+const MAX_CONFIDENTIAL_PAYLOAD_LENGTH: usize = 256;
+
+/// Record of a confidential transfer: the commitment to the amount, with the amount and the blinding of the commitment 
+/// encrypted to the box key of the recipient and to the auditor key. The record does not hide the amount, which is a 
+/// parameter of the transfer and appears in the balances and ledger events.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ConfidentialTransfer<AccountId, Hash> {
+    pub from: AccountId,
+    pub to: AccountId,
+    pub commitment: Hash,
+    pub recipient_key: Hash,
+    pub auditor_key: Hash,
+    pub for_recipient: Vec<u8>,
+    pub for_auditor: Vec<u8>,
+}
 
 pub trait Trait: system::Trait + balances::Trait + accounting::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
@@ -79,9 +102,28 @@ pub trait Trait: system::Trait + balances::Trait + accounting::Trait {
     + Convert<Self::Balance, i128>
     + Convert<u64, AccountOf<Self>>
     + Convert<CurrencyBalanceOf<Self>, i128>
-    + Convert<i128, AccountBalanceOf<Self>>;
+    + Convert<i128, AccountBalanceOf<Self>>
+    + Convert<u64, Self::BlockNumber>;
     type Bonsai: Storing<Self::Hash>;
     type Accounting: Posting<Self::AccountId,Self::Hash,Self::BlockNumber,Self::CoinAmount>;
+    type BoxKeys: KeyVerifying<Self::AccountId,Self::Hash>;
+}
+
+decl_storage! {
+    trait Store for Module<T: Trait> as TransferModule {
+        // Successor nominated by an identity and the number of blocks of inactivity after which the successor can claim the balance
+        Successor get(successor): map T::AccountId => Option<(T::AccountId, T::BlockNumber)>;
+        // Last observed activity of an identity (account nonce, block number at which the nonce was observed)
+        LastSeenActivity get(last_seen_activity): map T::AccountId => Option<(T::Index, T::BlockNumber)>;
+        // Pending inheritance claim (claimant, account nonce at claim, end of challenge window)
+        InheritanceClaim get(inheritance_claim): map T::AccountId => Option<(T::AccountId, T::Index, T::BlockNumber)>;
+        // Incoming payments to an identity by structured creditor reference (payer, amount, transaction)
+        IncomingByCreditorReference get(incoming_by_creditor_reference): map (T::AccountId, CreditorReference) => Vec<(T::AccountId, T::Balance, T::Hash)>;
+        // Designated auditor of confidential transfers and the verified box key the amounts are escrowed to
+        Auditor get(auditor): Option<(T::AccountId, T::Hash)>;
+        // Confidential transfers by transaction
+        ConfidentialTransfers get(confidential_transfer_record): map T::Hash => Option<ConfidentialTransfer<T::AccountId, T::Hash>>;
+    }
 }
 
 decl_module! {
@@ -101,70 +143,702 @@ decl_module! {
             // Convert incoming amount to currency for transfer
             let amount: CurrencyBalanceOf<T> = <T::TransferConversions as Convert<T::Balance, CurrencyBalanceOf<T>>>::convert(payment_amount.clone());
             let posting_amount: i128 = <T::TransferConversions as Convert<T::Balance, i128>>::convert(payment_amount);
-            let account_1: AccountOf<T> = <T::TransferConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // debit increase - credit decrease 110100040000000 XTX Balance
             
-            // Convert this for the inversion
-            let to_invert: i128 = 0i128 - posting_amount.clone();
+            Self::account_for_transfer(from.clone(), to.clone(), posting_amount, tx_uid, Vec::new())?;
 
-            let increase_amount: AccountBalanceOf<T> = <T::TransferConversions as Convert<i128, AccountBalanceOf<T>>>::convert(posting_amount);
-            let decrease_amount: AccountBalanceOf<T> = <T::TransferConversions as Convert<i128, AccountBalanceOf<T>>>::convert(to_invert);
+            match T::Currency::transfer(&from, &to, amount) {
+                Ok(_) => (),
+                Err(_) => {
+                    Self::deposit_event(RawEvent::ErrorDuringTransfer(tx_uid));
+                    return Err("Error during transfer");
+                },
+            }
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
+            Ok(())
+        }
+        /// Transfers funds quoting an ISO 11649 structured creditor reference supplied by the recipient (e.g. on an invoice).
+        /// The check digits are validated, the reference is stored against the postings of both parties 
+        /// and the payment is indexed so the recipient can match it.
+        fn network_currency_with_reference(
+            origin, 
+            to: T::AccountId, 
+            #[compact] payment_amount: T::Balance,
+            creditor_reference: CreditorReference,
+            tx_uid: T::Hash 
+        ) -> Result {
+            let from = ensure_signed(origin)?;
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid.clone())?;
             
-            // This sets the change block and the applicable posting period. For this context they will always be
-            // the same.
-            let current_block = <system::Module<T>>::block_number(); // For audit on change
-            let current_block_dupe = current_block.clone(); // Applicable period for accounting
-    
-            // Generate dummy Hash reference (it has no real bearing but allows posting to happen)
-            let tx_ref_hash: T::Hash = tx_uid.clone();
-                
-            // Keys for posting by payer
-            let mut forward_keys = Vec::<(
-                T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-            )>::with_capacity(2);
+            let creditor_reference: CreditorReference = match Self::validate_creditor_reference(creditor_reference) {
+                Some(r) => r,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorCreditorReference(tx_uid));
+                    return Err("Invalid creditor reference");
+                },
+            };
+            if Self::incoming_by_creditor_reference((to.clone(), creditor_reference.clone())).len() >= MAX_INCOMING_BY_REFERENCE {
+                Self::deposit_event(RawEvent::ErrorTooManyIncomingPayments(tx_uid));
+                return Err("Too many unmatched payments for this creditor reference");
+            }
+
+            let amount: CurrencyBalanceOf<T> = <T::TransferConversions as Convert<T::Balance, CurrencyBalanceOf<T>>>::convert(payment_amount.clone());
+            let posting_amount: i128 = <T::TransferConversions as Convert<T::Balance, i128>>::convert(payment_amount.clone());
             
-            // Sender
-            forward_keys.push((from.clone(),to.clone(),account_1,decrease_amount,true,tx_ref_hash,current_block,current_block_dupe,));
-            // Receiver
-            forward_keys.push((to.clone(),from.clone(),account_1,increase_amount,false,tx_ref_hash,current_block,current_block_dupe,));
+            Self::account_for_transfer(from.clone(), to.clone(), posting_amount, tx_uid, creditor_reference.clone())?;
+
+            match T::Currency::transfer(&from, &to, amount) {
+                Ok(_) => (),
+                Err(_) => {
+                    // The funds did not move, so the postings are reversed out and the payment is not indexed
+                    Self::reverse_transfer(from.clone(), to.clone(), posting_amount, tx_uid)?;
+                    Self::deposit_event(RawEvent::ErrorDuringTransfer(tx_uid));
+                    return Err("Error during transfer");
+                },
+            }
+
+            <IncomingByCreditorReference<T>>::mutate((to.clone(), creditor_reference.clone()), |incoming| {
+                incoming.push((from.clone(), payment_amount, tx_uid))
+            });
+
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
+            Self::deposit_event(RawEvent::PaidWithReference(from, to, creditor_reference, tx_uid));
+            Ok(())
+        }
+        /// The recipient removes the payments it has matched from the index of a creditor reference
+        fn clear_incoming_payments(origin, creditor_reference: CreditorReference) -> Result {
+            let who = ensure_signed(origin)?;
+            let creditor_reference: CreditorReference = match Self::validate_creditor_reference(creditor_reference) {
+                Some(r) => r,
+                None => return Err("Invalid creditor reference"),
+            };
+            <IncomingByCreditorReference<T>>::remove((who.clone(), creditor_reference.clone()));
+            Self::deposit_event(RawEvent::IncomingPaymentsCleared(who, creditor_reference));
+            Ok(())
+        }
+        /// Nominates a successor that can claim the native balance after the identity has been inactive 
+        /// (signed no transactions) for the inactivity period.
+        fn set_successor(origin, successor: T::AccountId, inactivity_period: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            if successor == who {
+                Self::deposit_event(RawEvent::ErrorSuccessorIsSelf());
+                return Err("You cannot be your own successor");
+            }
+            // The inactivity period cannot be shorter than the challenge window
+            if inactivity_period < Self::challenge_window() {
+                Self::deposit_event(RawEvent::ErrorShortInactivityPeriod());
+                return Err("Inactivity period is too short!");
+            }
+            <Successor<T>>::insert(&who, (successor.clone(), inactivity_period));
+            // Signing this transaction is activity. This also invalidates any pending claim.
+            Self::record_activity(who.clone());
+            <InheritanceClaim<T>>::remove(&who);
+            Self::deposit_event(RawEvent::SuccessorSet(who, successor));
+            Ok(())
+        }
+        /// Removes the successor and any pending claim
+        fn remove_successor(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            <Successor<T>>::remove(&who);
+            <LastSeenActivity<T>>::remove(&who);
+            <InheritanceClaim<T>>::remove(&who);
+            Self::deposit_event(RawEvent::SuccessorRemoved(who));
+            Ok(())
+        }
+        /// The successor starts a claim on the balance of an inactive identity. 
+        /// The claim can only be executed after the challenge window, during which any activity by the identity cancels the claim.
+        fn start_inheritance_claim(origin, identity: T::AccountId) -> Result {
+            let who = ensure_signed(origin)?;
+            let current_block = <system::Module<T>>::block_number();
+            let (successor, inactivity_period) = Self::check_successor(who.clone(), identity.clone())?;
+            let (nonce, last_seen) = Self::last_seen_activity(&identity).ok_or("Error fetching last activity")?;
+            if nonce != <system::Module<T>>::account_nonce(&identity) {
+                // The identity has been active since it was last observed.
+                Self::record_activity(identity.clone());
+                Self::deposit_event(RawEvent::ErrorIdentityActive(identity));
+                return Err("The identity is still active");
+            }
+            if current_block < last_seen + inactivity_period {
+                Self::deposit_event(RawEvent::ErrorIdentityActive(identity));
+                return Err("The inactivity period has not passed yet");
+            }
+            let challenge_end: T::BlockNumber = current_block + Self::challenge_window();
+            <InheritanceClaim<T>>::insert(&identity, (successor.clone(), nonce, challenge_end));
+            Self::deposit_event(RawEvent::InheritanceClaimStarted(identity, successor, challenge_end));
+            Ok(())
+        }
+        /// The identity rejects a pending claim on its balance
+        fn cancel_inheritance_claim(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            <InheritanceClaim<T>>::remove(&who);
+            Self::record_activity(who.clone());
+            Self::deposit_event(RawEvent::InheritanceClaimCancelled(who));
+            Ok(())
+        }
+        /// Transfers the free balance of the inactive identity to the successor once the challenge window has passed.
+        /// The transfer fee is paid out of the balance, and the rest is transferred leaving the identity empty.
+        fn execute_inheritance_claim(origin, identity: T::AccountId, tx_uid: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid.clone())?;
+            let current_block = <system::Module<T>>::block_number();
+            Self::check_successor(who.clone(), identity.clone())?;
+            let (claimant, nonce, challenge_end) = Self::inheritance_claim(&identity).ok_or("There is no claim for this identity")?;
+            if claimant != who {
+                Self::deposit_event(RawEvent::ErrorNotSuccessor(tx_uid));
+                return Err("You are not the successor of this identity");
+            }
+            if nonce != <system::Module<T>>::account_nonce(&identity) {
+                // Activity during the challenge window cancels the claim
+                <InheritanceClaim<T>>::remove(&identity);
+                Self::record_activity(identity.clone());
+                Self::deposit_event(RawEvent::InheritanceClaimCancelled(identity));
+                return Err("The identity is still active");
+            }
+            if current_block < challenge_end {
+                Self::deposit_event(RawEvent::ErrorChallengeWindow(tx_uid));
+                return Err("The challenge window has not passed yet");
+            }
             
-            // Reversal keys in case of errors
-            let mut reversal_keys = Vec::<(
-                T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-            )>::with_capacity(1);
-            reversal_keys.push((from.clone(),to.clone(),account_1,increase_amount,false,tx_ref_hash,current_block,current_block_dupe,));
-    
-            let track_rev_keys = Vec::<(
-                T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-            )>::with_capacity(2);
-    
-            match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts(forward_keys.clone(),reversal_keys.clone(),track_rev_keys.clone()) {
+            // The fee is charged on top of the transferred amount, a successor without a balance pays the creation fee
+            let fee: T::Balance = match T::Currency::free_balance(&who).is_zero() {
+                true => <balances::Module<T>>::creation_fee(),
+                false => <balances::Module<T>>::transfer_fee(),
+            };
+            let fee: CurrencyBalanceOf<T> = <T::TransferConversions as Convert<T::Balance, CurrencyBalanceOf<T>>>::convert(fee);
+            let amount: CurrencyBalanceOf<T> = match T::Currency::free_balance(&identity).checked_sub(&fee) {
+                Some(a) if !a.is_zero() => a,
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorDuringTransfer(tx_uid));
+                    return Err("The balance does not cover the transfer fee");
+                },
+            };
+            let posting_amount: i128 = <T::TransferConversions as Convert<CurrencyBalanceOf<T>, i128>>::convert(amount.clone());
+            
+            // Only the amount to be transferred is posted
+            Self::account_for_transfer(identity.clone(), who.clone(), posting_amount, tx_uid, Vec::new())?;
+            
+            match T::Currency::transfer(&identity, &who, amount) {
                 Ok(_) => (),
-                Err(_e) => {
-                    Self::deposit_event(RawEvent::ErrorPostingAccounts(tx_uid));
-                    return Err("An error occured posting to accounts");
+                Err(_) => {
+                    // The funds did not move. The postings are reversed out and the claim remains open.
+                    Self::reverse_transfer(identity.clone(), who.clone(), posting_amount, tx_uid)?;
+                    Self::deposit_event(RawEvent::ErrorDuringTransfer(tx_uid));
+                    return Err("Error during transfer");
+                },
+            }
+            
+            <Successor<T>>::remove(&identity);
+            <LastSeenActivity<T>>::remove(&identity);
+            <InheritanceClaim<T>>::remove(&identity);
+            
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
+            Self::deposit_event(RawEvent::InheritanceClaimed(identity, who, tx_uid));
+            Ok(())
+        }
+        /// Super User designates the auditor of confidential transfers. The box keys must be verified and owned by the auditor.
+        fn set_auditor(origin, auditor: T::AccountId, user_hash: T::Hash) -> Result {
+            let _who = ensure_root(origin)?;
+            let key: T::Hash = match <<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::verified_encryption_key(user_hash) {
+                Some(key) if <<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::is_keys_owner(&auditor, user_hash) => key,
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorBoxKeys(user_hash));
+                    return Err("The box keys are not verified");
                 },
+            };
+            <Auditor<T>>::put((auditor.clone(), key));
+            Self::deposit_event(RawEvent::AuditorSet(auditor, key));
+            Ok(())
+        }
+        /// Transfers funds with a commitment to the amount. The amount and the blinding of the commitment are encrypted 
+        /// off-chain to the verified box key of the recipient and to the auditor key, so that both can check the commitment.
+        /// This is not a private transfer: the amount is a parameter of the call and is emitted in the balances and ledger 
+        /// events. The postings of both parties are only masked in the network wide posting feed.
+        fn confidential_transfer(
+            origin, 
+            to: T::AccountId, 
+            #[compact] payment_amount: T::Balance,
+            commitment: T::Hash,
+            recipient_user_hash: T::Hash,
+            for_recipient: Vec<u8>,
+            for_auditor: Vec<u8>,
+            tx_uid: T::Hash 
+        ) -> Result {
+            let from = ensure_signed(origin)?;
+            if <ConfidentialTransfers<T>>::exists(&tx_uid) {
+                Self::deposit_event(RawEvent::ErrorConfidentialTransfer(tx_uid));
+                return Err("The confidential transfer already exists");
             }
+            let auditor_key: T::Hash = match Self::auditor() {
+                Some((_, key)) => key,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorConfidentialTransfer(tx_uid));
+                    return Err("There is no auditor for confidential transfers");
+                },
+            };
+            let recipient_key: T::Hash = match <<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::verified_encryption_key(recipient_user_hash) {
+                Some(key) if <<T as Trait>::BoxKeys as KeyVerifying<T::AccountId,T::Hash>>::is_keys_owner(&to, recipient_user_hash) => key,
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorBoxKeys(tx_uid));
+                    return Err("The box keys are not verified");
+                },
+            };
+            if for_recipient.is_empty() || for_recipient.len() > MAX_CONFIDENTIAL_PAYLOAD_LENGTH 
+            || for_auditor.is_empty() || for_auditor.len() > MAX_CONFIDENTIAL_PAYLOAD_LENGTH {
+                Self::deposit_event(RawEvent::ErrorConfidentialTransfer(tx_uid));
+                return Err("The encrypted amount is empty or too long");
+            }
+            <<T as Trait>::Bonsai as Storing<T::Hash>>::start_tx(tx_uid.clone())?;
+
+            let amount: CurrencyBalanceOf<T> = <T::TransferConversions as Convert<T::Balance, CurrencyBalanceOf<T>>>::convert(payment_amount.clone());
+            let posting_amount: i128 = <T::TransferConversions as Convert<T::Balance, i128>>::convert(payment_amount);
+            
+            Self::account_for_transfer(from.clone(), to.clone(), posting_amount, tx_uid, Vec::new())?;
+            <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::restrict_reference(tx_uid);
 
             match T::Currency::transfer(&from, &to, amount) {
                 Ok(_) => (),
                 Err(_) => {
+                    // The funds did not move, so the postings are reversed out
+                    Self::reverse_transfer(from.clone(), to.clone(), posting_amount, tx_uid)?;
                     Self::deposit_event(RawEvent::ErrorDuringTransfer(tx_uid));
                     return Err("Error during transfer");
                 },
             }
+
+            let record = ConfidentialTransfer {
+                from: from.clone(),
+                to: to.clone(),
+                commitment: commitment,
+                recipient_key: recipient_key,
+                auditor_key: auditor_key,
+                for_recipient: for_recipient,
+                for_auditor: for_auditor,
+            };
+            <ConfidentialTransfers<T>>::insert(&tx_uid, record);
+
             <<T as Trait>::Bonsai as Storing<T::Hash>>::end_tx(tx_uid)?;
+            Self::deposit_event(RawEvent::PaidConfidentially(from, to, commitment, tx_uid));
             Ok(())
         }
     }
 }
 
+impl<T: Trait> Module<T> {
+    /// Updates the accounts for both parties of a transfer of network currency
+    /// An optional external reference is recorded against the postings of both parties.
+    fn account_for_transfer(from: T::AccountId, to: T::AccountId, posting_amount: i128, tx_uid: T::Hash, ext_ref: Vec<u8>) -> Result {
+        let forward_keys = Self::transfer_keys(from, to, posting_amount, tx_uid);
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_reference(forward_keys, ext_ref) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorPostingAccounts(tx_uid));
+                return Err("An error occured posting to accounts");
+            },
+        }
+        Ok(())
+    }
+    /// Reverses out the postings of a transfer of network currency whose funds did not move
+    fn reverse_transfer(from: T::AccountId, to: T::AccountId, posting_amount: i128, tx_uid: T::Hash) -> Result {
+        // Negated amounts with the opposite indicator
+        let compensating_keys: Vec<(T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber)> = 
+            Self::transfer_keys(from, to, 0i128 - posting_amount, tx_uid)
+                .into_iter()
+                .map(|k| (k.0, k.1, k.2, k.3, !k.4, k.5, k.6, k.7))
+                .collect();
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(compensating_keys, Vec::new(), ORIGIN_ADJUSTMENT) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorPostingAccounts(tx_uid));
+                return Err("An error occured reversing the transfer in accounts");
+            },
+        }
+        Ok(())
+    }
+    /// Keys posting a transfer of network currency for the payer and the recipient
+    fn transfer_keys(from: T::AccountId, to: T::AccountId, posting_amount: i128, tx_uid: T::Hash) -> Vec<(T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber)> {
+        let account_1: AccountOf<T> = <T::TransferConversions as Convert<u64, AccountOf<T>>>::convert(110100040000000u64); // debit increase - credit decrease 110100040000000 XTX Balance
+        
+        // Convert this for the inversion
+        let to_invert: i128 = 0i128 - posting_amount.clone();
+
+        let increase_amount: AccountBalanceOf<T> = <T::TransferConversions as Convert<i128, AccountBalanceOf<T>>>::convert(posting_amount);
+        let decrease_amount: AccountBalanceOf<T> = <T::TransferConversions as Convert<i128, AccountBalanceOf<T>>>::convert(to_invert);
+        
+        // This sets the change block and the applicable posting period. For this context they will always be
+        // the same.
+        let current_block = <system::Module<T>>::block_number(); // For audit on change
+        let current_block_dupe = current_block.clone(); // Applicable period for accounting
+
+        // Generate dummy Hash reference (it has no real bearing but allows posting to happen)
+        let tx_ref_hash: T::Hash = tx_uid.clone();
+            
+        // Keys for posting by payer
+        let mut forward_keys = Vec::<(
+            T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber,
+        )>::with_capacity(2);
+        
+        // Sender
+        forward_keys.push((from.clone(),to.clone(),account_1,decrease_amount,true,tx_ref_hash,current_block,current_block_dupe,));
+        // Receiver
+        forward_keys.push((to.clone(),from.clone(),account_1,increase_amount,false,tx_ref_hash,current_block,current_block_dupe,));
+        
+        forward_keys
+    }
+    /// Generates an ISO 11649 creditor reference from an alphanumeric base (for example an invoice number).
+    /// The base is converted to upper case. Returns None if the base is empty, too long or not alphanumeric.
+    pub fn generate_creditor_reference(base: Vec<u8>) -> Option<CreditorReference> {
+        if base.is_empty() || base.len() > MAX_CREDITOR_REFERENCE_BASE || !base.iter().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let base: Vec<u8> = base.to_ascii_uppercase();
+        // Check digits are calculated over base + "RF00"
+        let mut check_input: Vec<u8> = base.clone();
+        check_input.extend_from_slice(b"RF00");
+        let check: u32 = 98 - Self::iso7064_mod97(&check_input);
+        let mut reference: CreditorReference = b"RF".to_vec();
+        reference.push(b'0' + (check / 10) as u8);
+        reference.push(b'0' + (check % 10) as u8);
+        reference.extend_from_slice(&base);
+        Some(reference)
+    }
+    /// Validates the check digits of an ISO 11649 creditor reference and returns it in upper case
+    pub fn validate_creditor_reference(reference: CreditorReference) -> Option<CreditorReference> {
+        let reference: CreditorReference = reference.to_ascii_uppercase();
+        if reference.len() < 5 || reference.len() > MAX_CREDITOR_REFERENCE_BASE + 4 || !reference.starts_with(b"RF") {
+            return None;
+        }
+        if !reference[2..4].iter().all(|c| c.is_ascii_digit()) || !reference[4..].iter().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        // The first four characters are moved to the end, the remainder must be 1
+        let mut check_input: Vec<u8> = reference[4..].to_vec();
+        check_input.extend_from_slice(&reference[..4]);
+        match Self::iso7064_mod97(&check_input) {
+            1 => Some(reference),
+            _ => None,
+        }
+    }
+    /// Calculates the ISO 7064 MOD 97-10 remainder of an upper case alphanumeric string, where A = 10 ... Z = 35
+    fn iso7064_mod97(input: &[u8]) -> u32 {
+        input.iter().fold(0u32, |remainder, c| {
+            match c {
+                b'0'..=b'9' => (remainder * 10 + (c - b'0') as u32) % 97,
+                _ => (remainder * 100 + (c - b'A') as u32 + 10) % 97,
+            }
+        })
+    }
+    /// Returns the payments received by the identity quoting the creditor reference (payer, amount, transaction)
+    pub fn incoming_payments(to: T::AccountId, reference: CreditorReference) -> Vec<(T::AccountId, T::Balance, T::Hash)> {
+        match Self::validate_creditor_reference(reference) {
+            Some(r) => Self::incoming_by_creditor_reference((to, r)),
+            None => Vec::new(),
+        }
+    }
+    /// The number of blocks during which an inheritance claim can be challenged (48 hours)
+    fn challenge_window() -> T::BlockNumber {
+        <T::TransferConversions as Convert<u64, T::BlockNumber>>::convert(11520u64)
+    }
+    /// Records the current account nonce of the identity as the last seen activity
+    fn record_activity(o: T::AccountId) {
+        let current_block = <system::Module<T>>::block_number();
+        let nonce: T::Index = <system::Module<T>>::account_nonce(&o);
+        <LastSeenActivity<T>>::insert(&o, (nonce, current_block));
+    }
+    /// Checks that the claimant is the nominated successor and returns the successor and inactivity period
+    fn check_successor(c: T::AccountId, o: T::AccountId) -> rstd::result::Result<(T::AccountId, T::BlockNumber), &'static str> {
+        match Self::successor(&o) {
+            Some((successor, inactivity_period)) => {
+                if successor != c {
+                    return Err("You are not the successor of this identity");
+                }
+                Ok((successor, inactivity_period))
+            },
+            None => Err("This identity has not nominated a successor"),
+        }
+    }
+}
+
 decl_event!(
     pub enum Event<T>
     where
+    AccountId = <T as system::Trait>::AccountId,
     Hash = <T as system::Trait>::Hash,
+    BlockNumber = <T as system::Trait>::BlockNumber,
+    CreditorReference = Vec<u8>,
     {
+        /// Payer, recipient, creditor reference, transaction
+        PaidWithReference(AccountId, AccountId, CreditorReference, Hash),
+        /// Recipient, creditor reference
+        IncomingPaymentsCleared(AccountId, CreditorReference),
+        SuccessorSet(AccountId, AccountId),
+        SuccessorRemoved(AccountId),
+        /// Identity, successor, end of challenge window
+        InheritanceClaimStarted(AccountId, AccountId, BlockNumber),
+        InheritanceClaimCancelled(AccountId),
+        /// Identity, successor, transaction
+        InheritanceClaimed(AccountId, AccountId, Hash),
         /// There was an error calling the transfer function in balances
         ErrorDuringTransfer(Hash),
         ErrorPostingAccounts(Hash),
+        /// Auditor of confidential transfers, auditor key
+        AuditorSet(AccountId, Hash),
+        /// Payer, recipient, commitment to the amount, transaction
+        PaidConfidentially(AccountId, AccountId, Hash, Hash),
+        /// The box keys are not verified
+        ErrorBoxKeys(Hash),
+        /// The confidential transfer is a duplicate, has no auditor or its encrypted amount is invalid
+        ErrorConfidentialTransfer(Hash),
+        /// You cannot be your own successor
+        ErrorSuccessorIsSelf(),
+        /// Inactivity period is too short!
+        ErrorShortInactivityPeriod(),
+        /// The identity is still active
+        ErrorIdentityActive(AccountId),
+        /// You are not the successor of this identity
+        ErrorNotSuccessor(Hash),
+        /// The challenge window has not passed yet
+        ErrorChallengeWindow(Hash),
+        /// The creditor reference is not a valid ISO 11649 reference
+        ErrorCreditorReference(Hash),
+        /// Too many unmatched payments for this creditor reference
+        ErrorTooManyIncomingPayments(Hash),
+    }
+);
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use substrate_primitives::{Blake2Hasher, H256};
+    use runtime_io::with_externalities;
+    use support::{assert_err, assert_ok};
+    use support::traits::{LockableCurrency, WithdrawReasons};
+    use crate::mock::{account, Origin, Test};
+
+    type TransferModule = Module<Test>;
+    type Balances = balances::Module<Test>;
+    type System = system::Module<Test>;
+    type Accounting = accounting::Module<Test>;
+
+    const XTX_BALANCE: u64 = 110100040000000;
+    // Blocks of the challenge window, also the shortest inactivity period
+    const WINDOW: u64 = 11520;
+
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        crate::mock::new_test_ext(vec![(account(1), 5_000), (account(2), 5_000)])
     }
-);
\ No newline at end of file
+
+    fn reference(r: &[u8]) -> Option<CreditorReference> {
+        TransferModule::validate_creditor_reference(r.to_vec())
+    }
+
+    #[test]
+    fn creditor_reference_check_digits_are_validated() {
+        with_externalities(&mut new_test_ext(), || {
+            // Example reference of ISO 11649
+            assert_eq!(reference(b"RF18539007547034"), Some(b"RF18539007547034".to_vec()));
+            assert_eq!(reference(b"rf18539007547034"), Some(b"RF18539007547034".to_vec()));
+            assert_eq!(TransferModule::generate_creditor_reference(b"539007547034".to_vec()), Some(b"RF18539007547034".to_vec()));
+            assert_eq!(TransferModule::generate_creditor_reference(b"inv2024".to_vec()), Some(b"RF30INV2024".to_vec()));
+            assert_eq!(reference(b"RF30INV2024"), Some(b"RF30INV2024".to_vec()));
+
+            // A single changed character
+            assert_eq!(reference(b"RF18539007547035"), None);
+            assert_eq!(reference(b"RF19539007547034"), None);
+            assert_eq!(reference(b"RF30INV2025"), None);
+            // Transposed characters
+            assert_eq!(reference(b"RF18539007547043"), None);
+            assert_eq!(reference(b"RF81539007547034"), None);
+            assert_eq!(reference(b"RF30IVN2024"), None);
+            // Leading zeros of the base do not change the check digits
+            assert_eq!(reference(b"RF18000000000539007547034"), Some(b"RF18000000000539007547034".to_vec()));
+        });
+    }
+
+    #[test]
+    fn malformed_creditor_references_are_rejected() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_eq!(reference(b"RF18"), None);
+            assert_eq!(reference(b"XX18539007547034"), None);
+            assert_eq!(reference(b"RF1X539007547034"), None);
+            assert_eq!(reference(b"RF18 539007547034"), None);
+            // The base is limited to 21 characters
+            assert_eq!(reference(b"RF180000000000539007547034"), None);
+            assert_eq!(TransferModule::generate_creditor_reference(Vec::new()), None);
+            assert_eq!(TransferModule::generate_creditor_reference(b"INV-2024".to_vec()), None);
+            assert!(TransferModule::generate_creditor_reference(vec![b'1'; 21]).is_some());
+            assert_eq!(TransferModule::generate_creditor_reference(vec![b'1'; 22]), None);
+        });
+    }
+
+    #[test]
+    fn payments_are_indexed_by_creditor_reference_up_to_the_cap() {
+        with_externalities(&mut new_test_ext(), || {
+            let pay = |reference: &[u8], n: u8| {
+                TransferModule::network_currency_with_reference(Origin::signed(account(1)), account(2), 10, reference.to_vec(), H256::repeat_byte(n))
+            };
+            assert_err!(pay(b"RF18539007547035", 1), "Invalid creditor reference");
+            assert_ok!(pay(b"rf18539007547034", 2));
+            assert_eq!(Balances::free_balance(&account(2)), 5_010);
+            assert_eq!(
+                TransferModule::incoming_payments(account(2), b"RF18539007547034".to_vec()),
+                vec![(account(1), 10, H256::repeat_byte(2))]
+            );
+            assert!(TransferModule::incoming_payments(account(2), b"RF18539007547035".to_vec()).is_empty());
+
+            // The index is full once it holds the maximum number of unmatched payments
+            let key = (account(2), b"RF18539007547034".to_vec());
+            <IncomingByCreditorReference<Test>>::insert(&key, vec![(account(1), 10, H256::repeat_byte(2)); MAX_INCOMING_BY_REFERENCE - 1]);
+            assert_ok!(pay(b"RF18539007547034", 3));
+            assert_eq!(TransferModule::incoming_by_creditor_reference(&key).len(), MAX_INCOMING_BY_REFERENCE);
+            assert_err!(pay(b"RF18539007547034", 4), "Too many unmatched payments for this creditor reference");
+            assert_eq!(Balances::free_balance(&account(2)), 5_020);
+
+            // Other references of the recipient are not affected, and clearing the index reopens it
+            assert_ok!(pay(b"RF30INV2024", 5));
+            assert_ok!(TransferModule::clear_incoming_payments(Origin::signed(account(2)), b"RF18539007547034".to_vec()));
+            assert!(TransferModule::incoming_by_creditor_reference(&key).is_empty());
+            assert_ok!(pay(b"RF18539007547034", 6));
+            assert_eq!(TransferModule::incoming_by_creditor_reference(&key).len(), 1);
+        });
+    }
+
+    #[test]
+    fn failed_payment_with_reference_is_reversed_out_of_the_accounts() {
+        with_externalities(&mut new_test_ext(), || {
+            let tx_uid = H256::repeat_byte(1);
+            assert_err!(
+                TransferModule::network_currency_with_reference(Origin::signed(account(1)), account(2), 6_000, b"RF30INV2024".to_vec(), tx_uid),
+                "Error during transfer"
+            );
+            assert_eq!(Balances::free_balance(&account(2)), 5_000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), XTX_BALANCE)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), XTX_BALANCE)), 0);
+            assert_eq!(Accounting::postings_by_reference(tx_uid).len(), 4);
+            assert!(TransferModule::incoming_payments(account(2), b"RF30INV2024".to_vec()).is_empty());
+        });
+    }
+
+    #[test]
+    fn successor_claims_the_balance_after_inactivity_and_the_challenge_window() {
+        with_externalities(&mut new_test_ext(), || {
+            System::set_block_number(1);
+            assert_err!(TransferModule::set_successor(Origin::signed(account(1)), account(1), WINDOW), "You cannot be your own successor");
+            assert_err!(TransferModule::set_successor(Origin::signed(account(1)), account(2), WINDOW - 1), "Inactivity period is too short!");
+            assert_err!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)), "This identity has not nominated a successor");
+            assert_ok!(TransferModule::set_successor(Origin::signed(account(1)), account(2), WINDOW));
+            assert_err!(TransferModule::start_inheritance_claim(Origin::signed(account(3)), account(1)), "You are not the successor of this identity");
+            assert_err!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)), "The inactivity period has not passed yet");
+
+            System::set_block_number(1 + WINDOW);
+            assert_ok!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)));
+            assert_eq!(TransferModule::inheritance_claim(account(1)), Some((account(2), 0, 1 + 2 * WINDOW)));
+
+            // The last block of the challenge window
+            System::set_block_number(2 * WINDOW);
+            assert_err!(
+                TransferModule::execute_inheritance_claim(Origin::signed(account(2)), account(1), H256::repeat_byte(1)),
+                "The challenge window has not passed yet"
+            );
+            System::set_block_number(1 + 2 * WINDOW);
+            assert_err!(
+                TransferModule::execute_inheritance_claim(Origin::signed(account(3)), account(1), H256::repeat_byte(2)),
+                "You are not the successor of this identity"
+            );
+            assert_ok!(TransferModule::execute_inheritance_claim(Origin::signed(account(2)), account(1), H256::repeat_byte(3)));
+            assert_eq!(Balances::free_balance(&account(1)), 0);
+            assert_eq!(Balances::free_balance(&account(2)), 10_000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), XTX_BALANCE)), -5_000);
+            assert_eq!(Accounting::balance_by_ledger((account(2), XTX_BALANCE)), 5_000);
+            assert!(TransferModule::successor(account(1)).is_none());
+            assert!(TransferModule::inheritance_claim(account(1)).is_none());
+            assert!(TransferModule::last_seen_activity(account(1)).is_none());
+            assert!(bonsai::Module::<Test>::is_successful(H256::repeat_byte(3)).is_some());
+        });
+    }
+
+    #[test]
+    fn activity_of_the_identity_cancels_the_inheritance_claim() {
+        with_externalities(&mut new_test_ext(), || {
+            System::set_block_number(1);
+            assert_ok!(TransferModule::set_successor(Origin::signed(account(1)), account(2), WINDOW));
+
+            // Activity before the claim restarts the inactivity period
+            System::set_block_number(1 + WINDOW);
+            System::inc_account_nonce(&account(1));
+            assert_err!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)), "The identity is still active");
+            assert_eq!(TransferModule::last_seen_activity(account(1)), Some((1, 1 + WINDOW)));
+            assert_err!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)), "The inactivity period has not passed yet");
+
+            // Activity during the challenge window cancels the claim
+            System::set_block_number(1 + 2 * WINDOW);
+            assert_ok!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)));
+            System::inc_account_nonce(&account(1));
+            System::set_block_number(1 + 3 * WINDOW);
+            assert_err!(
+                TransferModule::execute_inheritance_claim(Origin::signed(account(2)), account(1), H256::repeat_byte(1)),
+                "The identity is still active"
+            );
+            assert!(TransferModule::inheritance_claim(account(1)).is_none());
+
+            // The identity can also reject the claim
+            System::set_block_number(1 + 4 * WINDOW);
+            assert_ok!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)));
+            assert_ok!(TransferModule::cancel_inheritance_claim(Origin::signed(account(1))));
+            assert!(TransferModule::inheritance_claim(account(1)).is_none());
+            System::set_block_number(1 + 5 * WINDOW);
+            assert_err!(
+                TransferModule::execute_inheritance_claim(Origin::signed(account(2)), account(1), H256::repeat_byte(2)),
+                "There is no claim for this identity"
+            );
+            assert_eq!(Balances::free_balance(&account(1)), 5_000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), XTX_BALANCE)), 0);
+        });
+    }
+
+    #[test]
+    fn failed_inheritance_transfer_is_reversed_out_of_the_accounts() {
+        with_externalities(&mut new_test_ext(), || {
+            System::set_block_number(1);
+            assert_ok!(TransferModule::set_successor(Origin::signed(account(1)), account(2), WINDOW));
+            System::set_block_number(1 + WINDOW);
+            assert_ok!(TransferModule::start_inheritance_claim(Origin::signed(account(2)), account(1)));
+            // Part of the balance is locked, so that the whole free balance cannot be transferred
+            <Balances as LockableCurrency<_>>::set_lock(*b"inherits", &account(1), 1_000, u64::max_value(), WithdrawReasons::all());
+            assert_eq!(Balances::free_balance(&account(1)), 4_000);
+
+            System::set_block_number(1 + 2 * WINDOW);
+            assert_err!(
+                TransferModule::execute_inheritance_claim(Origin::signed(account(2)), account(1), H256::repeat_byte(1)),
+                "Error during transfer"
+            );
+            assert_eq!(Balances::free_balance(&account(1)), 4_000);
+            assert_eq!(Balances::free_balance(&account(2)), 5_000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), XTX_BALANCE)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), XTX_BALANCE)), 0);
+            // Both the transfer and its reversal are on record against the transaction
+            assert_eq!(Accounting::postings_by_reference(H256::repeat_byte(1)).len(), 4);
+            assert!(TransferModule::inheritance_claim(account(1)).is_some());
+            assert!(TransferModule::successor(account(1)).is_some());
+        });
+    }
+
+    #[test]
+    fn failed_confidential_transfer_is_reversed_out_of_the_accounts() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(TransferModule::set_auditor(Origin::ROOT, account(3), H256::repeat_byte(3)));
+            let tx_uid = H256::repeat_byte(1);
+            assert_err!(
+                TransferModule::confidential_transfer(
+                    Origin::signed(account(1)), account(2), 6_000, H256::repeat_byte(9), H256::repeat_byte(2), vec![1], vec![2], tx_uid
+                ),
+                "Error during transfer"
+            );
+            assert_eq!(Balances::free_balance(&account(1)), 5_000);
+            assert_eq!(Balances::free_balance(&account(2)), 5_000);
+            assert_eq!(Accounting::balance_by_ledger((account(1), XTX_BALANCE)), 0);
+            assert_eq!(Accounting::balance_by_ledger((account(2), XTX_BALANCE)), 0);
+            assert_eq!(Accounting::postings_by_reference(tx_uid).len(), 4);
+            assert!(TransferModule::confidential_transfer_record(tx_uid).is_none());
+        });
+    }
+}
@@ -0,0 +1,125 @@
+//! Copyright 2020 Chris D'Costa
+//! This file is part of Totem Live Accounting.
+//! Author Chris D'Costa email: chris.dcosta@totemaccounting.com
+
+//! Totem is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+
+//! Totem is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+
+//! You should have received a copy of the GNU General Public License
+//! along with Totem.  If not, see <http://www.gnu.org/licenses/>.
+
+//********************************************************//
+// Version agnostic ledger logic
+//********************************************************//
+
+use crate::{Account, LedgerBalance};
+
+/// Returns the financial statement type (the first digit) of a ledger account
+pub fn statement_type(a: Account) -> u8 {
+    (a / 100_000_000_000_000u64) as u8
+}
+
+/// Returns the category (the second digit) of a ledger account: 
+/// 1 assets, 2 liabilities, 3 equity, 4 income, 5 expenses, 6 control
+pub fn account_category(a: Account) -> u8 {
+    ((a / 10_000_000_000_000u64) % 10) as u8
+}
+
+/// Whether the normal balance of an account is a debit (assets and expenses) or a credit (liabilities, equity and income).
+/// Control accounts have no nature.
+pub fn is_debit_nature(a: Account) -> Option<bool> {
+    match account_category(a) {
+        1 | 5 => Some(true),
+        2 | 3 | 4 => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns the indicator (false debit, true credit) of an amount signed in the sense of the account's nature. 
+/// A positive amount is on the side of the normal balance, a negative amount on the opposite side.
+pub fn indicator(a: Account, c: LedgerBalance) -> Option<bool> {
+    is_debit_nature(a).map(|debit| debit != (c > 0))
+}
+
+/// Returns the amount of a posting line (stored as an absolute amount with its indicator) signed in the sense of the 
+/// account's nature. Control accounts have no nature, a debit is read as positive.
+pub fn signed_amount(a: Account, c: LedgerBalance, indicator: bool) -> LedgerBalance {
+    if is_debit_nature(a).unwrap_or(true) != indicator { c.abs() } else { -c.abs() }
+}
+
+/// A contra account offsets a parent account of the same statement type and category (for example accumulated 
+/// depreciation against fixed assets, or sales returns against sales), so that its balance is presented net of the parent.
+/// Control accounts cannot be contra accounts.
+pub fn is_valid_contra(contra: Account, parent: Account) -> bool {
+    contra != parent
+        && statement_type(contra) == statement_type(parent)
+        && account_category(contra) == account_category(parent)
+        && is_debit_nature(contra).is_some()
+}
+
+/// Returns the rate period of a block number
+pub fn rate_period(block: u64, period_length: u64) -> u64 {
+    block / period_length
+}
+
+/// Returns the period in a sorted list of rate periods that applies to a period, which is the period itself or the latest earlier one
+pub fn applicable_period(periods: &[u64], period: u64) -> Option<u64> {
+    match periods.binary_search(&period) {
+        Ok(i) => Some(periods[i]),
+        Err(0) => None,
+        Err(i) => Some(periods[i - 1]),
+    }
+}
+
+/// Divides rounding the absolute value half up, or half to even when `half_even` is set.
+/// The sign is applied after rounding so that positive and negative amounts round symmetrically.
+/// Returns None on overflow or division by zero.
+pub fn round_divide(n: LedgerBalance, d: LedgerBalance, half_even: bool) -> Option<LedgerBalance> {
+    if d == 0 { return None; }
+    let negative: bool = (n < 0) != (d < 0);
+    let n_abs: LedgerBalance = n.checked_abs()?;
+    let d_abs: LedgerBalance = d.checked_abs()?;
+    let mut q: LedgerBalance = n_abs / d_abs;
+    let r: LedgerBalance = n_abs % d_abs;
+    let round_up: bool = match r.cmp(&(d_abs - r)) {
+        rstd::cmp::Ordering::Greater => true,
+        rstd::cmp::Ordering::Less => false,
+        // exactly half
+        rstd::cmp::Ordering::Equal => r != 0 && (!half_even || q % 2 == 1),
+    };
+    if round_up {
+        q = q.checked_add(1)?;
+    }
+    if negative { Some(-q) } else { Some(q) }
+}
+
+/// Calculates amount * numerator / denominator rounded with `round_divide`
+pub fn apply_rate(amount: LedgerBalance, numerator: LedgerBalance, denominator: LedgerBalance, half_even: bool) -> Option<LedgerBalance> {
+    round_divide(amount.checked_mul(numerator)?, denominator, half_even)
+}
+
+/// Whether an amount is a multiple of the smallest unit of a quantity account with `decimals` decimals,
+/// where ledger quantities are stored with `quantity_decimals` decimals
+pub fn respects_precision(c: LedgerBalance, decimals: u8, quantity_decimals: u8) -> bool {
+    let step: LedgerBalance = 10i128.pow(quantity_decimals.saturating_sub(decimals) as u32);
+    c % step == 0
+}
+
+/// Returns the account an account is re-mapped to by a chart of accounts version, or the account itself
+pub fn remap(a: Account, remapping: &[(Account, Account)]) -> Account {
+    remapping.iter().find(|r| r.0 == a).map_or(a, |r| r.1)
+}
+
+/// A remapping is valid when no account is re-mapped to itself or more than once
+pub fn is_valid_remapping(remapping: &[(Account, Account)]) -> bool {
+    remapping.iter().enumerate().all(|(i, r)| {
+        r.0 != r.1 && !remapping[..i].iter().any(|p| p.0 == r.0)
+    })
+}
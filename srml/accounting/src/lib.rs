@@ -84,28 +84,26 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use parity_codec::{Codec, Decode, Encode};
-// use codec::{ Encode, Decode }; // v2
+use parity_codec::{Codec, Decode, Encode, Input};
 
 use srml_support::{
     decl_event, decl_module, decl_storage, dispatch::Result, Parameter, StorageMap, StorageValue, 
+    traits::{Currency, MakePayment},
 };
-//v1
-// use frame_support::{decl_event, decl_error, decl_module, decl_storage, dispatch::DispatchResult, weights::{Weight, DispatchClass}, StorageValue, StorageMap}; // v2
 
-use system::{self};
-//v1
-// use frame_system::{self}; //v2
+use system::{self, ensure_root, ensure_signed};
 
 use rstd::prelude::*;
-//v1
-// use sp_std::prelude::*; //v2
+use rstd::marker::PhantomData;
 
-use sr_primitives::traits::{As, Convert, Hash, MaybeSerializeDebug, Member, SimpleArithmetic};
-// use sp_runtime::traits::{ Member, Hash }; // v2
+use sr_primitives::traits::{As, CheckedSub, Convert, Hash, MaybeSerializeDebug, Member, SimpleArithmetic, Zero};
 
 use substrate_primitives::crypto::UncheckedFrom;
 
+pub mod ledger;
+mod mock;
+mod tests;
+
 // Balance on an account can be negative
 type LedgerBalance = i128;
 // General ledger account number
@@ -114,6 +112,177 @@ type Account = u64;
 type Indicator = bool;
 // The index number for identifying the posting to ledgers
 type PostingIndex = u128;
+// External document reference (for example an ERP document number)
+type ExternalReference = Vec<u8>;
+// Free-form label of a user entry (for example a cost center or a campaign)
+type PostingTag = Vec<u8>;
+// ISO 3166-1 numeric country code
+type CountryCode = u16;
+// ISO 4217 numeric currency code of a reporting (presentation) currency
+type CurrencyCode = u16;
+// Rounding applied to derived amounts (percentages, pro-rata splits) 0=Half-up 1=Half-even (banker's)
+type RoundingPolicy = u8;
+// Display metadata of a ledger account (decimals, display unit, quantity account)
+type AccountDisplay = (u8, Vec<u8>, bool);
+// Origin of a posting, so that system recipes can be separated from user entries
+pub type PostingOrigin = u8;
+// Journal number of a user entry, gapless per identity
+type JournalNumber = u64;
+
+// Recipes generated by the runtime (fees, memorandum entries)
+pub const ORIGIN_SYSTEM: PostingOrigin = 0;
+// Entries resulting from a user transaction (transfers, prefunding, invoices, settlements)
+pub const ORIGIN_USER: PostingOrigin = 1;
+// Corrections of earlier entries (invoice adjustments, credit notes, reversals)
+pub const ORIGIN_ADJUSTMENT: PostingOrigin = 2;
+// Balances carried over from a previous network
+pub const ORIGIN_MIGRATION: PostingOrigin = 3;
+
+// Decimals of XTX. Ledger balances are denominated in the smallest unit of XTX, so amounts are only scaled by 
+// 10^XTX_DECIMALS for display.
+pub const XTX_DECIMALS: u8 = 0;
+// Smallest units of the currency balance per ledger unit. Conversions between the currency balance (u128) and the 
+// ledger balance (i128) must apply this scale.
+pub const LEDGER_UNIT_SCALE: u128 = 1;
+
+const ROUND_HALF_UP: RoundingPolicy = 0;
+const ROUND_HALF_EVEN: RoundingPolicy = 1;
+// Escrowed funds are held here until an order is settled or the funds are released (Asset)
+pub const ESCROW_ACCOUNT: Account = 110100050000000_u64; // Totem Runtime Deposit (Escrow)
+// Accounts whose balances are also kept per reference hash (sub-ledger), so that the balance can be broken down by
+// order, bond or other reference
+const SUB_LEDGER_ACCOUNTS: [Account; 1] = [ESCROW_ACCOUNT];
+// Residual units left over from rounding derived amounts are posted here (Expense)
+const ROUNDING_DIFFERENCE_ACCOUNT: Account = 250500420000000_u64; // Rounding differences
+// The other side of opening balances is posted here (Equity)
+pub const OPENING_BALANCE_ACCOUNT: Account = 130300010000000_u64; // Opening balance equity
+// Receivables account on which the exposure to each counterparty is tracked
+const TRADE_RECEIVABLES_ACCOUNT: Account = 110100090000000_u64; // Trade receivables - non-related parties
+// Equity account the profit and loss of a period is closed to
+pub const RETAINED_EARNINGS_ACCOUNT: Account = 130200010000000_u64; // Retained earnings
+
+// Maximum length in bytes of an external document reference
+const MAX_EXTERNAL_REFERENCE_LENGTH: usize = 64;
+// Maximum number of global ledger accounts returned in one page
+const MAX_GLOBAL_LEDGER_PAGE: u32 = 100;
+// Maximum number of postings returned in one page of a block range report
+const MAX_POSTINGS_PAGE: u32 = 100;
+// Maximum number of blocks covered by one block range report
+const MAX_POSTINGS_RANGE: u64 = 14400;
+// Maximum number of posting lines held in one page of the postings of a block
+const MAX_BLOCK_POSTINGS_PAGE: usize = 100;
+// Number of recent blocks over which the movements of the network identities are counted
+const NETWORK_STATUS_WINDOW: u64 = 14400;
+// Key accounts of the global ledger reported on the network status page
+const NETWORK_STATUS_ACCOUNTS: [Account; 5] = [
+    110100040000000_u64, // XTX Balance
+    ESCROW_ACCOUNT,
+    360600040000000_u64, // Escrowed Funds Control
+    250500300000000_u64, // Totem Transaction Fees
+    ROUNDING_DIFFERENCE_ACCOUNT,
+];
+// Amounts posted to quantity accounts are recorded in millionths of the display unit
+const QUANTITY_DECIMALS: u8 = 6;
+// Maximum display decimals of a currency account
+const MAX_DISPLAY_DECIMALS: u8 = 18;
+// Maximum length in bytes of the display unit of an account
+const MAX_DISPLAY_UNIT_LENGTH: usize = 16;
+// Number of blocks covered by an exchange rate (one day)
+const RATE_PERIOD: u64 = 5760;
+// Maximum number of account remappings in one chart of accounts version
+const MAX_CHART_REMAPPINGS: usize = 200;
+// Postings retargeted to a period more than this number of blocks before the current block are flagged as backdated (30 days)
+const DEFAULT_BACKDATING_TOLERANCE: u64 = 172800;
+// Maximum number of ledger accounts with a budget per identity
+const MAX_BUDGETS: usize = 100;
+// Maximum number of ledger account balances carried forward in one call of the period close
+const MAX_CLOSE_ACCOUNTS: usize = 200;
+// Maximum number of postings (identity, account, posting index) indexed in one index rebuild
+const MAX_REBUILD_KEYS: usize = 500;
+// Maximum number of tags on a user entry
+const MAX_POSTING_TAGS: usize = 4;
+// Maximum length in bytes of a tag
+const MAX_POSTING_TAG_LENGTH: usize = 16;
+/// Detail of a posting line (counterparty, block, signed amount, indicator, reference, applicable block, origin, journal number).
+/// Encoded as the tuple it replaces. Lines stored before the origin or the journal number were recorded decode with 
+/// the system origin and without a journal number, so that no storage needs to be rewritten.
+#[derive(PartialEq, Eq, Clone, Encode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PostingDetailRecord<AccountId, BlockNumber, Hash>(
+    pub AccountId, pub BlockNumber, pub LedgerBalance, pub Indicator, pub Hash, pub BlockNumber, pub PostingOrigin, pub Option<JournalNumber>,
+);
+
+impl<AccountId: Decode, BlockNumber: Decode, Hash: Decode> Decode for PostingDetailRecord<AccountId, BlockNumber, Hash> {
+    fn decode<I: Input>(input: &mut I) -> Option<Self> {
+        let (p, b, c, d, h, t) = <(AccountId, BlockNumber, LedgerBalance, Indicator, Hash, BlockNumber)>::decode(input)?;
+        let origin: PostingOrigin = PostingOrigin::decode(input).unwrap_or(ORIGIN_SYSTEM);
+        let journal: Option<JournalNumber> = <Option<JournalNumber>>::decode(input).unwrap_or(None);
+        Some(PostingDetailRecord(p, b, c, d, h, t, origin, journal))
+    }
+}
+
+// Version of the journal replication record. Bumped whenever the shape of the record changes.
+pub const JOURNAL_REPLICATION_VERSION: u8 = 1;
+
+/// Journal replication record, emitted once per posting batch in the JournalReplicated event for downstream databases.
+/// This is a stable interface: the shape of a version never changes, a change of shape comes with a new version, 
+/// so that the records of earlier runtime versions can still be decoded. Replaying the records in batch order 
+/// (adding the signed amount of every line to the identity's account) reproduces the ledger balances.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct JournalReplication<AccountId, Hash, BlockNumber> {
+    pub version: u8,
+    /// Posting index shared by all the lines of the batch
+    pub batch: u128,
+    pub block: BlockNumber,
+    pub origin: PostingOrigin,
+    pub lines: Vec<ReplicationLine<AccountId, Hash, BlockNumber>>,
+    /// External document reference of the batch (empty when none)
+    pub external_reference: Vec<u8>,
+}
+
+/// Line of a journal replication record in posting order. The account is the account of the chart in force when posted.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ReplicationLine<AccountId, Hash, BlockNumber> {
+    pub identity: AccountId,
+    pub counterparty: AccountId,
+    pub account: u64,
+    /// Signed amount added to the balance
+    pub amount: i128,
+    /// false debit, true credit
+    pub credit: bool,
+    pub reference: Hash,
+    /// Block of the accounting period the line applies to
+    pub applicable_block: BlockNumber,
+    pub journal: Option<u64>,
+}
+
+/// Totals of the books of an identity per category of the balance sheet and the profit and loss. 
+/// Balanced when assets equal liabilities and equity after the profit and loss is notionally closed to equity.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct AccountingEquation {
+    pub assets: i128,
+    pub liabilities: i128,
+    pub equity: i128,
+    pub revenue: i128,
+    pub expenses: i128,
+    pub balanced: bool,
+}
+
+/// Preview of the period close of an identity at the current block, computed without changing state: 
+/// the last close, the budget variances the close would record (account, budget, actual, variance) and the proposed 
+/// closing entries (account, amount signed in the sense of the account's nature, indicator) rolling the profit and loss
+/// of the period up to retained earnings. The functional currency of the books is always XTX so there are no foreign 
+/// currency balances to revalue, and depreciation is not yet tracked on chain, so no entries are proposed for either.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ClosePreview<BlockNumber> {
+    pub last_close: Option<BlockNumber>,
+    pub variances: Vec<(u64, i128, i128, i128)>,
+    pub entries: Vec<(u64, i128, bool)>,
+}
 
 pub trait Trait: system::Trait + timestamp::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
@@ -133,20 +302,46 @@ pub trait Trait: system::Trait + timestamp::Trait {
         Convert<Self::CoinAmount, LedgerBalance>
         + Convert<i128, LedgerBalance>
         + Convert<LedgerBalance, i128>;
+    // Handler for when an identity closes its accounting period
+    type OnPeriodClose: OnPeriodClose<Self::AccountId, Self::BlockNumber>;
+}
+
+/// Called when an identity closes its accounting period, with the block at which the period was closed.
+/// Allows other modules to freeze the records that belong to the closed period.
+pub trait OnPeriodClose<AccountId, BlockNumber> {
+    fn on_period_close(who: &AccountId, closed_at: BlockNumber);
+}
+
+impl<AccountId, BlockNumber> OnPeriodClose<AccountId, BlockNumber> for () {
+    fn on_period_close(_who: &AccountId, _closed_at: BlockNumber) {}
 }
 
 pub trait Posting<AccountId, Hash, BlockNumber, CoinAmount> {
-    type Account: Member + Copy + Eq;
+    type Account: Member + Copy + Eq + Into<Account>;
     type PostingIndex: Member + Copy + Into<u128> + Encode + Decode + Eq;
-    type LedgerBalance: Member + Copy + Into<i128> + Encode + Decode + Eq;
+    type LedgerBalance: Member + Copy + Into<i128> + From<LedgerBalance> + Encode + Decode + Eq;
     fn handle_multiposting_amounts(
-        fwd: Vec<(
+        keys: Vec<(
             AccountId,AccountId,Self::Account,Self::LedgerBalance,bool,Hash,BlockNumber,BlockNumber,
         )>,
-        rev: Vec<(
+    ) -> Result;
+    fn handle_multiposting_amounts_with_reference(
+        keys: Vec<(
             AccountId,AccountId,Self::Account,Self::LedgerBalance,bool,Hash,BlockNumber,BlockNumber,
         )>,
-        trk: Vec<(
+        ext_ref: Vec<u8>,
+    ) -> Result;
+    fn handle_multiposting_amounts_with_origin(
+        keys: Vec<(
+            AccountId,AccountId,Self::Account,Self::LedgerBalance,bool,Hash,BlockNumber,BlockNumber,
+        )>,
+        ext_ref: Vec<u8>,
+        origin: PostingOrigin,
+    ) -> Result;
+    /// Posts a batch whose amounts were converted with `apply_rate`. The rounding residual of each identity is posted
+    /// to the rounding difference account; a residual of more than one smallest unit per line is rejected.
+    fn handle_converted_multiposting_amounts(
+        keys: Vec<(
             AccountId,AccountId,Self::Account,Self::LedgerBalance,bool,Hash,BlockNumber,BlockNumber,
         )>,
     ) -> Result;
@@ -155,7 +350,54 @@ pub trait Posting<AccountId, Hash, BlockNumber, CoinAmount> {
     fn get_netfees_account() -> AccountId;
     fn get_pseudo_random_hash(s: AccountId, r: AccountId) -> Hash;
     fn get_gl_account_balance(sender: AccountId, account: Account) -> LedgerBalance;
+    fn get_postings_by_reference(h: Hash) -> Vec<(AccountId, Self::Account, Self::LedgerBalance)>;
+    fn get_posting_indexes_by_reference(h: Hash) -> Vec<Self::PostingIndex>;
+    fn get_sub_ledger_balance(o: AccountId, a: Self::Account, h: Hash) -> Self::LedgerBalance;
+    fn apply_rate(amount: Self::LedgerBalance, numerator: Self::LedgerBalance, denominator: Self::LedgerBalance) -> Option<Self::LedgerBalance>;
+    fn get_exchange_rate(currency: u16, b: BlockNumber) -> Option<(Self::LedgerBalance, Self::LedgerBalance)>;
     fn force_set_gl_account_balance(sender: AccountId, amount: CoinAmount) -> Result;
+    fn share_last_fee(payer: AccountId, counterparty: AccountId, share: u8, reference: Hash) -> Result;
+    fn check_credit_limit(o: AccountId, p: AccountId, amount: Self::LedgerBalance) -> Result;
+    fn get_block_number() -> BlockNumber;
+    /// Marks the postings against a reference as of restricted visibility (for example a confidential transfer).
+    /// Their amounts are masked in the network wide posting feed.
+    fn restrict_reference(h: Hash);
+    /// Posts a simple entry of two lines for the payer: the debit account and the credit account move by the same 
+    /// positive amount. Each line is signed in the sense of its account's nature, so that the caller only names the accounts.
+    /// Control accounts have no nature and cannot be posted to in a simple entry.
+    fn post_simple(
+        payer: AccountId,
+        counterparty: AccountId,
+        debit_account: Self::Account,
+        credit_account: Self::Account,
+        amount: Self::LedgerBalance,
+        reference: Hash,
+    ) -> Result
+    where
+        AccountId: Clone,
+        Hash: Clone,
+        BlockNumber: Clone,
+    {
+        let amount: LedgerBalance = amount.into();
+        if amount <= 0 {
+            return Err("Simple posting amount must be greater than zero");
+        }
+        let (debit_nature, credit_nature) = match (
+            ledger::is_debit_nature(debit_account.into()),
+            ledger::is_debit_nature(credit_account.into()),
+        ) {
+            (Some(d), Some(c)) => (d, c),
+            _ => return Err("Simple postings cannot be made to control accounts"),
+        };
+        let debit_amount: LedgerBalance = if debit_nature { amount } else { -amount };
+        let credit_amount: LedgerBalance = if credit_nature { -amount } else { amount };
+        let block = Self::get_block_number();
+        let keys = vec![
+            (payer.clone(), counterparty.clone(), debit_account, debit_amount.into(), false, reference.clone(), block.clone(), block.clone()),
+            (payer, counterparty, credit_account, credit_amount.into(), true, reference, block.clone(), block),
+        ];
+        Self::handle_multiposting_amounts(keys)
+    }
 }
 
 decl_storage! {
@@ -169,11 +411,117 @@ decl_storage! {
         // Accounting Balances
         BalanceByLedger get(balance_by_ledger): map (T::AccountId, Account) => LedgerBalance;
         // Detail of the accounting posting (for Audit)
-        PostingDetail get(posting_detail): map (T::AccountId, Account, u128) => Option<(T::AccountId, T::BlockNumber,LedgerBalance,Indicator,T::Hash, T::BlockNumber)>;
+        // The journal number is only assigned to user entries and adjustments
+        PostingDetail get(posting_detail): map (T::AccountId, Account, u128) => Option<PostingDetailRecord<T::AccountId, T::BlockNumber, T::Hash>>;
+        // Detail of the further lines of a posting to the same ledger account of an identity, from line 1 
+        // (an amount split over several lines). The first line is in PostingDetail.
+        PostingDetailLines get(posting_detail_line): map (T::AccountId, Account, u128, u32) => Option<PostingDetailRecord<T::AccountId, T::BlockNumber, T::Hash>>;
         // yay! Totem!
         GlobalLedger get(global_ledger): map Account => LedgerBalance;
+        // Accounts in the global ledger ordered by account number. Allows the global ledger to be read in pages
+        GlobalLedgerAccounts get(global_ledger_accounts): Vec<Account>;
         // Address to book the sales tax to and the tax jurisdiction (Experimental, may be deprecated in future)
         TaxesByJurisdiction get(taxes_by_jurisdiction): map (T::AccountId, T::AccountId) => LedgerBalance;
+        // Budgeted movement per period on a ledger account for an identity
+        BudgetByLedger get(budget_by_ledger): map (T::AccountId, Account) => Option<LedgerBalance>;
+        // Ledger accounts of an identity with a budget, so that budgets on accounts without postings are reported
+        BudgetAccounts get(budget_accounts): map T::AccountId => Vec<Account>;
+        // Period close in progress (close block, number of account balances carried forward so far)
+        PeriodCloseCursor get(period_close_cursor): map T::AccountId => Option<(T::BlockNumber, u32)>;
+        // Accounts of the period close in progress whose balance has not been carried forward yet, in account order. 
+        // Taken from the accounts of the identity when the close starts
+        PeriodCloseAccounts get(period_close_accounts): map T::AccountId => Vec<Account>;
+        // Ledger balance at the last period close. Used to derive the actual movement in the period being closed
+        PeriodCloseBalance get(period_close_balance): map (T::AccountId, Account) => LedgerBalance;
+        // The block number of the last period close for an identity
+        LastPeriodClose get(last_period_close): map T::AccountId => Option<T::BlockNumber>;
+        // Summary of the budget variances calculated at period close (Account, Budget, Actual, Variance)
+        VarianceSummary get(variance_summary): map (T::AccountId, T::BlockNumber) => Vec<(Account, LedgerBalance, LedgerBalance, LedgerBalance)>;
+        // External document reference supplied with the posting (for example an ERP document number)
+        PostingExternalReference get(posting_external_reference): map (T::AccountId, Account, u128) => Option<ExternalReference>;
+        // Reverse index to locate the postings of an identity by external document reference
+        ExternalReferenceIndex get(external_reference_index): map (T::AccountId, ExternalReference) => Vec<u128>;
+        // Postings made against a reference hash (for example an order). Identity, account, signed amount, posting index
+        PostingsByReference get(postings_by_reference): map T::Hash => Vec<(T::AccountId, Account, LedgerBalance, u128)>;
+        // Balance of a sub-ledger account per reference hash (for example the escrow held for each order)
+        SubLedgerBalance get(sub_ledger_balance): map (T::AccountId, Account, T::Hash) => LedgerBalance;
+        // Reference hashes with an open (non-zero) balance on a sub-ledger account of an identity
+        SubLedgerReferences get(sub_ledger_references): map (T::AccountId, Account) => Vec<T::Hash>;
+        // Postings committed in a block (Identity, Account, Amount, Posting Index, Reference Hash) for incremental indexing.
+        // Legacy index with one entry per line and no line number. No longer written.
+        PostingsByBlock get(postings_by_block): map T::BlockNumber => Vec<(T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash)>;
+        // Posting lines committed in a block by page (Identity, Account, Amount, Posting Index, Reference Hash, Line) for incremental indexing
+        BlockPostings get(block_postings_page): map (T::BlockNumber, u32) => Vec<(T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash, u32)>;
+        // Last page of the posting lines committed in a block
+        BlockPostingsLastPage get(block_postings_last_page): map T::BlockNumber => u32;
+        // References whose postings are of restricted visibility. The amounts are masked in the network wide posting feed only
+        RestrictedReferences get(restricted_reference): map T::Hash => bool;
+        // Network wide rounding policy for derived amounts. Defaults to half-up
+        RoundingMode get(rounding_mode): RoundingPolicy;
+        // Display profile of an identity for rendering counterparties (name hash, country code, business registration hash)
+        // Only hashes are stored on chain, the UI resolves them against the documents held off chain.
+        Profile get(profile): map T::AccountId => Option<(T::Hash, CountryCode, T::Hash)>;
+        // Attestation of the current profile by a registrar (registrar, block)
+        ProfileAttestation get(profile_attestation): map T::AccountId => Option<(T::AccountId, T::BlockNumber)>;
+        // Identities allowed to attest profiles
+        ProfileRegistrars get(profile_registrars): Vec<T::AccountId>;
+        // Display decimals and unit of ledger accounts in the chart of accounts. For quantity accounts the decimals
+        // are also the precision that postings must respect.
+        AccountDecimals get(account_decimals): map Account => Option<AccountDisplay>;
+        // Ordered list of the accounts with display metadata
+        AccountDecimalsAccounts get(account_decimals_accounts): Vec<Account>;
+        // Decimals of XTX, never written so that the constant is available to clients in the metadata
+        LedgerDecimals get(ledger_decimals): u8 = XTX_DECIMALS;
+        // Exchange rate of a reporting currency for a rate period (numerator, denominator): numerator smallest units of the 
+        // currency per denominator smallest units of XTX. A period without a rate uses the latest earlier rate.
+        ExchangeRates get(exchange_rates): map (CurrencyCode, u64) => Option<(LedgerBalance, LedgerBalance)>;
+        // Rate periods with an exchange rate per currency in ascending order
+        RatePeriods get(rate_periods): map CurrencyCode => Vec<u64>;
+        // Reporting (presentation) currency preferred by an identity. The functional currency is always XTX.
+        ReportingCurrency get(reporting_currency): map T::AccountId => Option<CurrencyCode>;
+        // Versions of the chart of accounts (version, effective from block) in ascending order. 
+        // The original chart is version 0 and is in force until the first version takes effect.
+        ChartVersions get(chart_versions): Vec<(u32, u64)>;
+        // Account numbers re-mapped by a chart version (previous account, new account)
+        ChartRemapping get(chart_remapping): map u32 => Vec<(Account, Account)>;
+        // Last journal number assigned to the user entries of an identity
+        LastJournalNumber get(last_journal_number): map T::AccountId => JournalNumber;
+        // Posting index of a journal of an identity
+        Journals get(journal): map (T::AccountId, JournalNumber) => Option<PostingIndex>;
+        // Tags set by an identity on one of its journals
+        JournalTags get(journal_tags): map (T::AccountId, JournalNumber) => Vec<PostingTag>;
+        // Journals of an identity carrying a tag, in ascending order
+        TaggedJournals get(tagged_journals): map (T::AccountId, PostingTag) => Vec<JournalNumber>;
+        // Opening balance set by an identity on an account, signed in the sense of the account's nature
+        OpeningBalance get(opening_balance): map (T::AccountId, Account) => Option<LedgerBalance>;
+        // Parent account offset by a contra account in the chart of accounts
+        ContraAccounts get(contra_of): map Account => Option<Account>;
+        // Ordered list of the contra accounts
+        ContraAccountsList get(contra_accounts_list): Vec<Account>;
+        // Number of blocks a posting may be retargeted before the current block without being flagged as backdated
+        BackdatingTolerance get(backdating_tolerance): u64 = DEFAULT_BACKDATING_TOLERANCE;
+        // Identity nominated by an identity to approve its backdated postings
+        BackdatingApprover get(backdating_approver): map T::AccountId => Option<T::AccountId>;
+        // Blocks backdated of the postings of an identity awaiting approval, keyed by (identity, posting index) 
+        // so that the postings awaiting approval do not accumulate in one value
+        UnapprovedBackdated get(unapproved_backdated): map (T::AccountId, PostingIndex) => Option<u64>;
+        // Fees charged to a payer in the current block, posted as one batch when the block is finalised
+        PendingFees get(pending_fees): map T::AccountId => LedgerBalance;
+        // Payers with pending fees in the current block, in the order they were first charged
+        PendingFeePayers get(pending_fee_payers): Vec<T::AccountId>;
+        // Fee of the last extrinsic of a payer in the current block (extrinsic index, fee), until it is shared with a 
+        // counterparty or the block is finalised
+        LastFee get(last_fee): map T::AccountId => Option<(u32, LedgerBalance)>;
+        // Shares of fees recharged to counterparties in the current block (payer, counterparty, amount, reference), 
+        // posted with the fees when the block is finalised
+        PendingFeeShares get(pending_fee_shares): Vec<(T::AccountId, T::AccountId, LedgerBalance, T::Hash)>;
+        // Credit limit set by an identity for a counterparty (limit, enforced). Invoices exceeding an enforced limit
+        // are rejected, otherwise the identity is warned
+        CreditLimits get(credit_limit): map (T::AccountId, T::AccountId) => Option<(LedgerBalance, bool)>;
+        // Open receivables of an identity from a counterparty (trade receivables posted with the counterparty)
+        ReceivableExposure get(receivable_exposure): map (T::AccountId, T::AccountId) => LedgerBalance;
+        // Counterparties of an identity with a credit limit or an open receivable
+        ExposureCounterparties get(exposure_counterparties): map T::AccountId => Vec<T::AccountId>;
 
         // TODO
         // Quantities Accounting
@@ -184,109 +532,408 @@ decl_storage! {
 decl_module! {
 pub struct Module<T: Trait> for enum Call where origin: T::Origin {
     fn deposit_event<T>() = default;
-    // fn opening_balance() -> Result {
-        //     Ok(())
-        // }
-        // fn adjustment() -> Result {
-            //     Ok(())
-            // }
-        }
+    fn on_finalize(_n: T::BlockNumber) {
+        Self::flush_fee_postings();
     }
-
-impl<T: Trait> Module<T> {
-    #[allow(dead_code)]
-    /// Basic posting function (warning! can cause imbalance if not called with corresponding debit or credit entries)
-    /// The reason why this is a simple function is that (for example) one debit posting may correspond with one or many credit
-    /// postings and vice-versa. For example a debit to Accounts Receivable is the gross invoice amount, which could correspond with
-    /// a credit to liabilities for the sales tax amount and a credit to revenue for the net invoice amount. The sum of both credits being
-    /// equal to the single debit in accounts receivable, but only one posting needs to be made to that account, and two posting for the others.
-    /// The Totem Accounting Recipes are constructed using this simple function.
-    /// The second Blocknumber is for re-targeting the entry in the accounts, i.e. for adjustments prior to or after the current period (generally accruals).
-    fn post_amounts(
-        (o, p, a, c, d, h, b, t, i): (
-            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,PostingIndex,
-        ),
-    ) -> Result {
-        let new_balance: LedgerBalance;
-        let new_global_balance: LedgerBalance;
-        let posting_index = i.into();
-        
-        let ab: LedgerBalance = c.abs();
-        let balance_key = (o.clone(), a);
-        let posting_key = (o.clone(), a, posting_index);
-        let detail = (p, b, ab, d, h, t);
-        // !! Warning !!
-        // Values could feasibly overflow, with no visibility on other accounts. In this event this function returns an error.
-        // Reversals must occur in the parent function (i.e. that calls this function).
-        // As all values passed to this function are already signed +/- we only need to sum to the previous balance and check for overflow
-        // Updates are only made to storage once tests below are passed for debits or credits.
-        match Self::balance_by_ledger(&balance_key).checked_add(c) {
-            None => {
-                Self::deposit_event(RawEvent::ErrorOverflow(a));
-                return Err("Balance Value overflowed");
+    /// Sets the budgeted movement for a period on one of the identity's ledger accounts
+    fn set_budget(origin, account: Account, amount: LedgerBalance) -> Result {
+        let who = ensure_signed(origin)?;
+        let mut accounts = Self::budget_accounts(&who);
+        if !accounts.contains(&account) {
+            if accounts.len() >= MAX_BUDGETS {
+                Self::deposit_event(RawEvent::ErrorTooManyBudgets());
+                return Err("Too many budgets");
             }
+            accounts.push(account);
+            <BudgetAccounts<T>>::insert(&who, accounts);
+        }
+        <BudgetByLedger<T>>::insert((who.clone(), account), amount);
+        Self::deposit_event(RawEvent::BudgetSet(who, account, amount));
+        Ok(())
+    }
+    /// Removes the budget for one of the identity's ledger accounts
+    fn remove_budget(origin, account: Account) -> Result {
+        let who = ensure_signed(origin)?;
+        <BudgetByLedger<T>>::remove((who.clone(), account));
+        <BudgetAccounts<T>>::mutate(&who, |accounts| accounts.retain(|a| a != &account));
+        Self::deposit_event(RawEvent::BudgetRemoved(who, account));
+        Ok(())
+    }
+    /// Sets or removes the credit limit of the identity for a counterparty. With an enforced limit new invoices that would
+    /// take the open receivables from the counterparty above the limit are rejected, otherwise a warning is issued.
+    fn set_credit_limit(origin, counterparty: T::AccountId, limit: Option<LedgerBalance>, enforced: bool) -> Result {
+        let who = ensure_signed(origin)?;
+        let key = (who.clone(), counterparty.clone());
+        match limit {
+            Some(l) if l < 0 || counterparty == who => {
+                Self::deposit_event(RawEvent::ErrorCreditLimit());
+                return Err("Invalid credit limit");
+            },
             Some(l) => {
-                new_balance = l;
-                match Self::global_ledger(&a).checked_add(c) {
-                    Some(g) => new_global_balance = g,        
-                    None => {
-                        Self::deposit_event(RawEvent::ErrorGlobalOverflow());
-                        return Err("Global Balance Value overflowed");
-                    }
+                <CreditLimits<T>>::insert(&key, (l, enforced));
+                Self::add_exposure_counterparty(&who, &counterparty);
+            },
+            None => {
+                <CreditLimits<T>>::remove(&key);
+                if Self::receivable_exposure(&key) == 0 {
+                    <ExposureCounterparties<T>>::mutate(&who, |counterparties| counterparties.retain(|c| c != &counterparty));
                 }
+            },
+        }
+        Self::deposit_event(RawEvent::CreditLimitSet(who, counterparty, limit, enforced));
+        Ok(())
+    }
+    /// Closes the accounting period for the identity at the current block.
+    /// For every ledger account that has a budget the variance between the budget and the actual movement
+    /// since the last period close is calculated, issued as an event and stored as a summary.
+    /// The balances are carried forward a page of accounts at a time: while the close is in progress 
+    /// each further call continues it, and the period is closed once every account is carried forward.
+    fn close_period(origin) -> Result {
+        let who = ensure_signed(origin)?;
+        Self::close_period_for(who)?;
+        Ok(())
+    }
+    /// Sets or updates the display profile of the identity. Any attestation of the previous profile is removed.
+    fn set_profile(origin, name_hash: T::Hash, country: CountryCode, registration_hash: T::Hash) -> Result {
+        let who = ensure_signed(origin)?;
+        if country == 0 || country > 999 {
+            Self::deposit_event(RawEvent::ErrorCountryCode());
+            return Err("Invalid country code");
+        }
+        <Profile<T>>::insert(&who, (name_hash, country, registration_hash));
+        <ProfileAttestation<T>>::remove(&who);
+        Self::deposit_event(RawEvent::ProfileSet(who));
+        Ok(())
+    }
+    /// Removes the display profile of the identity and its attestation
+    fn remove_profile(origin) -> Result {
+        let who = ensure_signed(origin)?;
+        <Profile<T>>::remove(&who);
+        <ProfileAttestation<T>>::remove(&who);
+        Self::deposit_event(RawEvent::ProfileRemoved(who));
+        Ok(())
+    }
+    /// A registrar attests that the profile of the identity has been verified
+    fn attest_profile(origin, identity: T::AccountId) -> Result {
+        let who = ensure_signed(origin)?;
+        if !Self::profile_registrars().contains(&who) {
+            Self::deposit_event(RawEvent::ErrorNotProfileRegistrar());
+            return Err("Not a profile registrar");
+        }
+        if !<Profile<T>>::exists(&identity) {
+            Self::deposit_event(RawEvent::ErrorNoProfile());
+            return Err("Identity has no profile");
+        }
+        let current_block = <system::Module<T>>::block_number();
+        <ProfileAttestation<T>>::insert(&identity, (who.clone(), current_block));
+        Self::deposit_event(RawEvent::ProfileAttested(identity, who));
+        Ok(())
+    }
+    /// Super User adds a profile registrar
+    fn add_profile_registrar(origin, registrar: T::AccountId) -> Result {
+        ensure_root(origin)?;
+        <ProfileRegistrars<T>>::mutate(|registrars| {
+            registrars.retain(|r| r != &registrar);
+            registrars.push(registrar)
+        });
+        Ok(())
+    }
+    /// Super User removes a profile registrar. Existing attestations are kept.
+    fn remove_profile_registrar(origin, registrar: T::AccountId) -> Result {
+        ensure_root(origin)?;
+        <ProfileRegistrars<T>>::mutate(|registrars| registrars.retain(|r| r != &registrar));
+        Ok(())
+    }
+    /// Super User sets the display decimals and unit of a ledger account. 
+    /// Quantity accounts cannot have more than QUANTITY_DECIMALS decimals.
+    fn set_account_decimals(origin, account: Account, decimals: u8, unit: Vec<u8>, quantity: bool) -> Result {
+        ensure_root(origin)?;
+        let max_decimals: u8 = if quantity { QUANTITY_DECIMALS } else { MAX_DISPLAY_DECIMALS };
+        if decimals > max_decimals || unit.len() > MAX_DISPLAY_UNIT_LENGTH {
+            Self::deposit_event(RawEvent::ErrorAccountDecimals(account));
+            return Err("Invalid account decimals or display unit");
+        }
+        <AccountDecimals<T>>::insert(account, (decimals, unit, quantity));
+        <AccountDecimalsAccounts<T>>::mutate(|accounts| {
+            if let Err(pos) = accounts.binary_search(&account) {
+                accounts.insert(pos, account);
+            }
+        });
+        Self::deposit_event(RawEvent::AccountDecimalsSet(account, decimals, quantity));
+        Ok(())
+    }
+    /// Super User removes the display decimals and unit of a ledger account
+    fn remove_account_decimals(origin, account: Account) -> Result {
+        ensure_root(origin)?;
+        <AccountDecimals<T>>::remove(account);
+        <AccountDecimalsAccounts<T>>::mutate(|accounts| accounts.retain(|a| a != &account));
+        Ok(())
+    }
+    /// Super User marks an account as contra to a parent account of the same statement type and category. 
+    /// A parent cannot itself be a contra account, so that the netting is one level deep.
+    fn set_contra_account(origin, contra: Account, parent: Account) -> Result {
+        ensure_root(origin)?;
+        if !ledger::is_valid_contra(contra, parent) 
+            || <ContraAccounts<T>>::exists(parent) 
+            || Self::contra_accounts_list().into_iter().any(|c| Self::contra_of(c) == Some(contra)) 
+        {
+            Self::deposit_event(RawEvent::ErrorContraAccount(contra));
+            return Err("Invalid contra account");
+        }
+        <ContraAccounts<T>>::insert(contra, parent);
+        <ContraAccountsList<T>>::mutate(|accounts| {
+            if let Err(pos) = accounts.binary_search(&contra) {
+                accounts.insert(pos, contra);
             }
+        });
+        Self::deposit_event(RawEvent::ContraAccountSet(contra, parent));
+        Ok(())
+    }
+    /// Super User removes the contra relationship of an account
+    fn remove_contra_account(origin, contra: Account) -> Result {
+        ensure_root(origin)?;
+        let parent: Account = match <ContraAccounts<T>>::take(contra) {
+            Some(parent) => parent,
+            None => {
+                Self::deposit_event(RawEvent::ErrorContraAccount(contra));
+                return Err("Not a contra account");
+            },
         };
-
-        <PostingNumber<T>>::put(posting_index);
-        // The index should be unique, it may already have been posted?
-        <IdAccountPostingIdList<T>>::mutate(&balance_key, |id_account_posting_id_list| {id_account_posting_id_list.retain(|i| i != &posting_index)});
-        <IdAccountPostingIdList<T>>::mutate(&balance_key, |id_account_posting_id_list| {id_account_posting_id_list.push(posting_index)});
-
-        <AccountsById<T>>::mutate(&o, |accounts_by_id| accounts_by_id.retain(|h| h != &a));
-        <AccountsById<T>>::mutate(&o, |accounts_by_id| accounts_by_id.push(a));
-        // <BalanceByLedger<T>>::remove(&balance_key);
-        <BalanceByLedger<T>>::insert(&balance_key, new_balance);
-        // <PostingDetail<T>>::remove(&posting_key);
-        <PostingDetail<T>>::insert(&posting_key, detail);
-        // <GlobalLedger<T>>::remove(&a);
-        <GlobalLedger<T>>::insert(&a, new_global_balance);
-
-        Self::deposit_event(RawEvent::LegderUpdate(o, a, c, posting_index));
-
+        <ContraAccountsList<T>>::mutate(|accounts| accounts.retain(|a| a != &contra));
+        Self::deposit_event(RawEvent::ContraAccountRemoved(contra, parent));
         Ok(())
     }
-}
-
-impl<T: Trait> Posting<T::AccountId, T::Hash, T::BlockNumber, T::CoinAmount> for Module<T> 
-where 
-    T::AccountId: UncheckedFrom<[u8; 32]>,
-{
-    type Account = Account;
-    type LedgerBalance = LedgerBalance;
-    type PostingIndex = PostingIndex;
+    /// Sets the rounding policy used for derived amounts (0 half-up, 1 half-even)
+    fn set_rounding_policy(origin, policy: RoundingPolicy) -> Result {
+        ensure_root(origin)?;
+        match policy {
+            ROUND_HALF_UP | ROUND_HALF_EVEN => (),
+            _ => {
+                Self::deposit_event(RawEvent::ErrorRoundingPolicy());
+                return Err("Unknown rounding policy");
+            },
+        }
+        <RoundingMode<T>>::put(policy);
+        Self::deposit_event(RawEvent::RoundingPolicySet(policy));
+        Ok(())
+    }
+    /// Super User publishes the exchange rate of a reporting currency for a rate period: 
+    /// numerator smallest units of the currency per denominator smallest units of XTX
+    fn set_exchange_rate(origin, currency: CurrencyCode, period: u64, numerator: LedgerBalance, denominator: LedgerBalance) -> Result {
+        ensure_root(origin)?;
+        if currency == 0 || currency > 999 || numerator <= 0 || denominator <= 0 {
+            Self::deposit_event(RawEvent::ErrorExchangeRate(currency));
+            return Err("Invalid exchange rate");
+        }
+        <ExchangeRates<T>>::insert((currency, period), (numerator, denominator));
+        <RatePeriods<T>>::mutate(currency, |periods| {
+            if let Err(i) = periods.binary_search(&period) {
+                periods.insert(i, period);
+            }
+        });
+        Self::deposit_event(RawEvent::ExchangeRateSet(currency, period, numerator, denominator));
+        Ok(())
+    }
+    /// Sets the reporting currency in which the read APIs also present the figures of the identity. 
+    /// Only currencies with a published exchange rate can be set. Zero removes the preference.
+    fn set_reporting_currency(origin, currency: CurrencyCode) -> Result {
+        let who = ensure_signed(origin)?;
+        if currency == 0 {
+            <ReportingCurrency<T>>::remove(&who);
+        } else {
+            if Self::rate_periods(currency).is_empty() {
+                Self::deposit_event(RawEvent::ErrorUnknownCurrency(currency));
+                return Err("No exchange rate for this currency");
+            }
+            <ReportingCurrency<T>>::insert(&who, currency);
+        }
+        Self::deposit_event(RawEvent::ReportingCurrencySet(who, currency));
+        Ok(())
+    }
+    /// Super User adds a version of the chart of accounts that re-maps account numbers from a block onwards. 
+    /// Postings from that block are made to the new accounts and the read APIs report earlier postings 
+    /// under the accounts in force at the block being reported on.
+    fn add_chart_version(origin, effective_from: u64, remapping: Vec<(Account, Account)>) -> Result {
+        ensure_root(origin)?;
+        let current_block: u64 = <system::Module<T>>::block_number().as_();
+        let versions: Vec<(u32, u64)> = Self::chart_versions();
+        let last: Option<(u32, u64)> = versions.last().cloned();
+        if effective_from <= current_block
+            || last.map_or(false, |v| effective_from <= v.1)
+            || remapping.is_empty()
+            || remapping.len() > MAX_CHART_REMAPPINGS
+            || !ledger::is_valid_remapping(&remapping)
+        {
+            Self::deposit_event(RawEvent::ErrorChartVersion());
+            return Err("Invalid chart of accounts version");
+        }
+        let version: u32 = match last {
+            Some(v) => v.0.checked_add(1).ok_or("Chart version overflowed")?,
+            None => 1,
+        };
+        <ChartRemapping<T>>::insert(version, remapping);
+        <ChartVersions<T>>::mutate(|versions| versions.push((version, effective_from)));
+        Self::deposit_event(RawEvent::ChartVersionAdded(version, effective_from));
+        Ok(())
+    }
+    /// Super User rebuilds the posting indices (AccountsById and IdAccountPostingIdList) from the posting detail of the 
+    /// postings given (identity, account, posting index). Used to recover from corrupted indices and to migrate the index 
+    /// format. Postings without posting detail are not indexed, and the entries without posting detail are dropped from 
+    /// the lists of the accounts rebuilt, so that nothing is cleared that the posting detail cannot restore. 
+    /// Bounded per call by MAX_REBUILD_KEYS.
+    fn rebuild_indices(origin, keys: Vec<(T::AccountId, Account, PostingIndex)>) -> Result {
+        ensure_root(origin)?;
+        if keys.is_empty() || keys.len() > MAX_REBUILD_KEYS {
+            Self::deposit_event(RawEvent::ErrorRebuildIndices());
+            return Err("Too many postings to rebuild in one call");
+        }
+        let mut indexed: u32 = 0;
+        let mut dropped: u32 = 0;
+        let mut rebuilt: Vec<(T::AccountId, Account)> = Vec::new();
+        for (o, a, i) in keys.into_iter() {
+            if !rebuilt.contains(&(o.clone(), a)) {
+                // Duplicated and dangling entries of the list are dropped once, before the first posting is indexed
+                <IdAccountPostingIdList<T>>::mutate((o.clone(), a), |list| {
+                    let before: usize = list.len();
+                    list.sort();
+                    list.dedup();
+                    list.retain(|n| <PostingDetail<T>>::exists((o.clone(), a, *n)));
+                    dropped = dropped.saturating_add((before - list.len()) as u32);
+                });
+                rebuilt.push((o.clone(), a));
+            }
+            if !<PostingDetail<T>>::exists((o.clone(), a, i)) {
+                continue;
+            }
+            <IdAccountPostingIdList<T>>::mutate((o.clone(), a), |list| {
+                if let Err(pos) = list.binary_search(&i) {
+                    list.insert(pos, i);
+                    indexed = indexed.saturating_add(1);
+                }
+            });
+            <AccountsById<T>>::mutate(&o, |accounts| {
+                if !accounts.contains(&a) {
+                    accounts.push(a);
+                }
+            });
+        }
+        Self::deposit_event(RawEvent::IndicesRebuilt(indexed, dropped));
+        Ok(())
+    }
+    /// Sets the opening balance of a balance sheet account of the identity. The amount is signed in the sense of the 
+    /// account's nature: positive is a debit on an asset and a credit on a liability or equity account, negative is the 
+    /// opposite (for example an overdrawn bank account). The other side is posted to the opening balance equity account.
+    /// An opening balance can only be set once per account.
+    fn set_opening_balance(origin, account: Account, amount: LedgerBalance) -> Result {
+        let who = ensure_signed(origin)?;
+        Self::set_opening_balance_for(who, account, amount)
+    }
+    /// Super User sets the number of blocks a posting may be retargeted before the current block without being flagged
+    fn set_backdating_tolerance(origin, tolerance: u64) -> Result {
+        ensure_root(origin)?;
+        <BackdatingTolerance<T>>::put(tolerance);
+        Self::deposit_event(RawEvent::BackdatingToleranceSet(tolerance));
+        Ok(())
+    }
+    /// Nominates the identity (for example an auditor or a second officer) that approves the backdated postings 
+    /// of the sender. No approver removes the nomination.
+    fn set_backdating_approver(origin, approver: Option<T::AccountId>) -> Result {
+        let who = ensure_signed(origin)?;
+        match approver.clone() {
+            Some(a) => {
+                if a == who {
+                    Self::deposit_event(RawEvent::ErrorBackdatingApprover());
+                    return Err("The approver of backdated postings must be another identity");
+                }
+                <BackdatingApprover<T>>::insert(&who, a);
+            },
+            None => <BackdatingApprover<T>>::remove(&who),
+        }
+        Self::deposit_event(RawEvent::BackdatingApproverSet(who, approver));
+        Ok(())
+    }
+    /// The approver nominated by an identity approves one of its backdated postings
+    fn approve_backdated_posting(origin, identity: T::AccountId, index: PostingIndex) -> Result {
+        let who = ensure_signed(origin)?;
+        if Self::backdating_approver(&identity) != Some(who.clone()) {
+            Self::deposit_event(RawEvent::ErrorBackdatingApprover());
+            return Err("Not the approver of backdated postings of this identity");
+        }
+        let key = (identity.clone(), index);
+        if !<UnapprovedBackdated<T>>::exists(&key) {
+            Self::deposit_event(RawEvent::ErrorBackdatingApprover());
+            return Err("No backdated posting awaiting approval with this index");
+        }
+        <UnapprovedBackdated<T>>::remove(&key);
+        Self::deposit_event(RawEvent::BackdatedPostingApproved(identity, index, who));
+        Ok(())
+    }
+    /// Sets the tags of one of the identity's journals (user entries), replacing the tags already set. 
+    /// Up to MAX_POSTING_TAGS distinct tags of at most MAX_POSTING_TAG_LENGTH bytes. No tags removes them.
+    fn set_journal_tags(origin, journal: JournalNumber, tags: Vec<PostingTag>) -> Result {
+        let who = ensure_signed(origin)?;
+        let mut sorted = tags.clone();
+        sorted.sort();
+        sorted.dedup();
+        if tags.len() > MAX_POSTING_TAGS 
+            || sorted.len() != tags.len() 
+            || tags.iter().any(|t| t.is_empty() || t.len() > MAX_POSTING_TAG_LENGTH) 
+        {
+            Self::deposit_event(RawEvent::ErrorPostingTags(journal));
+            return Err("Too many tags, a tag is repeated, empty or too long");
+        }
+        if !<Journals<T>>::exists((who.clone(), journal)) {
+            Self::deposit_event(RawEvent::ErrorPostingTags(journal));
+            return Err("Journal does not exist");
+        }
+        for t in <JournalTags<T>>::take((who.clone(), journal)).into_iter() {
+            <TaggedJournals<T>>::mutate((who.clone(), t), |journals| journals.retain(|n| n != &journal));
+        }
+        for t in tags.iter() {
+            <TaggedJournals<T>>::mutate((who.clone(), t.clone()), |journals| {
+                if let Err(pos) = journals.binary_search(&journal) {
+                    journals.insert(pos, journal);
+                }
+            });
+        }
+        if !tags.is_empty() {
+            <JournalTags<T>>::insert((who.clone(), journal), tags.clone());
+        }
+        Self::deposit_event(RawEvent::JournalTagsSet(who, journal, tags));
+        Ok(())
+    }
+    // fn adjustment() -> Result {
+            //     Ok(())
+            // }
+        }
+    }
 
-    /// The Totem Accounting Recipes are constructed using this function which handles posting to multiple accounts.
-    /// It is exposed to other modules as a trait
-    /// If for whatever reason an error occurs during the storage processing which is sequential
-    /// this function also handles reversing out the prior accounting entries
-    /// Therefore the recipes that are passed as arguments need to be be accompanied with a reversal
-    /// Obviously the last posting does not need a reversal for if it errors, then it was not posted in the first place.
-    fn handle_multiposting_amounts(
-        // o: <T as system::Trait>::AccountId,
-        // o: T::AccountId,
-        fwd: Vec<(
-            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-        )>,
-        rev: Vec<(
-            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-        )>,
-        trk: Vec<(
+impl<T: Trait> Module<T> {
+    /// Posts a batch of postings with their origin. The movements of trade receivables with a counterparty are tracked 
+    /// as credit exposure, except for postings that do not invoice or settle (for example the recharge of a fee share).
+    fn post_batch(
+        keys: Vec<(
             T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
         )>,
+        ext_ref: ExternalReference,
+        origin: PostingOrigin,
+        exposure: bool,
     ) -> Result {
-        let reversal_keys = rev.clone();
-        let mut track_rev_keys = trk.clone();
-        let length_limit = track_rev_keys.len();
+        if origin > ORIGIN_MIGRATION {
+            Self::deposit_event(RawEvent::ErrorPostingOrigin());
+            return Err("Unknown posting origin");
+        }
+        if ext_ref.len() > MAX_EXTERNAL_REFERENCE_LENGTH {
+            Self::deposit_event(RawEvent::ErrorExternalReferenceTooLong());
+            return Err("External reference is too long");
+        }
+
+        // Postings are made to the accounts of the chart of accounts in force
+        let current_block: u64 = <system::Module<T>>::block_number().as_();
+        let keys: Vec<_> = keys
+            .into_iter()
+            .map(|k| (k.0, k.1, Self::chart_account(k.2, current_block), k.3, k.4, k.5, k.6, k.7))
+            .collect();
         
         let mut posting_index: PostingIndex = 0;
         if <PostingNumber<T>>::exists() {
@@ -298,61 +945,207 @@ where
                 }
             }
         }
-
-        // Iterate over forward keys. If Ok add reversal key to tracking, if error, then reverse out prior postings.
-        for (pos, a) in fwd.clone().iter().enumerate() {
-            // build tuple for posting
-            let p = (a.0.clone(), a.1.clone(), a.2, a.3, a.4, a.5, a.6, a.7, posting_index);
+        
+        // Buffers for the new balances. The same account may be posted to more than once in a batch
+        // so the buffered value is used in preference to the stored value.
+        let mut new_balances = Vec::<((T::AccountId, Account), LedgerBalance)>::with_capacity(keys.len());
+        let mut new_global_balances = Vec::<(Account, LedgerBalance)>::with_capacity(keys.len());
+        let mut new_sub_balances = Vec::<((T::AccountId, Account, T::Hash), LedgerBalance)>::new();
+        
+        // !! Warning !!
+        // Values could feasibly overflow, with no visibility on other accounts. In this event this function returns an error
+        // before anything has been written to storage.
+        // As all values passed to this function are already signed +/- we only need to sum to the previous balance and check for overflow
+        for k in keys.iter() {
+            if !Self::respects_precision(k.2, k.3) {
+                Self::deposit_event(RawEvent::ErrorPrecision(k.2));
+                return Err("Amount exceeds the precision of the quantity account");
+            }
+            let balance_key = (k.0.clone(), k.2);
+            let balance: LedgerBalance = match new_balances.iter().find(|b| b.0 == balance_key) {
+                Some(b) => b.1,
+                None => Self::balance_by_ledger(&balance_key),
+            };
+            let new_balance: LedgerBalance = match balance.checked_add(k.3) {
+                Some(l) => l,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow(k.2));
+                    return Err("Balance Value overflowed");
+                },
+            };
+            new_balances.retain(|b| b.0 != balance_key);
+            new_balances.push((balance_key, new_balance));
             
-            // match Self::post_amounts(a.clone()) {
-                match Self::post_amounts(p.clone()) {
-                    Ok(_) => {
-                        if pos < length_limit {
-                            track_rev_keys.push(reversal_keys[pos].clone())
-                        };
-                    }
-                    Err(_e) => {
-                        // Error before the value was updated. Need to reverse-out the earlier debit amount and account combination
-                        // as this has already changed in storage.
-                        for (_dummy_pos, b) in track_rev_keys.iter().enumerate() {
-                        let r = (b.0.clone(), b.1.clone(), b.2, b.3, b.4, b.5, b.6, b.7, posting_index);
-
-                        // match Self::post_amounts(b.clone()) {
-                        match Self::post_amounts(r.clone()) {
-                            Ok(_) => (),                
-                            Err(_e) => {
-                                // This event is because there is a major system error in the reversal process
-                                Self::deposit_event(RawEvent::ErrorInError());
-                                return Err("System Failure in Account Posting");
-                            }
-                        }
-                    }
-                    Self::deposit_event(RawEvent::ErrorOverflow(a.2));
-                    return Err("Overflow error, amount too big!");
+            let global_balance: LedgerBalance = match new_global_balances.iter().find(|g| g.0 == k.2) {
+                Some(g) => g.1,
+                None => Self::global_ledger(&k.2),
+            };
+            let new_global_balance: LedgerBalance = match global_balance.checked_add(k.3) {
+                Some(g) => g,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorGlobalOverflow());
+                    return Err("Global Balance Value overflowed");
+                },
+            };
+            new_global_balances.retain(|g| g.0 != k.2);
+            new_global_balances.push((k.2, new_global_balance));
+
+            if Self::is_sub_ledger_account(k.2) {
+                let sub_key = (k.0.clone(), k.2, k.5);
+                let sub_balance: LedgerBalance = match new_sub_balances.iter().find(|s| s.0 == sub_key) {
+                    Some(s) => s.1,
+                    None => Self::sub_ledger_balance(&sub_key),
+                };
+                let new_sub_balance: LedgerBalance = match sub_balance.checked_add(k.3) {
+                    Some(s) => s,
+                    None => {
+                        Self::deposit_event(RawEvent::ErrorOverflow(k.2));
+                        return Err("Sub-ledger Balance Value overflowed");
+                    },
+                };
+                new_sub_balances.retain(|s| s.0 != sub_key);
+                new_sub_balances.push((sub_key, new_sub_balance));
+            }
+        }
+        
+        // User entries and adjustments get the next journal number of each identity in the batch
+        let mut journals = Vec::<(T::AccountId, JournalNumber)>::new();
+        if origin == ORIGIN_USER || origin == ORIGIN_ADJUSTMENT {
+            for k in keys.iter() {
+                if journals.iter().any(|j| j.0 == k.0) {
+                    continue;
+                }
+                match Self::last_journal_number(&k.0).checked_add(1) {
+                    Some(n) => journals.push((k.0.clone(), n)),
+                    None => {
+                        Self::deposit_event(RawEvent::ErrorGlobalOverflow());
+                        return Err("Journal number overflowed");
+                    },
+                }
+            }
+        }
+        
+        let replication = Self::replication_record(posting_index, origin, &keys, &journals, ext_ref.clone());
+        let backdated = Self::backdated_identities(&keys);
+        
+        // All checks passed. Commit everything to storage.
+        <PostingNumber<T>>::put(posting_index);
+        for (o, n) in journals.iter() {
+            <LastJournalNumber<T>>::insert(o, *n);
+            <Journals<T>>::insert((o.clone(), *n), posting_index);
+            Self::deposit_event(RawEvent::JournalRecorded(o.clone(), *n, posting_index));
+        }
+        let mut lines = Vec::<((T::AccountId, Account), u32)>::with_capacity(keys.len());
+        for k in keys.into_iter() {
+            let line: u32 = match lines.iter_mut().find(|l| (l.0).0 == k.0 && (l.0).1 == k.2) {
+                Some(l) => {
+                    l.1 = l.1.saturating_add(1);
+                    l.1
+                },
+                None => {
+                    lines.push(((k.0.clone(), k.2), 0));
+                    0
+                },
+            };
+            if !ext_ref.is_empty() {
+                <PostingExternalReference<T>>::insert((k.0.clone(), k.2, posting_index), ext_ref.clone());
+                <ExternalReferenceIndex<T>>::mutate((k.0.clone(), ext_ref.clone()), |index| {
+                    index.retain(|i| i != &posting_index);
+                    index.push(posting_index);
+                });
+            }
+            let journal: Option<JournalNumber> = journals.iter().find(|j| j.0 == k.0).map(|j| j.1);
+            Self::commit_posting((k.0, k.1, k.2, k.3, k.4, k.5, k.6, k.7, posting_index), line, origin, journal, exposure);
+        }
+        for (balance_key, new_balance) in new_balances.into_iter() {
+            Self::carry_before_posting(&balance_key.0, balance_key.1);
+            <BalanceByLedger<T>>::insert(&balance_key, new_balance);
+        }
+        for (account, new_global_balance) in new_global_balances.into_iter() {
+            <GlobalLedger<T>>::insert(&account, new_global_balance);
+            <GlobalLedgerAccounts<T>>::mutate(|global_ledger_accounts| {
+                if let Err(pos) = global_ledger_accounts.binary_search(&account) {
+                    global_ledger_accounts.insert(pos, account);
                 }
+            });
+        }
+        // References drop out of the sub-ledger once their balance has been cleared
+        for (sub_key, new_sub_balance) in new_sub_balances.into_iter() {
+            let references_key = (sub_key.0.clone(), sub_key.1);
+            let reference = sub_key.2;
+            if new_sub_balance == 0 {
+                <SubLedgerBalance<T>>::remove(&sub_key);
+                <SubLedgerReferences<T>>::mutate(&references_key, |references| references.retain(|h| h != &reference));
+            } else {
+                <SubLedgerBalance<T>>::insert(&sub_key, new_sub_balance);
+                <SubLedgerReferences<T>>::mutate(&references_key, |references| {
+                    if !references.contains(&reference) {
+                        references.push(reference);
+                    }
+                });
             }
         }
+        // Backdating is surfaced, and kept for approval, rather than blocked: the recipes of the other modules cannot wait
+        for (o, target, gap) in backdated.into_iter() {
+            <UnapprovedBackdated<T>>::insert((o.clone(), posting_index), gap);
+            Self::deposit_event(RawEvent::BackdatedPosting(o, posting_index, target, gap));
+        }
+        Self::deposit_event(RawEvent::JournalReplicated(replication));
+        
         Ok(())
     }
-    /// This function simply returns the Totem escrow account address
-    fn get_escrow_account() -> T::AccountId {
-        let escrow_account: [u8;32] = *b"TotemsEscrowAddress4LockingFunds";
-        UncheckedFrom::unchecked_from(escrow_account)
+    /// Posts the fees accumulated in the block, one batch per payer. A fee or a share that fails to post reports 
+    /// the error event of the posting and stays pending, so that it is retried when the next block is finalised.
+    fn flush_fee_postings() {
+        let mut unposted_payers: Vec<T::AccountId> = Vec::new();
+        for payer in <PendingFeePayers<T>>::take().into_iter() {
+            <LastFee<T>>::remove(&payer);
+            match Self::post_fees(Self::pending_fees(&payer), payer.clone()) {
+                Ok(_) => <PendingFees<T>>::remove(&payer),
+                Err(_e) => unposted_payers.push(payer),
+            }
+        }
+        if !unposted_payers.is_empty() {
+            <PendingFeePayers<T>>::put(unposted_payers);
+        }
+        let mut unposted_shares: Vec<(T::AccountId, T::AccountId, LedgerBalance, T::Hash)> = Vec::new();
+        for (payer, counterparty, amount, reference) in <PendingFeeShares<T>>::take().into_iter() {
+            if let Err(_e) = Self::post_fee_share(payer.clone(), counterparty.clone(), amount, reference) {
+                unposted_shares.push((payer, counterparty, amount, reference));
+            }
+        }
+        if !unposted_shares.is_empty() {
+            <PendingFeeShares<T>>::put(unposted_shares);
+        }
     }
-    /// This function simply returns the Totem network fees account address
-    fn get_netfees_account() -> T::AccountId {
-        let netfees_account: [u8;32] = *b"TotemAccountingNetworkFeeAddress";
-        UncheckedFrom::unchecked_from(netfees_account)
+    /// Recharges the share of a fee borne by the counterparty. The fee expense of the payer is reduced against a 
+    /// receivable from the counterparty, which records the fee expense against a payable to the payer.
+    fn post_fee_share(payer: T::AccountId, counterparty: T::AccountId, amount: LedgerBalance, reference: T::Hash) -> Result {
+        let account_1: Account = 250500300000000u64; // Debit increase (counterparty), Credit decrease (payer) 250500300000000 Totem Transaction Fees
+        let account_2: Account = 110100090000000u64; // Debit increase 110100090000000 Trade receivables - non-related parties
+        let account_3: Account = 120200030000000u64; // Credit increase 120200030000000 Accounts payable (Trade creditors)
+        
+        let current_block = <system::Module<T>>::block_number();
+        let keys = vec![
+            (payer.clone(), counterparty.clone(), account_2, amount, false, reference, current_block, current_block),
+            (payer.clone(), counterparty.clone(), account_1, -amount, true, reference, current_block, current_block),
+            (counterparty.clone(), payer.clone(), account_1, amount, false, reference, current_block, current_block),
+            (counterparty.clone(), payer.clone(), account_3, amount, true, reference, current_block, current_block),
+        ];
+        // The recharge is not an invoice of the counterparty, so it does not count towards the credit exposure
+        match Self::post_batch(keys, Vec::new(), ORIGIN_SYSTEM, false) {
+            Ok(_) => (),
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorPostingFees());
+                return Err("An error occured posting to accounts");
+            },
+        }
+        Self::deposit_event(RawEvent::FeeShared(payer, counterparty, amount, reference));
+        Ok(())
     }
-    /// This function takes the transaction fee and prepares to account for it in accounting.
     /// This is one of the few functions that will set the ledger accounts to be updated here. Fees
     /// are native to the Substrate Framework, and there may be other use cases.
-    fn account_for_fees(fee: T::CoinAmount, payer: T::AccountId) -> Result {
-
-        // Take the fee amount and convert for use with accounting. Fee is of type T::Balance which is u128.
-        // As amount will always be positive, convert for use in accounting
-        let fee_converted: LedgerBalance =
-            <T::AccountingConversions as Convert<T::CoinAmount, LedgerBalance>>::convert(fee);
+    fn post_fees(fee_converted: LedgerBalance, payer: T::AccountId) -> Result {
         // Convert this for the inversion
         let mut to_invert: LedgerBalance = <T::AccountingConversions as Convert<i128, LedgerBalance>>::convert(0i128);
         to_invert = to_invert - fee_converted.clone();
@@ -393,27 +1186,869 @@ where
         forward_keys.push((fee_address.clone(),payer.clone(),account_3,increase_amount,true,fee_hash,current_block,current_block_dupe,));
         forward_keys.push((fee_address.clone(),payer.clone(),account_2,increase_amount,false,fee_hash,current_block,current_block_dupe,));
         
-        // Reversal keys in case of errors
-        let mut reversal_keys = Vec::<(
-            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-        )>::with_capacity(3);
-        reversal_keys.push((payer.clone(),fee_address.clone(),account_1,decrease_amount,true,fee_hash,current_block,current_block_dupe,));
-        // reversal_keys.push((payer.clone(),fee_address.clone(),account_2,increase_amount,false,fee_hash,current_block,current_block_dupe,));
+        match Self::handle_multiposting_amounts_with_origin(forward_keys.clone(), Vec::new(), ORIGIN_SYSTEM) {
+            Ok(_) => (),Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorPostingFees());
+                return Err("An error occured posting to accounts");
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Basic posting function (warning! can cause imbalance if not called with corresponding debit or credit entries)
+    /// The reason why this is a simple function is that (for example) one debit posting may correspond with one or many credit
+    /// postings and vice-versa. For example a debit to Accounts Receivable is the gross invoice amount, which could correspond with
+    /// a credit to liabilities for the sales tax amount and a credit to revenue for the net invoice amount. The sum of both credits being
+    /// equal to the single debit in accounts receivable, but only one posting needs to be made to that account, and two posting for the others.
+    /// The Totem Accounting Recipes are constructed using this simple function.
+    /// The second Blocknumber is for re-targeting the entry in the accounts, i.e. for adjustments prior to or after the current period (generally accruals).
+    /// This function only records the posting detail and indexes. The balances are calculated and checked beforehand 
+    /// and are written by the calling function once all the postings have passed the checks.
+    /// The lines of a batch posted to the same account of the identity share the posting index and are told apart by 
+    /// their line number, so that the detail of a line never replaces the detail of another.
+    fn commit_posting(
+        (o, p, a, c, d, h, b, t, i): (
+            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,PostingIndex,
+        ),
+        line: u32,
+        origin: PostingOrigin,
+        journal: Option<JournalNumber>,
+        exposure: bool,
+    ) {
+        let posting_index = i.into();
+        
+        let ab: LedgerBalance = c.abs();
+        let balance_key = (o.clone(), a);
+        if exposure && a == TRADE_RECEIVABLES_ACCOUNT && o != p {
+            Self::track_exposure(&o, &p, c);
+        }
+        let detail = PostingDetailRecord(p, b, ab, d, h, t, origin, journal);
+
+        // The index is listed once per account, whatever the number of lines posted to it in the batch
+        <IdAccountPostingIdList<T>>::mutate(&balance_key, |id_account_posting_id_list| {
+            if let Err(pos) = id_account_posting_id_list.binary_search(&posting_index) {
+                id_account_posting_id_list.insert(pos, posting_index);
+            }
+        });
+
+        <AccountsById<T>>::mutate(&o, |accounts_by_id| accounts_by_id.retain(|h| h != &a));
+        <AccountsById<T>>::mutate(&o, |accounts_by_id| accounts_by_id.push(a));
+        match line {
+            0 => <PostingDetail<T>>::insert((o.clone(), a, posting_index), detail),
+            _ => <PostingDetailLines<T>>::insert((o.clone(), a, posting_index, line), detail),
+        }
+        <PostingsByReference<T>>::mutate(&h, |postings_by_reference| postings_by_reference.push((o.clone(), a, c, posting_index)));
+        let block = <system::Module<T>>::block_number();
+        let mut page: u32 = Self::block_postings_last_page(&block);
+        if Self::block_postings_page((block, page)).len() >= MAX_BLOCK_POSTINGS_PAGE {
+            page = page.saturating_add(1);
+            <BlockPostingsLastPage<T>>::insert(&block, page);
+        }
+        <BlockPostings<T>>::mutate((block, page), |block_postings| block_postings.push((o.clone(), a, c, posting_index, h, line)));
+
+        Self::deposit_event(RawEvent::LegderUpdate(o, a, c, posting_index));
+    }
+    /// Moves the open receivables of an identity from a counterparty. Counterparties without a credit limit are no longer 
+    /// listed once their receivables are cleared.
+    fn track_exposure(o: &T::AccountId, p: &T::AccountId, c: LedgerBalance) {
+        let key = (o.clone(), p.clone());
+        let exposure: LedgerBalance = Self::receivable_exposure(&key).saturating_add(c);
+        if exposure == 0 {
+            <ReceivableExposure<T>>::remove(&key);
+            if Self::credit_limit(&key).is_none() {
+                <ExposureCounterparties<T>>::mutate(o, |counterparties| counterparties.retain(|e| e != p));
+            }
+        } else {
+            <ReceivableExposure<T>>::insert(&key, exposure);
+            Self::add_exposure_counterparty(o, p);
+        }
+    }
+    fn add_exposure_counterparty(o: &T::AccountId, p: &T::AccountId) {
+        <ExposureCounterparties<T>>::mutate(o, |counterparties| {
+            if !counterparties.contains(p) {
+                counterparties.push(p.clone());
+            }
+        });
+    }
+    /// Returns the credit exposure of an identity to each counterparty with a credit limit or an open receivable
+    /// (counterparty, open receivables, credit limit and whether it is enforced)
+    pub fn credit_exposure(o: T::AccountId) -> Vec<(T::AccountId, LedgerBalance, Option<(LedgerBalance, bool)>)> {
+        Self::exposure_counterparties(&o)
+            .into_iter()
+            .map(|p| {
+                let key = (o.clone(), p.clone());
+                (p, Self::receivable_exposure(&key), Self::credit_limit(&key))
+            })
+            .collect()
+    }
+    /// Returns a page of the global ledger (Account, Balance) ordered by account number. 
+    /// The page size is capped at MAX_GLOBAL_LEDGER_PAGE. Used by the runtime API for explorers.
+    pub fn global_ledger_page(start: u32, count: u32) -> Vec<(Account, LedgerBalance)> {
+        let count = rstd::cmp::min(count, MAX_GLOBAL_LEDGER_PAGE) as usize;
+        Self::global_ledger_accounts()
+            .into_iter()
+            .skip(start as usize)
+            .take(count)
+            .map(|a| (a, Self::global_ledger(&a)))
+            .collect()
+    }
+    /// Returns the global ledger totals per statement type. The statement type is the first digit of the account number
+    /// 1 Balance Sheet, 2 Profit and Loss, 3 Control accounts, 4 Memorandum accounts
+    pub fn global_ledger_totals() -> Vec<(u8, LedgerBalance)> {
+        let mut totals = Vec::<(u8, LedgerBalance)>::new();
+        for a in Self::global_ledger_accounts().into_iter() {
+            let statement_type: u8 = ledger::statement_type(a);
+            let balance: LedgerBalance = Self::global_ledger(&a);
+            match totals.iter_mut().find(|t| t.0 == statement_type) {
+                Some(t) => t.1 = t.1.saturating_add(balance),
+                None => totals.push((statement_type, balance)),
+            }
+        }
+        totals
+    }
+    /// Returns a page of the postings committed between two blocks (inclusive) in block order, so that indexers can sync incrementally.
+    /// The range is capped at MAX_POSTINGS_RANGE blocks from the first block and the page size at MAX_POSTINGS_PAGE. 
+    /// An empty page means there are no more postings in the range. The amounts of restricted references are returned as zero.
+    pub fn postings_in_range(
+        from: T::BlockNumber, 
+        to: T::BlockNumber, 
+        page: u32
+    ) -> Vec<(T::BlockNumber, T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash)> {
+        Self::filtered_postings_in_range(from, to, page, None)
+    }
+    /// As above, only returning the postings with the given origin
+    pub fn postings_in_range_by_origin(
+        from: T::BlockNumber, 
+        to: T::BlockNumber, 
+        page: u32,
+        origin: PostingOrigin,
+    ) -> Vec<(T::BlockNumber, T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash)> {
+        Self::filtered_postings_in_range(from, to, page, Some(origin))
+    }
+    fn filtered_postings_in_range(
+        from: T::BlockNumber, 
+        to: T::BlockNumber, 
+        page: u32,
+        origin: Option<PostingOrigin>,
+    ) -> Vec<(T::BlockNumber, T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash)> {
+        let from: u64 = from.as_();
+        let to: u64 = rstd::cmp::min(to.as_(), from.saturating_add(MAX_POSTINGS_RANGE - 1));
+        let skip: usize = (page as usize).saturating_mul(MAX_POSTINGS_PAGE as usize);
+        (from..=to)
+            .flat_map(|b| {
+                let block: T::BlockNumber = T::BlockNumber::sa(b);
+                Self::block_postings(block)
+                    .into_iter()
+                    .map(move |p| (block, p.0, p.1, p.2, p.3, p.4))
+            })
+            .filter(|p| origin.map_or(true, |origin| {
+                Self::posting_detail((p.1.clone(), p.2, p.4)).map_or(false, |d| d.6 == origin)
+            }))
+            .map(|p| match Self::restricted_reference(&p.5) {
+                true => (p.0, p.1, p.2, 0, p.4, p.5),
+                false => p,
+            })
+            .skip(skip)
+            .take(MAX_POSTINGS_PAGE as usize)
+            .collect()
+    }
+    /// Returns the posting lines committed in a block (Identity, Account, Amount, Posting Index, Reference Hash, Line).
+    /// The lines of the legacy index are numbered in the order they were committed.
+    fn block_postings(block: T::BlockNumber) -> Vec<(T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash, u32)> {
+        let mut postings = Vec::<(T::AccountId, Account, LedgerBalance, PostingIndex, T::Hash, u32)>::new();
+        for (o, a, c, i, h) in Self::postings_by_block(&block).into_iter() {
+            let line: u32 = postings.iter().filter(|p| p.0 == o && p.1 == a && p.3 == i).count() as u32;
+            postings.push((o, a, c, i, h, line));
+        }
+        if <BlockPostings<T>>::exists((block, 0u32)) {
+            for page in 0..=Self::block_postings_last_page(&block) {
+                postings.extend(Self::block_postings_page((block, page)));
+            }
+        }
+        postings
+    }
+    /// Returns the detail of the lines of a posting of an identity on a ledger account, in line order
+    pub fn posting_lines(
+        o: T::AccountId, 
+        a: Account, 
+        i: PostingIndex
+    ) -> Vec<(T::AccountId, T::BlockNumber, LedgerBalance, Indicator, T::Hash, T::BlockNumber, PostingOrigin, Option<JournalNumber>)> {
+        let mut lines = Vec::new();
+        let mut next = Self::posting_detail((o.clone(), a, i));
+        let mut line: u32 = 0;
+        while let Some(PostingDetailRecord(p, b, c, d, h, t, origin, journal)) = next {
+            lines.push((p, b, c, d, h, t, origin, journal));
+            line = line.saturating_add(1);
+            next = Self::posting_detail_line((o.clone(), a, i, line));
+        }
+        lines
+    }
+    /// Returns the posting indexes of an identity on a ledger account that have the given origin
+    pub fn postings_by_origin(o: T::AccountId, a: Account, origin: PostingOrigin) -> Vec<PostingIndex> {
+        Self::id_account_posting_id_list((o.clone(), a))
+            .into_iter()
+            .filter(|i| Self::posting_detail((o.clone(), a, *i)).map_or(false, |d| d.6 == origin))
+            .collect()
+    }
+    /// Returns the posting index and the lines (account, amount, indicator) of a journal of an identity
+    pub fn journal_entry(o: T::AccountId, n: JournalNumber) -> Option<(PostingIndex, Vec<(Account, LedgerBalance, Indicator)>)> {
+        let posting_index: PostingIndex = Self::journal((o.clone(), n))?;
+        let lines = Self::accounts_by_id(&o)
+            .into_iter()
+            .flat_map(|a| Self::posting_lines(o.clone(), a, posting_index).into_iter().map(move |d| (a, d.2, d.3)))
+            .collect();
+        Some((posting_index, lines))
+    }
+    /// Returns the journals of an identity carrying a tag (journal number, posting index)
+    pub fn journals_by_tag(o: T::AccountId, tag: PostingTag) -> Vec<(JournalNumber, PostingIndex)> {
+        Self::tagged_journals((o.clone(), tag))
+            .into_iter()
+            .filter_map(|n| Self::journal((o.clone(), n)).map(|i| (n, i)))
+            .collect()
+    }
+    /// Returns the movement of an identity on a ledger account made up of the journals carrying a tag
+    pub fn balance_by_tag(o: T::AccountId, a: Account, tag: PostingTag) -> LedgerBalance {
+        Self::journals_by_tag(o.clone(), tag)
+            .into_iter()
+            .flat_map(|(_, i)| Self::posting_lines(o.clone(), a, i))
+            .fold(0 as LedgerBalance, |balance, p| balance.saturating_add(ledger::signed_amount(a, p.2, p.3)))
+    }
+    /// Returns the open balances of a sub-ledger account of an identity per reference hash
+    pub fn sub_ledger(o: T::AccountId, a: Account) -> Vec<(T::Hash, LedgerBalance)> {
+        Self::sub_ledger_references((o.clone(), a))
+            .into_iter()
+            .map(|h| (h, Self::sub_ledger_balance((o.clone(), a, h))))
+            .collect()
+    }
+    /// Returns the ledger accounts of the network identities (escrow, network fees) with the balance and the number of 
+    /// postings in the last NETWORK_STATUS_WINDOW blocks, and the global ledger balances of the key network accounts. 
+    /// Used by the runtime API for the network status page.
+    pub fn network_status() -> (Vec<(T::AccountId, Vec<(Account, LedgerBalance, u32)>)>, Vec<(Account, LedgerBalance)>) {
+        let current_block: u64 = <system::Module<T>>::block_number().as_();
+        let since: u64 = current_block.saturating_sub(NETWORK_STATUS_WINDOW);
+        let identities = [Self::get_escrow_account(), Self::get_netfees_account()]
+            .iter()
+            .map(|o| {
+                let accounts = Self::accounts_by_id(o)
+                    .into_iter()
+                    .map(|a| (a, Self::balance_by_ledger((o.clone(), a)), Self::movements_since(o.clone(), a, since)))
+                    .collect();
+                (o.clone(), accounts)
+            })
+            .collect();
+        let accounts = NETWORK_STATUS_ACCOUNTS
+            .iter()
+            .map(|a| (*a, Self::global_ledger(a)))
+            .collect();
+        (identities, accounts)
+    }
+    /// Counts the postings of an identity on a ledger account after a block. The postings are listed in posting order, 
+    /// so they are read from the last one until the first older posting.
+    fn movements_since(o: T::AccountId, a: Account, since: u64) -> u32 {
+        Self::id_account_posting_id_list((o.clone(), a))
+            .into_iter()
+            .rev()
+            .flat_map(|i| Self::posting_lines(o.clone(), a, i))
+            .take_while(|d| {
+                let block: u64 = d.1.as_();
+                block > since
+            })
+            .count() as u32
+    }
+    /// Returns the number of postings of an identity on a ledger account between two blocks (inclusive),
+    /// with the sum of the increases and the sum of the decreases (as a positive amount).
+    /// A line increases the account when it is on the side of the account's nature.
+    /// The account is taken from the chart of accounts in force at the last block, so postings made to the accounts 
+    /// it re-maps are included.
+    pub fn movements_in_period(o: T::AccountId, a: Account, from: u64, to: u64) -> (u32, LedgerBalance, LedgerBalance) {
+        Self::accounts_by_id(&o)
+            .into_iter()
+            .filter(|x| Self::chart_account(*x, to) == a)
+            .flat_map(|x| {
+                let o = o.clone();
+                Self::id_account_posting_id_list((o.clone(), x))
+                    .into_iter()
+                    .flat_map(move |i| Self::posting_lines(o.clone(), x, i))
+            })
+            .filter(|d| {
+                let block: u64 = d.1.as_();
+                block >= from && block <= to
+            })
+            .fold((0u32, 0 as LedgerBalance, 0 as LedgerBalance), |(count, increases, decreases), d| {
+                let amount: LedgerBalance = ledger::signed_amount(a, d.2, d.3);
+                if amount < 0 {
+                    (count + 1, increases, decreases.saturating_sub(amount))
+                } else {
+                    (count + 1, increases.saturating_add(amount), decreases)
+                }
+            })
+    }
+    /// Returns the balance of an identity on a ledger account made up of the postings against a dimension.
+    /// The dimension of a posting is its reference hash (the order, prefunding or project it was posted for).
+    pub fn balance_by_dimension(o: T::AccountId, a: Account, d: T::Hash) -> LedgerBalance {
+        Self::id_account_posting_id_list((o.clone(), a))
+            .into_iter()
+            .flat_map(|i| Self::posting_lines(o.clone(), a, i))
+            .filter(|p| p.4 == d)
+            .fold(0 as LedgerBalance, |balance, p| balance.saturating_add(ledger::signed_amount(a, p.2, p.3)))
+    }
+    /// Returns the profit and loss of an identity (account, movement) over a set of dimensions between two blocks (inclusive).
+    /// Accounts are reported as in the chart of accounts in force at the last block. Accounts without movements are left out.
+    pub fn pl_by_dimensions(o: T::AccountId, dimensions: Vec<T::Hash>, from: u64, to: u64) -> Vec<(Account, LedgerBalance)> {
+        let mut pl = Vec::<(Account, LedgerBalance)>::new();
+        for x in Self::accounts_by_id(&o).into_iter().filter(|x| ledger::statement_type(*x) == 2) {
+            let movement: LedgerBalance = Self::id_account_posting_id_list((o.clone(), x))
+                .into_iter()
+                .flat_map(|i| Self::posting_lines(o.clone(), x, i))
+                .filter(|p| {
+                    let block: u64 = p.1.as_();
+                    block >= from && block <= to && dimensions.contains(&p.4)
+                })
+                .fold(0 as LedgerBalance, |movement, p| movement.saturating_add(ledger::signed_amount(x, p.2, p.3)));
+            if movement == 0 {
+                continue;
+            }
+            let a: Account = Self::chart_account(x, to);
+            match pl.iter_mut().find(|l| l.0 == a) {
+                Some(l) => l.1 = l.1.saturating_add(movement),
+                None => pl.push((a, movement)),
+            }
+        }
+        pl.sort_by_key(|l| l.0);
+        pl
+    }
+    /// Returns the identities of a batch with postings retargeted beyond the backdating tolerance,
+    /// with the earliest period retargeted to and the number of blocks it lies before the current block
+    fn backdated_identities(
+        keys: &[(T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber)],
+    ) -> Vec<(T::AccountId, T::BlockNumber, u64)> {
+        let current_block: u64 = <system::Module<T>>::block_number().as_();
+        let tolerance: u64 = Self::backdating_tolerance();
+        let mut backdated = Vec::<(T::AccountId, T::BlockNumber, u64)>::new();
+        for k in keys.iter() {
+            let target: u64 = k.7.as_();
+            let gap: u64 = current_block.saturating_sub(target);
+            if gap <= tolerance {
+                continue;
+            }
+            match backdated.iter_mut().find(|b| b.0 == k.0) {
+                Some(b) => if gap > b.2 {
+                    b.1 = k.7;
+                    b.2 = gap;
+                },
+                None => backdated.push((k.0.clone(), k.7, gap)),
+            }
+        }
+        backdated
+    }
+    fn is_sub_ledger_account(a: Account) -> bool {
+        SUB_LEDGER_ACCOUNTS.iter().any(|s| *s == a)
+    }
+    /// Returns the exchange rate of a currency for the rate period of a block, or the latest earlier rate
+    fn rate_at(c: CurrencyCode, b: T::BlockNumber) -> Option<(LedgerBalance, LedgerBalance)> {
+        let block: u64 = b.as_();
+        let period: u64 = ledger::rate_period(block, RATE_PERIOD);
+        let found: u64 = ledger::applicable_period(&Self::rate_periods(c), period)?;
+        Self::exchange_rates((c, found))
+    }
+    /// Returns the version of the chart of accounts in force at a block. Zero is the original chart.
+    pub fn chart_version_at(b: u64) -> u32 {
+        Self::chart_versions()
+            .into_iter()
+            .take_while(|v| v.1 <= b)
+            .last()
+            .map_or(0, |v| v.0)
+    }
+    /// Returns the account number under which an account of the original chart is reported at a block, 
+    /// applying the remappings of the chart versions in force at that block in order.
+    pub fn chart_account(a: Account, b: u64) -> Account {
+        Self::chart_versions()
+            .into_iter()
+            .take_while(|v| v.1 <= b)
+            .fold(a, |a, v| ledger::remap(a, &Self::chart_remapping(v.0)))
+    }
+    /// Returns the balance of an identity on a ledger account at the end of a block: the current balance less the 
+    /// postings made after the block. The postings are read from the latest back to the block.
+    pub fn balance_at(o: &T::AccountId, a: Account, b: u64) -> LedgerBalance {
+        let mut balance: LedgerBalance = Self::balance_by_ledger((o.clone(), a));
+        for i in Self::id_account_posting_id_list((o.clone(), a)).into_iter().rev() {
+            let lines = Self::posting_lines(o.clone(), a, i);
+            match lines.first() {
+                Some(d) if d.1.as_() > b => (),
+                Some(_) => break,
+                None => continue,
+            }
+            for d in lines.into_iter() {
+                balance = balance.saturating_sub(ledger::signed_amount(a, d.2, d.3));
+            }
+        }
+        balance
+    }
+    /// Whether an identity had posted to a ledger account by the end of a block. Accounts of a later chart of accounts 
+    /// version that the runtime posts to after a remapping did not exist before, and are left out of the reports.
+    fn posted_by(o: &T::AccountId, a: Account, b: u64) -> bool {
+        Self::id_account_posting_id_list((o.clone(), a))
+            .first()
+            .and_then(|i| Self::posting_detail((o.clone(), a, *i)))
+            .map_or(true, |d| d.1.as_() <= b)
+    }
+    /// Converts the balances of an identity into its reporting currency at the rate for the period of the block.
+    /// The converted balance is None without a reporting currency or a rate for the period.
+    fn convert_balances(o: &T::AccountId, b: T::BlockNumber, balances: Vec<(u64, LedgerBalance)>) -> (Option<CurrencyCode>, Vec<(u64, LedgerBalance, Option<LedgerBalance>)>) {
+        let currency: Option<CurrencyCode> = Self::reporting_currency(o);
+        let rate: Option<(LedgerBalance, LedgerBalance)> = currency.and_then(|c| Self::rate_at(c, b));
+        let lines = balances
+            .into_iter()
+            .map(|(k, balance)| (k, balance, rate.and_then(|r| Self::round_divide(balance.checked_mul(r.0)?, r.1))))
+            .collect();
+        (currency, lines)
+    }
+    /// Returns the trial balance of an identity ordered by account number (account, XTX balance, converted balance)
+    /// with the reporting currency of the identity. Figures are converted at the rate for the period of the block.
+    /// Accounts are reported under the chart of accounts in force at the block, with their balances at the end of the block.
+    pub fn trial_balance(o: T::AccountId, b: T::BlockNumber) -> (Option<CurrencyCode>, Vec<(Account, LedgerBalance, Option<LedgerBalance>)>) {
+        let block: u64 = b.as_();
+        let mut balances = Vec::<(Account, LedgerBalance)>::new();
+        for a in Self::accounts_by_id(&o).into_iter().filter(|a| Self::posted_by(&o, *a, block)) {
+            let account: Account = Self::chart_account(a, block);
+            let balance: LedgerBalance = Self::balance_at(&o, a, block);
+            match balances.iter_mut().find(|l| l.0 == account) {
+                Some(l) => l.1 = l.1.saturating_add(balance),
+                None => balances.push((account, balance)),
+            }
+        }
+        balances.sort_by_key(|l| l.0);
+        Self::convert_balances(&o, b, balances)
+    }
+    /// Returns the totals of an identity per statement type (statement type, XTX total, converted total) 
+    /// with the reporting currency of the identity. Figures are the balances at the end of the block, converted at the 
+    /// rate for the period of the block.
+    pub fn statement_totals(o: T::AccountId, b: T::BlockNumber) -> (Option<CurrencyCode>, Vec<(u8, LedgerBalance, Option<LedgerBalance>)>) {
+        let block: u64 = b.as_();
+        let mut totals = Vec::<(u64, LedgerBalance)>::new();
+        for a in Self::accounts_by_id(&o).into_iter().filter(|a| Self::posted_by(&o, *a, block)) {
+            let statement_type: u64 = ledger::statement_type(Self::chart_account(a, block)) as u64;
+            let balance: LedgerBalance = Self::balance_at(&o, a, block);
+            match totals.iter_mut().find(|t| t.0 == statement_type) {
+                Some(t) => t.1 = t.1.saturating_add(balance),
+                None => totals.push((statement_type, balance)),
+            }
+        }
+        totals.sort_by_key(|t| t.0);
+        let (currency, lines) = Self::convert_balances(&o, b, totals);
+        (currency, lines.into_iter().map(|l| (l.0 as u8, l.1, l.2)).collect())
+    }
+    /// Returns the accounting equation of an identity. Balance sheet (statement type 1) and profit and loss (statement type 2)
+    /// accounts are totalled by category under the current chart of accounts. Control and memorandum accounts are left out.
+    pub fn accounting_equation(o: T::AccountId) -> AccountingEquation {
+        let block: u64 = <system::Module<T>>::block_number().as_();
+        let mut equation = AccountingEquation::default();
+        for a in Self::accounts_by_id(&o).into_iter() {
+            let account: Account = Self::chart_account(a, block);
+            let balance: LedgerBalance = Self::balance_by_ledger((o.clone(), a));
+            let total: &mut LedgerBalance = match (ledger::statement_type(account), ledger::account_category(account)) {
+                (1, 1) => &mut equation.assets,
+                (1, 2) => &mut equation.liabilities,
+                (1, 3) => &mut equation.equity,
+                (2, 4) => &mut equation.revenue,
+                (2, 5) => &mut equation.expenses,
+                _ => continue,
+            };
+            *total = total.saturating_add(balance);
+        }
+        let closed_equity: LedgerBalance = equation.equity.saturating_add(equation.revenue).saturating_sub(equation.expenses);
+        equation.balanced = equation.assets == equation.liabilities.saturating_add(closed_equity);
+        equation
+    }
+    /// Returns the preview of the period close of an identity at the current block for review before the close.
+    /// Each revenue and expense account is reversed by its movement since the last close, under the chart of accounts 
+    /// in force, and the profit (or loss) is carried to retained earnings. Nothing is posted or stored.
+    pub fn preview_close(o: T::AccountId) -> ClosePreview<T::BlockNumber> {
+        let block: u64 = <system::Module<T>>::block_number().as_();
+        let mut preview = ClosePreview { last_close: Self::last_period_close(&o), ..Default::default() };
+        preview.variances = Self::budget_variances(&o).unwrap_or_default();
+        let mut movements: Vec<(Account, LedgerBalance)> = Vec::new();
+        for a in Self::accounts_by_id(&o).into_iter() {
+            let account: Account = Self::chart_account(a, block);
+            if ledger::statement_type(account) != 2 || ledger::is_debit_nature(account).is_none() {
+                continue;
+            }
+            let balance_key = (o.clone(), a);
+            let movement: LedgerBalance = Self::balance_by_ledger(&balance_key).saturating_sub(Self::period_close_balance(&balance_key));
+            match movements.iter_mut().find(|m| m.0 == account) {
+                Some(m) => m.1 = m.1.saturating_add(movement),
+                None => movements.push((account, movement)),
+            }
+        }
+        movements.sort_by_key(|m| m.0);
+        let mut profit: LedgerBalance = 0;
+        for (account, movement) in movements.into_iter().filter(|m| m.1 != 0) {
+            profit = match ledger::account_category(account) {
+                4 => profit.saturating_add(movement),
+                _ => profit.saturating_sub(movement),
+            };
+            let amount: LedgerBalance = 0i128.saturating_sub(movement);
+            if let Some(indicator) = ledger::indicator(account, amount) {
+                preview.entries.push((account, amount, indicator));
+            }
+        }
+        if profit != 0 {
+            if let Some(indicator) = ledger::indicator(RETAINED_EARNINGS_ACCOUNT, profit) {
+                preview.entries.push((RETAINED_EARNINGS_ACCOUNT, profit, indicator));
+            }
+        }
+        preview
+    }
+    /// Returns the net presentation of the balances of an identity ordered by account number 
+    /// (account, gross balance, balance of its contra accounts, net balance). Contra accounts are not presented on their 
+    /// own but netted against their parent. Accounts are reported under the chart of accounts in force at the block.
+    pub fn net_presentation(o: T::AccountId, b: T::BlockNumber) -> Vec<(Account, LedgerBalance, LedgerBalance, LedgerBalance)> {
+        let (_, balances) = Self::trial_balance(o, b);
+        let mut lines = Vec::<(Account, LedgerBalance, LedgerBalance, LedgerBalance)>::new();
+        for (a, balance, _) in balances.into_iter() {
+            let (account, gross, contra) = match Self::contra_of(a) {
+                Some(parent) => (parent, 0, balance),
+                None => (a, balance, 0),
+            };
+            match lines.iter_mut().find(|l| l.0 == account) {
+                Some(l) => {
+                    l.1 = l.1.saturating_add(gross);
+                    l.2 = l.2.saturating_add(contra);
+                    l.3 = l.1.saturating_add(l.2);
+                },
+                None => lines.push((account, gross, contra, gross.saturating_add(contra))),
+            }
+        }
+        lines.sort_by_key(|l| l.0);
+        lines
+    }
+    /// Returns the contra accounts of the chart of accounts with their parent (contra account, parent account)
+    pub fn contra_accounts() -> Vec<(Account, Account)> {
+        Self::contra_accounts_list()
+            .into_iter()
+            .filter_map(|a| Self::contra_of(a).map(|p| (a, p)))
+            .collect()
+    }
+    /// Returns the display metadata of the accounts in the chart of accounts ordered by account number
+    pub fn accounts_display() -> Vec<(Account, AccountDisplay)> {
+        Self::account_decimals_accounts()
+            .into_iter()
+            .filter_map(|a| Self::account_decimals(a).map(|d| (a, d)))
+            .collect()
+    }
+    /// Amounts posted to quantity accounts must be a multiple of the smallest unit allowed by the account decimals
+    fn respects_precision(a: Account, c: LedgerBalance) -> bool {
+        match Self::account_decimals(a) {
+            Some((decimals, _, true)) => ledger::respects_precision(c, decimals, QUANTITY_DECIMALS),
+            _ => true,
+        }
+    }
+    /// Returns the display profile of a counterparty and whether it is attested by a registrar
+    pub fn counterparty_profile(o: T::AccountId) -> Option<((T::Hash, CountryCode, T::Hash), bool)> {
+        Self::profile(&o).map(|p| (p, <ProfileAttestation<T>>::exists(&o)))
+    }
+    /// Divides using the current rounding policy. The sign is applied after rounding the absolute value
+    /// so that positive and negative amounts round symmetrically.
+    fn round_divide(n: LedgerBalance, d: LedgerBalance) -> Option<LedgerBalance> {
+        ledger::round_divide(n, d, Self::rounding_mode() == ROUND_HALF_EVEN)
+    }
+    /// Where the debits and credits of an identity in a converted batch differ by rounding (at most one smallest unit per line),
+    /// a line is added for the difference to the rounding difference account so the batch balances to the smallest unit.
+    /// Larger differences are not rounding residuals and the batch is rejected.
+    fn add_rounding_differences(
+        mut keys: Vec<(T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber)>,
+    ) -> rstd::result::Result<Vec<(T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber)>, &'static str> {
+        let mut identities = Vec::<T::AccountId>::new();
+        for k in keys.iter() {
+            if !identities.contains(&k.0) {
+                identities.push(k.0.clone());
+            }
+        }
+        for o in identities.into_iter() {
+            let mut residual: LedgerBalance = 0;
+            let mut lines: LedgerBalance = 0;
+            let mut first = None;
+            for k in keys.iter().filter(|k| k.0 == o) {
+                let ab: LedgerBalance = k.3.checked_abs().unwrap_or(LedgerBalance::max_value());
+                residual = match k.4 {
+                    false => residual.saturating_add(ab),
+                    true => residual.saturating_sub(ab),
+                };
+                lines += 1;
+                if first.is_none() {
+                    first = Some(k.clone());
+                }
+            }
+            if residual == 0 {
+                continue;
+            }
+            if residual.checked_abs().map_or(true, |r| r > lines) {
+                Self::deposit_event(RawEvent::ErrorConversionImbalance(o));
+                return Err("The converted postings do not balance to the rounding precision");
+            }
+            if let Some(f) = first {
+                // Debits exceed credits: credit (decrease) the expense. Credits exceed debits: debit (increase) the expense.
+                let indicator: Indicator = residual > 0;
+                keys.push((o, f.1, ROUNDING_DIFFERENCE_ACCOUNT, -residual, indicator, f.5, f.6, f.7));
+            }
+        }
+        Ok(keys)
+    }
+    /// Builds the journal replication record of a posting batch from the keys as they are committed
+    fn replication_record(
+        batch: PostingIndex,
+        origin: PostingOrigin,
+        keys: &[(T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber)],
+        journals: &[(T::AccountId, JournalNumber)],
+        ext_ref: ExternalReference,
+    ) -> JournalReplication<T::AccountId, T::Hash, T::BlockNumber> {
+        let lines = keys
+            .iter()
+            .map(|k| ReplicationLine {
+                identity: k.0.clone(),
+                counterparty: k.1.clone(),
+                account: k.2,
+                amount: k.3,
+                credit: k.4,
+                reference: k.5,
+                applicable_block: k.7,
+                journal: journals.iter().find(|j| j.0 == k.0).map(|j| j.1),
+            })
+            .collect();
+        JournalReplication {
+            version: JOURNAL_REPLICATION_VERSION,
+            batch: batch,
+            block: <system::Module<T>>::block_number(),
+            origin: origin,
+            lines: lines,
+            external_reference: ext_ref,
+        }
+    }
+    /// Posts the opening balance of an account against the opening balance equity account. 
+    /// The indicator is derived from the nature of the account so that the sign of the amount alone decides the side.
+    fn set_opening_balance_for(o: T::AccountId, a: Account, c: LedgerBalance) -> Result {
+        let indicator: Indicator = match ledger::indicator(a, c) {
+            Some(d) => d,
+            None => {
+                Self::deposit_event(RawEvent::ErrorOpeningBalance(a));
+                return Err("Opening balances can only be set on asset, liability or equity accounts");
+            },
+        };
+        if c == 0 
+            || c == LedgerBalance::min_value() 
+            || ledger::statement_type(a) != 1 
+            || a == OPENING_BALANCE_ACCOUNT 
+        {
+            Self::deposit_event(RawEvent::ErrorOpeningBalance(a));
+            return Err("Invalid opening balance");
+        }
+        if <OpeningBalance<T>>::exists((o.clone(), a)) {
+            Self::deposit_event(RawEvent::ErrorOpeningBalance(a));
+            return Err("The opening balance of this account has already been set");
+        }
+        // The equity account is a credit account: it increases when the opening balance is a debit
+        let equity_amount: LedgerBalance = if indicator { -c.abs() } else { c.abs() };
+        let current_block = <system::Module<T>>::block_number();
+        let h: T::Hash = Self::get_pseudo_random_hash(o.clone(), o.clone());
+        let keys = vec![
+            (o.clone(), o.clone(), a, c, indicator, h, current_block, current_block),
+            (o.clone(), o.clone(), OPENING_BALANCE_ACCOUNT, equity_amount, !indicator, h, current_block, current_block),
+        ];
+        Self::handle_multiposting_amounts_with_origin(keys, Vec::new(), ORIGIN_USER)?;
+        <OpeningBalance<T>>::insert((o.clone(), a), c);
+        Self::deposit_event(RawEvent::OpeningBalanceSet(o, a, c, indicator, equity_amount, !indicator));
+        Ok(())
+    }
+    /// Calculates the variance between the budget and the actual movement since the last close of the accounts with a budget
+    fn budget_variances(o: &T::AccountId) -> rstd::result::Result<Vec<(Account, LedgerBalance, LedgerBalance, LedgerBalance)>, &'static str> {
+        let mut variances: Vec<(Account, LedgerBalance, LedgerBalance, LedgerBalance)> = Vec::new();
+        for a in Self::budget_accounts(o).into_iter() {
+            let balance_key = (o.clone(), a);
+            if let Some(budget) = Self::budget_by_ledger(&balance_key) {
+                let actual: LedgerBalance = Self::balance_by_ledger(&balance_key)
+                    .checked_sub(Self::period_close_balance(&balance_key))
+                    .ok_or("Actual value overflowed")?;
+                let variance: LedgerBalance = actual.checked_sub(budget).ok_or("Variance value overflowed")?;
+                variances.push((a, budget, actual, variance));
+            }
+        }
+        Ok(variances)
+    }
+    /// Carries forward the balance of an account that the period close in progress has not reached yet, 
+    /// before a posting changes it
+    fn carry_before_posting(o: &T::AccountId, a: Account) {
+        let (close_block, carried) = match Self::period_close_cursor(o) {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let mut accounts: Vec<Account> = Self::period_close_accounts(o);
+        if let Ok(pos) = accounts.binary_search(&a) {
+            accounts.remove(pos);
+            let balance_key = (o.clone(), a);
+            <PeriodCloseBalance<T>>::insert(&balance_key, Self::balance_by_ledger(&balance_key));
+            <PeriodCloseAccounts<T>>::insert(o, accounts);
+            <PeriodCloseCursor<T>>::insert(o, (close_block, carried.saturating_add(1)));
+        }
+    }
+    /// Calculates the budget variances for the period ending in the current block.
+    /// The actual movement is the difference between the current ledger balance and the balance at the last close.
+    /// The variances are calculated and the accounts to carry forward are listed when the close starts. 
+    /// At most MAX_CLOSE_ACCOUNTS balances are carried forward per call. The balance of an account posted to while the 
+    /// close is in progress is carried forward before the posting, so that all balances are those of the close block.
+    fn close_period_for(o: T::AccountId) -> Result {
+        let (close_block, carried) = match Self::period_close_cursor(&o) {
+            Some(cursor) => cursor,
+            None => {
+                let current_block = <system::Module<T>>::block_number();
+                let summary_key = (o.clone(), current_block);
+                if <VarianceSummary<T>>::exists(&summary_key) {
+                    Self::deposit_event(RawEvent::ErrorPeriodClosed());
+                    return Err("The period has already been closed in this block");
+                }
+                let summary: Vec<(Account, LedgerBalance, LedgerBalance, LedgerBalance)> = Self::budget_variances(&o)?;
+                for (a, budget, actual, variance) in summary.iter() {
+                    Self::deposit_event(RawEvent::BudgetVariance(o.clone(), *a, *budget, *actual, *variance, current_block));
+                }
+                <VarianceSummary<T>>::insert(&summary_key, summary);
+                let mut accounts: Vec<Account> = Self::accounts_by_id(&o);
+                accounts.sort();
+                <PeriodCloseAccounts<T>>::insert(&o, accounts);
+                (current_block, 0u32)
+            },
+        };
+        let mut accounts: Vec<Account> = Self::period_close_accounts(&o);
+        let end: usize = rstd::cmp::min(MAX_CLOSE_ACCOUNTS, accounts.len());
+        for a in accounts.drain(..end) {
+            let balance_key = (o.clone(), a);
+            <PeriodCloseBalance<T>>::insert(&balance_key, Self::balance_by_ledger(&balance_key));
+        }
+        let carried: u32 = carried.saturating_add(end as u32);
+        if !accounts.is_empty() {
+            <PeriodCloseAccounts<T>>::insert(&o, accounts);
+            <PeriodCloseCursor<T>>::insert(&o, (close_block, carried));
+            Self::deposit_event(RawEvent::PeriodCloseInProgress(o, close_block, carried));
+            return Ok(());
+        }
+        <PeriodCloseAccounts<T>>::remove(&o);
+        <PeriodCloseCursor<T>>::remove(&o);
+        <LastPeriodClose<T>>::insert(&o, close_block);
+        T::OnPeriodClose::on_period_close(&o, close_block);
+        Self::deposit_event(RawEvent::PeriodClosed(o, close_block));
+        Ok(())
+    }
+}
 
-        reversal_keys.push((fee_address.clone(),payer.clone(),account_3,decrease_amount,false,fee_hash,current_block,current_block_dupe,));
-        // reversal_keys.push((fee_address.clone(),payer.clone(),account_2,decrease_amount,true,fee_hash,current_block,current_block_dupe,));
+impl<T: Trait> Posting<T::AccountId, T::Hash, T::BlockNumber, T::CoinAmount> for Module<T> 
+where 
+    T::AccountId: UncheckedFrom<[u8; 32]>,
+{
+    type Account = Account;
+    type LedgerBalance = LedgerBalance;
+    type PostingIndex = PostingIndex;
 
-        let track_rev_keys = Vec::<(
+    /// The Totem Accounting Recipes are constructed using this function which handles posting to multiple accounts.
+    /// It is exposed to other modules as a trait
+    /// All the new balances (per identity and in the global ledger) are calculated in memory and checked for overflow first. 
+    /// Storage is only updated once every posting has passed the checks, so a batch is either posted in full or not at all,
+    /// and no reversal entries are needed.
+    fn handle_multiposting_amounts(
+        keys: Vec<(
             T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
-        )>::with_capacity(4);
+        )>,
+    ) -> Result {
+        Self::handle_multiposting_amounts_with_reference(keys, Vec::new())
+    }
+    /// As above but also records an external document reference against the postings. 
+    /// The reference is indexed for each identity in the postings so it can be used to locate the posting index.
+    /// An empty reference is not recorded.
+    fn handle_multiposting_amounts_with_reference(
+        keys: Vec<(
+            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
+        )>,
+        ext_ref: ExternalReference,
+    ) -> Result {
+        Self::handle_multiposting_amounts_with_origin(keys, ext_ref, ORIGIN_USER)
+    }
+    /// As above and records the origin of the postings (system, user, adjustment or migration)
+    fn handle_multiposting_amounts_with_origin(
+        keys: Vec<(
+            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
+        )>,
+        ext_ref: ExternalReference,
+        origin: PostingOrigin,
+    ) -> Result {
+        Self::post_batch(keys, ext_ref, origin, true)
+    }
+    /// This function simply returns the Totem escrow account address
+    fn get_escrow_account() -> T::AccountId {
+        let escrow_account: [u8;32] = *b"TotemsEscrowAddress4LockingFunds";
+        UncheckedFrom::unchecked_from(escrow_account)
+    }
+    /// This function simply returns the Totem network fees account address
+    fn get_netfees_account() -> T::AccountId {
+        let netfees_account: [u8;32] = *b"TotemAccountingNetworkFeeAddress";
+        UncheckedFrom::unchecked_from(netfees_account)
+    }
+    /// This function takes the transaction fee and prepares to account for it in accounting.
+    /// Fees are accumulated per payer and posted in one batch when the block is finalised, so that a payer
+    /// with many extrinsics in a block costs one set of postings rather than one per extrinsic.
+    fn account_for_fees(fee: T::CoinAmount, payer: T::AccountId) -> Result {
 
-        match Self::handle_multiposting_amounts(forward_keys.clone(),reversal_keys.clone(),track_rev_keys.clone()) {
-            Ok(_) => (),Err(_e) => {
+        // Take the fee amount and convert for use with accounting. Fee is of type T::Balance which is u128.
+        // As amount will always be positive, convert for use in accounting
+        let fee_converted: LedgerBalance =
+            <T::AccountingConversions as Convert<T::CoinAmount, LedgerBalance>>::convert(fee);
+        let pending: LedgerBalance = match Self::pending_fees(&payer).checked_add(fee_converted) {
+            Some(p) => p,
+            None => {
                 Self::deposit_event(RawEvent::ErrorPostingFees());
                 return Err("An error occured posting to accounts");
             },
+        };
+        if !<PendingFees<T>>::exists(&payer) {
+            <PendingFeePayers<T>>::mutate(|payers| payers.push(payer.clone()));
+        }
+        <PendingFees<T>>::insert(&payer, pending);
+        if let Some(index) = <system::Module<T>>::extrinsic_index() {
+            <LastFee<T>>::insert(&payer, (index, fee_converted));
+        }
+
+        Ok(())
+    }
+    /// Recharges a percentage of the fee of the extrinsic being dispatched to the counterparty. Nothing is shared when 
+    /// the last fee charged to the payer is not the fee of this extrinsic, and a fee can only be shared once. 
+    /// The recharge is posted with the fees when the block is finalised.
+    fn share_last_fee(payer: T::AccountId, counterparty: T::AccountId, share: u8, reference: T::Hash) -> Result {
+        if share > 100 || payer == counterparty {
+            Self::deposit_event(RawEvent::ErrorPostingFees());
+            return Err("Invalid fee share");
         }
+        let fee: LedgerBalance = match (<LastFee<T>>::get(&payer), <system::Module<T>>::extrinsic_index()) {
+            (Some((index, fee)), Some(current)) if index == current => fee,
+            _ => return Ok(()),
+        };
+        <LastFee<T>>::remove(&payer);
+        let amount: LedgerBalance = fee.saturating_mul(share as LedgerBalance) / 100;
+        if amount > 0 {
+            <PendingFeeShares<T>>::mutate(|shares| shares.push((payer, counterparty, amount, reference)));
+        }
+        Ok(())
+    }
 
+    /// Checks that a new invoice of an identity to a counterparty keeps the open receivables within the credit limit.
+    /// Exceeding an enforced limit is an error, exceeding a limit that is not enforced only issues a warning.
+    fn check_credit_limit(o: T::AccountId, p: T::AccountId, amount: LedgerBalance) -> Result {
+        let key = (o.clone(), p.clone());
+        if let Some((limit, enforced)) = Self::credit_limit(&key) {
+            let exposure: LedgerBalance = Self::receivable_exposure(&key).saturating_add(amount);
+            if exposure > limit {
+                Self::deposit_event(RawEvent::CreditLimitExceeded(o, p, exposure, limit, enforced));
+                if enforced {
+                    return Err("The invoice would exceed the credit limit of the counterparty");
+                }
+            }
+        }
         Ok(())
     }
 
@@ -433,12 +2068,56 @@ where
         }
         return balance;
     }
+    /// Returns the postings (identity, account, signed amount) made against a reference hash
+    fn get_postings_by_reference(h: T::Hash) -> Vec<(T::AccountId, Account, LedgerBalance)> {
+        Self::postings_by_reference(&h).into_iter().map(|p| (p.0, p.1, p.2)).collect()
+    }
+    /// Returns the distinct posting indexes made against a reference, in posting order
+    fn get_posting_indexes_by_reference(h: T::Hash) -> Vec<PostingIndex> {
+        let mut indexes: Vec<PostingIndex> = Self::postings_by_reference(&h).into_iter().map(|p| p.3).collect();
+        indexes.sort();
+        indexes.dedup();
+        indexes
+    }
+    /// Marks the postings against a reference as of restricted visibility
+    fn restrict_reference(h: T::Hash) {
+        <RestrictedReferences<T>>::insert(&h, true);
+    }
+    /// Returns the balance of a sub-ledger account of an identity for a reference hash
+    fn get_sub_ledger_balance(o: T::AccountId, a: Account, h: T::Hash) -> LedgerBalance {
+        Self::sub_ledger_balance((o, a, h))
+    }
+    /// Posts a batch of amounts converted with `apply_rate` together with the rounding differences of each identity
+    fn handle_converted_multiposting_amounts(
+        keys: Vec<(
+            T::AccountId,T::AccountId,Account,LedgerBalance,bool,T::Hash,T::BlockNumber,T::BlockNumber,
+        )>,
+    ) -> Result {
+        let keys = Self::add_rounding_differences(keys)?;
+        Self::handle_multiposting_amounts_with_origin(keys, Vec::new(), ORIGIN_SYSTEM)
+    }
+    /// Calculates amount * numerator / denominator (for example a percentage or a pro-rata share) 
+    /// rounded to the smallest unit using the network rounding policy. Returns None on overflow or division by zero.
+    fn apply_rate(amount: LedgerBalance, numerator: LedgerBalance, denominator: LedgerBalance) -> Option<LedgerBalance> {
+        ledger::apply_rate(amount, numerator, denominator, Self::rounding_mode() == ROUND_HALF_EVEN)
+    }
+    /// Exchange rate of a currency (XTX to currency numerator, denominator) for the rate period of a block,
+    /// or the latest earlier rate
+    fn get_exchange_rate(currency: CurrencyCode, b: T::BlockNumber) -> Option<(LedgerBalance, LedgerBalance)> {
+        Self::rate_at(currency, b)
+    }
     // DO NOT MIGRATE TO LEGO - This Function only exists for Meccano Network 
+    fn get_block_number() -> T::BlockNumber {
+        <system::Module<T>>::block_number()
+    }
     fn force_set_gl_account_balance(account_id: T::AccountId, amount: T::CoinAmount) -> Result {
         let account: Account = 110100040000000u64;
         let key = (account_id, account);
+        // Fees already withdrawn in this block are deducted from the ledger when they are posted at the end of the block
+        let pending: LedgerBalance = Self::pending_fees(&key.0);
         let amount_converted: LedgerBalance =
-        <T::AccountingConversions as Convert<T::CoinAmount, LedgerBalance>>::convert(amount);
+        <T::AccountingConversions as Convert<T::CoinAmount, LedgerBalance>>::convert(amount) + pending;
+        Self::carry_before_posting(&key.0, key.1);
         <BalanceByLedger<T>>::insert(key, amount_converted);
         <GlobalLedger<T>>::remove(account);
         Ok(())
@@ -446,19 +2125,149 @@ where
     // ^^^^^^^^^^^^ DO NOT MIGRATE TO LEGO - This Function only exists for Meccano Network 
 }
 
+/// Payment handler of the executive. Charges the fee of every signed extrinsic through the wrapped payment handler
+/// and accounts for the fee actually withdrawn from the payer, so that every fee reaches the ledger without each
+/// module having to account for it.
+pub struct AccountedPayment<T, C, P>(PhantomData<(T, C, P)>);
+
+impl<T, C, P> MakePayment<T::AccountId> for AccountedPayment<T, C, P>
+where
+    T: Trait,
+    T::AccountId: UncheckedFrom<[u8; 32]>,
+    C: Currency<T::AccountId, Balance = T::CoinAmount>,
+    P: MakePayment<T::AccountId>,
+{
+    fn make_payment(transactor: &T::AccountId, encoded_len: usize) -> Result {
+        let balance_before: T::CoinAmount = C::free_balance(transactor);
+        // DO NOT MIGRATE TO LEGO - Meccano Network aligns the XTX ledger balance with the free balance
+        match <Module<T> as Posting<T::AccountId, T::Hash, T::BlockNumber, T::CoinAmount>>::force_set_gl_account_balance(transactor.clone(), balance_before) {
+            Ok(_) => (),
+            Err(_e) => {
+                return Err("An error occured posting reseting XTX account");
+            },
+        }
+
+        P::make_payment(transactor, encoded_len)?;
+
+        // The fee is only accounted for once it has been withdrawn
+        let fee: T::CoinAmount = balance_before.checked_sub(&C::free_balance(transactor)).unwrap_or_else(Zero::zero);
+        if fee.is_zero() {
+            return Ok(());
+        }
+        match <Module<T> as Posting<T::AccountId, T::Hash, T::BlockNumber, T::CoinAmount>>::account_for_fees(fee, transactor.clone()) {
+            Ok(_) => (),
+            Err(_e) => {
+                return Err("An error occured posting txfees to accounts");
+            },
+        }
+        Ok(())
+    }
+}
+
 decl_event!(
     pub enum Event<T>
     where
         AccountId = <T as system::Trait>::AccountId,
+        BlockNumber = <T as system::Trait>::BlockNumber,
+        Hash = <T as system::Trait>::Hash,
         Account = u64,
         LedgerBalance = i128,
         PostingIndex = u128,
+        Replication = JournalReplication<<T as system::Trait>::AccountId, <T as system::Trait>::Hash, <T as system::Trait>::BlockNumber>,
     {
         LegderUpdate(AccountId, Account, LedgerBalance, PostingIndex),
         ErrorOverflow(Account),
         ErrorGlobalOverflow(),
         ErrorInError(),
+        ErrorExternalReferenceTooLong(),
         ErrorPostingFees(),
+        /// Payer, counterparty, share of the fee recharged, reference
+        FeeShared(AccountId, AccountId, LedgerBalance, Hash),
         ErrorBalanceAlignment(),
+        BudgetSet(AccountId, Account, LedgerBalance),
+        BudgetRemoved(AccountId, Account),
+        /// An identity cannot have more than MAX_BUDGETS budgets
+        ErrorTooManyBudgets(),
+        /// Identity, period close block, number of account balances carried forward so far
+        PeriodCloseInProgress(AccountId, BlockNumber, u32),
+        /// Identity, Account, Budget, Actual, Variance, Period close block
+        BudgetVariance(AccountId, Account, LedgerBalance, LedgerBalance, LedgerBalance, BlockNumber),
+        PeriodClosed(AccountId, BlockNumber),
+        /// The period has already been closed in this block
+        ErrorPeriodClosed(),
+        RoundingPolicySet(u8),
+        ErrorRoundingPolicy(),
+        ProfileSet(AccountId),
+        ProfileRemoved(AccountId),
+        /// Identity, registrar
+        ProfileAttested(AccountId, AccountId),
+        /// Invalid country code
+        ErrorCountryCode(),
+        /// Not a profile registrar
+        ErrorNotProfileRegistrar(),
+        /// Identity has no profile
+        ErrorNoProfile(),
+        /// Account, decimals, quantity account
+        AccountDecimalsSet(Account, u8, bool),
+        /// Invalid account decimals or display unit
+        ErrorAccountDecimals(Account),
+        /// Amount exceeds the precision of the quantity account
+        ErrorPrecision(Account),
+        /// Unknown posting origin
+        ErrorPostingOrigin(),
+        /// Identity whose converted postings differ by more than the rounding residual
+        ErrorConversionImbalance(AccountId),
+        /// Currency, rate period, numerator, denominator
+        ExchangeRateSet(u16, u64, LedgerBalance, LedgerBalance),
+        /// Identity, reporting currency (zero when removed)
+        ReportingCurrencySet(AccountId, u16),
+        /// Invalid exchange rate
+        ErrorExchangeRate(u16),
+        /// No exchange rate for this currency
+        ErrorUnknownCurrency(u16),
+        /// Identity, journal number, posting index
+        JournalRecorded(AccountId, u64, PostingIndex),
+        /// Chart of accounts version, effective from block
+        ChartVersionAdded(u32, u64),
+        /// Invalid chart of accounts version
+        ErrorChartVersion(),
+        /// Contra account, parent account
+        ContraAccountSet(Account, Account),
+        /// Contra account, parent account
+        ContraAccountRemoved(Account, Account),
+        /// Invalid contra account
+        ErrorContraAccount(Account),
+        /// Identity, account, signed amount and indicator as recorded, 
+        /// then the amount and indicator recorded on the opening balance equity account
+        OpeningBalanceSet(AccountId, Account, LedgerBalance, bool, LedgerBalance, bool),
+        /// Invalid opening balance
+        ErrorOpeningBalance(Account),
+        /// Journal replication record of a posting batch (see JournalReplication)
+        JournalReplicated(Replication),
+        /// Identity, posting index, period retargeted to, blocks before the current block. Awaits approval
+        BackdatedPosting(AccountId, PostingIndex, BlockNumber, u64),
+        /// Identity, posting index, approver
+        BackdatedPostingApproved(AccountId, PostingIndex, AccountId),
+        /// Blocks a posting may be backdated without being flagged
+        BackdatingToleranceSet(u64),
+        /// Identity, approver of its backdated postings
+        BackdatingApproverSet(AccountId, Option<AccountId>),
+        /// Invalid approver of backdated postings, or not the approver
+        ErrorBackdatingApprover(),
+        /// Postings added to the indices, dangling or duplicated index entries dropped
+        IndicesRebuilt(u32, u32),
+        /// No postings or too many postings to rebuild in one call
+        ErrorRebuildIndices(),
+        /// Identity, journal number, tags
+        JournalTagsSet(AccountId, u64, Vec<Vec<u8>>),
+        /// Invalid tags or unknown journal
+        ErrorPostingTags(u64),
+        /// Identity, counterparty, credit limit (None when removed), enforced
+        CreditLimitSet(AccountId, AccountId, Option<LedgerBalance>, bool),
+        /// Identity, counterparty, open receivables with the new invoice, credit limit, enforced. 
+        /// The invoice was rejected when the limit is enforced, otherwise this is a warning
+        CreditLimitExceeded(AccountId, AccountId, LedgerBalance, LedgerBalance, bool),
+        /// Invalid credit limit
+        ErrorCreditLimit(),
     }
 );
@@ -0,0 +1,73 @@
+//! Test utilities
+
+#![cfg(test)]
+
+use crate::{LedgerBalance, Module, Trait};
+use sr_primitives::BuildStorage;
+use sr_primitives::{
+    testing::{Digest, DigestItem, Header},
+    traits::{BlakeTwo256, Convert, IdentityLookup},
+};
+use srml_support::impl_outer_origin;
+use substrate_primitives::{sr25519, Blake2Hasher, H256};
+
+impl_outer_origin! {
+    pub enum Origin for Runtime {}
+}
+
+pub type AccountId = sr25519::Public;
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+impl system::Trait for Runtime {
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type Digest = Digest;
+    type AccountId = AccountId;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = ();
+    type Log = DigestItem;
+}
+impl timestamp::Trait for Runtime {
+    type Moment = u64;
+    type OnTimestampSet = ();
+}
+impl Trait for Runtime {
+    type Event = ();
+    type CoinAmount = u64;
+    type AccountingConversions = ConversionHandler;
+    type OnPeriodClose = ();
+}
+
+pub struct ConversionHandler;
+impl Convert<u64, LedgerBalance> for ConversionHandler {
+    fn convert(x: u64) -> LedgerBalance {
+        x as LedgerBalance
+    }
+}
+impl Convert<i128, LedgerBalance> for ConversionHandler {
+    fn convert(x: i128) -> LedgerBalance {
+        x
+    }
+}
+
+/// Identity used in tests
+pub fn id(n: u8) -> AccountId {
+    sr25519::Public([n; 32])
+}
+
+pub fn new_test_ext() -> sr_io::TestExternalities<Blake2Hasher> {
+    system::GenesisConfig::<Runtime>::default()
+        .build_storage()
+        .unwrap()
+        .0
+        .into()
+}
+
+pub type System = system::Module<Runtime>;
+pub type Accounting = Module<Runtime>;
@@ -0,0 +1,971 @@
+//! Tests for the module.
+//! Besides the negative tests, arbitrary sequences of balanced recipes are generated from a simple
+//! deterministic generator and the invariants of the posting engine are checked after every batch.
+
+#![cfg(test)]
+
+use super::*;
+use mock::{id, new_test_ext, Accounting, AccountId, Origin, System};
+use sr_io::with_externalities;
+use srml_support::{assert_err, assert_noop, assert_ok};
+use sr_primitives::traits::OnFinalize;
+use substrate_primitives::H256;
+
+type Key = (AccountId, AccountId, Account, LedgerBalance, bool, H256, u64, u64);
+
+const IDENTITIES: u8 = 4;
+const SEEDS: u64 = 20;
+const BATCHES: usize = 30;
+
+const ACCOUNTS: [Account; 6] = [
+    110100040000000, // XTX Balance
+    110100050000000, // Totem Runtime Deposit (Escrow)
+    120200030000000, // Accounts payable
+    110100090000000, // Accounts receivable
+    240400010000000, // Sales of services
+    250500120000013, // Labour
+];
+
+/// Xorshift generator so that the "arbitrary" sequences are reproducible
+struct Generator(u64);
+
+impl Generator {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn post(keys: Vec<Key>) -> Result {
+    <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts(keys)
+}
+
+fn key(o: u8, p: u8, a: Account, c: LedgerBalance, d: bool) -> Key {
+    let block = System::block_number();
+    (id(o), id(p), a, c, d, H256::repeat_byte(o), block, block)
+}
+
+/// Reversal of a batch: negated amounts with the opposite indicator
+fn reversal(keys: &[Key]) -> Vec<Key> {
+    keys.iter().map(|k| (k.0.clone(), k.1.clone(), k.2, -k.3, !k.4, k.5, k.6, k.7)).collect()
+}
+
+/// A balanced recipe for one or two identities. Each identity debits and credits the same absolute amount,
+/// split over one or two lines, so that the batch balances per identity.
+fn arbitrary_recipe(g: &mut Generator) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let parties = 1 + g.below(2) as u8;
+    let p = g.below(IDENTITIES as u64) as u8;
+    for i in 0..parties {
+        let o = (p + i) % IDENTITIES;
+        let amount = 1 + g.below(1_000_000) as LedgerBalance;
+        let sign: LedgerBalance = if g.below(2) == 0 { 1 } else { -1 };
+        let dr = ACCOUNTS[g.below(ACCOUNTS.len() as u64) as usize];
+        let cr = ACCOUNTS[g.below(ACCOUNTS.len() as u64) as usize];
+        keys.push(key(o, p, dr, sign * amount, false));
+        if g.below(2) == 0 {
+            let split = amount / 2;
+            keys.push(key(o, p, cr, sign * split, true));
+            keys.push(key(o, p, cr, sign * (amount - split), true));
+        } else {
+            keys.push(key(o, p, cr, sign * amount, true));
+        }
+    }
+    keys
+}
+
+fn balances() -> Vec<((AccountId, Account), LedgerBalance)> {
+    let mut balances = Vec::new();
+    for o in 0..IDENTITIES {
+        for a in ACCOUNTS.iter().chain([ROUNDING_DIFFERENCE_ACCOUNT].iter()) {
+            let balance_key = (id(o), *a);
+            balances.push((balance_key.clone(), Accounting::balance_by_ledger(&balance_key)));
+        }
+    }
+    balances
+}
+
+/// Per identity, the absolute debits recorded in the posting detail equal the absolute credits
+fn assert_identities_balance() {
+    for o in 0..IDENTITIES {
+        let mut debits: LedgerBalance = 0;
+        let mut credits: LedgerBalance = 0;
+        for a in Accounting::accounts_by_id(id(o)).into_iter() {
+            for i in Accounting::id_account_posting_id_list((id(o), a)).into_iter() {
+                let lines = Accounting::posting_lines(id(o), a, i);
+                assert!(!lines.is_empty(), "posting detail exists");
+                for detail in lines.into_iter() {
+                    match detail.3 {
+                        false => debits += detail.2,
+                        true => credits += detail.2,
+                    }
+                }
+            }
+        }
+        assert_eq!(debits, credits);
+    }
+}
+
+/// Number of lines recorded in the posting detail of all identities
+fn detail_rows() -> usize {
+    (0..IDENTITIES)
+        .flat_map(|o| Accounting::accounts_by_id(id(o)).into_iter().map(move |a| (o, a)))
+        .flat_map(|(o, a)| Accounting::id_account_posting_id_list((id(o), a)).into_iter().map(move |i| (o, a, i)))
+        .map(|(o, a, i)| Accounting::posting_lines(id(o), a, i).len())
+        .sum()
+}
+
+/// The global ledger equals the sum of the ledgers of all identities
+fn assert_global_ledger_is_sum() {
+    for a in Accounting::global_ledger_accounts().into_iter() {
+        let sum: LedgerBalance = (0..IDENTITIES).map(|o| Accounting::balance_by_ledger((id(o), a))).sum();
+        assert_eq!(Accounting::global_ledger(a), sum);
+    }
+}
+
+#[test]
+fn arbitrary_recipes_keep_invariants() {
+    for seed in 1..=SEEDS {
+        with_externalities(&mut new_test_ext(), || {
+            let mut g = Generator(seed);
+            let mut last_index: Option<u128> = None;
+            let mut rows: usize = 0;
+            for b in 0..BATCHES {
+                System::set_block_number(b as u64 + 1);
+                let keys = arbitrary_recipe(&mut g);
+                rows += keys.len();
+                assert_ok!(post(keys));
+                // No line replaces the detail of another
+                assert_eq!(detail_rows(), rows);
+
+                let index = Accounting::posting_number().expect("posting number is set");
+                if let Some(last) = last_index {
+                    assert_eq!(index, last + 1);
+                }
+                last_index = Some(index);
+
+                assert_identities_balance();
+                assert_global_ledger_is_sum();
+            }
+            let ordered = Accounting::global_ledger_accounts();
+            assert!(ordered.windows(2).all(|w| w[0] < w[1]));
+        });
+    }
+}
+
+#[test]
+fn reversal_restores_balances() {
+    for seed in 1..=SEEDS {
+        with_externalities(&mut new_test_ext(), || {
+            let mut g = Generator(seed);
+            for _ in 0..BATCHES {
+                assert_ok!(post(arbitrary_recipe(&mut g)));
+            }
+            let before = balances();
+            let global_before: Vec<LedgerBalance> = ACCOUNTS.iter().map(|a| Accounting::global_ledger(a)).collect();
+
+            let keys = arbitrary_recipe(&mut g);
+            assert_ok!(post(keys.clone()));
+            assert_ok!(post(reversal(&keys)));
+
+            assert_eq!(balances(), before);
+            let global_after: Vec<LedgerBalance> = ACCOUNTS.iter().map(|a| Accounting::global_ledger(a)).collect();
+            assert_eq!(global_after, global_before);
+            assert_identities_balance();
+        });
+    }
+}
+
+#[test]
+fn lines_on_the_same_account_keep_their_own_detail() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let keys = vec![
+            key(1, 2, ACCOUNTS[3], 100, false),
+            key(1, 2, ACCOUNTS[4], 40, true),
+            key(1, 2, ACCOUNTS[4], 60, true),
+        ];
+        assert_ok!(post(keys.clone()));
+        let index = Accounting::posting_number().expect("posting number is set");
+        assert_eq!(Accounting::id_account_posting_id_list((id(1), ACCOUNTS[4])), vec![index]);
+        let amounts: Vec<LedgerBalance> = Accounting::posting_lines(id(1), ACCOUNTS[4], index).iter().map(|d| d.2).collect();
+        assert_eq!(amounts, vec![40, 60]);
+        let block_lines: Vec<(Account, u32)> = Accounting::block_postings(1).iter().map(|p| (p.1, p.5)).collect();
+        assert_eq!(block_lines, vec![(ACCOUNTS[3], 0), (ACCOUNTS[4], 0), (ACCOUNTS[4], 1)]);
+        assert_eq!(
+            Accounting::journal_entry(id(1), 1),
+            Some((index, vec![(ACCOUNTS[3], 100, false), (ACCOUNTS[4], 40, true), (ACCOUNTS[4], 60, true)]))
+        );
+        
+        // A batch holding a posting and its reversal keeps every line
+        let mut batch = keys.clone();
+        batch.extend(reversal(&keys));
+        let before = balances();
+        assert_ok!(post(batch));
+        let index = Accounting::posting_number().expect("posting number is set");
+        assert_eq!(Accounting::posting_lines(id(1), ACCOUNTS[3], index).len(), 2);
+        assert_eq!(Accounting::posting_lines(id(1), ACCOUNTS[4], index).len(), 4);
+        assert_eq!(detail_rows(), 9);
+        assert_eq!(balances(), before);
+        assert_identities_balance();
+    });
+}
+
+#[test]
+fn overflow_leaves_storage_untouched() {
+    with_externalities(&mut new_test_ext(), || {
+        assert_ok!(post(vec![
+            key(1, 1, ACCOUNTS[0], LedgerBalance::max_value(), false),
+            key(1, 1, ACCOUNTS[4], LedgerBalance::max_value(), true),
+        ]));
+        let index = Accounting::posting_number();
+        let before = balances();
+
+        // The first line is fine, the second overflows. Nothing may be written.
+        assert_err!(
+            post(vec![
+                key(2, 1, ACCOUNTS[1], 10, false),
+                key(1, 2, ACCOUNTS[0], 1, false),
+                key(1, 2, ACCOUNTS[4], 1, true),
+            ]),
+            "Balance Value overflowed"
+        );
+        assert_eq!(Accounting::posting_number(), index);
+        assert_eq!(balances(), before);
+        assert!(Accounting::id_account_posting_id_list((id(2), ACCOUNTS[1])).is_empty());
+    });
+}
+
+#[test]
+fn global_overflow_is_rejected() {
+    with_externalities(&mut new_test_ext(), || {
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[0], LedgerBalance::max_value(), false)]));
+        assert_err!(
+            post(vec![key(2, 2, ACCOUNTS[0], 1, false)]),
+            "Global Balance Value overflowed"
+        );
+    });
+}
+
+#[test]
+fn external_reference_too_long_is_rejected() {
+    with_externalities(&mut new_test_ext(), || {
+        let keys = vec![key(1, 1, ACCOUNTS[0], 5, false), key(1, 1, ACCOUNTS[4], 5, true)];
+        assert_err!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts_with_reference(
+                keys.clone(),
+                vec![b'x'; MAX_EXTERNAL_REFERENCE_LENGTH + 1]
+            ),
+            "External reference is too long"
+        );
+        assert_ok!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts_with_reference(
+                keys,
+                b"INV-1".to_vec()
+            )
+        );
+        assert_eq!(Accounting::external_reference_index((id(1), b"INV-1".to_vec())), vec![0]);
+    });
+}
+
+#[test]
+fn rounding_difference_is_posted() {
+    with_externalities(&mut new_test_ext(), || {
+        // Debits exceed credits by one unit over three lines
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::handle_converted_multiposting_amounts(vec![
+            key(1, 1, ACCOUNTS[3], 100, false),
+            key(1, 1, ACCOUNTS[4], 33, true),
+            key(1, 1, ACCOUNTS[4], 66, true),
+        ]));
+        assert_eq!(Accounting::balance_by_ledger((id(1), ROUNDING_DIFFERENCE_ACCOUNT)), -1);
+        assert_identities_balance();
+        assert_global_ledger_is_sum();
+    });
+}
+
+#[test]
+fn imbalances_are_only_rounded_in_converted_batches() {
+    with_externalities(&mut new_test_ext(), || {
+        // More than one unit per line is not a rounding residual
+        assert_err!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_converted_multiposting_amounts(vec![
+                key(1, 1, ACCOUNTS[3], 100, false),
+                key(1, 1, ACCOUNTS[4], 90, true),
+            ]),
+            "The converted postings do not balance to the rounding precision"
+        );
+        // Other batches are posted as given
+        assert_ok!(post(vec![
+            key(1, 1, ACCOUNTS[3], 100, false),
+            key(1, 1, ACCOUNTS[4], 99, true),
+        ]));
+        assert_eq!(Accounting::balance_by_ledger((id(1), ROUNDING_DIFFERENCE_ACCOUNT)), 0);
+    });
+}
+
+#[test]
+fn rounding_policy_is_applied() {
+    with_externalities(&mut new_test_ext(), || {
+        let rate = |a, n, d| <Accounting as Posting<AccountId, H256, u64, u64>>::apply_rate(a, n, d);
+        assert_eq!(rate(5, 1, 2), Some(3));
+        assert_eq!(rate(-5, 1, 2), Some(-3));
+        assert_eq!(rate(1, 1, 3), Some(0));
+        assert_eq!(rate(1, 1, 0), None);
+
+        assert_noop!(Accounting::set_rounding_policy(Origin::signed(id(1)), ROUND_HALF_EVEN), "bad origin: expected to be a root origin");
+        assert_err!(Accounting::set_rounding_policy(Origin::ROOT, 7), "Unknown rounding policy");
+        assert_ok!(Accounting::set_rounding_policy(Origin::ROOT, ROUND_HALF_EVEN));
+        assert_eq!(rate(5, 1, 2), Some(2));
+        assert_eq!(rate(7, 1, 2), Some(4));
+        assert_eq!(rate(-5, 1, 2), Some(-2));
+        assert_eq!(rate(LedgerBalance::max_value(), 2, 1), None);
+    });
+}
+
+#[test]
+fn quantity_postings_respect_precision() {
+    with_externalities(&mut new_test_ext(), || {
+        assert_err!(
+            Accounting::set_account_decimals(Origin::ROOT, ACCOUNTS[0], QUANTITY_DECIMALS + 1, b"kg".to_vec(), true),
+            "Invalid account decimals or display unit"
+        );
+        assert_ok!(Accounting::set_account_decimals(Origin::ROOT, ACCOUNTS[0], 2, b"kg".to_vec(), true));
+        assert_eq!(Accounting::accounts_display(), vec![(ACCOUNTS[0], (2, b"kg".to_vec(), true))]);
+
+        // Two decimals of a quantity account are multiples of 10^4 millionths
+        assert_err!(
+            post(vec![key(1, 1, ACCOUNTS[0], 10_001, false), key(1, 1, ACCOUNTS[4], 10_001, true)]),
+            "Amount exceeds the precision of the quantity account"
+        );
+        assert_eq!(Accounting::posting_number(), None);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[0], 20_000, false), key(1, 1, ACCOUNTS[4], 20_000, true)]));
+
+        assert_ok!(Accounting::remove_account_decimals(Origin::ROOT, ACCOUNTS[0]));
+        assert!(Accounting::accounts_display().is_empty());
+    });
+}
+
+#[test]
+fn postings_record_their_origin() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let keys = vec![key(1, 1, ACCOUNTS[0], 5, false), key(1, 1, ACCOUNTS[4], 5, true)];
+        assert_ok!(post(keys.clone()));
+        assert_ok!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts_with_origin(
+                reversal(&keys),
+                Vec::new(),
+                ORIGIN_ADJUSTMENT
+            )
+        );
+        assert_err!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts_with_origin(
+                keys,
+                Vec::new(),
+                ORIGIN_MIGRATION + 1
+            ),
+            "Unknown posting origin"
+        );
+
+        assert_eq!(Accounting::postings_by_origin(id(1), ACCOUNTS[0], ORIGIN_USER), vec![0]);
+        assert_eq!(Accounting::postings_by_origin(id(1), ACCOUNTS[0], ORIGIN_ADJUSTMENT), vec![1]);
+        assert!(Accounting::postings_by_origin(id(1), ACCOUNTS[0], ORIGIN_SYSTEM).is_empty());
+        assert_eq!(Accounting::postings_in_range(1, 1, 0).len(), 4);
+        let adjustments = Accounting::postings_in_range_by_origin(1, 1, 0, ORIGIN_ADJUSTMENT);
+        assert_eq!(adjustments.len(), 2);
+        assert!(adjustments.iter().all(|p| p.4 == 1));
+    });
+}
+
+#[test]
+fn escrow_is_broken_down_by_reference() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let order = |h: u8, c: LedgerBalance| {
+            let mut keys = vec![key(1, 2, ESCROW_ACCOUNT, c, false), key(1, 2, ACCOUNTS[0], -c, true)];
+            for k in keys.iter_mut() {
+                k.5 = H256::repeat_byte(h);
+            }
+            keys
+        };
+        assert_ok!(post(order(10, 30)));
+        assert_ok!(post(order(11, 20)));
+        assert_ok!(post(order(10, 5)));
+
+        assert_eq!(Accounting::balance_by_ledger((id(1), ESCROW_ACCOUNT)), 55);
+        assert_eq!(
+            Accounting::sub_ledger(id(1), ESCROW_ACCOUNT),
+            vec![(H256::repeat_byte(10), 35), (H256::repeat_byte(11), 20)]
+        );
+        // Accounts outside the sub-ledger are not broken down
+        assert!(Accounting::sub_ledger(id(1), ACCOUNTS[0]).is_empty());
+
+        // Releasing the escrow of an order removes it from the sub-ledger
+        assert_ok!(post(reversal(&order(10, 35))));
+        assert_eq!(Accounting::sub_ledger(id(1), ESCROW_ACCOUNT), vec![(H256::repeat_byte(11), 20)]);
+        assert_eq!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::get_sub_ledger_balance(id(1), ESCROW_ACCOUNT, H256::repeat_byte(10)),
+            0
+        );
+    });
+}
+
+#[test]
+fn network_status_counts_recent_movements() {
+    with_externalities(&mut new_test_ext(), || {
+        let netfees = <Accounting as Posting<AccountId, H256, u64, u64>>::get_netfees_account();
+        let escrow = <Accounting as Posting<AccountId, H256, u64, u64>>::get_escrow_account();
+        System::set_block_number(1);
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(10, id(1)));
+        Accounting::on_finalize(1);
+        // The fee of the first block is outside the window by now
+        System::set_block_number(NETWORK_STATUS_WINDOW + 2);
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(5, id(1)));
+        Accounting::on_finalize(NETWORK_STATUS_WINDOW + 2);
+
+        let (identities, accounts) = Accounting::network_status();
+        assert_eq!(
+            identities,
+            vec![
+                (escrow, Vec::new()),
+                (netfees, vec![(240400010000000, 15, 1), (110100040000000, 15, 1)]),
+            ]
+        );
+        assert_eq!(accounts.len(), NETWORK_STATUS_ACCOUNTS.len());
+        assert!(accounts.contains(&(250500300000000, 15)));
+        assert!(accounts.contains(&(110100040000000, 0)));
+    });
+}
+
+#[test]
+fn fees_are_batched_per_payer_and_posted_at_the_end_of_the_block() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let fees = |n: u8| Accounting::balance_by_ledger((id(n), 250500300000000));
+        for (n, fee) in [(1u8, 10u64), (2, 3), (1, 5)].iter() {
+            assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(*fee, id(*n)));
+        }
+        assert_eq!((fees(1), fees(2)), (0, 0));
+        assert_eq!(Accounting::pending_fee_payers(), vec![id(1), id(2)]);
+        assert_eq!(Accounting::pending_fees(id(1)), 15);
+        
+        // The XTX ledger is realigned to the wallet net of the fees withdrawn but not yet posted
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::force_set_gl_account_balance(id(1), 85));
+        assert_eq!(Accounting::balance_by_ledger((id(1), 110100040000000)), 100);
+        
+        Accounting::on_finalize(1);
+        assert_eq!((fees(1), fees(2)), (15, 3));
+        assert_eq!(Accounting::balance_by_ledger((id(1), 110100040000000)), 85);
+        // One set of postings per payer
+        assert_eq!(Accounting::id_account_posting_id_list((id(1), 250500300000000)).len(), 1);
+        assert_eq!(Accounting::block_postings(1).len(), 8);
+        assert!(Accounting::pending_fee_payers().is_empty());
+        assert_eq!(Accounting::pending_fees(id(1)), 0);
+    });
+}
+
+#[test]
+fn fees_that_fail_to_post_stay_pending_until_the_next_block() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let fees = |n: u8| Accounting::balance_by_ledger((id(n), 250500300000000));
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(10, id(1)));
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(3, id(2)));
+        // The fee expense of the first payer cannot take the fee
+        <BalanceByLedger<mock::Runtime>>::insert((id(1), 250500300000000), LedgerBalance::max_value());
+        
+        Accounting::on_finalize(1);
+        assert_eq!(fees(2), 3);
+        assert_eq!(Accounting::pending_fee_payers(), vec![id(1)]);
+        assert_eq!(Accounting::pending_fees(id(1)), 10);
+        
+        // Fees of the next block are added to the fee still pending and posted together
+        System::set_block_number(2);
+        <BalanceByLedger<mock::Runtime>>::insert((id(1), 250500300000000), 0);
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::account_for_fees(5, id(1)));
+        assert_eq!(Accounting::pending_fee_payers(), vec![id(1)]);
+        Accounting::on_finalize(2);
+        assert_eq!(fees(1), 15);
+        assert!(Accounting::pending_fee_payers().is_empty());
+        assert_eq!(Accounting::pending_fees(id(1)), 0);
+    });
+}
+
+#[test]
+fn accounting_equation_holds_after_the_notional_close() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        // Services paid in XTX and accrued labour
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[0], 1000, false), key(1, 2, ACCOUNTS[4], 1000, true)]));
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[5], 300, false), key(1, 2, ACCOUNTS[2], 300, true)]));
+        assert_eq!(
+            Accounting::accounting_equation(id(1)),
+            AccountingEquation { assets: 1000, liabilities: 300, equity: 0, revenue: 1000, expenses: 300, balanced: true }
+        );
+
+        // A balance set outside the postings replaces the XTX balance and breaks the equation
+        assert_ok!(<Accounting as Posting<AccountId, H256, u64, u64>>::force_set_gl_account_balance(id(1), 5));
+        let equation = Accounting::accounting_equation(id(1));
+        assert_eq!(equation.assets, 5);
+        assert!(!equation.balanced);
+        assert_eq!(Accounting::accounting_equation(id(2)), AccountingEquation { balanced: true, ..Default::default() });
+    });
+}
+
+#[test]
+fn close_preview_rolls_the_period_result_up_without_posting() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[0], 1000, false), key(1, 2, ACCOUNTS[4], 1000, true)]));
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[5], 300, false), key(1, 2, ACCOUNTS[2], 300, true)]));
+        assert_ok!(Accounting::set_budget(Origin::signed(id(1)), ACCOUNTS[4], 800));
+        let posted = Accounting::posting_number();
+
+        let preview = Accounting::preview_close(id(1));
+        assert_eq!(preview.last_close, None);
+        assert_eq!(preview.variances, vec![(ACCOUNTS[4], 800, 1000, 200)]);
+        assert_eq!(
+            preview.entries,
+            vec![(ACCOUNTS[4], -1000, false), (ACCOUNTS[5], -300, true), (RETAINED_EARNINGS_ACCOUNT, 700, true)]
+        );
+        assert_eq!(Accounting::posting_number(), posted);
+        assert_eq!(Accounting::balance_by_ledger((id(1), ACCOUNTS[4])), 1000);
+        assert!(Accounting::last_period_close(id(1)).is_none());
+
+        // Once closed only the movement of the next period is rolled up
+        assert_ok!(Accounting::close_period(Origin::signed(id(1))));
+        System::set_block_number(2);
+        assert_eq!(Accounting::preview_close(id(1)).last_close, Some(1));
+        assert!(Accounting::preview_close(id(1)).entries.is_empty());
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[5], 500, false), key(1, 2, ACCOUNTS[2], 500, true)]));
+        assert_eq!(
+            Accounting::preview_close(id(1)).entries,
+            vec![(ACCOUNTS[5], -500, true), (RETAINED_EARNINGS_ACCOUNT, -500, false)]
+        );
+    });
+}
+
+#[test]
+fn budgets_on_accounts_without_postings_are_reported() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        assert_ok!(Accounting::set_budget(Origin::signed(id(1)), ACCOUNTS[5], 300));
+        assert_ok!(Accounting::set_budget(Origin::signed(id(1)), ACCOUNTS[4], 800));
+        assert_ok!(Accounting::remove_budget(Origin::signed(id(1)), ACCOUNTS[4]));
+        assert_eq!(Accounting::budget_accounts(id(1)), vec![ACCOUNTS[5]]);
+
+        assert_ok!(Accounting::close_period(Origin::signed(id(1))));
+        assert_eq!(Accounting::variance_summary((id(1), 1)), vec![(ACCOUNTS[5], 300, 0, -300)]);
+        assert_eq!(Accounting::last_period_close(id(1)), Some(1));
+        assert!(Accounting::period_close_cursor(id(1)).is_none());
+    });
+}
+
+#[test]
+fn period_close_carries_forward_the_balances_of_the_close_block() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        for i in 0..MAX_CLOSE_ACCOUNTS as u64 {
+            assert_ok!(post(vec![key(1, 2, ACCOUNTS[0] + i, 10, false), key(1, 2, ACCOUNTS[4], 10, true)]));
+        }
+        let sales_at_close = Accounting::balance_by_ledger((id(1), ACCOUNTS[4]));
+        
+        // The accounts are carried forward in account order, so the sales account is left for the next call
+        assert_ok!(Accounting::close_period(Origin::signed(id(1))));
+        assert_eq!(Accounting::period_close_cursor(id(1)), Some((1, MAX_CLOSE_ACCOUNTS as u32)));
+        assert_eq!(Accounting::period_close_accounts(id(1)), vec![ACCOUNTS[4]]);
+        
+        // Postings while the close is in progress do not change the balances carried forward
+        System::set_block_number(2);
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[0], 50, false), key(1, 2, ACCOUNTS[4], 50, true)]));
+        assert_eq!(Accounting::period_close_balance((id(1), ACCOUNTS[0])), 10);
+        assert_eq!(Accounting::period_close_balance((id(1), ACCOUNTS[4])), sales_at_close);
+        assert!(Accounting::period_close_accounts(id(1)).is_empty());
+        
+        assert_ok!(Accounting::close_period(Origin::signed(id(1))));
+        assert_eq!(Accounting::last_period_close(id(1)), Some(1));
+        assert!(Accounting::period_close_cursor(id(1)).is_none());
+        assert_eq!(Accounting::period_close_balance((id(1), ACCOUNTS[4])), sales_at_close);
+        assert_ne!(Accounting::balance_by_ledger((id(1), ACCOUNTS[4])), sales_at_close);
+    });
+}
+
+#[test]
+fn simple_postings_take_their_signs_from_the_account_nature() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let post_simple = |d: Account, c: Account, amount: LedgerBalance| {
+            <Accounting as Posting<AccountId, H256, u64, u64>>::post_simple(id(1), id(2), d, c, amount, H256::repeat_byte(1))
+        };
+        // Labour paid in XTX, then a payable settled against sales
+        assert_ok!(post_simple(ACCOUNTS[5], ACCOUNTS[0], 300));
+        assert_ok!(post_simple(ACCOUNTS[2], ACCOUNTS[4], 100));
+        let expected: [(Account, LedgerBalance); 4] = [(ACCOUNTS[5], 300), (ACCOUNTS[0], -300), (ACCOUNTS[2], -100), (ACCOUNTS[4], 100)];
+        for (a, c) in expected.iter() {
+            assert_eq!(Accounting::balance_by_ledger((id(1), *a)), *c);
+        }
+        assert_eq!(Accounting::balance_by_ledger((id(2), ACCOUNTS[0])), 0);
+        assert_identities_balance();
+
+        assert_err!(post_simple(ACCOUNTS[5], ACCOUNTS[0], 0), "Simple posting amount must be greater than zero");
+        assert_err!(post_simple(360600050000000, ACCOUNTS[0], 10), "Simple postings cannot be made to control accounts");
+    });
+}
+
+#[test]
+fn reporting_currency_converts_read_apis() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[4], 300, true), key(1, 1, ACCOUNTS[0], 300, false)]));
+        assert_eq!(Accounting::trial_balance(id(1), 1), (None, vec![(ACCOUNTS[0], 300, None), (ACCOUNTS[4], 300, None)]));
+
+        assert_err!(Accounting::set_reporting_currency(Origin::signed(id(1)), 978), "No exchange rate for this currency");
+        assert_err!(Accounting::set_exchange_rate(Origin::ROOT, 978, 0, 1, 0), "Invalid exchange rate");
+        assert_ok!(Accounting::set_exchange_rate(Origin::ROOT, 978, 2, 3, 4));
+        assert_ok!(Accounting::set_exchange_rate(Origin::ROOT, 978, 0, 1, 2));
+        assert_eq!(Accounting::rate_periods(978), vec![0, 2]);
+        assert_ok!(Accounting::set_reporting_currency(Origin::signed(id(1)), 978));
+
+        // The rate of the period of the block, or the latest earlier rate
+        assert_eq!(
+            Accounting::trial_balance(id(1), RATE_PERIOD - 1),
+            (Some(978), vec![(ACCOUNTS[0], 300, Some(150)), (ACCOUNTS[4], 300, Some(150))])
+        );
+        assert_eq!(
+            Accounting::statement_totals(id(1), RATE_PERIOD * 3),
+            (Some(978), vec![(1, 300, Some(225)), (2, 300, Some(225))])
+        );
+
+        // Later postings are not in the figures at an earlier block
+        System::set_block_number(RATE_PERIOD * 3 + 1);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[4], 100, true), key(1, 1, ACCOUNTS[0], 100, false)]));
+        assert_eq!(Accounting::trial_balance(id(1), 1), (Some(978), vec![(ACCOUNTS[0], 300, Some(150)), (ACCOUNTS[4], 300, Some(150))]));
+        assert_eq!(Accounting::statement_totals(id(1), RATE_PERIOD * 3 + 1).1, vec![(1, 400, Some(300)), (2, 400, Some(300))]);
+    });
+}
+
+#[test]
+fn chart_versions_remap_new_postings_and_reports() {
+    with_externalities(&mut new_test_ext(), || {
+        const RENUMBERED: Account = 240400020000000;
+        System::set_block_number(1);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[4], 300, true), key(1, 1, ACCOUNTS[0], 300, false)]));
+
+        assert_err!(
+            Accounting::add_chart_version(Origin::ROOT, 1, vec![(ACCOUNTS[4], RENUMBERED)]),
+            "Invalid chart of accounts version"
+        );
+        assert_err!(
+            Accounting::add_chart_version(Origin::ROOT, 10, vec![(ACCOUNTS[4], RENUMBERED), (ACCOUNTS[4], ACCOUNTS[5])]),
+            "Invalid chart of accounts version"
+        );
+        assert_ok!(Accounting::add_chart_version(Origin::ROOT, 10, vec![(ACCOUNTS[4], RENUMBERED)]));
+        assert_eq!(Accounting::chart_version_at(9), 0);
+        assert_eq!(Accounting::chart_version_at(10), 1);
+
+        // New postings use the current version
+        System::set_block_number(10);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[4], 200, true), key(1, 1, ACCOUNTS[0], 200, false)]));
+        assert_eq!(Accounting::balance_by_ledger((id(1), ACCOUNTS[4])), 300);
+        assert_eq!(Accounting::balance_by_ledger((id(1), RENUMBERED)), 200);
+
+        // Reports use the version in force at the block reported on
+        // The renumbered account the runtime posted to at block 10 is not in the trial balance at block 9
+        assert_eq!(Accounting::trial_balance(id(1), 9), (None, vec![(ACCOUNTS[0], 300, None), (ACCOUNTS[4], 300, None)]));
+        assert_eq!(Accounting::trial_balance(id(1), 10), (None, vec![(ACCOUNTS[0], 500, None), (RENUMBERED, 500, None)]));
+        assert_eq!(Accounting::movements_in_period(id(1), RENUMBERED, 0, 10), (2, 500, 0));
+        assert_eq!(Accounting::movements_in_period(id(1), ACCOUNTS[4], 0, 10), (0, 0, 0));
+    });
+}
+
+#[test]
+fn user_entries_are_numbered_per_identity() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        assert_ok!(post(vec![key(1, 1, ACCOUNTS[4], 10, true), key(1, 1, ACCOUNTS[0], 10, false)]));
+        assert_ok!(
+            <Accounting as Posting<AccountId, H256, u64, u64>>::handle_multiposting_amounts_with_origin(
+                vec![key(1, 2, ACCOUNTS[4], 5, true), key(1, 2, ACCOUNTS[0], 5, false)],
+                Vec::new(),
+                ORIGIN_SYSTEM
+            )
+        );
+        assert_ok!(post(vec![
+            key(1, 2, ACCOUNTS[4], 7, true), key(1, 2, ACCOUNTS[0], 7, false),
+            key(2, 1, ACCOUNTS[2], 7, true), key(2, 1, ACCOUNTS[5], 7, false),
+        ]));
+
+        // System recipes do not take a journal number
+        assert_eq!(Accounting::last_journal_number(id(1)), 2);
+        assert_eq!(Accounting::last_journal_number(id(2)), 1);
+        assert_eq!(Accounting::posting_detail((id(1), ACCOUNTS[0], 1)).map(|d| d.7), Some(None));
+        assert_eq!(Accounting::posting_detail((id(1), ACCOUNTS[0], 2)).map(|d| d.7), Some(Some(2)));
+        assert_eq!(Accounting::journal_entry(id(1), 1), Some((0, vec![(ACCOUNTS[4], 10, true), (ACCOUNTS[0], 10, false)])));
+        assert_eq!(Accounting::journal_entry(id(2), 1).map(|j| j.0), Some(2));
+        assert!(Accounting::journal_entry(id(2), 2).is_none());
+    });
+}
+
+#[test]
+fn posting_detail_stored_before_the_origin_and_journal_decodes() {
+    let reference = H256::repeat_byte(1);
+    let legacy = (id(2), 1u64, 10 as LedgerBalance, true, reference, 1u64).encode();
+    assert_eq!(
+        PostingDetailRecord::<AccountId, u64, H256>::decode(&mut &legacy[..]),
+        Some(PostingDetailRecord(id(2), 1, 10, true, reference, 1, ORIGIN_SYSTEM, None))
+    );
+    let with_origin = (id(2), 1u64, 10 as LedgerBalance, true, reference, 1u64, ORIGIN_USER).encode();
+    assert_eq!(
+        PostingDetailRecord::<AccountId, u64, H256>::decode(&mut &with_origin[..]),
+        Some(PostingDetailRecord(id(2), 1, 10, true, reference, 1, ORIGIN_USER, None))
+    );
+    let current = PostingDetailRecord(id(2), 1u64, 10, true, reference, 1u64, ORIGIN_USER, Some(3));
+    assert_eq!(PostingDetailRecord::<AccountId, u64, H256>::decode(&mut &current.encode()[..]), Some(current));
+}
+
+#[test]
+fn journals_are_queried_by_tag() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        for amount in [10, 20, 40].iter() {
+            assert_ok!(post(vec![key(1, 2, ACCOUNTS[5], *amount, false), key(1, 2, ACCOUNTS[2], *amount, true)]));
+        }
+        let tag = |t: &[u8]| t.to_vec();
+        assert_ok!(Accounting::set_journal_tags(Origin::signed(id(1)), 1, vec![tag(b"marketing"), tag(b"q3")]));
+        assert_ok!(Accounting::set_journal_tags(Origin::signed(id(1)), 3, vec![tag(b"marketing")]));
+        assert_eq!(Accounting::journals_by_tag(id(1), tag(b"marketing")), vec![(1, 0), (3, 2)]);
+        assert_eq!(Accounting::balance_by_tag(id(1), ACCOUNTS[5], tag(b"marketing")), 50);
+        assert_eq!(Accounting::balance_by_tag(id(1), ACCOUNTS[5], tag(b"q3")), 10);
+
+        // Tags are replaced
+        assert_ok!(Accounting::set_journal_tags(Origin::signed(id(1)), 1, vec![tag(b"q3")]));
+        assert_eq!(Accounting::journals_by_tag(id(1), tag(b"marketing")), vec![(3, 2)]);
+        assert_eq!(Accounting::journal_tags((id(1), 1)), vec![tag(b"q3")]);
+
+        // A reduction of the expense is deducted in the sense of the account's nature
+        assert_ok!(post(reversal(&[key(1, 2, ACCOUNTS[5], 5, false), key(1, 2, ACCOUNTS[2], 5, true)])));
+        assert_ok!(Accounting::set_journal_tags(Origin::signed(id(1)), 4, vec![tag(b"q3")]));
+        assert_eq!(Accounting::balance_by_tag(id(1), ACCOUNTS[5], tag(b"q3")), 5);
+        assert_eq!(Accounting::balance_by_tag(id(1), ACCOUNTS[2], tag(b"q3")), 5);
+
+        let invalid = "Too many tags, a tag is repeated, empty or too long";
+        assert_err!(Accounting::set_journal_tags(Origin::signed(id(1)), 2, vec![tag(b"a"), tag(b"a")]), invalid);
+        assert_err!(Accounting::set_journal_tags(Origin::signed(id(1)), 2, vec![vec![1u8; 17]]), invalid);
+        assert_err!(Accounting::set_journal_tags(Origin::signed(id(1)), 2, (1..=5u8).map(|n| vec![n]).collect()), invalid);
+        assert_err!(Accounting::set_journal_tags(Origin::signed(id(2)), 2, vec![tag(b"q3")]), "Journal does not exist");
+    });
+}
+
+#[test]
+fn opening_balances_take_their_side_from_the_account_nature() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        // An overdrawn bank account (asset) is a credit
+        assert_ok!(Accounting::set_opening_balance(Origin::signed(id(1)), ACCOUNTS[0], -40));
+        assert_ok!(Accounting::set_opening_balance(Origin::signed(id(1)), ACCOUNTS[2], 100));
+        assert_err!(Accounting::set_opening_balance(Origin::signed(id(1)), ACCOUNTS[0], 10), "The opening balance of this account has already been set");
+        assert_err!(Accounting::set_opening_balance(Origin::signed(id(1)), ACCOUNTS[4], 10), "Invalid opening balance");
+        assert_err!(
+            Accounting::set_opening_balance(Origin::signed(id(1)), 360600020000000, 10),
+            "Opening balances can only be set on asset, liability or equity accounts"
+        );
+        assert_err!(Accounting::set_opening_balance(Origin::signed(id(1)), ACCOUNTS[1], 0), "Invalid opening balance");
+
+        assert_eq!(Accounting::opening_balance((id(1), ACCOUNTS[0])), Some(-40));
+        assert_eq!(Accounting::journal_entry(id(1), 1), Some((0, vec![(ACCOUNTS[0], 40, true), (OPENING_BALANCE_ACCOUNT, 40, false)])));
+        assert_eq!(Accounting::journal_entry(id(1), 2), Some((1, vec![(ACCOUNTS[2], 100, true), (OPENING_BALANCE_ACCOUNT, 100, false)])));
+        assert_eq!(Accounting::balance_by_ledger((id(1), OPENING_BALANCE_ACCOUNT)), -140);
+        assert_identities_balance();
+    });
+}
+
+/// The encoding of the replication record is a stable interface for downstream databases. 
+/// If this test fails the shape has changed and JOURNAL_REPLICATION_VERSION must be bumped.
+#[test]
+fn journal_replication_record_shape_is_stable() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(3);
+        let keys = vec![key(1, 2, ACCOUNTS[0], -5, true), key(1, 2, ACCOUNTS[4], 5, false)];
+        let record = Accounting::replication_record(7, ORIGIN_USER, &keys, &[(id(1), 4)], b"INV-1".to_vec());
+        assert_eq!(JOURNAL_REPLICATION_VERSION, 1);
+
+        let mut expected: Vec<u8> = vec![1];
+        expected.extend_from_slice(&7u128.to_le_bytes());
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.push(ORIGIN_USER);
+        // compact encoded number of lines
+        expected.push(2 << 2);
+        for (account, amount, credit) in [(ACCOUNTS[0], -5i128, true), (ACCOUNTS[4], 5i128, false)].iter() {
+            expected.extend_from_slice(&[1; 32]);
+            expected.extend_from_slice(&[2; 32]);
+            expected.extend_from_slice(&account.to_le_bytes());
+            expected.extend_from_slice(&amount.to_le_bytes());
+            expected.push(*credit as u8);
+            expected.extend_from_slice(H256::repeat_byte(1).as_bytes());
+            expected.extend_from_slice(&3u64.to_le_bytes());
+            expected.extend_from_slice(&[1, 4, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        expected.push(5 << 2);
+        expected.extend_from_slice(b"INV-1");
+        assert_eq!(record.encode(), expected);
+    });
+}
+
+#[test]
+fn contra_accounts_are_presented_net_of_their_parent() {
+    with_externalities(&mut new_test_ext(), || {
+        const ALLOWANCE: Account = 110100110000000; // Allowance for doubtful debts
+        System::set_block_number(1);
+        assert_err!(Accounting::set_contra_account(Origin::ROOT, ALLOWANCE, ACCOUNTS[2]), "Invalid contra account");
+        assert_err!(Accounting::set_contra_account(Origin::ROOT, ALLOWANCE, ALLOWANCE), "Invalid contra account");
+        assert_ok!(Accounting::set_contra_account(Origin::ROOT, ALLOWANCE, ACCOUNTS[3]));
+        // Netting is one level deep
+        assert_err!(Accounting::set_contra_account(Origin::ROOT, ACCOUNTS[3], ACCOUNTS[0]), "Invalid contra account");
+        assert_err!(Accounting::set_contra_account(Origin::ROOT, ACCOUNTS[0], ALLOWANCE), "Invalid contra account");
+        assert_eq!(Accounting::contra_accounts(), vec![(ALLOWANCE, ACCOUNTS[3])]);
+
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[3], 1000, false), key(1, 2, ACCOUNTS[4], 1000, true)]));
+        assert_ok!(post(vec![key(1, 2, ALLOWANCE, -100, true), key(1, 2, ACCOUNTS[5], 100, false)]));
+        assert_eq!(
+            Accounting::net_presentation(id(1), 1),
+            vec![(ACCOUNTS[3], 1000, -100, 900), (ACCOUNTS[4], 1000, 0, 1000), (ACCOUNTS[5], 100, 0, 100)]
+        );
+
+        assert_ok!(Accounting::remove_contra_account(Origin::ROOT, ALLOWANCE));
+        assert_err!(Accounting::remove_contra_account(Origin::ROOT, ALLOWANCE), "Not a contra account");
+        assert_eq!(Accounting::net_presentation(id(1), 1)[0], (ACCOUNTS[3], 1000, 0, 1000));
+    });
+}
+
+#[test]
+fn profit_and_loss_is_filtered_by_dimension_and_period() {
+    with_externalities(&mut new_test_ext(), || {
+        let dimension = |o: u8, p: u8, a: Account, c: LedgerBalance, d: bool, h: u8| {
+            let block = System::block_number();
+            (id(o), id(p), a, c, d, H256::repeat_byte(h), block, block)
+        };
+        System::set_block_number(1);
+        assert_ok!(post(vec![dimension(1, 2, ACCOUNTS[3], 1000, false, 10), dimension(1, 2, ACCOUNTS[4], 1000, true, 10)]));
+        assert_ok!(post(vec![dimension(1, 2, ACCOUNTS[5], 300, false, 11), dimension(1, 2, ACCOUNTS[0], -300, true, 11)]));
+        assert_ok!(post(vec![dimension(1, 2, ACCOUNTS[3], 50, false, 12), dimension(1, 2, ACCOUNTS[4], 50, true, 12)]));
+        System::set_block_number(5);
+        assert_ok!(post(vec![dimension(1, 2, ACCOUNTS[3], 200, false, 10), dimension(1, 2, ACCOUNTS[4], 200, true, 10)]));
+        // A return reduces the sales of the dimension
+        assert_ok!(post(vec![dimension(1, 2, ACCOUNTS[3], -50, true, 10), dimension(1, 2, ACCOUNTS[4], -50, false, 10)]));
+
+        assert_eq!(Accounting::balance_by_dimension(id(1), ACCOUNTS[4], H256::repeat_byte(10)), 1150);
+        assert_eq!(Accounting::balance_by_dimension(id(1), ACCOUNTS[4], H256::repeat_byte(11)), 0);
+
+        let dimensions = vec![H256::repeat_byte(10), H256::repeat_byte(11)];
+        assert_eq!(Accounting::pl_by_dimensions(id(1), dimensions.clone(), 0, 10), vec![(ACCOUNTS[4], 1150), (ACCOUNTS[5], 300)]);
+        assert_eq!(Accounting::pl_by_dimensions(id(1), dimensions, 2, 10), vec![(ACCOUNTS[4], 150)]);
+        assert!(Accounting::pl_by_dimensions(id(2), vec![H256::repeat_byte(10)], 0, 10).is_empty());
+    });
+}
+
+#[test]
+fn backdated_postings_are_flagged_for_approval() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(200_000);
+        let backdated = |o: u8, a: Account, c: LedgerBalance, d: bool, target: u64| (id(o), id(2), a, c, d, H256::repeat_byte(o), 200_000, target);
+        // Within the tolerance
+        assert_ok!(post(vec![backdated(1, ACCOUNTS[3], 10, false, 100_000), backdated(1, ACCOUNTS[4], 10, true, 100_000)]));
+        let tolerated = Accounting::posting_number().expect("postings were made");
+        assert_eq!(Accounting::unapproved_backdated((id(1), tolerated)), None);
+        // The earliest period of the batch is reported
+        assert_ok!(post(vec![backdated(1, ACCOUNTS[3], 10, false, 20_000), backdated(1, ACCOUNTS[4], 10, true, 10_000)]));
+        let index = Accounting::posting_number().expect("postings were made");
+        assert_eq!(Accounting::unapproved_backdated((id(1), index)), Some(190_000));
+
+        assert_err!(
+            Accounting::set_backdating_approver(Origin::signed(id(1)), Some(id(1))),
+            "The approver of backdated postings must be another identity"
+        );
+        assert_err!(
+            Accounting::approve_backdated_posting(Origin::signed(id(3)), id(1), index),
+            "Not the approver of backdated postings of this identity"
+        );
+        assert_ok!(Accounting::set_backdating_approver(Origin::signed(id(1)), Some(id(3))));
+        assert_ok!(Accounting::approve_backdated_posting(Origin::signed(id(3)), id(1), index));
+        assert_eq!(Accounting::unapproved_backdated((id(1), index)), None);
+        assert_err!(
+            Accounting::approve_backdated_posting(Origin::signed(id(3)), id(1), index),
+            "No backdated posting awaiting approval with this index"
+        );
+    });
+}
+
+#[test]
+fn indices_are_rebuilt_from_the_posting_detail() {
+    with_externalities(&mut new_test_ext(), || {
+        for b in 1..=3 {
+            System::set_block_number(b);
+            assert_ok!(post(vec![key(1, 2, ACCOUNTS[3], 10, false), key(1, 2, ACCOUNTS[4], 10, true)]));
+        }
+        let accounts = Accounting::accounts_by_id(id(1));
+        let receivables = Accounting::id_account_posting_id_list((id(1), ACCOUNTS[3]));
+        assert_eq!(receivables.len(), 3);
+
+        // Corrupt the indices: a lost account, a duplicated and a dangling posting index
+        <AccountsById<mock::Runtime>>::insert(id(1), vec![ACCOUNTS[3]]);
+        <IdAccountPostingIdList<mock::Runtime>>::insert((id(1), ACCOUNTS[3]), vec![receivables[1], receivables[1], 999]);
+
+        let keys: Vec<(AccountId, Account, PostingIndex)> = receivables.iter().map(|i| (id(1), ACCOUNTS[3], *i)).collect();
+        assert_err!(
+            Accounting::rebuild_indices(Origin::ROOT, vec![keys[0].clone(); MAX_REBUILD_KEYS + 1]),
+            "Too many postings to rebuild in one call"
+        );
+        // Rebuilt in two calls. The entries the posting detail restores are kept, only the dangling entry is dropped
+        assert_ok!(Accounting::rebuild_indices(Origin::ROOT, keys[..1].to_vec()));
+        assert_eq!(Accounting::id_account_posting_id_list((id(1), ACCOUNTS[3])), receivables[..2].to_vec());
+        let mut keys = keys[2..].to_vec();
+        keys.push((id(1), ACCOUNTS[4], Accounting::id_account_posting_id_list((id(1), ACCOUNTS[4]))[0]));
+        assert_ok!(Accounting::rebuild_indices(Origin::ROOT, keys));
+        assert_eq!(Accounting::id_account_posting_id_list((id(1), ACCOUNTS[3])), receivables);
+        assert_eq!(Accounting::accounts_by_id(id(1)), accounts);
+        // A posting without posting detail is not indexed
+        assert_ok!(Accounting::rebuild_indices(Origin::ROOT, vec![(id(1), ACCOUNTS[3], 999)]));
+        assert_eq!(Accounting::id_account_posting_id_list((id(1), ACCOUNTS[3])), receivables);
+    });
+}
+
+#[test]
+fn receivable_exposure_is_checked_against_the_credit_limit() {
+    with_externalities(&mut new_test_ext(), || {
+        System::set_block_number(1);
+        let check = |amount: LedgerBalance| <Accounting as Posting<AccountId, H256, u64, u64>>::check_credit_limit(id(1), id(2), amount);
+        assert_err!(Accounting::set_credit_limit(Origin::signed(id(1)), id(2), Some(-1), true), "Invalid credit limit");
+        assert_ok!(Accounting::set_credit_limit(Origin::signed(id(1)), id(2), Some(1000), true));
+
+        // Invoiced and partly settled
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[3], 800, false), key(1, 2, ACCOUNTS[4], 800, true)]));
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[0], 300, false), key(1, 2, ACCOUNTS[3], -300, true)]));
+        assert_eq!(Accounting::receivable_exposure((id(1), id(2))), 500);
+        assert_ok!(check(500));
+        assert_err!(check(501), "The invoice would exceed the credit limit of the counterparty");
+
+        assert_ok!(Accounting::set_credit_limit(Origin::signed(id(1)), id(2), Some(1000), false));
+        assert_ok!(check(501));
+        assert_eq!(Accounting::credit_exposure(id(1)), vec![(id(2), 500, Some((1000, false)))]);
+
+        // Without a limit the counterparty is listed until its receivables are cleared
+        assert_ok!(Accounting::set_credit_limit(Origin::signed(id(1)), id(2), None, false));
+        assert_eq!(Accounting::credit_exposure(id(1)), vec![(id(2), 500, None)]);
+        assert_ok!(post(vec![key(1, 2, ACCOUNTS[0], 500, false), key(1, 2, ACCOUNTS[3], -500, true)]));
+        assert!(Accounting::credit_exposure(id(1)).is_empty());
+
+        // The recharge of a fee share to the counterparty is not an invoice
+        <PendingFeeShares<mock::Runtime>>::put(vec![(id(1), id(2), 20 as LedgerBalance, H256::repeat_byte(9))]);
+        Accounting::on_finalize(1);
+        assert_eq!(Accounting::balance_by_ledger((id(1), ACCOUNTS[3])), 20);
+        assert_eq!(Accounting::receivable_exposure((id(1), id(2))), 0);
+        assert!(Accounting::credit_exposure(id(1)).is_empty());
+    });
+}
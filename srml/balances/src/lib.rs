@@ -715,6 +715,7 @@ impl<T: Subtrait<I>, I: Instance> accounting::Trait for ElevatedTrait<T, I> {
     type Event = ();
     type CoinAmount = T::Balance;
     type AccountingConversions = ();
+    type OnPeriodClose = ();
 }
 impl<T: Subtrait<I>, I: Instance> timestamp::Trait for ElevatedTrait<T, I> {
     type Moment = T::BlockNumber;
@@ -1220,22 +1221,7 @@ impl<T: Trait<I>, I: Instance> MakePayment<T::AccountId> for Module<T, I> {
         let encoded_len = <T::Balance as As<u64>>::sa(encoded_len as u64);
         let transaction_fee =
             Self::transaction_base_fee() + Self::transaction_byte_fee() * encoded_len;
-            // Account for fees in Totem
-            let who: T::AccountId = transactor.clone(); 
-            let current_balance: T::Balance = Self::free_balance(&who);
-            match <T::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::Balance>>::force_set_gl_account_balance(who.clone(), current_balance) {
-                Ok(_) => (),
-                Err(_e) => {
-                    return Err("An error occured posting reseting XTX account");
-                },
-            }
-
-            match <T::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::Balance>>::account_for_fees(transaction_fee.clone(), who) {
-                Ok(_) => (),
-                Err(_e) => {
-                    return Err("An error occured posting txfees to accounts");
-                },
-            }
+        // The fee is accounted for in Totem by the payment handler of the runtime (see accounting::AccountedPayment)
         let imbalance = Self::withdraw(
             transactor,
             transaction_fee,
@@ -40,22 +40,22 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use parity_codec::{Decode, Encode};
-// use codec::{ Encode, Decode }; // v2
 
 use srml_support::{
     decl_event, decl_module, decl_storage, dispatch::Result, StorageMap,
     StorageValue,
+    traits::{Currency, ReservableCurrency, WithdrawReason},
 };
-//v1
-// use frame_support::{decl_event, decl_error, decl_module, decl_storage, dispatch::DispatchResult, weights::{Weight, DispatchClass}, StorageValue, StorageMap}; // v2
 
-use system::{self, ensure_root, ensure_signed};
-//v1
-// use frame_system::{self}; //v2
+use system::{self, ensure_inherent, ensure_root, ensure_signed};
 
 use rstd::prelude::*;
-//v1
-// use sp_std::prelude::*; //v2
+use sr_primitives::traits::{As, CheckedAdd, CheckedSub, Convert, Hash, StaticLookup, Zero};
+
+use accounting::{Posting, ORIGIN_SYSTEM};
+
+mod mock;
+mod tests;
 
 #[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -63,8 +63,195 @@ pub struct TXKeysT<Hash> {
     pub tx_uid: Hash,
 }
 
-pub trait Trait: system::Trait {
+// Supply management actions recorded in the audit log
+pub type AuditAction = u16; // mint(1), rebalance(2), distribute(3), imported claim(4), distribution claim(5), cancelled claim(6)
+
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct AuditEntry<AccountId, Hash, BlockNumber> {
+    pub actor: Option<AccountId>, // None is the Super User
+    pub action: AuditAction,
+    pub amount: u128,
+    pub counterparty: Option<AccountId>,
+    pub reason: Hash,
+    pub block: BlockNumber,
+}
+
+// Kinds of token movements recorded in the movement history of a holder
+pub type MovementKind = u16; // transfer(1), distribution(2), sale allocation(3), imported claim(4), fee(5), burn(6), dust sweep(7), otc trade(8)
+
+/// Token movement of a holder. The counterparty is the holder itself when tokens are burned.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct HolderMovement<AccountId, Hash, BlockNumber> {
+    pub kind: MovementKind,
+    pub counterparty: AccountId,
+    pub amount: u128,
+    pub incoming: bool,
+    pub reference: Hash,
+    pub block: BlockNumber,
+}
+
+/// Statement by a custodian (for example an exchange) of the tokens it holds on behalf of its customers.
+/// The breakdown per beneficial owner is kept off chain and committed to by its merkle root, so that an
+/// owner can verify that their balance is included without the breakdown being published.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct HoldingsAttestation<Hash, BlockNumber> {
+    pub holdings: u128,
+    pub beneficial_owners: u32,
+    pub breakdown_root: Hash,
+    pub block: BlockNumber,
+}
+
+/// Statement of the token holdings of a holder over a period, for example for tax reporting.
+/// Balances are derived from the memorandum postings, so they only reflect movements made through this module.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct HolderStatement<AccountId, BlockNumber> {
+    pub holder: AccountId,
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+    pub opening_balance: u128,
+    pub closing_balance: u128,
+    pub movements: u32,
+    pub increases: u128,
+    pub decreases: u128,
+    pub issued: BlockNumber,
+}
+
+/// Balance imported by the controller for a holder from a previous network or off-chain records.
+/// The holder is only credited when accepting the claim, so that both parties have confirmed the migrated balance.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ImportClaim<AccountId, Hash, BlockNumber> {
+    pub holder: AccountId,
+    pub amount: u128,
+    pub proof_hash: Hash,
+    pub imported: BlockNumber,
+    pub accepted: Option<BlockNumber>,
+}
+
+/// Distribution published by the controller as the merkle root of the (index, holder, amount) allocations. 
+/// Holders claim their own allocation with a merkle proof, so that a large distribution does not need an extrinsic 
+/// signed by the controller for every holder. The total is set aside from the issued coins until the distribution is closed.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MerkleDistribution<Hash, BlockNumber> {
+    pub root: Hash,
+    pub total: u128,
+    pub claimed: u128,
+    pub expires: BlockNumber,
+    pub reason: Hash,
+    pub closed: bool,
+}
+
+/// Tokens offered over the counter for a price in XTX. The tokens are escrowed from the balance of the seller
+/// until the offer is accepted, cancelled or expires. Without a buyer any holder can accept the offer.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct OtcOffer<AccountId, BlockNumber> {
+    pub seller: AccountId,
+    pub tokens: u128,
+    pub price: u128,
+    pub buyer: Option<AccountId>,
+    pub expires: BlockNumber,
+}
+
+/// Balances registered by their holders for the migration to the next chain. The registrations are committed to by the
+/// merkle root of the (index, holder, amount) leaves, the same leaves as a distribution, so that the set can be exported 
+/// to the genesis of the next chain and every holder can prove their balance. Registrations close once finalized.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MigrationSet<Hash, BlockNumber> {
+    pub registrations: u32,
+    pub total: u128,
+    pub root: Hash,
+    pub finalized: Option<BlockNumber>,
+}
+
+/// Token header for wallets and explorers, read in one call so that the values are from the same block
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FundingInfo<AccountId> {
+    pub transfer_status: bool,
+    pub max_issuance: u128,
+    pub issued: u128,
+    pub unissued: u128,
+    pub total_distributed: u128,
+    pub controller: AccountId,
+}
+
+// Percentage transfer fee in basis points (1/100th of a percent)
+pub type FeeRate = u16;
+
+// Maximum percentage transfer fee (10%)
+const MAX_FEE_RATE: FeeRate = 1_000;
+
+// Maximum length in bytes of a statement endpoint (URL)
+const MAX_ENDPOINT_LENGTH: usize = 256;
+
+// Number of blocks in a transfer velocity period (one day)
+const VELOCITY_PERIOD: u64 = 5760;
+// Maximum number of velocity periods returned at once
+const MAX_VELOCITY_PERIODS: u64 = 100;
+
+// Maximum depth of the merkle proof of a distribution claim
+const MAX_PROOF_LENGTH: usize = 32;
+// Number of claims tracked per word of the claimed bitmap
+const CLAIMED_BITMAP_WORD: u32 = 128;
+// Number of blocks the nominee has to accept the controller handover (three days)
+const CONTROLLER_HANDOVER_WINDOW: u64 = 17280;
+// Movements kept per holder. The oldest movement is overwritten once the history is full
+const MAX_HOLDER_MOVEMENTS: u64 = 1_000;
+// Longest an OTC offer can be open (30 days)
+const MAX_OTC_OFFER_DURATION: u64 = 172800;
+// Maximum number of OTC offers expiring at the same block
+const MAX_OTC_EXPIRIES_PER_BLOCK: usize = 100;
+// Depth of the merkle tree of the migration set (about a million holders)
+const MIGRATION_TREE_DEPTH: u32 = 20;
+
+// Memorandum accounts (statement type 4) reflecting crowdsale token holdings. They do not affect the financial statements.
+const MEMO_TOKEN_HOLDINGS: u64 = 450100010000000; // Memo: Crowdsale token holdings
+const MEMO_TOKEN_HOLDINGS_CONTRA: u64 = 450100020000000; // Memo: Crowdsale token holdings contra
+const XTX_BALANCE: u64 = 110100040000000; // Balance sheet: XTX balance
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type AccountOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::Account;
+type AccountBalanceOf<T> = <<T as Trait>::Accounting as Posting<<T as system::Trait>::AccountId,<T as system::Trait>::Hash,<T as system::Trait>::BlockNumber,<T as accounting::Trait>::CoinAmount>>::LedgerBalance;
+
+/// Voting weight for governance derived from the crowdsale holdings at the latest snapshot.
+/// Can be consumed by governance modules before the token is live as the network currency.
+pub trait VotingWeight<AccountId, BlockNumber> {
+    /// Weight of the identity at the latest snapshot
+    fn voting_weight(who: &AccountId) -> u128;
+    /// Total weight at the latest snapshot
+    fn total_voting_weight() -> u128;
+    /// Block at which the latest snapshot was taken
+    fn snapshot_block() -> Option<BlockNumber>;
+}
+
+/// Submits the statements built by the offchain worker
+pub trait StatementIssuing<AccountId, BlockNumber> {
+    fn submit_statement(id: u64, statement: HolderStatement<AccountId, BlockNumber>);
+}
+
+impl<AccountId, BlockNumber> StatementIssuing<AccountId, BlockNumber> for () {
+    fn submit_statement(_id: u64, _statement: HolderStatement<AccountId, BlockNumber>) {}
+}
+
+pub trait Trait: system::Trait + balances::Trait + accounting::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    type Currency: ReservableCurrency<Self::AccountId>;
+    type FundingConversions: Convert<BalanceOf<Self>, u128> 
+    + Convert<u128, BalanceOf<Self>>
+    + Convert<Self::Balance, BalanceOf<Self>>
+    + Convert<BalanceOf<Self>, Self::CoinAmount>
+    + Convert<u128, i128>
+    + Convert<i128, AccountBalanceOf<Self>>
+    + Convert<u64, AccountOf<Self>>;
+    type Accounting: Posting<Self::AccountId,Self::Hash,Self::BlockNumber,Self::CoinAmount>;
+    type Statements: StatementIssuing<Self::AccountId,Self::BlockNumber>;
     // type Bonsai: Storing<Self::Hash>;
 }
 
@@ -72,6 +259,10 @@ decl_storage! {
     trait Store for Module<T: Trait> as Funding {
         /// Defines if the transfer mechanism is open yet
         TransferStatus get(transfer_status) config(): bool = false;
+        // Block at which transfers are opened automatically
+        ScheduledOpening get(scheduled_opening): Option<T::BlockNumber>;
+        // Block at which transfers are closed automatically
+        ScheduledClosing get(scheduled_closing): Option<T::BlockNumber>;
         /// The Maximum Quantity of Coins that can be minted
         MaxlIssuance get(max_issuance) config(): u128 = 161_803_398_875u128;
         /// Initially 45% of Supply (Reserved Funds).
@@ -80,33 +271,160 @@ decl_storage! {
         Issued get(issued) config(): u128 = 88_991_869_382u128;
         // Controller of funds (Live Accounting Association Account)
         Controller get(controller): T::AccountId;
+        // Controller nominated by the Super User awaiting acceptance 
+        // (nominee, last block to accept, distributions frozen until the handover completes or lapses)
+        NominatedController get(nominated_controller): Option<(T::AccountId, T::BlockNumber, bool)>;
         // The number of coins distributed. It should equal the sum in AccountIdBalances.
         TotalDistributed get(total_distributed): u128;
         // Place to store investors accountids with balances
         AccountIdBalances get(account_id_balances): map T::AccountId => Option<u128>;
         // List of account Ids who have tokens (updated when  token value is 0)
         HoldersAccountIds get(holders_account_ids): Vec<T::AccountId>;
+        // Public sale window (start block, end block)
+        SaleWindow get(sale_window): Option<(T::BlockNumber, T::BlockNumber)>;
+        // Minimum total contributions (in native coin) for the sale to succeed
+        SoftCap get(soft_cap): u128;
+        // Number of tokens allocated per unit of native coin contributed
+        SaleRate get(sale_rate): u128;
+        // Total native coin contributed in the sale
+        TotalContributions get(total_contributions): u128;
+        // Native coin contributed (reserved) and tokens allocated per contributor. 
+        // Allocations are taken from the issued coins and are only distributed if the soft cap is met.
+        Contributions get(contributions): map T::AccountId => Option<(u128, u128)>;
+        // List of contributors with open contributions
+        ContributorAccountIds get(contributor_account_ids): Vec<T::AccountId>;
+        // Refunded contributions and voided allocations when the soft cap was not met
+        Refunds get(refunds): map T::AccountId => Option<(u128, u128)>;
+        // Number of entries in the audit log. Also the id of the next entry
+        AuditLogCount get(audit_log_count): u64;
+        // Append-only log of the supply management actions
+        AuditLog get(audit_log): map u64 => Option<AuditEntry<T::AccountId, T::Hash, T::BlockNumber>>;
+        // Transfer fee charged to the sender. Flat fee plus a percentage of the amount in basis points
+        TransferFee get(transfer_fee): (u128, FeeRate);
+        // Network account credited with transfer fees. If not set the fees are burned
+        FeeAccount get(fee_account): Option<T::AccountId>;
+        // Identities exempt from transfer fees (as sender or receiver)
+        FeeExempt get(fee_exempt): map T::AccountId => bool;
+        // Total coins burned by transfer fees
+        TotalBurned get(total_burned): u128;
+        // Latest governance snapshot (snapshot id, block)
+        Snapshot get(snapshot): Option<(u64, T::BlockNumber)>;
+        // Holdings plus unclaimed sale allocations per identity at a snapshot
+        SnapshotBalances get(snapshot_balances): map (u64, T::AccountId) => u128;
+        // Total holdings at a snapshot
+        SnapshotTotal get(snapshot_total): map u64 => u128;
+        // Balances below this threshold are dust that can be swept to the community account. Zero disables sweeping.
+        DustThreshold get(dust_threshold): u128;
+        // Community account receiving the swept dust
+        CommunityAccount get(community_account): Option<T::AccountId>;
+        // Holders that consent to their dust being swept automatically when a transfer leaves them with dust
+        DustSweepConsent get(dust_sweep_consent): map T::AccountId => bool;
+        // Custodians allowed to publish proof-of-holdings attestations
+        Custodians get(custodians): Vec<T::AccountId>;
+        // Number of attestations published by a custodian
+        HoldingsAttestationCount get(holdings_attestation_count): map T::AccountId => u64;
+        // Attestation history of a custodian by attestation id
+        HoldingsAttestations get(holdings_attestations): map (T::AccountId, u64) => Option<HoldingsAttestation<T::Hash, T::BlockNumber>>;
+        // Fee in tokens paid by a holder requesting a statement. Credited to the fee account, or burned if not set
+        StatementFee get(statement_fee): u128;
+        // Endpoint (URL) registered by a holder to receive its statements
+        StatementEndpoint get(statement_endpoint): map T::AccountId => Option<Vec<u8>>;
+        // Number of statements requested. Also the id of the next request
+        StatementCount get(statement_count): u64;
+        // Statement requests by id (holder, first block, last block)
+        StatementRequests get(statement_requests): map u64 => Option<(T::AccountId, T::BlockNumber, T::BlockNumber)>;
+        // Statement requests waiting for the offchain worker
+        PendingStatements get(pending_statements): Vec<u64>;
+        // Hash of the issued statement by request id. The statement itself is only carried by the extrinsic that issued it
+        StatementHashes get(statement_hash): map u64 => Option<T::Hash>;
+        // Number of imported claims. Also the id of the next claim
+        ImportClaimCount get(import_claim_count): u64;
+        // Imported claims by id. Accepted claims are kept as the audit record of the migration.
+        // Cancelled claims are removed and recorded in the audit log.
+        ImportClaims get(import_claims): map u64 => Option<ImportClaim<T::AccountId, T::Hash, T::BlockNumber>>;
+        // Claim awaiting the acceptance of a holder
+        PendingClaim get(pending_claim): map T::AccountId => Option<u64>;
+        // Part of the balance of a holder locked under vesting until a block (amount, release block)
+        VestingLocks get(vesting_lock): map T::AccountId => Option<(u128, T::BlockNumber)>;
+        // Holders whose vesting lock is released at a block
+        VestingReleases get(vesting_releases): map T::BlockNumber => Vec<T::AccountId>;
+        // Total of the balances locked under vesting
+        TotalLocked get(total_locked): u128;
+        // Holders whose allocation cannot be transferred until a block (advisor and team allocations),
+        // even while transfers are open. Only the Super User can lift the restriction early
+        NonTransferableUntil get(non_transferable_until): map T::AccountId => Option<T::BlockNumber>;
+        // Number of merkle distributions. Also the id of the next distribution
+        DistributionCount get(distribution_count): u64;
+        // Merkle distributions by id
+        Distributions get(distribution): map u64 => Option<MerkleDistribution<T::Hash, T::BlockNumber>>;
+        // Claimed allocations of a distribution, one bit per allocation index (distribution id, word) => bits
+        ClaimedBitmap get(claimed_bitmap): map (u64, u32) => u128;
+        // Number and volume of the transfers per velocity period
+        TransferVelocity get(transfer_velocity): map u64 => (u32, u128);
+        // Number of movements ever recorded for a holder. Also the id of the next movement
+        HolderMovementCount get(holder_movement_count): map T::AccountId => u64;
+        // Movement history of a holder, kept as a ring of MAX_HOLDER_MOVEMENTS (holder, movement id % MAX_HOLDER_MOVEMENTS)
+        HolderMovements get(holder_movement): map (T::AccountId, u64) => Option<HolderMovement<T::AccountId, T::Hash, T::BlockNumber>>;
+        // Number of OTC offers made. Also the id of the next offer
+        OtcOfferCount get(otc_offer_count): u64;
+        // Open OTC offers by id
+        OtcOffers get(otc_offer): map u64 => Option<OtcOffer<T::AccountId, T::BlockNumber>>;
+        // OTC offers expiring at a block
+        OtcOffersExpiring get(otc_offers_expiring): map T::BlockNumber => Vec<u64>;
+        // Total of the tokens escrowed in open OTC offers
+        OtcEscrowed get(otc_escrowed): u128;
+        // Holders registered for the migration to the next chain and the merkle root of their balances
+        MigrationSetInfo get(migration_set): MigrationSet<T::Hash, T::BlockNumber>;
+        // Index in the migration set and frozen balance of a registered holder
+        MigrationRegistrations get(migration_registration): map T::AccountId => Option<(u32, u128)>;
+        // Holder at an index of the migration set
+        MigrationHolders get(migration_holder): map u32 => Option<T::AccountId>;
+        // Left nodes of the migration tree waiting for their right sibling, per level
+        MigrationFrontier get(migration_frontier): map u32 => T::Hash;
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event<T>() = default;
-        /// Super User sets the controller account.
-        fn set_controller_account(origin, controller: T::AccountId) -> Result {
+        fn on_initialize(n: T::BlockNumber) {
+            Self::execute_transfer_schedule(n);
+            Self::release_vesting(n);
+            Self::lapse_controller_nomination(n);
+            Self::expire_otc_offers(n);
+        }
+        /// Super User nominates the controller account. The nominee only becomes the controller by accepting 
+        /// within the handover window, otherwise the nomination lapses. The current controller stays in control 
+        /// until then, unless distributions are frozen for the handover, in which case neither can act as the controller.
+        fn nominate_controller(origin, controller: T::AccountId, freeze: bool) -> Result {
             // Only Sudo
             let _who = ensure_root(origin)?;
 
             // abandon if this is the same controller
-            if controller == Self::controller() {
+            if <Controller<T>>::exists() && controller == Self::controller() {
                 Self::deposit_event(RawEvent::ErrorSameController());
                 return Err("No need to change the same controller");
-            } else {
-                // remove any existing controller
-                <Controller<T>>::take();
-                // insert new controller
-                <Controller<T>>::put(controller);
-            };
+            }
+            let expires: T::BlockNumber = <system::Module<T>>::block_number() + T::BlockNumber::sa(CONTROLLER_HANDOVER_WINDOW);
+            // a new nomination replaces an open one
+            <NominatedController<T>>::put((controller.clone(), expires, freeze));
+            Self::deposit_event(RawEvent::ControllerNominated(controller, expires, freeze));
+
+            Ok(())
+        }
+        /// The nominee accepts the handover and becomes the controller
+        fn accept_controller(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            match Self::nominated_controller() {
+                Some((nominee, expires, _)) if nominee == who && <system::Module<T>>::block_number() <= expires => (),
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorNotNominee());
+                    return Err("There is no open nomination for this account");
+                },
+            }
+            <NominatedController<T>>::kill();
+            <Controller<T>>::put(who.clone());
+            Self::deposit_event(RawEvent::ControllerAccepted(who));
 
             Ok(())
         }
@@ -130,8 +448,44 @@ decl_module! {
 
             Ok(())
         }
+        /// Super User schedules the opening of transfers at a future block, and optionally their closing at a later block.
+        /// The schedule is public so that the opening time is known in advance.
+        fn schedule_transfers(origin, open_at: T::BlockNumber, close_at: Option<T::BlockNumber>) -> Result {
+            let _who = ensure_root(origin)?;
+            
+            if Self::transfer_status() {
+                Self::deposit_event(RawEvent::ErrorTransfersAlreadyOpen());
+                return Err("Transfers are already open");
+            }
+            if !Self::check_setup() {
+                Self::deposit_event(RawEvent::ErrorControllerNotSet());
+                return Err("Cannot open transfers when controller not set.");
+            }
+            let current_block = <system::Module<T>>::block_number();
+            let valid = open_at > current_block && close_at.map_or(true, |c| c > open_at);
+            if !valid {
+                Self::deposit_event(RawEvent::ErrorTransferSchedule());
+                return Err("Transfers must be scheduled in the future and closed after opening");
+            }
+            
+            <ScheduledOpening<T>>::put(open_at);
+            match close_at {
+                Some(c) => <ScheduledClosing<T>>::put(c),
+                None => <ScheduledClosing<T>>::kill(),
+            }
+            Self::deposit_event(RawEvent::TransfersScheduled(open_at, close_at));
+            Ok(())
+        }
+        /// Super User cancels the scheduled opening and closing of transfers
+        fn cancel_transfer_schedule(origin) -> Result {
+            let _who = ensure_root(origin)?;
+            <ScheduledOpening<T>>::kill();
+            <ScheduledClosing<T>>::kill();
+            Self::deposit_event(RawEvent::TransferScheduleCancelled());
+            Ok(())
+        }
         /// Super User can only mint coins if transfers are disabled
-        fn mint_coins(origin, quantity: u128) -> Result {
+        fn mint_coins(origin, quantity: u128, reason: T::Hash) -> Result {
             let _who = ensure_root(origin)?;
 
             let mut supply: u128 = Self::max_issuance();
@@ -167,11 +521,13 @@ decl_module! {
             // Update Max Supply
             <MaxlIssuance<T>>::take();
             <MaxlIssuance<T>>::put(supply);
+            
+            Self::record_audit_entry(None, 1, quantity, None, reason);
 
             Ok(())
         }
         /// Super User can move from unissued to issued coins if transfers are disabled
-        fn rebalance_issued_coins(origin, amount: u128) -> Result {
+        fn rebalance_issued_coins(origin, amount: u128, reason: T::Hash) -> Result {
             let _who = ensure_root(origin)?;
             let mut unissued = Self::unissued();
             let mut issued = Self::issued();
@@ -203,19 +559,16 @@ decl_module! {
             <UnIssued<T>>::put(unissued);
             <Issued<T>>::take();
             <Issued<T>>::put(issued);
+            Self::record_audit_entry(None, 2, amount, None, reason);
             Ok(())
         }
         /// Only the controller can do the initial distribution
-        fn distribute(origin, to: T::AccountId, amount: u128) -> Result {
+        /// The recipient can be given as an account index or an account id
+        fn distribute(origin, to: <T::Lookup as StaticLookup>::Source, amount: u128, reason: T::Hash) -> Result {
             let who = ensure_signed(origin)?;
+            let to = T::Lookup::lookup(to)?;
             // ensure that this is the controller account
-            if who == Self::controller() {
-                // This is the controller and funds can be distributed.
-                ();
-            } else {
-                Self::deposit_event(RawEvent::ErrorNotController());
-                return Err("You are not the controller");
-            }
+            Self::ensure_controller(&who)?;
             // Ensure that the amount to send is less the available funds.
             let mut issued: u128 = Self::issued();
             let total_distributed: u128;
@@ -257,99 +610,944 @@ decl_module! {
                     return Err("Minting Overflowed!");
                 },
             }
+            Self::post_token_memo(vec![(to.clone(), who.clone(), amount, true)], reason, 2)?;
             <Issued<T>>::take();
             <Issued<T>>::put(issued);
             <AccountIdBalances<T>>::insert(&to, new_balance);
             <TotalDistributed<T>>::take();
             <TotalDistributed<T>>::put(total_distributed);
-            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(to));
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(to.clone()));
+            Self::record_audit_entry(Some(who), 3, amount, Some(to), reason);
             Ok(())
         }
         /// This function transfers funds between accounts (only when opened)
-        fn transfer(origin, to: T::AccountId, amount: u128) -> Result {
+        /// The recipient can be given as an account index or an account id
+        fn transfer(origin, to: <T::Lookup as StaticLookup>::Source, amount: u128) -> Result {
             let from = ensure_signed(origin)?;
+            let to = T::Lookup::lookup(to)?;
 
             // are transfers open?
             if !Self::transfer_status() {
                 Self::deposit_event(RawEvent::ErrorTransfersNotOpen());
                 return Err("Transfers not open.");
             } else {
-                let mut new_sender_balance: u128;
-                let mut new_receiver_balance: u128 = 0u128;
+                // The receiver is credited the amount net of the transfer fee
+                let fee: u128 = match Self::calculate_transfer_fee(&from, &to, amount) {
+                    Some(f) if f <= amount => f,
+                    _ => {
+                        Self::deposit_event(RawEvent::ErrorFeeTooLarge());
+                        return Err("Transfer fee is larger than the amount");
+                    },
+                };
+                let net_amount: u128 = amount - fee;
                 // Get the balance of sender
-                match Self::account_id_balances(&from) {
-                    Some(b) => new_sender_balance = b,
+                let new_sender_balance: u128 = match Self::account_id_balances(&from) {
+                    Some(b) => b,
                     None => {
                         Self::deposit_event(RawEvent::ErrorInsufficientFunds());
                         return Err("Insufficient funds to transfer.");
                     },
-                }
-                match Self::account_id_balances(&to) {
-                    Some(b) => new_receiver_balance = b,
-                    None => (),
-                }
+                };
+                let new_receiver_balance: u128 = Self::account_id_balances(&to).unwrap_or(0u128);
                 if new_sender_balance < amount {
                     Self::deposit_event(RawEvent::ErrorInsufficientFunds());
                     return Err("Insufficient funds to transfer.");
-                } else if new_sender_balance > amount{
-                    // reduce balance on sender
-                    match new_sender_balance.checked_sub(amount) {
-                        Some(n) => {
-                            new_sender_balance = n;
-                        },
-                        None => {
-                            Self::deposit_event(RawEvent::ErrorOverflow());
-                            return Err("Reduction Overflowed!");
-                        },
-                    }
-                    // increase balance on receiver
-                    match new_receiver_balance.checked_add(amount) {
-                        Some(n) => {
-                            new_receiver_balance = n;
-                        },
-                        None => {
-                            Self::deposit_event(RawEvent::ErrorOverflow());
-                            return Err("Adding Overflowed!");
-                        },
-                    }
-                    <AccountIdBalances<T>>::take(&from);
+                }
+                if new_sender_balance.saturating_sub(Self::vesting_lock(&from).map_or(0u128, |l| l.0)) < amount {
+                    Self::deposit_event(RawEvent::ErrorVestingLocked());
+                    return Err("Funds are locked under vesting.");
+                }
+                Self::ensure_transferable(&from)?;
+                // reduce balance on sender
+                let new_sender_balance: u128 = match new_sender_balance.checked_sub(amount) {
+                    Some(n) => n,
+                    None => {
+                        Self::deposit_event(RawEvent::ErrorOverflow());
+                        return Err("Reduction Overflowed!");
+                    },
+                };
+                // increase balance on receiver
+                let new_receiver_balance: u128 = match new_receiver_balance.checked_add(net_amount) {
+                    Some(n) => n,
+                    None => {
+                        Self::deposit_event(RawEvent::ErrorOverflow());
+                        return Err("Adding Overflowed!");
+                    },
+                };
+                // Reflect the movement in the memorandum accounts of the parties once all the checks have passed
+                let mut movements = vec![(from.clone(), to.clone(), amount, false), (to.clone(), from.clone(), net_amount, true)];
+                if let Some(fee_account) = Self::fee_account() {
+                    movements.push((fee_account, from.clone(), fee, true));
+                }
+                let transfer_hash: T::Hash = T::Hashing::hash_of(&(from.clone(), to.clone(), amount, <system::Module<T>>::block_number()));
+                Self::post_token_memo(movements, transfer_hash, 1)?;
+                
+                if new_sender_balance > 0u128 {
                     <AccountIdBalances<T>>::insert(&from, new_sender_balance);
-                    <AccountIdBalances<T>>::take(&to);
-                    <AccountIdBalances<T>>::insert(&to, new_receiver_balance);
-                    // Following ensures that only one entry exists in the list of addresses with funds.
-                    <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|t| {t != &to}));
-                    <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(to));
                 } else {
-                    let mut new_receiver_balance: u128 = 0u128;
-                    match Self::account_id_balances(&to) {
-                        Some(b) => new_receiver_balance = b,
-                        None => (),
-                    }
-                    
-                    match new_receiver_balance.checked_add(amount) {
-                        Some(n) => {
-                            new_receiver_balance = n;
-                        },
-                        None => {
-                            Self::deposit_event(RawEvent::ErrorOverflow());
-                            return Err("Adding Overflowed!");
-                        },
-                    }
                     // balance of sender will be 0 remove from table
                     <AccountIdBalances<T>>::remove(&from);
                     <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|f| {f != &from}));
-                    // increase balance on receiver
-                    <AccountIdBalances<T>>::take(&to);
-                    <AccountIdBalances<T>>::insert(&to, new_receiver_balance);
-                    // Following ensures that only one entry exists in the list of addresses with funds.
-                    <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|t| {t != &to}));
-                    <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(to));
-                    
-                };
+                }
+                <AccountIdBalances<T>>::insert(&to, new_receiver_balance);
+                // Following ensures that only one entry exists in the list of addresses with funds.
+                <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|t| {t != &to}));
+                <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(to.clone()));
+                
+                if fee > 0u128 {
+                    Self::collect_transfer_fee(from.clone(), to, net_amount, fee);
+                }
+                Self::record_velocity(amount);
+                // The transfer stands even if the dust cannot be swept
+                if Self::dust_sweep_consent(&from) && Self::is_dust(&from) {
+                    let _ = Self::sweep_dust_for(from);
+                }
             };
             Ok(())
         }
-        
+        /// Holder offers tokens over the counter for a price in XTX, optionally to a single buyer. 
+        /// The tokens are escrowed from the balance of the holder until the offer is accepted, cancelled or expires.
+        /// OTC trades are not subject to the transfer fee.
+        fn offer_otc_trade(origin, tokens: u128, price: u128, buyer: Option<T::AccountId>, expires: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            if !Self::transfer_status() {
+                Self::deposit_event(RawEvent::ErrorTransfersNotOpen());
+                return Err("Transfers not open.");
+            }
+            let current_block: u64 = <system::Module<T>>::block_number().as_();
+            let expiry: u64 = expires.as_();
+            if tokens == 0u128 || price == 0u128 || buyer.as_ref() == Some(&who) || expiry <= current_block || expiry - current_block > MAX_OTC_OFFER_DURATION {
+                Self::deposit_event(RawEvent::ErrorOtcOffer());
+                return Err("Invalid OTC offer");
+            }
+            let balance: u128 = Self::account_id_balances(&who).unwrap_or(0u128);
+            if balance < tokens {
+                Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                return Err("Insufficient funds to offer.");
+            }
+            if balance.saturating_sub(Self::vesting_lock(&who).map_or(0u128, |l| l.0)) < tokens {
+                Self::deposit_event(RawEvent::ErrorVestingLocked());
+                return Err("Funds are locked under vesting.");
+            }
+            Self::ensure_transferable(&who)?;
+            let mut expiring: Vec<u64> = Self::otc_offers_expiring(&expires);
+            if expiring.len() >= MAX_OTC_EXPIRIES_PER_BLOCK {
+                Self::deposit_event(RawEvent::ErrorOtcOffer());
+                return Err("Too many OTC offers expire at this block");
+            }
+            let id: u64 = Self::otc_offer_count();
+            let (next_id, escrowed) = match (id.checked_add(1), Self::otc_escrowed().checked_add(tokens)) {
+                (Some(n), Some(e)) => (n, e),
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("OTC offer count or escrow overflowed");
+                },
+            };
+            
+            if balance > tokens {
+                <AccountIdBalances<T>>::insert(&who, balance - tokens);
+            } else {
+                <AccountIdBalances<T>>::remove(&who);
+                <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| h != &who));
+            }
+            expiring.push(id);
+            <OtcOffersExpiring<T>>::insert(&expires, expiring);
+            <OtcOffers<T>>::insert(id, OtcOffer { seller: who.clone(), tokens, price, buyer, expires });
+            <OtcOfferCount<T>>::put(next_id);
+            <OtcEscrowed<T>>::put(escrowed);
+            
+            Self::deposit_event(RawEvent::OtcOfferCreated(id, who, tokens, price, expires));
+            Ok(())
+        }
+        /// Seller cancels an open OTC offer. The escrowed tokens are returned to the seller.
+        fn cancel_otc_offer(origin, id: u64) -> Result {
+            let who = ensure_signed(origin)?;
+            let offer = match Self::otc_offer(id) {
+                Some(o) => o,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOtcOfferNotFound());
+                    return Err("OTC offer not found");
+                },
+            };
+            if offer.seller != who {
+                Self::deposit_event(RawEvent::ErrorOtcNotAllowed());
+                return Err("Only the seller can cancel the OTC offer");
+            }
+            <OtcOffersExpiring<T>>::mutate(&offer.expires, |expiring| expiring.retain(|o| o != &id));
+            Self::return_otc_escrow(id, offer);
+            Self::deposit_event(RawEvent::OtcOfferCancelled(id));
+            Ok(())
+        }
+        /// Buyer accepts an open OTC offer. The price is paid in XTX to the seller and the escrowed tokens are 
+        /// credited to the buyer in the same call, with the postings for both legs and the XTX transfer fee paid by the buyer. 
+        /// Every step is checked before anything moves, so that either both legs execute or neither does.
+        fn accept_otc_offer(origin, id: u64) -> Result {
+            let who = ensure_signed(origin)?;
+            if !Self::transfer_status() {
+                Self::deposit_event(RawEvent::ErrorTransfersNotOpen());
+                return Err("Transfers not open.");
+            }
+            let offer = match Self::otc_offer(id) {
+                Some(o) => o,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOtcOfferNotFound());
+                    return Err("OTC offer not found");
+                },
+            };
+            if offer.seller == who || offer.buyer.as_ref().map_or(false, |b| b != &who) || <system::Module<T>>::block_number() >= offer.expires {
+                Self::deposit_event(RawEvent::ErrorOtcNotAllowed());
+                return Err("The OTC offer is expired or not open to you");
+            }
+            let price: BalanceOf<T> = <T::FundingConversions as Convert<u128, BalanceOf<T>>>::convert(offer.price);
+            // The fee is charged on top of the price, a seller without a balance is charged the creation fee
+            let seller_free: BalanceOf<T> = T::Currency::free_balance(&offer.seller);
+            let fee: BalanceOf<T> = match seller_free.is_zero() {
+                true => <T::FundingConversions as Convert<T::Balance, BalanceOf<T>>>::convert(<balances::Module<T>>::creation_fee()),
+                false => <T::FundingConversions as Convert<T::Balance, BalanceOf<T>>>::convert(<balances::Module<T>>::transfer_fee()),
+            };
+            let remaining: BalanceOf<T> = match price.checked_add(&fee).and_then(|l| T::Currency::free_balance(&who).checked_sub(&l)) {
+                Some(r) => r,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                    return Err("Insufficient funds to pay the price and the transfer fee.");
+                },
+            };
+            if T::Currency::ensure_can_withdraw(&who, price, WithdrawReason::Transfer, remaining).is_err() 
+                || seller_free.checked_add(&price).is_none() 
+                || (seller_free.is_zero() && price < T::Currency::minimum_balance()) {
+                Self::deposit_event(RawEvent::ErrorOtcPayment());
+                return Err("Error paying the price of the OTC offer");
+            }
+            let buyer_balance: u128 = match Self::account_id_balances(&who).unwrap_or(0u128).checked_add(offer.tokens) {
+                Some(b) => b,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Adding Overflowed!");
+                },
+            };
+            
+            // The postings are checked before they are written, and the payment leg has been checked above
+            let trade_hash: T::Hash = T::Hashing::hash_of(&(id, offer.seller.clone(), who.clone(), <system::Module<T>>::block_number()));
+            let payment = Self::xtx_payment_keys(who.clone(), offer.seller.clone(), offer.price, trade_hash);
+            let movements = vec![(offer.seller.clone(), who.clone(), offer.tokens, false), (who.clone(), offer.seller.clone(), offer.tokens, true)];
+            Self::post_token_memo_with(movements, trade_hash, 8, payment)?;
+            if T::Currency::transfer(&who, &offer.seller, price).is_err() {
+                Self::deposit_event(RawEvent::ErrorOtcPayment());
+                return Err("Error paying the price of the OTC offer");
+            }
+            // The transfer fee is posted with the transaction fees of the buyer when the block is finalised
+            if !fee.is_zero() {
+                let fee_amount: T::CoinAmount = <T::FundingConversions as Convert<BalanceOf<T>, T::CoinAmount>>::convert(fee);
+                <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::account_for_fees(fee_amount, who.clone())?;
+            }
+            
+            <OtcOffers<T>>::remove(id);
+            <OtcOffersExpiring<T>>::mutate(&offer.expires, |expiring| expiring.retain(|o| o != &id));
+            <OtcEscrowed<T>>::mutate(|escrowed| *escrowed = escrowed.saturating_sub(offer.tokens));
+            <AccountIdBalances<T>>::insert(&who, buyer_balance);
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| {
+                holders_account_ids.retain(|h| h != &who);
+                holders_account_ids.push(who.clone());
+            });
+            Self::record_velocity(offer.tokens);
+            
+            Self::deposit_event(RawEvent::OtcTradeExecuted(id, offer.seller, who, offer.tokens, offer.price));
+            Ok(())
+        }
+        /// Super User sets the dust threshold and the community account receiving the swept dust
+        fn set_dust_sweep(origin, threshold: u128, community_account: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            <DustThreshold<T>>::put(threshold);
+            <CommunityAccount<T>>::put(community_account.clone());
+            Self::deposit_event(RawEvent::DustSweepSet(threshold, community_account));
+            Ok(())
+        }
+        /// Holder donates a dust balance to the community account in one call
+        fn sweep_dust(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            if !Self::transfer_status() {
+                Self::deposit_event(RawEvent::ErrorTransfersNotOpen());
+                return Err("Transfers not open.");
+            }
+            Self::ensure_transferable(&who)?;
+            Self::sweep_dust_for(who)?;
+            Ok(())
+        }
+        /// Holder consents (or withdraws consent) to dust being swept automatically after a transfer
+        fn set_dust_sweep_consent(origin, consent: bool) -> Result {
+            let who = ensure_signed(origin)?;
+            if consent {
+                <DustSweepConsent<T>>::insert(&who, true);
+            } else {
+                <DustSweepConsent<T>>::remove(&who);
+            }
+            Ok(())
+        }
+        /// Super User sets the transfer fee (flat fee plus rate in basis points) and the account credited with the fees.
+        /// Without a fee account the fees are burned.
+        fn set_transfer_fee(origin, flat_fee: u128, rate: FeeRate, fee_account: Option<T::AccountId>) -> Result {
+            let _who = ensure_root(origin)?;
+            if rate > MAX_FEE_RATE {
+                Self::deposit_event(RawEvent::ErrorFeeRate());
+                return Err("Fee rate is too high");
+            }
+            <TransferFee<T>>::put((flat_fee, rate));
+            match fee_account {
+                Some(a) => <FeeAccount<T>>::put(a),
+                None => <FeeAccount<T>>::kill(),
+            }
+            Self::deposit_event(RawEvent::TransferFeeSet(flat_fee, rate));
+            Ok(())
+        }
+        /// Super User exempts an identity from transfer fees (or removes the exemption)
+        fn set_fee_exempt(origin, who: T::AccountId, exempt: bool) -> Result {
+            let _who = ensure_root(origin)?;
+            if exempt {
+                <FeeExempt<T>>::insert(&who, true);
+            } else {
+                <FeeExempt<T>>::remove(&who);
+            }
+            Ok(())
+        }
+        /// Super User takes a snapshot of the holdings (including unclaimed sale allocations) used as governance voting weight.
+        fn take_snapshot(origin) -> Result {
+            let _who = ensure_root(origin)?;
+            let id: u64 = match Self::snapshot() {
+                Some((id, _)) => id.saturating_add(1),
+                None => 0u64,
+            };
+            let mut total: u128 = 0u128;
+            for holder in Self::holders_account_ids().into_iter() {
+                if !<SnapshotBalances<T>>::exists((id, holder.clone())) {
+                    let balance: u128 = Self::account_id_balances(&holder).unwrap_or(0u128);
+                    total = total.saturating_add(balance);
+                    <SnapshotBalances<T>>::insert((id, holder), balance);
+                }
+            }
+            for contributor in Self::contributor_account_ids().into_iter() {
+                if let Some((_, allocation)) = Self::contributions(&contributor) {
+                    total = total.saturating_add(allocation);
+                    <SnapshotBalances<T>>::mutate((id, contributor), |balance| *balance = balance.saturating_add(allocation));
+                }
+            }
+            let current_block = <system::Module<T>>::block_number();
+            <SnapshotTotal<T>>::insert(id, total);
+            <Snapshot<T>>::put((id, current_block));
+            Self::deposit_event(RawEvent::SnapshotTaken(id, current_block, total));
+            Ok(())
+        }
+        /// Super User allows an identity to publish proof-of-holdings attestations as a custodian
+        fn add_custodian(origin, custodian: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            <Custodians<T>>::mutate(|custodians| {
+                if !custodians.contains(&custodian) {
+                    custodians.push(custodian.clone());
+                }
+            });
+            Self::deposit_event(RawEvent::CustodianAdded(custodian));
+            Ok(())
+        }
+        /// Super User removes a custodian. The attestations already published remain in the history.
+        fn remove_custodian(origin, custodian: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            <Custodians<T>>::mutate(|custodians| custodians.retain(|c| c != &custodian));
+            Self::deposit_event(RawEvent::CustodianRemoved(custodian));
+            Ok(())
+        }
+        /// Custodian publishes the quantity of tokens it holds on behalf of a number of beneficial owners, 
+        /// together with the merkle root of the breakdown per owner.
+        fn attest_holdings(origin, holdings: u128, beneficial_owners: u32, breakdown_root: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            if !Self::custodians().contains(&who) {
+                Self::deposit_event(RawEvent::ErrorNotCustodian());
+                return Err("Not a custodian");
+            }
+            // holdings without owners (or owners without holdings) cannot be broken down
+            if (holdings == 0u128) != (beneficial_owners == 0u32) {
+                Self::deposit_event(RawEvent::ErrorHoldingsAttestation());
+                return Err("Holdings and beneficial owners must both be zero or both be set");
+            }
+            let id: u64 = Self::holdings_attestation_count(&who);
+            let attestation = HoldingsAttestation {
+                holdings: holdings,
+                beneficial_owners: beneficial_owners,
+                breakdown_root: breakdown_root,
+                block: <system::Module<T>>::block_number(),
+            };
+            <HoldingsAttestations<T>>::insert((who.clone(), id), attestation);
+            <HoldingsAttestationCount<T>>::insert(&who, id.saturating_add(1));
+            Self::deposit_event(RawEvent::HoldingsAttested(who, id, holdings, beneficial_owners));
+            Ok(())
+        }
+        /// Super User sets up the public sale. Cannot be changed once contributions have been made.
+        fn set_sale(origin, start: T::BlockNumber, end: T::BlockNumber, soft_cap: u128, rate: u128) -> Result {
+            let _who = ensure_root(origin)?;
+            
+            if Self::total_contributions() > 0u128 {
+                Self::deposit_event(RawEvent::ErrorSaleStarted());
+                return Err("Contributions already made. Cannot change the sale.");
+            }
+            if end <= start || rate == 0u128 {
+                Self::deposit_event(RawEvent::ErrorSaleParameters());
+                return Err("Invalid sale parameters.");
+            }
+            
+            <SaleWindow<T>>::put((start, end));
+            <SoftCap<T>>::put(soft_cap);
+            <SaleRate<T>>::put(rate);
+            
+            Self::deposit_event(RawEvent::SaleSet(start, end, soft_cap, rate));
+            Ok(())
+        }
+        /// Contribute native coin to the public sale. The contribution is reserved until the sale ends 
+        /// and tokens are allocated at the sale rate.
+        fn contribute(origin, #[compact] amount: BalanceOf<T>) -> Result {
+            let who = ensure_signed(origin)?;
+            let current_block = <system::Module<T>>::block_number();
+            let amount: u128 = <T::FundingConversions as Convert<BalanceOf<T>, u128>>::convert(amount);
+            
+            match Self::sale_window() {
+                Some((start, end)) => {
+                    if current_block < start || current_block > end {
+                        Self::deposit_event(RawEvent::ErrorSaleNotOpen());
+                        return Err("Sale is not open.");
+                    }
+                },
+                None => {
+                    Self::deposit_event(RawEvent::ErrorSaleNotOpen());
+                    return Err("Sale is not open.");
+                },
+            }
+            if amount == 0u128 {
+                Self::deposit_event(RawEvent::ErrorSaleParameters());
+                return Err("Contribution cannot be zero.");
+            }
+            
+            let allocation: u128;
+            match amount.checked_mul(Self::sale_rate()) {
+                Some(a) => allocation = a,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Allocation Overflowed!");
+                },
+            }
+            // Allocation is taken from the issued coins
+            let issued: u128 = Self::issued();
+            if allocation > issued {
+                Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                return Err("Insufficient funds for allocation.");
+            }
+            
+            let mut contribution: (u128, u128) = Self::contributions(&who).unwrap_or((0u128, 0u128));
+            let total_contributions: u128;
+            match (contribution.0.checked_add(amount), contribution.1.checked_add(allocation), Self::total_contributions().checked_add(amount)) {
+                (Some(c), Some(a), Some(t)) => {
+                    contribution = (c, a);
+                    total_contributions = t;
+                },
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Contribution Overflowed!");
+                },
+            }
+            
+            // Reserve the native coin in the contributor's account
+            T::Currency::reserve(&who, <T::FundingConversions as Convert<u128, BalanceOf<T>>>::convert(amount))?;
+            
+            <Issued<T>>::put(issued - allocation);
+            <TotalContributions<T>>::put(total_contributions);
+            <Contributions<T>>::insert(&who, contribution);
+            <ContributorAccountIds<T>>::mutate(|contributor_account_ids| contributor_account_ids.retain(|c| {c != &who}));
+            <ContributorAccountIds<T>>::mutate(|contributor_account_ids| contributor_account_ids.push(who.clone()));
+            
+            Self::deposit_event(RawEvent::Contributed(who, amount, allocation));
+            Ok(())
+        }
+        /// After the sale has ended below the soft cap contributors recover their contribution 
+        /// and the token allocation is returned to the issued coins.
+        fn claim_refund(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            
+            if !Self::sale_ended() {
+                Self::deposit_event(RawEvent::ErrorSaleNotEnded());
+                return Err("Sale has not ended.");
+            }
+            if Self::total_contributions() >= Self::soft_cap() {
+                Self::deposit_event(RawEvent::ErrorSoftCapReached());
+                return Err("Soft cap reached. No refunds.");
+            }
+            let contribution: (u128, u128) = match Self::contributions(&who) {
+                Some(c) => c,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoContribution());
+                    return Err("No contribution to refund.");
+                },
+            };
+            let issued: u128;
+            let total_contributions: u128;
+            match (Self::issued().checked_add(contribution.1), Self::total_contributions().checked_sub(contribution.0)) {
+                (Some(i), Some(t)) => {
+                    issued = i;
+                    total_contributions = t;
+                },
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            }
+            
+            T::Currency::unreserve(&who, <T::FundingConversions as Convert<u128, BalanceOf<T>>>::convert(contribution.0));
+            
+            // void the allocation
+            <Issued<T>>::put(issued);
+            <TotalContributions<T>>::put(total_contributions);
+            <Contributions<T>>::remove(&who);
+            <ContributorAccountIds<T>>::mutate(|contributor_account_ids| contributor_account_ids.retain(|c| {c != &who}));
+            <Refunds<T>>::insert(&who, contribution);
+            
+            Self::deposit_event(RawEvent::Refunded(who, contribution.0, contribution.1));
+            Ok(())
+        }
+        /// After the sale has ended at or above the soft cap contributors receive their token allocation
+        /// and the contribution is paid to the controller.
+        fn claim_allocation(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            
+            if !Self::sale_ended() {
+                Self::deposit_event(RawEvent::ErrorSaleNotEnded());
+                return Err("Sale has not ended.");
+            }
+            if Self::total_contributions() < Self::soft_cap() {
+                Self::deposit_event(RawEvent::ErrorSoftCapNotReached());
+                return Err("Soft cap not reached. Claim a refund.");
+            }
+            let contribution: (u128, u128) = match Self::contributions(&who) {
+                Some(c) => c,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoContribution());
+                    return Err("No contribution to claim.");
+                },
+            };
+            let new_balance: u128;
+            let total_distributed: u128;
+            match (Self::account_id_balances(&who).unwrap_or(0u128).checked_add(contribution.1), Self::total_distributed().checked_add(contribution.1)) {
+                (Some(b), Some(t)) => {
+                    new_balance = b;
+                    total_distributed = t;
+                },
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            }
+            
+            // The contribution must still be reserved in full, so that it is paid to the controller in full or not at all
+            let contributed: BalanceOf<T> = <T::FundingConversions as Convert<u128, BalanceOf<T>>>::convert(contribution.0);
+            if T::Currency::reserved_balance(&who) < contributed {
+                Self::deposit_event(RawEvent::ErrorContributionNotReserved());
+                return Err("The contribution is no longer reserved.");
+            }
+            
+            // Pay the reserved contribution to the controller
+            match T::Currency::repatriate_reserved(&who, &Self::controller(), contributed) {
+                Ok(leftover) if leftover.is_zero() => (),
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorContributionNotReserved());
+                    return Err("The contribution could not be paid to the controller.");
+                },
+            }
+            
+            let allocation_hash: T::Hash = T::Hashing::hash_of(&(who.clone(), contribution));
+            Self::post_token_memo(vec![(who.clone(), Self::controller(), contribution.1, true)], allocation_hash, 3)?;
+            
+            <AccountIdBalances<T>>::insert(&who, new_balance);
+            <TotalDistributed<T>>::put(total_distributed);
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| {h != &who}));
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(who.clone()));
+            <Contributions<T>>::remove(&who);
+            <ContributorAccountIds<T>>::mutate(|contributor_account_ids| contributor_account_ids.retain(|c| {c != &who}));
+            
+            Self::deposit_event(RawEvent::AllocationClaimed(who, contribution.0, contribution.1));
+            Ok(())
+        }
+        /// The controller imports the balance of a holder from a previous network or off-chain records.
+        /// The proof hash commits to the source record. The holder must accept the claim before it is credited.
+        fn import_claim(origin, holder: T::AccountId, amount: u128, proof_hash: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            if Self::pending_claim(&holder).is_some() {
+                Self::deposit_event(RawEvent::ErrorClaimPending());
+                return Err("The holder already has a pending claim");
+            }
+            if amount == 0u128 || amount > Self::issued() {
+                Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                return Err("Claim amount must be greater than zero and within the issued coins");
+            }
+            let id: u64 = Self::import_claim_count();
+            let claim = ImportClaim {
+                holder: holder.clone(),
+                amount: amount,
+                proof_hash: proof_hash,
+                imported: <system::Module<T>>::block_number(),
+                accepted: None,
+            };
+            <ImportClaims<T>>::insert(id, claim);
+            <ImportClaimCount<T>>::put(id.saturating_add(1));
+            <PendingClaim<T>>::insert(&holder, id);
+            
+            Self::deposit_event(RawEvent::ClaimImported(id, holder, amount, proof_hash));
+            Ok(())
+        }
+        /// The holder accepts its pending claim, which is credited from the issued coins
+        fn accept_claim(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            let (id, mut claim) = match Self::pending_claim(&who).and_then(|id| Self::import_claims(id).map(|c| (id, c))) {
+                Some(c) => c,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoPendingClaim());
+                    return Err("No pending claim");
+                },
+            };
+            let issued: u128;
+            let new_balance: u128;
+            let total_distributed: u128;
+            match (
+                Self::issued().checked_sub(claim.amount),
+                Self::account_id_balances(&who).unwrap_or(0u128).checked_add(claim.amount),
+                Self::total_distributed().checked_add(claim.amount),
+            ) {
+                (Some(i), Some(b), Some(t)) => {
+                    issued = i;
+                    new_balance = b;
+                    total_distributed = t;
+                },
+                (None, _, _) => {
+                    Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                    return Err("Insufficient funds to credit the claim");
+                },
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            }
+            let controller = Self::controller();
+            Self::post_token_memo(vec![(who.clone(), controller.clone(), claim.amount, true)], claim.proof_hash, 4)?;
+            
+            <Issued<T>>::put(issued);
+            <AccountIdBalances<T>>::insert(&who, new_balance);
+            <TotalDistributed<T>>::put(total_distributed);
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| {h != &who}));
+            <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(who.clone()));
+            claim.accepted = Some(<system::Module<T>>::block_number());
+            let (amount, proof_hash) = (claim.amount, claim.proof_hash);
+            <ImportClaims<T>>::insert(id, claim);
+            <PendingClaim<T>>::remove(&who);
+            
+            Self::record_audit_entry(Some(who.clone()), 4, amount, Some(controller), proof_hash);
+            Self::deposit_event(RawEvent::ClaimAccepted(id, who, amount));
+            Ok(())
+        }
+        /// The controller cancels the pending claim of a holder, for example one imported with a wrong amount.
+        /// The claim is removed and the cancellation is recorded in the audit log, after which a new claim can be imported.
+        fn cancel_claim(origin, holder: T::AccountId) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            let (id, claim) = match Self::pending_claim(&holder).and_then(|id| Self::import_claims(id).map(|c| (id, c))) {
+                Some(c) => c,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorNoPendingClaim());
+                    return Err("No pending claim");
+                },
+            };
+            <ImportClaims<T>>::remove(id);
+            <PendingClaim<T>>::remove(&holder);
+            
+            Self::record_audit_entry(Some(who), 6, claim.amount, Some(holder.clone()), claim.proof_hash);
+            Self::deposit_event(RawEvent::ClaimCancelled(id, holder, claim.amount));
+            Ok(())
+        }
+        /// The controller publishes a distribution as the merkle root of the allocations and the total to be distributed. 
+        /// The total is set aside from the issued coins. Holders claim their allocation until the expiry block.
+        fn publish_distribution(origin, root: T::Hash, total: u128, expires: T::BlockNumber, reason: T::Hash) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            if expires <= <system::Module<T>>::block_number() {
+                Self::deposit_event(RawEvent::ErrorDistribution());
+                return Err("The distribution must expire in the future");
+            }
+            let issued: u128 = match Self::issued().checked_sub(total) {
+                Some(i) if total > 0u128 => i,
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                    return Err("Distribution total must be greater than zero and within the issued coins");
+                },
+            };
+            let id: u64 = Self::distribution_count();
+            let distribution = MerkleDistribution {
+                root: root,
+                total: total,
+                claimed: 0u128,
+                expires: expires,
+                reason: reason,
+                closed: false,
+            };
+            <Issued<T>>::put(issued);
+            <Distributions<T>>::insert(id, distribution);
+            <DistributionCount<T>>::put(id.saturating_add(1));
+            
+            Self::deposit_event(RawEvent::DistributionPublished(id, root, total, expires));
+            Ok(())
+        }
+        /// The holder claims its allocation in a distribution with the merkle proof of the (index, holder, amount) leaf
+        fn claim(origin, distribution: u64, index: u32, amount: u128, proof: Vec<T::Hash>) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::claim_distribution(who, distribution, index, amount, proof)
+        }
+        /// Closes a distribution after it has expired and returns the unclaimed coins to the issued coins. 
+        /// The controller can close a distribution at any time.
+        fn close_distribution(origin, distribution: u64) -> Result {
+            let who = ensure_signed(origin)?;
+            let mut d = match Self::distribution(distribution) {
+                Some(d) if !d.closed => d,
+                _ => {
+                    Self::deposit_event(RawEvent::ErrorDistribution());
+                    return Err("Unknown or closed distribution");
+                },
+            };
+            if !Self::is_acting_controller(&who) && <system::Module<T>>::block_number() < d.expires {
+                Self::deposit_event(RawEvent::ErrorDistribution());
+                return Err("The distribution has not expired");
+            }
+            let unclaimed: u128 = d.total.saturating_sub(d.claimed);
+            let issued: u128 = match Self::issued().checked_add(unclaimed) {
+                Some(i) => i,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            };
+            d.closed = true;
+            <Issued<T>>::put(issued);
+            <Distributions<T>>::insert(distribution, d);
+            
+            Self::deposit_event(RawEvent::DistributionClosed(distribution, unclaimed));
+            Ok(())
+        }
+        /// The controller locks part of the balance of a holder under vesting until a block. 
+        /// The locked part cannot be transferred and is not part of the circulating supply.
+        fn lock_vesting(origin, holder: T::AccountId, amount: u128, until: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            if Self::vesting_lock(&holder).is_some() {
+                Self::deposit_event(RawEvent::ErrorVestingLocked());
+                return Err("The holder already has a vesting lock");
+            }
+            if amount == 0u128 || amount > Self::account_id_balances(&holder).unwrap_or(0u128) || until <= <system::Module<T>>::block_number() {
+                Self::deposit_event(RawEvent::ErrorVestingLock());
+                return Err("Vesting lock must be within the balance and end in the future");
+            }
+            let total_locked: u128 = match Self::total_locked().checked_add(amount) {
+                Some(t) => t,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            };
+            <VestingLocks<T>>::insert(&holder, (amount, until));
+            <VestingReleases<T>>::mutate(until, |holders| holders.push(holder.clone()));
+            <TotalLocked<T>>::put(total_locked);
+            
+            Self::deposit_event(RawEvent::VestingLocked(holder, amount, until));
+            Ok(())
+        }
+        /// The controller makes the balance of a holder non-transferable until a block (the cliff of an advisor or team 
+        /// allocation), regardless of whether transfers are open. The restriction can be extended but not shortened.
+        fn set_non_transferable(origin, holder: T::AccountId, until: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            if until <= <system::Module<T>>::block_number() || Self::non_transferable_until(&holder).map_or(false, |b| until < b) {
+                Self::deposit_event(RawEvent::ErrorNonTransferableUntil());
+                return Err("The restriction must end in the future and cannot be shortened");
+            }
+            <NonTransferableUntil<T>>::insert(&holder, until);
+            Self::deposit_event(RawEvent::NonTransferableSet(holder, until));
+            Ok(())
+        }
+        /// Super User lifts the transfer restriction of a holder before the cliff
+        fn remove_non_transferable(origin, holder: T::AccountId) -> Result {
+            let _who = ensure_root(origin)?;
+            if <NonTransferableUntil<T>>::take(&holder).is_some() {
+                Self::deposit_event(RawEvent::NonTransferableRemoved(holder));
+            }
+            Ok(())
+        }
+        /// Holder registers its balance for the migration to the next chain. The balance is frozen on this chain and added 
+        /// to the migration set. Tokens received after the registration are frozen as well but are not migrated.
+        fn register_for_migration(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            let mut set = Self::migration_set();
+            if set.finalized.is_some() {
+                Self::deposit_event(RawEvent::ErrorMigrationClosed());
+                return Err("Registrations for the migration are closed");
+            }
+            if Self::migration_registration(&who).is_some() {
+                Self::deposit_event(RawEvent::ErrorMigrationRegistered());
+                return Err("Already registered for the migration");
+            }
+            let amount: u128 = Self::account_id_balances(&who).unwrap_or(0u128);
+            if amount == 0u128 || Self::vesting_lock(&who).is_some() {
+                Self::deposit_event(RawEvent::ErrorMigrationBalance());
+                return Err("Only an unlocked balance can be registered for the migration");
+            }
+            Self::ensure_transferable(&who)?;
+            let index: u32 = set.registrations;
+            if (index as u64) >> MIGRATION_TREE_DEPTH != 0 {
+                Self::deposit_event(RawEvent::ErrorMigrationSetFull());
+                return Err("The migration set is full");
+            }
+            let total: u128 = match set.total.checked_add(amount) {
+                Some(t) => t,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Overflow error");
+                },
+            };
+            let leaf: T::Hash = T::Hashing::hash_of(&(index, who.clone(), amount));
+            Self::insert_migration_leaf(leaf, index);
+            set.registrations = index + 1;
+            set.total = total;
+            set.root = Self::migration_root(set.registrations);
+            <MigrationSetInfo<T>>::put(set);
+            <MigrationRegistrations<T>>::insert(&who, (index, amount));
+            <MigrationHolders<T>>::insert(index, who.clone());
+            
+            Self::deposit_event(RawEvent::RegisteredForMigration(who, index, amount));
+            Ok(())
+        }
+        /// The controller finalizes the migration set. Registrations close and the merkle root of the set is the one to 
+        /// take into the genesis of the next chain.
+        fn finalize_migration(origin) -> Result {
+            let who = ensure_signed(origin)?;
+            Self::ensure_controller(&who)?;
+            let mut set = Self::migration_set();
+            if set.finalized.is_some() {
+                Self::deposit_event(RawEvent::ErrorMigrationClosed());
+                return Err("Registrations for the migration are closed");
+            }
+            set.finalized = Some(<system::Module<T>>::block_number());
+            let (registrations, total, root) = (set.registrations, set.total, set.root);
+            <MigrationSetInfo<T>>::put(set);
+            
+            Self::deposit_event(RawEvent::MigrationFinalized(registrations, total, root));
+            Ok(())
+        }
+        /// Super User sets the fee in tokens paid by a holder requesting a statement
+        fn set_statement_fee(origin, fee: u128) -> Result {
+            let _who = ensure_root(origin)?;
+            <StatementFee<T>>::put(fee);
+            Self::deposit_event(RawEvent::StatementFeeSet(fee));
+            Ok(())
+        }
+        /// Registers (or with an empty endpoint removes) the endpoint at which the holder receives its statements
+        fn set_statement_endpoint(origin, endpoint: Vec<u8>) -> Result {
+            let who = ensure_signed(origin)?;
+            if endpoint.len() > MAX_ENDPOINT_LENGTH {
+                Self::deposit_event(RawEvent::ErrorEndpointTooLong());
+                return Err("Endpoint is too long");
+            }
+            if endpoint.is_empty() {
+                <StatementEndpoint<T>>::remove(&who);
+            } else {
+                <StatementEndpoint<T>>::insert(&who, endpoint);
+            }
+            Ok(())
+        }
+        /// Holder requests a statement of its holdings between two blocks (inclusive) and pays the statement fee.
+        /// The period must have ended. The statement is built by the offchain worker.
+        fn request_statement(origin, from: T::BlockNumber, to: T::BlockNumber) -> Result {
+            let who = ensure_signed(origin)?;
+            if Self::statement_endpoint(&who).is_none() {
+                Self::deposit_event(RawEvent::ErrorNoStatementEndpoint());
+                return Err("No statement endpoint registered");
+            }
+            if from > to || to >= <system::Module<T>>::block_number() {
+                Self::deposit_event(RawEvent::ErrorStatementPeriod());
+                return Err("The statement period must have ended");
+            }
+            let fee: u128 = Self::statement_fee();
+            let balance: u128 = Self::account_id_balances(&who).unwrap_or(0u128);
+            if balance < fee {
+                Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                return Err("Insufficient funds to pay the statement fee.");
+            }
+            let id: u64 = Self::statement_count();
+            let next_id: u64 = match id.checked_add(1) {
+                Some(n) => n,
+                None => {
+                    Self::deposit_event(RawEvent::ErrorOverflow());
+                    return Err("Statement count overflowed");
+                },
+            };
+            if fee > 0u128 {
+                // Without a fee account the fee is burned
+                let mut movements = vec![(who.clone(), who.clone(), fee, false)];
+                let mut kind: MovementKind = 6;
+                if let Some(fee_account) = Self::fee_account() {
+                    movements = vec![(who.clone(), fee_account.clone(), fee, false), (fee_account, who.clone(), fee, true)];
+                    kind = 5;
+                }
+                let fee_hash: T::Hash = T::Hashing::hash_of(&(who.clone(), id, fee));
+                Self::post_token_memo(movements, fee_hash, kind)?;
+                
+                if balance > fee {
+                    <AccountIdBalances<T>>::insert(&who, balance - fee);
+                } else {
+                    <AccountIdBalances<T>>::remove(&who);
+                    <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| h != &who));
+                }
+                Self::credit_fee(fee);
+            }
+            <StatementRequests<T>>::insert(id, (who.clone(), from, to));
+            <PendingStatements<T>>::mutate(|pending| pending.push(id));
+            <StatementCount<T>>::put(next_id);
+            Self::deposit_event(RawEvent::StatementRequested(id, who, from, to, fee));
+            Ok(())
+        }
+        /// Issues a requested statement built by the offchain worker. Only the hash of the statement is stored, 
+        /// the statement is delivered to the endpoint in this extrinsic and authenticated by the stored hash.
+        /// Submitted as an inherent. The statement must answer the request and have been built after the period.
+        fn record_statement(origin, id: u64, statement: HolderStatement<T::AccountId, T::BlockNumber>) -> Result {
+            ensure_inherent(origin)?;
+            if <StatementHashes<T>>::exists(id) || !Self::pending_statements().contains(&id) {
+                return Err("No statement is pending for this request");
+            }
+            let (holder, from, to) = match Self::statement_requests(id) {
+                Some(r) => r,
+                None => return Err("No statement is pending for this request"),
+            };
+            if statement.holder != holder || statement.from != from || statement.to != to 
+            || statement.issued <= to || statement.issued > <system::Module<T>>::block_number() {
+                return Err("The statement does not answer the request");
+            }
+            let statement_hash: T::Hash = T::Hashing::hash_of(&statement);
+            <StatementHashes<T>>::insert(id, statement_hash);
+            <PendingStatements<T>>::mutate(|pending| pending.retain(|p| p != &id));
+            Self::deposit_event(RawEvent::StatementIssued(id, holder, statement_hash));
+            Ok(())
+        }
+        /// Builds the pending statements from the memorandum postings as of the imported block and submits them.
+        /// The offchain API of this node can neither sign nor post to an endpoint, so the statement is delivered in 
+        /// the extrinsic issuing it and authenticated by the hash recorded on-chain.
+        fn offchain_worker(n: T::BlockNumber) {
+            for id in Self::pending_statements().into_iter() {
+                if <StatementHashes<T>>::exists(id) {
+                    continue;
+                }
+                if let Some((holder, from, to)) = Self::statement_requests(id) {
+                    T::Statements::submit_statement(id, Self::holder_statement(holder, from, to, n));
+                }
+            }
+        }
     }
 }
 
@@ -367,16 +1565,582 @@ impl<T: Trait> Module<T> {
         };
         return answer;
     }
+    // checks that the account can act as the controller
+    fn ensure_controller(who: &T::AccountId) -> Result {
+        if !<Controller<T>>::exists() || *who != Self::controller() {
+            Self::deposit_event(RawEvent::ErrorNotController());
+            return Err("You are not the controller");
+        }
+        if !Self::is_acting_controller(who) {
+            Self::deposit_event(RawEvent::ErrorControllerHandover());
+            return Err("Distributions are frozen during the controller handover");
+        }
+        Ok(())
+    }
+    // the controller, unless distributions are frozen for a controller handover
+    fn is_acting_controller(who: &T::AccountId) -> bool {
+        <Controller<T>>::exists() && *who == Self::controller() && !Self::nominated_controller().map_or(false, |n| n.2)
+    }
+    // drops the controller nomination once the nominee can no longer accept it
+    fn lapse_controller_nomination(n: T::BlockNumber) {
+        if let Some((nominee, expires, _)) = Self::nominated_controller() {
+            if n > expires {
+                <NominatedController<T>>::kill();
+                Self::deposit_event(RawEvent::ControllerNominationLapsed(nominee));
+            }
+        }
+    }
+    // opens or closes transfers when the scheduled block is reached
+    fn execute_transfer_schedule(n: T::BlockNumber) {
+        if let Some(open_at) = Self::scheduled_opening() {
+            if n >= open_at {
+                <ScheduledOpening<T>>::kill();
+                match Self::check_setup() {
+                    true => {
+                        <TransferStatus<T>>::put(true);
+                        Self::deposit_event(RawEvent::TransfersOpened(n));
+                    },
+                    false => {
+                        // the closing is dropped as well, as the transfers were never opened
+                        <ScheduledClosing<T>>::kill();
+                        Self::deposit_event(RawEvent::ErrorControllerNotSet());
+                    },
+                }
+            }
+        }
+        if let Some(close_at) = Self::scheduled_closing() {
+            if n >= close_at {
+                <ScheduledClosing<T>>::kill();
+                <TransferStatus<T>>::put(false);
+                Self::deposit_event(RawEvent::TransfersClosed(n));
+            }
+        }
+    }
+    // append an entry to the audit log
+    fn record_audit_entry(actor: Option<T::AccountId>, action: AuditAction, amount: u128, counterparty: Option<T::AccountId>, reason: T::Hash) {
+        let id: u64 = Self::audit_log_count();
+        let entry = AuditEntry {
+            actor: actor,
+            action: action,
+            amount: amount,
+            counterparty: counterparty,
+            reason: reason,
+            block: <system::Module<T>>::block_number(),
+        };
+        <AuditLog<T>>::insert(id, entry);
+        <AuditLogCount<T>>::put(id.saturating_add(1));
+        Self::deposit_event(RawEvent::AuditLogged(id, action, amount));
+    }
+    /// Credits the allocation of a holder in a distribution once the leaf has been proven against the merkle root
+    fn claim_distribution(who: T::AccountId, id: u64, index: u32, amount: u128, proof: Vec<T::Hash>) -> Result {
+        let mut d = match Self::distribution(id) {
+            Some(d) if !d.closed && <system::Module<T>>::block_number() < d.expires => d,
+            _ => {
+                Self::deposit_event(RawEvent::ErrorDistribution());
+                return Err("The distribution is closed or has expired");
+            },
+        };
+        if Self::is_claimed(id, index) {
+            Self::deposit_event(RawEvent::ErrorAlreadyClaimed());
+            return Err("The allocation has already been claimed");
+        }
+        let leaf: T::Hash = T::Hashing::hash_of(&(index, who.clone(), amount));
+        if amount == 0u128 
+            || proof.len() > MAX_PROOF_LENGTH 
+            || (index as u64) >> proof.len() != 0 
+            || Self::merkle_root(leaf, index, &proof) != d.root 
+        {
+            Self::deposit_event(RawEvent::ErrorInvalidProof());
+            return Err("Invalid merkle proof");
+        }
+        let claimed: u128;
+        let new_balance: u128;
+        let total_distributed: u128;
+        match (
+            d.claimed.checked_add(amount).filter(|c| *c <= d.total),
+            Self::account_id_balances(&who).unwrap_or(0u128).checked_add(amount),
+            Self::total_distributed().checked_add(amount),
+        ) {
+            (Some(c), Some(b), Some(t)) => {
+                claimed = c;
+                new_balance = b;
+                total_distributed = t;
+            },
+            (None, _, _) => {
+                Self::deposit_event(RawEvent::ErrorInsufficientFunds());
+                return Err("The claim exceeds the remaining distribution");
+            },
+            _ => {
+                Self::deposit_event(RawEvent::ErrorOverflow());
+                return Err("Overflow error");
+            },
+        }
+        let controller = Self::controller();
+        Self::post_token_memo(vec![(who.clone(), controller.clone(), amount, true)], d.reason, 2)?;
+        
+        <AccountIdBalances<T>>::insert(&who, new_balance);
+        <TotalDistributed<T>>::put(total_distributed);
+        <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| {h != &who}));
+        <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(who.clone()));
+        <ClaimedBitmap<T>>::mutate((id, index / CLAIMED_BITMAP_WORD), |bits| *bits |= 1u128 << (index % CLAIMED_BITMAP_WORD));
+        d.claimed = claimed;
+        let reason: T::Hash = d.reason;
+        <Distributions<T>>::insert(id, d);
+        
+        Self::record_audit_entry(Some(who.clone()), 5, amount, Some(controller), reason);
+        Self::deposit_event(RawEvent::DistributionClaimed(id, who, amount));
+        Ok(())
+    }
+    /// Whether the allocation at an index of a distribution has been claimed
+    pub fn is_claimed(id: u64, index: u32) -> bool {
+        Self::claimed_bitmap((id, index / CLAIMED_BITMAP_WORD)) & (1u128 << (index % CLAIMED_BITMAP_WORD)) != 0
+    }
+    // Recomputes the merkle root from a leaf and its proof. At each level the bit of the index gives the position of the node,
+    // 0 being the left node.
+    fn merkle_root(leaf: T::Hash, index: u32, proof: &[T::Hash]) -> T::Hash {
+        let mut node: T::Hash = leaf;
+        let mut position: u32 = index;
+        for sibling in proof.iter() {
+            node = match position & 1 {
+                0 => T::Hashing::hash_of(&(node, *sibling)),
+                _ => T::Hashing::hash_of(&(*sibling, node)),
+            };
+            position >>= 1;
+        }
+        node
+    }
+    /// Returns the audit log entries from start id (inclusive), at most count entries
+    pub fn audit_log_entries(start: u64, count: u64) -> Vec<(u64, AuditEntry<T::AccountId, T::Hash, T::BlockNumber>)> {
+        let end: u64 = rstd::cmp::min(start.saturating_add(count), Self::audit_log_count());
+        (start..end).filter_map(|id| Self::audit_log(id).map(|entry| (id, entry))).collect()
+    }
+    /// Returns the latest attestation published by a custodian
+    pub fn latest_holdings_attestation(custodian: T::AccountId) -> Option<(u64, HoldingsAttestation<T::Hash, T::BlockNumber>)> {
+        let id: u64 = Self::holdings_attestation_count(&custodian).checked_sub(1)?;
+        Self::holdings_attestations((custodian, id)).map(|attestation| (id, attestation))
+    }
+    /// Returns the attestation history of a custodian from start id (inclusive), at most count entries
+    pub fn holdings_attestation_history(custodian: T::AccountId, start: u64, count: u64) -> Vec<(u64, HoldingsAttestation<T::Hash, T::BlockNumber>)> {
+        let end: u64 = rstd::cmp::min(start.saturating_add(count), Self::holdings_attestation_count(&custodian));
+        (start..end).filter_map(|id| Self::holdings_attestations((custodian.clone(), id)).map(|attestation| (id, attestation))).collect()
+    }
+    // checks that the balance of a holder is not restricted until a block that has not yet been reached
+    fn ensure_transferable(who: &T::AccountId) -> Result {
+        if Self::non_transferable_until(who).map_or(false, |b| b > <system::Module<T>>::block_number()) {
+            Self::deposit_event(RawEvent::ErrorNonTransferable());
+            return Err("The allocation is not transferable yet");
+        }
+        if Self::migration_registration(who).is_some() {
+            Self::deposit_event(RawEvent::ErrorMigrationFrozen());
+            return Err("The balance is frozen for the migration");
+        }
+        Ok(())
+    }
+    // checks if the holder has a balance below the dust threshold. Balances under vesting are never dust
+    fn is_dust(who: &T::AccountId) -> bool {
+        let balance: u128 = Self::account_id_balances(who).unwrap_or(0u128);
+        balance > 0u128 && balance < Self::dust_threshold() && Self::vesting_lock(who).is_none()
+    }
+    // releases the vesting locks ending at the block
+    fn release_vesting(n: T::BlockNumber) {
+        for holder in <VestingReleases<T>>::take(n).into_iter() {
+            if let Some((amount, _)) = <VestingLocks<T>>::take(&holder) {
+                <TotalLocked<T>>::mutate(|total_locked| *total_locked = total_locked.saturating_sub(amount));
+                Self::deposit_event(RawEvent::VestingReleased(holder, amount));
+            }
+        }
+    }
+    // returns the tokens of the OTC offers expiring at the block to their sellers
+    fn expire_otc_offers(n: T::BlockNumber) {
+        for id in <OtcOffersExpiring<T>>::take(&n).into_iter() {
+            if let Some(offer) = Self::otc_offer(id) {
+                Self::return_otc_escrow(id, offer);
+                Self::deposit_event(RawEvent::OtcOfferExpired(id));
+            }
+        }
+    }
+    // removes an OTC offer and credits the escrowed tokens back to the seller
+    fn return_otc_escrow(id: u64, offer: OtcOffer<T::AccountId, T::BlockNumber>) {
+        let balance: u128 = Self::account_id_balances(&offer.seller).unwrap_or(0u128);
+        <AccountIdBalances<T>>::insert(&offer.seller, balance.saturating_add(offer.tokens));
+        <HoldersAccountIds<T>>::mutate(|holders_account_ids| {
+            holders_account_ids.retain(|h| h != &offer.seller);
+            holders_account_ids.push(offer.seller.clone());
+        });
+        <OtcEscrowed<T>>::mutate(|escrowed| *escrowed = escrowed.saturating_sub(offer.tokens));
+        <OtcOffers<T>>::remove(id);
+    }
+    /// Returns the open OTC offers from id (inclusive), at most count offers
+    pub fn otc_offers(start: u64, count: u64) -> Vec<(u64, OtcOffer<T::AccountId, T::BlockNumber>)> {
+        let end: u64 = rstd::cmp::min(start.saturating_add(count), Self::otc_offer_count());
+        (start..end).filter_map(|id| Self::otc_offer(id).map(|offer| (id, offer))).collect()
+    }
+    // adds a transfer to the counters of the current velocity period
+    fn record_velocity(amount: u128) {
+        let block: u64 = <system::Module<T>>::block_number().as_();
+        <TransferVelocity<T>>::mutate(block / VELOCITY_PERIOD, |(count, volume)| {
+            *count = count.saturating_add(1);
+            *volume = volume.saturating_add(amount);
+        });
+    }
+    /// Returns the distributed supply, the supply locked under vesting and the circulating supply (distributed less locked)
+    pub fn supply_statistics() -> (u128, u128, u128) {
+        let distributed: u128 = Self::total_distributed();
+        let locked: u128 = Self::total_locked();
+        (distributed, locked, distributed.saturating_sub(locked))
+    }
+    /// Returns the transfer status, the caps and supply, and the controller
+    pub fn funding_info() -> FundingInfo<T::AccountId> {
+        FundingInfo {
+            transfer_status: Self::transfer_status(),
+            max_issuance: Self::max_issuance(),
+            issued: Self::issued(),
+            unissued: Self::unissued(),
+            total_distributed: Self::total_distributed(),
+            controller: Self::controller(),
+        }
+    }
+    /// Returns the number and volume of the transfers per velocity period (period, transfers, volume) 
+    /// from a period, at most MAX_VELOCITY_PERIODS periods
+    pub fn velocity(from_period: u64, count: u64) -> Vec<(u64, u32, u128)> {
+        let end: u64 = from_period.saturating_add(rstd::cmp::min(count, MAX_VELOCITY_PERIODS));
+        (from_period..end)
+            .map(|p| {
+                let (transfers, volume) = Self::transfer_velocity(p);
+                (p, transfers, volume)
+            })
+            .collect()
+    }
+    // moves the dust balance of a holder to the community account and removes the holder from the holder list
+    fn sweep_dust_for(who: T::AccountId) -> Result {
+        let community: T::AccountId = match Self::community_account() {
+            Some(c) => c,
+            None => {
+                Self::deposit_event(RawEvent::ErrorDustSweepNotSet());
+                return Err("Dust sweep has not been set up");
+            },
+        };
+        if who == community || !Self::is_dust(&who) {
+            Self::deposit_event(RawEvent::ErrorNotDust());
+            return Err("Balance is not dust");
+        }
+        let dust: u128 = Self::account_id_balances(&who).unwrap_or(0u128);
+        let community_balance: u128 = match Self::account_id_balances(&community).unwrap_or(0u128).checked_add(dust) {
+            Some(b) => b,
+            None => {
+                Self::deposit_event(RawEvent::ErrorOverflow());
+                return Err("Adding Overflowed!");
+            },
+        };
+        let sweep_hash: T::Hash = T::Hashing::hash_of(&(who.clone(), community.clone(), dust, <system::Module<T>>::block_number()));
+        Self::post_token_memo(vec![(who.clone(), community.clone(), dust, false), (community.clone(), who.clone(), dust, true)], sweep_hash, 7)?;
+        
+        <AccountIdBalances<T>>::remove(&who);
+        <AccountIdBalances<T>>::insert(&community, community_balance);
+        <HoldersAccountIds<T>>::mutate(|holders_account_ids| {
+            holders_account_ids.retain(|h| h != &who && h != &community);
+            holders_account_ids.push(community.clone());
+        });
+        Self::deposit_event(RawEvent::DustSwept(who, community, dust));
+        Ok(())
+    }
+    // calculates the transfer fee, zero when either party is exempt. None on overflow
+    fn calculate_transfer_fee(from: &T::AccountId, to: &T::AccountId, amount: u128) -> Option<u128> {
+        if Self::fee_exempt(from) || Self::fee_exempt(to) {
+            return Some(0u128);
+        }
+        let (flat_fee, rate) = Self::transfer_fee();
+        let percentage: u128 = amount.checked_mul(rate as u128)? / 10_000u128;
+        flat_fee.checked_add(percentage)
+    }
+    // credits the fee to the fee account, or burns it when no fee account is set
+    fn collect_transfer_fee(from: T::AccountId, to: T::AccountId, net_amount: u128, fee: u128) {
+        let burned: bool = Self::credit_fee(fee);
+        Self::deposit_event(RawEvent::TransferFeeCharged(from, to, net_amount, fee, burned));
+    }
+    // credits a fee to the fee account, or burns it when no fee account is set. Returns true if the fee was burned
+    fn credit_fee(fee: u128) -> bool {
+        match Self::fee_account() {
+            Some(fee_account) => {
+                let balance: u128 = Self::account_id_balances(&fee_account).unwrap_or(0u128);
+                <AccountIdBalances<T>>::insert(&fee_account, balance.saturating_add(fee));
+                <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.retain(|h| h != &fee_account));
+                <HoldersAccountIds<T>>::mutate(|holders_account_ids| holders_account_ids.push(fee_account));
+                false
+            },
+            None => {
+                <TotalDistributed<T>>::mutate(|total_distributed| *total_distributed = total_distributed.saturating_sub(fee));
+                <TotalBurned<T>>::mutate(|total_burned| *total_burned = total_burned.saturating_add(fee));
+                true
+            },
+        }
+    }
+    // Builds the statement of a holder from the memorandum postings up to a block. The closing balance is the balance less the 
+    // movements after the period, and the opening balance the closing balance less the movements in the period.
+    fn holder_statement(holder: T::AccountId, from: T::BlockNumber, to: T::BlockNumber, current_block: T::BlockNumber) -> HolderStatement<T::AccountId, T::BlockNumber> {
+        let first: u64 = from.as_();
+        let last: u64 = to.as_();
+        let current: u64 = current_block.as_();
+        let (movements, increases, decreases) = <accounting::Module<T>>::movements_in_period(holder.clone(), MEMO_TOKEN_HOLDINGS, first, last);
+        let (_, later_increases, later_decreases) = <accounting::Module<T>>::movements_in_period(holder.clone(), MEMO_TOKEN_HOLDINGS, last.saturating_add(1), current);
+        let balance: i128 = <T::FundingConversions as Convert<u128, i128>>::convert(Self::account_id_balances(&holder).unwrap_or(0u128));
+        let closing: i128 = balance.saturating_sub(later_increases).saturating_add(later_decreases);
+        let opening: i128 = closing.saturating_sub(increases).saturating_add(decreases);
+        let unsigned = |x: i128| -> u128 { if x < 0 { 0u128 } else { x as u128 } };
+        HolderStatement {
+            holder,
+            from,
+            to,
+            opening_balance: unsigned(opening),
+            closing_balance: unsigned(closing),
+            movements,
+            increases: unsigned(increases),
+            decreases: unsigned(decreases),
+            issued: current_block,
+        }
+    }
+    // Posts memorandum entries for token movements (identity, counterparty, amount, increase) so that the
+    // identity's statement reflects its token holdings. Each movement debits the holdings and credits the contra account
+    // (reversed for a decrease), so the memo accounts balance per identity. Once posted, the movements are added to the 
+    // movement history of the identities.
+    fn post_token_memo(movements: Vec<(T::AccountId, T::AccountId, u128, bool)>, reference: T::Hash, kind: MovementKind) -> Result {
+        Self::post_token_memo_with(movements, reference, kind, Vec::new())
+    }
+    // keys posting a payment in XTX from the payer to the payee
+    fn xtx_payment_keys(payer: T::AccountId, payee: T::AccountId, amount: u128, reference: T::Hash) -> Vec<(T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber)> {
+        let xtx: AccountOf<T> = <T::FundingConversions as Convert<u64, AccountOf<T>>>::convert(XTX_BALANCE);
+        let posting_amount: i128 = <T::FundingConversions as Convert<u128, i128>>::convert(amount);
+        let increase_amount: AccountBalanceOf<T> = <T::FundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(posting_amount);
+        let decrease_amount: AccountBalanceOf<T> = <T::FundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(0i128 - posting_amount);
+        let current_block = <system::Module<T>>::block_number();
+        vec![
+            (payer.clone(), payee.clone(), xtx, decrease_amount, true, reference, current_block, current_block),
+            (payee, payer, xtx, increase_amount, false, reference, current_block, current_block),
+        ]
+    }
+    // posts the movements to the memorandum accounts together with other postings of the same transaction
+    fn post_token_memo_with(
+        movements: Vec<(T::AccountId, T::AccountId, u128, bool)>, 
+        reference: T::Hash, 
+        kind: MovementKind,
+        other_keys: Vec<(T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber)>,
+    ) -> Result {
+        let holdings: AccountOf<T> = <T::FundingConversions as Convert<u64, AccountOf<T>>>::convert(MEMO_TOKEN_HOLDINGS);
+        let contra: AccountOf<T> = <T::FundingConversions as Convert<u64, AccountOf<T>>>::convert(MEMO_TOKEN_HOLDINGS_CONTRA);
+        let current_block = <system::Module<T>>::block_number();
+        
+        let mut keys = Vec::<(
+            T::AccountId,T::AccountId,AccountOf<T>,AccountBalanceOf<T>,bool,T::Hash,T::BlockNumber,T::BlockNumber,
+        )>::with_capacity(movements.len() * 2 + other_keys.len());
+        keys.extend(other_keys);
+        
+        let movements: Vec<(T::AccountId, T::AccountId, u128, bool)> = movements.into_iter().filter(|m| m.2 > 0u128).collect();
+        for (o, p, amount, increase) in movements.iter().cloned() {
+            let mut posting_amount: i128 = <T::FundingConversions as Convert<u128, i128>>::convert(amount);
+            if !increase {
+                posting_amount = 0i128 - posting_amount;
+            }
+            let amount_converted: AccountBalanceOf<T> = <T::FundingConversions as Convert<i128, AccountBalanceOf<T>>>::convert(posting_amount);
+            keys.push((o.clone(), p.clone(), holdings, amount_converted, !increase, reference, current_block, current_block));
+            keys.push((o, p, contra, amount_converted, increase, reference, current_block, current_block));
+        }
+        if keys.is_empty() {
+            return Ok(());
+        }
+        match <<T as Trait>::Accounting as Posting<T::AccountId,T::Hash,T::BlockNumber,T::CoinAmount>>::handle_multiposting_amounts_with_origin(keys, Vec::new(), ORIGIN_SYSTEM) {
+            Ok(_) => {
+                for (o, p, amount, increase) in movements.into_iter() {
+                    Self::record_movement(o, p, amount, increase, reference, kind, current_block);
+                }
+                Ok(())
+            },
+            Err(_e) => {
+                Self::deposit_event(RawEvent::ErrorPostingMemo());
+                Err("An error occured posting to the memorandum accounts")
+            },
+        }
+    }
+    // adds a movement to the history of the holder, overwriting the oldest movement once the history is full
+    fn record_movement(holder: T::AccountId, counterparty: T::AccountId, amount: u128, incoming: bool, reference: T::Hash, kind: MovementKind, block: T::BlockNumber) {
+        let id: u64 = Self::holder_movement_count(&holder);
+        let movement = HolderMovement { kind, counterparty, amount, incoming, reference, block };
+        <HolderMovements<T>>::insert((holder.clone(), id % MAX_HOLDER_MOVEMENTS), movement);
+        <HolderMovementCount<T>>::insert(&holder, id.saturating_add(1));
+    }
+    /// Returns the movements of a holder from start id (inclusive), at most count entries. 
+    /// Only the latest MAX_HOLDER_MOVEMENTS movements are kept, older ids are skipped.
+    pub fn holder_movements(holder: T::AccountId, start: u64, count: u64) -> Vec<(u64, HolderMovement<T::AccountId, T::Hash, T::BlockNumber>)> {
+        let total: u64 = Self::holder_movement_count(&holder);
+        let first: u64 = rstd::cmp::max(start, total.saturating_sub(MAX_HOLDER_MOVEMENTS));
+        let end: u64 = rstd::cmp::min(start.saturating_add(count), total);
+        (first..end)
+            .filter_map(|id| Self::holder_movement((holder.clone(), id % MAX_HOLDER_MOVEMENTS)).map(|movement| (id, movement)))
+            .collect()
+    }
+    // adds a leaf to the migration tree. Only the left nodes still waiting for their right sibling are kept
+    fn insert_migration_leaf(leaf: T::Hash, index: u32) {
+        let mut node: T::Hash = leaf;
+        let mut size: u64 = index as u64 + 1;
+        for level in 0..MIGRATION_TREE_DEPTH {
+            if size & 1 == 1 {
+                <MigrationFrontier<T>>::insert(level, node);
+                return;
+            }
+            node = T::Hashing::hash_of(&(Self::migration_frontier(level), node));
+            size >>= 1;
+        }
+    }
+    // Computes the root of the migration tree over the first count leaves, the missing leaves being the default hash.
+    // The proof of a leaf has MIGRATION_TREE_DEPTH nodes and is checked like the proof of a distribution claim.
+    fn migration_root(count: u32) -> T::Hash {
+        let mut node: T::Hash = T::Hash::default();
+        let mut empty: T::Hash = T::Hash::default();
+        let mut size: u32 = count;
+        for level in 0..MIGRATION_TREE_DEPTH {
+            node = match size & 1 {
+                1 => T::Hashing::hash_of(&(Self::migration_frontier(level), node)),
+                _ => T::Hashing::hash_of(&(node, empty)),
+            };
+            empty = T::Hashing::hash_of(&(empty, empty));
+            size >>= 1;
+        }
+        node
+    }
+    /// Returns the registrations of the migration set (index, holder, amount) from an index (inclusive), 
+    /// at most count registrations, for the genesis of the next chain
+    pub fn migration_entries(start: u32, count: u32) -> Vec<(u32, T::AccountId, u128)> {
+        let end: u32 = rstd::cmp::min(start.saturating_add(count), Self::migration_set().registrations);
+        (start..end)
+            .filter_map(|index| {
+                let holder = Self::migration_holder(index)?;
+                let (_, amount) = Self::migration_registration(&holder)?;
+                Some((index, holder, amount))
+            })
+            .collect()
+    }
+    /// Whether the balance of a holder is included at an index of the migration set, given the merkle proof of its leaf
+    pub fn verify_migration(index: u32, holder: T::AccountId, amount: u128, proof: Vec<T::Hash>) -> bool {
+        let leaf: T::Hash = T::Hashing::hash_of(&(index, holder, amount));
+        proof.len() == MIGRATION_TREE_DEPTH as usize 
+            && index < Self::migration_set().registrations 
+            && Self::merkle_root(leaf, index, &proof) == Self::migration_set().root
+    }
+    // check if the sale window has closed
+    fn sale_ended() -> bool {
+        match Self::sale_window() {
+            Some((_, end)) => <system::Module<T>>::block_number() > end,
+            None => false,
+        }
+    }
+}
+
+impl<T: Trait> VotingWeight<T::AccountId, T::BlockNumber> for Module<T> {
+    fn voting_weight(who: &T::AccountId) -> u128 {
+        match Self::snapshot() {
+            Some((id, _)) => Self::snapshot_balances((id, who.clone())),
+            None => 0u128,
+        }
+    }
+    fn total_voting_weight() -> u128 {
+        match Self::snapshot() {
+            Some((id, _)) => Self::snapshot_total(id),
+            None => 0u128,
+        }
+    }
+    fn snapshot_block() -> Option<T::BlockNumber> {
+        Self::snapshot().map(|s| s.1)
+    }
 }
 
 decl_event!(
     pub enum Event<T>
     where
         AccountId = <T as system::Trait>::AccountId,
+        BlockNumber = <T as system::Trait>::BlockNumber,
+        Hash = <T as system::Trait>::Hash,
     {
         SuccessMessage(AccountId),
+        /// Start, end, soft cap, rate
+        SaleSet(BlockNumber, BlockNumber, u128, u128),
+        /// Contributor, contribution, allocation
+        Contributed(AccountId, u128, u128),
+        /// Contributor, refunded contribution, voided allocation
+        Refunded(AccountId, u128, u128),
+        /// Contributor, contribution, allocation
+        AllocationClaimed(AccountId, u128, u128),
+        /// Audit log id, action, amount
+        AuditLogged(u64, u16, u128),
+        /// Flat fee, rate in basis points
+        TransferFeeSet(u128, u16),
+        /// Sender, receiver, amount received, fee, fee burned
+        TransferFeeCharged(AccountId, AccountId, u128, u128, bool),
+        /// Snapshot id, block, total voting weight
+        SnapshotTaken(u64, BlockNumber, u128),
+        /// Opening block, closing block
+        TransfersScheduled(BlockNumber, Option<BlockNumber>),
+        TransferScheduleCancelled(),
+        /// Block at which transfers were opened
+        TransfersOpened(BlockNumber),
+        /// Block at which transfers were closed
+        TransfersClosed(BlockNumber),
+        /// Dust threshold, community account
+        DustSweepSet(u128, AccountId),
+        /// Holder, community account, swept amount
+        DustSwept(AccountId, AccountId, u128),
+        CustodianAdded(AccountId),
+        CustodianRemoved(AccountId),
+        /// Custodian, attestation id, holdings, beneficial owners
+        HoldingsAttested(AccountId, u64, u128, u32),
+        /// Statement fee
+        StatementFeeSet(u128),
+        /// Request id, holder, first block, last block, fee paid
+        StatementRequested(u64, AccountId, BlockNumber, BlockNumber, u128),
+        /// Request id, holder, hash of the statement
+        StatementIssued(u64, AccountId, Hash),
+        /// Claim id, holder, amount, proof hash
+        ClaimImported(u64, AccountId, u128, Hash),
+        /// Claim id, holder, amount
+        ClaimAccepted(u64, AccountId, u128),
+        /// Claim id, holder, amount
+        ClaimCancelled(u64, AccountId, u128),
+        /// Holder, amount, release block
+        VestingLocked(AccountId, u128, BlockNumber),
+        /// Holder, amount
+        VestingReleased(AccountId, u128),
+        /// Holder, block until which the allocation is not transferable
+        NonTransferableSet(AccountId, BlockNumber),
+        /// Holder
+        NonTransferableRemoved(AccountId),
+        /// Distribution id, merkle root, total, expiry block
+        DistributionPublished(u64, Hash, u128, BlockNumber),
+        /// Distribution id, holder, amount
+        DistributionClaimed(u64, AccountId, u128),
+        /// Distribution id, unclaimed amount returned to the issued coins
+        DistributionClosed(u64, u128),
+        /// Nominee, last block to accept, distributions frozen
+        ControllerNominated(AccountId, BlockNumber, bool),
+        /// New controller
+        ControllerAccepted(AccountId),
+        /// Nominee that did not accept in time
+        ControllerNominationLapsed(AccountId),
+        /// OTC offer id, seller, tokens, price in XTX, expiry block
+        OtcOfferCreated(u64, AccountId, u128, u128, BlockNumber),
+        /// OTC offer id, cancelled by the seller
+        OtcOfferCancelled(u64),
+        /// OTC offer id, expired without being accepted
+        OtcOfferExpired(u64),
+        /// OTC offer id, seller, buyer, tokens, price in XTX
+        OtcTradeExecuted(u64, AccountId, AccountId, u128, u128),
+        /// Holder, index in the migration set, frozen balance
+        RegisteredForMigration(AccountId, u32, u128),
+        /// Registrations, total, merkle root of the migration set
+        MigrationFinalized(u32, u128, Hash),
         /// You cannot change a controller to the same controller
         ErrorSameController(),
+        /// There is no open nomination for this account
+        ErrorNotNominee(),
+        /// Distributions are frozen during the controller handover
+        ErrorControllerHandover(),
         /// You are not the controller
         ErrorNotController(),
         /// Cannot open transfers when controller not set
@@ -389,5 +2153,81 @@ decl_event!(
         ErrorInsufficientFunds(),
         /// Transfers not open.
         ErrorTransfersNotOpen(),
+        /// Contributions already made. Cannot change the sale.
+        ErrorSaleStarted(),
+        /// Invalid sale parameters
+        ErrorSaleParameters(),
+        /// Sale is not open
+        ErrorSaleNotOpen(),
+        /// Sale has not ended
+        ErrorSaleNotEnded(),
+        /// Soft cap reached. No refunds.
+        ErrorSoftCapReached(),
+        /// Soft cap not reached. Claim a refund.
+        ErrorSoftCapNotReached(),
+        /// No contribution
+        ErrorNoContribution(),
+        /// The contribution is no longer reserved in full
+        ErrorContributionNotReserved(),
+        /// Transfer fee is larger than the amount
+        ErrorFeeTooLarge(),
+        /// Fee rate is too high
+        ErrorFeeRate(),
+        /// An error occured posting to the memorandum accounts
+        ErrorPostingMemo(),
+        /// Transfers are already open
+        ErrorTransfersAlreadyOpen(),
+        /// Transfers must be scheduled in the future and closed after opening
+        ErrorTransferSchedule(),
+        /// Dust sweep has not been set up
+        ErrorDustSweepNotSet(),
+        /// Balance is not dust
+        ErrorNotDust(),
+        /// Not a custodian
+        ErrorNotCustodian(),
+        /// Holdings and beneficial owners must both be zero or both be set
+        ErrorHoldingsAttestation(),
+        /// Endpoint is too long
+        ErrorEndpointTooLong(),
+        /// No statement endpoint registered
+        ErrorNoStatementEndpoint(),
+        /// The statement period must have ended
+        ErrorStatementPeriod(),
+        /// The holder already has a pending claim
+        ErrorClaimPending(),
+        /// No pending claim
+        ErrorNoPendingClaim(),
+        /// Funds are locked under vesting
+        ErrorVestingLocked(),
+        /// Vesting lock must be within the balance and end in the future
+        ErrorVestingLock(),
+        /// The allocation is not transferable yet
+        ErrorNonTransferable(),
+        /// The restriction must end in the future and cannot be shortened
+        ErrorNonTransferableUntil(),
+        /// Unknown, closed or expired distribution
+        ErrorDistribution(),
+        /// The allocation has already been claimed
+        ErrorAlreadyClaimed(),
+        /// Invalid merkle proof
+        ErrorInvalidProof(),
+        /// Invalid OTC offer
+        ErrorOtcOffer(),
+        /// OTC offer not found
+        ErrorOtcOfferNotFound(),
+        /// The OTC offer is expired or not open to you
+        ErrorOtcNotAllowed(),
+        /// Error paying the price of the OTC offer
+        ErrorOtcPayment(),
+        /// Registrations for the migration are closed
+        ErrorMigrationClosed(),
+        /// Already registered for the migration
+        ErrorMigrationRegistered(),
+        /// Only an unlocked balance can be registered for the migration
+        ErrorMigrationBalance(),
+        /// The migration set is full
+        ErrorMigrationSetFull(),
+        /// The balance is frozen for the migration
+        ErrorMigrationFrozen(),
     }
 );
\ No newline at end of file
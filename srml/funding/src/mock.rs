@@ -0,0 +1,120 @@
+//! Test utilities
+
+#![cfg(test)]
+
+use crate::{GenesisConfig, Module, Trait};
+use sr_primitives::BuildStorage;
+use sr_primitives::{
+    testing::{Digest, DigestItem, Header},
+    traits::{BlakeTwo256, Convert, IdentityLookup},
+};
+use srml_support::impl_outer_origin;
+use substrate_primitives::{sr25519, Blake2Hasher, H256};
+
+impl_outer_origin! {
+    pub enum Origin for Runtime {}
+}
+
+pub type AccountId = sr25519::Public;
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+impl system::Trait for Runtime {
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type Digest = Digest;
+    type AccountId = AccountId;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = ();
+    type Log = DigestItem;
+}
+impl timestamp::Trait for Runtime {
+    type Moment = u64;
+    type OnTimestampSet = ();
+}
+impl accounting::Trait for Runtime {
+    type Event = ();
+    type CoinAmount = u64;
+    type AccountingConversions = ConversionHandler;
+    type OnPeriodClose = ();
+}
+impl balances::Trait for Runtime {
+    type Balance = u64;
+    type OnFreeBalanceZero = ();
+    type OnNewAccount = ();
+    type TransactionPayment = ();
+    type TransferPayment = ();
+    type DustRemoval = ();
+    type Event = ();
+    type Accounting = accounting::Module<Runtime>;
+    type BalancesConversions = ConversionHandler;
+}
+impl Trait for Runtime {
+    type Event = ();
+    type Currency = balances::Module<Runtime>;
+    type FundingConversions = ConversionHandler;
+    type Accounting = accounting::Module<Runtime>;
+    type Statements = ();
+}
+
+pub struct ConversionHandler;
+impl Convert<i128, i128> for ConversionHandler { fn convert(x: i128) -> i128 { x } }
+impl Convert<u128, i128> for ConversionHandler { fn convert(x: u128) -> i128 { x as i128 } }
+impl Convert<u128, u64> for ConversionHandler { fn convert(x: u128) -> u64 { x as u64 } }
+impl Convert<u64, i128> for ConversionHandler { fn convert(x: u64) -> i128 { x as i128 } }
+impl Convert<u64, u128> for ConversionHandler { fn convert(x: u64) -> u128 { x as u128 } }
+impl Convert<u64, u64> for ConversionHandler { fn convert(x: u64) -> u64 { x } }
+
+/// Identity used in tests
+pub fn id(n: u8) -> AccountId {
+    sr25519::Public([n; 32])
+}
+
+/// The controller of the funds in tests
+pub fn controller() -> AccountId {
+    id(9)
+}
+
+/// Genesis storage with the free balances of the identities and 1_000_000 issued coins,
+/// without fees or an existential deposit. The controller is set up by the tests.
+pub fn new_test_ext(balances: Vec<(AccountId, u64)>) -> sr_io::TestExternalities<Blake2Hasher> {
+    let mut t = system::GenesisConfig::<Runtime>::default()
+        .build_storage()
+        .unwrap()
+        .0;
+    t.extend(
+        balances::GenesisConfig::<Runtime> {
+            transaction_base_fee: 0,
+            transaction_byte_fee: 0,
+            balances,
+            existential_deposit: 0,
+            transfer_fee: 0,
+            creation_fee: 0,
+            vesting: vec![],
+        }
+        .build_storage()
+        .unwrap()
+        .0,
+    );
+    t.extend(
+        GenesisConfig::<Runtime> {
+            transfer_status: false,
+            max_issuance: 2_000_000,
+            unissued: 1_000_000,
+            issued: 1_000_000,
+        }
+        .build_storage()
+        .unwrap()
+        .0,
+    );
+    t.into()
+}
+
+pub type System = system::Module<Runtime>;
+pub type Balances = balances::Module<Runtime>;
+pub type Funding = Module<Runtime>;
@@ -0,0 +1,877 @@
+//! Tests for the module.
+//! Holders are credited through accepted import claims, so that their memorandum postings exist like on chain.
+
+#![cfg(test)]
+
+use super::*;
+use mock::{controller, id, new_test_ext, AccountId, Balances, Funding, Origin, Runtime, System};
+use sr_io::with_externalities;
+use srml_support::{assert_err, assert_ok};
+use sr_primitives::traits::{BlakeTwo256, OnInitialize};
+use substrate_primitives::H256;
+
+fn set_controller() {
+    assert_ok!(Funding::nominate_controller(Origin::ROOT, controller(), false));
+    assert_ok!(Funding::accept_controller(Origin::signed(controller())));
+}
+
+fn open_transfers() {
+    assert_ok!(Funding::set_transfer_status(Origin::ROOT));
+    assert!(Funding::transfer_status());
+}
+
+/// Credits a holder through an imported claim accepted by the holder
+fn fund(n: u8, amount: u128) {
+    assert_ok!(Funding::import_claim(Origin::signed(controller()), id(n), amount, H256::repeat_byte(n)));
+    assert_ok!(Funding::accept_claim(Origin::signed(id(n))));
+}
+
+fn balance(n: u8) -> u128 {
+    Funding::account_id_balances(id(n)).unwrap_or(0)
+}
+
+/// Leaf of a distribution or of the migration set
+fn leaf(index: u32, n: u8, amount: u128) -> H256 {
+    BlakeTwo256::hash_of(&(index, id(n), amount))
+}
+
+fn node(left: H256, right: H256) -> H256 {
+    BlakeTwo256::hash_of(&(left, right))
+}
+
+/// Distribution of 100, 200, 300 and 400 to identities 1 to 4 at indexes 0 to 3. Returns the root and the leaves.
+fn distribution_tree() -> (H256, Vec<H256>) {
+    let leaves: Vec<H256> = (0..4u32).map(|i| leaf(i, i as u8 + 1, (i as u128 + 1) * 100)).collect();
+    let root = node(node(leaves[0], leaves[1]), node(leaves[2], leaves[3]));
+    (root, leaves)
+}
+
+/// Root of the migration tree over the leaves, the missing leaves being the default hash, and the proof of a leaf
+fn migration_tree(leaves: &[H256], index: usize) -> (H256, Vec<H256>) {
+    let mut level: Vec<H256> = leaves.to_vec();
+    let mut empty = H256::default();
+    let mut position = index;
+    let mut proof = Vec::new();
+    for _ in 0..MIGRATION_TREE_DEPTH {
+        if level.len() % 2 == 1 {
+            level.push(empty);
+        }
+        proof.push(level[position ^ 1]);
+        level = level.chunks(2).map(|pair| node(pair[0], pair[1])).collect();
+        empty = node(empty, empty);
+        position >>= 1;
+    }
+    (level[0], proof)
+}
+
+#[test]
+fn sale_above_soft_cap_allocates_tokens_and_pays_the_controller() {
+    with_externalities(&mut new_test_ext(vec![(controller(), 10), (id(1), 1_000), (id(2), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::set_sale(Origin::ROOT, 2, 10, 500, 10));
+        assert_err!(Funding::contribute(Origin::signed(id(1)), 300), "Sale is not open.");
+
+        System::set_block_number(2);
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 300));
+        assert_ok!(Funding::contribute(Origin::signed(id(2)), 200));
+        assert_eq!(Balances::reserved_balance(id(1)), 300);
+        assert_eq!(Funding::contributions(id(1)), Some((300, 3_000)));
+        assert_eq!(Funding::total_contributions(), 500);
+        assert_eq!(Funding::issued(), 1_000_000 - 5_000);
+        assert_err!(Funding::set_sale(Origin::ROOT, 2, 20, 500, 10), "Contributions already made. Cannot change the sale.");
+
+        // The last block of the window is still open
+        System::set_block_number(10);
+        assert_err!(Funding::claim_allocation(Origin::signed(id(1))), "Sale has not ended.");
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 100));
+
+        System::set_block_number(11);
+        assert_err!(Funding::contribute(Origin::signed(id(1)), 100), "Sale is not open.");
+        assert_err!(Funding::claim_refund(Origin::signed(id(1))), "Soft cap reached. No refunds.");
+        assert_ok!(Funding::claim_allocation(Origin::signed(id(1))));
+        assert_eq!(balance(1), 4_000);
+        assert_eq!(Balances::reserved_balance(id(1)), 0);
+        assert_eq!(Balances::free_balance(id(1)), 600);
+        assert_eq!(Balances::free_balance(controller()), 410);
+        assert_eq!(Funding::total_distributed(), 4_000);
+        assert_eq!(Funding::contributions(id(1)), None);
+        assert_eq!(Funding::contributor_account_ids(), vec![id(2)]);
+        assert_err!(Funding::claim_allocation(Origin::signed(id(1))), "No contribution to claim.");
+    });
+}
+
+#[test]
+fn allocation_is_not_claimed_without_the_reserved_contribution() {
+    with_externalities(&mut new_test_ext(vec![(controller(), 10), (id(1), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::set_sale(Origin::ROOT, 1, 10, 100, 10));
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 300));
+        // Part of the contribution is released outside the sale
+        <Balances as ReservableCurrency<AccountId>>::unreserve(&id(1), 100);
+
+        System::set_block_number(11);
+        assert_err!(Funding::claim_allocation(Origin::signed(id(1))), "The contribution is no longer reserved.");
+        assert_eq!(Balances::reserved_balance(id(1)), 200);
+        assert_eq!(Balances::free_balance(controller()), 10);
+        assert_eq!(balance(1), 0);
+        assert_eq!(Funding::contributions(id(1)), Some((300, 3_000)));
+    });
+}
+
+#[test]
+fn allocation_is_not_claimed_when_the_controller_cannot_be_paid() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::set_sale(Origin::ROOT, 1, 10, 100, 10));
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 300));
+
+        // The controller account does not exist
+        System::set_block_number(11);
+        assert_err!(Funding::claim_allocation(Origin::signed(id(1))), "The contribution could not be paid to the controller.");
+        assert_eq!(Balances::reserved_balance(id(1)), 300);
+        assert_eq!(balance(1), 0);
+        assert_eq!(Funding::total_distributed(), 0);
+    });
+}
+
+#[test]
+fn sale_below_soft_cap_refunds_and_voids_the_allocation() {
+    with_externalities(&mut new_test_ext(vec![(controller(), 10), (id(1), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::set_sale(Origin::ROOT, 1, 10, 1_000, 10));
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 300));
+        assert_err!(Funding::claim_refund(Origin::signed(id(1))), "Sale has not ended.");
+
+        System::set_block_number(11);
+        assert_err!(Funding::claim_allocation(Origin::signed(id(1))), "Soft cap not reached. Claim a refund.");
+        assert_ok!(Funding::claim_refund(Origin::signed(id(1))));
+        assert_eq!(Balances::reserved_balance(id(1)), 0);
+        assert_eq!(Balances::free_balance(id(1)), 1_000);
+        assert_eq!(Funding::issued(), 1_000_000);
+        assert_eq!(Funding::total_contributions(), 0);
+        assert_eq!(Funding::refunds(id(1)), Some((300, 3_000)));
+        assert_err!(Funding::claim_refund(Origin::signed(id(1))), "No contribution to refund.");
+    });
+}
+
+#[test]
+fn invalid_sale_and_contributions_are_rejected() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 1_000)]), || {
+        System::set_block_number(1);
+        assert_err!(Funding::contribute(Origin::signed(id(1)), 100), "Sale is not open.");
+        assert_err!(Funding::set_sale(Origin::ROOT, 10, 10, 500, 10), "Invalid sale parameters.");
+        assert_err!(Funding::set_sale(Origin::ROOT, 1, 10, 500, 0), "Invalid sale parameters.");
+        assert!(Funding::set_sale(Origin::signed(id(1)), 1, 10, 500, 10).is_err());
+
+        assert_ok!(Funding::set_sale(Origin::ROOT, 1, 10, 500, 1_000_000));
+        assert_err!(Funding::contribute(Origin::signed(id(1)), 0), "Contribution cannot be zero.");
+        assert_err!(Funding::contribute(Origin::signed(id(1)), 2), "Insufficient funds for allocation.");
+        assert_ok!(Funding::contribute(Origin::signed(id(1)), 1));
+        assert!(Funding::contribute(Origin::signed(id(1)), 0).is_err());
+        assert_eq!(Funding::issued(), 0);
+    });
+}
+
+#[test]
+fn transfer_fee_is_credited_to_the_fee_account() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 10_000);
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(2), 1_000), "Transfers not open.");
+        open_transfers();
+
+        assert_err!(Funding::set_transfer_fee(Origin::ROOT, 10, 1_001, Some(id(5))), "Fee rate is too high");
+        // 10 flat plus 1%
+        assert_ok!(Funding::set_transfer_fee(Origin::ROOT, 10, 100, Some(id(5))));
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 1_000));
+        assert_eq!(balance(1), 9_000);
+        assert_eq!(balance(2), 980);
+        assert_eq!(balance(5), 20);
+        assert_eq!(Funding::total_burned(), 0);
+        assert_eq!(Funding::total_distributed(), 10_000);
+
+        assert_ok!(Funding::set_transfer_fee(Origin::ROOT, 2_000, 0, Some(id(5))));
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(2), 1_000), "Transfer fee is larger than the amount");
+        assert_eq!(balance(1), 9_000);
+    });
+}
+
+#[test]
+fn transfer_fee_is_burned_without_a_fee_account_and_waived_for_exempt_identities() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 10_000);
+        open_transfers();
+        assert_ok!(Funding::set_transfer_fee(Origin::ROOT, 10, 100, None));
+
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 1_000));
+        assert_eq!(balance(2), 980);
+        assert_eq!(Funding::total_burned(), 20);
+        assert_eq!(Funding::total_distributed(), 9_980);
+
+        assert_ok!(Funding::set_fee_exempt(Origin::ROOT, id(3), true));
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(3), 1_000));
+        assert_eq!(balance(3), 1_000);
+        assert_eq!(Funding::total_burned(), 20);
+
+        assert_ok!(Funding::set_fee_exempt(Origin::ROOT, id(3), false));
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(3), 1_000));
+        assert_eq!(balance(3), 1_980);
+        assert_eq!(balance(1), 7_000);
+    });
+}
+
+#[test]
+fn scheduled_transfers_open_and_close_at_the_scheduled_blocks() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        assert_err!(Funding::schedule_transfers(Origin::ROOT, 5, None), "Cannot open transfers when controller not set.");
+        set_controller();
+        assert_err!(
+            Funding::schedule_transfers(Origin::ROOT, 1, None),
+            "Transfers must be scheduled in the future and closed after opening"
+        );
+        assert_err!(
+            Funding::schedule_transfers(Origin::ROOT, 5, Some(5)),
+            "Transfers must be scheduled in the future and closed after opening"
+        );
+        assert_ok!(Funding::schedule_transfers(Origin::ROOT, 5, Some(8)));
+        assert_eq!(Funding::scheduled_opening(), Some(5));
+        assert_eq!(Funding::scheduled_closing(), Some(8));
+
+        Funding::on_initialize(4);
+        assert!(!Funding::transfer_status());
+        Funding::on_initialize(5);
+        assert!(Funding::transfer_status());
+        assert_eq!(Funding::scheduled_opening(), None);
+
+        System::set_block_number(5);
+        assert_err!(Funding::schedule_transfers(Origin::ROOT, 6, None), "Transfers are already open");
+
+        Funding::on_initialize(7);
+        assert!(Funding::transfer_status());
+        Funding::on_initialize(8);
+        assert!(!Funding::transfer_status());
+        assert_eq!(Funding::scheduled_closing(), None);
+    });
+}
+
+#[test]
+fn cancelled_schedule_does_not_open_transfers() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::schedule_transfers(Origin::ROOT, 5, None));
+        assert_ok!(Funding::cancel_transfer_schedule(Origin::ROOT));
+        Funding::on_initialize(5);
+        assert!(!Funding::transfer_status());
+        assert_eq!(Funding::scheduled_opening(), None);
+    });
+}
+
+#[test]
+fn dust_is_swept_to_the_community_account() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_err!(Funding::sweep_dust(Origin::signed(id(1))), "Dust sweep has not been set up");
+        assert_ok!(Funding::set_dust_sweep(Origin::ROOT, 100, id(7)));
+        assert_err!(Funding::sweep_dust(Origin::signed(id(1))), "Balance is not dust");
+
+        // Without consent the dust stays with the holder after a transfer
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 950));
+        assert_eq!(balance(1), 50);
+        assert_ok!(Funding::sweep_dust(Origin::signed(id(1))));
+        assert_eq!(balance(1), 0);
+        assert_eq!(balance(7), 50);
+        assert!(!Funding::holders_account_ids().contains(&id(1)));
+        assert!(Funding::holders_account_ids().contains(&id(7)));
+        // The community account cannot sweep its own balance
+        assert_err!(Funding::sweep_dust(Origin::signed(id(7))), "Balance is not dust");
+    });
+}
+
+#[test]
+fn dust_is_swept_after_a_transfer_with_consent() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::set_dust_sweep(Origin::ROOT, 100, id(7)));
+        assert_ok!(Funding::set_dust_sweep_consent(Origin::signed(id(1)), true));
+
+        // A balance of exactly the threshold is not dust
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 900));
+        assert_eq!(balance(1), 100);
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 1));
+        assert_eq!(balance(1), 0);
+        assert_eq!(balance(7), 99);
+        assert_eq!(balance(2), 901);
+    });
+}
+
+#[test]
+fn balance_under_vesting_is_never_dust() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 50);
+        open_transfers();
+        assert_ok!(Funding::set_dust_sweep(Origin::ROOT, 100, id(7)));
+        assert_ok!(Funding::lock_vesting(Origin::signed(controller()), id(1), 10, 5));
+        assert_err!(Funding::sweep_dust(Origin::signed(id(1))), "Balance is not dust");
+        assert_eq!(balance(1), 50);
+    });
+}
+
+#[test]
+fn imported_claim_is_credited_once_accepted() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        let proof = H256::repeat_byte(42);
+        assert_err!(Funding::import_claim(Origin::signed(id(1)), id(1), 500, proof), "You are not the controller");
+        assert_err!(
+            Funding::import_claim(Origin::signed(controller()), id(1), 0, proof),
+            "Claim amount must be greater than zero and within the issued coins"
+        );
+        assert_err!(
+            Funding::import_claim(Origin::signed(controller()), id(1), 1_000_001, proof),
+            "Claim amount must be greater than zero and within the issued coins"
+        );
+        assert_err!(Funding::accept_claim(Origin::signed(id(1))), "No pending claim");
+
+        assert_ok!(Funding::import_claim(Origin::signed(controller()), id(1), 500, proof));
+        assert_err!(
+            Funding::import_claim(Origin::signed(controller()), id(1), 600, proof),
+            "The holder already has a pending claim"
+        );
+        // Nothing is credited until the holder accepts
+        assert_eq!(balance(1), 0);
+        assert_eq!(Funding::issued(), 1_000_000);
+
+        System::set_block_number(2);
+        assert_ok!(Funding::accept_claim(Origin::signed(id(1))));
+        assert_eq!(balance(1), 500);
+        assert_eq!(Funding::issued(), 999_500);
+        assert_eq!(Funding::total_distributed(), 500);
+        assert_eq!(Funding::pending_claim(id(1)), None);
+        let claim = Funding::import_claims(0).expect("accepted claims are kept");
+        assert_eq!(claim.accepted, Some(2));
+        assert_eq!(claim.proof_hash, proof);
+        let (_, entry) = Funding::audit_log_entries(0, 10).pop().expect("acceptance is audited");
+        assert_eq!((entry.action, entry.amount, entry.actor), (4, 500, Some(id(1))));
+        assert_err!(Funding::accept_claim(Origin::signed(id(1))), "No pending claim");
+    });
+}
+
+#[test]
+fn cancelled_claim_can_be_imported_again() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_err!(Funding::cancel_claim(Origin::signed(controller()), id(1)), "No pending claim");
+        assert_ok!(Funding::import_claim(Origin::signed(controller()), id(1), 5_000, H256::repeat_byte(1)));
+        assert_err!(Funding::cancel_claim(Origin::signed(id(1)), id(1)), "You are not the controller");
+        assert_ok!(Funding::cancel_claim(Origin::signed(controller()), id(1)));
+        assert_eq!(Funding::pending_claim(id(1)), None);
+        assert_eq!(Funding::import_claims(0), None);
+        let (_, entry) = Funding::audit_log_entries(0, 10).pop().expect("cancellation is audited");
+        assert_eq!((entry.action, entry.amount, entry.counterparty), (6, 5_000, Some(id(1))));
+        assert_err!(Funding::accept_claim(Origin::signed(id(1))), "No pending claim");
+
+        assert_ok!(Funding::import_claim(Origin::signed(controller()), id(1), 500, H256::repeat_byte(1)));
+        assert_ok!(Funding::accept_claim(Origin::signed(id(1))));
+        assert_eq!(balance(1), 500);
+    });
+}
+
+#[test]
+fn claim_beyond_the_remaining_issued_coins_cannot_be_accepted() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        assert_ok!(Funding::import_claim(Origin::signed(controller()), id(1), 600_000, H256::repeat_byte(1)));
+        assert_ok!(Funding::import_claim(Origin::signed(controller()), id(2), 600_000, H256::repeat_byte(2)));
+        assert_ok!(Funding::accept_claim(Origin::signed(id(1))));
+        assert_err!(Funding::accept_claim(Origin::signed(id(2))), "Insufficient funds to credit the claim");
+        assert_eq!(balance(2), 0);
+        assert_eq!(Funding::pending_claim(id(2)), Some(1));
+    });
+}
+
+#[test]
+fn vesting_lock_holds_back_transfers_until_released() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_err!(
+            Funding::lock_vesting(Origin::signed(controller()), id(1), 0, 10),
+            "Vesting lock must be within the balance and end in the future"
+        );
+        assert_err!(
+            Funding::lock_vesting(Origin::signed(controller()), id(1), 1_001, 10),
+            "Vesting lock must be within the balance and end in the future"
+        );
+        assert_err!(
+            Funding::lock_vesting(Origin::signed(controller()), id(1), 600, 1),
+            "Vesting lock must be within the balance and end in the future"
+        );
+        assert_ok!(Funding::lock_vesting(Origin::signed(controller()), id(1), 600, 10));
+        assert_err!(
+            Funding::lock_vesting(Origin::signed(controller()), id(1), 100, 20),
+            "The holder already has a vesting lock"
+        );
+        assert_eq!(Funding::supply_statistics(), (1_000, 600, 400));
+
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(2), 401), "Funds are locked under vesting.");
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 400));
+
+        // The lock is released at the release block, not before
+        System::set_block_number(9);
+        Funding::on_initialize(9);
+        assert_eq!(Funding::vesting_lock(id(1)), Some((600, 10)));
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(2), 1), "Funds are locked under vesting.");
+
+        System::set_block_number(10);
+        Funding::on_initialize(10);
+        assert_eq!(Funding::vesting_lock(id(1)), None);
+        assert_eq!(Funding::total_locked(), 0);
+        assert_eq!(Funding::supply_statistics(), (1_000, 0, 1_000));
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 600));
+        assert_eq!(balance(2), 1_000);
+    });
+}
+
+#[test]
+fn velocity_is_counted_per_period() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 100));
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 200));
+
+        // First block of the next period
+        System::set_block_number(VELOCITY_PERIOD);
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 50));
+        assert_eq!(Funding::velocity(0, 3), vec![(0, 2, 300), (1, 1, 50), (2, 0, 0)]);
+        assert_eq!(Funding::velocity(0, 1_000).len(), MAX_VELOCITY_PERIODS as usize);
+    });
+}
+
+#[test]
+fn statement_is_built_offchain_and_only_its_hash_is_stored() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        System::set_block_number(2);
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 100));
+
+        System::set_block_number(10);
+        assert_err!(Funding::request_statement(Origin::signed(id(1)), 1, 5), "No statement endpoint registered");
+        assert_ok!(Funding::set_statement_endpoint(Origin::signed(id(1)), b"https://holder".to_vec()));
+        assert_err!(Funding::request_statement(Origin::signed(id(1)), 1, 10), "The statement period must have ended");
+        assert_ok!(Funding::request_statement(Origin::signed(id(1)), 1, 5));
+        assert_eq!(Funding::pending_statements(), vec![0]);
+        assert!(Funding::statement_hash(0).is_none());
+
+        // Built by the offchain worker as of the imported block
+        let statement = Funding::holder_statement(id(1), 1, 5, 10);
+        assert_eq!(statement.opening_balance, 0);
+        assert_eq!(statement.closing_balance, 900);
+
+        System::set_block_number(11);
+        let other_period = HolderStatement { from: 2, ..statement.clone() };
+        assert_err!(Funding::record_statement(Origin::INHERENT, 0, other_period), "The statement does not answer the request");
+        assert_err!(
+            Funding::record_statement(Origin::signed(id(1)), 0, statement.clone()),
+            "bad origin: expected to be an inherent origin"
+        );
+        assert_ok!(Funding::record_statement(Origin::INHERENT, 0, statement.clone()));
+        assert_eq!(Funding::statement_hash(0), Some(BlakeTwo256::hash_of(&statement)));
+        assert!(Funding::pending_statements().is_empty());
+        assert_err!(Funding::record_statement(Origin::INHERENT, 0, statement), "No statement is pending for this request");
+    });
+}
+
+#[test]
+fn cliff_holds_back_transfers_until_the_cliff_block() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_err!(
+            Funding::set_non_transferable(Origin::signed(id(1)), id(1), 10),
+            "You are not the controller"
+        );
+        assert_err!(
+            Funding::set_non_transferable(Origin::signed(controller()), id(1), 1),
+            "The restriction must end in the future and cannot be shortened"
+        );
+        assert_ok!(Funding::set_non_transferable(Origin::signed(controller()), id(1), 10));
+        assert_err!(
+            Funding::set_non_transferable(Origin::signed(controller()), id(1), 9),
+            "The restriction must end in the future and cannot be shortened"
+        );
+        assert_ok!(Funding::set_non_transferable(Origin::signed(controller()), id(1), 12));
+
+        System::set_block_number(11);
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(2), 100), "The allocation is not transferable yet");
+        assert_err!(Funding::sweep_dust(Origin::signed(id(1))), "The allocation is not transferable yet");
+        assert_eq!(balance(1), 1_000);
+
+        // Transferable from the cliff block
+        System::set_block_number(12);
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 100));
+        assert_eq!(balance(2), 100);
+    });
+}
+
+#[test]
+fn cliff_can_be_lifted_by_the_super_user() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::set_non_transferable(Origin::signed(controller()), id(1), 10));
+        assert!(Funding::remove_non_transferable(Origin::signed(controller()), id(1)).is_err());
+        assert_ok!(Funding::remove_non_transferable(Origin::ROOT, id(1)));
+        assert_eq!(Funding::non_transferable_until(id(1)), None);
+        assert_ok!(Funding::transfer(Origin::signed(id(1)), id(2), 100));
+    });
+}
+
+#[test]
+fn distribution_claims_are_proven_against_the_root() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        let (root, leaves) = distribution_tree();
+        assert_ok!(Funding::publish_distribution(Origin::signed(controller()), root, 1_000, 10, H256::repeat_byte(7)));
+        assert_eq!(Funding::issued(), 999_000);
+
+        assert_ok!(Funding::claim(Origin::signed(id(3)), 0, 2, 300, vec![leaves[3], node(leaves[0], leaves[1])]));
+        assert_eq!(balance(3), 300);
+        assert!(Funding::is_claimed(0, 2));
+        assert!(!Funding::is_claimed(0, 3));
+        assert_err!(
+            Funding::claim(Origin::signed(id(3)), 0, 2, 300, vec![leaves[3], node(leaves[0], leaves[1])]),
+            "The allocation has already been claimed"
+        );
+
+        assert_ok!(Funding::claim(Origin::signed(id(1)), 0, 0, 100, vec![leaves[1], node(leaves[2], leaves[3])]));
+        assert_eq!(balance(1), 100);
+        assert_eq!(balance(3), 300);
+        let distribution = Funding::distribution(0).expect("distribution is published");
+        assert_eq!(distribution.claimed, 400);
+        assert_eq!(Funding::total_distributed(), 400);
+        let (_, entry) = Funding::audit_log_entries(0, 10).pop().expect("claims are audited");
+        assert_eq!((entry.action, entry.amount, entry.actor), (5, 100, Some(id(1))));
+    });
+}
+
+#[test]
+fn distribution_claim_with_a_bad_proof_is_rejected() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        let (root, leaves) = distribution_tree();
+        assert_ok!(Funding::publish_distribution(Origin::signed(controller()), root, 1_000, 10, H256::repeat_byte(7)));
+        let proof = vec![leaves[0], node(leaves[2], leaves[3])];
+
+        // Another amount, another identity, another index or a wrong sibling
+        assert_err!(Funding::claim(Origin::signed(id(2)), 0, 1, 201, proof.clone()), "Invalid merkle proof");
+        assert_err!(Funding::claim(Origin::signed(id(5)), 0, 1, 200, proof.clone()), "Invalid merkle proof");
+        assert_err!(Funding::claim(Origin::signed(id(2)), 0, 0, 200, proof.clone()), "Invalid merkle proof");
+        assert_err!(Funding::claim(Origin::signed(id(2)), 0, 1, 200, vec![leaves[2], node(leaves[2], leaves[3])]), "Invalid merkle proof");
+        // The index must fit the depth of the proof
+        assert_err!(Funding::claim(Origin::signed(id(2)), 0, 5, 200, proof.clone()), "Invalid merkle proof");
+        assert_err!(Funding::claim(Origin::signed(id(2)), 0, 1, 200, vec![H256::zero(); MAX_PROOF_LENGTH + 1]), "Invalid merkle proof");
+        assert_err!(Funding::claim(Origin::signed(id(2)), 1, 1, 200, proof.clone()), "The distribution is closed or has expired");
+        assert!(!Funding::is_claimed(0, 1));
+        assert_eq!(balance(2), 0);
+
+        assert_ok!(Funding::claim(Origin::signed(id(2)), 0, 1, 200, proof));
+        assert_eq!(balance(2), 200);
+    });
+}
+
+#[test]
+fn distribution_claims_cannot_exceed_the_total() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        let (root, leaves) = distribution_tree();
+        assert_ok!(Funding::publish_distribution(Origin::signed(controller()), root, 250, 10, H256::repeat_byte(7)));
+        assert_ok!(Funding::claim(Origin::signed(id(2)), 0, 1, 200, vec![leaves[0], node(leaves[2], leaves[3])]));
+        assert_err!(
+            Funding::claim(Origin::signed(id(1)), 0, 0, 100, vec![leaves[1], node(leaves[2], leaves[3])]),
+            "The claim exceeds the remaining distribution"
+        );
+        assert!(!Funding::is_claimed(0, 0));
+        assert_eq!(balance(1), 0);
+    });
+}
+
+#[test]
+fn expired_distribution_is_closed_and_returns_the_unclaimed_coins() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        let (root, leaves) = distribution_tree();
+        assert_err!(
+            Funding::publish_distribution(Origin::signed(id(1)), root, 1_000, 10, H256::repeat_byte(7)),
+            "You are not the controller"
+        );
+        assert_err!(
+            Funding::publish_distribution(Origin::signed(controller()), root, 1_000, 1, H256::repeat_byte(7)),
+            "The distribution must expire in the future"
+        );
+        assert_err!(
+            Funding::publish_distribution(Origin::signed(controller()), root, 0, 10, H256::repeat_byte(7)),
+            "Distribution total must be greater than zero and within the issued coins"
+        );
+        assert_err!(
+            Funding::publish_distribution(Origin::signed(controller()), root, 1_000_001, 10, H256::repeat_byte(7)),
+            "Distribution total must be greater than zero and within the issued coins"
+        );
+        assert_ok!(Funding::publish_distribution(Origin::signed(controller()), root, 1_000, 10, H256::repeat_byte(7)));
+        assert_ok!(Funding::claim(Origin::signed(id(4)), 0, 3, 400, vec![leaves[2], node(leaves[0], leaves[1])]));
+
+        // Claims are open until the block before the expiry block
+        System::set_block_number(9);
+        assert_err!(Funding::close_distribution(Origin::signed(id(1)), 0), "The distribution has not expired");
+        System::set_block_number(10);
+        assert_err!(
+            Funding::claim(Origin::signed(id(1)), 0, 0, 100, vec![leaves[1], node(leaves[2], leaves[3])]),
+            "The distribution is closed or has expired"
+        );
+        assert_ok!(Funding::close_distribution(Origin::signed(id(1)), 0));
+        assert_eq!(Funding::issued(), 1_000_000 - 400);
+        assert!(Funding::distribution(0).expect("closed distributions are kept").closed);
+        assert_err!(Funding::close_distribution(Origin::signed(controller()), 0), "Unknown or closed distribution");
+    });
+}
+
+#[test]
+fn otc_offer_is_filled_against_payment_in_xtx() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 10), (id(2), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 100));
+        assert_eq!(balance(1), 600);
+        assert_eq!(Funding::otc_escrowed(), 400);
+        assert_eq!(Funding::otc_offers_expiring(100), vec![0]);
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(1)), 0), "The OTC offer is expired or not open to you");
+
+        assert_ok!(Funding::accept_otc_offer(Origin::signed(id(2)), 0));
+        assert_eq!(balance(1), 600);
+        assert_eq!(balance(2), 400);
+        assert_eq!(Balances::free_balance(id(1)), 310);
+        assert_eq!(Balances::free_balance(id(2)), 700);
+        assert_eq!(accounting::Module::<Runtime>::balance_by_ledger((id(1), XTX_BALANCE)), 300);
+        assert_eq!(accounting::Module::<Runtime>::balance_by_ledger((id(2), XTX_BALANCE)), -300);
+        assert_eq!(Funding::otc_offer(0), None);
+        assert_eq!(Funding::otc_escrowed(), 0);
+        assert!(Funding::otc_offers_expiring(100).is_empty());
+        assert_eq!(Funding::velocity(0, 1), vec![(0, 1, 400)]);
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(2)), 0), "OTC offer not found");
+    });
+}
+
+#[test]
+fn invalid_otc_offers_are_rejected() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 100), "Transfers not open.");
+        open_transfers();
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 0, 300, None, 100), "Invalid OTC offer");
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 0, None, 100), "Invalid OTC offer");
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, Some(id(1)), 100), "Invalid OTC offer");
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 1), "Invalid OTC offer");
+        assert_err!(
+            Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 1 + MAX_OTC_OFFER_DURATION + 1),
+            "Invalid OTC offer"
+        );
+        assert_ok!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 1 + MAX_OTC_OFFER_DURATION));
+
+        // Only the tokens that are not escrowed or locked can be offered
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 601, 300, None, 100), "Insufficient funds to offer.");
+        assert_ok!(Funding::lock_vesting(Origin::signed(controller()), id(1), 500, 50));
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(1)), 101, 300, None, 100), "Funds are locked under vesting.");
+        assert_eq!(balance(1), 600);
+        assert_eq!(Funding::otc_escrowed(), 400);
+    });
+}
+
+#[test]
+fn otc_offer_is_not_filled_without_the_funds_to_pay() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 10), (id(2), 299)]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 100));
+
+        assert_err!(
+            Funding::accept_otc_offer(Origin::signed(id(2)), 0),
+            "Insufficient funds to pay the price and the transfer fee."
+        );
+        assert_eq!(Balances::free_balance(id(1)), 10);
+        assert_eq!(Balances::free_balance(id(2)), 299);
+        assert_eq!(balance(2), 0);
+        assert_eq!(accounting::Module::<Runtime>::balance_by_ledger((id(2), XTX_BALANCE)), 0);
+        assert_eq!(Funding::otc_escrowed(), 400);
+        assert!(Funding::otc_offer(0).is_some());
+    });
+}
+
+#[test]
+fn otc_offer_to_a_buyer_can_only_be_filled_by_the_buyer_or_cancelled_by_the_seller() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 10), (id(2), 1_000), (id(3), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, Some(id(2)), 100));
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(3)), 0), "The OTC offer is expired or not open to you");
+        assert_err!(Funding::cancel_otc_offer(Origin::signed(id(2)), 0), "Only the seller can cancel the OTC offer");
+
+        assert_ok!(Funding::cancel_otc_offer(Origin::signed(id(1)), 0));
+        assert_eq!(balance(1), 1_000);
+        assert_eq!(Funding::otc_escrowed(), 0);
+        assert!(Funding::otc_offers_expiring(100).is_empty());
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(2)), 0), "OTC offer not found");
+        assert_err!(Funding::cancel_otc_offer(Origin::signed(id(1)), 0), "OTC offer not found");
+    });
+}
+
+#[test]
+fn expired_otc_offer_returns_the_escrowed_tokens() {
+    with_externalities(&mut new_test_ext(vec![(id(1), 10), (id(2), 1_000)]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        open_transfers();
+        assert_ok!(Funding::offer_otc_trade(Origin::signed(id(1)), 400, 300, None, 5));
+
+        System::set_block_number(4);
+        Funding::on_initialize(4);
+        assert!(Funding::otc_offer(0).is_some());
+
+        // The offer cannot be accepted at its expiry block, and is returned to the seller when the block is initialised
+        System::set_block_number(5);
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(2)), 0), "The OTC offer is expired or not open to you");
+        Funding::on_initialize(5);
+        assert_eq!(Funding::otc_offer(0), None);
+        assert_eq!(balance(1), 1_000);
+        assert_eq!(Funding::otc_escrowed(), 0);
+        assert_eq!(Balances::free_balance(id(2)), 1_000);
+        assert_err!(Funding::accept_otc_offer(Origin::signed(id(2)), 0), "OTC offer not found");
+    });
+}
+
+#[test]
+fn migration_set_commits_to_the_registered_balances() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        // Balances held before the migration was opened
+        for n in 1..=5u8 {
+            <AccountIdBalances<Runtime>>::insert(id(n), n as u128 * 100);
+            <HoldersAccountIds<Runtime>>::mutate(|holders_account_ids| holders_account_ids.push(id(n)));
+        }
+        assert_err!(
+            Funding::register_for_migration(Origin::signed(id(6))),
+            "Only an unlocked balance can be registered for the migration"
+        );
+
+        let mut leaves = Vec::new();
+        for n in 1..=5u8 {
+            let index = n as u32 - 1;
+            assert_ok!(Funding::register_for_migration(Origin::signed(id(n))));
+            leaves.push(leaf(index, n, n as u128 * 100));
+            // The root is maintained as each holder registers
+            assert_eq!(Funding::migration_set().root, migration_tree(&leaves, 0).0);
+            assert_eq!(Funding::migration_registration(id(n)), Some((index, n as u128 * 100)));
+        }
+        assert_err!(Funding::register_for_migration(Origin::signed(id(1))), "Already registered for the migration");
+        let set = Funding::migration_set();
+        assert_eq!((set.registrations, set.total, set.finalized), (5, 1_500, None));
+        assert_eq!(
+            Funding::migration_entries(0, 10),
+            (1..=5u8).map(|n| (n as u32 - 1, id(n), n as u128 * 100)).collect::<Vec<_>>()
+        );
+        assert_eq!(Funding::migration_entries(3, 1), vec![(3, id(4), 400)]);
+
+        // Every registered balance can be proven against the root
+        for n in 1..=5u8 {
+            let index = n as u32 - 1;
+            let (_, proof) = migration_tree(&leaves, index as usize);
+            assert!(Funding::verify_migration(index, id(n), n as u128 * 100, proof));
+        }
+        let (_, proof) = migration_tree(&leaves, 2);
+        assert!(!Funding::verify_migration(2, id(3), 301, proof.clone()));
+        assert!(!Funding::verify_migration(2, id(4), 300, proof.clone()));
+        assert!(!Funding::verify_migration(3, id(3), 300, proof.clone()));
+        assert!(!Funding::verify_migration(2, id(3), 300, proof[..MIGRATION_TREE_DEPTH as usize - 1].to_vec()));
+        let (_, padding_proof) = migration_tree(&leaves, 5);
+        assert!(!Funding::verify_migration(5, id(6), 0, padding_proof));
+
+        // The registered balances are frozen
+        open_transfers();
+        assert_err!(Funding::transfer(Origin::signed(id(1)), id(6), 100), "The balance is frozen for the migration");
+        assert_err!(Funding::offer_otc_trade(Origin::signed(id(2)), 100, 100, None, 10), "The balance is frozen for the migration");
+        assert_eq!(balance(1), 100);
+
+        System::set_block_number(2);
+        assert_err!(Funding::finalize_migration(Origin::signed(id(1))), "You are not the controller");
+        assert_ok!(Funding::finalize_migration(Origin::signed(controller())));
+        let set = Funding::migration_set();
+        assert_eq!(set.finalized, Some(2));
+        assert_eq!(set.root, migration_tree(&leaves, 0).0);
+        assert_err!(Funding::finalize_migration(Origin::signed(controller())), "Registrations for the migration are closed");
+        <AccountIdBalances<Runtime>>::insert(id(6), 600u128);
+        assert_err!(Funding::register_for_migration(Origin::signed(id(6))), "Registrations for the migration are closed");
+    });
+}
+
+#[test]
+fn locked_or_restricted_balances_are_not_registered_for_the_migration() {
+    with_externalities(&mut new_test_ext(vec![]), || {
+        System::set_block_number(1);
+        set_controller();
+        fund(1, 1_000);
+        fund(2, 1_000);
+        assert_ok!(Funding::lock_vesting(Origin::signed(controller()), id(1), 100, 10));
+        assert_err!(
+            Funding::register_for_migration(Origin::signed(id(1))),
+            "Only an unlocked balance can be registered for the migration"
+        );
+        assert_ok!(Funding::set_non_transferable(Origin::signed(controller()), id(2), 10));
+        assert_err!(Funding::register_for_migration(Origin::signed(id(2))), "The allocation is not transferable yet");
+        assert_eq!(Funding::migration_set().registrations, 0);
+        assert_eq!(Funding::migration_set().root, H256::default());
+    });
+}
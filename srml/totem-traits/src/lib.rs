@@ -33,19 +33,9 @@
 //! You should have received a copy of the GNU General Public License
 //! along with Totem.  If not, see <http://www.gnu.org/licenses/>.
 
-use support::dispatch::Result;
-use runtime_primitives::traits::{ Member};
+//! Traits shared by the Totem modules, so that a module depends on the interface of another module rather than on its 
+//! implementation, and can be unit tested against a mock of it.
 
-pub trait Encumbrance<AccountId,Hash,BlockNumber> {
-    
-    type UnLocked: Member + Copy;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-    fn prefunding_for(who: AccountId, recipient: AccountId, amount: u128, deadline: BlockNumber, ref_hash: Hash, uid: Hash) -> Result;
-    fn send_simple_invoice(o: AccountId, p: AccountId, n: i128, h: Hash, uid: Hash) -> Result;
-    fn settle_prefunded_invoice(o: AccountId, h: Hash, uid: Hash) -> Result;
-    fn set_release_state(o: AccountId, o_lock: Self::UnLocked, h: Hash, uid: Hash) -> Result;
-    fn unlock_funds_for_owner(o: AccountId, h: Hash, uid: Hash) -> Result;
-    fn check_ref_owner(o: AccountId, h: Hash) -> bool;
-    fn check_ref_beneficiary(o: AccountId, h: Hash) -> bool;
-
-}
\ No newline at end of file
+pub mod prefunding;
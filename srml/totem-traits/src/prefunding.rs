@@ -0,0 +1,213 @@
+//!                              Næ§@@@ÑÉ©
+//!                        æ@@@@@@@@@@@@@@@@@@
+//!                    Ñ@@@@?.?@@@@@@@@@@@@@@@@@@@N
+//!                 ¶@@@@@?^%@@.=@@@@@@@@@@@@@@@@@@@@
+//!               N@@@@@@@?^@@@»^@@@@@@@@@@@@@@@@@@@@@@
+//!               @@@@@@@@?^@@@».............?@@@@@@@@@É
+//!              Ñ@@@@@@@@?^@@@@@@@@@@@@@@@@@@'?@@@@@@@@Ñ
+//!              @@@@@@@@@?^@@@»..............»@@@@@@@@@@
+//!              @@@@@@@@@?^@@@»^@@@@@@@@@@@@@@@@@@@@@@@@
+//!              @@@@@@@@@?^ë@@&.@@@@@@@@@@@@@@@@@@@@@@@@
+//!               @@@@@@@@?^´@@@o.%@@@@@@@@@@@@@@@@@@@@©
+//!                @@@@@@@?.´@@@@@ë.........*.±@@@@@@@æ
+//!                 @@@@@@@@?´.I@@@@@@@@@@@@@@.&@@@@@N
+//!                  N@@@@@@@@@@ë.*=????????=?@@@@@Ñ
+//!                    @@@@@@@@@@@@@@@@@@@@@@@@@@@¶
+//!                        É@@@@@@@@@@@@@@@@Ñ¶
+//!                             Næ§@@@ÑÉ©
+
+//! Copyright 2020 Chris D'Costa
+//! This file is part of Totem Live Accounting.
+//! Author Chris D'Costa email: chris.dcosta@totemaccounting.com
+
+//! Totem is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+
+//! Totem is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+
+//! You should have received a copy of the GNU General Public License
+//! along with Totem.  If not, see <http://www.gnu.org/licenses/>.
+
+use srml_support::dispatch::Result;
+
+/// Order type. Determines the ledger accounts the purchase and the sale are recognised in at settlement
+pub type OrderType = u16;
+/// Services. Labour for the buyer, Sales of services for the seller
+pub const ORDER_TYPE_SERVICE: OrderType = 0;
+/// Goods. Inventory for the buyer, Sales of goods for the seller
+pub const ORDER_TYPE_GOODS: OrderType = 1;
+/// Expense reimbursement. Reimbursed expenses for the buyer, Recharged expenses for the seller
+pub const ORDER_TYPE_EXPENSE: OrderType = 2;
+
+/// State of the funds locked for a prefunding, which says who can take them. 
+/// Each state is a pair of locks (owner locked, beneficiary locked), shown in brackets.
+#[derive(PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum LockState {
+    /// Locked by the owner and not yet accepted. The owner can take the funds back after the deadline (10). Initial state
+    AwaitingAcceptance,
+    /// Accepted by the beneficiary. The funds are locked for the intended purpose and nobody can take them (11)
+    Accepted,
+    /// Approved by the owner. The beneficiary can take the funds, or release them to the owner (01)
+    SenderApproved,
+    /// Released by the beneficiary. The owner can take the funds back regardless of the deadline (00)
+    ReleasedToSender,
+}
+
+impl LockState {
+    /// Lock state of a pair of locks (owner locked, beneficiary locked)
+    pub fn from_locks(owner: bool, beneficiary: bool) -> Self {
+        match (owner, beneficiary) {
+            (true, false) => LockState::AwaitingAcceptance,
+            (true, true) => LockState::Accepted,
+            (false, true) => LockState::SenderApproved,
+            (false, false) => LockState::ReleasedToSender,
+        }
+    }
+    /// Pair of locks (owner locked, beneficiary locked) of the lock state
+    pub fn locks(self) -> (bool, bool) {
+        match self {
+            LockState::AwaitingAcceptance => (true, false),
+            LockState::Accepted => (true, true),
+            LockState::SenderApproved => (false, true),
+            LockState::ReleasedToSender => (false, false),
+        }
+    }
+    /// Whether the beneficiary has accepted and not released the funds
+    pub fn is_accepted(self) -> bool {
+        self.locks().1
+    }
+}
+
+/// Hook for downstream modules (reputation, rewards, analytics) to react to the outcome of a prefunding
+pub trait PostSettlement<AccountId, Hash> {
+    /// A prefunded invoice was settled. Reference, payer, beneficiary, amount paid, amount released to the payer
+    fn on_settled(h: Hash, payer: AccountId, beneficiary: AccountId, paid: u128, released: u128);
+    /// A prefunding was cancelled and the funds returned to the owner. Reference, owner, beneficiary, amount
+    fn on_cancelled(h: Hash, owner: AccountId, beneficiary: AccountId, amount: u128);
+}
+
+impl<AccountId, Hash> PostSettlement<AccountId, Hash> for () {
+    fn on_settled(_h: Hash, _payer: AccountId, _beneficiary: AccountId, _paid: u128, _released: u128) {}
+    fn on_cancelled(_h: Hash, _owner: AccountId, _beneficiary: AccountId, _amount: u128) {}
+}
+
+/// Compliance holds on an order or prefunding. A held reference can be neither settled nor cancelled
+pub trait ComplianceHolding<Hash> {
+    fn is_held(h: Hash) -> bool;
+}
+
+impl<Hash> ComplianceHolding<Hash> for () {
+    fn is_held(_h: Hash) -> bool { false }
+}
+
+pub trait Encumbrance<AccountId,Hash,BlockNumber> {
+
+    fn prefunding_for(who: AccountId, recipient: AccountId, amount: u128, deadline: BlockNumber, grace_period: BlockNumber, ref_hash: Hash, uid: Hash) -> Result;
+    fn send_simple_invoice(o: AccountId, p: AccountId, n: i128, h: Hash, t: OrderType, uid: Hash) -> Result;
+    fn send_express_invoice(o: AccountId, p: AccountId, h: Hash, t: OrderType, uid: Hash) -> Result;
+    fn settle_prefunded_invoice(o: AccountId, h: Hash, uid: Hash) -> Result;
+    fn set_release_state(o: AccountId, state: LockState, h: Hash, uid: Hash) -> Result;
+    fn unlock_funds_for_owner(o: AccountId, h: Hash, uid: Hash) -> Result;
+    fn release_on_default(o: AccountId, h: Hash, uid: Hash) -> Result;
+    fn cancel_prefunding(o: AccountId, h: Hash, uid: Hash) -> Result;
+    fn check_ref_owner(o: AccountId, h: Hash) -> bool;
+    fn check_ref_beneficiary(o: AccountId, h: Hash) -> bool;
+    fn lock_performance_bond(o: AccountId, p: AccountId, amount: u128, h: Hash, uid: Hash) -> Result;
+    fn release_performance_bond(h: Hash, uid: Hash) -> Result;
+    fn forfeit_performance_bond(h: Hash, forfeit: u128, uid: Hash) -> Result;
+    fn credit_invoice(o: AccountId, h: Hash, amount: u128, reason: Hash, uid: Hash) -> Result;
+    fn acceptance_open(h: Hash) -> bool;
+    fn within_maximum(amount: u128) -> bool;
+    fn minimum_deadline(o: AccountId, p: AccountId) -> u64;
+    fn adjust_prefunding(o: AccountId, h: Hash, amount: u128, deadline: BlockNumber, uid: Hash) -> Result;
+    fn awaiting_co_approval(h: Hash) -> bool;
+
+}
+/// Encumbrance implementation for unit testing the modules that depend on prefunding without the prefunding module.
+/// Every call succeeds, and every check passes, unless a different outcome has been scripted. The calls are recorded in order.
+/// Enabled by the `mock` feature for the tests of the dependent modules.
+#[cfg(feature = "mock")]
+pub mod mock {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static FAILURES: RefCell<Vec<(&'static str, &'static str)>> = RefCell::new(Vec::new());
+        static DENIED: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+        static CALLS: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+        static INVOICED: RefCell<Vec<i128>> = RefCell::new(Vec::new());
+    }
+
+    pub struct MockEncumbrance;
+
+    impl MockEncumbrance {
+        /// Scripts the next call of a function to fail with the error
+        pub fn fail_next(call: &'static str, error: &'static str) {
+            FAILURES.with(|f| f.borrow_mut().push((call, error)));
+        }
+        /// Scripts a check (check_ref_owner, check_ref_beneficiary, acceptance_open, within_maximum) to fail until reset
+        pub fn deny(check: &'static str) {
+            DENIED.with(|d| d.borrow_mut().push(check));
+        }
+        /// The functions called since the last reset, in order
+        pub fn calls() -> Vec<&'static str> {
+            CALLS.with(|c| c.borrow().clone())
+        }
+        /// The amounts of the simple invoices sent since the last reset, in order
+        pub fn invoiced() -> Vec<i128> {
+            INVOICED.with(|i| i.borrow().clone())
+        }
+        /// Clears the scripted outcomes and the recorded calls
+        pub fn reset() {
+            FAILURES.with(|f| f.borrow_mut().clear());
+            DENIED.with(|d| d.borrow_mut().clear());
+            CALLS.with(|c| c.borrow_mut().clear());
+            INVOICED.with(|i| i.borrow_mut().clear());
+        }
+        fn outcome(call: &'static str) -> Result {
+            CALLS.with(|c| c.borrow_mut().push(call));
+            FAILURES.with(|f| {
+                let mut failures = f.borrow_mut();
+                match failures.iter().position(|s| s.0 == call) {
+                    Some(i) => Err(failures.remove(i).1),
+                    None => Ok(()),
+                }
+            })
+        }
+        fn check(call: &'static str) -> bool {
+            CALLS.with(|c| c.borrow_mut().push(call));
+            DENIED.with(|d| !d.borrow().contains(&call))
+        }
+    }
+
+    impl<AccountId, Hash, BlockNumber> Encumbrance<AccountId, Hash, BlockNumber> for MockEncumbrance {
+        fn prefunding_for(_: AccountId, _: AccountId, _: u128, _: BlockNumber, _: BlockNumber, _: Hash, _: Hash) -> Result { Self::outcome("prefunding_for") }
+        fn send_simple_invoice(_: AccountId, _: AccountId, n: i128, _: Hash, _: OrderType, _: Hash) -> Result {
+            INVOICED.with(|i| i.borrow_mut().push(n));
+            Self::outcome("send_simple_invoice")
+        }
+        fn send_express_invoice(_: AccountId, _: AccountId, _: Hash, _: OrderType, _: Hash) -> Result { Self::outcome("send_express_invoice") }
+        fn settle_prefunded_invoice(_: AccountId, _: Hash, _: Hash) -> Result { Self::outcome("settle_prefunded_invoice") }
+        fn set_release_state(_: AccountId, _: LockState, _: Hash, _: Hash) -> Result { Self::outcome("set_release_state") }
+        fn unlock_funds_for_owner(_: AccountId, _: Hash, _: Hash) -> Result { Self::outcome("unlock_funds_for_owner") }
+        fn release_on_default(_: AccountId, _: Hash, _: Hash) -> Result { Self::outcome("release_on_default") }
+        fn cancel_prefunding(_: AccountId, _: Hash, _: Hash) -> Result { Self::outcome("cancel_prefunding") }
+        fn check_ref_owner(_: AccountId, _: Hash) -> bool { Self::check("check_ref_owner") }
+        fn check_ref_beneficiary(_: AccountId, _: Hash) -> bool { Self::check("check_ref_beneficiary") }
+        fn lock_performance_bond(_: AccountId, _: AccountId, _: u128, _: Hash, _: Hash) -> Result { Self::outcome("lock_performance_bond") }
+        fn release_performance_bond(_: Hash, _: Hash) -> Result { Self::outcome("release_performance_bond") }
+        fn forfeit_performance_bond(_: Hash, _: u128, _: Hash) -> Result { Self::outcome("forfeit_performance_bond") }
+        fn credit_invoice(_: AccountId, _: Hash, _: u128, _: Hash, _: Hash) -> Result { Self::outcome("credit_invoice") }
+        fn acceptance_open(_: Hash) -> bool { Self::check("acceptance_open") }
+        fn within_maximum(_: u128) -> bool { Self::check("within_maximum") }
+        fn minimum_deadline(_: AccountId, _: AccountId) -> u64 { 11520 }
+        fn adjust_prefunding(_: AccountId, _: Hash, _: u128, _: BlockNumber, _: Hash) -> Result { Self::outcome("adjust_prefunding") }
+        fn awaiting_co_approval(_: Hash) -> bool { false }
+    }
+}